@@ -0,0 +1,346 @@
+//! Parses and runs "cardinality" aggregations
+//!
+//! Distinct values are counted with HyperLogLog++. While there are fewer distinct values
+//! than the precision threshold, their hashes are kept so the count is exact. Past that,
+//! the hashes are folded into HyperLogLog registers which use a fixed amount of memory
+//! (2^precision bytes) and give an estimate within a few percent.
+
+use std::collections::BTreeSet;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use serde_json::Value as Json;
+use kite::document::DocRef;
+use kite::schema::{Schema, FieldRef};
+use kite_rocksdb::RocksDBIndexReader;
+
+use query_parser::QueryBuildContext;
+use aggregations::{Aggregation, AggregationBuilder, AggregationResult, AggregationParseError, AggregationBuildError, AggregationReduceError, BucketKey, datetime_to_millis};
+
+
+const DEFAULT_PRECISION_THRESHOLD: u64 = 3000;
+const MAX_PRECISION_THRESHOLD: u64 = 40000;
+
+
+#[derive(Debug, Clone, PartialEq)]
+enum HyperLogLogState {
+    /// The hash of every value seen so far
+    Exact(BTreeSet<u64>),
+
+    /// The highest rank seen in each register
+    Registers(Vec<u8>),
+}
+
+
+/// Estimates the number of distinct values that have been added to it
+#[derive(Debug, Clone, PartialEq)]
+pub struct HyperLogLog {
+    precision: u8,
+    threshold: u64,
+    state: HyperLogLogState,
+}
+
+
+impl HyperLogLog {
+    /// Creates a counter that is exact up to `threshold` distinct values
+    pub fn new(threshold: u64) -> HyperLogLog {
+        // Use enough registers to keep the error small just above the threshold
+        let mut precision = 4;
+        while precision < 18 && (1u64 << precision) < threshold * 4 {
+            precision += 1;
+        }
+
+        HyperLogLog {
+            precision: precision,
+            threshold: threshold,
+            state: HyperLogLogState::Exact(BTreeSet::new()),
+        }
+    }
+
+    pub fn add_hash(&mut self, hash: u64) {
+        let convert = match self.state {
+            HyperLogLogState::Exact(ref mut hashes) => {
+                hashes.insert(hash);
+                hashes.len() as u64 > self.threshold
+            }
+            HyperLogLogState::Registers(ref mut registers) => {
+                add_to_registers(registers, self.precision, hash);
+                false
+            }
+        };
+
+        if convert {
+            self.convert_to_registers();
+        }
+    }
+
+    pub fn merge(&mut self, other: &HyperLogLog) {
+        match other.state {
+            HyperLogLogState::Exact(ref hashes) => {
+                for hash in hashes.iter() {
+                    self.add_hash(*hash);
+                }
+            }
+            HyperLogLogState::Registers(ref other_registers) => {
+                self.convert_to_registers();
+
+                if let HyperLogLogState::Registers(ref mut registers) = self.state {
+                    for (register, other_register) in registers.iter_mut().zip(other_registers.iter()) {
+                        if *other_register > *register {
+                            *register = *other_register;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn convert_to_registers(&mut self) {
+        let registers = match self.state {
+            HyperLogLogState::Exact(ref hashes) => {
+                let mut registers = vec![0; 1 << self.precision];
+                for hash in hashes.iter() {
+                    add_to_registers(&mut registers, self.precision, *hash);
+                }
+                registers
+            }
+            HyperLogLogState::Registers(_) => return,
+        };
+
+        self.state = HyperLogLogState::Registers(registers);
+    }
+
+    pub fn estimate(&self) -> u64 {
+        match self.state {
+            HyperLogLogState::Exact(ref hashes) => hashes.len() as u64,
+            HyperLogLogState::Registers(ref registers) => {
+                let m = registers.len() as f64;
+                let alpha = 0.7213 / (1.0 + 1.079 / m);
+                let sum = registers.iter().map(|register| 2.0f64.powi(-(*register as i32))).sum::<f64>();
+                let estimate = alpha * m * m / sum;
+
+                // The raw estimate is biased for small cardinalities so fall back to linear counting
+                let zeros = registers.iter().filter(|register| **register == 0).count();
+                if estimate <= 2.5 * m && zeros > 0 {
+                    (m * (m / zeros as f64).ln()).round() as u64
+                } else {
+                    estimate.round() as u64
+                }
+            }
+        }
+    }
+}
+
+
+fn add_to_registers(registers: &mut Vec<u8>, precision: u8, hash: u64) {
+    // The first bits of the hash pick the register, the rest give the rank
+    let index = (hash >> (64 - precision)) as usize;
+    let rank = ((hash << precision) | (1 << (precision - 1))).leading_zeros() as u8 + 1;
+
+    if rank > registers[index] {
+        registers[index] = rank;
+    }
+}
+
+
+fn hash_key(key: &BucketKey) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    match *key {
+        BucketKey::Integer(integer) => integer.hash(&mut hasher),
+        BucketKey::Boolean(boolean) => boolean.hash(&mut hasher),
+        BucketKey::DateTime(ref date_time) => datetime_to_millis(date_time).hash(&mut hasher),
+        BucketKey::String(ref string) => string.hash(&mut hasher),
+    }
+    hasher.finish()
+}
+
+
+#[derive(Debug, PartialEq)]
+pub struct CardinalityAggregationBuilder {
+    field: String,
+    precision_threshold: u64,
+}
+
+
+impl CardinalityAggregationBuilder {
+    pub fn new(field: &str) -> CardinalityAggregationBuilder {
+        CardinalityAggregationBuilder {
+            field: field.to_string(),
+            precision_threshold: DEFAULT_PRECISION_THRESHOLD,
+        }
+    }
+}
+
+
+/// Counts the distinct values of a field on one index
+#[derive(Debug)]
+pub struct CardinalityAggregation {
+    field_ref: Option<FieldRef>,
+    counter: HyperLogLog,
+}
+
+
+impl Aggregation for CardinalityAggregation {
+    fn name(&self) -> &'static str {
+        "CardinalityAggregation"
+    }
+
+    fn collect(&mut self, index_reader: &RocksDBIndexReader, doc_id: u64) {
+        let field_ref = match self.field_ref {
+            Some(field_ref) => field_ref,
+            None => return,
+        };
+
+        if let Ok(Some(value)) = index_reader.read_stored_field(field_ref, DocRef::from_u64(doc_id)) {
+            self.counter.add_hash(hash_key(&BucketKey::from_field_value(value)));
+        }
+    }
+
+    fn empty_clone(&self) -> Box<Aggregation> {
+        Box::new(CardinalityAggregation {
+            field_ref: self.field_ref,
+            counter: HyperLogLog::new(self.counter.threshold),
+        })
+    }
+
+    fn into_result(self: Box<Self>) -> AggregationResult {
+        AggregationResult::Cardinality(self.counter)
+    }
+}
+
+
+impl AggregationBuilder for CardinalityAggregationBuilder {
+    fn build(&self, context: &QueryBuildContext, schema: &Schema) -> Result<Box<Aggregation>, AggregationBuildError> {
+        let field_ref = match context.index_metadata.and_then(|index_metadata| index_metadata.get_field_mapping(&self.field)) {
+            Some(field_mapping) => {
+                let is_analyzed = field_mapping.index_analyzer().is_some();
+                if !field_mapping.has_doc_values && !(field_mapping.is_stored && !is_analyzed) {
+                    return Err(AggregationBuildError::FieldNotAggregatable(self.field.clone()));
+                }
+
+                schema.get_field_by_name(&self.field)
+            }
+            None => None,
+        };
+
+        Ok(Box::new(CardinalityAggregation {
+            field_ref: field_ref,
+            counter: HyperLogLog::new(self.precision_threshold),
+        }))
+    }
+
+    fn reduce(&self, results: Vec<AggregationResult>) -> Result<Json, AggregationReduceError> {
+        let mut counter = HyperLogLog::new(self.precision_threshold);
+        for result in results {
+            if let AggregationResult::Cardinality(ref result_counter) = result {
+                counter.merge(result_counter);
+            }
+        }
+
+        Ok(json!({
+            "value": counter.estimate(),
+        }))
+    }
+}
+
+
+pub fn parse(json: &Json) -> Result<Box<AggregationBuilder>, AggregationParseError> {
+    let object = try!(json.as_object().ok_or(AggregationParseError::ExpectedObject));
+
+    let mut field = None;
+    let mut precision_threshold = None;
+
+    for (key, value) in object.iter() {
+        match key.as_ref() {
+            "field" => {
+                field = Some(try!(value.as_str().ok_or(AggregationParseError::ExpectedString)));
+            }
+            "precision_threshold" => {
+                precision_threshold = Some(try!(value.as_u64().ok_or(AggregationParseError::ExpectedPositiveInteger)));
+            }
+            _ => return Err(AggregationParseError::UnrecognisedKey(key.clone())),
+        }
+    }
+
+    let mut builder = CardinalityAggregationBuilder::new(try!(field.ok_or(AggregationParseError::ExpectedKey("field"))));
+
+    if let Some(precision_threshold) = precision_threshold {
+        // Higher thresholds would use too much memory
+        builder.precision_threshold = if precision_threshold > MAX_PRECISION_THRESHOLD { MAX_PRECISION_THRESHOLD } else { precision_threshold };
+    }
+
+    Ok(Box::new(builder))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+
+    use aggregations::{AggregationBuilder, AggregationResult, BucketKey};
+
+    use super::{HyperLogLog, CardinalityAggregationBuilder, hash_key, parse};
+
+    fn counter_of(threshold: u64, values: ::std::ops::Range<i64>) -> HyperLogLog {
+        let mut counter = HyperLogLog::new(threshold);
+        for value in values {
+            counter.add_hash(hash_key(&BucketKey::Integer(value)));
+        }
+        counter
+    }
+
+    #[test]
+    fn test_parse() {
+        let builder = parse(&serde_json::from_str("
+        {
+            \"field\": \"author\",
+            \"precision_threshold\": 100000
+        }
+        ").unwrap());
+
+        assert_eq!(format!("{:?}", builder.unwrap()), format!("{:?}", CardinalityAggregationBuilder {
+            field: "author".to_string(),
+            precision_threshold: 40000,
+        }));
+    }
+
+    #[test]
+    fn test_exact_below_threshold() {
+        let mut counter = counter_of(100, 0..50);
+        counter.merge(&counter_of(100, 25..75));
+
+        assert_eq!(counter.estimate(), 75);
+    }
+
+    #[test]
+    fn test_estimate_above_threshold() {
+        let counter = counter_of(1000, 0..100000);
+        let estimate = counter.estimate() as f64;
+
+        assert!((estimate - 100000.0).abs() < 5000.0, "estimate was {}", estimate);
+    }
+
+    #[test]
+    fn test_merge_registers() {
+        let mut counter = counter_of(1000, 0..30000);
+        counter.merge(&counter_of(1000, 20000..50000));
+        counter.merge(&counter_of(1000, 49000..49500));
+        let estimate = counter.estimate() as f64;
+
+        assert!((estimate - 50000.0).abs() < 2500.0, "estimate was {}", estimate);
+    }
+
+    #[test]
+    fn test_reduce() {
+        let builder = CardinalityAggregationBuilder::new("author");
+
+        let result = builder.reduce(vec![
+            AggregationResult::Cardinality(counter_of(3000, 0..10)),
+            AggregationResult::Cardinality(counter_of(3000, 5..20)),
+        ]).unwrap();
+
+        assert_eq!(result, json!({
+            "value": 20,
+        }));
+    }
+}
@@ -0,0 +1,576 @@
+//! Parses and runs "date_histogram" aggregations
+//!
+//! Buckets are keyed by the time they start at (in milliseconds since the epoch). Calendar
+//! intervals start on the boundaries of the calendar unit in the requested time zone, so
+//! they can vary in length. Fixed intervals are always the same length.
+
+use std::collections::BTreeMap;
+
+use serde_json;
+use serde_json::Value as Json;
+use chrono::{DateTime, UTC, NaiveDate, NaiveDateTime, Datelike, FixedOffset, TimeZone};
+use kite::document::{DocRef, FieldValue};
+use kite::schema::{Schema, FieldRef};
+use kite_rocksdb::RocksDBIndexReader;
+
+use query_parser::QueryBuildContext;
+use index::metadata::parse::settings::parse_time_value;
+use mapping::FieldType;
+use aggregations::{Aggregation, AggregationBuilder, AggregationResult, AggregationParseError, AggregationBuildError, AggregationReduceError, MAX_BUCKETS, BucketKey, BucketCollector, MergedBucket, merge_buckets, build_sub_aggregations, datetime_to_millis};
+use aggregations::histogram::bucket_key as fixed_bucket_key;
+
+
+const MINUTE: i64 = 60 * 1000;
+const HOUR: i64 = 60 * MINUTE;
+const DAY: i64 = 24 * HOUR;
+const WEEK: i64 = 7 * DAY;
+
+/// Weeks start on Monday. The epoch was on a Thursday so the first Monday was four days later
+const FIRST_MONDAY: i64 = 4 * DAY;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CalendarUnit {
+    Minute,
+    Hour,
+    Day,
+    Week,
+    Month,
+    Quarter,
+    Year,
+}
+
+
+impl CalendarUnit {
+    fn from_str(string: &str) -> Option<CalendarUnit> {
+        match string {
+            "minute" | "1m" => Some(CalendarUnit::Minute),
+            "hour" | "1h" => Some(CalendarUnit::Hour),
+            "day" | "1d" => Some(CalendarUnit::Day),
+            "week" | "1w" => Some(CalendarUnit::Week),
+            "month" | "1M" => Some(CalendarUnit::Month),
+            "quarter" | "1q" => Some(CalendarUnit::Quarter),
+            "year" | "1y" => Some(CalendarUnit::Year),
+            _ => None,
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DateInterval {
+    Calendar(CalendarUnit),
+
+    /// A number of milliseconds
+    Fixed(i64),
+}
+
+
+impl DateInterval {
+    /// Finds the start of the bucket that a time falls into
+    ///
+    /// `time_zone` is the offset from UTC in milliseconds.
+    fn bucket_key(&self, time: i64, time_zone: i64) -> i64 {
+        match *self {
+            DateInterval::Fixed(interval) => fixed_bucket_key(time, interval, -time_zone),
+            DateInterval::Calendar(CalendarUnit::Minute) => fixed_bucket_key(time, MINUTE, -time_zone),
+            DateInterval::Calendar(CalendarUnit::Hour) => fixed_bucket_key(time, HOUR, -time_zone),
+            DateInterval::Calendar(CalendarUnit::Day) => fixed_bucket_key(time, DAY, -time_zone),
+            DateInterval::Calendar(CalendarUnit::Week) => fixed_bucket_key(time, WEEK, FIRST_MONDAY - time_zone),
+            DateInterval::Calendar(unit) => {
+                let local = to_naive(time + time_zone);
+                let month = match unit {
+                    CalendarUnit::Quarter => (local.month0() / 3) * 3,
+                    CalendarUnit::Year => 0,
+                    _ => local.month0(),
+                };
+
+                from_naive(NaiveDate::from_ymd(local.year(), month + 1, 1).and_hms(0, 0, 0)) - time_zone
+            }
+        }
+    }
+
+    /// Finds the start of the bucket after the one starting at `key`
+    ///
+    /// Returns None if it's too far in the future to be represented.
+    fn next_bucket_key(&self, key: i64, time_zone: i64) -> Option<i64> {
+        let months = match *self {
+            DateInterval::Fixed(interval) => return key.checked_add(interval),
+            DateInterval::Calendar(CalendarUnit::Minute) => return key.checked_add(MINUTE),
+            DateInterval::Calendar(CalendarUnit::Hour) => return key.checked_add(HOUR),
+            DateInterval::Calendar(CalendarUnit::Day) => return key.checked_add(DAY),
+            DateInterval::Calendar(CalendarUnit::Week) => return key.checked_add(WEEK),
+            DateInterval::Calendar(CalendarUnit::Month) => 1,
+            DateInterval::Calendar(CalendarUnit::Quarter) => 3,
+            DateInterval::Calendar(CalendarUnit::Year) => 12,
+        };
+
+        let local = to_naive(key + time_zone);
+        let month = local.year() as i64 * 12 + local.month0() as i64 + months;
+        let year = fixed_bucket_key(month, 12, 0) / 12;
+        NaiveDate::from_ymd_opt(year as i32, (month - year * 12) as u32 + 1, 1).map(|date| from_naive(date.and_hms(0, 0, 0)) - time_zone)
+    }
+}
+
+
+fn to_naive(millis: i64) -> NaiveDateTime {
+    NaiveDateTime::from_timestamp(fixed_bucket_key(millis, 1000, 0) / 1000, 0)
+}
+
+
+fn from_naive(naive: NaiveDateTime) -> i64 {
+    naive.timestamp() * 1000
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExtendedBounds {
+    pub min: i64,
+    pub max: i64,
+}
+
+
+#[derive(Debug)]
+pub struct DateHistogramAggregationBuilder {
+    field: String,
+    interval: DateInterval,
+
+    /// The offset of the time zone from UTC in milliseconds
+    time_zone: i64,
+
+    min_doc_count: u64,
+    extended_bounds: Option<ExtendedBounds>,
+    sub_aggregations: Vec<(String, Box<AggregationBuilder>)>,
+}
+
+
+impl DateHistogramAggregationBuilder {
+    pub fn new(field: &str, interval: DateInterval) -> DateHistogramAggregationBuilder {
+        DateHistogramAggregationBuilder {
+            field: field.to_string(),
+            interval: interval,
+            time_zone: 0,
+            min_doc_count: 0,
+            extended_bounds: None,
+            sub_aggregations: Vec::new(),
+        }
+    }
+}
+
+
+/// Counts the documents in each time bucket of a date field on one index
+#[derive(Debug)]
+pub struct DateHistogramAggregation {
+    field_ref: Option<FieldRef>,
+    interval: DateInterval,
+    time_zone: i64,
+    buckets: BucketCollector,
+}
+
+
+impl Aggregation for DateHistogramAggregation {
+    fn name(&self) -> &'static str {
+        "DateHistogramAggregation"
+    }
+
+    fn collect(&mut self, index_reader: &RocksDBIndexReader, doc_id: u64) {
+        let field_ref = match self.field_ref {
+            Some(field_ref) => field_ref,
+            None => return,
+        };
+
+        if let Ok(Some(FieldValue::DateTime(value))) = index_reader.read_stored_field(field_ref, DocRef::from_u64(doc_id)) {
+            let key = self.interval.bucket_key(datetime_to_millis(&value), self.time_zone);
+            self.buckets.collect(BucketKey::Integer(key), index_reader, doc_id);
+        }
+    }
+
+    fn empty_clone(&self) -> Box<Aggregation> {
+        Box::new(DateHistogramAggregation {
+            field_ref: self.field_ref,
+            interval: self.interval,
+            time_zone: self.time_zone,
+            buckets: self.buckets.empty_clone(),
+        })
+    }
+
+    fn into_result(self: Box<Self>) -> AggregationResult {
+        self.buckets.into_result()
+    }
+}
+
+
+impl AggregationBuilder for DateHistogramAggregationBuilder {
+    fn build(&self, context: &QueryBuildContext, schema: &Schema) -> Result<Box<Aggregation>, AggregationBuildError> {
+        let field_ref = match context.index_metadata.and_then(|index_metadata| index_metadata.get_field_mapping(&self.field)) {
+            Some(field_mapping) => {
+                if field_mapping.data_type != FieldType::Date {
+                    return Err(AggregationBuildError::FieldNotDate(self.field.clone()));
+                }
+
+                if !field_mapping.has_doc_values && !field_mapping.is_stored {
+                    return Err(AggregationBuildError::FieldNotAggregatable(self.field.clone()));
+                }
+
+                schema.get_field_by_name(&self.field)
+            }
+            None => None,
+        };
+
+        Ok(Box::new(DateHistogramAggregation {
+            field_ref: field_ref,
+            interval: self.interval,
+            time_zone: self.time_zone,
+            buckets: BucketCollector::new(try!(build_sub_aggregations(&self.sub_aggregations, context, schema))),
+        }))
+    }
+
+    fn reduce(&self, results: Vec<AggregationResult>) -> Result<Json, AggregationReduceError> {
+        let mut buckets = merge_buckets(results, self.sub_aggregations.len()).into_iter().filter_map(|(key, bucket)| {
+            match key {
+                BucketKey::Integer(key) => Some((key, bucket)),
+                _ => None,
+            }
+        }).collect::<BTreeMap<i64, MergedBucket>>();
+
+        // Fill in the empty buckets between the first and last bucket, widened by the extended bounds
+        if self.min_doc_count == 0 {
+            let mut first = buckets.keys().next().cloned();
+            let mut last = buckets.keys().next_back().cloned();
+
+            if let Some(extended_bounds) = self.extended_bounds {
+                let min = self.interval.bucket_key(extended_bounds.min, self.time_zone);
+                let max = self.interval.bucket_key(extended_bounds.max, self.time_zone);
+                first = Some(first.map_or(min, |first| if min < first { min } else { first }));
+                last = Some(last.map_or(max, |last| if max > last { max } else { last }));
+            }
+
+            if let (Some(first), Some(last)) = (first, last) {
+                let num_sub_aggregations = self.sub_aggregations.len();
+                let mut key = first;
+                while key <= last {
+                    buckets.entry(key).or_insert_with(|| MergedBucket::new(num_sub_aggregations));
+                    if buckets.len() > MAX_BUCKETS {
+                        return Err(AggregationReduceError::TooManyBuckets);
+                    }
+
+                    key = match self.interval.next_bucket_key(key, self.time_zone) {
+                        Some(key) => key,
+                        None => break,
+                    };
+                }
+            }
+        }
+
+        let time_zone = FixedOffset::east((self.time_zone / 1000) as i32);
+        let buckets_json = try!(buckets.into_iter().filter(|&(_, ref bucket)| bucket.doc_count >= self.min_doc_count).map(|(key, bucket)| {
+            let key_as_string = UTC.timestamp(fixed_bucket_key(key, 1000, 0) / 1000, 0).with_timezone(&time_zone).to_rfc3339();
+
+            let mut bucket_json = serde_json::Map::new();
+            bucket_json.insert("key".to_string(), json!(key));
+            bucket_json.insert("key_as_string".to_string(), json!(key_as_string));
+            try!(bucket.add_to_bucket_json(&self.sub_aggregations, &mut bucket_json));
+            Ok(Json::Object(bucket_json))
+        }).collect::<Result<Vec<_>, _>>());
+
+        Ok(json!({
+            "buckets": buckets_json,
+        }))
+    }
+
+    fn set_sub_aggregations(&mut self, sub_aggregations: Vec<(String, Box<AggregationBuilder>)>) -> Result<(), AggregationParseError> {
+        self.sub_aggregations = sub_aggregations;
+        Ok(())
+    }
+}
+
+
+fn parse_interval(json: &Json, calendar: bool, fixed: bool) -> Result<DateInterval, AggregationParseError> {
+    let string = try!(json.as_str().ok_or(AggregationParseError::ExpectedString));
+
+    if calendar {
+        if let Some(unit) = CalendarUnit::from_str(string) {
+            return Ok(DateInterval::Calendar(unit));
+        }
+    }
+
+    if fixed {
+        if let Some(Some(interval)) = parse_time_value(json) {
+            if interval > 0 {
+                return Ok(DateInterval::Fixed(interval as i64));
+            }
+        }
+    }
+
+    Err(AggregationParseError::InvalidInterval(string.to_string()))
+}
+
+
+/// Parses a time zone into its offset from UTC in milliseconds
+///
+/// Only "UTC" and fixed offsets such as "+01:00" are supported.
+fn parse_time_zone(json: &Json) -> Result<i64, AggregationParseError> {
+    let string = try!(json.as_str().ok_or(AggregationParseError::ExpectedString));
+
+    if string == "UTC" || string == "Z" {
+        return Ok(0);
+    }
+
+    let invalid = || AggregationParseError::InvalidTimeZone(string.to_string());
+
+    let sign = match string.chars().next() {
+        Some('+') => 1,
+        Some('-') => -1,
+        _ => return Err(invalid()),
+    };
+
+    let digits = string[1..].replace(":", "");
+    if digits.len() != 4 || !digits.chars().all(|c| c.is_digit(10)) {
+        return Err(invalid());
+    }
+
+    let hours = digits[..2].parse::<i64>().unwrap();
+    let minutes = digits[2..].parse::<i64>().unwrap();
+    if hours > 18 || minutes >= 60 {
+        return Err(invalid());
+    }
+
+    Ok(sign * (hours * HOUR + minutes * MINUTE))
+}
+
+
+/// Parses a bound given either as a date or in milliseconds since the epoch
+fn parse_bound(json: &Json) -> Result<i64, AggregationParseError> {
+    match *json {
+        Json::Number(_) => json.as_i64().ok_or(AggregationParseError::ExpectedInteger),
+        Json::String(ref string) => {
+            match string.parse::<DateTime<UTC>>() {
+                Ok(date) => Ok(datetime_to_millis(&date)),
+                Err(_) => Err(AggregationParseError::InvalidDate(string.clone())),
+            }
+        }
+        _ => Err(AggregationParseError::ExpectedString),
+    }
+}
+
+
+fn parse_extended_bounds(json: &Json) -> Result<ExtendedBounds, AggregationParseError> {
+    let object = try!(json.as_object().ok_or(AggregationParseError::ExpectedObject));
+
+    let mut min = None;
+    let mut max = None;
+
+    for (key, value) in object.iter() {
+        match key.as_ref() {
+            "min" => min = Some(try!(parse_bound(value))),
+            "max" => max = Some(try!(parse_bound(value))),
+            _ => return Err(AggregationParseError::UnrecognisedKey(key.clone())),
+        }
+    }
+
+    Ok(ExtendedBounds {
+        min: try!(min.ok_or(AggregationParseError::ExpectedKey("min"))),
+        max: try!(max.ok_or(AggregationParseError::ExpectedKey("max"))),
+    })
+}
+
+
+pub fn parse(json: &Json) -> Result<Box<AggregationBuilder>, AggregationParseError> {
+    let object = try!(json.as_object().ok_or(AggregationParseError::ExpectedObject));
+
+    let mut field = None;
+    let mut interval = None;
+    let mut time_zone = None;
+    let mut min_doc_count = None;
+    let mut extended_bounds = None;
+
+    for (key, value) in object.iter() {
+        match key.as_ref() {
+            "field" => {
+                field = Some(try!(value.as_str().ok_or(AggregationParseError::ExpectedString)));
+            }
+            "calendar_interval" => {
+                interval = Some(try!(parse_interval(value, true, false)));
+            }
+            "fixed_interval" => {
+                interval = Some(try!(parse_interval(value, false, true)));
+            }
+            "interval" => {
+                interval = Some(try!(parse_interval(value, true, true)));
+            }
+            "time_zone" => {
+                time_zone = Some(try!(parse_time_zone(value)));
+            }
+            "min_doc_count" => {
+                min_doc_count = Some(try!(value.as_u64().ok_or(AggregationParseError::ExpectedPositiveInteger)));
+            }
+            "extended_bounds" => {
+                extended_bounds = Some(try!(parse_extended_bounds(value)));
+            }
+            _ => return Err(AggregationParseError::UnrecognisedKey(key.clone())),
+        }
+    }
+
+    let mut builder = DateHistogramAggregationBuilder::new(
+        try!(field.ok_or(AggregationParseError::ExpectedKey("field"))),
+        try!(interval.ok_or(AggregationParseError::ExpectedKey("calendar_interval")))
+    );
+
+    if let Some(time_zone) = time_zone {
+        builder.time_zone = time_zone;
+    }
+
+    if let Some(min_doc_count) = min_doc_count {
+        builder.min_doc_count = min_doc_count;
+    }
+
+    builder.extended_bounds = extended_bounds;
+
+    Ok(Box::new(builder))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::i64;
+
+    use serde_json;
+    use chrono::{DateTime, UTC};
+
+    use aggregations::{AggregationBuilder, AggregationResult, AggregationParseError, AggregationReduceError, BucketKey, Bucket, datetime_to_millis};
+
+    use super::{DateHistogramAggregationBuilder, DateInterval, CalendarUnit, ExtendedBounds, HOUR, DAY, parse};
+
+    fn millis(date: &str) -> i64 {
+        datetime_to_millis(&date.parse::<DateTime<UTC>>().unwrap())
+    }
+
+    #[test]
+    fn test_parse() {
+        let builder = parse(&serde_json::from_str("
+        {
+            \"field\": \"timestamp\",
+            \"calendar_interval\": \"month\",
+            \"time_zone\": \"-05:00\",
+            \"min_doc_count\": 1,
+            \"extended_bounds\": {
+                \"min\": \"2017-01-01T00:00:00Z\",
+                \"max\": 1500000000000
+            }
+        }
+        ").unwrap());
+
+        assert_eq!(format!("{:?}", builder.unwrap()), format!("{:?}", DateHistogramAggregationBuilder {
+            field: "timestamp".to_string(),
+            interval: DateInterval::Calendar(CalendarUnit::Month),
+            time_zone: -5 * HOUR,
+            min_doc_count: 1,
+            extended_bounds: Some(ExtendedBounds {
+                min: 1483228800000,
+                max: 1500000000000,
+            }),
+            sub_aggregations: Vec::new(),
+        }));
+    }
+
+    #[test]
+    fn test_parse_fixed_interval() {
+        let builder = parse(&serde_json::from_str("
+        {
+            \"field\": \"timestamp\",
+            \"fixed_interval\": \"90m\"
+        }
+        ").unwrap());
+
+        assert_eq!(format!("{:?}", builder.unwrap()), format!("{:?}", DateHistogramAggregationBuilder::new("timestamp", DateInterval::Fixed(90 * 60 * 1000))));
+    }
+
+    #[test]
+    fn test_parse_calendar_interval_not_fixed() {
+        let builder = parse(&serde_json::from_str("
+        {
+            \"field\": \"timestamp\",
+            \"calendar_interval\": \"90m\"
+        }
+        ").unwrap());
+
+        assert_eq!(builder.err(), Some(AggregationParseError::InvalidInterval("90m".to_string())));
+    }
+
+    #[test]
+    fn test_parse_invalid_time_zone() {
+        let builder = parse(&serde_json::from_str("
+        {
+            \"field\": \"timestamp\",
+            \"interval\": \"day\",
+            \"time_zone\": \"Europe/London\"
+        }
+        ").unwrap());
+
+        assert_eq!(builder.err(), Some(AggregationParseError::InvalidTimeZone("Europe/London".to_string())));
+    }
+
+    #[test]
+    fn test_bucket_key_calendar() {
+        let time = millis("2017-05-17T13:45:12Z");
+
+        assert_eq!(DateInterval::Calendar(CalendarUnit::Hour).bucket_key(time, 0), millis("2017-05-17T13:00:00Z"));
+        assert_eq!(DateInterval::Calendar(CalendarUnit::Day).bucket_key(time, 0), millis("2017-05-17T00:00:00Z"));
+        assert_eq!(DateInterval::Calendar(CalendarUnit::Week).bucket_key(time, 0), millis("2017-05-15T00:00:00Z"));
+        assert_eq!(DateInterval::Calendar(CalendarUnit::Month).bucket_key(time, 0), millis("2017-05-01T00:00:00Z"));
+        assert_eq!(DateInterval::Calendar(CalendarUnit::Quarter).bucket_key(time, 0), millis("2017-04-01T00:00:00Z"));
+        assert_eq!(DateInterval::Calendar(CalendarUnit::Year).bucket_key(time, 0), millis("2017-01-01T00:00:00Z"));
+    }
+
+    #[test]
+    fn test_bucket_key_time_zone() {
+        // Still the 31st of December in New York
+        let time = millis("2017-01-01T03:00:00Z");
+
+        assert_eq!(DateInterval::Calendar(CalendarUnit::Day).bucket_key(time, -5 * HOUR), millis("2016-12-31T05:00:00Z"));
+        assert_eq!(DateInterval::Calendar(CalendarUnit::Year).bucket_key(time, -5 * HOUR), millis("2016-01-01T05:00:00Z"));
+    }
+
+    #[test]
+    fn test_next_bucket_key() {
+        let interval = DateInterval::Calendar(CalendarUnit::Month);
+
+        assert_eq!(interval.next_bucket_key(millis("2017-01-01T00:00:00Z"), 0), Some(millis("2017-02-01T00:00:00Z")));
+        assert_eq!(interval.next_bucket_key(millis("2017-12-01T00:00:00Z"), 0), Some(millis("2018-01-01T00:00:00Z")));
+        assert_eq!(DateInterval::Calendar(CalendarUnit::Quarter).next_bucket_key(millis("2017-10-01T00:00:00Z"), 0), Some(millis("2018-01-01T00:00:00Z")));
+        assert_eq!(DateInterval::Fixed(DAY).next_bucket_key(i64::MAX - HOUR, 0), None);
+    }
+
+    #[test]
+    fn test_reduce_fills_gaps() {
+        let builder = DateHistogramAggregationBuilder::new("timestamp", DateInterval::Calendar(CalendarUnit::Month));
+
+        let result = builder.reduce(vec![
+            AggregationResult::Buckets(btreemap! {
+                BucketKey::Integer(millis("2017-01-01T00:00:00Z")) => Bucket::new(2)
+            }),
+            AggregationResult::Buckets(btreemap! {
+                BucketKey::Integer(millis("2017-03-01T00:00:00Z")) => Bucket::new(1)
+            }),
+        ]).unwrap();
+
+        assert_eq!(result, json!({
+            "buckets": [
+                {"key": 1483228800000i64, "key_as_string": "2017-01-01T00:00:00+00:00", "doc_count": 2},
+                {"key": 1485907200000i64, "key_as_string": "2017-02-01T00:00:00+00:00", "doc_count": 0},
+                {"key": 1488326400000i64, "key_as_string": "2017-03-01T00:00:00+00:00", "doc_count": 1},
+            ]
+        }));
+    }
+
+    #[test]
+    fn test_reduce_too_many_buckets() {
+        let mut builder = DateHistogramAggregationBuilder::new("timestamp", DateInterval::Calendar(CalendarUnit::Minute));
+        builder.extended_bounds = Some(ExtendedBounds {
+            min: millis("2017-01-01T00:00:00Z"),
+            max: millis("2018-01-01T00:00:00Z"),
+        });
+
+        assert_eq!(builder.reduce(vec![]), Err(AggregationReduceError::TooManyBuckets));
+    }
+}
@@ -0,0 +1,173 @@
+//! Parses and runs "filter" aggregations
+
+use std::collections::HashSet;
+use std::rc::Rc;
+
+use serde_json;
+use serde_json::Value as Json;
+use kite::Query;
+use kite::schema::Schema;
+use kite_rocksdb::RocksDBIndexReader;
+
+use collectors::AllDocumentsCollector;
+use query_parser::{QueryBuildContext, QueryBuilder, parse as parse_query};
+use aggregations::{Aggregation, AggregationBuilder, AggregationResult, AggregationParseError, AggregationBuildError, AggregationReduceError, Bucket, MergedBucket, build_sub_aggregations};
+
+
+/// Finds the ids of the documents that match both a filter and the search query
+///
+/// The store can't check a single document against a query, so the filter is run as a
+/// separate search and each collected document is looked up in the result. Combining it
+/// with the search query keeps the result down to documents that can be collected.
+pub fn find_matching_documents(filter: &QueryBuilder, context: &QueryBuildContext, schema: &Schema) -> Result<HashSet<u64>, AggregationBuildError> {
+    let index_reader = match context.index_reader {
+        Some(index_reader) => index_reader,
+        None => return Err(AggregationBuildError::SearchFailed),
+    };
+
+    let filter_context = context.clone().no_score();
+    let query = match context.search_query {
+        Some(search_query) => {
+            Query::Filter {
+                query: Box::new(search_query.build(&filter_context, schema)),
+                filter: Box::new(filter.build(&filter_context, schema)),
+            }
+        }
+        None => filter.build(&filter_context, schema),
+    };
+
+    let mut collector = AllDocumentsCollector::new().no_score();
+    try!(index_reader.search(&mut collector, &query).map_err(|_| AggregationBuildError::SearchFailed));
+
+    Ok(collector.into_vec().into_iter().map(|(doc_id, _)| doc_id).collect())
+}
+
+
+#[derive(Debug)]
+pub struct FilterAggregationBuilder {
+    filter: Box<QueryBuilder>,
+    sub_aggregations: Vec<(String, Box<AggregationBuilder>)>,
+}
+
+
+/// Counts the documents that match a filter on one index
+#[derive(Debug)]
+pub struct FilterAggregation {
+    matching_documents: Rc<HashSet<u64>>,
+    doc_count: u64,
+    sub_aggregations: Vec<Box<Aggregation>>,
+}
+
+
+impl Aggregation for FilterAggregation {
+    fn name(&self) -> &'static str {
+        "FilterAggregation"
+    }
+
+    fn collect(&mut self, index_reader: &RocksDBIndexReader, doc_id: u64) {
+        if !self.matching_documents.contains(&doc_id) {
+            return;
+        }
+
+        self.doc_count += 1;
+        for aggregation in self.sub_aggregations.iter_mut() {
+            aggregation.collect(index_reader, doc_id);
+        }
+    }
+
+    fn empty_clone(&self) -> Box<Aggregation> {
+        Box::new(FilterAggregation {
+            matching_documents: self.matching_documents.clone(),
+            doc_count: 0,
+            sub_aggregations: self.sub_aggregations.iter().map(|aggregation| aggregation.empty_clone()).collect(),
+        })
+    }
+
+    fn into_result(self: Box<Self>) -> AggregationResult {
+        AggregationResult::SingleBucket(Bucket {
+            doc_count: self.doc_count,
+            sub_aggregations: self.sub_aggregations.into_iter().map(|aggregation| aggregation.into_result()).collect(),
+        })
+    }
+}
+
+
+impl AggregationBuilder for FilterAggregationBuilder {
+    fn build(&self, context: &QueryBuildContext, schema: &Schema) -> Result<Box<Aggregation>, AggregationBuildError> {
+        Ok(Box::new(FilterAggregation {
+            matching_documents: Rc::new(try!(find_matching_documents(&*self.filter, context, schema))),
+            doc_count: 0,
+            sub_aggregations: try!(build_sub_aggregations(&self.sub_aggregations, context, schema)),
+        }))
+    }
+
+    fn reduce(&self, results: Vec<AggregationResult>) -> Result<Json, AggregationReduceError> {
+        let mut bucket = MergedBucket::new(self.sub_aggregations.len());
+        for result in results {
+            if let AggregationResult::SingleBucket(result_bucket) = result {
+                bucket.merge(result_bucket);
+            }
+        }
+
+        let mut bucket_json = serde_json::Map::new();
+        try!(bucket.add_to_bucket_json(&self.sub_aggregations, &mut bucket_json));
+        Ok(Json::Object(bucket_json))
+    }
+
+    fn set_sub_aggregations(&mut self, sub_aggregations: Vec<(String, Box<AggregationBuilder>)>) -> Result<(), AggregationParseError> {
+        self.sub_aggregations = sub_aggregations;
+        Ok(())
+    }
+}
+
+
+pub fn parse(json: &Json) -> Result<Box<AggregationBuilder>, AggregationParseError> {
+    let filter = try!(parse_query(json).map_err(AggregationParseError::InvalidFilter));
+
+    Ok(Box::new(FilterAggregationBuilder {
+        filter: filter,
+        sub_aggregations: Vec::new(),
+    }))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+
+    use aggregations::{AggregationBuilder, AggregationResult, AggregationParseError, Bucket};
+    use query_parser::QueryParseError;
+
+    use super::parse;
+
+    #[test]
+    fn test_parse_invalid_filter() {
+        let builder = parse(&serde_json::from_str("
+        {
+            \"foo\": {}
+        }
+        ").unwrap());
+
+        assert_eq!(builder.err(), Some(AggregationParseError::InvalidFilter(QueryParseError::UnrecognisedQueryType("foo".to_string()))));
+    }
+
+    #[test]
+    fn test_reduce() {
+        let builder = parse(&serde_json::from_str("
+        {
+            \"term\": {
+                \"genre\": \"fantasy\"
+            }
+        }
+        ").unwrap()).unwrap();
+
+        let result = builder.reduce(vec![
+            AggregationResult::SingleBucket(Bucket::new(3)),
+            AggregationResult::SingleBucket(Bucket::new(4)),
+        ]).unwrap();
+
+        assert_eq!(result, json!({
+            "doc_count": 7,
+        }));
+    }
+}
@@ -0,0 +1,274 @@
+//! Parses and runs "filters" aggregations
+//!
+//! Each filter gets its own bucket. Filters can either be given names (in an object) or be
+//! anonymous (in an array), which decides whether the buckets are returned in an object or
+//! an array. Documents can fall into more than one bucket.
+
+use std::collections::HashSet;
+use std::rc::Rc;
+
+use serde_json;
+use serde_json::Value as Json;
+use kite::schema::Schema;
+use kite_rocksdb::RocksDBIndexReader;
+
+use query_parser::{QueryBuildContext, QueryBuilder, parse as parse_query};
+use aggregations::{Aggregation, AggregationBuilder, AggregationResult, AggregationParseError, AggregationBuildError, AggregationReduceError, BucketKey, BucketCollector, MergedBucket, merge_buckets, build_sub_aggregations};
+use aggregations::filter::find_matching_documents;
+
+
+#[derive(Debug)]
+pub struct FiltersAggregationBuilder {
+    /// The filters with the keys of their buckets
+    ///
+    /// Anonymous filters are keyed by their position.
+    filters: Vec<(BucketKey, Box<QueryBuilder>)>,
+
+    /// True if the filters were given names
+    keyed: bool,
+
+    /// The key of the bucket for documents that don't match any of the filters
+    other_bucket: Option<BucketKey>,
+
+    sub_aggregations: Vec<(String, Box<AggregationBuilder>)>,
+}
+
+
+/// Puts the documents on one index into a bucket for each filter that they match
+#[derive(Debug)]
+pub struct FiltersAggregation {
+    filters: Vec<(BucketKey, Rc<HashSet<u64>>)>,
+    other_bucket: Option<BucketKey>,
+    buckets: BucketCollector,
+}
+
+
+impl Aggregation for FiltersAggregation {
+    fn name(&self) -> &'static str {
+        "FiltersAggregation"
+    }
+
+    fn collect(&mut self, index_reader: &RocksDBIndexReader, doc_id: u64) {
+        let mut matched = false;
+
+        for &(ref key, ref matching_documents) in self.filters.iter() {
+            if matching_documents.contains(&doc_id) {
+                self.buckets.collect(key.clone(), index_reader, doc_id);
+                matched = true;
+            }
+        }
+
+        if !matched {
+            if let Some(ref other_bucket) = self.other_bucket {
+                self.buckets.collect(other_bucket.clone(), index_reader, doc_id);
+            }
+        }
+    }
+
+    fn empty_clone(&self) -> Box<Aggregation> {
+        Box::new(FiltersAggregation {
+            filters: self.filters.clone(),
+            other_bucket: self.other_bucket.clone(),
+            buckets: self.buckets.empty_clone(),
+        })
+    }
+
+    fn into_result(self: Box<Self>) -> AggregationResult {
+        self.buckets.into_result()
+    }
+}
+
+
+impl AggregationBuilder for FiltersAggregationBuilder {
+    fn build(&self, context: &QueryBuildContext, schema: &Schema) -> Result<Box<Aggregation>, AggregationBuildError> {
+        let mut filters = Vec::with_capacity(self.filters.len());
+        for &(ref key, ref filter) in self.filters.iter() {
+            filters.push((key.clone(), Rc::new(try!(find_matching_documents(&**filter, context, schema)))));
+        }
+
+        Ok(Box::new(FiltersAggregation {
+            filters: filters,
+            other_bucket: self.other_bucket.clone(),
+            buckets: BucketCollector::new(try!(build_sub_aggregations(&self.sub_aggregations, context, schema))),
+        }))
+    }
+
+    fn reduce(&self, results: Vec<AggregationResult>) -> Result<Json, AggregationReduceError> {
+        let mut buckets = merge_buckets(results, self.sub_aggregations.len());
+
+        // Every filter gets a bucket, even if no documents matched it
+        let keys = self.filters.iter().map(|&(ref key, _)| key).chain(self.other_bucket.iter());
+        let buckets_json = try!(keys.map(|key| {
+            let bucket = buckets.remove(key).unwrap_or_else(|| MergedBucket::new(self.sub_aggregations.len()));
+            let mut bucket_json = serde_json::Map::new();
+            try!(bucket.add_to_bucket_json(&self.sub_aggregations, &mut bucket_json));
+            Ok((key, Json::Object(bucket_json)))
+        }).collect::<Result<Vec<_>, _>>());
+
+        if self.keyed {
+            let mut buckets_object = serde_json::Map::new();
+            for (key, bucket_json) in buckets_json {
+                if let BucketKey::String(ref name) = *key {
+                    buckets_object.insert(name.clone(), bucket_json);
+                }
+            }
+
+            Ok(json!({
+                "buckets": buckets_object,
+            }))
+        } else {
+            Ok(json!({
+                "buckets": buckets_json.into_iter().map(|(_, bucket_json)| bucket_json).collect::<Vec<_>>(),
+            }))
+        }
+    }
+
+    fn set_sub_aggregations(&mut self, sub_aggregations: Vec<(String, Box<AggregationBuilder>)>) -> Result<(), AggregationParseError> {
+        self.sub_aggregations = sub_aggregations;
+        Ok(())
+    }
+}
+
+
+pub fn parse(json: &Json) -> Result<Box<AggregationBuilder>, AggregationParseError> {
+    let object = try!(json.as_object().ok_or(AggregationParseError::ExpectedObject));
+
+    let mut filters = None;
+    let mut keyed = false;
+    let mut other_bucket = false;
+    let mut other_bucket_key = None;
+
+    for (key, value) in object.iter() {
+        match key.as_ref() {
+            "filters" => {
+                let mut parsed_filters = Vec::new();
+
+                match *value {
+                    Json::Object(ref named_filters) => {
+                        keyed = true;
+                        for (name, filter) in named_filters.iter() {
+                            let filter = try!(parse_query(filter).map_err(AggregationParseError::InvalidFilter));
+                            parsed_filters.push((BucketKey::String(name.clone()), filter));
+                        }
+                    }
+                    Json::Array(ref anonymous_filters) => {
+                        for (position, filter) in anonymous_filters.iter().enumerate() {
+                            let filter = try!(parse_query(filter).map_err(AggregationParseError::InvalidFilter));
+                            parsed_filters.push((BucketKey::Integer(position as i64), filter));
+                        }
+                    }
+                    _ => return Err(AggregationParseError::ExpectedObjectOrArray),
+                }
+
+                filters = Some(parsed_filters);
+            }
+            "other_bucket" => {
+                other_bucket = try!(value.as_bool().ok_or(AggregationParseError::ExpectedBoolean));
+            }
+            "other_bucket_key" => {
+                other_bucket_key = Some(try!(value.as_str().ok_or(AggregationParseError::ExpectedString)).to_string());
+            }
+            _ => return Err(AggregationParseError::UnrecognisedKey(key.clone())),
+        }
+    }
+
+    let filters = try!(filters.ok_or(AggregationParseError::ExpectedKey("filters")));
+
+    // Setting a key for the other bucket turns it on
+    let other_bucket = if other_bucket || other_bucket_key.is_some() {
+        if keyed {
+            Some(BucketKey::String(other_bucket_key.unwrap_or_else(|| "_other_".to_string())))
+        } else {
+            Some(BucketKey::Integer(filters.len() as i64))
+        }
+    } else {
+        None
+    };
+
+    Ok(Box::new(FiltersAggregationBuilder {
+        filters: filters,
+        keyed: keyed,
+        other_bucket: other_bucket,
+        sub_aggregations: Vec::new(),
+    }))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+
+    use aggregations::{AggregationBuilder, AggregationResult, AggregationParseError, BucketKey, Bucket};
+
+    use super::parse;
+
+    #[test]
+    fn test_parse_requires_filters() {
+        let builder = parse(&serde_json::from_str("
+        {
+            \"other_bucket\": true
+        }
+        ").unwrap());
+
+        assert_eq!(builder.err(), Some(AggregationParseError::ExpectedKey("filters")));
+    }
+
+    #[test]
+    fn test_reduce_keyed() {
+        let builder = parse(&serde_json::from_str("
+        {
+            \"filters\": {
+                \"fantasy\": {\"term\": {\"genre\": \"fantasy\"}},
+                \"horror\": {\"term\": {\"genre\": \"horror\"}}
+            },
+            \"other_bucket_key\": \"everything_else\"
+        }
+        ").unwrap()).unwrap();
+
+        let result = builder.reduce(vec![
+            AggregationResult::Buckets(btreemap! {
+                BucketKey::String("fantasy".to_string()) => Bucket::new(2),
+                BucketKey::String("everything_else".to_string()) => Bucket::new(1)
+            }),
+            AggregationResult::Buckets(btreemap! {
+                BucketKey::String("fantasy".to_string()) => Bucket::new(1)
+            }),
+        ]).unwrap();
+
+        assert_eq!(result, json!({
+            "buckets": {
+                "fantasy": {"doc_count": 3},
+                "horror": {"doc_count": 0},
+                "everything_else": {"doc_count": 1},
+            }
+        }));
+    }
+
+    #[test]
+    fn test_reduce_anonymous() {
+        let builder = parse(&serde_json::from_str("
+        {
+            \"filters\": [
+                {\"term\": {\"genre\": \"fantasy\"}},
+                {\"term\": {\"genre\": \"horror\"}}
+            ],
+            \"other_bucket\": true
+        }
+        ").unwrap()).unwrap();
+
+        let result = builder.reduce(vec![
+            AggregationResult::Buckets(btreemap! {
+                BucketKey::Integer(1) => Bucket::new(4),
+                BucketKey::Integer(2) => Bucket::new(5)
+            }),
+        ]).unwrap();
+
+        assert_eq!(result, json!({
+            "buckets": [
+                {"doc_count": 0},
+                {"doc_count": 4},
+                {"doc_count": 5},
+            ]
+        }));
+    }
+}
@@ -0,0 +1,353 @@
+//! Parses and runs "geohash_grid" and "geotile_grid" aggregations
+//!
+//! Both put points into the cells of a grid over the map. "geohash_grid" uses geohash
+//! cells, keyed by their geohash. "geotile_grid" uses Web Mercator map tiles, keyed by
+//! `"zoom/x/y"`, which line up with the tiles that mapping libraries render.
+
+use kite::document::{DocRef, FieldValue};
+use kite::schema::{Schema, FieldRef};
+use kite_rocksdb::RocksDBIndexReader;
+use serde_json;
+use serde_json::Value as Json;
+
+use geo;
+use mapping::FieldType;
+use query_parser::QueryBuildContext;
+use aggregations::{Aggregation, AggregationBuilder, AggregationResult, AggregationParseError, AggregationBuildError, AggregationReduceError, BucketKey, BucketCollector, merge_buckets, build_sub_aggregations};
+
+
+const DEFAULT_SIZE: usize = 10000;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GeoGrid {
+    /// Geohash cells, precision is the length of the geohash (1 - 12)
+    Geohash,
+
+    /// Map tiles, precision is the zoom level (0 - 29)
+    Geotile,
+}
+
+
+impl GeoGrid {
+    fn default_precision(&self) -> u32 {
+        match *self {
+            GeoGrid::Geohash => 5,
+            GeoGrid::Geotile => 7,
+        }
+    }
+
+    fn precision_range(&self) -> (u32, u32) {
+        match *self {
+            GeoGrid::Geohash => (1, geo::MAX_PRECISION as u32),
+            GeoGrid::Geotile => (0, 29),
+        }
+    }
+
+    fn cell_key(&self, point: &geo::GeoPoint, precision: u32) -> String {
+        match *self {
+            GeoGrid::Geohash => point.to_geohash(precision as usize),
+            GeoGrid::Geotile => {
+                let (x, y) = point.to_geotile(precision);
+                format!("{}/{}/{}", precision, x, y)
+            }
+        }
+    }
+}
+
+
+#[derive(Debug)]
+pub struct GeoGridAggregationBuilder {
+    field: String,
+    grid: GeoGrid,
+    precision: u32,
+
+    /// The most buckets to return
+    size: usize,
+
+    /// The most buckets to return from each index, the ones with the most documents are kept
+    shard_size: usize,
+
+    sub_aggregations: Vec<(String, Box<AggregationBuilder>)>,
+}
+
+
+impl GeoGridAggregationBuilder {
+    pub fn new(field: &str, grid: GeoGrid) -> GeoGridAggregationBuilder {
+        GeoGridAggregationBuilder {
+            field: field.to_string(),
+            grid: grid,
+            precision: grid.default_precision(),
+            size: DEFAULT_SIZE,
+            shard_size: DEFAULT_SIZE,
+            sub_aggregations: Vec::new(),
+        }
+    }
+}
+
+
+/// Removes all but the buckets with the most documents
+fn prune_buckets(result: AggregationResult, size: usize) -> AggregationResult {
+    match result {
+        AggregationResult::Buckets(buckets) => {
+            if buckets.len() <= size {
+                return AggregationResult::Buckets(buckets);
+            }
+
+            let mut buckets = buckets.into_iter().collect::<Vec<_>>();
+            buckets.sort_by(|a, b| (b.1.doc_count, &a.0).cmp(&(a.1.doc_count, &b.0)));
+            AggregationResult::Buckets(buckets.into_iter().take(size).collect())
+        }
+        result => result,
+    }
+}
+
+
+/// Counts the points in each cell of the grid on one index
+#[derive(Debug)]
+pub struct GeoGridAggregation {
+    field_ref: Option<FieldRef>,
+    grid: GeoGrid,
+    precision: u32,
+    shard_size: usize,
+    buckets: BucketCollector,
+}
+
+
+impl Aggregation for GeoGridAggregation {
+    fn name(&self) -> &'static str {
+        "GeoGridAggregation"
+    }
+
+    fn collect(&mut self, index_reader: &RocksDBIndexReader, doc_id: u64) {
+        let field_ref = match self.field_ref {
+            Some(field_ref) => field_ref,
+            None => return,
+        };
+
+        if let Ok(Some(FieldValue::String(points))) = index_reader.read_stored_field(field_ref, DocRef::from_u64(doc_id)) {
+            let mut keys = geo::parse_stored_points(&points).iter().map(|point| self.grid.cell_key(point, self.precision)).collect::<Vec<_>>();
+
+            // Documents with multiple points in the same cell are only counted once
+            keys.sort();
+            keys.dedup();
+
+            for key in keys {
+                self.buckets.collect(BucketKey::String(key), index_reader, doc_id);
+            }
+        }
+    }
+
+    fn empty_clone(&self) -> Box<Aggregation> {
+        Box::new(GeoGridAggregation {
+            field_ref: self.field_ref,
+            grid: self.grid,
+            precision: self.precision,
+            shard_size: self.shard_size,
+            buckets: self.buckets.empty_clone(),
+        })
+    }
+
+    fn into_result(self: Box<Self>) -> AggregationResult {
+        let shard_size = self.shard_size;
+        prune_buckets(self.buckets.into_result(), shard_size)
+    }
+}
+
+
+impl AggregationBuilder for GeoGridAggregationBuilder {
+    fn build(&self, context: &QueryBuildContext, schema: &Schema) -> Result<Box<Aggregation>, AggregationBuildError> {
+        let field_ref = match context.index_metadata.and_then(|index_metadata| index_metadata.get_field_mapping(&self.field)) {
+            Some(field_mapping) => {
+                if field_mapping.data_type != FieldType::GeoPoint {
+                    return Err(AggregationBuildError::FieldNotGeoPoint(self.field.clone()));
+                }
+
+                if !field_mapping.has_doc_values && !field_mapping.is_stored {
+                    return Err(AggregationBuildError::FieldNotAggregatable(self.field.clone()));
+                }
+
+                schema.get_field_by_name(&self.field)
+            }
+            None => None,
+        };
+
+        Ok(Box::new(GeoGridAggregation {
+            field_ref: field_ref,
+            grid: self.grid,
+            precision: self.precision,
+            shard_size: self.shard_size,
+            buckets: BucketCollector::new(try!(build_sub_aggregations(&self.sub_aggregations, context, schema))),
+        }))
+    }
+
+    fn reduce(&self, results: Vec<AggregationResult>) -> Result<Json, AggregationReduceError> {
+        let mut buckets = merge_buckets(results, self.sub_aggregations.len()).into_iter().collect::<Vec<_>>();
+        buckets.sort_by(|a, b| (b.1.doc_count, &a.0).cmp(&(a.1.doc_count, &b.0)));
+
+        let buckets_json = try!(buckets.into_iter().take(self.size).map(|(key, bucket)| {
+            let mut bucket_json = serde_json::Map::new();
+            key.add_to_bucket_json(&mut bucket_json);
+            try!(bucket.add_to_bucket_json(&self.sub_aggregations, &mut bucket_json));
+            Ok(Json::Object(bucket_json))
+        }).collect::<Result<Vec<_>, _>>());
+
+        Ok(json!({
+            "buckets": buckets_json,
+        }))
+    }
+
+    fn set_sub_aggregations(&mut self, sub_aggregations: Vec<(String, Box<AggregationBuilder>)>) -> Result<(), AggregationParseError> {
+        self.sub_aggregations = sub_aggregations;
+        Ok(())
+    }
+}
+
+
+fn parse(json: &Json, grid: GeoGrid) -> Result<Box<AggregationBuilder>, AggregationParseError> {
+    let object = try!(json.as_object().ok_or(AggregationParseError::ExpectedObject));
+
+    let mut field = None;
+    let mut precision = None;
+    let mut size = None;
+    let mut shard_size = None;
+
+    for (key, value) in object.iter() {
+        match key.as_ref() {
+            "field" => {
+                field = Some(try!(value.as_str().ok_or(AggregationParseError::ExpectedString)));
+            }
+            "precision" => {
+                let (min, max) = grid.precision_range();
+                match value.as_u64() {
+                    Some(value) if value >= min as u64 && value <= max as u64 => precision = Some(value as u32),
+                    _ => return Err(AggregationParseError::InvalidPrecision),
+                }
+            }
+            "size" => {
+                size = Some(try!(value.as_u64().ok_or(AggregationParseError::ExpectedPositiveInteger)) as usize);
+            }
+            "shard_size" => {
+                shard_size = Some(try!(value.as_u64().ok_or(AggregationParseError::ExpectedPositiveInteger)) as usize);
+            }
+            _ => return Err(AggregationParseError::UnrecognisedKey(key.clone())),
+        }
+    }
+
+    let mut builder = GeoGridAggregationBuilder::new(try!(field.ok_or(AggregationParseError::ExpectedKey("field"))), grid);
+
+    if let Some(precision) = precision {
+        builder.precision = precision;
+    }
+
+    if let Some(size) = size {
+        builder.size = size;
+    }
+
+    // Each index must return at least as many buckets as the response needs
+    builder.shard_size = match shard_size {
+        Some(shard_size) if shard_size > builder.size => shard_size,
+        _ => builder.size,
+    };
+
+    Ok(Box::new(builder))
+}
+
+
+pub fn parse_geohash_grid(json: &Json) -> Result<Box<AggregationBuilder>, AggregationParseError> {
+    parse(json, GeoGrid::Geohash)
+}
+
+
+pub fn parse_geotile_grid(json: &Json) -> Result<Box<AggregationBuilder>, AggregationParseError> {
+    parse(json, GeoGrid::Geotile)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+
+    use geo::GeoPoint;
+    use aggregations::{AggregationBuilder, AggregationResult, AggregationParseError, BucketKey, Bucket};
+
+    use super::{GeoGrid, GeoGridAggregationBuilder, prune_buckets, parse_geohash_grid, parse_geotile_grid};
+
+    #[test]
+    fn test_parse() {
+        let builder = parse_geotile_grid(&serde_json::from_str("
+        {
+            \"field\": \"location\",
+            \"precision\": 8,
+            \"size\": 100
+        }
+        ").unwrap());
+
+        assert_eq!(format!("{:?}", builder.unwrap()), format!("{:?}", GeoGridAggregationBuilder {
+            precision: 8,
+            size: 100,
+            shard_size: 100,
+            ..GeoGridAggregationBuilder::new("location", GeoGrid::Geotile)
+        }));
+    }
+
+    #[test]
+    fn test_parse_invalid_precision() {
+        let builder = parse_geohash_grid(&serde_json::from_str("
+        {
+            \"field\": \"location\",
+            \"precision\": 13
+        }
+        ").unwrap());
+
+        assert_eq!(builder.err(), Some(AggregationParseError::InvalidPrecision));
+    }
+
+    #[test]
+    fn test_cell_key() {
+        let point = GeoPoint::new(51.5, -0.12).unwrap();
+
+        assert_eq!(GeoGrid::Geohash.cell_key(&point, 3), "gcp");
+        assert_eq!(GeoGrid::Geotile.cell_key(&point, 8), "8/127/85");
+    }
+
+    #[test]
+    fn test_prune_buckets() {
+        let result = prune_buckets(AggregationResult::Buckets(btreemap! {
+            BucketKey::String("gcp".to_string()) => Bucket::new(2),
+            BucketKey::String("u10".to_string()) => Bucket::new(5),
+            BucketKey::String("u09".to_string()) => Bucket::new(2)
+        }), 2);
+
+        assert_eq!(result, AggregationResult::Buckets(btreemap! {
+            BucketKey::String("gcp".to_string()) => Bucket::new(2),
+            BucketKey::String("u10".to_string()) => Bucket::new(5)
+        }));
+    }
+
+    #[test]
+    fn test_reduce() {
+        let builder = GeoGridAggregationBuilder {
+            size: 2,
+            ..GeoGridAggregationBuilder::new("location", GeoGrid::Geohash)
+        };
+
+        let result = builder.reduce(vec![
+            AggregationResult::Buckets(btreemap! {
+                BucketKey::String("gcp".to_string()) => Bucket::new(2),
+                BucketKey::String("u09".to_string()) => Bucket::new(1)
+            }),
+            AggregationResult::Buckets(btreemap! {
+                BucketKey::String("u09".to_string()) => Bucket::new(3),
+                BucketKey::String("u10".to_string()) => Bucket::new(1)
+            }),
+        ]).unwrap();
+
+        assert_eq!(result, json!({
+            "buckets": [
+                {"key": "u09", "doc_count": 4},
+                {"key": "gcp", "doc_count": 2},
+            ]
+        }));
+    }
+}
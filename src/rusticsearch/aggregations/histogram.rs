@@ -0,0 +1,431 @@
+//! Parses and runs "histogram" aggregations
+
+use std::collections::BTreeMap;
+
+use serde_json;
+use serde_json::Value as Json;
+use kite::document::{DocRef, FieldValue};
+use kite::schema::{Schema, FieldRef};
+use kite_rocksdb::RocksDBIndexReader;
+
+use query_parser::QueryBuildContext;
+use mapping::FieldType;
+use aggregations::{Aggregation, AggregationBuilder, AggregationResult, AggregationParseError, AggregationBuildError, AggregationReduceError, MAX_BUCKETS, BucketKey, BucketCollector, MergedBucket, merge_buckets, build_sub_aggregations};
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExtendedBounds {
+    pub min: i64,
+    pub max: i64,
+}
+
+
+#[derive(Debug)]
+pub struct HistogramAggregationBuilder {
+    field: String,
+    interval: i64,
+    offset: i64,
+    min_doc_count: u64,
+    extended_bounds: Option<ExtendedBounds>,
+    sub_aggregations: Vec<(String, Box<AggregationBuilder>)>,
+}
+
+
+impl HistogramAggregationBuilder {
+    pub fn new(field: &str, interval: i64) -> HistogramAggregationBuilder {
+        HistogramAggregationBuilder {
+            field: field.to_string(),
+            interval: interval,
+            offset: 0,
+            min_doc_count: 0,
+            extended_bounds: None,
+            sub_aggregations: Vec::new(),
+        }
+    }
+}
+
+
+/// Finds the key of the bucket that the value falls into
+pub fn bucket_key(value: i64, interval: i64, offset: i64) -> i64 {
+    // Rounds towards negative infinity so negative values go in the right bucket
+    let remainder = ((value - offset) % interval + interval) % interval;
+    value - remainder
+}
+
+
+/// Counts the documents in each bucket of a numeric field on one index
+#[derive(Debug)]
+pub struct HistogramAggregation {
+    field_ref: Option<FieldRef>,
+    interval: i64,
+    offset: i64,
+    buckets: BucketCollector,
+}
+
+
+impl Aggregation for HistogramAggregation {
+    fn name(&self) -> &'static str {
+        "HistogramAggregation"
+    }
+
+    fn collect(&mut self, index_reader: &RocksDBIndexReader, doc_id: u64) {
+        let field_ref = match self.field_ref {
+            Some(field_ref) => field_ref,
+            None => return,
+        };
+
+        if let Ok(Some(FieldValue::Integer(value))) = index_reader.read_stored_field(field_ref, DocRef::from_u64(doc_id)) {
+            self.buckets.collect(BucketKey::Integer(bucket_key(value, self.interval, self.offset)), index_reader, doc_id);
+        }
+    }
+
+    fn empty_clone(&self) -> Box<Aggregation> {
+        Box::new(HistogramAggregation {
+            field_ref: self.field_ref,
+            interval: self.interval,
+            offset: self.offset,
+            buckets: self.buckets.empty_clone(),
+        })
+    }
+
+    fn into_result(self: Box<Self>) -> AggregationResult {
+        self.buckets.into_result()
+    }
+}
+
+
+impl AggregationBuilder for HistogramAggregationBuilder {
+    fn build(&self, context: &QueryBuildContext, schema: &Schema) -> Result<Box<Aggregation>, AggregationBuildError> {
+        let field_ref = match context.index_metadata.and_then(|index_metadata| index_metadata.get_field_mapping(&self.field)) {
+            Some(field_mapping) => {
+                if field_mapping.data_type != FieldType::Integer {
+                    return Err(AggregationBuildError::FieldNotNumeric(self.field.clone()));
+                }
+
+                if !field_mapping.has_doc_values && !field_mapping.is_stored {
+                    return Err(AggregationBuildError::FieldNotAggregatable(self.field.clone()));
+                }
+
+                schema.get_field_by_name(&self.field)
+            }
+            None => None,
+        };
+
+        Ok(Box::new(HistogramAggregation {
+            field_ref: field_ref,
+            interval: self.interval,
+            offset: self.offset,
+            buckets: BucketCollector::new(try!(build_sub_aggregations(&self.sub_aggregations, context, schema))),
+        }))
+    }
+
+    fn reduce(&self, results: Vec<AggregationResult>) -> Result<Json, AggregationReduceError> {
+        let mut buckets = merge_buckets(results, self.sub_aggregations.len()).into_iter().filter_map(|(key, bucket)| {
+            match key {
+                BucketKey::Integer(key) => Some((key, bucket)),
+                _ => None,
+            }
+        }).collect::<BTreeMap<i64, MergedBucket>>();
+
+        // Fill in the empty buckets between the first and last bucket, widened by the extended bounds
+        if self.min_doc_count == 0 {
+            let mut first = buckets.keys().next().cloned();
+            let mut last = buckets.keys().next_back().cloned();
+
+            if let Some(extended_bounds) = self.extended_bounds {
+                let min = bucket_key(extended_bounds.min, self.interval, self.offset);
+                let max = bucket_key(extended_bounds.max, self.interval, self.offset);
+                first = Some(first.map_or(min, |first| if min < first { min } else { first }));
+                last = Some(last.map_or(max, |last| if max > last { max } else { last }));
+            }
+
+            if let (Some(first), Some(last)) = (first, last) {
+                let num_sub_aggregations = self.sub_aggregations.len();
+                let mut key = first;
+                while key <= last {
+                    buckets.entry(key).or_insert_with(|| MergedBucket::new(num_sub_aggregations));
+                    if buckets.len() > MAX_BUCKETS {
+                        return Err(AggregationReduceError::TooManyBuckets);
+                    }
+
+                    key = match key.checked_add(self.interval) {
+                        Some(key) => key,
+                        None => break,
+                    };
+                }
+            }
+        }
+
+        let buckets_json = try!(buckets.into_iter().filter(|&(_, ref bucket)| bucket.doc_count >= self.min_doc_count).map(|(key, bucket)| {
+            let mut bucket_json = serde_json::Map::new();
+            BucketKey::Integer(key).add_to_bucket_json(&mut bucket_json);
+            try!(bucket.add_to_bucket_json(&self.sub_aggregations, &mut bucket_json));
+            Ok(Json::Object(bucket_json))
+        }).collect::<Result<Vec<_>, _>>());
+
+        Ok(json!({
+            "buckets": buckets_json,
+        }))
+    }
+
+    fn set_sub_aggregations(&mut self, sub_aggregations: Vec<(String, Box<AggregationBuilder>)>) -> Result<(), AggregationParseError> {
+        self.sub_aggregations = sub_aggregations;
+        Ok(())
+    }
+}
+
+
+fn parse_extended_bounds(json: &Json) -> Result<ExtendedBounds, AggregationParseError> {
+    let object = try!(json.as_object().ok_or(AggregationParseError::ExpectedObject));
+
+    let mut min = None;
+    let mut max = None;
+
+    for (key, value) in object.iter() {
+        match key.as_ref() {
+            "min" => {
+                min = Some(try!(value.as_i64().ok_or(AggregationParseError::ExpectedInteger)));
+            }
+            "max" => {
+                max = Some(try!(value.as_i64().ok_or(AggregationParseError::ExpectedInteger)));
+            }
+            _ => return Err(AggregationParseError::UnrecognisedKey(key.clone())),
+        }
+    }
+
+    Ok(ExtendedBounds {
+        min: try!(min.ok_or(AggregationParseError::ExpectedKey("min"))),
+        max: try!(max.ok_or(AggregationParseError::ExpectedKey("max"))),
+    })
+}
+
+
+pub fn parse(json: &Json) -> Result<Box<AggregationBuilder>, AggregationParseError> {
+    let object = try!(json.as_object().ok_or(AggregationParseError::ExpectedObject));
+
+    let mut field = None;
+    let mut interval = None;
+    let mut offset = None;
+    let mut min_doc_count = None;
+    let mut extended_bounds = None;
+
+    for (key, value) in object.iter() {
+        match key.as_ref() {
+            "field" => {
+                field = Some(try!(value.as_str().ok_or(AggregationParseError::ExpectedString)));
+            }
+            "interval" => {
+                match value.as_u64() {
+                    Some(value) if value > 0 => interval = Some(value as i64),
+                    _ => return Err(AggregationParseError::ExpectedPositiveInteger),
+                }
+            }
+            "offset" => {
+                offset = Some(try!(value.as_i64().ok_or(AggregationParseError::ExpectedInteger)));
+            }
+            "min_doc_count" => {
+                min_doc_count = Some(try!(value.as_u64().ok_or(AggregationParseError::ExpectedPositiveInteger)));
+            }
+            "extended_bounds" => {
+                extended_bounds = Some(try!(parse_extended_bounds(value)));
+            }
+            _ => return Err(AggregationParseError::UnrecognisedKey(key.clone())),
+        }
+    }
+
+    let mut builder = HistogramAggregationBuilder::new(
+        try!(field.ok_or(AggregationParseError::ExpectedKey("field"))),
+        try!(interval.ok_or(AggregationParseError::ExpectedKey("interval")))
+    );
+
+    if let Some(offset) = offset {
+        // Only the offset within an interval matters
+        builder.offset = offset % builder.interval;
+    }
+
+    if let Some(min_doc_count) = min_doc_count {
+        builder.min_doc_count = min_doc_count;
+    }
+
+    builder.extended_bounds = extended_bounds;
+
+    Ok(Box::new(builder))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::i64;
+
+    use serde_json;
+
+    use aggregations::{AggregationBuilder, AggregationResult, AggregationParseError, AggregationReduceError, BucketKey, Bucket};
+
+    use super::{HistogramAggregationBuilder, ExtendedBounds, bucket_key, parse};
+
+    #[test]
+    fn test_parse() {
+        let builder = parse(&serde_json::from_str("
+        {
+            \"field\": \"price\",
+            \"interval\": 50,
+            \"offset\": 10,
+            \"min_doc_count\": 1,
+            \"extended_bounds\": {
+                \"min\": 0,
+                \"max\": 500
+            }
+        }
+        ").unwrap());
+
+        assert_eq!(format!("{:?}", builder.unwrap()), format!("{:?}", HistogramAggregationBuilder {
+            field: "price".to_string(),
+            interval: 50,
+            offset: 10,
+            min_doc_count: 1,
+            extended_bounds: Some(ExtendedBounds {
+                min: 0,
+                max: 500,
+            }),
+            sub_aggregations: Vec::new(),
+        }));
+    }
+
+    #[test]
+    fn test_parse_missing_interval() {
+        let builder = parse(&serde_json::from_str("
+        {
+            \"field\": \"price\"
+        }
+        ").unwrap());
+
+        assert_eq!(builder.err(), Some(AggregationParseError::ExpectedKey("interval")));
+    }
+
+    #[test]
+    fn test_parse_zero_interval() {
+        let builder = parse(&serde_json::from_str("
+        {
+            \"field\": \"price\",
+            \"interval\": 0
+        }
+        ").unwrap());
+
+        assert_eq!(builder.err(), Some(AggregationParseError::ExpectedPositiveInteger));
+    }
+
+    #[test]
+    fn test_bucket_key() {
+        assert_eq!(bucket_key(5, 10, 5), 5);
+        assert_eq!(bucket_key(14, 10, 5), 5);
+        assert_eq!(bucket_key(15, 10, 5), 15);
+        assert_eq!(bucket_key(4, 10, 5), -5);
+        assert_eq!(bucket_key(-6, 10, 5), -15);
+    }
+
+    #[test]
+    fn test_reduce_fills_gaps() {
+        let builder = HistogramAggregationBuilder::new("price", 10);
+
+        let result = builder.reduce(vec![
+            AggregationResult::Buckets(btreemap! {
+                BucketKey::Integer(0) => Bucket::new(2),
+                BucketKey::Integer(30) => Bucket::new(1)
+            }),
+            AggregationResult::Buckets(btreemap! {
+                BucketKey::Integer(30) => Bucket::new(1)
+            }),
+        ]).unwrap();
+
+        assert_eq!(result, json!({
+            "buckets": [
+                {"key": 0, "doc_count": 2},
+                {"key": 10, "doc_count": 0},
+                {"key": 20, "doc_count": 0},
+                {"key": 30, "doc_count": 2},
+            ]
+        }));
+    }
+
+    #[test]
+    fn test_reduce_extended_bounds() {
+        let builder = HistogramAggregationBuilder {
+            extended_bounds: Some(ExtendedBounds {
+                min: -5,
+                max: 25,
+            }),
+            ..HistogramAggregationBuilder::new("price", 10)
+        };
+
+        let result = builder.reduce(vec![
+            AggregationResult::Buckets(btreemap! {
+                BucketKey::Integer(10) => Bucket::new(1)
+            }),
+        ]).unwrap();
+
+        assert_eq!(result, json!({
+            "buckets": [
+                {"key": -10, "doc_count": 0},
+                {"key": 0, "doc_count": 0},
+                {"key": 10, "doc_count": 1},
+                {"key": 20, "doc_count": 0},
+            ]
+        }));
+    }
+
+    #[test]
+    fn test_reduce_too_many_buckets() {
+        let builder = HistogramAggregationBuilder {
+            extended_bounds: Some(ExtendedBounds {
+                min: 0,
+                max: 1000000,
+            }),
+            ..HistogramAggregationBuilder::new("price", 1)
+        };
+
+        assert_eq!(builder.reduce(vec![]), Err(AggregationReduceError::TooManyBuckets));
+    }
+
+    #[test]
+    fn test_reduce_last_bucket_near_max() {
+        let builder = HistogramAggregationBuilder::new("price", 10);
+
+        let result = builder.reduce(vec![
+            AggregationResult::Buckets(btreemap! {
+                BucketKey::Integer(i64::MAX - 7) => Bucket::new(1)
+            }),
+        ]).unwrap();
+
+        assert_eq!(result, json!({
+            "buckets": [
+                {"key": i64::MAX - 7, "doc_count": 1},
+            ]
+        }));
+    }
+
+    #[test]
+    fn test_reduce_min_doc_count() {
+        let builder = HistogramAggregationBuilder {
+            min_doc_count: 1,
+            extended_bounds: Some(ExtendedBounds {
+                min: 0,
+                max: 100,
+            }),
+            ..HistogramAggregationBuilder::new("price", 10)
+        };
+
+        let result = builder.reduce(vec![
+            AggregationResult::Buckets(btreemap! {
+                BucketKey::Integer(10) => Bucket::new(1),
+                BucketKey::Integer(40) => Bucket::new(3)
+            }),
+        ]).unwrap();
+
+        assert_eq!(result, json!({
+            "buckets": [
+                {"key": 10, "doc_count": 1},
+                {"key": 40, "doc_count": 3},
+            ]
+        }));
+    }
+}
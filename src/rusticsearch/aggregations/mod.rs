@@ -0,0 +1,494 @@
+//! Parses and runs the aggregations in the "aggs" section of a search request
+//!
+//! Aggregations run in two phases. While the query is running, each index that's searched
+//! builds its own `Aggregation` which collects every matching document. Once all of the
+//! indices have been searched, their results are reduced into the response by the
+//! `AggregationBuilder` that was parsed from the request.
+
+pub mod terms;
+pub mod histogram;
+pub mod date_histogram;
+pub mod stats;
+pub mod cardinality;
+pub mod filter;
+pub mod filters;
+pub mod geo_grid;
+
+use std::fmt::{self, Debug};
+use std::collections::BTreeMap;
+
+use serde_json;
+use serde_json::Value as Json;
+use chrono::{DateTime, UTC, Timelike};
+use kite::document::FieldValue;
+use kite::schema::Schema;
+use kite_rocksdb::RocksDBIndexReader;
+
+use query_parser::{QueryBuildContext, QueryParseError};
+
+
+#[derive(Debug, PartialEq)]
+pub enum AggregationParseError {
+    UnrecognisedAggregationType(String),
+    UnrecognisedKey(String),
+    ExpectedKey(&'static str),
+    ExpectedObject,
+    ExpectedString,
+    ExpectedBoolean,
+    ExpectedObjectOrArray,
+    ExpectedSingleKey,
+    ExpectedInteger,
+    ExpectedPositiveInteger,
+    ExpectedPositiveNumber,
+    InvalidOrder,
+    InvalidInterval(String),
+    InvalidTimeZone(String),
+    InvalidDate(String),
+    InvalidPrecision,
+    SubAggregationsNotAllowed,
+    InvalidFilter(QueryParseError),
+    InvalidAggregation(String, Box<AggregationParseError>),
+}
+
+
+#[derive(Debug, PartialEq)]
+pub enum AggregationBuildError {
+    /// The field's values can't be read back from the index
+    FieldNotAggregatable(String),
+
+    /// The aggregation only works on numeric fields
+    FieldNotNumeric(String),
+
+    /// The aggregation only works on date fields
+    FieldNotDate(String),
+
+    /// The aggregation only works on geo_point fields
+    FieldNotGeoPoint(String),
+
+    /// A filter of the aggregation couldn't be run on the index
+    SearchFailed,
+}
+
+
+/// The most buckets an aggregation can return, like Elasticsearch's "search.max_buckets"
+pub const MAX_BUCKETS: usize = 65535;
+
+
+#[derive(Debug, PartialEq)]
+pub enum AggregationReduceError {
+    /// Filling in the empty buckets of a histogram would go over `MAX_BUCKETS`
+    TooManyBuckets,
+}
+
+
+impl fmt::Display for AggregationReduceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AggregationReduceError::TooManyBuckets => write!(f, "Trying to create too many buckets. Must be less than or equal to: [{}]", MAX_BUCKETS),
+        }
+    }
+}
+
+
+/// Converts a date into milliseconds since the epoch
+pub fn datetime_to_millis(date_time: &DateTime<UTC>) -> i64 {
+    date_time.timestamp() * 1000 + (date_time.nanosecond() / 1_000_000) as i64
+}
+
+
+/// The value of a field that documents are grouped by
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BucketKey {
+    Integer(i64),
+    Boolean(bool),
+    DateTime(DateTime<UTC>),
+    String(String),
+}
+
+
+impl BucketKey {
+    pub fn from_field_value(value: FieldValue) -> BucketKey {
+        match value {
+            FieldValue::String(string) => BucketKey::String(string),
+            FieldValue::Integer(integer) => BucketKey::Integer(integer),
+            FieldValue::Boolean(boolean) => BucketKey::Boolean(boolean),
+            FieldValue::DateTime(date_time) => BucketKey::DateTime(date_time),
+        }
+    }
+
+    /// Adds the key to a bucket's JSON
+    ///
+    /// Booleans and dates are given as numbers with their usual representation in "key_as_string".
+    pub fn add_to_bucket_json(&self, bucket_json: &mut serde_json::Map<String, Json>) {
+        match *self {
+            BucketKey::Integer(integer) => {
+                bucket_json.insert("key".to_string(), json!(integer));
+            }
+            BucketKey::Boolean(boolean) => {
+                bucket_json.insert("key".to_string(), json!(if boolean { 1 } else { 0 }));
+                bucket_json.insert("key_as_string".to_string(), json!(boolean.to_string()));
+            }
+            BucketKey::DateTime(ref date_time) => {
+                bucket_json.insert("key".to_string(), json!(datetime_to_millis(date_time)));
+                bucket_json.insert("key_as_string".to_string(), json!(date_time.to_rfc3339()));
+            }
+            BucketKey::String(ref string) => {
+                bucket_json.insert("key".to_string(), json!(string));
+            }
+        }
+    }
+}
+
+
+/// The partial result of an aggregation on one index
+#[derive(Debug, Clone, PartialEq)]
+pub enum AggregationResult {
+    /// The documents that fell into each bucket
+    Buckets(BTreeMap<BucketKey, Bucket>),
+
+    /// The documents that fell into the only bucket
+    SingleBucket(Bucket),
+
+    /// Statistics about the values of a numeric field
+    Stats(stats::Stats),
+
+    /// An estimate of the number of distinct values of a field
+    Cardinality(cardinality::HyperLogLog),
+}
+
+
+/// The documents that fell into one bucket on one index
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bucket {
+    pub doc_count: u64,
+
+    /// The results of the sub aggregations, in the order they were given in the request
+    pub sub_aggregations: Vec<AggregationResult>,
+}
+
+
+impl Bucket {
+    pub fn new(doc_count: u64) -> Bucket {
+        Bucket {
+            doc_count: doc_count,
+            sub_aggregations: Vec::new(),
+        }
+    }
+}
+
+
+/// Puts documents into buckets for a bucket aggregation, running the sub aggregations
+/// separately on the documents in each bucket
+#[derive(Debug)]
+pub struct BucketCollector {
+    /// Empty sub aggregations which are cloned for each new bucket
+    sub_aggregations: Vec<Box<Aggregation>>,
+
+    buckets: BTreeMap<BucketKey, (u64, Vec<Box<Aggregation>>)>,
+}
+
+
+impl BucketCollector {
+    pub fn new(sub_aggregations: Vec<Box<Aggregation>>) -> BucketCollector {
+        BucketCollector {
+            sub_aggregations: sub_aggregations,
+            buckets: BTreeMap::new(),
+        }
+    }
+
+    pub fn collect(&mut self, key: BucketKey, index_reader: &RocksDBIndexReader, doc_id: u64) {
+        let sub_aggregations = &self.sub_aggregations;
+        let bucket = self.buckets.entry(key).or_insert_with(|| {
+            (0, sub_aggregations.iter().map(|aggregation| aggregation.empty_clone()).collect())
+        });
+
+        bucket.0 += 1;
+        for aggregation in bucket.1.iter_mut() {
+            aggregation.collect(index_reader, doc_id);
+        }
+    }
+
+    pub fn empty_clone(&self) -> BucketCollector {
+        BucketCollector::new(self.sub_aggregations.iter().map(|aggregation| aggregation.empty_clone()).collect())
+    }
+
+    pub fn into_result(self) -> AggregationResult {
+        AggregationResult::Buckets(self.buckets.into_iter().map(|(key, (doc_count, sub_aggregations))| {
+            (key, Bucket {
+                doc_count: doc_count,
+                sub_aggregations: sub_aggregations.into_iter().map(|aggregation| aggregation.into_result()).collect(),
+            })
+        }).collect())
+    }
+}
+
+
+/// The documents that fell into one bucket across all of the indices
+#[derive(Debug)]
+pub struct MergedBucket {
+    pub doc_count: u64,
+    sub_aggregation_results: Vec<Vec<AggregationResult>>,
+}
+
+
+impl MergedBucket {
+    pub fn new(num_sub_aggregations: usize) -> MergedBucket {
+        MergedBucket {
+            doc_count: 0,
+            sub_aggregation_results: (0..num_sub_aggregations).map(|_| Vec::new()).collect(),
+        }
+    }
+
+    fn merge(&mut self, bucket: Bucket) {
+        self.doc_count += bucket.doc_count;
+        for (results, result) in self.sub_aggregation_results.iter_mut().zip(bucket.sub_aggregations) {
+            results.push(result);
+        }
+    }
+
+    /// Adds the doc count and the reduced sub aggregations to a bucket's JSON
+    pub fn add_to_bucket_json(self, sub_aggregations: &[(String, Box<AggregationBuilder>)], bucket_json: &mut serde_json::Map<String, Json>) -> Result<(), AggregationReduceError> {
+        bucket_json.insert("doc_count".to_string(), json!(self.doc_count));
+        for (&(ref name, ref aggregation), results) in sub_aggregations.iter().zip(self.sub_aggregation_results) {
+            bucket_json.insert(name.clone(), try!(aggregation.reduce(results)));
+        }
+
+        Ok(())
+    }
+}
+
+
+/// Combines the buckets from each index
+pub fn merge_buckets(results: Vec<AggregationResult>, num_sub_aggregations: usize) -> BTreeMap<BucketKey, MergedBucket> {
+    let mut buckets = BTreeMap::new();
+    for result in results {
+        if let AggregationResult::Buckets(result_buckets) = result {
+            for (key, bucket) in result_buckets {
+                buckets.entry(key).or_insert_with(|| MergedBucket::new(num_sub_aggregations)).merge(bucket);
+            }
+        }
+    }
+
+    buckets
+}
+
+
+/// Collects the documents matched on one index
+pub trait Aggregation: Debug {
+    /// The name of the aggregation's type, shown in search profiles
+    fn name(&self) -> &'static str;
+
+    fn collect(&mut self, index_reader: &RocksDBIndexReader, doc_id: u64);
+
+    /// Creates a new copy of this aggregation that hasn't collected anything yet
+    fn empty_clone(&self) -> Box<Aggregation>;
+
+    fn into_result(self: Box<Self>) -> AggregationResult;
+}
+
+
+/// An aggregation parsed from a search request
+pub trait AggregationBuilder: Debug {
+    /// Creates the aggregation that runs on one index
+    fn build(&self, context: &QueryBuildContext, schema: &Schema) -> Result<Box<Aggregation>, AggregationBuildError>;
+
+    /// Combines the results from each index into the JSON for the response
+    fn reduce(&self, results: Vec<AggregationResult>) -> Result<Json, AggregationReduceError>;
+
+    /// Sets the aggregations to run on the documents in each bucket
+    ///
+    /// Only bucket aggregations can have sub aggregations.
+    fn set_sub_aggregations(&mut self, _sub_aggregations: Vec<(String, Box<AggregationBuilder>)>) -> Result<(), AggregationParseError> {
+        Err(AggregationParseError::SubAggregationsNotAllowed)
+    }
+}
+
+
+/// Creates the sub aggregations of a bucket aggregation for one index
+pub fn build_sub_aggregations(sub_aggregations: &[(String, Box<AggregationBuilder>)], context: &QueryBuildContext, schema: &Schema) -> Result<Vec<Box<Aggregation>>, AggregationBuildError> {
+    let mut built = Vec::with_capacity(sub_aggregations.len());
+    for &(_, ref aggregation) in sub_aggregations.iter() {
+        built.push(try!(aggregation.build(context, schema)));
+    }
+
+    Ok(built)
+}
+
+
+fn get_aggregation_parser(aggregation_type: &str) -> Option<fn(&Json) -> Result<Box<AggregationBuilder>, AggregationParseError>> {
+    match aggregation_type {
+        "terms" => Some(terms::parse),
+        "histogram" => Some(histogram::parse),
+        "date_histogram" => Some(date_histogram::parse),
+        "min" => Some(stats::parse_min),
+        "max" => Some(stats::parse_max),
+        "sum" => Some(stats::parse_sum),
+        "avg" => Some(stats::parse_avg),
+        "stats" => Some(stats::parse_stats),
+        "extended_stats" => Some(stats::parse_extended_stats),
+        "cardinality" => Some(cardinality::parse),
+        "filter" => Some(filter::parse),
+        "filters" => Some(filters::parse),
+        "geohash_grid" => Some(geo_grid::parse_geohash_grid),
+        "geotile_grid" => Some(geo_grid::parse_geotile_grid),
+        _ => None
+    }
+}
+
+
+fn parse_aggregation(json: &Json) -> Result<Box<AggregationBuilder>, AggregationParseError> {
+    let object = try!(json.as_object().ok_or(AggregationParseError::ExpectedObject));
+
+    let mut aggregation = None;
+    let mut sub_aggregations = None;
+
+    for (key, value) in object.iter() {
+        match key.as_ref() {
+            "aggs" | "aggregations" => {
+                if sub_aggregations.is_some() {
+                    return Err(AggregationParseError::ExpectedSingleKey);
+                }
+
+                sub_aggregations = Some(try!(parse(value)));
+            }
+            aggregation_type => {
+                if aggregation.is_some() {
+                    return Err(AggregationParseError::ExpectedSingleKey);
+                }
+
+                match get_aggregation_parser(aggregation_type) {
+                    Some(parse) => aggregation = Some(try!(parse(value))),
+                    None => return Err(AggregationParseError::UnrecognisedAggregationType(aggregation_type.to_string())),
+                }
+            }
+        }
+    }
+
+    let mut aggregation = try!(aggregation.ok_or(AggregationParseError::ExpectedSingleKey));
+
+    if let Some(sub_aggregations) = sub_aggregations {
+        try!(aggregation.set_sub_aggregations(sub_aggregations));
+    }
+
+    Ok(aggregation)
+}
+
+
+/// Parses the "aggs" section of a search request
+pub fn parse(json: &Json) -> Result<Vec<(String, Box<AggregationBuilder>)>, AggregationParseError> {
+    let object = try!(json.as_object().ok_or(AggregationParseError::ExpectedObject));
+
+    let mut aggregations = Vec::new();
+    for (name, aggregation_json) in object.iter() {
+        match parse_aggregation(aggregation_json) {
+            Ok(aggregation) => aggregations.push((name.clone(), aggregation)),
+            Err(e) => return Err(AggregationParseError::InvalidAggregation(name.clone(), Box::new(e))),
+        }
+    }
+
+    Ok(aggregations)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+
+    use super::{parse, AggregationParseError};
+
+    #[test]
+    fn test_parse() {
+        let aggregations = parse(&serde_json::from_str("
+        {
+            \"genres\": {
+                \"terms\": {
+                    \"field\": \"genre\"
+                }
+            },
+            \"authors\": {
+                \"terms\": {
+                    \"field\": \"author\"
+                }
+            }
+        }
+        ").unwrap()).unwrap();
+
+        assert_eq!(aggregations.iter().map(|&(ref name, _)| name.clone()).collect::<Vec<_>>(), vec!["authors".to_string(), "genres".to_string()]);
+    }
+
+    #[test]
+    fn test_parse_unrecognised_type() {
+        let aggregations = parse(&serde_json::from_str("
+        {
+            \"genres\": {
+                \"foo\": {}
+            }
+        }
+        ").unwrap());
+
+        assert_eq!(aggregations.err(), Some(AggregationParseError::InvalidAggregation("genres".to_string(), Box::new(AggregationParseError::UnrecognisedAggregationType("foo".to_string())))));
+    }
+
+    #[test]
+    fn test_parse_sub_aggregations() {
+        let aggregations = parse(&serde_json::from_str("
+        {
+            \"genres\": {
+                \"terms\": {
+                    \"field\": \"genre\"
+                },
+                \"aggs\": {
+                    \"price\": {
+                        \"avg\": {
+                            \"field\": \"price\"
+                        }
+                    }
+                }
+            }
+        }
+        ").unwrap()).unwrap();
+
+        assert_eq!(aggregations.len(), 1);
+        assert!(format!("{:?}", aggregations[0].1).contains("StatsAggregationBuilder"));
+    }
+
+    #[test]
+    fn test_parse_sub_aggregations_on_metric() {
+        let aggregations = parse(&serde_json::from_str("
+        {
+            \"price\": {
+                \"avg\": {
+                    \"field\": \"price\"
+                },
+                \"aggs\": {
+                    \"genres\": {
+                        \"terms\": {
+                            \"field\": \"genre\"
+                        }
+                    }
+                }
+            }
+        }
+        ").unwrap());
+
+        assert_eq!(aggregations.err(), Some(AggregationParseError::InvalidAggregation("price".to_string(), Box::new(AggregationParseError::SubAggregationsNotAllowed))));
+    }
+
+    #[test]
+    fn test_parse_multiple_types() {
+        let aggregations = parse(&serde_json::from_str("
+        {
+            \"genres\": {
+                \"terms\": {
+                    \"field\": \"genre\"
+                },
+                \"avg\": {
+                    \"field\": \"price\"
+                }
+            }
+        }
+        ").unwrap());
+
+        assert_eq!(aggregations.err(), Some(AggregationParseError::InvalidAggregation("genres".to_string(), Box::new(AggregationParseError::ExpectedSingleKey))));
+    }
+}
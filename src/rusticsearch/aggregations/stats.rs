@@ -0,0 +1,390 @@
+//! Parses and runs the metric aggregations ("min", "max", "sum", "avg", "stats" and "extended_stats")
+//!
+//! These all collect the same statistics about the values of a numeric field and only
+//! differ in what they put in the response.
+
+use serde_json::Value as Json;
+use kite::document::{DocRef, FieldValue};
+use kite::schema::{Schema, FieldRef};
+use kite_rocksdb::RocksDBIndexReader;
+
+use query_parser::QueryBuildContext;
+use mapping::FieldType;
+use aggregations::{Aggregation, AggregationBuilder, AggregationResult, AggregationParseError, AggregationBuildError, AggregationReduceError};
+
+
+/// Statistics about the values of a numeric field
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats {
+    pub count: u64,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub sum: f64,
+    pub sum_of_squares: f64,
+}
+
+
+impl Stats {
+    pub fn new() -> Stats {
+        Stats {
+            count: 0,
+            min: None,
+            max: None,
+            sum: 0.0,
+            sum_of_squares: 0.0,
+        }
+    }
+
+    pub fn add_value(&mut self, value: f64) {
+        self.count += 1;
+        self.min = Some(self.min.map_or(value, |min| min.min(value)));
+        self.max = Some(self.max.map_or(value, |max| max.max(value)));
+        self.sum += value;
+        self.sum_of_squares += value * value;
+    }
+
+    pub fn merge(&mut self, other: &Stats) {
+        self.count += other.count;
+        self.min = match (self.min, other.min) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        self.max = match (self.max, other.max) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            (a, b) => a.or(b),
+        };
+        self.sum += other.sum;
+        self.sum_of_squares += other.sum_of_squares;
+    }
+
+    pub fn avg(&self) -> Option<f64> {
+        if self.count > 0 {
+            Some(self.sum / self.count as f64)
+        } else {
+            None
+        }
+    }
+
+    /// The population variance of the values
+    pub fn variance(&self) -> Option<f64> {
+        self.avg().map(|avg| {
+            // Rounding errors can push this slightly below zero when all values are the same
+            let variance = self.sum_of_squares / self.count as f64 - avg * avg;
+            if variance > 0.0 { variance } else { 0.0 }
+        })
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StatsMetric {
+    Min,
+    Max,
+    Sum,
+    Avg,
+    Stats,
+    ExtendedStats {
+        /// How many standard deviations above and below the mean to put the bounds
+        sigma: f64,
+    },
+}
+
+
+#[derive(Debug, PartialEq)]
+pub struct StatsAggregationBuilder {
+    field: String,
+    metric: StatsMetric,
+}
+
+
+impl StatsAggregationBuilder {
+    pub fn new(field: &str, metric: StatsMetric) -> StatsAggregationBuilder {
+        StatsAggregationBuilder {
+            field: field.to_string(),
+            metric: metric,
+        }
+    }
+}
+
+
+/// Collects statistics about a numeric field on one index
+#[derive(Debug)]
+pub struct StatsAggregation {
+    field_ref: Option<FieldRef>,
+    stats: Stats,
+}
+
+
+impl Aggregation for StatsAggregation {
+    fn name(&self) -> &'static str {
+        "StatsAggregation"
+    }
+
+    fn collect(&mut self, index_reader: &RocksDBIndexReader, doc_id: u64) {
+        let field_ref = match self.field_ref {
+            Some(field_ref) => field_ref,
+            None => return,
+        };
+
+        if let Ok(Some(FieldValue::Integer(value))) = index_reader.read_stored_field(field_ref, DocRef::from_u64(doc_id)) {
+            self.stats.add_value(value as f64);
+        }
+    }
+
+    fn empty_clone(&self) -> Box<Aggregation> {
+        Box::new(StatsAggregation {
+            field_ref: self.field_ref,
+            stats: Stats::new(),
+        })
+    }
+
+    fn into_result(self: Box<Self>) -> AggregationResult {
+        AggregationResult::Stats(self.stats)
+    }
+}
+
+
+impl AggregationBuilder for StatsAggregationBuilder {
+    fn build(&self, context: &QueryBuildContext, schema: &Schema) -> Result<Box<Aggregation>, AggregationBuildError> {
+        let field_ref = match context.index_metadata.and_then(|index_metadata| index_metadata.get_field_mapping(&self.field)) {
+            Some(field_mapping) => {
+                if field_mapping.data_type != FieldType::Integer {
+                    return Err(AggregationBuildError::FieldNotNumeric(self.field.clone()));
+                }
+
+                if !field_mapping.has_doc_values && !field_mapping.is_stored {
+                    return Err(AggregationBuildError::FieldNotAggregatable(self.field.clone()));
+                }
+
+                schema.get_field_by_name(&self.field)
+            }
+            None => None,
+        };
+
+        Ok(Box::new(StatsAggregation {
+            field_ref: field_ref,
+            stats: Stats::new(),
+        }))
+    }
+
+    fn reduce(&self, results: Vec<AggregationResult>) -> Result<Json, AggregationReduceError> {
+        let mut stats = Stats::new();
+        for result in results {
+            if let AggregationResult::Stats(ref result_stats) = result {
+                stats.merge(result_stats);
+            }
+        }
+
+        Ok(match self.metric {
+            StatsMetric::Min => json!({"value": stats.min}),
+            StatsMetric::Max => json!({"value": stats.max}),
+            StatsMetric::Sum => json!({"value": stats.sum}),
+            StatsMetric::Avg => json!({"value": stats.avg()}),
+            StatsMetric::Stats => {
+                json!({
+                    "count": stats.count,
+                    "min": stats.min,
+                    "max": stats.max,
+                    "avg": stats.avg(),
+                    "sum": stats.sum,
+                })
+            }
+            StatsMetric::ExtendedStats{sigma} => {
+                let variance = stats.variance();
+                let std_deviation = variance.map(|variance| variance.sqrt());
+                let bounds = match (stats.avg(), std_deviation) {
+                    (Some(avg), Some(std_deviation)) => (Some(avg + sigma * std_deviation), Some(avg - sigma * std_deviation)),
+                    _ => (None, None),
+                };
+
+                json!({
+                    "count": stats.count,
+                    "min": stats.min,
+                    "max": stats.max,
+                    "avg": stats.avg(),
+                    "sum": stats.sum,
+                    "sum_of_squares": if stats.count > 0 { Some(stats.sum_of_squares) } else { None },
+                    "variance": variance,
+                    "std_deviation": std_deviation,
+                    "std_deviation_bounds": {
+                        "upper": bounds.0,
+                        "lower": bounds.1,
+                    },
+                })
+            }
+        })
+    }
+}
+
+
+fn parse(json: &Json, metric: StatsMetric) -> Result<Box<AggregationBuilder>, AggregationParseError> {
+    let object = try!(json.as_object().ok_or(AggregationParseError::ExpectedObject));
+
+    let mut field = None;
+    let mut metric = metric;
+
+    for (key, value) in object.iter() {
+        match (key.as_ref(), &mut metric) {
+            ("field", _) => {
+                field = Some(try!(value.as_str().ok_or(AggregationParseError::ExpectedString)));
+            }
+            ("sigma", &mut StatsMetric::ExtendedStats{ref mut sigma}) => {
+                match value.as_f64() {
+                    Some(value) if value >= 0.0 => *sigma = value,
+                    _ => return Err(AggregationParseError::ExpectedPositiveNumber),
+                }
+            }
+            _ => return Err(AggregationParseError::UnrecognisedKey(key.clone())),
+        }
+    }
+
+    Ok(Box::new(StatsAggregationBuilder::new(try!(field.ok_or(AggregationParseError::ExpectedKey("field"))), metric)))
+}
+
+
+pub fn parse_min(json: &Json) -> Result<Box<AggregationBuilder>, AggregationParseError> {
+    parse(json, StatsMetric::Min)
+}
+
+
+pub fn parse_max(json: &Json) -> Result<Box<AggregationBuilder>, AggregationParseError> {
+    parse(json, StatsMetric::Max)
+}
+
+
+pub fn parse_sum(json: &Json) -> Result<Box<AggregationBuilder>, AggregationParseError> {
+    parse(json, StatsMetric::Sum)
+}
+
+
+pub fn parse_avg(json: &Json) -> Result<Box<AggregationBuilder>, AggregationParseError> {
+    parse(json, StatsMetric::Avg)
+}
+
+
+pub fn parse_stats(json: &Json) -> Result<Box<AggregationBuilder>, AggregationParseError> {
+    parse(json, StatsMetric::Stats)
+}
+
+
+pub fn parse_extended_stats(json: &Json) -> Result<Box<AggregationBuilder>, AggregationParseError> {
+    parse(json, StatsMetric::ExtendedStats{sigma: 2.0})
+}
+
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+
+    use aggregations::{AggregationBuilder, AggregationResult, AggregationParseError};
+
+    use super::{Stats, StatsMetric, StatsAggregationBuilder, parse_avg, parse_extended_stats};
+
+    fn stats_of(values: &[f64]) -> Stats {
+        let mut stats = Stats::new();
+        for value in values {
+            stats.add_value(*value);
+        }
+        stats
+    }
+
+    #[test]
+    fn test_parse() {
+        let builder = parse_avg(&serde_json::from_str("
+        {
+            \"field\": \"price\"
+        }
+        ").unwrap());
+
+        assert_eq!(format!("{:?}", builder.unwrap()), format!("{:?}", StatsAggregationBuilder::new("price", StatsMetric::Avg)));
+    }
+
+    #[test]
+    fn test_parse_sigma() {
+        let builder = parse_extended_stats(&serde_json::from_str("
+        {
+            \"field\": \"price\",
+            \"sigma\": 3
+        }
+        ").unwrap());
+
+        assert_eq!(format!("{:?}", builder.unwrap()), format!("{:?}", StatsAggregationBuilder::new("price", StatsMetric::ExtendedStats{sigma: 3.0})));
+    }
+
+    #[test]
+    fn test_parse_sigma_only_on_extended_stats() {
+        let builder = parse_avg(&serde_json::from_str("
+        {
+            \"field\": \"price\",
+            \"sigma\": 3
+        }
+        ").unwrap());
+
+        assert_eq!(builder.err(), Some(AggregationParseError::UnrecognisedKey("sigma".to_string())));
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut stats = stats_of(&[3.0, 5.0]);
+        stats.merge(&stats_of(&[]));
+        stats.merge(&stats_of(&[1.0]));
+
+        assert_eq!(stats, stats_of(&[3.0, 5.0, 1.0]));
+    }
+
+    #[test]
+    fn test_reduce_stats() {
+        let builder = StatsAggregationBuilder::new("price", StatsMetric::Stats);
+
+        let result = builder.reduce(vec![
+            AggregationResult::Stats(stats_of(&[2.0, 4.0])),
+            AggregationResult::Stats(stats_of(&[9.0])),
+        ]).unwrap();
+
+        assert_eq!(result, json!({
+            "count": 3,
+            "min": 2.0,
+            "max": 9.0,
+            "avg": 5.0,
+            "sum": 15.0,
+        }));
+    }
+
+    #[test]
+    fn test_reduce_extended_stats() {
+        let builder = StatsAggregationBuilder::new("price", StatsMetric::ExtendedStats{sigma: 2.0});
+
+        let result = builder.reduce(vec![
+            AggregationResult::Stats(stats_of(&[2.0, 4.0, 4.0, 4.0])),
+            AggregationResult::Stats(stats_of(&[5.0, 5.0, 7.0, 9.0])),
+        ]).unwrap();
+
+        assert_eq!(result, json!({
+            "count": 8,
+            "min": 2.0,
+            "max": 9.0,
+            "avg": 5.0,
+            "sum": 40.0,
+            "sum_of_squares": 232.0,
+            "variance": 4.0,
+            "std_deviation": 2.0,
+            "std_deviation_bounds": {
+                "upper": 9.0,
+                "lower": 1.0,
+            },
+        }));
+    }
+
+    #[test]
+    fn test_reduce_no_values() {
+        let builder = StatsAggregationBuilder::new("price", StatsMetric::Avg);
+
+        let result = builder.reduce(vec![
+            AggregationResult::Stats(Stats::new()),
+        ]).unwrap();
+
+        assert_eq!(result, json!({
+            "value": null,
+        }));
+    }
+}
@@ -0,0 +1,337 @@
+//! Parses and runs "terms" aggregations
+
+use serde_json;
+use serde_json::Value as Json;
+use kite::document::DocRef;
+use kite::schema::{Schema, FieldRef};
+use kite_rocksdb::RocksDBIndexReader;
+
+use query_parser::QueryBuildContext;
+use aggregations::{Aggregation, AggregationBuilder, AggregationResult, AggregationParseError, AggregationBuildError, AggregationReduceError, BucketKey, BucketCollector, merge_buckets, build_sub_aggregations};
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TermsOrder {
+    CountDesc,
+    CountAsc,
+    KeyAsc,
+    KeyDesc,
+}
+
+
+#[derive(Debug)]
+pub struct TermsAggregationBuilder {
+    field: String,
+    size: usize,
+    order: TermsOrder,
+    min_doc_count: u64,
+    sub_aggregations: Vec<(String, Box<AggregationBuilder>)>,
+}
+
+
+impl TermsAggregationBuilder {
+    pub fn new(field: &str) -> TermsAggregationBuilder {
+        TermsAggregationBuilder {
+            field: field.to_string(),
+            size: 10,
+            order: TermsOrder::CountDesc,
+            min_doc_count: 1,
+            sub_aggregations: Vec::new(),
+        }
+    }
+}
+
+
+/// Counts the documents with each value of a field on one index
+///
+/// If the field isn't mapped in the index, no documents are counted.
+#[derive(Debug)]
+pub struct TermsAggregation {
+    field_ref: Option<FieldRef>,
+    buckets: BucketCollector,
+}
+
+
+impl Aggregation for TermsAggregation {
+    fn name(&self) -> &'static str {
+        "TermsAggregation"
+    }
+
+    fn collect(&mut self, index_reader: &RocksDBIndexReader, doc_id: u64) {
+        let field_ref = match self.field_ref {
+            Some(field_ref) => field_ref,
+            None => return,
+        };
+
+        if let Ok(Some(value)) = index_reader.read_stored_field(field_ref, DocRef::from_u64(doc_id)) {
+            self.buckets.collect(BucketKey::from_field_value(value), index_reader, doc_id);
+        }
+    }
+
+    fn empty_clone(&self) -> Box<Aggregation> {
+        Box::new(TermsAggregation {
+            field_ref: self.field_ref,
+            buckets: self.buckets.empty_clone(),
+        })
+    }
+
+    fn into_result(self: Box<Self>) -> AggregationResult {
+        self.buckets.into_result()
+    }
+}
+
+
+impl AggregationBuilder for TermsAggregationBuilder {
+    fn build(&self, context: &QueryBuildContext, schema: &Schema) -> Result<Box<Aggregation>, AggregationBuildError> {
+        let field_ref = match context.index_metadata.and_then(|index_metadata| index_metadata.get_field_mapping(&self.field)) {
+            Some(field_mapping) => {
+                // Values are read back from the store so they must be kept whole
+                let is_analyzed = field_mapping.index_analyzer().is_some();
+                if !field_mapping.has_doc_values && !(field_mapping.is_stored && !is_analyzed) {
+                    return Err(AggregationBuildError::FieldNotAggregatable(self.field.clone()));
+                }
+
+                schema.get_field_by_name(&self.field)
+            }
+            None => None,
+        };
+
+        Ok(Box::new(TermsAggregation {
+            field_ref: field_ref,
+            buckets: BucketCollector::new(try!(build_sub_aggregations(&self.sub_aggregations, context, schema))),
+        }))
+    }
+
+    fn reduce(&self, results: Vec<AggregationResult>) -> Result<Json, AggregationReduceError> {
+        let buckets = merge_buckets(results, self.sub_aggregations.len());
+
+        let mut buckets = buckets.into_iter().filter(|&(_, ref bucket)| bucket.doc_count >= self.min_doc_count).collect::<Vec<_>>();
+
+        // Buckets with the same count are ordered by key
+        match self.order {
+            TermsOrder::CountDesc => buckets.sort_by(|a, b| (b.1.doc_count, &a.0).cmp(&(a.1.doc_count, &b.0))),
+            TermsOrder::CountAsc => buckets.sort_by(|a, b| (a.1.doc_count, &a.0).cmp(&(b.1.doc_count, &b.0))),
+            TermsOrder::KeyAsc => buckets.sort_by(|a, b| a.0.cmp(&b.0)),
+            TermsOrder::KeyDesc => buckets.sort_by(|a, b| b.0.cmp(&a.0)),
+        }
+
+        let sum_other_doc_count = buckets.iter().skip(self.size).map(|&(_, ref bucket)| bucket.doc_count).sum::<u64>();
+
+        let buckets_json = try!(buckets.into_iter().take(self.size).map(|(key, bucket)| {
+            let mut bucket_json = serde_json::Map::new();
+            key.add_to_bucket_json(&mut bucket_json);
+            try!(bucket.add_to_bucket_json(&self.sub_aggregations, &mut bucket_json));
+            Ok(Json::Object(bucket_json))
+        }).collect::<Result<Vec<_>, _>>());
+
+        Ok(json!({
+            // Every document is counted on each index so the counts are exact
+            "doc_count_error_upper_bound": 0,
+            "sum_other_doc_count": sum_other_doc_count,
+            "buckets": buckets_json,
+        }))
+    }
+
+    fn set_sub_aggregations(&mut self, sub_aggregations: Vec<(String, Box<AggregationBuilder>)>) -> Result<(), AggregationParseError> {
+        self.sub_aggregations = sub_aggregations;
+        Ok(())
+    }
+}
+
+
+fn parse_order(json: &Json) -> Result<TermsOrder, AggregationParseError> {
+    let object = try!(json.as_object().ok_or(AggregationParseError::ExpectedObject));
+
+    if object.len() != 1 {
+        return Err(AggregationParseError::InvalidOrder);
+    }
+
+    let (key, direction) = object.iter().next().unwrap();
+    match (key.as_ref(), direction.as_str()) {
+        ("_count", Some("desc")) => Ok(TermsOrder::CountDesc),
+        ("_count", Some("asc")) => Ok(TermsOrder::CountAsc),
+        ("_key", Some("asc")) | ("_term", Some("asc")) => Ok(TermsOrder::KeyAsc),
+        ("_key", Some("desc")) | ("_term", Some("desc")) => Ok(TermsOrder::KeyDesc),
+        _ => Err(AggregationParseError::InvalidOrder),
+    }
+}
+
+
+pub fn parse(json: &Json) -> Result<Box<AggregationBuilder>, AggregationParseError> {
+    let object = try!(json.as_object().ok_or(AggregationParseError::ExpectedObject));
+
+    let mut field = None;
+    let mut size = None;
+    let mut order = None;
+    let mut min_doc_count = None;
+
+    for (key, value) in object.iter() {
+        match key.as_ref() {
+            "field" => {
+                field = Some(try!(value.as_str().ok_or(AggregationParseError::ExpectedString)));
+            }
+            "size" => {
+                size = Some(try!(value.as_u64().ok_or(AggregationParseError::ExpectedPositiveInteger)) as usize);
+            }
+            "order" => {
+                order = Some(try!(parse_order(value)));
+            }
+            "min_doc_count" => {
+                min_doc_count = Some(try!(value.as_u64().ok_or(AggregationParseError::ExpectedPositiveInteger)));
+            }
+            _ => return Err(AggregationParseError::UnrecognisedKey(key.clone())),
+        }
+    }
+
+    let mut builder = TermsAggregationBuilder::new(try!(field.ok_or(AggregationParseError::ExpectedKey("field"))));
+
+    if let Some(size) = size {
+        builder.size = size;
+    }
+
+    if let Some(order) = order {
+        builder.order = order;
+    }
+
+    if let Some(min_doc_count) = min_doc_count {
+        builder.min_doc_count = min_doc_count;
+    }
+
+    Ok(Box::new(builder))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+
+    use aggregations::{AggregationBuilder, AggregationResult, AggregationParseError, BucketKey, Bucket};
+
+    use aggregations::stats::{Stats, StatsMetric, StatsAggregationBuilder};
+
+    use super::{TermsAggregationBuilder, TermsOrder, parse};
+
+    #[test]
+    fn test_parse() {
+        let builder = parse(&serde_json::from_str("
+        {
+            \"field\": \"genre\",
+            \"size\": 5,
+            \"order\": {\"_key\": \"asc\"},
+            \"min_doc_count\": 2
+        }
+        ").unwrap());
+
+        assert_eq!(format!("{:?}", builder.unwrap()), format!("{:?}", TermsAggregationBuilder {
+            field: "genre".to_string(),
+            size: 5,
+            order: TermsOrder::KeyAsc,
+            min_doc_count: 2,
+            sub_aggregations: Vec::new(),
+        }));
+    }
+
+    #[test]
+    fn test_parse_invalid_order() {
+        let builder = parse(&serde_json::from_str("
+        {
+            \"field\": \"genre\",
+            \"order\": {\"_count\": \"up\"}
+        }
+        ").unwrap());
+
+        assert_eq!(builder.err(), Some(AggregationParseError::InvalidOrder));
+    }
+
+    #[test]
+    fn test_parse_missing_field() {
+        let builder = parse(&serde_json::from_str("
+        {
+            \"size\": 5
+        }
+        ").unwrap());
+
+        assert_eq!(builder.err(), Some(AggregationParseError::ExpectedKey("field")));
+    }
+
+    #[test]
+    fn test_reduce() {
+        let builder = TermsAggregationBuilder {
+            size: 2,
+            ..TermsAggregationBuilder::new("genre")
+        };
+
+        let result = builder.reduce(vec![
+            AggregationResult::Buckets(btreemap! {
+                BucketKey::String("fantasy".to_string()) => Bucket::new(3),
+                BucketKey::String("horror".to_string()) => Bucket::new(1),
+                BucketKey::String("romance".to_string()) => Bucket::new(2)
+            }),
+            AggregationResult::Buckets(btreemap! {
+                BucketKey::String("horror".to_string()) => Bucket::new(2),
+                BucketKey::String("sci-fi".to_string()) => Bucket::new(1)
+            }),
+        ]).unwrap();
+
+        assert_eq!(result, json!({
+            "doc_count_error_upper_bound": 0,
+            "sum_other_doc_count": 3,
+            "buckets": [
+                {"key": "fantasy", "doc_count": 3},
+                {"key": "horror", "doc_count": 3},
+            ]
+        }));
+    }
+
+    #[test]
+    fn test_reduce_min_doc_count() {
+        let builder = TermsAggregationBuilder {
+            order: TermsOrder::KeyDesc,
+            min_doc_count: 2,
+            ..TermsAggregationBuilder::new("genre")
+        };
+
+        let result = builder.reduce(vec![
+            AggregationResult::Buckets(btreemap! {
+                BucketKey::Integer(1) => Bucket::new(3),
+                BucketKey::Integer(2) => Bucket::new(1),
+                BucketKey::Integer(3) => Bucket::new(2)
+            }),
+        ]).unwrap();
+
+        assert_eq!(result, json!({
+            "doc_count_error_upper_bound": 0,
+            "sum_other_doc_count": 0,
+            "buckets": [
+                {"key": 3, "doc_count": 2},
+                {"key": 1, "doc_count": 3},
+            ]
+        }));
+    }
+
+    #[test]
+    fn test_reduce_sub_aggregations() {
+        let mut builder = TermsAggregationBuilder::new("genre");
+        builder.sub_aggregations.push(("price".to_string(), Box::new(StatsAggregationBuilder::new("price", StatsMetric::Max))));
+
+        let mut stats = Stats::new();
+        stats.add_value(12.0);
+
+        let result = builder.reduce(vec![
+            AggregationResult::Buckets(btreemap! {
+                BucketKey::String("fantasy".to_string()) => Bucket {
+                    doc_count: 1,
+                    sub_aggregations: vec![AggregationResult::Stats(stats)],
+                }
+            }),
+        ]).unwrap();
+
+        assert_eq!(result, json!({
+            "doc_count_error_upper_bound": 0,
+            "sum_other_doc_count": 0,
+            "buckets": [
+                {"key": "fantasy", "doc_count": 1, "price": {"value": 12.0}},
+            ]
+        }));
+    }
+}
@@ -0,0 +1,141 @@
+//! Classes of characters that tokenizers can be configured to keep or split on
+
+use std::str::CharIndices;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CharClass {
+    Letter,
+    Digit,
+    Whitespace,
+    Punctuation,
+    Symbol,
+}
+
+
+impl CharClass {
+    pub fn from_name(name: &str) -> Option<CharClass> {
+        match name {
+            "letter" => Some(CharClass::Letter),
+            "digit" => Some(CharClass::Digit),
+            "whitespace" => Some(CharClass::Whitespace),
+            "punctuation" => Some(CharClass::Punctuation),
+            "symbol" => Some(CharClass::Symbol),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            CharClass::Letter => "letter",
+            CharClass::Digit => "digit",
+            CharClass::Whitespace => "whitespace",
+            CharClass::Punctuation => "punctuation",
+            CharClass::Symbol => "symbol",
+        }
+    }
+
+    pub fn matches(&self, c: char) -> bool {
+        match *self {
+            CharClass::Letter => c.is_alphabetic(),
+            CharClass::Digit => c.is_numeric(),
+            CharClass::Whitespace => c.is_whitespace(),
+            CharClass::Punctuation => is_punctuation(c),
+            CharClass::Symbol => is_symbol(c),
+        }
+    }
+}
+
+
+fn is_punctuation(c: char) -> bool {
+    match c {
+        '!' | '"' | '#' | '%' | '&' | '\'' | '(' | ')' | '*' | ',' | '-' | '.' | '/' |
+        ':' | ';' | '?' | '@' | '[' | '\\' | ']' | '_' | '{' | '}' => true,
+        '\u{00A1}' | '\u{00A7}' | '\u{00AB}' | '\u{00B6}' | '\u{00B7}' | '\u{00BB}' | '\u{00BF}' => true,
+        '\u{2010}'...'\u{2027}' | '\u{2030}'...'\u{205E}' => true,
+        '\u{3001}'...'\u{3003}' | '\u{3008}'...'\u{3011}' | '\u{3014}'...'\u{301F}' => true,
+        '\u{FF01}'...'\u{FF0F}' | '\u{FF1A}'...'\u{FF20}' | '\u{FF3B}'...'\u{FF3D}' | '\u{FF5B}'...'\u{FF65}' => true,
+        _ => false,
+    }
+}
+
+
+fn is_symbol(c: char) -> bool {
+    !c.is_alphanumeric() && !c.is_whitespace() && !c.is_control() && !is_punctuation(c)
+}
+
+
+/// Splits a string into words made up of characters in any of the given classes
+pub struct CharClassWords<'a> {
+    input: &'a str,
+    char_indices: CharIndices<'a>,
+    classes: Vec<CharClass>,
+}
+
+
+impl<'a> CharClassWords<'a> {
+    pub fn new(input: &'a str, classes: Vec<CharClass>) -> CharClassWords<'a> {
+        CharClassWords {
+            input: input,
+            char_indices: input.char_indices(),
+            classes: classes,
+        }
+    }
+
+    fn is_word_char(&self, c: char) -> bool {
+        self.classes.iter().any(|class| class.matches(c))
+    }
+}
+
+
+impl<'a> Iterator for CharClassWords<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        // Find start of the word
+        let mut start = None;
+        while let Some((i, c)) = self.char_indices.next() {
+            if self.is_word_char(c) {
+                start = Some(i);
+                break;
+            }
+        }
+
+        let start = match start {
+            Some(start) => start,
+            None => return None,
+        };
+
+        // Find end of the word
+        let mut end = self.input.len();
+        while let Some((i, c)) = self.char_indices.next() {
+            if !self.is_word_char(c) {
+                end = i;
+                break;
+            }
+        }
+
+        Some(&self.input[start..end])
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{CharClass, CharClassWords};
+
+    #[test]
+    fn test_letters_and_digits() {
+        let words = CharClassWords::new("Hello, world! It's 2017.", vec![CharClass::Letter, CharClass::Digit]).collect::<Vec<&str>>();
+
+        assert_eq!(words, vec!["Hello", "world", "It", "s", "2017"]);
+    }
+
+    #[test]
+    fn test_punctuation_and_symbols() {
+        assert!(CharClass::Punctuation.matches('!'));
+        assert!(!CharClass::Punctuation.matches('$'));
+        assert!(CharClass::Symbol.matches('$'));
+        assert!(!CharClass::Symbol.matches('a'));
+    }
+}
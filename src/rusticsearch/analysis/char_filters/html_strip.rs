@@ -0,0 +1,223 @@
+//! Removes HTML tags and decodes HTML entities
+//!
+//! Block-level tags (eg, `<p>` and `<br>`) are replaced with a newline so the words
+//! either side of them don't get joined together. The contents of `<script>` and
+//! `<style>` elements are removed along with the tags.
+
+use std::char;
+
+
+const BLOCK_TAGS: &'static [&'static str] = &[
+    "address", "article", "aside", "blockquote", "br", "dd", "div", "dl", "dt", "fieldset",
+    "figcaption", "figure", "footer", "form", "h1", "h2", "h3", "h4", "h5", "h6", "header",
+    "hr", "li", "main", "nav", "ol", "p", "pre", "section", "table", "td", "th", "title",
+    "tr", "ul",
+];
+
+
+fn starts_with_ignore_case(string: &str, prefix: &str) -> bool {
+    string.len() >= prefix.len()
+        && string.is_char_boundary(prefix.len())
+        && string[..prefix.len()].to_lowercase() == prefix
+}
+
+
+/// Parses the tag at the start of the input
+///
+/// Returns the length of the tag in bytes, its name in lowercase and whether it is a
+/// closing tag. Returns None if the input doesn't start with a tag.
+fn parse_tag(input: &str) -> Option<(usize, String, bool)> {
+    let mut chars = input.char_indices().skip(1).peekable();
+
+    let is_closing = match chars.peek() {
+        Some(&(_, '/')) => {
+            chars.next();
+            true
+        }
+        _ => false,
+    };
+
+    // Read the name
+    let mut name = String::new();
+    while let Some(&(_, c)) = chars.peek() {
+        if c.is_alphanumeric() || (name.is_empty() && c == '!') || (!name.is_empty() && c == '-') {
+            name.extend(c.to_lowercase());
+            chars.next();
+        } else {
+            break;
+        }
+    }
+
+    match name.chars().next() {
+        Some(c) if c.is_alphabetic() || c == '!' => {}
+        _ => return None,
+    }
+
+    // Find the end of the tag, skipping over quoted attribute values
+    let mut quote = None;
+    for (i, c) in chars {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '>' => return Some((i + 1, name, is_closing)),
+            None => {}
+        }
+    }
+
+    None
+}
+
+
+/// Decodes the entity at the start of the input
+///
+/// Returns the length of the entity in bytes and the character it represents.
+fn decode_entity(input: &str) -> Option<(usize, char)> {
+    let end = match input.find(';') {
+        Some(end) if end > 1 && end <= 10 => end,
+        _ => return None,
+    };
+
+    let entity = &input[1..end];
+
+    let c = if entity.starts_with("#x") || entity.starts_with("#X") {
+        u32::from_str_radix(&entity[2..], 16).ok().and_then(char::from_u32)
+    } else if entity.starts_with('#') {
+        entity[1..].parse::<u32>().ok().and_then(char::from_u32)
+    } else {
+        match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{A0}'),
+            "copy" => Some('\u{A9}'),
+            "reg" => Some('\u{AE}'),
+            "trade" => Some('\u{2122}'),
+            "ndash" => Some('\u{2013}'),
+            "mdash" => Some('\u{2014}'),
+            "lsquo" => Some('\u{2018}'),
+            "rsquo" => Some('\u{2019}'),
+            "ldquo" => Some('\u{201C}'),
+            "rdquo" => Some('\u{201D}'),
+            "hellip" => Some('\u{2026}'),
+            "euro" => Some('\u{20AC}'),
+            "pound" => Some('\u{A3}'),
+            _ => None,
+        }
+    };
+
+    c.map(|c| (end + 1, c))
+}
+
+
+/// Strips HTML from the input
+///
+/// Tags listed in `escaped_tags` are left in the output.
+pub fn strip_html(input: &str, escaped_tags: &[String]) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+
+    while let Some(i) = rest.find(|c: char| c == '<' || c == '&') {
+        output.push_str(&rest[..i]);
+        rest = &rest[i..];
+
+        // Comments
+        if rest.starts_with("<!--") {
+            rest = match rest.find("-->") {
+                Some(end) => &rest[end + 3..],
+                None => "",
+            };
+            continue;
+        }
+
+        if rest.starts_with('&') {
+            match decode_entity(rest) {
+                Some((length, c)) => {
+                    output.push(c);
+                    rest = &rest[length..];
+                }
+                None => {
+                    output.push('&');
+                    rest = &rest[1..];
+                }
+            }
+            continue;
+        }
+
+        let (length, name, is_closing) = match parse_tag(rest) {
+            Some(tag) => tag,
+            None => {
+                output.push('<');
+                rest = &rest[1..];
+                continue;
+            }
+        };
+
+        if escaped_tags.contains(&name) {
+            output.push_str(&rest[..length]);
+            rest = &rest[length..];
+            continue;
+        }
+
+        rest = &rest[length..];
+
+        if !is_closing && (name == "script" || name == "style") {
+            // Skip to the end of the element
+            let closing_tag = format!("</{}", name);
+            loop {
+                match rest.find("</") {
+                    Some(i) => {
+                        rest = &rest[i..];
+                        if starts_with_ignore_case(rest, &closing_tag) {
+                            break;
+                        }
+                        rest = &rest[2..];
+                    }
+                    None => {
+                        rest = "";
+                        break;
+                    }
+                }
+            }
+        } else if BLOCK_TAGS.iter().any(|tag| *tag == name) {
+            output.push('\n');
+        }
+    }
+
+    output.push_str(rest);
+    output
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::strip_html;
+
+    #[test]
+    fn test_strip_html() {
+        let html = "<div class=\"post\"><p>Hello <b>wor</b>ld!</p><p>It&apos;s &lt;great&gt;&#33;</p></div>";
+
+        assert_eq!(strip_html(html, &[]), "\n\nHello world!\n\nIt's <great>!\n\n");
+    }
+
+    #[test]
+    fn test_strip_html_script_and_comments() {
+        let html = "before<script type='text/javascript'>if (a < b) { x(); }</SCRIPT><!-- comment -->after";
+
+        assert_eq!(strip_html(html, &[]), "beforeafter");
+    }
+
+    #[test]
+    fn test_strip_html_escaped_tags() {
+        let html = "<p>a <b>bold</b> move</p>";
+
+        assert_eq!(strip_html(html, &["b".to_string()]), "\na <b>bold</b> move\n");
+    }
+
+    #[test]
+    fn test_strip_html_not_a_tag() {
+        assert_eq!(strip_html("1 < 2 && 3 > 2", &[]), "1 < 2 && 3 > 2");
+    }
+}
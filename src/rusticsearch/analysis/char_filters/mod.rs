@@ -0,0 +1,74 @@
+pub mod html_strip;
+pub mod pattern_replace;
+
+use serde_json;
+use serde_json::value::ToJson;
+
+use analysis::compiled_regex::CompiledRegex;
+use analysis::char_filters::html_strip::strip_html;
+use analysis::char_filters::pattern_replace;
+
+
+/// Defines a character filter
+///
+/// Character filters transform the text before it is passed to the tokenizer
+///
+/// # Examples
+///
+/// ```
+/// use kite::analysis::char_filters::CharFilterSpec;
+///
+/// let html_strip = CharFilterSpec::HTMLStrip { escaped_tags: vec![] };
+///
+/// assert_eq!(html_strip.apply("<b>Hello</b> &amp; welcome"), "Hello & welcome");
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum CharFilterSpec {
+    HTMLStrip {
+        escaped_tags: Vec<String>,
+    },
+    PatternReplace {
+        pattern: String,
+        replacement: String,
+        flags: String,
+
+        /// The pattern compiled with its flags
+        regex: CompiledRegex,
+    },
+}
+
+
+impl CharFilterSpec {
+    pub fn apply(&self, input: &str) -> String {
+        match *self {
+            CharFilterSpec::HTMLStrip{ref escaped_tags} => {
+                strip_html(input, escaped_tags)
+            }
+            CharFilterSpec::PatternReplace{ref replacement, ref regex, ..} => {
+                pattern_replace::replace_pattern(input, regex, replacement)
+            }
+        }
+    }
+}
+
+
+impl ToJson for CharFilterSpec {
+    fn to_json(&self) -> Result<serde_json::Value, serde_json::Error> {
+        match *self {
+            CharFilterSpec::HTMLStrip{ref escaped_tags} => {
+                Ok(json!({
+                    "type": "html_strip",
+                    "escaped_tags": escaped_tags,
+                }))
+            }
+            CharFilterSpec::PatternReplace{ref pattern, ref replacement, ref flags, ..} => {
+                Ok(json!({
+                    "type": "pattern_replace",
+                    "pattern": pattern,
+                    "replacement": replacement,
+                    "flags": flags,
+                }))
+            }
+        }
+    }
+}
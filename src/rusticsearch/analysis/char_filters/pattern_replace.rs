@@ -0,0 +1,92 @@
+//! Replaces matches of a regular expression
+//!
+//! Flags are given in the same format as Elasticsearch, which uses the names of
+//! Java's regex flags separated by pipes (eg, `"CASE_INSENSITIVE|MULTILINE"`).
+
+use regex::{self, Regex};
+
+use analysis::compiled_regex::CompiledRegex;
+
+
+#[derive(Debug, PartialEq)]
+pub enum PatternReplaceError {
+    UnrecognisedFlag(String),
+    InvalidPattern(String),
+}
+
+
+/// Converts a string of Java regex flags into the inline flags of the regex crate
+fn parse_flags(flags: &str) -> Result<String, PatternReplaceError> {
+    let mut inline_flags = String::new();
+
+    for flag in flags.split('|').map(|flag| flag.trim()).filter(|flag| !flag.is_empty()) {
+        let inline_flag = match flag {
+            "CASE_INSENSITIVE" => "i",
+            "MULTILINE" => "m",
+            "DOTALL" => "s",
+            "COMMENTS" => "x",
+
+            // The regex crate is always unicode aware
+            "UNICODE_CASE" | "UNICODE_CHARACTER_CLASS" => continue,
+            _ => return Err(PatternReplaceError::UnrecognisedFlag(flag.to_string())),
+        };
+
+        if !inline_flags.contains(inline_flag) {
+            inline_flags.push_str(inline_flag);
+        }
+    }
+
+    Ok(inline_flags)
+}
+
+
+/// Compiles a pattern with the given flags
+pub fn compile(pattern: &str, flags: &str) -> Result<CompiledRegex, PatternReplaceError> {
+    let inline_flags = try!(parse_flags(flags));
+
+    let pattern = if inline_flags.is_empty() {
+        pattern.to_string()
+    } else {
+        format!("(?{}){}", inline_flags, pattern)
+    };
+
+    CompiledRegex::new(&pattern).map_err(|error: regex::Error| PatternReplaceError::InvalidPattern(error.to_string()))
+}
+
+
+/// Replaces every match of the pattern in the input
+///
+/// The replacement can refer to capture groups with `$1` or `${name}`.
+pub fn replace_pattern(input: &str, pattern: &Regex, replacement: &str) -> String {
+    pattern.replace_all(input, replacement).into_owned()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{compile, replace_pattern, PatternReplaceError};
+
+    #[test]
+    fn test_replace_pattern() {
+        let pattern = compile("(\\d{3})-(\\d{4})", "").unwrap();
+
+        assert_eq!(replace_pattern("call 555-1234 now", &pattern, "$1$2"), "call 5551234 now");
+    }
+
+    #[test]
+    fn test_replace_pattern_with_flags() {
+        let pattern = compile("foo", "CASE_INSENSITIVE|UNICODE_CASE").unwrap();
+
+        assert_eq!(replace_pattern("Foo FOO foo", &pattern, "bar"), "bar bar bar");
+    }
+
+    #[test]
+    fn test_compile_errors() {
+        assert_eq!(compile("foo", "LITERAL").err(), Some(PatternReplaceError::UnrecognisedFlag("LITERAL".to_string())));
+
+        match compile("(foo", "") {
+            Err(PatternReplaceError::InvalidPattern(_)) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+}
@@ -0,0 +1,62 @@
+//! Regular expressions that are compiled when the analysis settings are parsed
+
+use std::fmt;
+use std::ops::Deref;
+use std::sync::Arc;
+
+use regex::{self, Regex};
+
+
+/// A compiled regular expression that can be kept in a tokenizer or filter definition
+///
+/// Definitions are cloned and compared, so the regex is shared between clones and two
+/// regexes are equal if they were compiled from the same pattern.
+#[derive(Clone)]
+pub struct CompiledRegex(Arc<Regex>);
+
+
+impl CompiledRegex {
+    pub fn new(pattern: &str) -> Result<CompiledRegex, regex::Error> {
+        Ok(CompiledRegex(Arc::new(try!(Regex::new(pattern)))))
+    }
+}
+
+
+impl Deref for CompiledRegex {
+    type Target = Regex;
+
+    fn deref(&self) -> &Regex {
+        &self.0
+    }
+}
+
+
+impl PartialEq for CompiledRegex {
+    fn eq(&self, other: &CompiledRegex) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+
+impl fmt::Debug for CompiledRegex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CompiledRegex({:?})", self.as_str())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::CompiledRegex;
+
+    #[test]
+    fn test_compiled_regex_eq() {
+        assert_eq!(CompiledRegex::new("\\W+").unwrap(), CompiledRegex::new("\\W+").unwrap());
+        assert!(CompiledRegex::new("\\W+").unwrap() != CompiledRegex::new("\\s+").unwrap());
+    }
+
+    #[test]
+    fn test_compiled_regex_invalid() {
+        assert!(CompiledRegex::new("(foo").is_err());
+    }
+}
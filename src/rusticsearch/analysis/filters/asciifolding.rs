@@ -8,10 +8,11 @@ use std::str;
 use kite::{Term, Token};
 
 use analysis::lucene_asciifold::fold_to_ascii;
+use analysis::TokenStream;
 
 
 pub struct ASCIIFoldingFilter<'a> {
-    tokens: Box<Iterator<Item=Token> + 'a>,
+    tokens: TokenStream<'a>,
 }
 
 
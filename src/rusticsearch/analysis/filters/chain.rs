@@ -0,0 +1,98 @@
+//! A chain of filters that tokens are pushed through one at a time
+//!
+//! The filters are initialised once over a queue. Each token is added to the queue and
+//! then the chain is read until it runs out, which gives everything the filters output
+//! for that token. Filters that keep state between tokens (eg, "unique") keep it for as
+//! long as the chain exists, but filters that look ahead (eg, multi-word synonyms) only
+//! ever see one token.
+
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::collections::{HashSet, VecDeque};
+
+use kite::Token;
+
+use analysis::TokenStream;
+use analysis::filters::FilterSpec;
+
+
+/// Gives out the tokens that have been pushed into the queue
+struct TokenQueue {
+    tokens: Rc<RefCell<VecDeque<Token>>>,
+}
+
+
+impl Iterator for TokenQueue {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        self.tokens.borrow_mut().pop_front()
+    }
+}
+
+
+pub struct FilterChain {
+    input: Rc<RefCell<VecDeque<Token>>>,
+    output: TokenStream<'static>,
+}
+
+
+impl FilterChain {
+    pub fn new(filters: &[FilterSpec], keywords: &HashSet<String>) -> FilterChain {
+        let input = Rc::new(RefCell::new(VecDeque::new()));
+        let mut output: TokenStream<'static> = Box::new(TokenQueue {
+            tokens: input.clone(),
+        });
+
+        // Keywords only apply to the filters after the one that marked them
+        let mut keywords = keywords.clone();
+
+        for filter in filters.iter() {
+            output = filter.initialise_with_keywords(output, &keywords);
+            keywords.extend(filter.keywords());
+        }
+
+        FilterChain {
+            input: input,
+            output: output,
+        }
+    }
+
+    /// Runs a token through the chain and returns the tokens that come out of it
+    pub fn process(&mut self, token: Token) -> &mut TokenStream<'static> {
+        self.input.borrow_mut().push_back(token);
+        &mut self.output
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use kite::{Term, Token};
+
+    use analysis::filters::FilterSpec;
+
+    use super::FilterChain;
+
+    #[test]
+    fn test_filter_chain() {
+        let filters = vec![
+            FilterSpec::Lowercase,
+            FilterSpec::Unique { only_on_same_position: false },
+        ];
+        let mut chain = FilterChain::new(&filters, &HashSet::new());
+
+        assert_eq!(chain.process(Token { term: Term::from_string("Hello"), position: 1 }).collect::<Vec<Token>>(), vec![
+            Token { term: Term::from_string("hello"), position: 1 },
+        ]);
+
+        // The unique filter remembers the terms it saw for earlier tokens
+        assert_eq!(chain.process(Token { term: Term::from_string("HELLO"), position: 2 }).collect::<Vec<Token>>(), vec![]);
+
+        assert_eq!(chain.process(Token { term: Term::from_string("World"), position: 3 }).collect::<Vec<Token>>(), vec![
+            Token { term: Term::from_string("world"), position: 3 },
+        ]);
+    }
+}
@@ -0,0 +1,184 @@
+//! Normalises the width of CJK text
+//!
+//! Full-width ASCII characters (eg, "ＡＢＣ１２３") are folded into basic latin and
+//! half-width katakana (eg, "ｶﾀｶﾅ") are folded into their standard forms. Voiced
+//! sound marks that follow a half-width katakana are combined with it.
+
+use std::str;
+use std::char;
+
+use kite::{Term, Token};
+
+use analysis::TokenStream;
+
+
+/// Standard forms of the half-width katakana from U+FF65 to U+FF9F
+static HALFWIDTH_KATAKANA: [char; 59] = [
+    '\u{30FB}', '\u{30F2}', '\u{30A1}', '\u{30A3}', '\u{30A5}', '\u{30A7}', '\u{30A9}', '\u{30E3}',
+    '\u{30E5}', '\u{30E7}', '\u{30C3}', '\u{30FC}', '\u{30A2}', '\u{30A4}', '\u{30A6}', '\u{30A8}',
+    '\u{30AA}', '\u{30AB}', '\u{30AD}', '\u{30AF}', '\u{30B1}', '\u{30B3}', '\u{30B5}', '\u{30B7}',
+    '\u{30B9}', '\u{30BB}', '\u{30BD}', '\u{30BF}', '\u{30C1}', '\u{30C4}', '\u{30C6}', '\u{30C8}',
+    '\u{30CA}', '\u{30CB}', '\u{30CC}', '\u{30CD}', '\u{30CE}', '\u{30CF}', '\u{30D2}', '\u{30D5}',
+    '\u{30D8}', '\u{30DB}', '\u{30DE}', '\u{30DF}', '\u{30E0}', '\u{30E1}', '\u{30E2}', '\u{30E4}',
+    '\u{30E6}', '\u{30E8}', '\u{30E9}', '\u{30EA}', '\u{30EB}', '\u{30EC}', '\u{30ED}', '\u{30EF}',
+    '\u{30F3}', '\u{3099}', '\u{309A}',
+];
+
+
+/// Combines a katakana with a following voiced (dakuten) mark, if possible
+fn combine_voiced(c: char) -> Option<char> {
+    match c {
+        // カ to ト, which alternate with their voiced forms
+        '\u{30AB}' | '\u{30AD}' | '\u{30AF}' | '\u{30B1}' | '\u{30B3}' |
+        '\u{30B5}' | '\u{30B7}' | '\u{30B9}' | '\u{30BB}' | '\u{30BD}' |
+        '\u{30BF}' | '\u{30C1}' | '\u{30C4}' | '\u{30C6}' | '\u{30C8}' |
+        // ハ to ホ
+        '\u{30CF}' | '\u{30D2}' | '\u{30D5}' | '\u{30D8}' | '\u{30DB}' => {
+            char::from_u32(c as u32 + 1)
+        }
+        '\u{30A6}' => Some('\u{30F4}'),  // ウ => ヴ
+        '\u{30EF}' => Some('\u{30F7}'),  // ワ => ヷ
+        '\u{30F2}' => Some('\u{30FA}'),  // ヲ => ヺ
+        _ => None,
+    }
+}
+
+
+/// Combines a katakana with a following semi-voiced (handakuten) mark, if possible
+fn combine_semi_voiced(c: char) -> Option<char> {
+    match c {
+        // ハ to ホ
+        '\u{30CF}' | '\u{30D2}' | '\u{30D5}' | '\u{30D8}' | '\u{30DB}' => {
+            char::from_u32(c as u32 + 2)
+        }
+        _ => None,
+    }
+}
+
+
+pub fn fold_width(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut last_was_halfwidth_kana = false;
+
+    for c in input.chars() {
+        match c {
+            '\u{FF01}'...'\u{FF5E}' => {
+                // Full-width ASCII variants are offset from basic latin
+                output.push(char::from_u32(c as u32 - 0xFEE0).unwrap_or(c));
+                last_was_halfwidth_kana = false;
+            }
+            '\u{FF65}'...'\u{FF9F}' => {
+                let folded = HALFWIDTH_KATAKANA[c as usize - 0xFF65];
+
+                // Try to combine voiced sound marks with the previous half-width katakana
+                if last_was_halfwidth_kana && (c == '\u{FF9E}' || c == '\u{FF9F}') {
+                    let combined = output.chars().last().and_then(|previous| {
+                        if c == '\u{FF9E}' {
+                            combine_voiced(previous)
+                        } else {
+                            combine_semi_voiced(previous)
+                        }
+                    });
+
+                    if let Some(combined) = combined {
+                        output.pop();
+                        output.push(combined);
+                        last_was_halfwidth_kana = false;
+                        continue;
+                    }
+                }
+
+                output.push(folded);
+                last_was_halfwidth_kana = true;
+            }
+            _ => {
+                output.push(c);
+                last_was_halfwidth_kana = false;
+            }
+        }
+    }
+
+    output
+}
+
+
+pub struct CJKWidthFilter<'a> {
+    tokens: TokenStream<'a>,
+}
+
+
+impl<'a> CJKWidthFilter<'a> {
+    pub fn new(tokens: TokenStream<'a>) -> CJKWidthFilter<'a> {
+        CJKWidthFilter {
+            tokens: tokens,
+        }
+    }
+}
+
+
+impl<'a> Iterator for CJKWidthFilter<'a> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        match self.tokens.next() {
+            Some(token) => {
+                Some(Token {
+                    term: match str::from_utf8(token.term.as_bytes()) {
+                        Ok(string) => Term::from_string(&fold_width(string)),
+                        _ => token.term.clone(),
+                    },
+                    position: token.position,
+                })
+            }
+            None => None
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use kite::{Term, Token};
+
+    use super::{CJKWidthFilter, fold_width};
+
+    #[test]
+    fn test_fold_fullwidth_ascii() {
+        assert_eq!(fold_width("ＡＢＣ１２３ｘｙｚ"), "ABC123xyz");
+    }
+
+    #[test]
+    fn test_fold_halfwidth_katakana() {
+        assert_eq!(fold_width("ｶﾀｶﾅ"), "カタカナ");
+    }
+
+    #[test]
+    fn test_fold_halfwidth_katakana_voiced_marks() {
+        assert_eq!(fold_width("ｶﾞｲﾄﾞﾌﾞｯｸ"), "ガイドブック");
+        assert_eq!(fold_width("ﾊﾟﾝ"), "パン");
+        assert_eq!(fold_width("ｳﾞｧｲｵﾘﾝ"), "ヴァイオリン");
+    }
+
+    #[test]
+    fn test_uncombinable_voiced_mark() {
+        assert_eq!(fold_width("ｱﾞ"), "ア\u{3099}");
+    }
+
+    #[test]
+    fn test_cjk_width_filter() {
+        let mut tokens: Vec<Token> = vec![
+            Token { term: Term::from_string("ＴＶ"), position: 1 },
+            Token { term: Term::from_string("ﾃﾚﾋﾞ"), position: 2 },
+            Token { term: Term::from_string("テレビ"), position: 3 },
+        ];
+
+        let token_filter = CJKWidthFilter::new(Box::new(tokens.drain((..))));
+        let tokens = token_filter.collect::<Vec<Token>>();
+
+        assert_eq!(tokens, vec![
+            Token { term: Term::from_string("TV"), position: 1 },
+            Token { term: Term::from_string("テレビ"), position: 2 },
+            Token { term: Term::from_string("テレビ"), position: 3 },
+        ]);
+    }
+}
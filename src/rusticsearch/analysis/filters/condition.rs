@@ -0,0 +1,184 @@
+//! Applies a chain of filters only to the tokens that match a condition
+//!
+//! Tokens that don't match the condition are passed through unchanged. For example,
+//! this can be used to only stem words that are longer than four characters.
+
+use std::str;
+use std::collections::{HashSet, VecDeque};
+
+use regex::{self, Regex};
+use kite::{Term, Token};
+
+use analysis::TokenStream;
+use analysis::filters::FilterSpec;
+use analysis::filters::chain::FilterChain;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenCondition {
+    /// The number of characters in the token is between min and max (inclusive)
+    Length {
+        min: usize,
+        max: usize,
+    },
+
+    /// All of the letters in the token are in the script (eg, "latin" or "cyrillic")
+    Script(String),
+
+    /// The token contains a match of the regular expression
+    Pattern(String),
+}
+
+
+impl TokenCondition {
+    /// Compiles the regular expression used by script and pattern conditions
+    pub fn regex(&self) -> Result<Option<Regex>, regex::Error> {
+        match *self {
+            TokenCondition::Length{..} => Ok(None),
+            TokenCondition::Script(ref script) => {
+                // Unicode script names are capitalised (eg, "Latin")
+                let mut chars = script.chars();
+                let script = match chars.next() {
+                    Some(first) => first.to_uppercase().chain(chars).collect::<String>(),
+                    None => String::new(),
+                };
+
+                Regex::new(&format!("^\\P{{L}}*\\p{{{0}}}[\\p{{{0}}}\\P{{L}}]*$", script)).map(Some)
+            }
+            TokenCondition::Pattern(ref pattern) => Regex::new(pattern).map(Some),
+        }
+    }
+}
+
+
+pub struct ConditionFilter<'a> {
+    tokens: TokenStream<'a>,
+    condition: TokenCondition,
+    regex: Option<Regex>,
+    filters: FilterChain,
+    output_buffer: VecDeque<Token>,
+}
+
+
+impl<'a> ConditionFilter<'a> {
+    pub fn new(tokens: TokenStream<'a>, condition: &TokenCondition, filters: &[FilterSpec], keywords: &HashSet<String>) -> ConditionFilter<'a> {
+        // Conditions are checked when the filter is configured so this shouldn't fail
+        let regex = condition.regex().ok().and_then(|regex| regex);
+
+        ConditionFilter {
+            tokens: tokens,
+            condition: condition.clone(),
+            regex: regex,
+            filters: FilterChain::new(filters, keywords),
+            output_buffer: VecDeque::new(),
+        }
+    }
+
+    fn matches(&self, term: &Term) -> bool {
+        let word = match str::from_utf8(term.as_bytes()) {
+            Ok(word) => word,
+            Err(_) => return false,
+        };
+
+        match self.condition {
+            TokenCondition::Length{min, max} => {
+                let length = word.chars().count();
+                length >= min && length <= max
+            }
+            TokenCondition::Script(_) | TokenCondition::Pattern(_) => {
+                match self.regex {
+                    Some(ref regex) => regex.is_match(word),
+                    None => false,
+                }
+            }
+        }
+    }
+
+    fn process_token(&mut self, token: Token) {
+        if !self.matches(&token.term) {
+            self.output_buffer.push_back(token);
+            return;
+        }
+
+        self.output_buffer.extend(self.filters.process(token));
+    }
+}
+
+
+impl<'a> Iterator for ConditionFilter<'a> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        while self.output_buffer.is_empty() {
+            match self.tokens.next() {
+                Some(token) => self.process_token(token),
+                None => return None,
+            }
+        }
+
+        self.output_buffer.pop_front()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use kite::{Term, Token};
+
+    use analysis::filters::FilterSpec;
+
+    use super::{ConditionFilter, TokenCondition};
+
+    #[test]
+    fn test_condition_filter_length() {
+        let mut tokens: Vec<Token> = vec![
+            Token { term: Term::from_string("runs"), position: 1 },
+            Token { term: Term::from_string("jumping"), position: 2 },
+        ];
+
+        let condition = TokenCondition::Length { min: 5, max: 100 };
+        let token_filter = ConditionFilter::new(Box::new(tokens.drain((..))), &condition, &[FilterSpec::PorterStem], &HashSet::new());
+        let tokens = token_filter.collect::<Vec<Token>>();
+
+        assert_eq!(tokens, vec![
+            Token { term: Term::from_string("runs"), position: 1 },
+            Token { term: Term::from_string("jump"), position: 2 },
+        ]);
+    }
+
+    #[test]
+    fn test_condition_filter_script() {
+        let mut tokens: Vec<Token> = vec![
+            Token { term: Term::from_string("Hello"), position: 1 },
+            Token { term: Term::from_string("Привет"), position: 2 },
+        ];
+
+        let condition = TokenCondition::Script("cyrillic".to_string());
+        let token_filter = ConditionFilter::new(Box::new(tokens.drain((..))), &condition, &[FilterSpec::Lowercase], &HashSet::new());
+        let tokens = token_filter.collect::<Vec<Token>>();
+
+        assert_eq!(tokens, vec![
+            Token { term: Term::from_string("Hello"), position: 1 },
+            Token { term: Term::from_string("привет"), position: 2 },
+        ]);
+    }
+
+    #[test]
+    fn test_condition_filter_pattern() {
+        let mut tokens: Vec<Token> = vec![
+            Token { term: Term::from_string("abc123"), position: 1 },
+            Token { term: Term::from_string("abc"), position: 2 },
+        ];
+
+        let condition = TokenCondition::Pattern("\\d".to_string());
+        let token_filter = ConditionFilter::new(Box::new(tokens.drain((..))), &condition, &[FilterSpec::Reverse], &HashSet::new());
+        let tokens = token_filter.collect::<Vec<Token>>();
+
+        assert_eq!(tokens, vec![
+            Token { term: Term::from_string("321cba"), position: 1 },
+            Token { term: Term::from_string("abc"), position: 2 },
+        ]);
+    }
+}
@@ -0,0 +1,125 @@
+//! Converts decimal digits from any script into their ASCII equivalents (0-9)
+//!
+//! For example, "٣" (arabic-indic three) and "３" (full-width three) are both
+//! converted to "3".
+
+use std::str;
+use std::char;
+
+use kite::{Term, Token};
+
+use analysis::TokenStream;
+
+
+/// The zero digit of each run of ten decimal digits (general category "Nd") in Unicode
+static DIGIT_ZEROS: &'static [u32] = &[
+    0x0030, 0x0660, 0x06F0, 0x07C0, 0x0966, 0x09E6, 0x0A66, 0x0AE6, 0x0B66, 0x0BE6,
+    0x0C66, 0x0CE6, 0x0D66, 0x0DE6, 0x0E50, 0x0ED0, 0x0F20, 0x1040, 0x1090, 0x17E0,
+    0x1810, 0x1946, 0x19D0, 0x1A80, 0x1A90, 0x1B50, 0x1BB0, 0x1C40, 0x1C50, 0xA620,
+    0xA8D0, 0xA900, 0xA9D0, 0xA9F0, 0xAA50, 0xABF0, 0xFF10, 0x104A0, 0x11066, 0x110F0,
+    0x11136, 0x111D0, 0x112F0, 0x11450, 0x114D0, 0x11650, 0x116C0, 0x11730, 0x118E0, 0x11C50,
+    0x16A60, 0x16B50, 0x1D7CE, 0x1D7D8, 0x1D7E2, 0x1D7EC, 0x1D7F6, 0x1E950,
+];
+
+
+/// Returns the value of a decimal digit in any script
+fn digit_value(c: char) -> Option<u32> {
+    let code = c as u32;
+
+    // The list is sorted, so find the last zero that isn't after this character
+    let zero = match DIGIT_ZEROS.binary_search(&code) {
+        Ok(index) => DIGIT_ZEROS[index],
+        Err(0) => return None,
+        Err(index) => DIGIT_ZEROS[index - 1],
+    };
+
+    if code - zero < 10 {
+        Some(code - zero)
+    } else {
+        None
+    }
+}
+
+
+pub fn fold_digits(input: &str) -> String {
+    input.chars().map(|c| {
+        match digit_value(c) {
+            Some(value) => char::from_digit(value, 10).unwrap_or(c),
+            None => c,
+        }
+    }).collect()
+}
+
+
+pub struct DecimalDigitFilter<'a> {
+    tokens: TokenStream<'a>,
+}
+
+
+impl<'a> DecimalDigitFilter<'a> {
+    pub fn new(tokens: TokenStream<'a>) -> DecimalDigitFilter<'a> {
+        DecimalDigitFilter {
+            tokens: tokens,
+        }
+    }
+}
+
+
+impl<'a> Iterator for DecimalDigitFilter<'a> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        match self.tokens.next() {
+            Some(token) => {
+                Some(Token {
+                    term: match str::from_utf8(token.term.as_bytes()) {
+                        Ok(string) => Term::from_string(&fold_digits(string)),
+                        _ => token.term.clone(),
+                    },
+                    position: token.position,
+                })
+            }
+            None => None
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use kite::{Term, Token};
+
+    use super::{DecimalDigitFilter, fold_digits};
+
+    #[test]
+    fn test_fold_digits() {
+        assert_eq!(fold_digits("123"), "123");
+        assert_eq!(fold_digits("١٢٣"), "123");
+        assert_eq!(fold_digits("१२३"), "123");
+        assert_eq!(fold_digits("１２３"), "123");
+        assert_eq!(fold_digits("𝟙𝟚𝟛"), "123");
+    }
+
+    #[test]
+    fn test_non_digits_not_changed() {
+        assert_eq!(fold_digits("abc"), "abc");
+        assert_eq!(fold_digits("½"), "½");
+        assert_eq!(fold_digits("一二三"), "一二三");
+    }
+
+    #[test]
+    fn test_decimal_digit_filter() {
+        let mut tokens: Vec<Token> = vec![
+            Token { term: Term::from_string("ｉＰｈｏｎｅ７"), position: 1 },
+            Token { term: Term::from_string("٢٠١٧"), position: 2 },
+        ];
+
+        let token_filter = DecimalDigitFilter::new(Box::new(tokens.drain((..))));
+        let tokens = token_filter.collect::<Vec<Token>>();
+
+        assert_eq!(tokens, vec![
+            Token { term: Term::from_string("ｉＰｈｏｎｅ7"), position: 1 },
+            Token { term: Term::from_string("2017"), position: 2 },
+        ]);
+    }
+}
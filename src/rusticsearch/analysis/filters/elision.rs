@@ -0,0 +1,102 @@
+//! Removes elided articles from the start of each token (eg, "l'avion" becomes "avion")
+//!
+//! Articles are matched without regard to case.
+
+use std::str;
+
+use kite::{Term, Token};
+
+use analysis::TokenStream;
+
+
+/// Elided articles in French
+pub const FRENCH_ARTICLES: &'static [&'static str] = &[
+    "l", "m", "t", "qu", "n", "s", "j", "d", "c", "jusqu", "quoiqu", "lorsqu", "puisqu",
+];
+
+
+pub struct ElisionFilter<'a> {
+    tokens: TokenStream<'a>,
+    articles: Vec<String>,
+}
+
+
+impl<'a> ElisionFilter<'a> {
+    pub fn new(tokens: TokenStream<'a>, articles: &[String]) -> ElisionFilter<'a> {
+        ElisionFilter {
+            tokens: tokens,
+            articles: articles.iter().map(|article| article.to_lowercase()).collect(),
+        }
+    }
+
+    fn strip_article<'b>(&self, word: &'b str) -> Option<&'b str> {
+        let apostrophe = match word.find(|c: char| c == '\'' || c == '\u{2019}') {
+            Some(apostrophe) => apostrophe,
+            None => return None,
+        };
+
+        let article = word[..apostrophe].to_lowercase();
+        if self.articles.contains(&article) {
+            // Both apostrophes are a single character but the curly one is three bytes long
+            let rest = &word[apostrophe..];
+            let apostrophe_length = rest.chars().next().unwrap().len_utf8();
+            Some(&rest[apostrophe_length..])
+        } else {
+            None
+        }
+    }
+}
+
+
+impl<'a> Iterator for ElisionFilter<'a> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        match self.tokens.next() {
+            Some(token) => {
+                Some(Token {
+                    term: match str::from_utf8(token.term.as_bytes()) {
+                        Ok(string) => {
+                            match self.strip_article(string) {
+                                Some(stripped) => Term::from_string(stripped),
+                                None => token.term.clone(),
+                            }
+                        }
+                        _ => token.term.clone(),
+                    },
+                    position: token.position,
+                })
+            }
+            None => None
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use kite::{Term, Token};
+
+    use super::{ElisionFilter, FRENCH_ARTICLES};
+
+    #[test]
+    fn test_elision_filter() {
+        let mut tokens: Vec<Token> = vec![
+            Token { term: Term::from_string("l'avion"), position: 1 },
+            Token { term: Term::from_string("L\u{2019}hôtel"), position: 2 },
+            Token { term: Term::from_string("aujourd'hui"), position: 3 },
+            Token { term: Term::from_string("jusqu'ici"), position: 4 },
+        ];
+
+        let articles = FRENCH_ARTICLES.iter().map(|article| article.to_string()).collect::<Vec<String>>();
+        let token_filter = ElisionFilter::new(Box::new(tokens.drain((..))), &articles);
+        let tokens = token_filter.collect::<Vec<Token>>();
+
+        assert_eq!(tokens, vec![
+            Token { term: Term::from_string("avion"), position: 1 },
+            Token { term: Term::from_string("hôtel"), position: 2 },
+            Token { term: Term::from_string("aujourd'hui"), position: 3 },
+            Token { term: Term::from_string("ici"), position: 4 },
+        ]);
+    }
+}
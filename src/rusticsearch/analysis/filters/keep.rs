@@ -0,0 +1,76 @@
+//! Removes all tokens that are not in a list of words
+
+use std::str;
+use std::collections::HashSet;
+
+use kite::Token;
+
+use analysis::TokenStream;
+
+
+pub struct KeepWordsFilter<'a> {
+    tokens: TokenStream<'a>,
+    words: HashSet<String>,
+    ignore_case: bool,
+}
+
+
+impl<'a> KeepWordsFilter<'a> {
+    pub fn new(tokens: TokenStream<'a>, words: &[String], ignore_case: bool) -> KeepWordsFilter<'a> {
+        KeepWordsFilter {
+            tokens: tokens,
+            words: words.iter().map(|word| if ignore_case { word.to_lowercase() } else { word.clone() }).collect(),
+            ignore_case: ignore_case,
+        }
+    }
+
+    fn is_kept(&self, token: &Token) -> bool {
+        match str::from_utf8(token.term.as_bytes()) {
+            Ok(string) if self.ignore_case => self.words.contains(&string.to_lowercase()),
+            Ok(string) => self.words.contains(string),
+            Err(_) => false,
+        }
+    }
+}
+
+
+impl<'a> Iterator for KeepWordsFilter<'a> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        while let Some(token) = self.tokens.next() {
+            if self.is_kept(&token) {
+                return Some(token);
+            }
+        }
+
+        None
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use kite::{Term, Token};
+
+    use super::KeepWordsFilter;
+
+    #[test]
+    fn test_keep_words_filter() {
+        let mut tokens: Vec<Token> = vec![
+            Token { term: Term::from_string("Red"), position: 1 },
+            Token { term: Term::from_string("leather"), position: 2 },
+            Token { term: Term::from_string("blue"), position: 3 },
+            Token { term: Term::from_string("sofa"), position: 4 },
+        ];
+
+        let words = vec!["red".to_string(), "blue".to_string()];
+        let token_filter = KeepWordsFilter::new(Box::new(tokens.drain((..))), &words, true);
+        let tokens = token_filter.collect::<Vec<Token>>();
+
+        assert_eq!(tokens, vec![
+            Token { term: Term::from_string("Red"), position: 1 },
+            Token { term: Term::from_string("blue"), position: 3 },
+        ]);
+    }
+}
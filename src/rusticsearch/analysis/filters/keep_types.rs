@@ -0,0 +1,135 @@
+//! Keeps (or removes) tokens based on their type
+//!
+//! Tokens don't carry a type so it is worked out from the characters in the term,
+//! using the same names as the Lucene standard tokenizer:
+//!
+//!  - `<NUM>` terms made up entirely of digits (and decimal/thousand separators)
+//!  - `<IDEOGRAPHIC>` terms made up of CJK ideographs
+//!  - `<ALPHANUM>` everything else
+
+use std::str;
+
+use kite::Token;
+
+use analysis::TokenStream;
+
+
+pub fn token_type(term: &str) -> &'static str {
+    let is_number = term.chars().any(|c| c.is_numeric())
+        && term.chars().all(|c| c.is_numeric() || c == '.' || c == ',');
+
+    if is_number {
+        return "<NUM>";
+    }
+
+    let is_ideographic = !term.is_empty() && term.chars().all(|c| {
+        match c {
+            '\u{3400}'...'\u{4DBF}' | '\u{4E00}'...'\u{9FFF}' | '\u{F900}'...'\u{FAFF}' | '\u{20000}'...'\u{2FA1F}' => true,
+            _ => false,
+        }
+    });
+
+    if is_ideographic {
+        return "<IDEOGRAPHIC>";
+    }
+
+    "<ALPHANUM>"
+}
+
+
+pub struct KeepTypesFilter<'a> {
+    tokens: TokenStream<'a>,
+    types: Vec<String>,
+    exclude: bool,
+}
+
+
+impl<'a> KeepTypesFilter<'a> {
+    /// Creates a KeepTypesFilter
+    ///
+    /// If `exclude` is set, tokens of the given types are removed instead of kept.
+    pub fn new(tokens: TokenStream<'a>, types: &[String], exclude: bool) -> KeepTypesFilter<'a> {
+        KeepTypesFilter {
+            tokens: tokens,
+            types: types.to_vec(),
+            exclude: exclude,
+        }
+    }
+
+    fn is_kept(&self, token: &Token) -> bool {
+        let is_listed = match str::from_utf8(token.term.as_bytes()) {
+            Ok(string) => {
+                let token_type = token_type(string);
+                self.types.iter().any(|t| t == token_type)
+            }
+            Err(_) => false,
+        };
+
+        is_listed != self.exclude
+    }
+}
+
+
+impl<'a> Iterator for KeepTypesFilter<'a> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        while let Some(token) = self.tokens.next() {
+            if self.is_kept(&token) {
+                return Some(token);
+            }
+        }
+
+        None
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use kite::{Term, Token};
+
+    use super::{KeepTypesFilter, token_type};
+
+    #[test]
+    fn test_token_type() {
+        assert_eq!(token_type("123"), "<NUM>");
+        assert_eq!(token_type("1,234.5"), "<NUM>");
+        assert_eq!(token_type("abc123"), "<ALPHANUM>");
+        assert_eq!(token_type("公"), "<IDEOGRAPHIC>");
+        assert_eq!(token_type("."), "<ALPHANUM>");
+    }
+
+    #[test]
+    fn test_keep_types_filter() {
+        let mut tokens: Vec<Token> = vec![
+            Token { term: Term::from_string("size"), position: 1 },
+            Token { term: Term::from_string("42"), position: 2 },
+            Token { term: Term::from_string("shoes"), position: 3 },
+        ];
+
+        let token_filter = KeepTypesFilter::new(Box::new(tokens.drain((..))), &["<NUM>".to_string()], false);
+        let tokens = token_filter.collect::<Vec<Token>>();
+
+        assert_eq!(tokens, vec![
+            Token { term: Term::from_string("42"), position: 2 },
+        ]);
+    }
+
+    #[test]
+    fn test_keep_types_filter_exclude() {
+        let mut tokens: Vec<Token> = vec![
+            Token { term: Term::from_string("size"), position: 1 },
+            Token { term: Term::from_string("42"), position: 2 },
+            Token { term: Term::from_string("shoes"), position: 3 },
+        ];
+
+        let token_filter = KeepTypesFilter::new(Box::new(tokens.drain((..))), &["<NUM>".to_string()], true);
+        let tokens = token_filter.collect::<Vec<Token>>();
+
+        assert_eq!(tokens, vec![
+            Token { term: Term::from_string("size"), position: 1 },
+            Token { term: Term::from_string("shoes"), position: 3 },
+        ]);
+    }
+}
@@ -0,0 +1,77 @@
+//! Removes tokens that are too short or too long
+//!
+//! Lengths are measured in characters.
+
+use std::str;
+
+use kite::Token;
+
+use analysis::TokenStream;
+
+
+pub struct LengthFilter<'a> {
+    tokens: TokenStream<'a>,
+    min: usize,
+    max: usize,
+}
+
+
+impl<'a> LengthFilter<'a> {
+    pub fn new(tokens: TokenStream<'a>, min: usize, max: usize) -> LengthFilter<'a> {
+        LengthFilter {
+            tokens: tokens,
+            min: min,
+            max: max,
+        }
+    }
+
+    fn is_valid_length(&self, token: &Token) -> bool {
+        let length = match str::from_utf8(token.term.as_bytes()) {
+            Ok(string) => string.chars().count(),
+            Err(_) => token.term.as_bytes().len(),
+        };
+
+        length >= self.min && length <= self.max
+    }
+}
+
+
+impl<'a> Iterator for LengthFilter<'a> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        while let Some(token) = self.tokens.next() {
+            if self.is_valid_length(&token) {
+                return Some(token);
+            }
+        }
+
+        None
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use kite::{Term, Token};
+
+    use super::LengthFilter;
+
+    #[test]
+    fn test_length_filter() {
+        let mut tokens: Vec<Token> = vec![
+            Token { term: Term::from_string("a"), position: 1 },
+            Token { term: Term::from_string("quick"), position: 2 },
+            Token { term: Term::from_string("ハチ公"), position: 3 },
+            Token { term: Term::from_string("supercalifragilistic"), position: 4 },
+        ];
+
+        let token_filter = LengthFilter::new(Box::new(tokens.drain((..))), 2, 10);
+        let tokens = token_filter.collect::<Vec<Token>>();
+
+        assert_eq!(tokens, vec![
+            Token { term: Term::from_string("quick"), position: 2 },
+            Token { term: Term::from_string("ハチ公"), position: 3 },
+        ]);
+    }
+}
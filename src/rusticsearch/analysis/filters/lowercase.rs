@@ -4,14 +4,16 @@ use std::str;
 
 use kite::{Term, Token};
 
+use analysis::TokenStream;
+
 
 pub struct LowercaseFilter<'a> {
-    tokens: Box<Iterator<Item=Token> + 'a>,
+    tokens: TokenStream<'a>,
 }
 
 
 impl<'a> LowercaseFilter<'a> {
-    pub fn new(tokens: Box<Iterator<Item=Token> +'a>) -> LowercaseFilter<'a> {
+    pub fn new(tokens: TokenStream<'a>) -> LowercaseFilter<'a> {
         LowercaseFilter {
             tokens: tokens,
         }
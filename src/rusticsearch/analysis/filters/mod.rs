@@ -1,15 +1,58 @@
 pub mod lowercase;
 pub mod ngram;
 pub mod asciifolding;
+pub mod stop;
+pub mod stemmer;
+pub mod synonym;
+pub mod word_delimiter;
+pub mod length;
+pub mod trim;
+pub mod truncate;
+pub mod unique;
+pub mod reverse;
+pub mod keep;
+pub mod keep_types;
+pub mod elision;
+pub mod phonetic;
+pub mod stemmer_override;
+pub mod multiplexer;
+pub mod condition;
+pub mod cjk_width;
+pub mod decimal_digit;
+pub mod chain;
+
+use std::sync::Arc;
+use std::collections::HashSet;
 
 use serde_json;
 use serde_json::value::ToJson;
-use kite::Token;
+use rust_stemmers::Algorithm;
 
 use analysis::ngram_generator::Edge;
+use analysis::phonetic::PhoneticEncoder;
 use analysis::filters::lowercase::LowercaseFilter;
 use analysis::filters::ngram::NGramFilter;
 use analysis::filters::asciifolding::ASCIIFoldingFilter;
+use analysis::filters::stop::StopFilter;
+use analysis::filters::stemmer::{StemmerFilter, get_algorithm};
+use analysis::filters::synonym::{SynonymFilter, SynonymMap};
+use analysis::filters::word_delimiter::{WordDelimiterFilter, WordDelimiterOptions};
+use analysis::filters::length::LengthFilter;
+use analysis::filters::trim::TrimFilter;
+use analysis::filters::truncate::TruncateFilter;
+use analysis::filters::unique::UniqueFilter;
+use analysis::filters::reverse::ReverseFilter;
+use analysis::filters::keep::KeepWordsFilter;
+use analysis::filters::keep_types::KeepTypesFilter;
+use analysis::filters::elision::ElisionFilter;
+use analysis::filters::phonetic::PhoneticFilter;
+use analysis::filters::stemmer_override::StemmerOverrideFilter;
+use analysis::filters::multiplexer::MultiplexerFilter;
+use analysis::filters::condition::{ConditionFilter, TokenCondition};
+use analysis::filters::cjk_width::CJKWidthFilter;
+use analysis::filters::decimal_digit::DecimalDigitFilter;
+use analysis::resources::{self, ResourceError};
+use analysis::TokenStream;
 
 
 /// Defines a token filter
@@ -44,23 +87,247 @@ pub enum FilterSpec {
         min_size: usize,
         max_size: usize,
         edge: Edge,
+        preserve_original: bool,
     },
     ASCIIFolding,
+    /// Use `FilterSpec::new_stop` to create this, it builds the set of words
+    Stop {
+        words: Vec<String>,
+        words_path: Option<String>,
+        word_set: Arc<HashSet<String>>,
+    },
+    PorterStem,
+    Stemmer {
+        language: String,
+    },
+    /// Use `FilterSpec::new_synonym` to create this, it parses the rules into the map
+    Synonym {
+        synonyms: Vec<String>,
+        synonyms_path: Option<String>,
+        map: Arc<SynonymMap>,
+    },
+    WordDelimiter(WordDelimiterOptions),
+    Length {
+        min: usize,
+        max: usize,
+    },
+    Trim,
+    Truncate {
+        length: usize,
+    },
+    Unique {
+        only_on_same_position: bool,
+    },
+    Reverse,
+    CJKWidth,
+    DecimalDigit,
+    KeepWords {
+        words: Vec<String>,
+        ignore_case: bool,
+    },
+    KeepTypes {
+        types: Vec<String>,
+        exclude: bool,
+    },
+    Elision {
+        articles: Vec<String>,
+    },
+    Phonetic {
+        encoder: PhoneticEncoder,
+        replace: bool,
+    },
+    KeywordMarker {
+        keywords: Vec<String>,
+    },
+    StemmerOverride {
+        overrides: Vec<(String, String)>,
+    },
+    Multiplexer {
+        chains: Vec<Vec<FilterSpec>>,
+        preserve_original: bool,
+    },
+    Condition {
+        condition: TokenCondition,
+        filters: Vec<FilterSpec>,
+    },
 }
 
 
 impl FilterSpec {
-    pub fn initialise<'a>(&self, input: Box<Iterator<Item=Token> + 'a>) -> Box<Iterator<Item=Token> + 'a> {
+    /// Makes a stop filter, the set of words is shared by every stream it's initialised on
+    pub fn new_stop(words: Vec<String>, words_path: Option<String>) -> FilterSpec {
+        let word_set = words.iter().cloned().collect();
+
+        FilterSpec::Stop {
+            words: words,
+            words_path: words_path,
+            word_set: Arc::new(word_set),
+        }
+    }
+
+    /// Makes a synonym filter from a list of Solr-format rules
+    ///
+    /// The rules are parsed once here and the map is shared by every stream the
+    /// filter is initialised on. Returns the first invalid rule as an error.
+    pub fn new_synonym(synonyms: Vec<String>, synonyms_path: Option<String>) -> Result<FilterSpec, String> {
+        let map = try!(SynonymMap::from_rules(&synonyms));
+
+        Ok(FilterSpec::Synonym {
+            synonyms: synonyms,
+            synonyms_path: synonyms_path,
+            map: Arc::new(map),
+        })
+    }
+
+    pub fn initialise<'a>(&self, input: TokenStream<'a>) -> TokenStream<'a> {
+        self.initialise_with_keywords(input, &HashSet::new())
+    }
+
+    /// Initialises the filter, telling it which terms have been marked as keywords
+    /// by the filters before it
+    ///
+    /// Stemmers leave keywords unchanged.
+    pub fn initialise_with_keywords<'a>(&self, input: TokenStream<'a>, keywords: &HashSet<String>) -> TokenStream<'a> {
         match *self {
             FilterSpec::Lowercase => {
                 Box::new(LowercaseFilter::new(input))
             }
-            FilterSpec::NGram{min_size, max_size, edge} => {
-                Box::new(NGramFilter::new(input, min_size, max_size, edge))
+            FilterSpec::NGram{min_size, max_size, edge, preserve_original} => {
+                Box::new(NGramFilter::new_with_preserve_original(input, min_size, max_size, edge, preserve_original))
             }
             FilterSpec::ASCIIFolding => {
                 Box::new(ASCIIFoldingFilter::new(input))
             }
+            FilterSpec::Stop{ref word_set, ..} => {
+                Box::new(StopFilter::new(input, word_set.clone()))
+            }
+            FilterSpec::PorterStem => {
+                // The Snowball English stemmer implements Porter2
+                Box::new(StemmerFilter::new_with_keywords(input, Algorithm::English, keywords.clone()))
+            }
+            FilterSpec::Stemmer{ref language} => {
+                // Languages are checked when the filter is configured so this shouldn't fail
+                match get_algorithm(language) {
+                    Some(algorithm) => Box::new(StemmerFilter::new_with_keywords(input, algorithm, keywords.clone())),
+                    None => input,
+                }
+            }
+            FilterSpec::Synonym{ref map, ..} => {
+                Box::new(SynonymFilter::new(input, map.clone()))
+            }
+            FilterSpec::WordDelimiter(options) => {
+                Box::new(WordDelimiterFilter::new(input, options))
+            }
+            FilterSpec::Length{min, max} => {
+                Box::new(LengthFilter::new(input, min, max))
+            }
+            FilterSpec::Trim => {
+                Box::new(TrimFilter::new(input))
+            }
+            FilterSpec::Truncate{length} => {
+                Box::new(TruncateFilter::new(input, length))
+            }
+            FilterSpec::Unique{only_on_same_position} => {
+                Box::new(UniqueFilter::new(input, only_on_same_position))
+            }
+            FilterSpec::Reverse => {
+                Box::new(ReverseFilter::new(input))
+            }
+            FilterSpec::CJKWidth => {
+                Box::new(CJKWidthFilter::new(input))
+            }
+            FilterSpec::DecimalDigit => {
+                Box::new(DecimalDigitFilter::new(input))
+            }
+            FilterSpec::KeepWords{ref words, ignore_case} => {
+                Box::new(KeepWordsFilter::new(input, words, ignore_case))
+            }
+            FilterSpec::KeepTypes{ref types, exclude} => {
+                Box::new(KeepTypesFilter::new(input, types, exclude))
+            }
+            FilterSpec::Elision{ref articles} => {
+                Box::new(ElisionFilter::new(input, articles))
+            }
+            FilterSpec::Phonetic{encoder, replace} => {
+                Box::new(PhoneticFilter::new(input, encoder, replace))
+            }
+            FilterSpec::KeywordMarker{..} => {
+                // Keywords are tracked by the analyzer, the tokens themselves are left alone
+                input
+            }
+            FilterSpec::StemmerOverride{ref overrides} => {
+                Box::new(StemmerOverrideFilter::new(input, overrides))
+            }
+            FilterSpec::Multiplexer{ref chains, preserve_original} => {
+                Box::new(MultiplexerFilter::new(input, chains, preserve_original, keywords))
+            }
+            FilterSpec::Condition{ref condition, ref filters} => {
+                Box::new(ConditionFilter::new(input, condition, filters, keywords))
+            }
+        }
+    }
+
+    /// Returns true if this filter reads resources from files
+    pub fn uses_files(&self) -> bool {
+        match *self {
+            FilterSpec::Stop{words_path: Some(_), ..} => true,
+            FilterSpec::Synonym{synonyms_path: Some(_), ..} => true,
+            FilterSpec::Multiplexer{ref chains, ..} => chains.iter().any(|chain| chain.iter().any(|filter| filter.uses_files())),
+            FilterSpec::Condition{ref filters, ..} => filters.iter().any(|filter| filter.uses_files()),
+            _ => false,
+        }
+    }
+
+    /// Returns a copy of this filter with its file-based resources re-read
+    pub fn reload(&self) -> Result<FilterSpec, ResourceError> {
+        match *self {
+            FilterSpec::Stop{words_path: Some(ref words_path), ..} => {
+                let words = try!(resources::read_lines(words_path));
+
+                Ok(FilterSpec::new_stop(words, Some(words_path.clone())))
+            }
+            FilterSpec::Synonym{synonyms_path: Some(ref synonyms_path), ..} => {
+                let synonyms = try!(resources::read_lines(synonyms_path));
+
+                FilterSpec::new_synonym(synonyms, Some(synonyms_path.clone())).map_err(ResourceError::InvalidSynonymRule)
+            }
+            FilterSpec::Multiplexer{ref chains, preserve_original} => {
+                let mut reloaded_chains = Vec::new();
+                for chain in chains.iter() {
+                    let mut reloaded_chain = Vec::new();
+                    for filter in chain.iter() {
+                        reloaded_chain.push(try!(filter.reload()));
+                    }
+
+                    reloaded_chains.push(reloaded_chain);
+                }
+
+                Ok(FilterSpec::Multiplexer {
+                    chains: reloaded_chains,
+                    preserve_original: preserve_original,
+                })
+            }
+            FilterSpec::Condition{ref condition, ref filters} => {
+                let mut reloaded_filters = Vec::new();
+                for filter in filters.iter() {
+                    reloaded_filters.push(try!(filter.reload()));
+                }
+
+                Ok(FilterSpec::Condition {
+                    condition: condition.clone(),
+                    filters: reloaded_filters,
+                })
+            }
+            _ => Ok(self.clone()),
+        }
+    }
+
+    /// Returns the terms that this filter marks as keywords
+    pub fn keywords(&self) -> Vec<String> {
+        match *self {
+            FilterSpec::KeywordMarker{ref keywords} => keywords.clone(),
+            FilterSpec::StemmerOverride{ref overrides} => overrides.iter().map(|&(_, ref stem)| stem.clone()).collect(),
+            _ => Vec::new(),
         }
     }
 }
@@ -74,7 +341,7 @@ impl ToJson for FilterSpec {
                     "type": "lowercase",
                 }))
             }
-            FilterSpec::NGram{min_size, max_size, edge} => {
+            FilterSpec::NGram{min_size, max_size, edge, preserve_original} => {
                 match edge {
                     Edge::Left => {
                         Ok(json!({
@@ -82,6 +349,7 @@ impl ToJson for FilterSpec {
                             "side": "front",
                             "min_gram": min_size,
                             "max_gram": max_size,
+                            "preserve_original": preserve_original,
                         }))
                     }
                     Edge::Right => {
@@ -90,6 +358,7 @@ impl ToJson for FilterSpec {
                             "side": "back",
                             "min_gram": min_size,
                             "max_gram": max_size,
+                            "preserve_original": preserve_original,
                         }))
                     }
                     Edge::Neither => {
@@ -97,6 +366,7 @@ impl ToJson for FilterSpec {
                             "type": "ngram",
                             "min_gram": min_size,
                             "max_gram": max_size,
+                            "preserve_original": preserve_original,
                         }))
                     }
                 }
@@ -106,6 +376,182 @@ impl ToJson for FilterSpec {
                     "type": "asciifolding",
                 }))
             }
+            FilterSpec::Stop{ref words, ref words_path, ..} => {
+                // The words read from the file are saved too, so the index can still be loaded if it goes missing
+                match *words_path {
+                    Some(ref words_path) => {
+                        Ok(json!({
+                            "type": "stop",
+                            "stopwords_path": words_path,
+                            "stopwords": words,
+                        }))
+                    }
+                    None => {
+                        Ok(json!({
+                            "type": "stop",
+                            "stopwords": words,
+                        }))
+                    }
+                }
+            }
+            FilterSpec::PorterStem => {
+                Ok(json!({
+                    "type": "porter_stem",
+                }))
+            }
+            FilterSpec::Stemmer{ref language} => {
+                Ok(json!({
+                    "type": "stemmer",
+                    "language": language,
+                }))
+            }
+            FilterSpec::Synonym{ref synonyms, ref synonyms_path, ..} => {
+                match *synonyms_path {
+                    Some(ref synonyms_path) => {
+                        Ok(json!({
+                            "type": "synonym",
+                            "synonyms_path": synonyms_path,
+                            "synonyms": synonyms,
+                        }))
+                    }
+                    None => {
+                        Ok(json!({
+                            "type": "synonym",
+                            "synonyms": synonyms,
+                        }))
+                    }
+                }
+            }
+            FilterSpec::WordDelimiter(ref options) => {
+                Ok(json!({
+                    "type": "word_delimiter",
+                    "generate_word_parts": options.generate_word_parts,
+                    "generate_number_parts": options.generate_number_parts,
+                    "catenate_words": options.catenate_words,
+                    "catenate_numbers": options.catenate_numbers,
+                    "catenate_all": options.catenate_all,
+                    "split_on_case_change": options.split_on_case_change,
+                    "split_on_numerics": options.split_on_numerics,
+                    "preserve_original": options.preserve_original,
+                    "stem_english_possessive": options.stem_english_possessive,
+                }))
+            }
+            FilterSpec::Length{min, max} => {
+                Ok(json!({
+                    "type": "length",
+                    "min": min,
+                    "max": max,
+                }))
+            }
+            FilterSpec::Trim => {
+                Ok(json!({
+                    "type": "trim",
+                }))
+            }
+            FilterSpec::Truncate{length} => {
+                Ok(json!({
+                    "type": "truncate",
+                    "length": length,
+                }))
+            }
+            FilterSpec::Unique{only_on_same_position} => {
+                Ok(json!({
+                    "type": "unique",
+                    "only_on_same_position": only_on_same_position,
+                }))
+            }
+            FilterSpec::Reverse => {
+                Ok(json!({
+                    "type": "reverse",
+                }))
+            }
+            FilterSpec::CJKWidth => {
+                Ok(json!({
+                    "type": "cjk_width",
+                }))
+            }
+            FilterSpec::DecimalDigit => {
+                Ok(json!({
+                    "type": "decimal_digit",
+                }))
+            }
+            FilterSpec::KeepWords{ref words, ignore_case} => {
+                Ok(json!({
+                    "type": "keep",
+                    "keep_words": words,
+                    "keep_words_case": ignore_case,
+                }))
+            }
+            FilterSpec::KeepTypes{ref types, exclude} => {
+                Ok(json!({
+                    "type": "keep_types",
+                    "types": types,
+                    "mode": if exclude { "exclude" } else { "include" },
+                }))
+            }
+            FilterSpec::Elision{ref articles} => {
+                Ok(json!({
+                    "type": "elision",
+                    "articles": articles,
+                }))
+            }
+            FilterSpec::Phonetic{encoder, replace} => {
+                Ok(json!({
+                    "type": "phonetic",
+                    "encoder": encoder.name(),
+                    "replace": replace,
+                }))
+            }
+            FilterSpec::KeywordMarker{ref keywords} => {
+                Ok(json!({
+                    "type": "keyword_marker",
+                    "keywords": keywords,
+                }))
+            }
+            FilterSpec::StemmerOverride{ref overrides} => {
+                let rules = overrides.iter().map(|&(ref word, ref stem)| format!("{} => {}", word, stem)).collect::<Vec<String>>();
+
+                Ok(json!({
+                    "type": "stemmer_override",
+                    "rules": rules,
+                }))
+            }
+            FilterSpec::Multiplexer{ref chains, preserve_original} => {
+                // Each chain is written as an array of inline filter definitions
+                let mut chains_json = Vec::new();
+                for chain in chains.iter() {
+                    let mut chain_json = Vec::new();
+                    for filter in chain.iter() {
+                        chain_json.push(try!(filter.to_json()));
+                    }
+
+                    chains_json.push(chain_json);
+                }
+
+                Ok(json!({
+                    "type": "multiplexer",
+                    "filters": chains_json,
+                    "preserve_original": preserve_original,
+                }))
+            }
+            FilterSpec::Condition{ref condition, ref filters} => {
+                let mut filters_json = Vec::new();
+                for filter in filters.iter() {
+                    filters_json.push(try!(filter.to_json()));
+                }
+
+                let condition_json = match *condition {
+                    TokenCondition::Length{min, max} => json!({"length": {"min": min, "max": max}}),
+                    TokenCondition::Script(ref script) => json!({"script": script}),
+                    TokenCondition::Pattern(ref pattern) => json!({"pattern": pattern}),
+                };
+
+                Ok(json!({
+                    "type": "condition",
+                    "filter": filters_json,
+                    "condition": condition_json,
+                }))
+            }
         }
     }
 }
@@ -0,0 +1,136 @@
+//! Runs each token through several chains of filters and emits all of the results
+//!
+//! The outputs of every chain are given the position of the token they came from,
+//! so different forms of a word (eg, the original and its stem) can be indexed
+//! together. Duplicate terms at a position are only emitted once.
+//!
+//! Each chain's filters are only initialised once, see `FilterChain`.
+
+use std::collections::{HashSet, VecDeque};
+
+use kite::{Term, Token};
+
+use analysis::TokenStream;
+use analysis::filters::FilterSpec;
+use analysis::filters::chain::FilterChain;
+
+
+pub struct MultiplexerFilter<'a> {
+    tokens: TokenStream<'a>,
+    chains: Vec<FilterChain>,
+    preserve_original: bool,
+    output_buffer: VecDeque<Token>,
+}
+
+
+impl<'a> MultiplexerFilter<'a> {
+    pub fn new(tokens: TokenStream<'a>, chains: &[Vec<FilterSpec>], preserve_original: bool, keywords: &HashSet<String>) -> MultiplexerFilter<'a> {
+        MultiplexerFilter {
+            tokens: tokens,
+            chains: chains.iter().map(|chain| FilterChain::new(chain, keywords)).collect(),
+            preserve_original: preserve_original,
+            output_buffer: VecDeque::new(),
+        }
+    }
+
+    fn process_token(&mut self, token: Token) {
+        let mut terms: Vec<Term> = Vec::new();
+
+        if self.preserve_original {
+            terms.push(token.term.clone());
+        }
+
+        for chain in self.chains.iter_mut() {
+            let outputs = chain.process(Token {
+                term: token.term.clone(),
+                position: token.position,
+            });
+
+            for output in outputs {
+                if !terms.contains(&output.term) {
+                    terms.push(output.term);
+                }
+            }
+        }
+
+        for term in terms {
+            self.output_buffer.push_back(Token {
+                term: term,
+                position: token.position,
+            });
+        }
+    }
+}
+
+
+impl<'a> Iterator for MultiplexerFilter<'a> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        while self.output_buffer.is_empty() {
+            match self.tokens.next() {
+                Some(token) => self.process_token(token),
+                None => return None,
+            }
+        }
+
+        self.output_buffer.pop_front()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use kite::{Term, Token};
+
+    use analysis::filters::FilterSpec;
+    use analysis::phonetic::PhoneticEncoder;
+
+    use super::MultiplexerFilter;
+
+    #[test]
+    fn test_multiplexer_filter() {
+        let mut tokens: Vec<Token> = vec![
+            Token { term: Term::from_string("Running"), position: 1 },
+            Token { term: Term::from_string("dogs"), position: 2 },
+        ];
+
+        let chains = vec![
+            vec![FilterSpec::Lowercase],
+            vec![FilterSpec::Lowercase, FilterSpec::PorterStem],
+        ];
+
+        let token_filter = MultiplexerFilter::new(Box::new(tokens.drain((..))), &chains, true, &HashSet::new());
+        let tokens = token_filter.collect::<Vec<Token>>();
+
+        assert_eq!(tokens, vec![
+            Token { term: Term::from_string("Running"), position: 1 },
+            Token { term: Term::from_string("running"), position: 1 },
+            Token { term: Term::from_string("run"), position: 1 },
+            Token { term: Term::from_string("dogs"), position: 2 },
+            Token { term: Term::from_string("dog"), position: 2 },
+        ]);
+    }
+
+    #[test]
+    fn test_multiplexer_filter_without_original() {
+        let mut tokens: Vec<Token> = vec![
+            Token { term: Term::from_string("Smith"), position: 1 },
+        ];
+
+        let chains = vec![
+            vec![FilterSpec::Phonetic { encoder: PhoneticEncoder::Soundex, replace: true }],
+            vec![FilterSpec::Reverse],
+        ];
+
+        let token_filter = MultiplexerFilter::new(Box::new(tokens.drain((..))), &chains, false, &HashSet::new());
+        let tokens = token_filter.collect::<Vec<Token>>();
+
+        assert_eq!(tokens, vec![
+            Token { term: Term::from_string("S530"), position: 1 },
+            Token { term: Term::from_string("htimS"), position: 1 },
+        ]);
+    }
+}
@@ -6,24 +6,34 @@ use std::str;
 use kite::{Term, Token};
 
 use analysis::ngram_generator::{Edge, NGramGenerator};
+use analysis::TokenStream;
 
 
 pub struct NGramFilter<'a> {
-    tokens: Box<Iterator<Item=Token> + 'a>,
+    tokens: TokenStream<'a>,
     min_size: usize,
     max_size: usize,
     edge: Edge,
+    preserve_original: bool,
     output_buffer: VecDeque<Token>,
 }
 
 
 impl<'a> NGramFilter<'a> {
     pub fn new(tokens: Box<Iterator<Item=Token> +'a >, min_size: usize, max_size: usize, edge: Edge) -> NGramFilter<'a> {
+        NGramFilter::new_with_preserve_original(tokens, min_size, max_size, edge, false)
+    }
+
+    /// Creates an NGramFilter that can also emit the source tokens
+    ///
+    /// Source tokens are only emitted if they are not already one of the ngrams.
+    pub fn new_with_preserve_original(tokens: Box<Iterator<Item=Token> +'a >, min_size: usize, max_size: usize, edge: Edge, preserve_original: bool) -> NGramFilter<'a> {
         NGramFilter {
             tokens: tokens,
             min_size: min_size,
             max_size: max_size,
             edge: edge,
+            preserve_original: preserve_original,
             output_buffer: VecDeque::new(),
         }
     }
@@ -41,6 +51,14 @@ impl<'a> Iterator for NGramFilter<'a> {
             match token {
                 Some(token) => {
                     if let Ok(ref word) = str::from_utf8(&token.term.as_bytes()) {
+                        let word_length = word.chars().count();
+                        if self.preserve_original && (word_length < self.min_size || word_length > self.max_size) {
+                            self.output_buffer.push_back(Token {
+                                term: Term::from_string(word),
+                                position: token.position,
+                            });
+                        }
+
                         let ngram_generator = NGramGenerator::new(&word, self.min_size, self.max_size, self.edge);
 
                         for gram in ngram_generator {
@@ -140,4 +158,22 @@ mod tests {
             Token { term: Term::from_string("rld"), position: 2 },
         ]);
     }
+
+    #[test]
+    fn test_edgengram_filter_preserve_original() {
+        let mut tokens: Vec<Token> = vec![
+            Token { term: Term::from_string("hello"), position: 1 },
+            Token { term: Term::from_string("hi"), position: 2 },
+        ];
+
+        let token_filter = NGramFilter::new_with_preserve_original(Box::new(tokens.drain((..))), 2, 3, Edge::Left, true);
+        let tokens = token_filter.collect::<Vec<Token>>();
+
+        assert_eq!(tokens, vec![
+            Token { term: Term::from_string("hello"), position: 1 },
+            Token { term: Term::from_string("he"), position: 1 },
+            Token { term: Term::from_string("hel"), position: 1 },
+            Token { term: Term::from_string("hi"), position: 2 },
+        ]);
+    }
 }
@@ -0,0 +1,114 @@
+//! Converts each token into a code that represents how it sounds
+
+use std::str;
+use std::collections::VecDeque;
+
+use kite::{Term, Token};
+
+use analysis::phonetic::PhoneticEncoder;
+use analysis::TokenStream;
+
+
+pub struct PhoneticFilter<'a> {
+    tokens: TokenStream<'a>,
+    encoder: PhoneticEncoder,
+    replace: bool,
+    output_buffer: VecDeque<Token>,
+}
+
+
+impl<'a> PhoneticFilter<'a> {
+    /// Creates a PhoneticFilter
+    ///
+    /// If `replace` is false, the original tokens are kept and the encoded tokens
+    /// are added at the same positions.
+    pub fn new(tokens: TokenStream<'a>, encoder: PhoneticEncoder, replace: bool) -> PhoneticFilter<'a> {
+        PhoneticFilter {
+            tokens: tokens,
+            encoder: encoder,
+            replace: replace,
+            output_buffer: VecDeque::new(),
+        }
+    }
+}
+
+
+impl<'a> Iterator for PhoneticFilter<'a> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        if let Some(token) = self.output_buffer.pop_front() {
+            return Some(token);
+        }
+
+        let token = match self.tokens.next() {
+            Some(token) => token,
+            None => return None,
+        };
+
+        let code = match str::from_utf8(token.term.as_bytes()) {
+            Ok(string) => self.encoder.encode(string),
+            Err(_) => String::new(),
+        };
+
+        // Tokens that can't be encoded are passed through
+        if code.is_empty() {
+            return Some(token);
+        }
+
+        let encoded_token = Token {
+            term: Term::from_string(&code),
+            position: token.position,
+        };
+
+        if self.replace {
+            Some(encoded_token)
+        } else {
+            self.output_buffer.push_back(encoded_token);
+            Some(token)
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use kite::{Term, Token};
+
+    use analysis::phonetic::PhoneticEncoder;
+
+    use super::PhoneticFilter;
+
+    #[test]
+    fn test_phonetic_filter() {
+        let mut tokens: Vec<Token> = vec![
+            Token { term: Term::from_string("John"), position: 1 },
+            Token { term: Term::from_string("Smyth"), position: 2 },
+        ];
+
+        let token_filter = PhoneticFilter::new(Box::new(tokens.drain((..))), PhoneticEncoder::Soundex, true);
+        let tokens = token_filter.collect::<Vec<Token>>();
+
+        assert_eq!(tokens, vec![
+            Token { term: Term::from_string("J500"), position: 1 },
+            Token { term: Term::from_string("S530"), position: 2 },
+        ]);
+    }
+
+    #[test]
+    fn test_phonetic_filter_keep_original() {
+        let mut tokens: Vec<Token> = vec![
+            Token { term: Term::from_string("Smyth"), position: 1 },
+            Token { term: Term::from_string("42"), position: 2 },
+        ];
+
+        let token_filter = PhoneticFilter::new(Box::new(tokens.drain((..))), PhoneticEncoder::Metaphone, false);
+        let tokens = token_filter.collect::<Vec<Token>>();
+
+        assert_eq!(tokens, vec![
+            Token { term: Term::from_string("Smyth"), position: 1 },
+            Token { term: Term::from_string("SM0"), position: 1 },
+            Token { term: Term::from_string("42"), position: 2 },
+        ]);
+    }
+}
@@ -0,0 +1,67 @@
+//! Reverses the characters of each token
+//!
+//! Indexing a reversed copy of a field turns suffix searches into prefix searches.
+
+use std::str;
+
+use kite::{Term, Token};
+
+use analysis::TokenStream;
+
+
+pub struct ReverseFilter<'a> {
+    tokens: TokenStream<'a>,
+}
+
+
+impl<'a> ReverseFilter<'a> {
+    pub fn new(tokens: TokenStream<'a>) -> ReverseFilter<'a> {
+        ReverseFilter {
+            tokens: tokens,
+        }
+    }
+}
+
+
+impl<'a> Iterator for ReverseFilter<'a> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        match self.tokens.next() {
+            Some(token) => {
+                Some(Token {
+                    term: match str::from_utf8(token.term.as_bytes()) {
+                        Ok(string) => Term::from_string(&string.chars().rev().collect::<String>()),
+                        _ => token.term.clone(),
+                    },
+                    position: token.position,
+                })
+            }
+            None => None
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use kite::{Term, Token};
+
+    use super::ReverseFilter;
+
+    #[test]
+    fn test_reverse_filter() {
+        let mut tokens: Vec<Token> = vec![
+            Token { term: Term::from_string("hello"), position: 1 },
+            Token { term: Term::from_string("ハチ公"), position: 2 },
+        ];
+
+        let token_filter = ReverseFilter::new(Box::new(tokens.drain((..))));
+        let tokens = token_filter.collect::<Vec<Token>>();
+
+        assert_eq!(tokens, vec![
+            Token { term: Term::from_string("olleh"), position: 1 },
+            Token { term: Term::from_string("公チハ"), position: 2 },
+        ]);
+    }
+}
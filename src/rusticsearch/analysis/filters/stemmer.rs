@@ -0,0 +1,146 @@
+//! Reduces each token to its stem using one of the Snowball stemming algorithms
+
+use std::str;
+use std::collections::HashSet;
+
+use rust_stemmers::{Algorithm, Stemmer};
+use kite::{Term, Token};
+
+use analysis::TokenStream;
+
+
+/// Finds the stemming algorithm for a language name (eg, "german")
+pub fn get_algorithm(language: &str) -> Option<Algorithm> {
+    match language {
+        "danish" => Some(Algorithm::Danish),
+        "dutch" => Some(Algorithm::Dutch),
+        "english" | "porter2" => Some(Algorithm::English),
+        "finnish" => Some(Algorithm::Finnish),
+        "french" => Some(Algorithm::French),
+        "german" => Some(Algorithm::German),
+        "hungarian" => Some(Algorithm::Hungarian),
+        "italian" => Some(Algorithm::Italian),
+        "portuguese" => Some(Algorithm::Portuguese),
+        "romanian" => Some(Algorithm::Romanian),
+        "russian" => Some(Algorithm::Russian),
+        "spanish" => Some(Algorithm::Spanish),
+        "swedish" => Some(Algorithm::Swedish),
+        "turkish" => Some(Algorithm::Turkish),
+        _ => None,
+    }
+}
+
+
+pub struct StemmerFilter<'a> {
+    tokens: TokenStream<'a>,
+    stemmer: Stemmer,
+    keywords: HashSet<String>,
+}
+
+
+impl<'a> StemmerFilter<'a> {
+    pub fn new(tokens: TokenStream<'a>, algorithm: Algorithm) -> StemmerFilter<'a> {
+        StemmerFilter::new_with_keywords(tokens, algorithm, HashSet::new())
+    }
+
+    /// Creates a StemmerFilter that leaves the given keywords unchanged
+    pub fn new_with_keywords(tokens: TokenStream<'a>, algorithm: Algorithm, keywords: HashSet<String>) -> StemmerFilter<'a> {
+        StemmerFilter {
+            tokens: tokens,
+            stemmer: Stemmer::create(algorithm),
+            keywords: keywords,
+        }
+    }
+}
+
+
+impl<'a> Iterator for StemmerFilter<'a> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        match self.tokens.next() {
+            Some(token) => {
+                Some(Token {
+                    term: match str::from_utf8(token.term.as_bytes()) {
+                        Ok(string) if self.keywords.contains(string) => token.term.clone(),
+                        Ok(string) => {
+                            Term::from_string(&self.stemmer.stem(string))
+                        }
+                        _ => token.term.clone(),
+                    },
+                    position: token.position,
+                })
+            }
+            None => None
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use rust_stemmers::Algorithm;
+    use kite::{Term, Token};
+
+    use super::{StemmerFilter, get_algorithm};
+
+    #[test]
+    fn test_english_stemmer_filter() {
+        let mut tokens: Vec<Token> = vec![
+            Token { term: Term::from_string("running"), position: 1 },
+            Token { term: Term::from_string("run"), position: 2 },
+            Token { term: Term::from_string("generously"), position: 3 },
+        ];
+
+        let token_filter = StemmerFilter::new(Box::new(tokens.drain((..))), Algorithm::English);
+        let tokens = token_filter.collect::<Vec<Token>>();
+
+        assert_eq!(tokens, vec![
+            Token { term: Term::from_string("run"), position: 1 },
+            Token { term: Term::from_string("run"), position: 2 },
+            Token { term: Term::from_string("generous"), position: 3 },
+        ]);
+    }
+
+    #[test]
+    fn test_german_stemmer_filter() {
+        let mut tokens: Vec<Token> = vec![
+            Token { term: Term::from_string("häuser"), position: 1 },
+            Token { term: Term::from_string("haus"), position: 2 },
+        ];
+
+        let token_filter = StemmerFilter::new(Box::new(tokens.drain((..))), get_algorithm("german").unwrap());
+        let tokens = token_filter.collect::<Vec<Token>>();
+
+        assert_eq!(tokens, vec![
+            Token { term: Term::from_string("haus"), position: 1 },
+            Token { term: Term::from_string("haus"), position: 2 },
+        ]);
+    }
+
+    #[test]
+    fn test_stemmer_filter_keywords() {
+        let mut tokens: Vec<Token> = vec![
+            Token { term: Term::from_string("running"), position: 1 },
+            Token { term: Term::from_string("shoes"), position: 2 },
+        ];
+
+        let mut keywords = HashSet::new();
+        keywords.insert("running".to_string());
+
+        let token_filter = StemmerFilter::new_with_keywords(Box::new(tokens.drain((..))), Algorithm::English, keywords);
+        let tokens = token_filter.collect::<Vec<Token>>();
+
+        assert_eq!(tokens, vec![
+            Token { term: Term::from_string("running"), position: 1 },
+            Token { term: Term::from_string("shoe"), position: 2 },
+        ]);
+    }
+
+    #[test]
+    fn test_get_algorithm_unknown_language() {
+        assert!(get_algorithm("klingon").is_none());
+    }
+}
@@ -0,0 +1,127 @@
+//! Replaces tokens with stems from a custom dictionary
+//!
+//! Rules are in the format `"running, ran => run"`. The replacement stems are
+//! protected from any stemmers further down the filter chain.
+
+use std::str;
+use std::collections::HashMap;
+
+use kite::{Term, Token};
+
+use analysis::TokenStream;
+
+
+/// Parses a list of stemmer override rules into (word, stem) pairs
+///
+/// Returns the first invalid rule as an error.
+pub fn parse_rules(rules: &[String]) -> Result<Vec<(String, String)>, String> {
+    let mut overrides = Vec::new();
+
+    for rule in rules.iter() {
+        let mut sides = rule.split("=>");
+        let (words, stem) = match (sides.next(), sides.next(), sides.next()) {
+            (Some(words), Some(stem), None) => (words, stem.trim()),
+            _ => return Err(rule.clone()),
+        };
+
+        if stem.is_empty() || stem.contains(',') {
+            return Err(rule.clone());
+        }
+
+        for word in words.split(',') {
+            let word = word.trim();
+
+            if word.is_empty() {
+                return Err(rule.clone());
+            }
+
+            overrides.push((word.to_string(), stem.to_string()));
+        }
+    }
+
+    Ok(overrides)
+}
+
+
+pub struct StemmerOverrideFilter<'a> {
+    tokens: TokenStream<'a>,
+    overrides: HashMap<String, String>,
+}
+
+
+impl<'a> StemmerOverrideFilter<'a> {
+    pub fn new(tokens: TokenStream<'a>, overrides: &[(String, String)]) -> StemmerOverrideFilter<'a> {
+        StemmerOverrideFilter {
+            tokens: tokens,
+            overrides: overrides.iter().cloned().collect(),
+        }
+    }
+}
+
+
+impl<'a> Iterator for StemmerOverrideFilter<'a> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        match self.tokens.next() {
+            Some(token) => {
+                Some(Token {
+                    term: match str::from_utf8(token.term.as_bytes()) {
+                        Ok(string) => {
+                            match self.overrides.get(string) {
+                                Some(stem) => Term::from_string(stem),
+                                None => token.term.clone(),
+                            }
+                        }
+                        _ => token.term.clone(),
+                    },
+                    position: token.position,
+                })
+            }
+            None => None
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use kite::{Term, Token};
+
+    use super::{StemmerOverrideFilter, parse_rules};
+
+    #[test]
+    fn test_parse_rules() {
+        let rules = vec!["mice, mouses => mouse".to_string(), "feet => foot".to_string()];
+
+        assert_eq!(parse_rules(&rules), Ok(vec![
+            ("mice".to_string(), "mouse".to_string()),
+            ("mouses".to_string(), "mouse".to_string()),
+            ("feet".to_string(), "foot".to_string()),
+        ]));
+    }
+
+    #[test]
+    fn test_parse_invalid_rules() {
+        assert_eq!(parse_rules(&["mice".to_string()]), Err("mice".to_string()));
+        assert_eq!(parse_rules(&["mice => ".to_string()]), Err("mice => ".to_string()));
+        assert_eq!(parse_rules(&["mice => mouse, rat".to_string()]), Err("mice => mouse, rat".to_string()));
+    }
+
+    #[test]
+    fn test_stemmer_override_filter() {
+        let mut tokens: Vec<Token> = vec![
+            Token { term: Term::from_string("three"), position: 1 },
+            Token { term: Term::from_string("mice"), position: 2 },
+        ];
+
+        let overrides = vec![("mice".to_string(), "mouse".to_string())];
+        let token_filter = StemmerOverrideFilter::new(Box::new(tokens.drain((..))), &overrides);
+        let tokens = token_filter.collect::<Vec<Token>>();
+
+        assert_eq!(tokens, vec![
+            Token { term: Term::from_string("three"), position: 1 },
+            Token { term: Term::from_string("mouse"), position: 2 },
+        ]);
+    }
+}
@@ -0,0 +1,79 @@
+//! Removes stopwords from the token stream
+//!
+//! The positions of the remaining tokens are left unchanged so phrase queries can't
+//! match across the gaps.
+
+use std::str;
+use std::sync::Arc;
+use std::collections::HashSet;
+
+use kite::Token;
+
+use analysis::TokenStream;
+
+
+pub struct StopFilter<'a> {
+    tokens: TokenStream<'a>,
+    words: Arc<HashSet<String>>,
+}
+
+
+impl<'a> StopFilter<'a> {
+    pub fn new(tokens: TokenStream<'a>, words: Arc<HashSet<String>>) -> StopFilter<'a> {
+        StopFilter {
+            tokens: tokens,
+            words: words,
+        }
+    }
+
+    fn is_stopword(&self, token: &Token) -> bool {
+        match str::from_utf8(token.term.as_bytes()) {
+            Ok(string) => self.words.contains(string),
+            Err(_) => false,
+        }
+    }
+}
+
+
+impl<'a> Iterator for StopFilter<'a> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        while let Some(token) = self.tokens.next() {
+            if !self.is_stopword(&token) {
+                return Some(token);
+            }
+        }
+
+        None
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use kite::{Term, Token};
+
+    use super::StopFilter;
+
+    #[test]
+    fn test_stop_filter() {
+        let mut tokens: Vec<Token> = vec![
+            Token { term: Term::from_string("the"), position: 1 },
+            Token { term: Term::from_string("quick"), position: 2 },
+            Token { term: Term::from_string("and"), position: 3 },
+            Token { term: Term::from_string("the"), position: 4 },
+            Token { term: Term::from_string("dead"), position: 5 },
+        ];
+
+        let token_filter = StopFilter::new(Box::new(tokens.drain((..))), Arc::new(vec!["the".to_string(), "and".to_string()].into_iter().collect()));
+        let tokens = token_filter.collect::<Vec<Token>>();
+
+        assert_eq!(tokens, vec![
+            Token { term: Term::from_string("quick"), position: 2 },
+            Token { term: Term::from_string("dead"), position: 5 },
+        ]);
+    }
+}
@@ -0,0 +1,259 @@
+//! Injects synonyms into the token stream
+//!
+//! Rules are given in the Solr format. Either a comma-separated list of equivalent
+//! phrases (`"nyc, new york"`) or explicit mappings that replace the matched phrase
+//! (`"i-pod, i pod => ipod"`). Words in the rules must match the terms produced by the
+//! tokenizer and filters before this one so, for example, if the text has been
+//! lowercased, the rules must be written in lowercase.
+//!
+//! Multi-word synonyms are emitted at consecutive positions starting from the position
+//! of the first token that was matched.
+//!
+//! This is a flat filter, like Elasticsearch's "synonym" filter. There's no
+//! "synonym_graph" filter because tokens don't have a position length and there are no
+//! positional (phrase) queries that could make use of one.
+
+use std::str;
+use std::cmp;
+use std::sync::Arc;
+use std::collections::{HashMap, VecDeque};
+
+use kite::{Term, Token};
+
+use analysis::TokenStream;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SynonymMap {
+    rules: HashMap<Vec<String>, Vec<Vec<String>>>,
+    max_phrase_length: usize,
+}
+
+
+fn parse_phrases(phrases: &str) -> Result<Vec<Vec<String>>, String> {
+    let mut parsed = Vec::new();
+
+    for phrase in phrases.split(',') {
+        let words = phrase.split_whitespace().map(|word| word.to_string()).collect::<Vec<String>>();
+
+        if words.is_empty() {
+            return Err(phrases.to_string());
+        }
+
+        parsed.push(words);
+    }
+
+    Ok(parsed)
+}
+
+
+impl SynonymMap {
+    /// Builds a synonym map from a list of Solr-format rules
+    ///
+    /// Returns the first invalid rule as an error.
+    pub fn from_rules(rules: &[String]) -> Result<SynonymMap, String> {
+        let mut map = SynonymMap {
+            rules: HashMap::new(),
+            max_phrase_length: 0,
+        };
+
+        for rule in rules.iter() {
+            let rule = rule.trim();
+
+            // Skip blank lines and comments
+            if rule.is_empty() || rule.starts_with('#') {
+                continue;
+            }
+
+            let mut sides = rule.split("=>");
+            let (inputs, outputs) = match (sides.next(), sides.next(), sides.next()) {
+                (Some(inputs), Some(outputs), None) => {
+                    let inputs = try!(parse_phrases(inputs).map_err(|_| rule.to_string()));
+                    let outputs = try!(parse_phrases(outputs).map_err(|_| rule.to_string()));
+                    (inputs, outputs)
+                }
+                (Some(phrases), None, None) => {
+                    let phrases = try!(parse_phrases(phrases).map_err(|_| rule.to_string()));
+                    (phrases.clone(), phrases)
+                }
+                _ => return Err(rule.to_string()),
+            };
+
+            for input in inputs {
+                map.max_phrase_length = cmp::max(map.max_phrase_length, input.len());
+
+                let existing_outputs = map.rules.entry(input).or_insert_with(Vec::new);
+                for output in outputs.iter() {
+                    if !existing_outputs.contains(output) {
+                        existing_outputs.push(output.clone());
+                    }
+                }
+            }
+        }
+
+        Ok(map)
+    }
+}
+
+
+/// Adds the synonyms of each phrase in the rules
+///
+/// The words of a multi-word synonym reuse the positions of the tokens that were matched
+/// (and the positions after them), so they're interleaved with the other synonyms.
+pub struct SynonymFilter<'a> {
+    tokens: TokenStream<'a>,
+    synonyms: Arc<SynonymMap>,
+    input_buffer: VecDeque<Token>,
+    output_buffer: VecDeque<Token>,
+}
+
+
+impl<'a> SynonymFilter<'a> {
+    pub fn new(tokens: TokenStream<'a>, synonyms: Arc<SynonymMap>) -> SynonymFilter<'a> {
+        SynonymFilter {
+            tokens: tokens,
+            synonyms: synonyms,
+            input_buffer: VecDeque::new(),
+            output_buffer: VecDeque::new(),
+        }
+    }
+
+    /// Finds the longest phrase at the start of the input buffer that has synonyms
+    fn find_match(&self) -> Option<(usize, &Vec<Vec<String>>)> {
+        let mut phrase = Vec::new();
+        let mut longest_match = None;
+
+        for token in self.input_buffer.iter() {
+            match str::from_utf8(token.term.as_bytes()) {
+                Ok(word) => phrase.push(word.to_string()),
+                Err(_) => break,
+            }
+
+            if let Some(outputs) = self.synonyms.rules.get(&phrase) {
+                longest_match = Some((phrase.len(), outputs));
+            }
+        }
+
+        longest_match
+    }
+}
+
+
+impl<'a> Iterator for SynonymFilter<'a> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        if let Some(token) = self.output_buffer.pop_front() {
+            return Some(token);
+        }
+
+        // Make sure there are enough tokens buffered to match the longest phrase
+        while self.input_buffer.len() < cmp::max(self.synonyms.max_phrase_length, 1) {
+            match self.tokens.next() {
+                Some(token) => self.input_buffer.push_back(token),
+                None => break,
+            }
+        }
+
+        let matched = match self.find_match() {
+            Some((length, outputs)) => {
+                let start_position = self.input_buffer[0].position;
+                let mut output = Vec::new();
+
+                for phrase in outputs.iter() {
+                    for (i, word) in phrase.iter().enumerate() {
+                        output.push(Token {
+                            term: Term::from_string(word),
+                            position: start_position + i as u32,
+                        });
+                    }
+                }
+
+                Some((length, output))
+            }
+            None => None,
+        };
+
+        match matched {
+            Some((length, mut output)) => {
+                for _ in 0..length {
+                    self.input_buffer.pop_front();
+                }
+
+                // Sort is stable, so tokens at the same position stay in rule order
+                output.sort_by_key(|token| token.position);
+                self.output_buffer.extend(output);
+                self.output_buffer.pop_front()
+            }
+            None => self.input_buffer.pop_front(),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use kite::{Term, Token};
+
+    use std::sync::Arc;
+
+    use super::{SynonymFilter, SynonymMap};
+
+    fn tokens(words: &[&str]) -> Vec<Token> {
+        words.iter().enumerate().map(|(i, word)| {
+            Token { term: Term::from_string(word), position: i as u32 + 1 }
+        }).collect()
+    }
+
+    #[test]
+    fn test_equivalent_synonyms() {
+        let synonyms = Arc::new(SynonymMap::from_rules(&["quick, fast".to_string()]).unwrap());
+        let mut input = tokens(&["the", "quick", "fox"]);
+
+        let token_filter = SynonymFilter::new(Box::new(input.drain((..))), synonyms);
+        let output = token_filter.collect::<Vec<Token>>();
+
+        assert_eq!(output, vec![
+            Token { term: Term::from_string("the"), position: 1 },
+            Token { term: Term::from_string("quick"), position: 2 },
+            Token { term: Term::from_string("fast"), position: 2 },
+            Token { term: Term::from_string("fox"), position: 3 },
+        ]);
+    }
+
+    #[test]
+    fn test_multi_word_synonyms() {
+        let synonyms = Arc::new(SynonymMap::from_rules(&["nyc, new york".to_string()]).unwrap());
+        let mut input = tokens(&["new", "york", "pizza"]);
+
+        let token_filter = SynonymFilter::new(Box::new(input.drain((..))), synonyms);
+        let output = token_filter.collect::<Vec<Token>>();
+
+        assert_eq!(output, vec![
+            Token { term: Term::from_string("nyc"), position: 1 },
+            Token { term: Term::from_string("new"), position: 1 },
+            Token { term: Term::from_string("york"), position: 2 },
+            Token { term: Term::from_string("pizza"), position: 3 },
+        ]);
+    }
+
+    #[test]
+    fn test_explicit_mapping() {
+        let synonyms = Arc::new(SynonymMap::from_rules(&["i pod, i-pod => ipod".to_string()]).unwrap());
+        let mut input = tokens(&["my", "i", "pod"]);
+
+        let token_filter = SynonymFilter::new(Box::new(input.drain((..))), synonyms);
+        let output = token_filter.collect::<Vec<Token>>();
+
+        assert_eq!(output, vec![
+            Token { term: Term::from_string("my"), position: 1 },
+            Token { term: Term::from_string("ipod"), position: 2 },
+        ]);
+    }
+
+    #[test]
+    fn test_invalid_rule() {
+        assert_eq!(SynonymMap::from_rules(&["foo => bar => baz".to_string()]), Err("foo => bar => baz".to_string()));
+        assert_eq!(SynonymMap::from_rules(&["foo, , bar".to_string()]), Err("foo, , bar".to_string()));
+    }
+}
@@ -0,0 +1,65 @@
+//! Removes leading and trailing whitespace from each token
+
+use std::str;
+
+use kite::{Term, Token};
+
+use analysis::TokenStream;
+
+
+pub struct TrimFilter<'a> {
+    tokens: TokenStream<'a>,
+}
+
+
+impl<'a> TrimFilter<'a> {
+    pub fn new(tokens: TokenStream<'a>) -> TrimFilter<'a> {
+        TrimFilter {
+            tokens: tokens,
+        }
+    }
+}
+
+
+impl<'a> Iterator for TrimFilter<'a> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        match self.tokens.next() {
+            Some(token) => {
+                Some(Token {
+                    term: match str::from_utf8(token.term.as_bytes()) {
+                        Ok(string) => Term::from_string(string.trim()),
+                        _ => token.term.clone(),
+                    },
+                    position: token.position,
+                })
+            }
+            None => None
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use kite::{Term, Token};
+
+    use super::TrimFilter;
+
+    #[test]
+    fn test_trim_filter() {
+        let mut tokens: Vec<Token> = vec![
+            Token { term: Term::from_string(" hello world\t"), position: 1 },
+            Token { term: Term::from_string("foo"), position: 2 },
+        ];
+
+        let token_filter = TrimFilter::new(Box::new(tokens.drain((..))));
+        let tokens = token_filter.collect::<Vec<Token>>();
+
+        assert_eq!(tokens, vec![
+            Token { term: Term::from_string("hello world"), position: 1 },
+            Token { term: Term::from_string("foo"), position: 2 },
+        ]);
+    }
+}
@@ -0,0 +1,74 @@
+//! Truncates each token to a maximum number of characters
+
+use std::str;
+
+use kite::{Term, Token};
+
+use analysis::TokenStream;
+
+
+pub struct TruncateFilter<'a> {
+    tokens: TokenStream<'a>,
+    length: usize,
+}
+
+
+impl<'a> TruncateFilter<'a> {
+    pub fn new(tokens: TokenStream<'a>, length: usize) -> TruncateFilter<'a> {
+        TruncateFilter {
+            tokens: tokens,
+            length: length,
+        }
+    }
+}
+
+
+impl<'a> Iterator for TruncateFilter<'a> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        match self.tokens.next() {
+            Some(token) => {
+                Some(Token {
+                    term: match str::from_utf8(token.term.as_bytes()) {
+                        Ok(string) => {
+                            match string.char_indices().nth(self.length) {
+                                Some((end, _)) => Term::from_string(&string[..end]),
+                                None => token.term.clone(),
+                            }
+                        }
+                        _ => token.term.clone(),
+                    },
+                    position: token.position,
+                })
+            }
+            None => None
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use kite::{Term, Token};
+
+    use super::TruncateFilter;
+
+    #[test]
+    fn test_truncate_filter() {
+        let mut tokens: Vec<Token> = vec![
+            Token { term: Term::from_string("hello"), position: 1 },
+            Token { term: Term::from_string("こんにちは"), position: 2 },
+            Token { term: Term::from_string("hi"), position: 3 },
+        ];
+
+        let token_filter = TruncateFilter::new(Box::new(tokens.drain((..))), 3);
+        let tokens = token_filter.collect::<Vec<Token>>();
+
+        assert_eq!(tokens, vec![
+            Token { term: Term::from_string("hel"), position: 1 },
+            Token { term: Term::from_string("こんに"), position: 2 },
+            Token { term: Term::from_string("hi"), position: 3 },
+        ]);
+    }
+}
@@ -0,0 +1,95 @@
+//! Removes duplicate tokens from the stream
+//!
+//! Only the first occurrence of each term is kept. If `only_on_same_position` is set,
+//! duplicates are only removed when they share a position (eg, when a stemmer has
+//! emitted the same stem as the original word).
+
+use std::collections::HashSet;
+
+use kite::Token;
+
+use analysis::TokenStream;
+
+
+pub struct UniqueFilter<'a> {
+    tokens: TokenStream<'a>,
+    only_on_same_position: bool,
+    current_position: Option<u32>,
+    seen: HashSet<Vec<u8>>,
+}
+
+
+impl<'a> UniqueFilter<'a> {
+    pub fn new(tokens: TokenStream<'a>, only_on_same_position: bool) -> UniqueFilter<'a> {
+        UniqueFilter {
+            tokens: tokens,
+            only_on_same_position: only_on_same_position,
+            current_position: None,
+            seen: HashSet::new(),
+        }
+    }
+}
+
+
+impl<'a> Iterator for UniqueFilter<'a> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        while let Some(token) = self.tokens.next() {
+            if self.only_on_same_position && self.current_position != Some(token.position) {
+                self.seen.clear();
+                self.current_position = Some(token.position);
+            }
+
+            if self.seen.insert(token.term.as_bytes().to_vec()) {
+                return Some(token);
+            }
+        }
+
+        None
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use kite::{Term, Token};
+
+    use super::UniqueFilter;
+
+    #[test]
+    fn test_unique_filter() {
+        let mut tokens: Vec<Token> = vec![
+            Token { term: Term::from_string("the"), position: 1 },
+            Token { term: Term::from_string("cat"), position: 2 },
+            Token { term: Term::from_string("the"), position: 3 },
+            Token { term: Term::from_string("dog"), position: 4 },
+        ];
+
+        let token_filter = UniqueFilter::new(Box::new(tokens.drain((..))), false);
+        let tokens = token_filter.collect::<Vec<Token>>();
+
+        assert_eq!(tokens, vec![
+            Token { term: Term::from_string("the"), position: 1 },
+            Token { term: Term::from_string("cat"), position: 2 },
+            Token { term: Term::from_string("dog"), position: 4 },
+        ]);
+    }
+
+    #[test]
+    fn test_unique_filter_only_on_same_position() {
+        let mut tokens: Vec<Token> = vec![
+            Token { term: Term::from_string("run"), position: 1 },
+            Token { term: Term::from_string("run"), position: 1 },
+            Token { term: Term::from_string("run"), position: 2 },
+        ];
+
+        let token_filter = UniqueFilter::new(Box::new(tokens.drain((..))), true);
+        let tokens = token_filter.collect::<Vec<Token>>();
+
+        assert_eq!(tokens, vec![
+            Token { term: Term::from_string("run"), position: 1 },
+            Token { term: Term::from_string("run"), position: 2 },
+        ]);
+    }
+}
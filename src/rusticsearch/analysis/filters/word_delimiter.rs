@@ -0,0 +1,332 @@
+//! Splits tokens into subwords on delimiters, case changes and letter-number transitions
+//!
+//! For example, "PowerShot500" becomes "Power", "Shot" and "500". The subwords are given
+//! consecutive positions and the positions of the following tokens are shifted along to
+//! make room for them.
+
+use std::str;
+use std::collections::VecDeque;
+
+use kite::{Term, Token};
+
+use analysis::TokenStream;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WordDelimiterOptions {
+    pub generate_word_parts: bool,
+    pub generate_number_parts: bool,
+    pub catenate_words: bool,
+    pub catenate_numbers: bool,
+    pub catenate_all: bool,
+    pub split_on_case_change: bool,
+    pub split_on_numerics: bool,
+    pub preserve_original: bool,
+    pub stem_english_possessive: bool,
+}
+
+
+impl Default for WordDelimiterOptions {
+    fn default() -> WordDelimiterOptions {
+        WordDelimiterOptions {
+            generate_word_parts: true,
+            generate_number_parts: true,
+            catenate_words: false,
+            catenate_numbers: false,
+            catenate_all: false,
+            split_on_case_change: true,
+            split_on_numerics: true,
+            preserve_original: false,
+            stem_english_possessive: true,
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CharType {
+    Lower,
+    Upper,
+    Digit,
+    Delimiter,
+}
+
+
+impl CharType {
+    fn of(c: char) -> CharType {
+        if c.is_numeric() {
+            CharType::Digit
+        } else if c.is_uppercase() {
+            CharType::Upper
+        } else if c.is_alphabetic() {
+            CharType::Lower
+        } else {
+            CharType::Delimiter
+        }
+    }
+
+    fn is_letter(&self) -> bool {
+        *self == CharType::Lower || *self == CharType::Upper
+    }
+}
+
+
+/// Splits a word into its parts. Each part is returned with a flag that is true if
+/// the part is a number
+fn split_word(word: &str, options: &WordDelimiterOptions) -> Vec<(String, bool)> {
+    let chars = word.chars().map(|c| (c, CharType::of(c))).collect::<Vec<_>>();
+    let mut parts: Vec<(String, bool)> = Vec::new();
+    let mut current = String::new();
+    let mut current_is_number = false;
+
+    for (i, &(c, char_type)) in chars.iter().enumerate() {
+        if char_type == CharType::Delimiter {
+            if !current.is_empty() {
+                parts.push((current, current_is_number));
+                current = String::new();
+            }
+
+            // Strip "'s" from the end of the word
+            if options.stem_english_possessive && c == '\'' && i + 2 == chars.len() && (chars[i + 1].0 == 's' || chars[i + 1].0 == 'S') {
+                break;
+            }
+
+            continue;
+        }
+
+        if !current.is_empty() {
+            let previous_type = chars[i - 1].1;
+            let next_type = chars.get(i + 1).map(|&(_, char_type)| char_type);
+
+            let is_boundary = if previous_type.is_letter() && char_type.is_letter() {
+                // "powerShot" splits before the "S" and "PDFFile" before the second "F"
+                options.split_on_case_change && char_type == CharType::Upper && (previous_type == CharType::Lower || next_type == Some(CharType::Lower))
+            } else {
+                options.split_on_numerics && previous_type != char_type && (previous_type == CharType::Digit || char_type == CharType::Digit)
+            };
+
+            if is_boundary {
+                parts.push((current, current_is_number));
+                current = String::new();
+            }
+        }
+
+        if current.is_empty() {
+            current_is_number = char_type == CharType::Digit;
+        }
+
+        current.push(c);
+    }
+
+    if !current.is_empty() {
+        parts.push((current, current_is_number));
+    }
+
+    parts
+}
+
+
+/// Joins together each run of two or more consecutive parts of the given kind,
+/// returning the index of the first part in each run along with the joined string
+fn catenate_runs(parts: &[(String, bool)], numbers: bool) -> Vec<(usize, String)> {
+    let mut runs = Vec::new();
+    let mut run_start = 0;
+    let mut run_length = 0;
+    let mut run = String::new();
+
+    for (i, &(ref part, is_number)) in parts.iter().enumerate() {
+        if is_number == numbers {
+            if run_length == 0 {
+                run_start = i;
+            }
+
+            run.push_str(part);
+            run_length += 1;
+        } else {
+            if run_length > 1 {
+                runs.push((run_start, run.clone()));
+            }
+
+            run.clear();
+            run_length = 0;
+        }
+    }
+
+    if run_length > 1 {
+        runs.push((run_start, run));
+    }
+
+    runs
+}
+
+
+pub struct WordDelimiterFilter<'a> {
+    tokens: TokenStream<'a>,
+    options: WordDelimiterOptions,
+    position_offset: u32,
+    output_buffer: VecDeque<Token>,
+}
+
+
+impl<'a> WordDelimiterFilter<'a> {
+    pub fn new(tokens: TokenStream<'a>, options: WordDelimiterOptions) -> WordDelimiterFilter<'a> {
+        WordDelimiterFilter {
+            tokens: tokens,
+            options: options,
+            position_offset: 0,
+            output_buffer: VecDeque::new(),
+        }
+    }
+
+    fn process_token(&mut self, token: Token) {
+        let position = token.position + self.position_offset;
+
+        let word = str::from_utf8(token.term.as_bytes()).ok().map(|word| word.to_string());
+        let word = match word {
+            Some(word) => word,
+            None => {
+                self.output_buffer.push_back(Token {
+                    term: token.term,
+                    position: position,
+                });
+                return;
+            }
+        };
+
+        let parts = split_word(&word, &self.options);
+
+        // Nothing to split, pass the token through
+        if parts.len() == 1 && parts[0].0 == word {
+            self.output_buffer.push_back(Token {
+                term: token.term,
+                position: position,
+            });
+            return;
+        }
+
+        // Each output is paired with the index of the part it starts at
+        let mut output: Vec<(usize, String)> = Vec::new();
+
+        if self.options.preserve_original {
+            output.push((0, word.clone()));
+        }
+
+        for (i, &(ref part, is_number)) in parts.iter().enumerate() {
+            if (is_number && self.options.generate_number_parts) || (!is_number && self.options.generate_word_parts) {
+                output.push((i, part.clone()));
+            }
+        }
+
+        if self.options.catenate_words {
+            output.extend(catenate_runs(&parts, false));
+        }
+
+        if self.options.catenate_numbers {
+            output.extend(catenate_runs(&parts, true));
+        }
+
+        if self.options.catenate_all && parts.len() > 1 {
+            output.push((0, parts.iter().map(|&(ref part, _)| part.as_str()).collect::<Vec<&str>>().concat()));
+        }
+
+        // Sort is stable, so the original stays in front of the parts
+        output.sort_by_key(|&(i, _)| i);
+
+        for (i, string) in output {
+            self.output_buffer.push_back(Token {
+                term: Term::from_string(&string),
+                position: position + i as u32,
+            });
+        }
+
+        // Make room for the extra parts
+        if parts.len() > 1 {
+            self.position_offset += parts.len() as u32 - 1;
+        }
+    }
+}
+
+
+impl<'a> Iterator for WordDelimiterFilter<'a> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        while self.output_buffer.is_empty() {
+            match self.tokens.next() {
+                Some(token) => self.process_token(token),
+                None => return None,
+            }
+        }
+
+        self.output_buffer.pop_front()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use kite::{Term, Token};
+
+    use super::{WordDelimiterFilter, WordDelimiterOptions};
+
+    #[test]
+    fn test_word_delimiter_filter() {
+        let mut tokens: Vec<Token> = vec![
+            Token { term: Term::from_string("PowerShot500"), position: 1 },
+            Token { term: Term::from_string("camera"), position: 2 },
+        ];
+
+        let token_filter = WordDelimiterFilter::new(Box::new(tokens.drain((..))), WordDelimiterOptions::default());
+        let tokens = token_filter.collect::<Vec<Token>>();
+
+        assert_eq!(tokens, vec![
+            Token { term: Term::from_string("Power"), position: 1 },
+            Token { term: Term::from_string("Shot"), position: 2 },
+            Token { term: Term::from_string("500"), position: 3 },
+            Token { term: Term::from_string("camera"), position: 4 },
+        ]);
+    }
+
+    #[test]
+    fn test_word_delimiter_filter_catenate() {
+        let mut tokens: Vec<Token> = vec![
+            Token { term: Term::from_string("wi-fi"), position: 1 },
+            Token { term: Term::from_string("O'Neil's"), position: 2 },
+        ];
+
+        let options = WordDelimiterOptions {
+            catenate_words: true,
+            preserve_original: true,
+            ..WordDelimiterOptions::default()
+        };
+
+        let token_filter = WordDelimiterFilter::new(Box::new(tokens.drain((..))), options);
+        let tokens = token_filter.collect::<Vec<Token>>();
+
+        assert_eq!(tokens, vec![
+            Token { term: Term::from_string("wi-fi"), position: 1 },
+            Token { term: Term::from_string("wi"), position: 1 },
+            Token { term: Term::from_string("wifi"), position: 1 },
+            Token { term: Term::from_string("fi"), position: 2 },
+            Token { term: Term::from_string("O'Neil's"), position: 3 },
+            Token { term: Term::from_string("O"), position: 3 },
+            Token { term: Term::from_string("ONeil"), position: 3 },
+            Token { term: Term::from_string("Neil"), position: 4 },
+        ]);
+    }
+
+    #[test]
+    fn test_word_delimiter_filter_acronym() {
+        let mut tokens: Vec<Token> = vec![
+            Token { term: Term::from_string("PDFFile"), position: 1 },
+        ];
+
+        let token_filter = WordDelimiterFilter::new(Box::new(tokens.drain((..))), WordDelimiterOptions::default());
+        let tokens = token_filter.collect::<Vec<Token>>();
+
+        assert_eq!(tokens, vec![
+            Token { term: Term::from_string("PDF"), position: 1 },
+            Token { term: Term::from_string("File"), position: 2 },
+        ]);
+    }
+}
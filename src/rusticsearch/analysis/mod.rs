@@ -3,21 +3,78 @@
 //! This module provides a library of tools for breaking down a string of text
 //! into Tokens.
 //!
-//! These tools are sorted into three categories:
+//! These tools are sorted into four categories:
 //!
+//!  - Character filters transform the text before it is tokenised
 //!  - Tokenisers split a string of text into a stream of tokens
 //!  - Filters apply transformations to streams of tokens
-//!  - Analyzers are a combination of a tokeniser and a group of filters
+//!  - Analyzers are a combination of character filters, a tokeniser and a group of filters
 
 pub mod ngram_generator;
+pub mod char_class;
 pub mod lucene_asciifold;
+pub mod stopwords;
+pub mod phonetic;
+pub mod resources;
+pub mod compiled_regex;
+pub mod char_filters;
 pub mod tokenizers;
 pub mod filters;
 
+use std::collections::HashSet;
+
+use serde_json;
+use serde_json::value::ToJson;
 use kite::token::Token;
 
+use analysis::char_filters::CharFilterSpec;
 use analysis::tokenizers::TokenizerSpec;
 use analysis::filters::FilterSpec;
+use analysis::resources::ResourceError;
+
+
+/// A lazy stream of tokens
+///
+/// Tokenizers produce these and each filter wraps the stream of the one before it,
+/// so tokens are pulled through the whole chain one at a time.
+pub type TokenStream<'a> = Box<Iterator<Item=Token> + 'a>;
+
+
+/// Tokenizes a string that the stream owns, such as the output of the char filters
+///
+/// Tokenizers borrow the text they're given, but the char filters make a new string
+/// that would go away before the stream is read. This keeps the string in a `Box<str>`,
+/// which doesn't move when the box does, for as long as the tokenizer needs it. The
+/// tokens themselves own their terms so nothing borrowed from the text can escape.
+struct OwnedTextTokenizer<'a> {
+    // This must be declared before `_text` so it's dropped first
+    tokens: TokenStream<'a>,
+    _text: Box<str>,
+}
+
+
+impl<'a> OwnedTextTokenizer<'a> {
+    fn new(text: String, tokenizer: &'a TokenizerSpec) -> OwnedTextTokenizer<'a> {
+        let text = text.into_boxed_str();
+
+        // The text stays at the same address and outlives the tokenizer, see above
+        let borrowed_text: &'a str = unsafe { &*(&*text as *const str) };
+
+        OwnedTextTokenizer {
+            tokens: tokenizer.initialise(borrowed_text),
+            _text: text,
+        }
+    }
+}
+
+
+impl<'a> Iterator for OwnedTextTokenizer<'a> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        self.tokens.next()
+    }
+}
 
 
 /// Defines an analyzer
@@ -34,6 +91,7 @@ use analysis::filters::FilterSpec;
 ///
 /// // Define an analyzer that splits words and converts them into lowercase
 /// let analyzer = AnalyzerSpec {
+///     char_filters: vec![],
 ///     tokenizer: TokenizerSpec::Standard,
 ///     filters: vec![
 ///         FilterSpec::Lowercase,
@@ -50,19 +108,171 @@ use analysis::filters::FilterSpec;
 /// ```
 #[derive(Debug, Clone, PartialEq)]
 pub struct AnalyzerSpec {
+    pub char_filters: Vec<CharFilterSpec>,
     pub tokenizer: TokenizerSpec,
     pub filters: Vec<FilterSpec>,
 }
 
 
 impl AnalyzerSpec {
-    pub fn initialise<'a>(&self, input: &'a str) -> Box<Iterator<Item=Token> + 'a> {
-        let mut analyzer = self.tokenizer.initialise(input);
+    pub fn initialise<'a>(&'a self, input: &'a str) -> TokenStream<'a> {
+        let mut analyzer = if self.char_filters.is_empty() {
+            self.tokenizer.initialise(input)
+        } else {
+            let mut text = input.to_string();
+            for char_filter in self.char_filters.iter() {
+                text = char_filter.apply(&text);
+            }
+
+            Box::new(OwnedTextTokenizer::new(text, &self.tokenizer))
+        };
+
+        // Keywords only apply to the filters after the one that marked them
+        let mut keywords = HashSet::new();
 
         for filter in self.filters.iter() {
-            analyzer = filter.initialise(analyzer);
+            analyzer = filter.initialise_with_keywords(analyzer, &keywords);
+            keywords.extend(filter.keywords());
         }
 
         analyzer
     }
+
+    /// Returns true if any of the filters in this analyzer read resources from files
+    pub fn uses_files(&self) -> bool {
+        self.filters.iter().any(|filter| filter.uses_files())
+    }
+
+    /// Returns a copy of this analyzer with its file-based resources re-read
+    pub fn reload(&self) -> Result<AnalyzerSpec, ResourceError> {
+        let mut filters = Vec::new();
+        for filter in self.filters.iter() {
+            filters.push(try!(filter.reload()));
+        }
+
+        Ok(AnalyzerSpec {
+            char_filters: self.char_filters.clone(),
+            tokenizer: self.tokenizer.clone(),
+            filters: filters,
+        })
+    }
+}
+
+
+impl ToJson for AnalyzerSpec {
+    fn to_json(&self) -> Result<serde_json::Value, serde_json::Error> {
+        // The specs are written inline as the names they were defined with aren't kept
+        let mut char_filters_json = Vec::new();
+        for char_filter in self.char_filters.iter() {
+            char_filters_json.push(try!(char_filter.to_json()));
+        }
+
+        let mut filters_json = Vec::new();
+        for filter in self.filters.iter() {
+            filters_json.push(try!(filter.to_json()));
+        }
+
+        Ok(json!({
+            "type": "custom",
+            "char_filter": char_filters_json,
+            "tokenizer": try!(self.tokenizer.to_json()),
+            "filter": filters_json,
+        }))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use kite::{Term, Token};
+
+    use analysis::char_filters::CharFilterSpec;
+    use analysis::tokenizers::TokenizerSpec;
+    use analysis::filters::FilterSpec;
+
+    use super::AnalyzerSpec;
+
+    #[test]
+    fn test_char_filters() {
+        let analyzer = AnalyzerSpec {
+            char_filters: vec![
+                CharFilterSpec::HTMLStrip {
+                    escaped_tags: vec![],
+                },
+            ],
+            tokenizer: TokenizerSpec::Standard,
+            filters: vec![
+                FilterSpec::Lowercase,
+            ]
+        };
+
+        let tokens = analyzer.initialise("<p>Hello</p><div>WORLD</div>").collect::<Vec<Token>>();
+
+        assert_eq!(tokens, vec![
+            Token { term: Term::from_string("hello"), position: 1 },
+            Token { term: Term::from_string("world"), position: 2 },
+        ]);
+    }
+
+    #[test]
+    fn test_keyword_marker_protects_from_stemmer() {
+        let analyzer = AnalyzerSpec {
+            char_filters: vec![],
+            tokenizer: TokenizerSpec::Standard,
+            filters: vec![
+                FilterSpec::KeywordMarker {
+                    keywords: vec!["running".to_string()],
+                },
+                FilterSpec::PorterStem,
+            ]
+        };
+
+        let tokens = analyzer.initialise("running shoes").collect::<Vec<Token>>();
+
+        assert_eq!(tokens, vec![
+            Token { term: Term::from_string("running"), position: 1 },
+            Token { term: Term::from_string("shoe"), position: 2 },
+        ]);
+    }
+
+    #[test]
+    fn test_keyword_marker_only_applies_to_later_filters() {
+        let analyzer = AnalyzerSpec {
+            char_filters: vec![],
+            tokenizer: TokenizerSpec::Standard,
+            filters: vec![
+                FilterSpec::PorterStem,
+                FilterSpec::KeywordMarker {
+                    keywords: vec!["running".to_string()],
+                },
+            ]
+        };
+
+        let tokens = analyzer.initialise("running shoes").collect::<Vec<Token>>();
+
+        assert_eq!(tokens, vec![
+            Token { term: Term::from_string("run"), position: 1 },
+            Token { term: Term::from_string("shoe"), position: 2 },
+        ]);
+    }
+
+    #[test]
+    fn test_stemmer_override() {
+        let analyzer = AnalyzerSpec {
+            char_filters: vec![],
+            tokenizer: TokenizerSpec::Standard,
+            filters: vec![
+                FilterSpec::StemmerOverride {
+                    overrides: vec![("mice".to_string(), "mouse".to_string())],
+                },
+                FilterSpec::PorterStem,
+            ]
+        };
+
+        let tokens = analyzer.initialise("mice").collect::<Vec<Token>>();
+
+        assert_eq!(tokens, vec![
+            Token { term: Term::from_string("mouse"), position: 1 },
+        ]);
+    }
 }
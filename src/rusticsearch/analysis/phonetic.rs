@@ -0,0 +1,293 @@
+//! Phonetic encoders
+//!
+//! These convert words into codes that represent how they sound so that words
+//! which are spelt differently but pronounced similarly (eg, "Smith" and "Smyth")
+//! end up with the same code.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PhoneticEncoder {
+    Soundex,
+    Metaphone,
+}
+
+
+impl PhoneticEncoder {
+    pub fn from_name(name: &str) -> Option<PhoneticEncoder> {
+        match name {
+            "soundex" => Some(PhoneticEncoder::Soundex),
+            "metaphone" => Some(PhoneticEncoder::Metaphone),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            PhoneticEncoder::Soundex => "soundex",
+            PhoneticEncoder::Metaphone => "metaphone",
+        }
+    }
+
+    /// Encodes a word
+    ///
+    /// Only the letters A-Z are considered, so this returns an empty string for words
+    /// which don't contain any.
+    pub fn encode(&self, word: &str) -> String {
+        let letters = word.chars()
+            .flat_map(|c| c.to_uppercase())
+            .filter(|&c| c >= 'A' && c <= 'Z')
+            .collect::<Vec<char>>();
+
+        if letters.is_empty() {
+            return String::new();
+        }
+
+        match *self {
+            PhoneticEncoder::Soundex => soundex(&letters),
+            PhoneticEncoder::Metaphone => metaphone(&letters),
+        }
+    }
+}
+
+
+fn soundex_digit(c: char) -> Option<char> {
+    match c {
+        'B' | 'F' | 'P' | 'V' => Some('1'),
+        'C' | 'G' | 'J' | 'K' | 'Q' | 'S' | 'X' | 'Z' => Some('2'),
+        'D' | 'T' => Some('3'),
+        'L' => Some('4'),
+        'M' | 'N' => Some('5'),
+        'R' => Some('6'),
+        _ => None,
+    }
+}
+
+
+fn soundex(letters: &[char]) -> String {
+    let mut code = String::new();
+    code.push(letters[0]);
+
+    let mut last_digit = soundex_digit(letters[0]);
+
+    for &c in letters[1..].iter() {
+        match c {
+            // H and W don't separate letters with the same code
+            'H' | 'W' => continue,
+            'A' | 'E' | 'I' | 'O' | 'U' | 'Y' => {
+                last_digit = None;
+            }
+            _ => {
+                let digit = soundex_digit(c);
+                if digit != last_digit {
+                    if let Some(digit) = digit {
+                        code.push(digit);
+                    }
+                }
+                last_digit = digit;
+            }
+        }
+
+        if code.len() == 4 {
+            break;
+        }
+    }
+
+    while code.len() < 4 {
+        code.push('0');
+    }
+
+    code
+}
+
+
+fn is_vowel(c: Option<char>) -> bool {
+    match c {
+        Some('A') | Some('E') | Some('I') | Some('O') | Some('U') => true,
+        _ => false,
+    }
+}
+
+
+fn metaphone(letters: &[char]) -> String {
+    const MAX_LENGTH: usize = 4;
+
+    let mut letters = letters.to_vec();
+
+    // Initial letter exceptions
+    match (letters.get(0).cloned(), letters.get(1).cloned()) {
+        (Some('A'), Some('E')) | (Some('G'), Some('N')) | (Some('K'), Some('N')) |
+        (Some('P'), Some('N')) | (Some('W'), Some('R')) => {
+            letters.remove(0);
+        }
+        (Some('W'), Some('H')) => {
+            letters.remove(1);
+        }
+        (Some('X'), _) => {
+            letters[0] = 'S';
+        }
+        _ => {}
+    }
+
+    let at = |i: isize| -> Option<char> {
+        if i < 0 { None } else { letters.get(i as usize).cloned() }
+    };
+
+    let mut code = String::new();
+    let mut i = 0isize;
+
+    while (i as usize) < letters.len() && code.len() < MAX_LENGTH {
+        let c = letters[i as usize];
+        let previous = at(i - 1);
+        let next = at(i + 1);
+
+        // Skip double letters, except for C
+        if previous == Some(c) && c != 'C' {
+            i += 1;
+            continue;
+        }
+
+        match c {
+            'A' | 'E' | 'I' | 'O' | 'U' => {
+                if i == 0 {
+                    code.push(c);
+                }
+            }
+            'B' => {
+                // Silent in "-MB" at the end of a word
+                if !(previous == Some('M') && next.is_none()) {
+                    code.push('B');
+                }
+            }
+            'C' => {
+                if next == Some('I') && at(i + 2) == Some('A') {
+                    code.push('X');
+                } else if next == Some('H') {
+                    code.push(if previous == Some('S') { 'K' } else { 'X' });
+                    i += 1;
+                } else if next == Some('I') || next == Some('E') || next == Some('Y') {
+                    // Silent in "SCI", "SCE" and "SCY"
+                    if previous != Some('S') {
+                        code.push('S');
+                    }
+                } else {
+                    code.push('K');
+                }
+            }
+            'D' => {
+                if next == Some('G') && (at(i + 2) == Some('E') || at(i + 2) == Some('Y') || at(i + 2) == Some('I')) {
+                    code.push('J');
+                    i += 2;
+                } else {
+                    code.push('T');
+                }
+            }
+            'G' => {
+                if next == Some('H') && !(at(i + 2).is_none() || is_vowel(at(i + 2))) {
+                    // Silent in "GH" unless at the end or before a vowel
+                } else if next == Some('N') && (at(i + 2).is_none() || (at(i + 2) == Some('E') && at(i + 3) == Some('D') && at(i + 4).is_none())) {
+                    // Silent in "GN" and "GNED" at the end
+                } else if (next == Some('I') || next == Some('E') || next == Some('Y')) && previous != Some('G') {
+                    code.push('J');
+                } else {
+                    code.push('K');
+                }
+            }
+            'H' => {
+                let after_vowel_only = is_vowel(previous) && !is_vowel(next);
+                let after_digraph = match previous {
+                    Some('C') | Some('S') | Some('P') | Some('T') | Some('G') => true,
+                    _ => false,
+                };
+
+                if !after_vowel_only && !after_digraph {
+                    code.push('H');
+                }
+            }
+            'K' => {
+                if previous != Some('C') {
+                    code.push('K');
+                }
+            }
+            'P' => {
+                code.push(if next == Some('H') { 'F' } else { 'P' });
+            }
+            'Q' => {
+                code.push('K');
+            }
+            'S' => {
+                if next == Some('H') {
+                    code.push('X');
+                    i += 1;
+                } else if next == Some('I') && (at(i + 2) == Some('O') || at(i + 2) == Some('A')) {
+                    code.push('X');
+                } else {
+                    code.push('S');
+                }
+            }
+            'T' => {
+                if next == Some('I') && (at(i + 2) == Some('O') || at(i + 2) == Some('A')) {
+                    code.push('X');
+                } else if next == Some('H') {
+                    code.push('0');
+                    i += 1;
+                } else if !(next == Some('C') && at(i + 2) == Some('H')) {
+                    code.push('T');
+                }
+            }
+            'V' => {
+                code.push('F');
+            }
+            'W' | 'Y' => {
+                if is_vowel(next) {
+                    code.push(c);
+                }
+            }
+            'X' => {
+                code.push('K');
+                code.push('S');
+            }
+            'Z' => {
+                code.push('S');
+            }
+            _ => {
+                // F, J, L, M, N and R
+                code.push(c);
+            }
+        }
+
+        i += 1;
+    }
+
+    code.truncate(MAX_LENGTH);
+    code
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::PhoneticEncoder;
+
+    #[test]
+    fn test_soundex() {
+        assert_eq!(PhoneticEncoder::Soundex.encode("Robert"), "R163");
+        assert_eq!(PhoneticEncoder::Soundex.encode("Rupert"), "R163");
+        assert_eq!(PhoneticEncoder::Soundex.encode("Ashcraft"), "A261");
+        assert_eq!(PhoneticEncoder::Soundex.encode("Tymczak"), "T522");
+        assert_eq!(PhoneticEncoder::Soundex.encode("Lee"), "L000");
+        assert_eq!(PhoneticEncoder::Soundex.encode("Smith"), PhoneticEncoder::Soundex.encode("Smyth"));
+    }
+
+    #[test]
+    fn test_metaphone() {
+        assert_eq!(PhoneticEncoder::Metaphone.encode("Smith"), "SM0");
+        assert_eq!(PhoneticEncoder::Metaphone.encode("Smyth"), "SM0");
+        assert_eq!(PhoneticEncoder::Metaphone.encode("Knight"), "NT");
+        assert_eq!(PhoneticEncoder::Metaphone.encode("Thumb"), "0M");
+        assert_eq!(PhoneticEncoder::Metaphone.encode("Philip"), "FLP");
+    }
+
+    #[test]
+    fn test_encode_without_letters() {
+        assert_eq!(PhoneticEncoder::Soundex.encode("123"), "");
+        assert_eq!(PhoneticEncoder::Metaphone.encode("123"), "");
+    }
+}
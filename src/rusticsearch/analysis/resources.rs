@@ -0,0 +1,107 @@
+//! Reads analysis resources (eg, stopword and synonym lists) from files
+//!
+//! Files contain one entry per line. Blank lines and lines starting with `#` are
+//! ignored.
+//!
+//! Like Elasticsearch, resource paths are relative to the config directory. Index
+//! settings can be set by anyone who can create an index so they can't be used to read
+//! files from anywhere else.
+
+use std::fmt;
+use std::io::{self, Read};
+use std::fs::File;
+use std::path::{Path, PathBuf, Component};
+
+
+/// The directory that resource paths are relative to
+pub const CONFIG_DIR: &'static str = "config";
+
+
+#[derive(Debug)]
+pub enum ResourceError {
+    /// The path is absolute or goes outside the config directory
+    InvalidPath(String),
+    IoError(String, io::Error),
+    InvalidSynonymRule(String),
+}
+
+
+impl fmt::Display for ResourceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ResourceError::InvalidPath(ref path) => write!(f, "invalid path [{}], it must be relative to the config directory", path),
+            ResourceError::IoError(ref path, ref error) => write!(f, "unable to read [{}]: {}", path, error),
+            ResourceError::InvalidSynonymRule(ref rule) => write!(f, "invalid synonym rule [{}]", rule),
+        }
+    }
+}
+
+
+/// Finds a resource file in the config directory
+pub fn resolve_path(path: &str) -> Result<PathBuf, ResourceError> {
+    let relative_path = Path::new(path);
+    let is_inside_config_dir = relative_path.components().all(|component| {
+        match component {
+            Component::Normal(_) | Component::CurDir => true,
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => false,
+        }
+    });
+
+    if path.is_empty() || !is_inside_config_dir {
+        return Err(ResourceError::InvalidPath(path.to_string()));
+    }
+
+    Ok(Path::new(CONFIG_DIR).join(relative_path))
+}
+
+
+fn read_file(path: &Path) -> io::Result<String> {
+    let mut file = try!(File::open(path));
+    let mut contents = String::new();
+    try!(file.read_to_string(&mut contents));
+
+    Ok(contents)
+}
+
+
+/// Reads a resource file, the path is relative to the config directory
+pub fn read_lines(path: &str) -> Result<Vec<String>, ResourceError> {
+    let contents = try!(read_file(&try!(resolve_path(path))).map_err(|e| ResourceError::IoError(path.to_string(), e)));
+
+    Ok(parse_lines(&contents))
+}
+
+
+fn parse_lines(contents: &str) -> Vec<String> {
+    contents.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.to_string())
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{parse_lines, resolve_path, CONFIG_DIR};
+
+    #[test]
+    fn test_resolve_path() {
+        assert_eq!(resolve_path("synonyms.txt").unwrap(), Path::new(CONFIG_DIR).join("synonyms.txt"));
+        assert_eq!(resolve_path("analysis/synonyms.txt").unwrap(), Path::new(CONFIG_DIR).join("analysis/synonyms.txt"));
+
+        assert!(resolve_path("").is_err());
+        assert!(resolve_path("/etc/passwd").is_err());
+        assert!(resolve_path("../secret.txt").is_err());
+        assert!(resolve_path("analysis/../../secret.txt").is_err());
+    }
+
+    #[test]
+    fn test_parse_lines() {
+        let contents = "# Stopwords\nthe\n\n  and  \r\n#a\nor";
+
+        assert_eq!(parse_lines(contents), vec!["the", "and", "or"]);
+    }
+}
@@ -0,0 +1,127 @@
+//! Builtin lists of stopwords
+//!
+//! These can be referenced by name (eg, `_english_`) when configuring a stop filter
+
+pub const ENGLISH: &'static [&'static str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "for", "if", "in", "into", "is",
+    "it", "no", "not", "of", "on", "or", "such", "that", "the", "their", "then", "there",
+    "these", "they", "this", "to", "was", "will", "with",
+];
+
+pub const FRENCH: &'static [&'static str] = &[
+    "au", "aux", "avec", "ce", "ces", "dans", "de", "des", "du", "elle", "en", "et", "eux",
+    "il", "je", "la", "le", "les", "leur", "lui", "ma", "mais", "me", "même", "mes", "moi",
+    "mon", "ne", "nos", "notre", "nous", "on", "ou", "par", "pas", "pour", "qu", "que",
+    "qui", "sa", "se", "ses", "son", "sur", "ta", "te", "tes", "toi", "ton", "tu", "un",
+    "une", "vos", "votre", "vous", "c", "d", "j", "l", "à", "m", "n", "s", "t", "y", "été",
+    "étée", "étées", "étés", "étant", "suis", "es", "est", "sommes", "êtes", "sont", "serai",
+    "seras", "sera", "serons", "serez", "seront", "ceci", "cela", "celà", "cet", "cette",
+    "ici", "ils", "les", "leurs", "quel", "quels", "quelle", "quelles", "sans", "soi",
+];
+
+pub const GERMAN: &'static [&'static str] = &[
+    "aber", "alle", "allem", "allen", "aller", "alles", "als", "also", "am", "an", "ander",
+    "andere", "anderem", "anderen", "anderer", "anderes", "auch", "auf", "aus", "bei", "bin",
+    "bis", "bist", "da", "damit", "dann", "der", "den", "des", "dem", "die", "das", "dass",
+    "daß", "derselbe", "dich", "dir", "du", "dies", "diese", "dieser", "dieses", "doch",
+    "dort", "durch", "ein", "eine", "einem", "einen", "einer", "eines", "er", "es", "euer",
+    "eure", "für", "hatte", "hatten", "hattest", "hattet", "hier", "hinter", "ich", "ihr",
+    "ihre", "im", "in", "ist", "ja", "jede", "jedem", "jeden", "jeder", "jedes", "jener",
+    "jenes", "jetzt", "kann", "kannst", "können", "könnt", "machen", "mein", "meine", "mit",
+    "muß", "mußt", "musst", "müssen", "müßt", "nach", "nachdem", "nein", "nicht", "nun",
+    "oder", "seid", "sein", "seine", "sich", "sie", "sind", "soll", "sollen", "sollst",
+    "sollt", "sonst", "soweit", "sowie", "und", "unser", "unsere", "unter", "vom", "von",
+    "vor", "wann", "warum", "was", "weiter", "weitere", "wenn", "wer", "werde", "werden",
+    "werdet", "weshalb", "wie", "wieder", "wieso", "wir", "wird", "wirst", "wo", "woher",
+    "wohin", "zu", "zum", "zur", "über",
+];
+
+pub const SPANISH: &'static [&'static str] = &[
+    "de", "la", "que", "el", "en", "y", "a", "los", "del", "se", "las", "por", "un", "para",
+    "con", "no", "una", "su", "al", "lo", "como", "más", "pero", "sus", "le", "ya", "o",
+    "este", "sí", "porque", "esta", "entre", "cuando", "muy", "sin", "sobre", "también",
+    "me", "hasta", "hay", "donde", "quien", "desde", "todo", "nos", "durante", "todos",
+    "uno", "les", "ni", "contra", "otros", "ese", "eso", "ante", "ellos", "e", "esto", "mí",
+    "antes", "algunos", "qué", "unos", "yo", "otro", "otras", "otra", "él", "tanto", "esa",
+    "estos", "mucho", "quienes", "nada", "muchos", "cual", "poco", "ella", "estar",
+    "estas", "algunas", "algo", "nosotros", "mi", "mis", "tú", "te", "ti", "tu", "tus",
+    "ellas", "vosotros", "vosotras", "os", "mío", "mía", "míos", "mías", "tuyo", "tuya",
+    "es", "son", "fue", "era", "ser", "ha", "han", "he",
+];
+
+pub const ITALIAN: &'static [&'static str] = &[
+    "ad", "al", "allo", "ai", "agli", "all", "agl", "alla", "alle", "con", "col", "coi",
+    "da", "dal", "dallo", "dai", "dagli", "dall", "dagl", "dalla", "dalle", "di", "del",
+    "dello", "dei", "degli", "dell", "degl", "della", "delle", "in", "nel", "nello", "nei",
+    "negli", "nell", "negl", "nella", "nelle", "su", "sul", "sullo", "sui", "sugli", "sull",
+    "sugl", "sulla", "sulle", "per", "tra", "contro", "io", "tu", "lui", "lei", "noi", "voi",
+    "loro", "mio", "mia", "miei", "mie", "tuo", "tua", "tuoi", "tue", "suo", "sua", "suoi",
+    "sue", "nostro", "nostra", "nostri", "nostre", "vostro", "vostra", "vostri", "vostre",
+    "mi", "ti", "ci", "vi", "lo", "la", "li", "le", "gli", "ne", "il", "un", "uno", "una",
+    "ma", "ed", "se", "perché", "anche", "come", "dov", "dove", "che", "chi", "cui", "non",
+    "più", "quale", "quanto", "quanti", "quanta", "quante", "quello", "quelli", "quella",
+    "quelle", "questo", "questi", "questa", "queste", "si", "tutto", "tutti", "a", "c", "e",
+    "i", "l", "o", "è",
+];
+
+pub const PORTUGUESE: &'static [&'static str] = &[
+    "a", "ainda", "alem", "ambas", "ambos", "antes", "ao", "aonde", "aos", "apos", "aquele",
+    "aqueles", "as", "assim", "com", "como", "contra", "contudo", "cuja", "cujas", "cujo",
+    "cujos", "da", "das", "de", "dela", "dele", "deles", "demais", "depois", "desde",
+    "desta", "deste", "dispoe", "dispoem", "diversa", "diversas", "diversos", "do", "dos",
+    "durante", "e", "ela", "elas", "ele", "eles", "em", "entao", "entre", "essa", "essas",
+    "esse", "esses", "esta", "estas", "este", "estes", "ha", "isso", "isto", "logo", "mais",
+    "mas", "mediante", "menos", "mesma", "mesmas", "mesmo", "mesmos", "na", "nas", "nao",
+    "nas", "nem", "nesse", "neste", "nos", "o", "os", "ou", "outra", "outras", "outro",
+    "outros", "pelas", "pelo", "pelos", "perante", "pois", "por", "porque", "portanto",
+    "proprio", "propios", "quais", "qual", "qualquer", "quando", "quanto", "que", "quem",
+    "quer", "se", "seja", "sem", "sendo", "seu", "seus", "sob", "sobre", "sua", "suas",
+    "tal", "tambem", "teu", "teus", "toda", "todas", "todo", "todos", "tua", "tuas", "tudo",
+    "um", "uma", "umas", "uns",
+];
+
+pub const DUTCH: &'static [&'static str] = &[
+    "de", "en", "van", "ik", "te", "dat", "die", "in", "een", "hij", "het", "niet", "zijn",
+    "is", "was", "op", "aan", "met", "als", "voor", "had", "er", "maar", "om", "hem", "dan",
+    "zou", "of", "wat", "mijn", "men", "dit", "zo", "door", "over", "ze", "zich", "bij",
+    "ook", "tot", "je", "mij", "uit", "der", "daar", "haar", "naar", "heb", "hoe", "heeft",
+    "hebben", "deze", "u", "want", "nog", "zal", "me", "zij", "nu", "ge", "geen", "omdat",
+    "iets", "worden", "toch", "al", "waren", "veel", "meer", "doen", "toen", "moet", "ben",
+    "zonder", "kan", "hun", "dus", "alles", "onder", "ja", "eens", "hier", "wie", "werd",
+    "altijd", "doch", "wordt", "wezen", "kunnen", "ons", "zelf", "tegen", "na", "reeds",
+    "wil", "kon", "niets", "uw", "iemand", "geweest", "andere",
+];
+
+pub const RUSSIAN: &'static [&'static str] = &[
+    "и", "в", "во", "не", "что", "он", "на", "я", "с", "со", "как", "а", "то", "все",
+    "она", "так", "его", "но", "да", "ты", "к", "у", "же", "вы", "за", "бы", "по",
+    "только", "ее", "мне", "было", "вот", "от", "меня", "еще", "нет", "о", "из", "ему",
+    "теперь", "когда", "даже", "ну", "вдруг", "ли", "если", "уже", "или", "ни", "быть",
+    "был", "него", "до", "вас", "нибудь", "опять", "уж", "вам", "ведь", "там", "потом",
+    "себя", "ничего", "ей", "может", "они", "тут", "где", "есть", "надо", "ней", "для",
+    "мы", "тебя", "их", "чем", "была", "сам", "чтоб", "без", "будто", "чего", "раз",
+    "тоже", "себе", "под", "будет", "ж", "тогда", "кто", "этот", "того", "потому",
+    "этого", "какой", "совсем", "ним", "здесь", "этом", "один", "почти", "мой", "тем",
+    "чтобы", "нее", "сейчас", "были", "куда", "зачем", "всех", "никогда", "можно", "при",
+    "наконец", "два", "об", "другой", "хоть", "после", "над", "больше", "тот", "через",
+    "эти", "нас", "про", "всего", "них", "какая", "много", "разве", "три", "эту", "моя",
+    "впрочем", "хорошо", "свою", "этой", "перед", "иногда", "лучше", "чуть", "том",
+    "нельзя", "такой", "им", "более", "всегда", "конечно", "всю", "между",
+];
+
+
+/// Finds a builtin stopword list by its name (eg, `_english_`)
+pub fn get_stopwords(name: &str) -> Option<&'static [&'static str]> {
+    match name {
+        "_english_" => Some(ENGLISH),
+        "_french_" => Some(FRENCH),
+        "_german_" => Some(GERMAN),
+        "_spanish_" => Some(SPANISH),
+        "_italian_" => Some(ITALIAN),
+        "_portuguese_" => Some(PORTUGUESE),
+        "_dutch_" => Some(DUTCH),
+        "_russian_" => Some(RUSSIAN),
+        "_none_" => Some(&[]),
+        _ => None,
+    }
+}
@@ -0,0 +1,105 @@
+//! Splits strings on any character in a configured set of classes and characters
+
+use std::str::CharIndices;
+
+use kite::{Term, Token};
+
+use analysis::char_class::CharClass;
+
+
+pub struct CharGroupTokenizer<'a> {
+    input: &'a str,
+    char_indices: CharIndices<'a>,
+    split_on_classes: Vec<CharClass>,
+    split_on_chars: Vec<char>,
+    position_counter: u32,
+}
+
+
+impl<'a> CharGroupTokenizer<'a> {
+    pub fn new(input: &'a str, split_on_classes: Vec<CharClass>, split_on_chars: Vec<char>) -> CharGroupTokenizer<'a> {
+        CharGroupTokenizer {
+            input: input,
+            char_indices: input.char_indices(),
+            split_on_classes: split_on_classes,
+            split_on_chars: split_on_chars,
+            position_counter: 0,
+        }
+    }
+
+    fn is_split_char(&self, c: char) -> bool {
+        self.split_on_chars.contains(&c) || self.split_on_classes.iter().any(|class| class.matches(c))
+    }
+}
+
+
+impl<'a> Iterator for CharGroupTokenizer<'a> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        // Skip over split characters to find the start of the token
+        let mut start = None;
+        while let Some((i, c)) = self.char_indices.next() {
+            if !self.is_split_char(c) {
+                start = Some(i);
+                break;
+            }
+        }
+
+        let start = match start {
+            Some(start) => start,
+            None => return None,
+        };
+
+        // Find end of the token
+        let mut end = self.input.len();
+        while let Some((i, c)) = self.char_indices.next() {
+            if self.is_split_char(c) {
+                end = i;
+                break;
+            }
+        }
+
+        self.position_counter += 1;
+
+        Some(Token {
+            term: Term::from_string(&self.input[start..end]),
+            position: self.position_counter,
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use kite::{Term, Token};
+
+    use analysis::char_class::CharClass;
+
+    use super::CharGroupTokenizer;
+
+    #[test]
+    fn test_char_group_tokenizer() {
+        let tokenizer = CharGroupTokenizer::new("2017-05-01 some-thing,else", vec![CharClass::Whitespace], vec!['-', ',']);
+        let tokens = tokenizer.collect::<Vec<Token>>();
+
+        assert_eq!(tokens, vec![
+            Token { term: Term::from_string("2017"), position: 1 },
+            Token { term: Term::from_string("05"), position: 2 },
+            Token { term: Term::from_string("01"), position: 3 },
+            Token { term: Term::from_string("some"), position: 4 },
+            Token { term: Term::from_string("thing"), position: 5 },
+            Token { term: Term::from_string("else"), position: 6 },
+        ]);
+    }
+
+    #[test]
+    fn test_char_group_tokenizer_nothing_to_split_on() {
+        let tokenizer = CharGroupTokenizer::new("Hello, world!", vec![], vec![]);
+        let tokens = tokenizer.collect::<Vec<Token>>();
+
+        assert_eq!(tokens, vec![
+            Token { term: Term::from_string("Hello, world!"), position: 1 },
+        ]);
+    }
+}
@@ -0,0 +1,191 @@
+//! Splits strings by word boundaries, like the standard tokenizer, but breaks runs of
+//! Chinese, Japanese and Korean characters into overlapping bigrams
+//!
+//! CJK text doesn't use spaces between words so the word boundary rules either emit
+//! each character separately or whole sentences at a time. Indexing bigrams instead
+//! allows phrases to be matched without needing a dictionary.
+
+use unicode_segmentation::{UnicodeSegmentation, UnicodeWords};
+
+use kite::{Term, Token};
+
+
+fn is_cjk(c: char) -> bool {
+    match c {
+        // Hiragana and Katakana
+        '\u{3040}'...'\u{30FF}' | '\u{31F0}'...'\u{31FF}' | '\u{FF66}'...'\u{FF9F}' => true,
+        // CJK Unified Ideographs (including extension A) and compatibility ideographs
+        '\u{3400}'...'\u{4DBF}' | '\u{4E00}'...'\u{9FFF}' | '\u{F900}'...'\u{FAFF}' => true,
+        // Hangul
+        '\u{1100}'...'\u{11FF}' | '\u{3130}'...'\u{318F}' | '\u{AC00}'...'\u{D7AF}' => true,
+        // CJK Unified Ideographs extensions B onwards
+        '\u{20000}'...'\u{2FA1F}' => true,
+        _ => false,
+    }
+}
+
+
+/// Emits the overlapping pairs of characters in a run of CJK characters
+///
+/// A run of a single character is emitted as it is.
+struct Bigrams<'a> {
+    run: &'a str,
+    start: usize,
+    emitted_any: bool,
+}
+
+
+impl<'a> Iterator for Bigrams<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        let run = self.run;
+        let mut chars = run[self.start..].char_indices();
+        if chars.next().is_none() {
+            return None;
+        }
+
+        match chars.next() {
+            Some((second_start, second)) => {
+                let bigram = &run[self.start..self.start + second_start + second.len_utf8()];
+                self.start += second_start;
+                self.emitted_any = true;
+                Some(bigram)
+            }
+            None => {
+                // The last character has already been emitted as part of a bigram
+                let start = self.start;
+                self.start = run.len();
+
+                if self.emitted_any {
+                    None
+                } else {
+                    self.emitted_any = true;
+                    Some(&run[start..])
+                }
+            }
+        }
+    }
+}
+
+
+enum Words<'a> {
+    Plain(UnicodeWords<'a>),
+    Bigrams(Bigrams<'a>),
+}
+
+
+pub struct CJKBigramTokenizer<'a> {
+    input: &'a str,
+
+    /// Where the run after the current one starts
+    run_start: usize,
+    words: Words<'a>,
+    position_counter: u32,
+}
+
+
+impl<'a> CJKBigramTokenizer<'a> {
+    pub fn new(input: &'a str) -> CJKBigramTokenizer<'a> {
+        CJKBigramTokenizer {
+            input: input,
+            run_start: 0,
+            words: Words::Plain("".unicode_words()),
+            position_counter: 0,
+        }
+    }
+
+    /// Moves on to the next run of CJK or non-CJK characters
+    ///
+    /// Returns false at the end of the input.
+    fn next_run(&mut self) -> bool {
+        let input = self.input;
+        let rest = &input[self.run_start..];
+        let run_is_cjk = match rest.chars().next() {
+            Some(c) => is_cjk(c),
+            None => return false,
+        };
+
+        let run_length = rest.char_indices().find(|&(_, c)| is_cjk(c) != run_is_cjk).map(|(i, _)| i).unwrap_or(rest.len());
+        let run = &rest[..run_length];
+        self.run_start += run_length;
+
+        self.words = if run_is_cjk {
+            Words::Bigrams(Bigrams {
+                run: run,
+                start: 0,
+                emitted_any: false,
+            })
+        } else {
+            Words::Plain(run.unicode_words())
+        };
+
+        true
+    }
+}
+
+
+impl<'a> Iterator for CJKBigramTokenizer<'a> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        loop {
+            let word = match self.words {
+                Words::Plain(ref mut words) => words.next(),
+                Words::Bigrams(ref mut bigrams) => bigrams.next(),
+            };
+
+            if let Some(word) = word {
+                self.position_counter += 1;
+
+                return Some(Token {
+                    term: Term::from_string(word),
+                    position: self.position_counter,
+                });
+            }
+
+            if !self.next_run() {
+                return None;
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use kite::{Term, Token};
+
+    use super::CJKBigramTokenizer;
+
+    #[test]
+    fn test_cjk_bigram_tokenizer() {
+        let tokenizer = CJKBigramTokenizer::new("こんにちは、ハチ公！");
+        let tokens = tokenizer.collect::<Vec<Token>>();
+
+        assert_eq!(tokens, vec![
+            Token { term: Term::from_string("こん"), position: 1 },
+            Token { term: Term::from_string("んに"), position: 2 },
+            Token { term: Term::from_string("にち"), position: 3 },
+            Token { term: Term::from_string("ちは"), position: 4 },
+            Token { term: Term::from_string("ハチ"), position: 5 },
+            Token { term: Term::from_string("チ公"), position: 6 },
+        ]);
+    }
+
+    #[test]
+    fn test_cjk_bigram_tokenizer_mixed() {
+        let tokenizer = CJKBigramTokenizer::new("Rust 是 一种编程语言");
+        let tokens = tokenizer.collect::<Vec<Token>>();
+
+        assert_eq!(tokens, vec![
+            Token { term: Term::from_string("Rust"), position: 1 },
+            Token { term: Term::from_string("是"), position: 2 },
+            Token { term: Term::from_string("一种"), position: 3 },
+            Token { term: Term::from_string("种编"), position: 4 },
+            Token { term: Term::from_string("编程"), position: 5 },
+            Token { term: Term::from_string("程语"), position: 6 },
+            Token { term: Term::from_string("语言"), position: 7 },
+        ]);
+    }
+}
@@ -0,0 +1,60 @@
+//! Emits the entire input as a single token
+
+use kite::{Term, Token};
+
+
+pub struct KeywordTokenizer<'a> {
+    input: Option<&'a str>,
+}
+
+
+impl<'a> KeywordTokenizer<'a> {
+    pub fn new(input: &'a str) -> KeywordTokenizer<'a> {
+        KeywordTokenizer {
+            input: if input.is_empty() { None } else { Some(input) },
+        }
+    }
+}
+
+
+impl<'a> Iterator for KeywordTokenizer<'a> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        match self.input.take() {
+            Some(input) => {
+                Some(Token {
+                    term: Term::from_string(input),
+                    position: 1,
+                })
+            }
+            None => None,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use kite::{Term, Token};
+
+    use super::KeywordTokenizer;
+
+    #[test]
+    fn test_keyword_tokenizer() {
+        let tokenizer = KeywordTokenizer::new("Hello, WORLD!");
+        let tokens = tokenizer.collect::<Vec<Token>>();
+
+        assert_eq!(tokens, vec![
+            Token { term: Term::from_string("Hello, WORLD!"), position: 1 },
+        ]);
+    }
+
+    #[test]
+    fn test_keyword_tokenizer_empty() {
+        let tokenizer = KeywordTokenizer::new("");
+        let tokens = tokenizer.collect::<Vec<Token>>();
+
+        assert_eq!(tokens, vec![]);
+    }
+}
@@ -1,14 +1,26 @@
 pub mod standard;
 pub mod ngram;
+pub mod keyword;
+pub mod pattern;
+pub mod uax_url_email;
+pub mod char_group;
+pub mod cjk_bigram;
 
 use serde_json;
 use serde_json::value::ToJson;
-use kite::token::Token;
 
 use analysis::ngram_generator::Edge;
+use analysis::char_class::CharClass;
+use analysis::compiled_regex::CompiledRegex;
 use analysis::filters::lowercase::LowercaseFilter;
 use analysis::tokenizers::standard::StandardTokenizer;
 use analysis::tokenizers::ngram::NGramTokenizer;
+use analysis::tokenizers::keyword::KeywordTokenizer;
+use analysis::tokenizers::pattern::PatternTokenizer;
+use analysis::tokenizers::uax_url_email::UaxUrlEmailTokenizer;
+use analysis::tokenizers::char_group::CharGroupTokenizer;
+use analysis::tokenizers::cjk_bigram::CJKBigramTokenizer;
+use analysis::TokenStream;
 
 
 /// Defines a tokenizer
@@ -39,12 +51,24 @@ pub enum TokenizerSpec {
         min_size: usize,
         max_size: usize,
         edge: Edge,
-    }
+        token_chars: Vec<CharClass>,
+    },
+    Keyword,
+    Pattern {
+        pattern: CompiledRegex,
+        group: Option<usize>,
+    },
+    UaxUrlEmail,
+    CharGroup {
+        split_on_classes: Vec<CharClass>,
+        split_on_chars: Vec<char>,
+    },
+    CJKBigram,
 }
 
 
 impl TokenizerSpec {
-    pub fn initialise<'a>(&self, input: &'a str) -> Box<Iterator<Item=Token> + 'a> {
+    pub fn initialise<'a>(&'a self, input: &'a str) -> TokenStream<'a> {
         match *self {
             TokenizerSpec::Standard => {
                 Box::new(StandardTokenizer::new(input))
@@ -52,8 +76,23 @@ impl TokenizerSpec {
             TokenizerSpec::Lowercase => {
                 Box::new(LowercaseFilter::new(Box::new(StandardTokenizer::new(input))))
             }
-            TokenizerSpec::NGram{min_size, max_size, edge} => {
-                Box::new(NGramTokenizer::new(input, min_size, max_size, edge))
+            TokenizerSpec::NGram{min_size, max_size, edge, ref token_chars} => {
+                Box::new(NGramTokenizer::new_with_token_chars(input, min_size, max_size, edge, token_chars))
+            }
+            TokenizerSpec::Keyword => {
+                Box::new(KeywordTokenizer::new(input))
+            }
+            TokenizerSpec::Pattern{ref pattern, group} => {
+                Box::new(PatternTokenizer::new(input, pattern, group))
+            }
+            TokenizerSpec::UaxUrlEmail => {
+                Box::new(UaxUrlEmailTokenizer::new(input))
+            }
+            TokenizerSpec::CharGroup{ref split_on_classes, ref split_on_chars} => {
+                Box::new(CharGroupTokenizer::new(input, split_on_classes.clone(), split_on_chars.clone()))
+            }
+            TokenizerSpec::CJKBigram => {
+                Box::new(CJKBigramTokenizer::new(input))
             }
         }
     }
@@ -73,7 +112,9 @@ impl ToJson for TokenizerSpec {
                     "type": "lowercase",
                 }))
             }
-            TokenizerSpec::NGram{min_size, max_size, edge} => {
+            TokenizerSpec::NGram{min_size, max_size, edge, ref token_chars} => {
+                let token_chars = token_chars.iter().map(|class| class.name()).collect::<Vec<&str>>();
+
                 match edge {
                     Edge::Left => {
                         Ok(json!({
@@ -81,6 +122,7 @@ impl ToJson for TokenizerSpec {
                             "side": "front",
                             "min_gram": min_size,
                             "max_gram": max_size,
+                            "token_chars": token_chars,
                         }))
                     }
                     Edge::Right => {
@@ -89,6 +131,7 @@ impl ToJson for TokenizerSpec {
                             "side": "back",
                             "min_gram": min_size,
                             "max_gram": max_size,
+                            "token_chars": token_chars,
                         }))
                     }
                     Edge::Neither => {
@@ -96,10 +139,48 @@ impl ToJson for TokenizerSpec {
                             "type": "ngram",
                             "min_gram": min_size,
                             "max_gram": max_size,
+                            "token_chars": token_chars,
                         }))
                     }
                 }
             }
+            TokenizerSpec::Keyword => {
+                Ok(json!({
+                    "type": "keyword",
+                }))
+            }
+            TokenizerSpec::Pattern{ref pattern, group} => {
+                // Elasticsearch uses -1 to mean "split on the pattern"
+                let group = match group {
+                    Some(group) => group as i64,
+                    None => -1,
+                };
+
+                Ok(json!({
+                    "type": "pattern",
+                    "pattern": pattern.as_str(),
+                    "group": group,
+                }))
+            }
+            TokenizerSpec::UaxUrlEmail => {
+                Ok(json!({
+                    "type": "uax_url_email",
+                }))
+            }
+            TokenizerSpec::CharGroup{ref split_on_classes, ref split_on_chars} => {
+                let mut tokenize_on_chars = split_on_classes.iter().map(|class| class.name().to_string()).collect::<Vec<String>>();
+                tokenize_on_chars.extend(split_on_chars.iter().map(|c| c.to_string()));
+
+                Ok(json!({
+                    "type": "char_group",
+                    "tokenize_on_chars": tokenize_on_chars,
+                }))
+            }
+            TokenizerSpec::CJKBigram => {
+                Ok(json!({
+                    "type": "cjk_bigram",
+                }))
+            }
         }
     }
 }
@@ -1,12 +1,13 @@
-use unicode_segmentation::{UnicodeSegmentation, UnicodeWords};
+use unicode_segmentation::UnicodeSegmentation;
 
 use kite::{Term, Token};
 
 use analysis::ngram_generator::{Edge, NGramGenerator};
+use analysis::char_class::{CharClass, CharClassWords};
 
 
 pub struct NGramTokenizer<'a> {
-    unicode_words: UnicodeWords<'a>,
+    words: Box<Iterator<Item=&'a str> + 'a>,
     min_size: usize,
     max_size: usize,
     edge: Edge,
@@ -17,8 +18,21 @@ pub struct NGramTokenizer<'a> {
 
 impl<'a> NGramTokenizer<'a> {
     pub fn new(input: &'a str, min_size: usize, max_size: usize, edge: Edge) -> NGramTokenizer<'a> {
+        NGramTokenizer::new_with_token_chars(input, min_size, max_size, edge, &[])
+    }
+
+    /// Creates an NGramTokenizer that only keeps characters in the given classes
+    ///
+    /// If no classes are given, the input is split by Unicode word boundaries.
+    pub fn new_with_token_chars(input: &'a str, min_size: usize, max_size: usize, edge: Edge, token_chars: &[CharClass]) -> NGramTokenizer<'a> {
+        let words: Box<Iterator<Item=&'a str> + 'a> = if token_chars.is_empty() {
+            Box::new(input.unicode_words())
+        } else {
+            Box::new(CharClassWords::new(input, token_chars.to_vec()))
+        };
+
         NGramTokenizer {
-            unicode_words: input.unicode_words(),
+            words: words,
             min_size: min_size,
             max_size: max_size,
             edge: edge,
@@ -45,7 +59,7 @@ impl<'a> Iterator for NGramTokenizer<'a> {
             }
 
             // No more ngrams for this word, get next word
-            let word = self.unicode_words.next();
+            let word = self.words.next();
 
             match word {
                 Some(word) => {
@@ -66,6 +80,7 @@ mod tests {
     use kite::{Term, Token};
 
     use analysis::ngram_generator::Edge;
+    use analysis::char_class::CharClass;
 
     use super::NGramTokenizer;
 
@@ -124,4 +139,19 @@ mod tests {
             Token { term: Term::from_string("rld"), position: 2 },
         ]);
     }
+
+    #[test]
+    fn test_edgengram_tokenizer_token_chars() {
+        let tokenizer = NGramTokenizer::new_with_token_chars("2 Quick-Foxes", 1, 3, Edge::Left, &[CharClass::Letter]);
+        let tokens = tokenizer.collect::<Vec<Token>>();
+
+        assert_eq!(tokens, vec![
+            Token { term: Term::from_string("Q"), position: 1 },
+            Token { term: Term::from_string("Qu"), position: 1 },
+            Token { term: Term::from_string("Qui"), position: 1 },
+            Token { term: Term::from_string("F"), position: 2 },
+            Token { term: Term::from_string("Fo"), position: 2 },
+            Token { term: Term::from_string("Fox"), position: 2 },
+        ]);
+    }
 }
@@ -0,0 +1,121 @@
+//! Splits the input on a regular expression, or emits the matches of one of its groups
+
+use regex::{Regex, Split, CaptureMatches};
+use kite::{Term, Token};
+
+
+/// The words of the input, before empty ones are skipped
+enum Words<'a> {
+    Split(Split<'a, 'a>),
+    Group(CaptureMatches<'a, 'a>, usize),
+}
+
+
+pub struct PatternTokenizer<'a> {
+    words: Words<'a>,
+    position_counter: u32,
+}
+
+
+impl<'a> PatternTokenizer<'a> {
+    /// Creates a PatternTokenizer
+    ///
+    /// If a group is given, each match of that capture group becomes a token
+    /// (group 0 being the whole match). Otherwise, the input is split on matches
+    /// of the pattern. Empty tokens are skipped.
+    pub fn new(input: &'a str, pattern: &'a Regex, group: Option<usize>) -> PatternTokenizer<'a> {
+        let words = match group {
+            Some(group) => Words::Group(pattern.captures_iter(input), group),
+            None => Words::Split(pattern.split(input)),
+        };
+
+        PatternTokenizer {
+            words: words,
+            position_counter: 0,
+        }
+    }
+
+    fn next_word(&mut self) -> Option<&'a str> {
+        match self.words {
+            Words::Split(ref mut split) => split.next(),
+            Words::Group(ref mut matches, group) => {
+                // Matches that the group didn't take part in are skipped
+                for captures in matches {
+                    if let Some(capture) = captures.get(group) {
+                        return Some(capture.as_str());
+                    }
+                }
+
+                None
+            }
+        }
+    }
+}
+
+
+impl<'a> Iterator for PatternTokenizer<'a> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        while let Some(word) = self.next_word() {
+            if word.is_empty() {
+                continue;
+            }
+
+            self.position_counter += 1;
+
+            return Some(Token {
+                term: Term::from_string(word),
+                position: self.position_counter,
+            });
+        }
+
+        None
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use regex::Regex;
+    use kite::{Term, Token};
+
+    use super::PatternTokenizer;
+
+    #[test]
+    fn test_pattern_tokenizer_split() {
+        let pattern = Regex::new(r"\W+").unwrap();
+        let tokenizer = PatternTokenizer::new("foo-bar, baz!", &pattern, None);
+        let tokens = tokenizer.collect::<Vec<Token>>();
+
+        assert_eq!(tokens, vec![
+            Token { term: Term::from_string("foo"), position: 1 },
+            Token { term: Term::from_string("bar"), position: 2 },
+            Token { term: Term::from_string("baz"), position: 3 },
+        ]);
+    }
+
+    #[test]
+    fn test_pattern_tokenizer_group() {
+        let pattern = Regex::new(r"([A-Z]{3})-(\d+)").unwrap();
+        let tokenizer = PatternTokenizer::new("Ordered ABC-123 and XYZ-9", &pattern, Some(2));
+        let tokens = tokenizer.collect::<Vec<Token>>();
+
+        assert_eq!(tokens, vec![
+            Token { term: Term::from_string("123"), position: 1 },
+            Token { term: Term::from_string("9"), position: 2 },
+        ]);
+    }
+
+    #[test]
+    fn test_pattern_tokenizer_whole_match() {
+        let pattern = Regex::new(r"[A-Z]{3}-\d+").unwrap();
+        let tokenizer = PatternTokenizer::new("Ordered ABC-123 and XYZ-9", &pattern, Some(0));
+        let tokens = tokenizer.collect::<Vec<Token>>();
+
+        assert_eq!(tokens, vec![
+            Token { term: Term::from_string("ABC-123"), position: 1 },
+            Token { term: Term::from_string("XYZ-9"), position: 2 },
+        ]);
+    }
+}
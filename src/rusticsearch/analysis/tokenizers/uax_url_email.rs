@@ -0,0 +1,130 @@
+//! Splits strings by word boundaries, like the standard tokenizer, but keeps URLs and
+//! email addresses together as single tokens
+
+use regex::{Regex, Matches};
+use unicode_segmentation::{UnicodeSegmentation, UnicodeWords};
+
+use kite::{Term, Token};
+
+
+lazy_static! {
+    static ref URL_EMAIL_REGEX: Regex = Regex::new(concat!(
+        // Email addresses
+        r"[\w.%+\-]+@[\w\-]+(?:\.[\w\-]+)+",
+        r"|",
+        // URLs (trailing punctuation is left out as it's most likely part of the sentence)
+        r#"(?i:(?:https?|ftp)://|www\.)[^\s<>"]*[^\s<>".,;:!?')\]}]"#,
+    )).unwrap();
+}
+
+
+pub struct UaxUrlEmailTokenizer<'a> {
+    input: &'a str,
+    url_or_emails: Matches<'static, 'a>,
+
+    /// The words between the end of the last URL or email address and the next one
+    words: UnicodeWords<'a>,
+
+    /// The URL or email address that comes after the current words
+    next_url_or_email: Option<&'a str>,
+    last_end: usize,
+    finished: bool,
+    position_counter: u32,
+}
+
+
+impl<'a> UaxUrlEmailTokenizer<'a> {
+    pub fn new(input: &'a str) -> UaxUrlEmailTokenizer<'a> {
+        UaxUrlEmailTokenizer {
+            input: input,
+            url_or_emails: URL_EMAIL_REGEX.find_iter(input),
+            words: "".unicode_words(),
+            next_url_or_email: None,
+            last_end: 0,
+            finished: false,
+            position_counter: 0,
+        }
+    }
+
+    fn next_word(&mut self) -> Option<&'a str> {
+        loop {
+            if let Some(word) = self.words.next() {
+                return Some(word);
+            }
+
+            if let Some(url_or_email) = self.next_url_or_email.take() {
+                return Some(url_or_email);
+            }
+
+            if self.finished {
+                return None;
+            }
+
+            // Move on to the words before the next URL or email address
+            let input = self.input;
+            match self.url_or_emails.next() {
+                Some(url_or_email) => {
+                    self.words = input[self.last_end..url_or_email.start()].unicode_words();
+                    self.next_url_or_email = Some(url_or_email.as_str());
+                    self.last_end = url_or_email.end();
+                }
+                None => {
+                    self.words = input[self.last_end..].unicode_words();
+                    self.finished = true;
+                }
+            }
+        }
+    }
+}
+
+
+impl<'a> Iterator for UaxUrlEmailTokenizer<'a> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        match self.next_word() {
+            Some(word) => {
+                self.position_counter += 1;
+
+                Some(Token {
+                    term: Term::from_string(word),
+                    position: self.position_counter,
+                })
+            }
+            None => None,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use kite::{Term, Token};
+
+    use super::UaxUrlEmailTokenizer;
+
+    #[test]
+    fn test_uax_url_email_tokenizer() {
+        let tokenizer = UaxUrlEmailTokenizer::new("Email foo.bar@example.com or see https://example.com/help?id=1.");
+        let tokens = tokenizer.collect::<Vec<Token>>();
+
+        assert_eq!(tokens, vec![
+            Token { term: Term::from_string("Email"), position: 1 },
+            Token { term: Term::from_string("foo.bar@example.com"), position: 2 },
+            Token { term: Term::from_string("or"), position: 3 },
+            Token { term: Term::from_string("see"), position: 4 },
+            Token { term: Term::from_string("https://example.com/help?id=1"), position: 5 },
+        ]);
+    }
+
+    #[test]
+    fn test_uax_url_email_tokenizer_without_urls() {
+        let tokenizer = UaxUrlEmailTokenizer::new("Hello, world!");
+        let tokens = tokenizer.collect::<Vec<Token>>();
+
+        assert_eq!(tokens, vec![
+            Token { term: Term::from_string("Hello"), position: 1 },
+            Token { term: Term::from_string("world"), position: 2 },
+        ]);
+    }
+}
@@ -1,10 +1,19 @@
-use std::collections::HashMap;
+use std::io::Read;
+use std::collections::{HashMap, BTreeMap};
+
+use serde_json;
+use serde_json::value::ToJson;
+
+use system::System;
+use index::metadata::AliasMetadata;
+use index::metadata::parse::alias::parse as parse_alias;
+use cluster::metadata::{ClusterMetadata, IndexRef};
 
 use api::persistent;
 use api::iron::prelude::*;
 use api::iron::status;
 use api::router::Router;
-use api::utils::json_response;
+use api::utils::{json_response, index_not_found_response};
 
 
 pub fn view_get_global_alias(req: &mut Request) -> IronResult<Response> {
@@ -38,13 +47,24 @@ pub fn view_get_global_alias(req: &mut Request) -> IronResult<Response> {
 }
 
 
-pub fn view_get_alias_list(_req: &mut Request) -> IronResult<Response> {
-    // let ref system = get_system!(req);
-    // let ref index_name = read_path_parameter!(req, "index").unwrap_or("");
+pub fn view_get_alias_list(req: &mut Request) -> IronResult<Response> {
+    let ref system = get_system!(req);
+    let ref index_selector = read_path_parameter!(req, "index").unwrap_or("");
+
+    // Lock cluster metadata
+    let cluster_metadata = system.metadata.read().unwrap();
+
+    let mut indices_json = BTreeMap::new();
+    for index in get_indices_or_404!(cluster_metadata, *index_selector) {
+        let mut aliases_json = BTreeMap::new();
+        for (alias_name, alias) in index.metadata.read().unwrap().aliases.iter() {
+            aliases_json.insert(alias_name.clone(), alias.to_json().unwrap_or(json!({})));
+        }
 
-    // TODO
+        indices_json.insert(index.canonical_name().to_string(), json!({"aliases": aliases_json}));
+    }
 
-    return Ok(json_response(status::Ok, json!({})));
+    return Ok(json_response(status::Ok, json!(indices_json)));
 }
 
 pub fn view_get_alias(req: &mut Request) -> IronResult<Response> {
@@ -70,28 +90,277 @@ pub fn view_get_alias(req: &mut Request) -> IronResult<Response> {
 }
 
 
+/// Replaces the aliases of some indices, keeping the name registry in sync
+///
+/// The new aliases must be checked beforehand, as all of the changes are applied together.
+fn set_index_aliases(system: &System, cluster_metadata: &mut ClusterMetadata, new_aliases: HashMap<IndexRef, BTreeMap<String, AliasMetadata>>) {
+    for (index_ref, aliases) in new_aliases {
+        let index = match cluster_metadata.indices.get(&index_ref) {
+            Some(index) => index,
+            None => continue,
+        };
+
+        let mut index_metadata = index.metadata.write().unwrap();
+
+        for alias_name in index_metadata.aliases.keys().filter(|alias_name| !aliases.contains_key(*alias_name)) {
+            cluster_metadata.names.delete_alias(alias_name, index_ref).unwrap();
+            system.log.info("[api] removed alias", b!("index" => index.canonical_name(), "alias" => alias_name.clone()));
+        }
+
+        for alias_name in aliases.keys().filter(|alias_name| !index_metadata.aliases.contains_key(*alias_name)) {
+            cluster_metadata.names.add_alias_index(alias_name.clone(), index_ref).unwrap();
+            system.log.info("[api] added alias", b!("index" => index.canonical_name(), "alias" => alias_name.clone()));
+        }
+
+        index_metadata.aliases = aliases;
+        index_metadata.save(index.metadata_path()).unwrap();
+    }
+}
+
+
+/// Checks that the new aliases leave each alias with at most one write index
+fn check_write_indices(cluster_metadata: &ClusterMetadata, new_aliases: &HashMap<IndexRef, BTreeMap<String, AliasMetadata>>) -> Result<(), String> {
+    let mut write_indices: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (index_ref, index) in cluster_metadata.indices.iter() {
+        let index_metadata = index.metadata.read().unwrap();
+        let aliases = new_aliases.get(index_ref).unwrap_or(&index_metadata.aliases);
+
+        for (alias_name, alias) in aliases.iter() {
+            if alias.is_write_index == Some(true) {
+                write_indices.entry(alias_name.clone()).or_insert_with(Vec::new).push(index.canonical_name().to_string());
+            }
+        }
+    }
+
+    for (alias_name, mut index_names) in write_indices {
+        if index_names.len() > 1 {
+            index_names.sort();
+            return Err(format!("alias [{}] has more than one write index [{}]", alias_name, index_names.join(",")));
+        }
+    }
+
+    Ok(())
+}
+
+
+/// Reads a name or list of names from an alias action ("index"/"indices", "alias"/"aliases")
+fn read_action_names(params: &serde_json::Map<String, serde_json::Value>, single_key: &str, multi_key: &str) -> Option<Vec<String>> {
+    if let Some(name) = params.get(single_key) {
+        return name.as_str().map(|name| vec![name.to_string()]);
+    }
+
+    match params.get(multi_key).and_then(|names| names.as_array()) {
+        Some(names) => names.iter().map(|name| name.as_str().map(|name| name.to_string())).collect(),
+        None => None,
+    }
+}
+
+
+pub fn view_post_aliases(req: &mut Request) -> IronResult<Response> {
+    let ref system = get_system!(req);
+
+    // Load data from body
+    let data = match json_from_request_body!(req) {
+        Some(data) => data,
+        None => {
+            return Ok(json_response(status::BadRequest, json!({"message": "Request body required"})));
+        }
+    };
+
+    let actions = match data.as_object().and_then(|object| object.get("actions")).and_then(|actions| actions.as_array()) {
+        Some(actions) => actions.clone(),
+        None => {
+            return Ok(json_response(status::BadRequest, json!({"message": "[actions] is required"})));
+        }
+    };
+
+    // Lock cluster metadata
+    let mut cluster_metadata = system.metadata.write().unwrap();
+
+    // Work out the new aliases of each index before changing anything, so the actions are
+    // applied all together or not at all
+    let mut new_aliases: HashMap<IndexRef, BTreeMap<String, AliasMetadata>> = HashMap::new();
+
+    for action in actions.iter() {
+        let (action_name, params) = match action.as_object().and_then(|object| object.iter().next()) {
+            Some((action_name, params)) => (action_name, params),
+            None => {
+                return Ok(json_response(status::BadRequest, json!({"message": "Each action must be an object"})));
+            }
+        };
+
+        let params = match params.as_object() {
+            Some(params) => params,
+            None => {
+                return Ok(json_response(status::BadRequest, json!({"message": format!("[{}] must be an object", action_name)})));
+            }
+        };
+
+        let index_selectors = match read_action_names(params, "index", "indices") {
+            Some(index_selectors) => index_selectors,
+            None => {
+                return Ok(json_response(status::BadRequest, json!({"message": format!("[{}] requires [index] or [indices]", action_name)})));
+            }
+        };
+
+        let alias_names = match read_action_names(params, "alias", "aliases") {
+            Some(alias_names) => alias_names,
+            None => {
+                return Ok(json_response(status::BadRequest, json!({"message": format!("[{}] requires [alias] or [aliases]", action_name)})));
+            }
+        };
+
+        // Filters and the write index flag can be set when adding an alias
+        let alias = if action_name == "add" {
+            let mut alias_json = serde_json::Map::new();
+            for key in ["filter", "is_write_index"].iter() {
+                if let Some(value) = params.get(*key) {
+                    alias_json.insert(key.to_string(), value.clone());
+                }
+            }
+
+            match parse_alias(&serde_json::Value::Object(alias_json)) {
+                Ok(alias) => alias,
+                Err(e) => {
+                    return Ok(json_response(status::BadRequest, json!({"message": format!("Couldn't parse alias: {:?}", e)})));
+                }
+            }
+        } else {
+            AliasMetadata::default()
+        };
+
+        let mut index_refs = Vec::new();
+        for index_selector in index_selectors.iter() {
+            let found = cluster_metadata.names.find(index_selector);
+            if found.is_empty() {
+                return Ok(json_response(status::NotFound, json!({"message": format!("no such index [{}]", index_selector)})));
+            }
+
+            index_refs.extend(found);
+        }
+
+        for index_ref in index_refs {
+            let aliases = match new_aliases.get(&index_ref).cloned() {
+                Some(aliases) => aliases,
+                None => {
+                    match cluster_metadata.indices.get(&index_ref) {
+                        Some(index) => index.metadata.read().unwrap().aliases.clone(),
+                        None => continue,
+                    }
+                }
+            };
+            let aliases = new_aliases.entry(index_ref).or_insert(aliases);
+
+            for alias_name in alias_names.iter() {
+                match action_name.as_ref() {
+                    "add" => {
+                        if cluster_metadata.names.find_canonical(alias_name).is_some() {
+                            return Ok(json_response(status::BadRequest, json!({"message": format!("Invalid alias name [{}], an index exists with the same name as the alias", alias_name)})));
+                        }
+
+                        aliases.insert(alias_name.clone(), alias.clone());
+                    }
+                    "remove" => {
+                        if aliases.remove(alias_name).is_none() {
+                            return Ok(json_response(status::NotFound, json!({"message": format!("aliases [{}] missing", alias_name)})));
+                        }
+                    }
+                    _ => {
+                        return Ok(json_response(status::BadRequest, json!({"message": format!("Unrecognised alias action [{}]", action_name)})));
+                    }
+                }
+            }
+        }
+    }
+
+    if let Err(message) = check_write_indices(&cluster_metadata, &new_aliases) {
+        return Ok(json_response(status::BadRequest, json!({"message": message})));
+    }
+
+    set_index_aliases(system, &mut cluster_metadata, new_aliases);
+
+    Ok(json_response(status::Ok, json!({"acknowledged": true})))
+}
+
+
 pub fn view_put_alias(req: &mut Request) -> IronResult<Response> {
     let ref system = get_system!(req);
     let ref index_selector = read_path_parameter!(req, "index").unwrap_or("");
     let ref alias_name = read_path_parameter!(req, "alias").unwrap_or("");
 
+    // The body can set a filter and write index flag
+    let alias = match json_from_request_body!(req) {
+        Some(data) => {
+            match parse_alias(&data) {
+                Ok(alias) => alias,
+                Err(e) => {
+                    return Ok(json_response(status::BadRequest, json!({"message": format!("Couldn't parse alias: {:?}", e)})));
+                }
+            }
+        }
+        None => AliasMetadata::default(),
+    };
+
     // Lock cluster metadata
     let mut cluster_metadata = system.metadata.write().unwrap();
 
-    // Insert alias into names registry
-    let index_refs = cluster_metadata.names.find(*index_selector);
-    match cluster_metadata.names.insert_or_replace_alias(alias_name.to_string(), index_refs) {
-        Ok(true) => {
-            system.log.info("[api] created alias", b!("index" => *index_selector, "alias" => *alias_name));
-        }
-        Ok(false) => {
-            system.log.info("[api] updated alias", b!("index" => *index_selector, "alias" => *alias_name));
+    if cluster_metadata.names.find_canonical(alias_name).is_some() {
+        return Ok(json_response(status::BadRequest, json!({"message": format!("Invalid alias name [{}], an index exists with the same name as the alias", alias_name)})));
+    }
+
+    // Add the alias to each selected index
+    let mut new_aliases = HashMap::new();
+    for index_ref in cluster_metadata.names.find(*index_selector) {
+        if let Some(index) = cluster_metadata.indices.get(&index_ref) {
+            let mut aliases = index.metadata.read().unwrap().aliases.clone();
+            aliases.insert(alias_name.to_string(), alias.clone());
+            new_aliases.insert(index_ref, aliases);
         }
-        Err(_) => {
-            // TODO
-            return Ok(json_response(status::Ok, json!({"acknowledged": false})));
+    }
+
+    if new_aliases.is_empty() {
+        return Ok(index_not_found_response());
+    }
+
+    if let Err(message) = check_write_indices(&cluster_metadata, &new_aliases) {
+        return Ok(json_response(status::BadRequest, json!({"message": message})));
+    }
+
+    set_index_aliases(system, &mut cluster_metadata, new_aliases);
+
+    Ok(json_response(status::Ok, json!({"acknowledged": true})))
+}
+
+
+pub fn view_delete_alias(req: &mut Request) -> IronResult<Response> {
+    let ref system = get_system!(req);
+    let ref index_selector = read_path_parameter!(req, "index").unwrap_or("");
+    let ref alias_name = read_path_parameter!(req, "alias").unwrap_or("");
+
+    // Lock cluster metadata
+    let mut cluster_metadata = system.metadata.write().unwrap();
+
+    // Remove the alias from each selected index that has it
+    let mut new_aliases = HashMap::new();
+    let index_refs = cluster_metadata.names.find(*index_selector);
+    if index_refs.is_empty() {
+        return Ok(index_not_found_response());
+    }
+
+    for index_ref in index_refs {
+        if let Some(index) = cluster_metadata.indices.get(&index_ref) {
+            let mut aliases = index.metadata.read().unwrap().aliases.clone();
+            if aliases.remove(*alias_name).is_some() {
+                new_aliases.insert(index_ref, aliases);
+            }
         }
     }
 
+    if new_aliases.is_empty() {
+        return Ok(json_response(status::NotFound, json!({"message": format!("aliases [{}] missing", alias_name)})));
+    }
+
+    set_index_aliases(system, &mut cluster_metadata, new_aliases);
+
     Ok(json_response(status::Ok, json!({"acknowledged": true})))
 }
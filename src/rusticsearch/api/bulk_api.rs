@@ -8,15 +8,12 @@ use document::DocumentSource;
 use api::persistent;
 use api::iron::prelude::*;
 use api::iron::status;
-use api::utils::{json_response};
+use api::utils::{json_response, index_closed_response};
 
 
 pub fn view_post_bulk(req: &mut Request) -> IronResult<Response> {
     let ref system = get_system!(req);
 
-    // Lock cluster metedata
-    let cluster_metadata = system.metadata.read().unwrap();
-
     // Load data from body
     let mut payload = String::new();
     req.body.read_to_string(&mut payload).unwrap();
@@ -56,8 +53,39 @@ pub fn view_post_bulk(req: &mut Request) -> IronResult<Response> {
                 let doc_line = payload_lines.next();
                 let doc_json = parse_json!(&doc_line.unwrap());;
 
+                // Create the index if it doesn't exist yet
+                match system.auto_create_index(doc_index) {
+                    Ok(true) => {
+                        system.log.info("[api] created index", b!("index" => doc_index));
+                    }
+                    Ok(false) => {}
+                    Err(e) => {
+                        return Ok(json_response(status::BadRequest, json!({"message": e})));
+                    }
+                }
+
                 // Find index
-                let index = get_index_or_404!(cluster_metadata, doc_index);
+                let cluster_metadata = system.metadata.read().unwrap();
+                let index = get_write_index_or_404!(cluster_metadata, doc_index);
+
+                let store = match index.store() {
+                    Ok(store) => store,
+                    Err(_) => return Ok(index_closed_response(index.canonical_name())),
+                };
+
+                let doc_data = doc_json.as_object().unwrap();
+
+                // Add any new fields to the mapping
+                match index.update_dynamic_mapping(doc_type, doc_data) {
+                    Ok(ref field_names) if !field_names.is_empty() => {
+                        system.log.info("[api] updated mapping", b!("index" => doc_index, "mapping" => doc_type, "new_fields" => field_names.join(", ")));
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        return Ok(json_response(status::InternalServerError, json!({"message": e})));
+                    }
+                }
+
                 let index_metadata = index.metadata.read().unwrap();
 
                 let doc = {
@@ -72,12 +100,24 @@ pub fn view_post_bulk(req: &mut Request) -> IronResult<Response> {
                     // Create document
                     let document_source = DocumentSource {
                         key: doc_id,
-                        data: doc_json.as_object().unwrap(),
+                        data: doc_data,
                     };
-                    document_source.prepare(mapping).unwrap()
+
+                    match document_source.prepare(mapping) {
+                        Ok(doc) => doc,
+                        Err(e) => {
+                            return Ok(json_response(status::BadRequest, json!({"message": e.to_string()})));
+                        }
+                    }
                 };
 
-                index.store.insert_or_update_document(&doc).unwrap();
+                store.insert_or_update_document(&doc).unwrap();
+
+                if let Some(mapping) = index_metadata.mappings.get(doc_type) {
+                    index.update_completions(mapping, doc_id, doc_data);
+                }
+
+                index.clear_term_dictionaries();
 
                 // Insert into "items" array
                 let mut item = HashMap::new();
@@ -1,52 +1,96 @@
 use std::io::Read;
 
 use serde_json;
+use url::form_urlencoded;
+use kite::{Term, Query, TermScorer};
+use kite::document::{DocRef, FieldValue};
 
 use document::DocumentSource;
+use collectors::AllDocumentsCollector;
+use source_filter::{SourceFilter, parse_url_parameter as parse_source_filter_url_parameter};
 
 use api::persistent;
 use api::iron::prelude::*;
 use api::iron::status;
 use api::router::Router;
-use api::utils::json_response;
+use api::utils::{json_response, index_closed_response};
 
 
 pub fn view_get_doc(req: &mut Request) -> IronResult<Response> {
     let ref system = get_system!(req);
     let ref index_name = read_path_parameter!(req, "index").unwrap_or("");
     let ref mapping_name = read_path_parameter!(req, "mapping").unwrap_or("");
-    // let ref doc_key = read_path_parameter!(req, "doc").unwrap_or("");
+    let ref doc_key = read_path_parameter!(req, "doc").unwrap_or("");
 
     // Get index
     let cluster_metadata = system.metadata.read().unwrap();
-    let index = get_index_or_404!(cluster_metadata, *index_name);
+    let index = get_single_index_or_404!(cluster_metadata, *index_name);
     let index_metadata = index.metadata.read().unwrap();
 
+    let store = match index.store() {
+        Ok(store) => store,
+        Err(_) => return Ok(index_closed_response(index.canonical_name())),
+    };
+
     // Check that the mapping exists
     if !index_metadata.mappings.contains_key(*mapping_name) {
         return Ok(json_response(status::NotFound, json!({"message": "Mapping not found"})));
     }
 
+    // Read source filter from the URL
+    let mut source_filter = SourceFilter::default();
+    if let Some(ref url_query) = req.url.query() {
+        for (key, value) in form_urlencoded::parse(url_query.as_bytes()) {
+            if !parse_source_filter_url_parameter(&mut source_filter, key.as_ref(), value.as_ref()) {
+                warn!("unrecognised GET parameter {:?}", key);
+            }
+        }
+    }
+
     // Find document
-    /*
-    let index_reader = index.store.reader();
-    let doc = match index_reader.get_document_by_key(doc_key) {
-        Some(doc) => doc,
+    let index_reader = store.reader();
+    let doc_id = index_reader.schema().get_field_by_name("_id").and_then(|key_field| {
+        let query = Query::Term {
+            field: key_field,
+            term: Term::from_string(doc_key),
+            scorer: TermScorer::default(),
+        };
+
+        let mut collector = AllDocumentsCollector::new().no_score();
+        index_reader.search(&mut collector, &query).unwrap();
+        collector.into_vec().first().map(|&(doc_id, _)| doc_id)
+    });
+
+    let doc_id = match doc_id {
+        Some(doc_id) => doc_id,
         None => {
-            return Ok(json_response(status::NotFound, "{\"message\": \"Document not found\"}"));
+            return Ok(json_response(status::NotFound, json!({
+                "_index": index.canonical_name(),
+                "_type": *mapping_name,
+                "_id": *doc_key,
+                "found": false,
+            })));
         }
     };
-    */
 
+    let mut doc_json = json!({
+        "_index": index.canonical_name(),
+        "_type": *mapping_name,
+        "_id": *doc_key,
+        "found": true,
+    });
+
+    if source_filter.enabled {
+        if let Some(source_field) = index_reader.schema().get_field_by_name("_source") {
+            if let Ok(Some(FieldValue::String(source))) = index_reader.read_stored_field(source_field, DocRef::from_u64(doc_id)) {
+                if let (Some(doc_json), Ok(source)) = (doc_json.as_object_mut(), serde_json::from_str::<serde_json::Value>(&source)) {
+                    doc_json.insert("_source".to_string(), source_filter.apply(source));
+                }
+            }
+        }
+    }
 
-    // Build JSON document
-    // TODO: This is probably completely wrong
-    // let json_object = BTreeMap::new();
-    // FIXME: for (field_name, field_value) in doc.fields.iter() {
-    // FIXME:     json_object.insert(field_name.clone(), Json::Array(field_value.iter().map(|v| v.term.as_json()).collect::<Vec<_>>()));
-    // FIXME: }
-
-    return Ok(json_response(status::Ok, json!({})));
+    return Ok(json_response(status::Ok, doc_json));
 }
 
 
@@ -56,9 +100,46 @@ pub fn view_put_doc(req: &mut Request) -> IronResult<Response> {
     let ref mapping_name = read_path_parameter!(req, "mapping").unwrap_or("");
     let ref doc_key = read_path_parameter!(req, "doc").unwrap_or("");
 
+    // Create the index if it doesn't exist yet
+    match system.auto_create_index(index_name) {
+        Ok(true) => {
+            system.log.info("[api] created index", b!("index" => *index_name));
+        }
+        Ok(false) => {}
+        Err(e) => {
+            return Ok(json_response(status::BadRequest, json!({"message": e})));
+        }
+    }
+
     // Get index
     let cluster_metadata = system.metadata.read().unwrap();
-    let index = get_index_or_404!(cluster_metadata, *index_name);
+    let index = get_write_index_or_404!(cluster_metadata, *index_name);
+
+    let store = match index.store() {
+        Ok(store) => store,
+        Err(_) => return Ok(index_closed_response(index.canonical_name())),
+    };
+
+    // Load data from body
+    let data = match json_from_request_body!(req) {
+        Some(data) => data,
+        None => {
+            return Ok(json_response(status::NotFound, json!({"message": "No data"})));
+        }
+    };
+    let data = data.as_object().unwrap();
+
+    // Add any new fields to the mapping
+    match index.update_dynamic_mapping(*mapping_name, data) {
+        Ok(ref field_names) if !field_names.is_empty() => {
+            system.log.info("[api] updated mapping", b!("index" => *index_name, "mapping" => *mapping_name, "new_fields" => field_names.join(", ")));
+        }
+        Ok(_) => {}
+        Err(e) => {
+            return Ok(json_response(status::InternalServerError, json!({"message": e})));
+        }
+    }
+
     let index_metadata = index.metadata.read().unwrap();
 
     let doc = {
@@ -71,18 +152,26 @@ pub fn view_put_doc(req: &mut Request) -> IronResult<Response> {
         };
 
         // Create document
-        if let Some(data) = json_from_request_body!(req) {
-            let document_source = DocumentSource {
-                key: doc_key,
-                data: data.as_object().unwrap(),
-            };
-            document_source.prepare(mapping).unwrap()
-        } else {
-            return Ok(json_response(status::NotFound, json!({"message": "No data"})));
+        let document_source = DocumentSource {
+            key: doc_key,
+            data: data,
+        };
+
+        match document_source.prepare(mapping) {
+            Ok(doc) => doc,
+            Err(e) => {
+                return Ok(json_response(status::BadRequest, json!({"message": e.to_string()})));
+            }
         }
     };
 
-    index.store.insert_or_update_document(&doc).unwrap();
+    store.insert_or_update_document(&doc).unwrap();
+
+    if let Some(mapping) = index_metadata.mappings.get(*mapping_name) {
+        index.update_completions(mapping, doc_key, data);
+    }
+
+    index.clear_term_dictionaries();
 
     // TODO: {"_index":"wagtail","_type":"searchtests_searchtest","_id":"searchtests_searchtest:5378","_version":1,"created":true}
     return Ok(json_response(status::Ok, json!({})));
@@ -97,21 +186,28 @@ pub fn view_delete_doc(req: &mut Request) -> IronResult<Response> {
 
     // Get index
     let cluster_metadata = system.metadata.read().unwrap();
-    let index = get_index_or_404!(cluster_metadata, *index_name);
+    let index = get_write_index_or_404!(cluster_metadata, *index_name);
     let index_metadata = index.metadata.read().unwrap();
 
+    let store = match index.store() {
+        Ok(store) => store,
+        Err(_) => return Ok(index_closed_response(index.canonical_name())),
+    };
+
     // Check that the mapping exists
     if !index_metadata.mappings.contains_key(*mapping_name) {
         return Ok(json_response(status::NotFound, json!({"message": "Mapping not found"})));
     }
 
     // Make sure the document exists
-    if !index.store.reader().contains_document_key(doc_key) {
+    if !store.reader().contains_document_key(doc_key) {
         return Ok(json_response(status::NotFound, json!({"message": "Document not found"})));
     }
 
     // Delete document
-    index.store.remove_document_by_key(doc_key).unwrap();
+    store.remove_document_by_key(doc_key).unwrap();
+    index.remove_completions(doc_key);
+    index.clear_term_dictionaries();
 
     return Ok(json_response(status::Ok, json!({})));
 }
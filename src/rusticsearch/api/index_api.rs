@@ -1,20 +1,20 @@
 use std::fs;
+use std::collections::BTreeMap;
 use std::io::Read;
 
 use serde_json;
 use serde_json::value::ToJson;
-use kite_rocksdb::RocksDBIndexStore;
-use uuid::Uuid;
 
-use index::Index;
-use index::metadata::IndexMetadata;
-use index::metadata::parse::parse as parse_index_metadata;
+use index::metadata::IndexState;
+use index::metadata::settings::is_dynamic_setting;
+use index::metadata::parse::parse_settings_update;
+use index::metadata::parse::settings::{flatten_settings, parse as parse_index_settings};
 
 use api::persistent;
 use api::iron::prelude::*;
 use api::iron::status;
 use api::router::Router;
-use api::utils::json_response;
+use api::utils::{json_response, index_closed_response};
 
 
 pub fn view_get_index(req: &mut Request) -> IronResult<Response> {
@@ -60,32 +60,12 @@ pub fn view_put_index(req: &mut Request) -> IronResult<Response> {
             system.log.info("[api] updated index", b!("index" => *index_name));
         }
         None => {
-            // Load metadata
-            let mut metadata = IndexMetadata::default();
-            match json_from_request_body!(req).map(|data| parse_index_metadata(&mut metadata, data)) {
-                Some(Ok(())) | None => {}
-                Some(Err(_)) => {
-                    // TODO: better error
-                    return Ok(json_response(status::BadRequest, json!({"message": "Couldn't parse index settings"})));
-                }
-            }
+            let data = json_from_request_body!(req);
 
-            // Create index
-            let mut indices_dir = system.get_indices_dir();
-            indices_dir.push(index_name);
-            let index = Index::new(Uuid::new_v4(), index_name.clone().to_owned(), metadata, RocksDBIndexStore::create(indices_dir).unwrap());
-            index.metadata.read().unwrap().save(index.metadata_path()).unwrap();
-            let index_ref = cluster_metadata.insert_index(index);
-
-            // If there's an alias with the new indexes name, delete it.
-            let alias_deleted = cluster_metadata.names.delete_alias_whole(index_name).unwrap();
-            if alias_deleted {
-                 system.log.info("[api] deleted alias", b!("alias" => format!("{}", index_name), "reason" => "replaced by index"));
+            if let Err(e) = system.create_index(&mut cluster_metadata, index_name, data) {
+                return Ok(json_response(status::BadRequest, json!({"message": e})));
             }
 
-            // Register canonical name
-            cluster_metadata.names.insert_canonical(index_name.clone().to_owned(), index_ref).unwrap();
-
             system.log.info("[api] created index", b!("index" => *index_name));
         }
     }
@@ -107,9 +87,9 @@ pub fn view_delete_index(req: &mut Request) -> IronResult<Response> {
     // Remove indices
     for index_ref in cluster_metadata.names.find(*index_selector) {
         // Get the index name
-        let index_name = {
+        let (index_id, index_name) = {
             if let Some(index) = cluster_metadata.indices.get(&index_ref) {
-                index.canonical_name().to_string()
+                (index.id().clone(), index.canonical_name().to_string())
             } else {
                 // Index doesn't exist
                 continue;
@@ -119,6 +99,9 @@ pub fn view_delete_index(req: &mut Request) -> IronResult<Response> {
         // Remove index from array
         cluster_metadata.indices.remove(&index_ref);
 
+        // Release the store before its files are deleted
+        system.scrolls.lock().unwrap().remove_index(&index_id);
+
         // Delete canonical name
         cluster_metadata.names.delete_canonical(&index_name, index_ref).unwrap();
 
@@ -161,3 +144,190 @@ pub fn view_post_refresh_index(_req: &mut Request) -> IronResult<Response> {
     // TODO: {"_shards":{"total":10,"successful":5,"failed":0}}
     return Ok(json_response(status::Ok, json!({"acknowledged": true})));
 }
+
+
+pub fn view_post_reload_search_analyzers(req: &mut Request) -> IronResult<Response> {
+    let ref system = get_system!(req);
+    let ref index_name = read_path_parameter!(req, "index").unwrap_or("");
+
+    // Get index
+    let cluster_metadata = system.metadata.read().unwrap();
+    let index = get_index_or_404!(cluster_metadata, *index_name);
+
+    if index.is_closed() {
+        return Ok(index_closed_response(index.canonical_name()));
+    }
+
+    // Re-read analysis files and swap in the new search analyzers
+    let mut index_metadata = index.metadata.write().unwrap();
+    let reloaded_analyzers = match index_metadata.reload_search_analyzers() {
+        Ok(reloaded_analyzers) => reloaded_analyzers,
+        Err(e) => {
+            return Ok(json_response(status::BadRequest, json!({"message": format!("Couldn't reload search analyzers: {}", e)})));
+        }
+    };
+    index_metadata.save(index.metadata_path()).unwrap();
+
+    system.log.info("[api] reloaded search analyzers", b!("index" => *index_name));
+
+    return Ok(json_response(status::Ok, json!({
+        "reload_details": [
+            {
+                "index": index.canonical_name(),
+                "reloaded_analyzers": reloaded_analyzers,
+            }
+        ]
+    })));
+}
+
+
+pub fn view_get_index_settings(req: &mut Request) -> IronResult<Response> {
+    let ref system = get_system!(req);
+    let ref index_name = read_path_parameter!(req, "index").unwrap_or("");
+
+    // Get index
+    let cluster_metadata = system.metadata.read().unwrap();
+    let index = get_index_or_404!(cluster_metadata, *index_name);
+
+    // Serialise index settings
+    let json = {
+        let index_metadata = index.metadata.read().unwrap();
+        match index_metadata.to_json() {
+            Ok(json) => json.as_object().and_then(|object| object.get("settings")).cloned().unwrap_or(json!({})),
+            Err(_) => {
+                return Ok(json_response(status::InternalServerError, json!({
+                    "message": "unable to serialise index metadata"
+                })));
+            }
+        }
+    };
+
+    let mut indices_json = BTreeMap::new();
+    indices_json.insert(index.canonical_name().to_string(), json!({"settings": json}));
+
+    return Ok(json_response(status::Ok, json!(indices_json)));
+}
+
+
+pub fn view_put_index_settings(req: &mut Request) -> IronResult<Response> {
+    let ref system = get_system!(req);
+    let ref index_name = read_path_parameter!(req, "index").unwrap_or("");
+
+    // Get index
+    let cluster_metadata = system.metadata.read().unwrap();
+    let index = get_index_or_404!(cluster_metadata, *index_name);
+
+    // Load data from body
+    let data = json_from_request_body!(req);
+
+    let data = match data {
+        Some(data) => data,
+        None => {
+            return Ok(json_response(status::BadRequest, json!({"message": "Request body required"})));
+        }
+    };
+
+    // The settings may be wrapped in a "settings" object
+    let data = match data.as_object().and_then(|object| object.get("settings")).cloned() {
+        Some(settings) => settings,
+        None => data,
+    };
+
+    if !data.is_object() {
+        return Ok(json_response(status::BadRequest, json!({"message": "Settings must be an object"})));
+    }
+
+    let settings = flatten_settings(&data);
+    let mut index_metadata = index.metadata.write().unwrap();
+
+    // Static settings affect how documents are indexed so they can only be changed while the index is closed
+    let static_settings = settings.keys().filter(|name| !is_dynamic_setting(name)).map(|name| format!("index.{}", name)).collect::<Vec<String>>();
+    if !static_settings.is_empty() && index_metadata.state == IndexState::Open {
+        return Ok(json_response(status::BadRequest, json!({
+            "message": format!("Can't update non dynamic settings [[{}]] for open indices [{}]", static_settings.join(", "), index.canonical_name())
+        })));
+    }
+
+    if settings.contains_key("analysis") || settings.keys().any(|name| name.starts_with("similarity.")) {
+        // The analyzers or similarities may have changed so the mappings need to be rebuilt
+        let metadata_json = match index_metadata.to_json() {
+            Ok(json) => json,
+            Err(_) => {
+                return Ok(json_response(status::InternalServerError, json!({
+                    "message": "unable to serialise index metadata"
+                })));
+            }
+        };
+
+        let mut new_metadata = match parse_settings_update(metadata_json, data) {
+            Ok(new_metadata) => new_metadata,
+            Err(e) => {
+                return Ok(json_response(status::BadRequest, json!({"message": format!("Couldn't parse index settings: {}", e)})));
+            }
+        };
+
+        // Link the mappings. The store is released while the index is closed, but the
+        // fields are the same so they can be linked the same way as the current mappings
+        for (mapping_name, mapping) in new_metadata.mappings.iter_mut() {
+            let current_mapping = index_metadata.mappings.get(mapping_name);
+
+            mapping.visit_fields_mut(|name, field_mapping| {
+                field_mapping.index_ref = current_mapping.and_then(|current_mapping| current_mapping.get_field(name)).and_then(|current_field| current_field.index_ref);
+            });
+        }
+
+        *index_metadata = new_metadata;
+    } else {
+        index_metadata.settings = match parse_index_settings(&index_metadata.settings, &settings) {
+            Ok(settings) => settings,
+            Err(e) => {
+                return Ok(json_response(status::BadRequest, json!({"message": format!("Couldn't parse index settings: {}", e)})));
+            }
+        };
+    }
+
+    index_metadata.save(index.metadata_path()).unwrap();
+
+    system.log.info("[api] updated index settings", b!("index" => *index_name));
+
+    return Ok(json_response(status::Ok, json!({"acknowledged": true})));
+}
+
+
+fn set_index_state(req: &mut Request, state: IndexState) -> IronResult<Response> {
+    let ref system = get_system!(req);
+    let ref index_name = read_path_parameter!(req, "index").unwrap_or("");
+
+    // Get index
+    let cluster_metadata = system.metadata.read().unwrap();
+    let index = get_index_or_404!(cluster_metadata, *index_name);
+
+    match index.set_state(state) {
+        Ok(true) => {
+            match state {
+                IndexState::Open => system.log.info("[api] opened index", b!("index" => *index_name)),
+                IndexState::Closed => {
+                    // Scrolls keep the store open, it must be released before the index can be opened again
+                    system.scrolls.lock().unwrap().remove_index(index.id());
+                    system.log.info("[api] closed index", b!("index" => *index_name));
+                }
+            }
+        }
+        Ok(false) => {}
+        Err(e) => {
+            return Ok(json_response(status::InternalServerError, json!({"message": e})));
+        }
+    }
+
+    return Ok(json_response(status::Ok, json!({"acknowledged": true})));
+}
+
+
+pub fn view_post_open_index(req: &mut Request) -> IronResult<Response> {
+    set_index_state(req, IndexState::Open)
+}
+
+
+pub fn view_post_close_index(req: &mut Request) -> IronResult<Response> {
+    set_index_state(req, IndexState::Closed)
+}
@@ -4,26 +4,32 @@ use std::collections::HashMap;
 use serde_json;
 use kite::schema::{FieldType, FieldFlags, FIELD_INDEXED, FIELD_STORED};
 
-use mapping::{self, MappingProperty};
 use mapping::parse::parse as parse_mapping;
+use mapping::build::{MappingBuildError, FieldMappingBuildError};
 
 use api::persistent;
 use api::iron::prelude::*;
 use api::iron::status;
 use api::router::Router;
-use api::utils::json_response;
+use api::utils::{json_response, index_closed_response};
 
 
 pub fn view_put_mapping(req: &mut Request) -> IronResult<Response> {
     let ref system = get_system!(req);
     let ref index_name = read_path_parameter!(req, "index").unwrap_or("");
-    let ref mapping_name = read_path_parameter!(req, "mapping").unwrap_or("");
+    let mapping_name = read_path_parameter!(req, "mapping").map(|mapping_name| mapping_name.to_string());
 
     // Lock cluster metadata
-    let mut cluster_metadata = system.metadata.write().unwrap();
+    let cluster_metadata = system.metadata.write().unwrap();
 
     // Get index
-    let mut index = get_index_or_404_mut!(cluster_metadata, *index_name);
+    let index = get_index_or_404!(cluster_metadata, *index_name);
+
+    // New fields are added to the store, which is only open while the index is
+    let store = match index.store() {
+        Ok(store) => store,
+        Err(_) => return Ok(index_closed_response(index.canonical_name())),
+    };
 
     // Load data from body
     let data = json_from_request_body!(req);
@@ -36,7 +42,26 @@ pub fn view_put_mapping(req: &mut Request) -> IronResult<Response> {
         }
     };
 
-    let data = data.as_object().unwrap().get(*mapping_name).unwrap();
+    // The mapping name can be left out of the URL if the index only has one mapping
+    let mapping_name = match mapping_name {
+        Some(mapping_name) => mapping_name,
+        None => {
+            let index_metadata = index.metadata.read().unwrap();
+
+            if index_metadata.mappings.len() == 1 {
+                index_metadata.mappings.keys().next().unwrap().clone()
+            } else {
+                return Ok(json_response(status::BadRequest, json!({
+                    "acknowledged": false,
+                    "message": "mapping type is missing",
+                })));
+            }
+        }
+    };
+
+    // The mapping may be wrapped in an object with the mapping name as its only key
+    let wrapped_data = data.as_object().and_then(|object| object.get(&mapping_name)).cloned();
+    let data = wrapped_data.unwrap_or(data);
 
     // Insert mapping
     let mapping_builder = match parse_mapping(&data) {
@@ -47,85 +72,110 @@ pub fn view_put_mapping(req: &mut Request) -> IronResult<Response> {
         }
     };
     let mut index_metadata = index.metadata.write().unwrap();
-    let mut mapping = mapping_builder.build(&index_metadata);
+    let mapping = match mapping_builder.build(&index_metadata) {
+        Ok(mapping) => mapping,
+        Err(MappingBuildError::FieldMappingBuildError(field_name, FieldMappingBuildError::UnrecognisedAnalyzer(analyzer))) => {
+            return Ok(json_response(status::BadRequest, json!({
+                "acknowledged": false,
+                "message": format!("unknown analyzer [{}] for field [{}]", analyzer, field_name),
+            })));
+        }
+        Err(MappingBuildError::FieldMappingBuildError(field_name, FieldMappingBuildError::UnrecognisedSimilarity(similarity))) => {
+            return Ok(json_response(status::BadRequest, json!({
+                "acknowledged": false,
+                "message": format!("unknown similarity [{}] for field [{}]", similarity, field_name),
+            })));
+        }
+        Err(MappingBuildError::FieldMappingBuildError(field_name, FieldMappingBuildError::UnrecognisedCopyToField(target_field_name))) => {
+            return Ok(json_response(status::BadRequest, json!({
+                "acknowledged": false,
+                "message": format!("unknown copy_to field [{}] for field [{}]", target_field_name, field_name),
+            })));
+        }
+        Err(MappingBuildError::FieldMappingBuildError(field_name, FieldMappingBuildError::InvalidNullValue(null_value))) => {
+            return Ok(json_response(status::BadRequest, json!({
+                "acknowledged": false,
+                "message": format!("failed to parse null_value {} for field [{}]", null_value, field_name),
+            })));
+        }
+    };
     debug!("{:#?}", mapping);
-    let is_updating = index_metadata.mappings.contains_key(*mapping_name);
+
+    // Fields that are already in the mapping can't be changed in a way that affects how they were indexed
+    let is_updating = index_metadata.mappings.contains_key(&mapping_name);
+    if let Some(existing_mapping) = index_metadata.mappings.get(&mapping_name) {
+        if let Err(error) = existing_mapping.check_merge(&mapping) {
+            return Ok(json_response(status::BadRequest, json!({
+                "acknowledged": false,
+                "message": error.to_string(),
+            })));
+        }
+    }
 
     // Find list of new fields that need to be added to the store
     let new_fields = {
-        let index_reader = index.store.reader();
+        let index_reader = store.reader();
         let schema = index_reader.schema();
         let mut new_fields: HashMap<String, (FieldType, FieldFlags)>  = HashMap::new();
-        for (name, property) in mapping.properties.iter() {
-            if let MappingProperty::Field(ref field_mapping) = *property {
-                let field_type = match field_mapping.data_type {
-                    mapping::FieldType::String => FieldType::Text,
-                    mapping::FieldType::Integer => FieldType::I64,
-                    mapping::FieldType::Boolean => FieldType::Boolean,
-                    mapping::FieldType::Date => FieldType::DateTime,
-                };
-
-                // Flags
-                let mut field_flags = FieldFlags::empty();
-
-                if field_mapping.is_indexed {
-                    field_flags |= FIELD_INDEXED;
-                }
-
-                if field_mapping.is_stored {
-                    field_flags |= FIELD_STORED;
+        for (name, field_mapping) in mapping.fields() {
+            let (field_type, field_flags) = field_mapping.store_field_info();
+
+            // Check if this field already exists
+            if let Some(field_ref) = schema.get_field_by_name(&name) {
+                let field_info = schema.get(&field_ref).expect("get_field_by_name returned an invalid FieldRef");
+
+                // Field already exists. Check for conflicting type or flags, otherwise ignore.
+                if field_info.field_type == field_type && field_info.field_flags == field_flags {
+                    continue;
+                } else {
+                    // Conflict!
+                    // TODO: Better error
+                    return Ok(json_response(status::BadRequest, json!({"acknowledged": false})));
                 }
-
-                // Check if this field already exists
-                if let Some(field_ref) = schema.get_field_by_name(&name) {
-                    let field_info = schema.get(&field_ref).expect("get_field_by_name returned an invalid FieldRef");
-
-                    // Field already exists. Check for conflicting type or flags, otherwise ignore.
-                    if field_info.field_type == field_type && field_info.field_flags == field_flags {
-                        continue;
-                    } else {
-                        // Conflict!
-                        // TODO: Better error
-                        return Ok(json_response(status::BadRequest, json!({"acknowledged": false})));
-                    }
-                }
-
-                new_fields.insert(name.clone(), (field_type, field_flags));
             }
+
+            new_fields.insert(name.clone(), (field_type, field_flags));
         }
 
         new_fields
     };
 
+    // Merge the new fields into the existing mapping
+    let mut mapping = match index_metadata.mappings.remove(&mapping_name) {
+        Some(mut existing_mapping) => {
+            existing_mapping.merge(mapping).expect("check_merge allowed a mapping that merge rejected");
+            existing_mapping
+        }
+        None => mapping,
+    };
+
     // Add new fields into the store
     for (field_name, (field_type, field_flags)) in new_fields {
         let indexed_yesno = if field_flags.contains(FIELD_INDEXED) { "yes" } else { "no" };
         let stored_yesno = if field_flags.contains(FIELD_STORED) { "yes" } else { "no" };
         system.log.info("[api] adding field", b!("index" => *index_name, "field" => field_name, "type" => format!("{:?}", field_type), "indexed" => indexed_yesno, "stored" => stored_yesno));
 
-        index.store.add_field(field_name, field_type, field_flags).unwrap();
+        store.add_field(field_name, field_type, field_flags).unwrap();
     }
 
     // Link the mapping
     {
-        let index_reader = index.store.reader();
+        let index_reader = store.reader();
         let schema = index_reader.schema();
 
-        for (name, property) in mapping.properties.iter_mut() {
-            if let MappingProperty::Field(ref mut field_mapping) = *property {
-                field_mapping.index_ref = schema.get_field_by_name(&name)
-            }
-        }
+        mapping.visit_fields_mut(|name, field_mapping| {
+            field_mapping.index_ref = schema.get_field_by_name(name)
+        });
     }
 
-    index_metadata.mappings.insert(mapping_name.clone().to_owned(), mapping);
+    index_metadata.mappings.insert(mapping_name.clone(), mapping);
     index_metadata.save(index.metadata_path()).unwrap();
+    index.clear_term_dictionaries();
 
     if is_updating {
-        // TODO: New mapping should be merged with existing one
-        system.log.info("[api] updated mapping", b!("index" => *index_name, "mapping" => *mapping_name));
+        system.log.info("[api] updated mapping", b!("index" => *index_name, "mapping" => mapping_name));
     } else {
-        system.log.info("[api] created mapping", b!("index" => *index_name, "mapping" => *mapping_name));
+        system.log.info("[api] created mapping", b!("index" => *index_name, "mapping" => mapping_name));
     }
 
     return Ok(json_response(status::Ok, json!({"acknowledged": true})));
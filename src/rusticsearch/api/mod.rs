@@ -10,6 +10,11 @@ mod document_api;
 mod index_api;
 mod mapping_api;
 mod bulk_api;
+mod snapshot_api;
+mod stats_api;
+mod template_api;
+mod reindex_api;
+mod rollover_api;
 
 use std::sync::Arc;
 
@@ -39,19 +44,50 @@ fn get_router() -> Router {
             post "/:index/_count" => search_api::view_count,
             get "/:index/_search" => search_api::view_search,
             post "/:index/_search" => search_api::view_search,
+            get "/_msearch" => search_api::view_msearch,
+            post "/_msearch" => search_api::view_msearch,
+            get "/:index/_msearch" => search_api::view_msearch,
+            post "/:index/_msearch" => search_api::view_msearch,
+            get "/_search/scroll" => search_api::view_scroll,
+            post "/_search/scroll" => search_api::view_scroll,
+            delete "/_search/scroll" => search_api::view_clear_scroll,
+            delete "/_search/scroll/:scroll_id" => search_api::view_clear_scroll,
+            get "/:index/_validate/query" => search_api::view_validate_query,
+            post "/:index/_validate/query" => search_api::view_validate_query,
             get "/_alias/:alias" => alias_api::view_get_global_alias,
             get "/:index/_alias" => alias_api::view_get_alias_list,
             get "/:index/_alias/:alias" => alias_api::view_get_alias,
             put "/:index/_alias/:alias" => alias_api::view_put_alias,
+            delete "/:index/_alias/:alias" => alias_api::view_delete_alias,
+            post "/_aliases" => alias_api::view_post_aliases,
             get "/:index/:mapping/:doc" => document_api::view_get_doc,
             put "/:index/:mapping/:doc" => document_api::view_put_doc,
             delete "/:index/:mapping/:doc" => document_api::view_delete_doc,
+            get "/_stats" => stats_api::view_get_global_stats,
+            get "/:index/_stats" => stats_api::view_get_index_stats,
+            get "/_cat/indices" => stats_api::view_get_cat_indices,
             get "/:index" => index_api::view_get_index,
             put "/:index" => index_api::view_put_index,
             delete "/:index" => index_api::view_delete_index,
             post "/:index/_refresh" => index_api::view_post_refresh_index,
+            post "/:index/_reload_search_analyzers" => index_api::view_post_reload_search_analyzers,
+            get "/:index/_settings" => index_api::view_get_index_settings,
+            put "/:index/_settings" => index_api::view_put_index_settings,
+            post "/:index/_open" => index_api::view_post_open_index,
+            post "/:index/_close" => index_api::view_post_close_index,
+            post "/:alias/_rollover" => rollover_api::view_post_rollover,
+            post "/:alias/_rollover/:new_index" => rollover_api::view_post_rollover,
+            put "/:index/_mapping" => mapping_api::view_put_mapping,
             put "/:index/_mapping/:mapping" => mapping_api::view_put_mapping,
-            post "/_bulk" => bulk_api::view_post_bulk)
+            post "/_bulk" => bulk_api::view_post_bulk,
+            post "/_reindex" => reindex_api::view_post_reindex,
+            get "/_index_template" => template_api::view_get_templates,
+            get "/_index_template/:template" => template_api::view_get_template,
+            put "/_index_template/:template" => template_api::view_put_template,
+            delete "/_index_template/:template" => template_api::view_delete_template,
+            get "/_snapshot/:repository/:snapshot" => snapshot_api::view_get_snapshot,
+            put "/_snapshot/:repository/:snapshot" => snapshot_api::view_put_snapshot,
+            post "/_snapshot/:repository/:snapshot/_restore" => snapshot_api::view_post_restore_snapshot)
 }
 
 
@@ -0,0 +1,48 @@
+use std::io::Read;
+
+use serde_json;
+use serde_json::value::ToJson;
+
+use reindex::{ReindexError, parse as parse_reindex_request};
+
+use api::persistent;
+use api::iron::prelude::*;
+use api::iron::status;
+use api::router::Router;
+use api::utils::json_response;
+
+
+pub fn view_post_reindex(req: &mut Request) -> IronResult<Response> {
+    let ref system = get_system!(req);
+
+    // Load data from body
+    let data = match json_from_request_body!(req) {
+        Some(data) => data,
+        None => {
+            return Ok(json_response(status::BadRequest, json!({"message": "Request body required"})));
+        }
+    };
+
+    let request = match parse_reindex_request(&data) {
+        Ok(request) => request,
+        Err(e) => {
+            return Ok(json_response(status::BadRequest, json!({"message": format!("Couldn't parse reindex request: {}", e)})));
+        }
+    };
+
+    system.log.info("[api] reindexing", b!("source" => request.source_index.clone(), "dest" => request.dest_index.clone()));
+
+    match system.reindex(&request) {
+        Ok(stats) => {
+            system.log.info("[api] reindexed", b!("source" => request.source_index.clone(), "dest" => request.dest_index.clone(), "created" => stats.created, "updated" => stats.updated, "version_conflicts" => stats.version_conflicts));
+
+            Ok(json_response(status::Ok, stats.to_json().unwrap()))
+        }
+        Err(ReindexError::IndexNotFound(name)) => {
+            Ok(json_response(status::NotFound, json!({"message": format!("no such index [{}]", name)})))
+        }
+        Err(e) => {
+            Ok(json_response(status::BadRequest, json!({"message": format!("{}", e)})))
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use std::io::Read;
+
+use serde_json;
+use serde_json::value::ToJson;
+use url::form_urlencoded;
+
+use rollover::{RolloverError, parse as parse_rollover_request};
+
+use api::persistent;
+use api::iron::prelude::*;
+use api::iron::status;
+use api::router::Router;
+use api::utils::json_response;
+
+
+pub fn view_post_rollover(req: &mut Request) -> IronResult<Response> {
+    let ref system = get_system!(req);
+    let ref alias_name = read_path_parameter!(req, "alias").unwrap_or("");
+    let new_index_name = read_path_parameter!(req, "new_index").map(|name| name.to_string());
+
+    let mut dry_run = false;
+    if let Some(ref url_query) = req.url.query() {
+        for (key, value) in form_urlencoded::parse(url_query.as_bytes()) {
+            if key == "dry_run" {
+                dry_run = value != "false";
+            }
+        }
+    }
+
+    // Load data from body
+    let data = match json_from_request_body!(req) {
+        Some(data) => data,
+        None => json!({}),
+    };
+
+    let request = match parse_rollover_request(&data) {
+        Ok(request) => request,
+        Err(e) => {
+            return Ok(json_response(status::BadRequest, json!({"message": format!("Couldn't parse rollover request: {}", e)})));
+        }
+    };
+
+    match system.rollover(alias_name, new_index_name.as_ref().map(|name| name.as_ref()), &request, dry_run) {
+        Ok(result) => Ok(json_response(status::Ok, result.to_json().unwrap())),
+        Err(RolloverError::AliasNotFound(name)) => {
+            Ok(json_response(status::NotFound, json!({"message": format!("no such alias [{}]", name)})))
+        }
+        Err(RolloverError::SaveMetadataError(e)) => {
+            Ok(json_response(status::InternalServerError, json!({"message": e})))
+        }
+        Err(e) => {
+            Ok(json_response(status::BadRequest, json!({"message": format!("{}", e)})))
+        }
+    }
+}
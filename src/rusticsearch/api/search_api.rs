@@ -1,164 +1,1389 @@
 use std::io::Read;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
 
 use serde_json;
+use serde_json::value::ToJson;
 use url::form_urlencoded;
-use kite::document::DocRef;
+use kite::{Term, TermScorer};
+use kite::document::{DocRef, FieldValue};
 use kite::query::Query;
+use kite::schema::{Schema, FieldRef};
+use kite::collectors::Collector;
 use kite::collectors::top_score::TopScoreCollector;
 use kite::collectors::total_count::TotalCountCollector;
+use kite_rocksdb::RocksDBIndexReader;
 
+use index::Index;
+use cluster::metadata::ClusterMetadata;
+use index::metadata::IndexMetadata;
+use index::metadata::parse::settings::parse_time_value;
+use mapping::FieldType;
+use system::{System, current_timestamp};
 use query_parser::{QueryBuildContext, parse as parse_query};
+use collectors::{AllDocumentsCollector, TopSortedCollector, AggregationsCollector, CountingCollector};
+use aggregations::{Aggregation, AggregationBuildError, parse as parse_aggregations};
+use highlight::{QueryTerms, parse as parse_highlight};
+use sort::{Sort, SortBuildError, parse as parse_sort};
+use scroll::{HitRef, HitOptions, ScrollIndex, ScrollContext};
+use explain::Explainer;
+use profile::{SearchTimings, TimingCollector, QueryProfiler, IndexProfile, CollectorProfile, AggregationProfile, duration_to_nanos};
+use hit_fields::{StoredFields, DocValueField, parse_stored_fields, parse_docvalue_fields};
+use suggest::{Suggestion, Suggester, TermDictionary, merge_term_dictionaries, parse as parse_suggest};
+use source_filter::{SourceFilter, parse as parse_source_filter, parse_url_parameter as parse_source_filter_url_parameter};
 
 use api::persistent;
 use api::iron::prelude::*;
 use api::iron::status;
 use api::router::Router;
-use api::utils::json_response;
+use api::utils::{json_response, index_closed_response, index_not_found_error, index_closed_error};
+
+
+/// The number of searches in a multi search request that are run at once, if the request
+/// doesn't set "max_concurrent_searches"
+const DEFAULT_MAX_CONCURRENT_SEARCHES: usize = 5;
+
+
+/// Restricts a query to the documents that can be seen through an alias
+///
+/// Queries on an index name are left alone. If the alias has a filter that can't be
+/// parsed, this returns an error response rather than the unfiltered query, as that would
+/// show documents that the alias is meant to hide.
+fn apply_alias_filter(query: Query, index_metadata: &IndexMetadata, alias_name: &str, context: &QueryBuildContext, schema: &Schema) -> Result<Query, (status::Status, serde_json::Value)> {
+    let filter_json = match index_metadata.aliases.get(alias_name).and_then(|alias| alias.filter.as_ref()) {
+        Some(filter_json) => filter_json,
+        None => return Ok(query),
+    };
+
+    match parse_query(filter_json) {
+        Ok(filter) => {
+            Ok(Query::Filter {
+                query: Box::new(query),
+                filter: Box::new(filter.build(&context.clone().no_score(), schema)),
+            })
+        }
+        Err(e) => {
+            Err((status::InternalServerError, json!({
+                "message": format!("Filter of alias [{}] couldn't be parsed: {:?}", alias_name, e)
+            })))
+        }
+    }
+}
+
+
+/// Runs a search on one index, passing the matching documents to any aggregations too
+///
+/// Returns the total number of matching documents. If timings are given, the search is
+/// profiled and its times are put into them.
+fn search_index<C: Collector>(index_reader: &RocksDBIndexReader, collector: &mut C, query: &Query, aggregations: &mut Vec<Box<Aggregation>>, timings: Option<&mut SearchTimings>) -> u64 {
+    if let Some(timings) = timings {
+        return profile_search_index(index_reader, collector, query, aggregations, timings);
+    }
+
+    let mut counting_collector = CountingCollector::new(collector);
+
+    if aggregations.is_empty() {
+        index_reader.search(&mut counting_collector, query).unwrap();
+    } else {
+        let mut aggregations_collector = AggregationsCollector::new(&mut counting_collector, index_reader, aggregations);
+        index_reader.search(&mut aggregations_collector, query).unwrap();
+    }
+
+    counting_collector.count()
+}
+
+
+/// Runs a search on one index like `search_index`, timing the search, the collector and
+/// each aggregation
+fn profile_search_index<C: Collector>(index_reader: &RocksDBIndexReader, collector: &mut C, query: &Query, aggregations: &mut Vec<Box<Aggregation>>, timings: &mut SearchTimings) -> u64 {
+    let start = Instant::now();
+    let mut timing_collector = TimingCollector::new(collector);
+
+    let count = {
+        let mut counting_collector = CountingCollector::new(&mut timing_collector);
+
+        if aggregations.is_empty() {
+            index_reader.search(&mut counting_collector, query).unwrap();
+        } else {
+            let mut aggregations_collector = AggregationsCollector::new(&mut counting_collector, index_reader, aggregations).timed();
+            index_reader.search(&mut aggregations_collector, query).unwrap();
+            timings.aggregation_times_in_nanos = aggregations_collector.times_in_nanos();
+        }
+
+        counting_collector.count()
+    };
+
+    timings.search_time_in_nanos = duration_to_nanos(start.elapsed());
+    timings.collector_time_in_nanos = timing_collector.time_in_nanos();
+    count
+}
+
+
+/// Finds the term dictionary of each suggestion's field on an index
+fn collect_suggest_terms(index: &Index, index_metadata: &IndexMetadata, suggestions: &[Suggestion], term_dictionaries: &mut [Vec<Arc<TermDictionary>>]) -> Result<(), String> {
+    for (suggestion, term_dictionaries) in suggestions.iter().zip(term_dictionaries.iter_mut()) {
+        // Completion suggestions use the completion indices instead
+        if let Suggester::Completion(_) = suggestion.suggester {
+            continue;
+        }
+
+        if index_metadata.get_field_mapping(suggestion.suggester.field()).is_some() {
+            term_dictionaries.push(try!(index.term_dictionary(index_metadata, suggestion.suggester.field())));
+        }
+    }
+
+    Ok(())
+}
+
+
+/// Reads the source of a document by its key, for the options of completion suggestions
+fn read_document_source(index: &Index, doc_key: &str) -> Option<serde_json::Value> {
+    let store = match index.store() {
+        Ok(store) => store,
+        Err(_) => return None,
+    };
+    let index_reader = store.reader();
+    let (key_field, source_field) = match (index_reader.schema().get_field_by_name("_id"), index_reader.schema().get_field_by_name("_source")) {
+        (Some(key_field), Some(source_field)) => (key_field, source_field),
+        _ => return None,
+    };
+
+    let query = Query::Term {
+        field: key_field,
+        term: Term::from_string(doc_key),
+        scorer: TermScorer::default(),
+    };
+
+    let mut collector = AllDocumentsCollector::new().no_score();
+    index_reader.search(&mut collector, &query).unwrap();
+    let doc_id = match collector.into_vec().first() {
+        Some(&(doc_id, _)) => doc_id,
+        None => return None,
+    };
+
+    match index_reader.read_stored_field(source_field, DocRef::from_u64(doc_id)) {
+        Ok(Some(FieldValue::String(source))) => serde_json::from_str(&source).ok(),
+        _ => None,
+    }
+}
+
+
+/// Checks whether a phrase suggester's collate query matches any documents on the indices
+fn collate_query_matches(query_json: &serde_json::Value, indices: &[&Index], cluster_metadata: &ClusterMetadata) -> bool {
+    let query = match parse_query(query_json) {
+        Ok(query) => query,
+        Err(_) => return false,
+    };
+
+    for index in indices.iter() {
+        let store = match index.store() {
+            Ok(store) => store,
+            Err(_) => continue,
+        };
+        let index_reader = store.reader();
+        let index_metadata = index.metadata.read().unwrap();
+        let context = QueryBuildContext::new().set_index_metadata(&index_metadata).set_index_reader(&index_reader).set_cluster_metadata(cluster_metadata).no_score();
+
+        let mut collector = TotalCountCollector::new();
+        index_reader.search(&mut collector, &query.build(&context, &index_reader.schema())).unwrap();
+        if collector.get_total_count() > 0 {
+            return true;
+        }
+    }
+
+    false
+}
+
+
+/// Reads the "from" or "size" of a search, which must be a non-negative integer
+fn parse_result_window_value(json: &serde_json::Value) -> Option<usize> {
+    match *json {
+        serde_json::Value::Number(ref number) => number.as_u64().map(|number| number as usize),
+        serde_json::Value::String(ref string) => string.parse().ok(),
+        _ => None,
+    }
+}
+
+
+fn invalid_result_window_value_error(key: &str) -> (status::Status, serde_json::Value) {
+    (status::BadRequest, json!({
+        "message": format!("[{}] must be a non-negative integer", key)
+    }))
+}
+
+
+/// Reads how long a scroll should be kept alive for, in milliseconds
+fn parse_scroll_keep_alive(json: &serde_json::Value) -> Result<u64, (status::Status, serde_json::Value)> {
+    match parse_time_value(json) {
+        Some(Some(keep_alive)) => Ok(keep_alive),
+        _ => {
+            Err((status::BadRequest, json!({
+                "message": format!("Invalid scroll keep alive: {}", json)
+            })))
+        }
+    }
+}
+
+
+/// Converts a hit into JSON
+fn hit_to_json(index: &Index, index_reader: &RocksDBIndexReader, index_metadata: &IndexMetadata, fields: &[(String, FieldRef)], docvalue_fields: &[(&DocValueField, FieldRef)], hit: &HitRef, hit_options: &HitOptions, explainer: Option<&mut Explainer>) -> serde_json::Value {
+    let mut field_values = BTreeMap::new();
+
+    for &(ref field_name, field_ref) in fields.iter() {
+        let value = match index_reader.read_stored_field(field_ref, DocRef::from_u64(hit.doc_id)) {
+            Ok(Some(value)) => vec![value],
+            Ok(None) => vec![],
+            Err(_) => vec![],
+        };
+
+        field_values.insert(field_name.clone(), json!(value));
+    }
+
+    for &(docvalue_field, field_ref) in docvalue_fields.iter() {
+        let value = match index_reader.read_stored_field(field_ref, DocRef::from_u64(hit.doc_id)) {
+            Ok(Some(value)) => vec![docvalue_field.value_to_json(value)],
+            Ok(None) => vec![],
+            Err(_) => vec![],
+        };
+
+        field_values.insert(docvalue_field.field.clone(), json!(value));
+    }
+
+    let mut hit_json = json!({
+        "_index": index.canonical_name(),
+        "_score": hit.sort_key.score,
+        "fields": field_values,
+    });
+
+    // Clients pass these back in "search_after" to get the next page
+    if let Some(ref sort) = hit_options.sort {
+        if let Some(hit_json) = hit_json.as_object_mut() {
+            hit_json.insert("sort".to_string(), sort.to_hit_json(&hit.sort_key));
+        }
+    }
+
+    if hit_options.source_filter.enabled || hit_options.highlight.is_some() || explainer.is_some() {
+        if let Some(source_field) = index_reader.schema().get_field_by_name("_source") {
+            if let Ok(Some(FieldValue::String(source))) = index_reader.read_stored_field(source_field, DocRef::from_u64(hit.doc_id)) {
+                if let (Some(hit_json), Ok(source)) = (hit_json.as_object_mut(), serde_json::from_str::<serde_json::Value>(&source)) {
+                    if let (Some(highlight), Some(query_terms)) = (hit_options.highlight.as_ref(), hit_options.query_terms.get(index.id())) {
+                        if let Some(highlight_json) = highlight.highlight(&source, index_metadata, &index_reader.schema(), query_terms) {
+                            hit_json.insert("highlight".to_string(), highlight_json);
+                        }
+                    }
+
+                    if let (Some(explainer), Some(query)) = (explainer, hit_options.explain_queries.get(index.id())) {
+                        hit_json.insert("_explanation".to_string(), explainer.explain(query, hit.doc_id, &source).to_json());
+                    }
+
+                    if hit_options.source_filter.enabled {
+                        hit_json.insert("_source".to_string(), hit_options.source_filter.apply(source));
+                    }
+                }
+            }
+        }
+    }
+
+    hit_json
+}
+
+
+/// Reads hits back from their indices and converts them into JSON
+///
+/// Hits on indices that aren't in the list (because they've been deleted since a scroll
+/// started, for example) are left out.
+fn hits_to_json(indices: &[&Index], hits: &[HitRef], hit_options: &HitOptions) -> Vec<serde_json::Value> {
+    let mut hits_json = hits.iter().map(|_| None).collect::<Vec<_>>();
+
+    for index in indices.iter() {
+        if !hits.iter().any(|hit| hit.index_id == *index.id()) {
+            continue;
+        }
+
+        // Hits on indices that have been closed since they were found are left out too
+        let store = match index.store() {
+            Ok(store) => store,
+            Err(_) => continue,
+        };
+        let index_reader = store.reader();
+        let index_metadata = index.metadata.read().unwrap();
+
+        let mut fields = Vec::new();
+        for field_name in hit_options.field_names.iter() {
+            match index_reader.schema().get_field_by_name(field_name) {
+                Some(field_ref) => fields.push((field_name.clone(), field_ref)),
+                None => warn!("unknown field {:?}", field_name),
+            }
+        }
+
+        let mut docvalue_fields = Vec::new();
+        for docvalue_field in hit_options.docvalue_fields.iter() {
+            match index_reader.schema().get_field_by_name(&docvalue_field.field) {
+                Some(field_ref) => docvalue_fields.push((docvalue_field, field_ref)),
+                None => warn!("unknown field {:?}", docvalue_field.field),
+            }
+        }
+
+        // Statistics for explanations are gathered once and shared between the hits on each index
+        let mut explainer = match hit_options.explain_queries.get(index.id()) {
+            Some(query) if hit_options.explain => {
+                match Explainer::new(&index_reader, &index_metadata, query) {
+                    Ok(explainer) => Some(explainer),
+                    Err(e) => {
+                        warn!("couldn't explain hits on index {:?}: {:?}", index.canonical_name(), e);
+                        None
+                    }
+                }
+            }
+            _ => None,
+        };
+
+        for (hit, hit_json) in hits.iter().zip(hits_json.iter_mut()) {
+            if hit.index_id == *index.id() {
+                *hit_json = Some(hit_to_json(index, &index_reader, &index_metadata, &fields, &docvalue_fields, hit, hit_options, explainer.as_mut()));
+            }
+        }
+    }
+
+    hits_json.into_iter().filter_map(|hit_json| hit_json).collect()
+}
 
 
 pub fn view_count(req: &mut Request) -> IronResult<Response> {
     let ref system = get_system!(req);
     let ref index_name = read_path_parameter!(req, "index").unwrap_or("");
 
-    // Get index
+    // Get indices
     let cluster_metadata = system.metadata.read().unwrap();
-    let index = get_index_or_404!(cluster_metadata, *index_name);
-    let index_reader = index.store.reader();
-    let index_metadata = index.metadata.read().unwrap();
-
-    let count = match json_from_request_body!(req) {
-        Some(query_json) => {
-            // Parse query
-            let query = parse_query(query_json.as_object().unwrap().get("query").unwrap());
-            debug!("{:#?}", query);
-
-            match query {
-                Ok(query) => {
-                    let mut collector = TotalCountCollector::new();
-                    index_reader.search(&mut collector, &query.build(&QueryBuildContext::new().set_index_metadata(&index_metadata).no_score(), &index_reader.schema())).unwrap();
-                    collector.get_total_count()
+    let indices = get_indices_or_404!(cluster_metadata, *index_name);
+
+    for index in indices.iter() {
+        if index.is_closed() {
+            return Ok(index_closed_response(index.canonical_name()));
+        }
+    }
+
+    // Without a query, every document is counted
+    let mut query = None;
+    if let Some(body_json) = json_from_request_body!(req) {
+        let body = match body_json.as_object() {
+            Some(body) => body,
+            None => {
+                return Ok(json_response(status::BadRequest, json!({
+                    "message": "Request body must be an object"
+                })));
+            }
+        };
+
+        for (key, value) in body.iter() {
+            match key.as_ref() {
+                "query" => {
+                    match parse_query(value) {
+                        Ok(parsed_query) => query = Some(parsed_query),
+                        Err(error) => {
+                            return Ok(json_response(status::BadRequest, json!({
+                                "message": format!("Query error: {:?}", error)
+                            })));
+                        }
+                    }
                 }
-                Err(_) => {
-                    // TODO: What specifically is bad about the Query?
-                    let mut response = Response::with((status::BadRequest,
-                                                       "{\"message\": \"Query error\"}"));
-                    response.headers.set_raw("Content-Type", vec![b"application/json".to_vec()]);
-                    return Ok(response);
+                _ => {
+                    return Ok(json_response(status::BadRequest, json!({
+                        "message": format!("Unrecognised key in count request: {}", key)
+                    })));
                 }
             }
         }
-        None => {
-            let mut collector = TotalCountCollector::new();
-            index_reader.search(&mut collector, &Query::new_all()).unwrap();
-            collector.get_total_count()
-        }
-    };
+    }
+
+    debug!("{:#?}", query);
+
+    // The query is run in filter context and only the matching documents are counted, so
+    // nothing is scored, collected or fetched
+    let mut count = 0;
+    for index in indices.iter() {
+        let store = match index.store() {
+            Ok(store) => store,
+            Err(_) => return Ok(index_closed_response(index.canonical_name())),
+        };
+        let index_reader = store.reader();
+        let index_metadata = index.metadata.read().unwrap();
+        let context = QueryBuildContext::new().set_index_metadata(&index_metadata).set_index_reader(&index_reader).set_cluster_metadata(&cluster_metadata).no_score();
+
+        let built_query = match query {
+            Some(ref query) => query.build(&context, &index_reader.schema()),
+            None => Query::new_all(),
+        };
+
+        let mut collector = TotalCountCollector::new();
+        let filtered_query = match apply_alias_filter(built_query, &index_metadata, index_name, &context, &index_reader.schema()) {
+            Ok(filtered_query) => filtered_query,
+            Err((status, error_json)) => return Ok(json_response(status, error_json)),
+        };
+        index_reader.search(&mut collector, &filtered_query).unwrap();
+        count += collector.get_total_count();
+    }
 
     return Ok(json_response(status::Ok, json!({"count": count})));
 }
 
 
-pub fn view_search(req: &mut Request) -> IronResult<Response> {
+pub fn view_validate_query(req: &mut Request) -> IronResult<Response> {
     let ref system = get_system!(req);
     let ref index_name = read_path_parameter!(req, "index").unwrap_or("");
 
-    // Get index
+    // Check that the index exists
     let cluster_metadata = system.metadata.read().unwrap();
-    let index = get_index_or_404!(cluster_metadata, *index_name);
-    let index_reader = index.store.reader();
-    let index_metadata = index.metadata.read().unwrap();
-
-    match json_from_request_body!(req) {
-        Some(query_json) => {
-            // Parse query
-            let query = parse_query(query_json.as_object().unwrap().get("query").unwrap());
-            debug!("{:#?}", query);
-
-            match query {
-                Ok(query) => {
-                    let mut from = 0;
-                    let mut size = 10;
-                    let mut fields = Vec::new();
-
-                    // TODO: Rewrite this
-                    if let Some(ref url_query) = req.url.query() {
-                        for (key, value) in form_urlencoded::parse(url_query.as_bytes()) {
-                            match key.as_ref() {
-                                "from" => {
-                                    from = value.as_ref().parse().expect("need a number");
-                                }
-                                "size" => {
-                                    size = value.as_ref().parse().expect("need a number");
-                                }
-                                "fields" => {
-                                    for field_name in value.split(",") {
-                                        let field_ref = match index_reader.schema().get_field_by_name(field_name) {
-                                            Some(field_ref) => field_ref,
-                                            None => {
-                                                warn!("unknown field {:?}", field_name);
-                                                continue;
-                                            }
-                                        };
-
-                                        fields.push((field_name.to_owned(), field_ref));
-                                    }
+    let indices = get_indices_or_404!(cluster_metadata, *index_name);
+
+    if let Some(index) = indices.iter().find(|index| index.is_closed()) {
+        return Ok(index_closed_response(index.canonical_name()));
+    }
+
+    let query_json = match json_from_request_body!(req) {
+        Some(query_json) => query_json,
+        None => return Ok(json_response(status::BadRequest, json!({"message": "Missing query"}))),
+    };
+
+    let query = match query_json.as_object().and_then(|object| object.get("query")) {
+        Some(query) => parse_query(query),
+        None => return Ok(json_response(status::BadRequest, json!({"message": "Missing query"}))),
+    };
+
+    match query {
+        Ok(query) => {
+            // Echo back the query as it was understood by the parser
+            let explanation = match query.to_json() {
+                Ok(explanation) => explanation,
+                Err(_) => serde_json::Value::Null,
+            };
+
+            Ok(json_response(status::Ok, json!({
+                "valid": true,
+                "explanations": [
+                    {
+                        "index": *index_name,
+                        "valid": true,
+                        "explanation": explanation,
+                    }
+                ]
+            })))
+        }
+        Err(error) => {
+            Ok(json_response(status::Ok, json!({
+                "valid": false,
+                "explanations": [
+                    {
+                        "index": *index_name,
+                        "valid": false,
+                        "error": format!("{:?}", error),
+                    }
+                ]
+            })))
+        }
+    }
+}
+
+
+/// Runs a search on the indices that an index name or alias points to
+///
+/// Returns the response body, or the status and body of an error response. URL parameters
+/// can override some of the settings in the search body.
+fn run_search(system: &System, index_name: &str, query_json: &serde_json::Value, url_query: Option<&str>) -> Result<serde_json::Value, (status::Status, serde_json::Value)> {
+    // Get indices
+    let cluster_metadata = system.metadata.read().unwrap();
+    let mut indices = cluster_metadata.names.find(index_name).iter().filter_map(|index_ref| cluster_metadata.indices.get(index_ref)).map(|index| &**index).collect::<Vec<_>>();
+
+    if indices.is_empty() {
+        return Err(index_not_found_error());
+    }
+
+    // The position of each index is part of its hits' tiebreakers, so they're always
+    // searched in the same order
+    indices.sort_by(|a, b| a.canonical_name().cmp(b.canonical_name()));
+
+    for index in indices.iter() {
+        if index.is_closed() {
+            return Err(index_closed_error(index.canonical_name()));
+        }
+    }
+
+    if !query_json.is_object() {
+        return Err((status::BadRequest, json!({"message": "Request body must be an object"})));
+    }
+
+    // Parse query, searches without one match every document
+    let match_all_json = json!({"match_all": {}});
+    let query = parse_query(query_json.as_object().unwrap().get("query").unwrap_or(&match_all_json));
+    debug!("{:#?}", query);
+
+    let query = match query {
+        Ok(query) => query,
+        Err(error) => {
+            return Err((status::BadRequest, json!({
+                "message": format!("Query error: {:?}", error)
+            })));
+        }
+    };
+
+    let mut from = 0;
+    let mut size = 10;
+    let mut field_names = Vec::new();
+    let mut source_filter = SourceFilter::default();
+    let mut source_filter_given = false;
+    let mut stored_fields = None;
+    let mut docvalue_fields = Vec::new();
+    let mut scroll_keep_alive = None;
+    let mut explain = false;
+    let mut profile = false;
+
+    // Read pagination from the body, these can be overridden in the URL
+    if let Some(from_json) = query_json.as_object().unwrap().get("from") {
+        match parse_result_window_value(from_json) {
+            Some(value) => from = value,
+            None => return Err(invalid_result_window_value_error("from")),
+        }
+    }
+
+    if let Some(size_json) = query_json.as_object().unwrap().get("size") {
+        match parse_result_window_value(size_json) {
+            Some(value) => size = value,
+            None => return Err(invalid_result_window_value_error("size")),
+        }
+    }
+
+    if let Some(source_json) = query_json.as_object().unwrap().get("_source") {
+        match parse_source_filter(source_json) {
+            Ok(filter) => source_filter = filter,
+            Err(e) => {
+                return Err((status::BadRequest, json!({
+                    "message": format!("Source filter error: {:?}", e)
+                })));
+            }
+        }
+        source_filter_given = true;
+    }
+
+    if let Some(stored_fields_json) = query_json.as_object().unwrap().get("stored_fields") {
+        match parse_stored_fields(stored_fields_json) {
+            Ok(value) => stored_fields = Some(value),
+            Err(e) => {
+                return Err((status::BadRequest, json!({
+                    "message": format!("Stored fields error: {:?}", e)
+                })));
+            }
+        }
+    }
+
+    if let Some(docvalue_fields_json) = query_json.as_object().unwrap().get("docvalue_fields") {
+        match parse_docvalue_fields(docvalue_fields_json) {
+            Ok(value) => docvalue_fields = value,
+            Err(e) => {
+                return Err((status::BadRequest, json!({
+                    "message": format!("Doc value fields error: {:?}", e)
+                })));
+            }
+        }
+    }
+
+    if let Some(explain_json) = query_json.as_object().unwrap().get("explain") {
+        match explain_json.as_bool() {
+            Some(value) => explain = value,
+            None => {
+                return Err((status::BadRequest, json!({
+                    "message": "[explain] must be a boolean"
+                })));
+            }
+        }
+    }
+
+    if let Some(profile_json) = query_json.as_object().unwrap().get("profile") {
+        match profile_json.as_bool() {
+            Some(value) => profile = value,
+            None => {
+                return Err((status::BadRequest, json!({
+                    "message": "[profile] must be a boolean"
+                })));
+            }
+        }
+    }
+
+    // TODO: Rewrite this
+    if let Some(url_query) = url_query {
+        for (key, value) in form_urlencoded::parse(url_query.as_bytes()) {
+            match key.as_ref() {
+                "from" => {
+                    match value.as_ref().parse() {
+                        Ok(value) => from = value,
+                        Err(_) => return Err(invalid_result_window_value_error("from")),
+                    }
+                }
+                "size" => {
+                    match value.as_ref().parse() {
+                        Ok(value) => size = value,
+                        Err(_) => return Err(invalid_result_window_value_error("size")),
+                    }
+                }
+                "fields" => {
+                    for field_name in value.split(",") {
+                        field_names.push(field_name.to_owned());
+                    }
+                }
+                "_source" | "_source_includes" | "_source_include" | "_source_excludes" | "_source_exclude" => {
+                    parse_source_filter_url_parameter(&mut source_filter, key.as_ref(), value.as_ref());
+                    source_filter_given = true;
+                }
+                "stored_fields" => {
+                    let field_names = value.split(",").map(|field_name| serde_json::Value::String(field_name.to_owned())).collect();
+                    match parse_stored_fields(&serde_json::Value::Array(field_names)) {
+                        Ok(value) => stored_fields = Some(value),
+                        Err(e) => {
+                            return Err((status::BadRequest, json!({
+                                "message": format!("Stored fields error: {:?}", e)
+                            })));
+                        }
+                    }
+                }
+                "docvalue_fields" => {
+                    let field_names = value.split(",").map(|field_name| serde_json::Value::String(field_name.to_owned())).collect();
+                    match parse_docvalue_fields(&serde_json::Value::Array(field_names)) {
+                        Ok(value) => docvalue_fields = value,
+                        Err(e) => {
+                            return Err((status::BadRequest, json!({
+                                "message": format!("Doc value fields error: {:?}", e)
+                            })));
+                        }
+                    }
+                }
+                "scroll" => {
+                    match parse_scroll_keep_alive(&serde_json::Value::String(value.into_owned())) {
+                        Ok(keep_alive) => scroll_keep_alive = Some(keep_alive),
+                        Err(error) => return Err(error),
+                    }
+                }
+                // terminate_after
+                "explain" => explain = value == "true",
+                // version
+                // timeout
+                // fielddata_fields
+                // track_scores
+                // stats
+                // suggest_field
+                _ => warn!("unrecognised GET parameter {:?}", key),
+            }
+        }
+    }
+
+    // Asking for stored fields leaves out the source, unless that was asked for too
+    match stored_fields {
+        Some(StoredFields::None) => {
+            field_names.clear();
+            source_filter = SourceFilter::disabled();
+        }
+        Some(StoredFields::Fields(stored_field_names)) => {
+            field_names.extend(stored_field_names);
+            if !source_filter_given {
+                source_filter = SourceFilter::disabled();
+            }
+        }
+        None => {}
+    }
+
+    // Parse aggregations
+    let aggregations_json = query_json.as_object().unwrap().get("aggs")
+        .or(query_json.as_object().unwrap().get("aggregations"));
+    let aggregations = match aggregations_json {
+        Some(aggregations_json) => {
+            match parse_aggregations(aggregations_json) {
+                Ok(aggregations) => Some(aggregations),
+                Err(e) => {
+                    return Err((status::BadRequest, json!({
+                        "message": format!("Aggregation error: {:?}", e)
+                    })));
+                }
+            }
+        }
+        None => None,
+    };
+
+    // Parse suggest
+    let suggestions = match query_json.as_object().unwrap().get("suggest") {
+        Some(suggest_json) => {
+            match parse_suggest(suggest_json) {
+                Ok(suggestions) => Some(suggestions),
+                Err(e) => {
+                    return Err((status::BadRequest, json!({
+                        "message": format!("Suggest error: {:?}", e)
+                    })));
+                }
+            }
+        }
+        None => None,
+    };
+
+    // Parse highlight
+    let highlight = match query_json.as_object().unwrap().get("highlight") {
+        Some(highlight_json) => {
+            match parse_highlight(highlight_json) {
+                Ok(highlight) => Some(highlight),
+                Err(e) => {
+                    return Err((status::BadRequest, json!({
+                        "message": format!("Highlight error: {:?}", e)
+                    })));
+                }
+            }
+        }
+        None => None,
+    };
+
+    // Parse sort
+    let sort = match query_json.as_object().unwrap().get("sort") {
+        Some(sort_json) => {
+            match parse_sort(sort_json) {
+                Ok(sort) => sort,
+                Err(e) => {
+                    return Err((status::BadRequest, json!({
+                        "message": format!("Sort error: {:?}", e)
+                    })));
+                }
+            }
+        }
+        None => Sort::default(),
+    };
+
+    // Parse search_after, which continues on from the last hit of a previous search
+    let search_after = match query_json.as_object().unwrap().get("search_after") {
+        Some(search_after_json) => {
+            if from > 0 {
+                return Err((status::BadRequest, json!({
+                    "message": "[from] must be 0 when [search_after] is used"
+                })));
+            }
+
+            match sort.parse_search_after(search_after_json) {
+                Ok(search_after) => Some(search_after),
+                Err(e) => {
+                    return Err((status::BadRequest, json!({
+                        "message": format!("Sort error: {:?}", e)
+                    })));
+                }
+            }
+        }
+        None => None,
+    };
+
+    // Scrolls return every hit, a page at a time
+    if scroll_keep_alive.is_some() && (from > 0 || search_after.is_some()) {
+        return Err((status::BadRequest, json!({
+            "message": "[from] and [search_after] can't be used with [scroll]"
+        })));
+    }
+
+    let mut aggregation_results = aggregations.as_ref().map(|aggregations| aggregations.iter().map(|_| Vec::new()).collect::<Vec<_>>()).unwrap_or(Vec::new());
+
+    // Search each index
+    let mut hits = Vec::new();
+    let mut total_hits = 0;
+    let mut hit_query_terms = HashMap::new();
+    let mut explain_queries = HashMap::new();
+    let mut index_profiles = Vec::new();
+    let mut scroll_indices = Vec::new();
+    let mut term_dictionaries = suggestions.as_ref().map(|suggestions| suggestions.iter().map(|_| Vec::new()).collect::<Vec<_>>()).unwrap_or(Vec::new());
+    for (index_ordinal, index) in indices.iter().enumerate() {
+        let store = match index.store() {
+            Ok(store) => store,
+            Err(_) => return Err(index_closed_error(index.canonical_name())),
+        };
+        let index_reader = store.reader();
+        let index_metadata = index.metadata.read().unwrap();
+
+        // Very large values can't overflow here as they're always over the limit
+        if from.saturating_add(size) > index_metadata.settings.max_result_window {
+            return Err((status::BadRequest, json!({
+                "message": format!("Result window is too large, from + size must be less than or equal to: [{}] but was [{}]", index_metadata.settings.max_result_window, from.saturating_add(size))
+            })));
+        }
+
+        // Do the search
+        let rewrite_start = Instant::now();
+        let context = QueryBuildContext::new().set_index_metadata(&index_metadata).set_index_reader(&index_reader).set_cluster_metadata(&cluster_metadata);
+        let built_query = query.build(&context, &index_reader.schema());
+        let query_terms = highlight.as_ref().map(|_| QueryTerms::from_query(&built_query));
+        let filtered_query = Arc::new(try!(apply_alias_filter(built_query, &index_metadata, index_name, &context, &index_reader.schema())));
+        let rewrite_time_in_nanos = duration_to_nanos(rewrite_start.elapsed());
+        if explain {
+            explain_queries.insert(index.id().clone(), filtered_query.clone());
+        }
+
+        let index_sort = match sort.build(&index_metadata, &index_reader.schema(), index_ordinal) {
+            Ok(index_sort) => index_sort,
+            Err(SortBuildError::FieldNotSortable(field_name)) => {
+                return Err((status::BadRequest, json!({
+                    "message": format!("Field [{}] can't be sorted on, it must either have doc values or be stored and not analyzed", field_name)
+                })));
+            }
+            Err(SortBuildError::FieldNotNumeric(field_name)) => {
+                return Err((status::BadRequest, json!({
+                    "message": format!("Field [{}] can't be sorted with the \"avg\" mode, it must be numeric", field_name)
+                })));
+            }
+            Err(SortBuildError::FieldNotGeoPoint(field_name)) => {
+                return Err((status::BadRequest, json!({
+                    "message": format!("Field [{}] can't be sorted by distance, it must be a geo_point", field_name)
+                })));
+            }
+            Err(SortBuildError::InvalidMissingValue(field_name)) => {
+                return Err((status::BadRequest, json!({
+                    "message": format!("The \"missing\" value for field [{}] doesn't match the field's type", field_name)
+                })));
+            }
+        };
+
+        // Doc values are kept as stored fields, so either will do
+        for docvalue_field in docvalue_fields.iter() {
+            if let Some(field_mapping) = index_metadata.get_field_mapping(&docvalue_field.field) {
+                if !field_mapping.has_doc_values && !field_mapping.is_stored {
+                    return Err((status::BadRequest, json!({
+                        "message": format!("Field [{}] doesn't have doc values", docvalue_field.field)
+                    })));
+                }
+            }
+        }
+
+        if let Some(ref suggestions) = suggestions {
+            for suggestion in suggestions.iter() {
+                if let Suggester::Completion(ref suggester) = suggestion.suggester {
+                    if let Some(field_mapping) = index_metadata.get_field_mapping(&suggester.field) {
+                        if field_mapping.data_type != FieldType::Completion {
+                            return Err((status::BadRequest, json!({
+                                "message": format!("Field [{}] is not a completion suggest field", suggester.field)
+                            })));
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut index_aggregations = Vec::new();
+        if let Some(ref aggregations) = aggregations {
+            let aggregation_context = context.clone().set_search_query(&*query);
+
+            for &(_, ref aggregation) in aggregations.iter() {
+                match aggregation.build(&aggregation_context, &index_reader.schema()) {
+                    Ok(index_aggregation) => index_aggregations.push(index_aggregation),
+                    Err(AggregationBuildError::FieldNotAggregatable(field_name)) => {
+                        return Err((status::BadRequest, json!({
+                            "message": format!("Field [{}] can't be aggregated on, it must either have doc values or be stored and not analyzed", field_name)
+                        })));
+                    }
+                    Err(AggregationBuildError::FieldNotNumeric(field_name)) => {
+                        return Err((status::BadRequest, json!({
+                            "message": format!("Field [{}] can't be used in this aggregation, it must be numeric", field_name)
+                        })));
+                    }
+                    Err(AggregationBuildError::FieldNotDate(field_name)) => {
+                        return Err((status::BadRequest, json!({
+                            "message": format!("Field [{}] can't be used in this aggregation, it must be a date", field_name)
+                        })));
+                    }
+                    Err(AggregationBuildError::FieldNotGeoPoint(field_name)) => {
+                        return Err((status::BadRequest, json!({
+                            "message": format!("Field [{}] can't be used in this aggregation, it must be a geo_point", field_name)
+                        })));
+                    }
+                    Err(AggregationBuildError::SearchFailed) => {
+                        return Err((status::InternalServerError, json!({
+                            "message": "Couldn't run the filters of the aggregations"
+                        })));
+                    }
+                }
+            }
+        }
+
+        // The aggregations are used up by the search, so their types are read first
+        let mut timings = if profile { Some(SearchTimings::default()) } else { None };
+        let aggregation_types = if profile { index_aggregations.iter().map(|aggregation| aggregation.name().to_string()).collect() } else { Vec::new() };
+
+        let top_hits_only = sort == Sort::default() && search_after.is_none() && scroll_keep_alive.is_none();
+        let mut doc_matches = if top_hits_only {
+            let mut collector = TopScoreCollector::new(from + size);
+            total_hits += search_index(&index_reader, &mut collector, &filtered_query, &mut index_aggregations, timings.as_mut());
+            collector.into_sorted_vec().iter().map(|doc_match| {
+                (doc_match.doc_id(), index_sort.read_key(&index_reader, doc_match.doc_id(), doc_match.score().unwrap()))
+            }).collect::<Vec<_>>()
+        } else {
+            let mut collector = TopSortedCollector::new(&sort, &index_sort, &index_reader, from + size).search_after(search_after.as_ref());
+            total_hits += search_index(&index_reader, &mut collector, &filtered_query, &mut index_aggregations, timings.as_mut());
+            collector.into_sorted_vec()
+        };
+
+        // Hits with equal scores are put in tiebreaker order
+        doc_matches.sort_by(|a, b| sort.compare(&a.1, &b.1));
+
+        if let Some(timings) = timings {
+            let profiler = QueryProfiler::new(&index_reader, &index_metadata);
+            let aggregation_names = aggregations.as_ref().map(|aggregations| aggregations.iter().map(|&(ref name, _)| name.clone()).collect()).unwrap_or(Vec::new());
+
+            index_profiles.push(IndexProfile {
+                index_name: index.canonical_name().to_string(),
+                query: profiler.profile(&filtered_query, timings.query_time_in_nanos()),
+                rewrite_time_in_nanos: rewrite_time_in_nanos,
+                collector: CollectorProfile {
+                    name: if top_hits_only { "TopScoreCollector".to_string() } else { "TopSortedCollector".to_string() },
+                    reason: "search_top_hits".to_string(),
+                    time_in_nanos: timings.collector_time_in_nanos,
+                },
+                aggregations: aggregation_names.into_iter().zip(aggregation_types.into_iter()).zip(timings.aggregation_times_in_nanos.iter()).map(|((name, aggregation_type), time_in_nanos)| {
+                    AggregationProfile {
+                        aggregation_type: aggregation_type,
+                        description: name,
+                        time_in_nanos: *time_in_nanos,
+                    }
+                }).collect(),
+            });
+        }
+
+        for (results, index_aggregation) in aggregation_results.iter_mut().zip(index_aggregations.into_iter()) {
+            results.push(index_aggregation.into_result());
+        }
+
+        if let Some(query_terms) = query_terms {
+            hit_query_terms.insert(index.id().clone(), query_terms);
+        }
+
+        if let Some(ref suggestions) = suggestions {
+            if let Err(e) = collect_suggest_terms(index, &index_metadata, suggestions, &mut term_dictionaries) {
+                return Err((status::InternalServerError, json!({
+                    "message": format!("Couldn't read the terms for suggestions: {}", e)
+                })));
+            }
+        }
+
+        // Scrolls run the query again for each page
+        if scroll_keep_alive.is_some() {
+            scroll_indices.push(ScrollIndex {
+                index_id: index.id().clone(),
+                store: store.clone(),
+                query: filtered_query.clone(),
+            });
+        }
+
+        hits.extend(doc_matches.into_iter().map(|(doc_id, sort_key)| {
+            HitRef {
+                index_id: index.id().clone(),
+                doc_id: doc_id,
+                sort_key: sort_key,
+            }
+        }));
+    }
+
+    // Merge the hits from each index
+    hits.sort_by(|a, b| sort.compare(&a.sort_key, &b.sort_key));
+    let mut hits = hits.into_iter().skip(from).collect::<Vec<_>>();
+    hits.truncate(size);
+
+    let hit_options = HitOptions {
+        field_names: field_names,
+        docvalue_fields: docvalue_fields,
+        source_filter: source_filter,
+        highlight: highlight,
+        query_terms: hit_query_terms,
+        sort: if sort != Sort::default() || search_after.is_some() { Some(sort.clone()) } else { None },
+        explain: explain,
+        explain_queries: explain_queries,
+    };
+    let hits_json = hits_to_json(&indices, &hits, &hit_options);
+
+    // Reduce the aggregation results from each index
+    let aggregations_json = match aggregations {
+        Some(aggregations) => {
+            let mut aggregations_json = BTreeMap::new();
+            for ((name, aggregation), results) in aggregations.into_iter().zip(aggregation_results.into_iter()) {
+                match aggregation.reduce(results) {
+                    Ok(aggregation_json) => aggregations_json.insert(name, aggregation_json),
+                    Err(e) => {
+                        return Err((status::BadRequest, json!({
+                            "message": format!("{}", e)
+                        })));
+                    }
+                };
+            }
+
+            Some(aggregations_json)
+        }
+        None => None,
+    };
+
+    // The scroll's next page starts after the last hit of this one
+    let scroll_id = match scroll_keep_alive {
+        Some(keep_alive) => {
+            let now = current_timestamp();
+            let last_key = hits.last().map(|hit| hit.sort_key.clone());
+            let context = ScrollContext::new(scroll_indices, sort.clone(), last_key, total_hits, size, hit_options, keep_alive, now);
+
+            match system.scrolls.lock().unwrap().insert(context, now) {
+                Ok(scroll_id) => Some(scroll_id),
+                Err(e) => {
+                    return Err((status::TooManyRequests, json!({
+                        "message": format!("{}", e)
+                    })));
+                }
+            }
+        }
+        None => None,
+    };
+
+    // The suggest text is analysed with the field's search analyzer on the first index that has it
+    let suggest_json = suggestions.map(|suggestions| {
+        let mut suggest_json = serde_json::Map::new();
+        for (suggestion, term_dictionaries) in suggestions.into_iter().zip(term_dictionaries.into_iter()) {
+            let term_dictionary = merge_term_dictionaries(term_dictionaries);
+            let analyzer = indices.iter().filter_map(|index| {
+                index.metadata.read().unwrap().get_field_mapping(suggestion.suggester.field()).map(|field_mapping| field_mapping.search_analyzer().cloned())
+            }).next().unwrap_or(None);
+
+            let entries = match suggestion.suggester {
+                Suggester::Term(ref suggester) => suggester.suggest(&suggestion.text, analyzer.as_ref(), &term_dictionary),
+                Suggester::Phrase(ref suggester) => {
+                    let options = suggester.find_options(&suggestion.text, analyzer.as_ref(), &term_dictionary);
+
+                    // Without pruning, suggestions that don't match the collate query are dropped
+                    let options = match suggester.collate {
+                        Some(ref collate) => {
+                            options.into_iter().filter_map(|option| {
+                                let collate_match = collate_query_matches(&collate.render(&option.text), &indices, &cluster_metadata);
+                                if collate.prune {
+                                    Some((option, Some(collate_match)))
+                                } else if collate_match {
+                                    Some((option, None))
+                                } else {
+                                    None
                                 }
-                                // terminate_after
-                                // explain
-                                // version
-                                // timeout
-                                // fielddata_fields
-                                // track_scores
-                                // stats
-                                // suggest_field
-                                _ => warn!("unrecognised GET parameter {:?}", key),
-                            }
+                            }).collect()
+                        }
+                        None => options.into_iter().map(|option| (option, None)).collect(),
+                    };
+
+                    suggester.to_json(&suggestion.text, options)
+                }
+                Suggester::Completion(ref suggester) => {
+                    let mut completions = Vec::new();
+                    for index in indices.iter() {
+                        let index_metadata = index.metadata.read().unwrap();
+                        let field_mapping = match index_metadata.get_field_mapping(&suggester.field) {
+                            Some(field_mapping) => field_mapping,
+                            None => continue,
+                        };
+
+                        if let Some(completion_index) = index.completions.read().unwrap().get(&suggester.field) {
+                            completions.extend(suggester.find_completions(&suggestion.text, field_mapping, completion_index).into_iter().map(|completion| (*index, completion)));
                         }
                     }
 
-                    // Do the search
-                    let mut collector = TopScoreCollector::new(from + size);
-                    index_reader.search(&mut collector, &query.build(&QueryBuildContext::new().set_index_metadata(&index_metadata), &index_reader.schema())).unwrap();
+                    let options = suggester.merge(completions).into_iter().map(|(index, completion)| {
+                        let source = read_document_source(index, &completion.doc_key);
+                        let mut option_json = json!({
+                            "text": completion.text,
+                            "_index": index.canonical_name(),
+                            "_id": completion.doc_key,
+                            "_score": completion.score,
+                        });
+
+                        if let (Some(object), Some(source)) = (option_json.as_object_mut(), source) {
+                            object.insert("_source".to_string(), source);
+                        }
+
+                        option_json
+                    }).collect();
+
+                    suggester.to_json(&suggestion.text, options)
+                }
+            };
+            suggest_json.insert(suggestion.name.clone(), entries);
+        }
+        serde_json::Value::Object(suggest_json)
+    });
+
+    // TODO: {"took":5,"timed_out":false,"_shards":{"total":5,"successful":5,"failed":0},"hits":{"total":4,"max_score":1.0,"hits":[{"_index":"wagtail","_type":"searchtests_searchtest_searchtests_searchtestchild","_id":"searchtests_searchtest:5380","_score":1.0,"fields":{"pk":["5380"]}},{"_index":"wagtail","_type":"searchtests_searchtest","_id":"searchtests_searchtest:5379","_score":1.0,"fields":{"pk":["5379"]}}]}}
+    let mut response_json = json!({
+        "hits": {
+            "total": total_hits,
+            "hits": hits_json
+        }
+    });
+
+    if let Some(scroll_id) = scroll_id {
+        if let Some(object) = response_json.as_object_mut() {
+            object.insert("_scroll_id".to_string(), json!(scroll_id));
+        }
+    }
+
+    if let Some(aggregations_json) = aggregations_json {
+        if let Some(object) = response_json.as_object_mut() {
+            object.insert("aggregations".to_string(), json!(aggregations_json));
+        }
+    }
+
+    if let Some(suggest_json) = suggest_json {
+        if let Some(object) = response_json.as_object_mut() {
+            object.insert("suggest".to_string(), suggest_json);
+        }
+    }
+
+    if profile {
+        if let Some(object) = response_json.as_object_mut() {
+            object.insert("profile".to_string(), json!({
+                "shards": index_profiles.iter().map(|index_profile| index_profile.to_json()).collect::<Vec<_>>(),
+            }));
+        }
+    }
+
+    Ok(response_json)
+}
+
 
-                    // Convert hits into JSON
-                    let mut hits = Vec::new();
-                    for doc_match in collector.into_sorted_vec().iter().skip(from) {
-                        let mut field_values = BTreeMap::new();
+pub fn view_search(req: &mut Request) -> IronResult<Response> {
+    let ref system = get_system!(req);
+    let ref index_name = read_path_parameter!(req, "index").unwrap_or("");
 
-                        for &(ref field_name, field_ref) in fields.iter() {
-                            let value = match index_reader.read_stored_field(field_ref, DocRef::from_u64(doc_match.doc_id())) {
-                                Ok(Some(value)) => vec![value],
-                                Ok(None) => vec![],
-                                Err(_) => vec![],
-                            };
+    let query_json = match json_from_request_body!(req) {
+        Some(query_json) => query_json,
+        None => return Ok(json_response(status::BadRequest, json!({"message": "Missing query"}))),
+    };
 
-                            field_values.insert(field_name.clone(), value);
+    match run_search(system, index_name, &query_json, req.url.query()) {
+        Ok(response_json) => Ok(json_response(status::Ok, response_json)),
+        Err((status, error_json)) => Ok(json_response(status, error_json)),
+    }
+}
+
+
+/// Runs several searches in one request
+///
+/// The body has a header line for each search, which can name the index to search if it
+/// isn't given in the URL, followed by a line with the search body. The searches are run
+/// concurrently and their responses are returned in the same order.
+pub fn view_msearch(req: &mut Request) -> IronResult<Response> {
+    let ref system = get_system!(req);
+    let default_index_name = read_path_parameter!(req, "index").map(|index_name| index_name.to_owned());
+
+    let mut max_concurrent_searches = DEFAULT_MAX_CONCURRENT_SEARCHES;
+    if let Some(ref url_query) = req.url.query() {
+        for (key, value) in form_urlencoded::parse(url_query.as_bytes()) {
+            match key.as_ref() {
+                "max_concurrent_searches" => {
+                    match value.as_ref().parse() {
+                        Ok(value) if value > 0 => max_concurrent_searches = value,
+                        _ => {
+                            return Ok(json_response(status::BadRequest, json!({
+                                "message": "[max_concurrent_searches] must be a positive integer"
+                            })));
                         }
+                    }
+                }
+                _ => warn!("unrecognised GET parameter {:?}", key),
+            }
+        }
+    }
+
+    // Load data from body
+    let mut payload = String::new();
+    req.body.read_to_string(&mut payload).unwrap();
 
-                        hits.push(json!({
-                            "_score": doc_match.score().unwrap(),
-                            "fields": field_values,
-                        }));
+    let mut searches = Vec::new();
+    let mut payload_lines = payload.split('\n').filter(|line| !line.trim().is_empty());
+    while let Some(header_line) = payload_lines.next() {
+        let header_json = parse_json!(header_line);
+        let header = match header_json.as_object() {
+            Some(header) => header,
+            None => {
+                return Ok(json_response(status::BadRequest, json!({
+                    "message": "Multi search headers must be objects"
+                })));
+            }
+        };
+
+        let mut index_name = default_index_name.clone();
+        for (key, value) in header.iter() {
+            match key.as_ref() {
+                "index" => {
+                    match value.as_str() {
+                        Some(value) => index_name = Some(value.to_owned()),
+                        None => {
+                            return Ok(json_response(status::BadRequest, json!({
+                                "message": "[index] must be a string"
+                            })));
+                        }
                     }
+                }
+                _ => warn!("unrecognised multi search header {:?}", key),
+            }
+        }
 
-                    // TODO: {"took":5,"timed_out":false,"_shards":{"total":5,"successful":5,"failed":0},"hits":{"total":4,"max_score":1.0,"hits":[{"_index":"wagtail","_type":"searchtests_searchtest_searchtests_searchtestchild","_id":"searchtests_searchtest:5380","_score":1.0,"fields":{"pk":["5380"]}},{"_index":"wagtail","_type":"searchtests_searchtest","_id":"searchtests_searchtest:5379","_score":1.0,"fields":{"pk":["5379"]}}]}}
-                    Ok(json_response(status::Ok,
-                                     json!({
-                                         "hits": {
-                                             "total": hits.len(),
-                                             "hits": hits
-                                        }})))
+        let index_name = match index_name {
+            Some(index_name) => index_name,
+            None => {
+                return Ok(json_response(status::BadRequest, json!({
+                    "message": "Multi search header is missing [index]"
+                })));
+            }
+        };
+
+        let query_json = match payload_lines.next() {
+            Some(body_line) => parse_json!(body_line),
+            None => {
+                return Ok(json_response(status::BadRequest, json!({
+                    "message": "Multi search header isn't followed by a search body"
+                })));
+            }
+        };
+
+        searches.push((index_name, query_json));
+    }
+
+    // Run the searches, a batch at a time
+    let mut responses = Vec::new();
+    for batch in searches.chunks(max_concurrent_searches) {
+        let search_threads = batch.iter().cloned().map(|(index_name, query_json)| {
+            let system = system.clone();
+            thread::spawn(move || run_search(&system, &index_name, &query_json, None))
+        }).collect::<Vec<_>>();
+
+        for search_thread in search_threads {
+            let response_json = match search_thread.join() {
+                Ok(Ok(mut response_json)) => {
+                    if let Some(object) = response_json.as_object_mut() {
+                        object.insert("status".to_string(), json!(200));
+                    }
+                    response_json
+                }
+                Ok(Err((status, error_json))) => {
+                    json!({
+                        "error": error_json,
+                        "status": status.to_u16(),
+                    })
                 }
                 Err(_) => {
-                    // TODO: What specifically is bad about the Query?
-                    let mut response = Response::with((status::BadRequest,
-                                                       "{\"message\": \"Query error\"}"));
-                    response.headers.set_raw("Content-Type", vec![b"application/json".to_vec()]);
-                    Ok(response)
+                    json!({
+                        "error": {"message": "Search failed"},
+                        "status": 500,
+                    })
                 }
+            };
+
+            responses.push(response_json);
+        }
+    }
+
+    Ok(json_response(status::Ok, json!({"responses": responses})))
+}
+
+
+pub fn view_scroll(req: &mut Request) -> IronResult<Response> {
+    let ref system = get_system!(req);
+
+    // The scroll id and keep alive can be given in the body or the URL
+    let mut scroll_id = None;
+    let mut keep_alive_json = None;
+
+    if let Some(request_json) = json_from_request_body!(req) {
+        if let Some(object) = request_json.as_object() {
+            scroll_id = object.get("scroll_id").and_then(|scroll_id| scroll_id.as_str()).map(|scroll_id| scroll_id.to_string());
+            keep_alive_json = object.get("scroll").cloned();
+        }
+    }
+
+    if let Some(ref url_query) = req.url.query() {
+        for (key, value) in form_urlencoded::parse(url_query.as_bytes()) {
+            match key.as_ref() {
+                "scroll_id" => scroll_id = Some(value.into_owned()),
+                "scroll" => keep_alive_json = Some(serde_json::Value::String(value.into_owned())),
+                _ => warn!("unrecognised GET parameter {:?}", key),
             }
         }
-        None => Ok(json_response(status::BadRequest, json!({"message": "Missing query"}))),
     }
+
+    let scroll_id = match scroll_id {
+        Some(scroll_id) => scroll_id,
+        None => return Ok(json_response(status::BadRequest, json!({"message": "Missing scroll_id"}))),
+    };
+
+    let keep_alive = match keep_alive_json {
+        Some(keep_alive_json) => {
+            match parse_scroll_keep_alive(&keep_alive_json) {
+                Ok(keep_alive) => Some(keep_alive),
+                Err((status, error_json)) => return Ok(json_response(status, error_json)),
+            }
+        }
+        None => None,
+    };
+
+    let cluster_metadata = system.metadata.read().unwrap();
+    let mut scrolls = system.scrolls.lock().unwrap();
+    let now = current_timestamp();
+
+    let context = match scrolls.get_mut(&scroll_id, now) {
+        Some(context) => context,
+        None => {
+            return Ok(json_response(status::NotFound, json!({
+                "message": format!("No search context found for id [{}]", scroll_id)
+            })));
+        }
+    };
+
+    // Hits on indices that have been deleted or closed since the scroll started are skipped
+    let indices = cluster_metadata.indices.values().map(|index| &**index).filter(|index| !index.is_closed()).collect::<Vec<_>>();
+
+    context.renew(keep_alive, now);
+    let hits = context.next_page(&indices);
+    let hits_json = hits_to_json(&indices, &hits, &context.hit_options);
+
+    Ok(json_response(status::Ok, json!({
+        "_scroll_id": scroll_id,
+        "hits": {
+            "total": context.total_hits,
+            "hits": hits_json,
+        }
+    })))
+}
+
+
+pub fn view_clear_scroll(req: &mut Request) -> IronResult<Response> {
+    let ref system = get_system!(req);
+
+    // Scroll ids can be given in the URL (comma separated) or in the body (as a string or array)
+    let mut scroll_ids = Vec::new();
+
+    if let Some(scroll_ids_param) = read_path_parameter!(req, "scroll_id") {
+        scroll_ids.extend(scroll_ids_param.split(',').map(|scroll_id| scroll_id.to_string()));
+    }
+
+    if let Some(request_json) = json_from_request_body!(req) {
+        match request_json.as_object().and_then(|object| object.get("scroll_id")) {
+            Some(&serde_json::Value::String(ref scroll_id)) => scroll_ids.push(scroll_id.clone()),
+            Some(&serde_json::Value::Array(ref array)) => {
+                scroll_ids.extend(array.iter().filter_map(|scroll_id| scroll_id.as_str()).map(|scroll_id| scroll_id.to_string()));
+            }
+            _ => {}
+        }
+    }
+
+    if scroll_ids.is_empty() {
+        return Ok(json_response(status::BadRequest, json!({"message": "Missing scroll_id"})));
+    }
+
+    let mut scrolls = system.scrolls.lock().unwrap();
+    let num_freed = if scroll_ids.iter().any(|scroll_id| scroll_id == "_all") {
+        scrolls.clear()
+    } else {
+        scroll_ids.iter().filter(|scroll_id| scrolls.remove(scroll_id)).count()
+    };
+
+    let status = if num_freed > 0 { status::Ok } else { status::NotFound };
+    Ok(json_response(status, json!({
+        "succeeded": true,
+        "num_freed": num_freed,
+    })))
 }
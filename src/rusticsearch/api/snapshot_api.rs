@@ -0,0 +1,103 @@
+use std::io::Read;
+
+use serde_json;
+
+use snapshot::SnapshotError;
+
+use api::persistent;
+use api::iron::prelude::*;
+use api::iron::status;
+use api::router::Router;
+use api::utils::json_response;
+
+
+fn snapshot_error_response(error: SnapshotError) -> Response {
+    let status = match error {
+        SnapshotError::IndexNotFound(_) | SnapshotError::SnapshotNotFound(_) => status::NotFound,
+        SnapshotError::IoError(_) | SnapshotError::LoadError(..) => status::InternalServerError,
+        _ => status::BadRequest,
+    };
+
+    json_response(status, json!({"message": format!("{}", error)}))
+}
+
+
+pub fn view_put_snapshot(req: &mut Request) -> IronResult<Response> {
+    let ref system = get_system!(req);
+    let ref repository = read_path_parameter!(req, "repository").unwrap_or("");
+    let ref snapshot = read_path_parameter!(req, "snapshot").unwrap_or("");
+
+    // Find which indices to snapshot (all of them by default)
+    let mut index_selector = "*".to_string();
+    if let Some(data) = json_from_request_body!(req) {
+        if let Some(indices_json) = data.as_object().and_then(|data| data.get("indices")) {
+            index_selector = match *indices_json {
+                serde_json::Value::String(ref indices) => indices.clone(),
+                serde_json::Value::Array(ref indices) => {
+                    indices.iter().filter_map(|index| index.as_str()).collect::<Vec<&str>>().join(",")
+                }
+                _ => {
+                    return Ok(json_response(status::BadRequest, json!({"message": "\"indices\" must be a string or an array"})));
+                }
+            };
+        }
+    }
+
+    let index_names = match system.create_snapshot(repository, snapshot, &index_selector) {
+        Ok(index_names) => index_names,
+        Err(e) => return Ok(snapshot_error_response(e)),
+    };
+
+    system.log.info("[api] created snapshot", b!("repository" => *repository, "snapshot" => *snapshot));
+
+    return Ok(json_response(status::Ok, json!({
+        "snapshot": {
+            "snapshot": snapshot,
+            "indices": index_names,
+            "state": "SUCCESS",
+        }
+    })));
+}
+
+
+pub fn view_get_snapshot(req: &mut Request) -> IronResult<Response> {
+    let ref system = get_system!(req);
+    let ref repository = read_path_parameter!(req, "repository").unwrap_or("");
+    let ref snapshot = read_path_parameter!(req, "snapshot").unwrap_or("");
+
+    let index_names = match system.get_snapshot_indices(repository, snapshot) {
+        Ok(index_names) => index_names,
+        Err(e) => return Ok(snapshot_error_response(e)),
+    };
+
+    return Ok(json_response(status::Ok, json!({
+        "snapshots": [
+            {
+                "snapshot": snapshot,
+                "indices": index_names,
+                "state": "SUCCESS",
+            }
+        ]
+    })));
+}
+
+
+pub fn view_post_restore_snapshot(req: &mut Request) -> IronResult<Response> {
+    let ref system = get_system!(req);
+    let ref repository = read_path_parameter!(req, "repository").unwrap_or("");
+    let ref snapshot = read_path_parameter!(req, "snapshot").unwrap_or("");
+
+    let index_names = match system.restore_snapshot(repository, snapshot) {
+        Ok(index_names) => index_names,
+        Err(e) => return Ok(snapshot_error_response(e)),
+    };
+
+    system.log.info("[api] restored snapshot", b!("repository" => *repository, "snapshot" => *snapshot));
+
+    return Ok(json_response(status::Ok, json!({
+        "snapshot": {
+            "snapshot": snapshot,
+            "indices": index_names,
+        }
+    })));
+}
@@ -0,0 +1,106 @@
+use std::collections::BTreeMap;
+
+use serde_json;
+
+use index::Index;
+use index::stats::StoreStats;
+
+use api::persistent;
+use api::iron::prelude::*;
+use api::iron::status;
+use api::router::Router;
+use api::utils::{json_response, index_closed_response};
+
+
+fn store_stats_json(stats: &StoreStats) -> serde_json::Value {
+    json!({
+        "primaries": {
+            "docs": {
+                "count": stats.num_docs,
+                "deleted": stats.deleted_docs,
+            },
+            "segments": {
+                "count": stats.segment_count,
+            },
+            "store": {
+                "size_in_bytes": stats.size_in_bytes,
+            },
+        }
+    })
+}
+
+
+fn stats_response<'a, I: Iterator<Item=&'a Index>>(indices: I) -> Response {
+    let mut total_stats = StoreStats::default();
+    let mut indices_json = BTreeMap::new();
+
+    for index in indices {
+        let stats = match index.store_stats() {
+            Ok(stats) => stats,
+            Err(e) => {
+                return json_response(status::InternalServerError, json!({"message": e}));
+            }
+        };
+
+        total_stats.add(&stats);
+        indices_json.insert(index.canonical_name().to_string(), store_stats_json(&stats));
+    }
+
+    json_response(status::Ok, json!({
+        "_all": store_stats_json(&total_stats),
+        "indices": indices_json,
+    }))
+}
+
+
+pub fn view_get_global_stats(req: &mut Request) -> IronResult<Response> {
+    let ref system = get_system!(req);
+
+    let cluster_metadata = system.metadata.read().unwrap();
+
+    // Closed indices don't report stats
+    Ok(stats_response(cluster_metadata.indices.values().map(|index| &**index).filter(|index| !index.is_closed())))
+}
+
+
+pub fn view_get_index_stats(req: &mut Request) -> IronResult<Response> {
+    let ref system = get_system!(req);
+    let ref index_name = read_path_parameter!(req, "index").unwrap_or("");
+
+    // Get index
+    let cluster_metadata = system.metadata.read().unwrap();
+    let index = get_index_or_404!(cluster_metadata, *index_name);
+
+    if index.is_closed() {
+        return Ok(index_closed_response(index.canonical_name()));
+    }
+
+    Ok(stats_response(vec![index].into_iter()))
+}
+
+
+pub fn view_get_cat_indices(req: &mut Request) -> IronResult<Response> {
+    let ref system = get_system!(req);
+
+    let cluster_metadata = system.metadata.read().unwrap();
+
+    // Sort the indices by name
+    let mut indices = cluster_metadata.indices.values().map(|index| &**index).collect::<Vec<&Index>>();
+    indices.sort_by_key(|index| index.canonical_name().to_string());
+
+    let mut lines = Vec::new();
+    for index in indices {
+        let stats = match index.store_stats() {
+            Ok(stats) => stats,
+            Err(e) => {
+                return Ok(json_response(status::InternalServerError, json!({"message": e})));
+            }
+        };
+
+        lines.push(format!("{} {} {} {} {}\n", index.canonical_name(), stats.num_docs, stats.deleted_docs, stats.segment_count, stats.size_in_bytes));
+    }
+
+    let mut response = Response::with((status::Ok, lines.concat()));
+    response.headers.set_raw("Content-Type", vec![b"text/plain".to_vec()]);
+    Ok(response)
+}
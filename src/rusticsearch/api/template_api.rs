@@ -0,0 +1,104 @@
+use std::io::Read;
+
+use serde_json;
+use serde_json::value::ToJson;
+
+use template::{IndexTemplate, parse as parse_template};
+
+use api::persistent;
+use api::iron::prelude::*;
+use api::iron::status;
+use api::router::Router;
+use api::utils::json_response;
+
+
+fn template_to_json(name: &str, template: &IndexTemplate) -> serde_json::Value {
+    json!({
+        "name": name,
+        "index_template": template.to_json().unwrap_or(serde_json::Value::Null),
+    })
+}
+
+
+pub fn view_get_templates(req: &mut Request) -> IronResult<Response> {
+    let ref system = get_system!(req);
+
+    // Lock cluster metadata
+    let cluster_metadata = system.metadata.read().unwrap();
+
+    let templates_json = cluster_metadata.templates.iter().map(|(name, template)| template_to_json(name, template)).collect::<Vec<_>>();
+
+    return Ok(json_response(status::Ok, json!({"index_templates": templates_json})));
+}
+
+
+pub fn view_get_template(req: &mut Request) -> IronResult<Response> {
+    let ref system = get_system!(req);
+    let ref template_name = read_path_parameter!(req, "template").unwrap_or("");
+
+    // Lock cluster metadata
+    let cluster_metadata = system.metadata.read().unwrap();
+
+    match cluster_metadata.templates.get(*template_name) {
+        Some(template) => {
+            Ok(json_response(status::Ok, json!({"index_templates": [template_to_json(template_name, template)]})))
+        }
+        None => {
+            Ok(json_response(status::NotFound, json!({"message": format!("index template matching [{}] not found", template_name)})))
+        }
+    }
+}
+
+
+pub fn view_put_template(req: &mut Request) -> IronResult<Response> {
+    let ref system = get_system!(req);
+    let ref template_name = read_path_parameter!(req, "template").unwrap_or("");
+
+    // Load data from body
+    let data = match json_from_request_body!(req) {
+        Some(data) => data,
+        None => {
+            return Ok(json_response(status::BadRequest, json!({"message": "Request body required"})));
+        }
+    };
+
+    let template = match parse_template(&data) {
+        Ok(template) => template,
+        Err(e) => {
+            return Ok(json_response(status::BadRequest, json!({"message": format!("Couldn't parse index template: {}", e)})));
+        }
+    };
+
+    // Lock cluster metadata
+    let mut cluster_metadata = system.metadata.write().unwrap();
+
+    let is_updating = cluster_metadata.templates.insert(template_name.to_string(), template).is_some();
+    system.save_templates(&cluster_metadata).unwrap();
+
+    if is_updating {
+        system.log.info("[api] updated index template", b!("template" => *template_name));
+    } else {
+        system.log.info("[api] created index template", b!("template" => *template_name));
+    }
+
+    return Ok(json_response(status::Ok, json!({"acknowledged": true})));
+}
+
+
+pub fn view_delete_template(req: &mut Request) -> IronResult<Response> {
+    let ref system = get_system!(req);
+    let ref template_name = read_path_parameter!(req, "template").unwrap_or("");
+
+    // Lock cluster metadata
+    let mut cluster_metadata = system.metadata.write().unwrap();
+
+    if cluster_metadata.templates.remove(*template_name).is_none() {
+        return Ok(json_response(status::NotFound, json!({"message": format!("index template matching [{}] not found", template_name)})));
+    }
+
+    system.save_templates(&cluster_metadata).unwrap();
+
+    system.log.info("[api] deleted index template", b!("template" => *template_name));
+
+    return Ok(json_response(status::Ok, json!({"acknowledged": true})));
+}
@@ -27,8 +27,27 @@ pub fn json_response(status: status::Status, content: serde_json::Value) -> Resp
 }
 
 
+/// The status and body of a response for an index that doesn't exist
+pub fn index_not_found_error() -> (status::Status, serde_json::Value) {
+    (status::NotFound, json!({"message": "Index not found"}))
+}
+
+
 pub fn index_not_found_response() -> Response {
-    json_response(status::NotFound, json!({"message": "Index not found"}))
+    let (status, content) = index_not_found_error();
+    json_response(status, content)
+}
+
+
+/// The status and body of a response for an index that is closed
+pub fn index_closed_error(index_name: &str) -> (status::Status, serde_json::Value) {
+    (status::BadRequest, json!({"message": format!("Index [{}] is closed", index_name)}))
+}
+
+
+pub fn index_closed_response(index_name: &str) -> Response {
+    let (status, content) = index_closed_error(index_name);
+    json_response(status, content)
 }
 
 
@@ -44,7 +63,7 @@ macro_rules! get_index_or_404 {
         };
 
         match $cluster_metadata.indices.get(&index_ref) {
-            Some(index) => index,
+            Some(index) => &**index,
             None => {
                 return Ok(index_not_found_response());
             }
@@ -53,21 +72,49 @@ macro_rules! get_index_or_404 {
 }
 
 
-macro_rules! get_index_or_404_mut {
-    ($cluster_metadata: expr, $index_name: expr) => {{
+/// Finds the indices that an index name or alias refers to
+macro_rules! get_indices_or_404 {
+    ($cluster_metadata: expr, $index_selector: expr) => {{
         use api::utils::index_not_found_response;
 
-        let index_ref = match $cluster_metadata.names.find_canonical($index_name) {
-            Some(index_ref) => index_ref,
-            None => {
-                return Ok(index_not_found_response());
-            }
-        };
+        let indices = $cluster_metadata.names.find($index_selector).iter().filter_map(|index_ref| $cluster_metadata.indices.get(index_ref)).map(|index| &**index).collect::<Vec<_>>();
+
+        if indices.is_empty() {
+            return Ok(index_not_found_response());
+        }
 
-        match $cluster_metadata.indices.get_mut(&index_ref) {
-            Some(index) => index,
+        indices
+    }}
+}
+
+
+/// Finds the index for operations on a single document through an index name or alias
+macro_rules! get_single_index_or_404 {
+    ($cluster_metadata: expr, $index_selector: expr) => {{
+        use api::utils::json_response;
+
+        let mut indices = get_indices_or_404!($cluster_metadata, $index_selector);
+
+        if indices.len() > 1 {
+            return Ok(json_response(status::BadRequest, json!({"message": format!("alias [{}] has more than one index associated with it, can't execute a single index op", $index_selector)})));
+        }
+
+        indices.remove(0)
+    }}
+}
+
+
+/// Finds the index that documents sent to an index name or alias should be written to
+macro_rules! get_write_index_or_404 {
+    ($cluster_metadata: expr, $index_selector: expr) => {{
+        use api::utils::json_response;
+
+        get_indices_or_404!($cluster_metadata, $index_selector);
+
+        match $cluster_metadata.find_write_index($index_selector) {
+            Some(index) => &**index,
             None => {
-                return Ok(index_not_found_response());
+                return Ok(json_response(status::BadRequest, json!({"message": format!("no write index is defined for alias [{}]", $index_selector)})));
             }
         }
     }}
@@ -1,10 +1,12 @@
 pub mod name_registry;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, BTreeMap};
+use std::sync::Arc;
 
 use uuid::Uuid;
 
 use index::Index;
+use template::IndexTemplate;
 
 use self::name_registry::NameRegistry;
 
@@ -22,8 +24,10 @@ impl IndexRef {
 
 #[derive(Debug)]
 pub struct ClusterMetadata {
-    pub indices: HashMap<IndexRef, Index>,
+    /// Indices are shared so that long running tasks can use them without holding the lock
+    pub indices: HashMap<IndexRef, Arc<Index>>,
     pub names: NameRegistry,
+    pub templates: BTreeMap<String, IndexTemplate>,
 }
 
 
@@ -32,13 +36,51 @@ impl ClusterMetadata {
         ClusterMetadata {
             indices: HashMap::new(),
             names: NameRegistry::new(),
+            templates: BTreeMap::new(),
         }
     }
 
     pub fn insert_index(&mut self, index: Index) -> IndexRef {
         let index_ref = IndexRef(index.id().clone());
-        self.indices.insert(index_ref, index);
+        self.indices.insert(index_ref, Arc::new(index));
 
         index_ref
     }
+
+    /// Finds the index that documents sent to an index name or alias should be written to
+    ///
+    /// Aliases that point to more than one index must have one of them marked as the write index.
+    pub fn find_write_index(&self, name: &str) -> Option<&Arc<Index>> {
+        let indices = self.names.find(name).iter().filter_map(|index_ref| self.indices.get(index_ref)).collect::<Vec<_>>();
+        let num_indices = indices.len();
+
+        let mut write_indices = indices.into_iter().filter(|index| {
+            let is_write_index = index.metadata.read().unwrap().aliases.get(name).and_then(|alias| alias.is_write_index);
+            is_write_index == Some(true) || (num_indices == 1 && is_write_index != Some(false))
+        }).collect::<Vec<_>>();
+
+        if write_indices.len() == 1 {
+            write_indices.pop()
+        } else {
+            None
+        }
+    }
+
+    /// Registers the aliases saved in an index's metadata
+    ///
+    /// Fails if any of the aliases have the name of an index.
+    pub fn insert_index_aliases(&mut self, index_ref: IndexRef) -> Result<(), String> {
+        let aliases = match self.indices.get(&index_ref) {
+            Some(index) => index.metadata.read().unwrap().aliases.keys().cloned().collect::<Vec<String>>(),
+            None => return Ok(()),
+        };
+
+        for alias_name in aliases {
+            if let Err(_) = self.names.add_alias_index(alias_name.clone(), index_ref) {
+                return Err(format!("alias [{}] has the same name as an index", alias_name));
+            }
+        }
+
+        Ok(())
+    }
 }
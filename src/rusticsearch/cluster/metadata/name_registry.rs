@@ -78,6 +78,36 @@ impl NameRegistry {
         }
     }
 
+    /// Points an alias at another index, creating the alias if it doesn't exist
+    ///
+    /// Returns false if the alias already pointed to the index.
+    pub fn add_alias_index(&mut self, name: String, index_ref: IndexRef) -> Result<bool, ()> {
+        match self.names.get_mut(&name) {
+            Some(&mut Name::Alias(ref mut indices)) => {
+                if indices.contains(&index_ref) {
+                    return Ok(false);
+                }
+
+                indices.push(index_ref);
+                return Ok(true);
+            }
+            Some(&mut Name::Canonical(_)) => {
+                return Err(());
+            }
+            None => {}
+        }
+
+        self.names.insert(name, Name::Alias(vec![index_ref]));
+        Ok(true)
+    }
+
+    pub fn is_alias(&self, name: &str) -> bool {
+        match self.names.get(name) {
+            Some(&Name::Alias(_)) => true,
+            Some(&Name::Canonical(_)) | None => false,
+        }
+    }
+
     pub fn delete_alias(&mut self, name: &str, index_ref: IndexRef) -> Result<bool, ()> {
         let mut remove_alias = false;
 
@@ -175,3 +205,32 @@ impl<'a> Iterator for IndexAliasesIterator<'a> {
         }
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use cluster::metadata::IndexRef;
+
+    use super::NameRegistry;
+
+    #[test]
+    fn test_add_alias_index() {
+        let mut names = NameRegistry::new();
+        let foo = IndexRef(Uuid::new_v4());
+        let bar = IndexRef(Uuid::new_v4());
+        names.insert_canonical("foo".to_string(), foo).unwrap();
+        names.insert_canonical("bar".to_string(), bar).unwrap();
+
+        assert_eq!(names.add_alias_index("baz".to_string(), foo), Ok(true));
+        assert_eq!(names.add_alias_index("baz".to_string(), bar), Ok(true));
+        assert_eq!(names.add_alias_index("baz".to_string(), bar), Ok(false));
+        assert_eq!(names.find("baz"), vec![foo, bar]);
+        assert!(names.is_alias("baz"));
+        assert!(!names.is_alias("foo"));
+
+        // Index names can't be used as aliases
+        assert_eq!(names.add_alias_index("bar".to_string(), foo), Err(()));
+    }
+}
@@ -0,0 +1,254 @@
+//! Collectors used internally by query builders and the search API
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::time::{Duration, Instant};
+
+use kite::collectors::{Collector, DocumentMatch};
+use kite_rocksdb::RocksDBIndexReader;
+
+use aggregations::Aggregation;
+use profile::duration_to_nanos;
+use sort::{Sort, SortKey, IndexSort};
+
+
+/// Collects the id and score of every matching document
+#[derive(Debug)]
+pub struct AllDocumentsCollector {
+    matches: Vec<(u64, Option<f64>)>,
+    needs_score: bool,
+}
+
+
+impl AllDocumentsCollector {
+    pub fn new() -> AllDocumentsCollector {
+        AllDocumentsCollector {
+            matches: Vec::new(),
+            needs_score: true,
+        }
+    }
+
+    pub fn no_score(mut self) -> AllDocumentsCollector {
+        self.needs_score = false;
+        self
+    }
+
+    pub fn into_vec(self) -> Vec<(u64, Option<f64>)> {
+        self.matches
+    }
+}
+
+
+impl Collector for AllDocumentsCollector {
+    fn needs_score(&self) -> bool {
+        self.needs_score
+    }
+
+    fn collect(&mut self, doc: DocumentMatch) {
+        self.matches.push((doc.doc_id(), doc.score()));
+    }
+}
+
+
+/// A hit kept by `TopSortedCollector`
+///
+/// These are ordered by the sort, so the greatest is the one that would be returned last.
+struct SortedHit<'a> {
+    sort: &'a Sort,
+    doc_id: u64,
+    key: SortKey,
+}
+
+
+impl<'a> Ord for SortedHit<'a> {
+    fn cmp(&self, other: &SortedHit<'a>) -> Ordering {
+        self.sort.compare(&self.key, &other.key)
+    }
+}
+
+
+impl<'a> PartialOrd for SortedHit<'a> {
+    fn partial_cmp(&self, other: &SortedHit<'a>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+
+impl<'a> PartialEq for SortedHit<'a> {
+    fn eq(&self, other: &SortedHit<'a>) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+
+impl<'a> Eq for SortedHit<'a> {}
+
+
+/// Collects the id and sort key of the top matching documents by a sort
+///
+/// Like kite's `TopScoreCollector`, this keeps a heap with the hits found so far and
+/// drops the last one whenever a better hit is found.
+pub struct TopSortedCollector<'a> {
+    sort: &'a Sort,
+    index_sort: &'a IndexSort<'a>,
+    index_reader: &'a RocksDBIndexReader<'a>,
+    search_after: Option<&'a SortKey>,
+    max_docs: usize,
+    heap: BinaryHeap<SortedHit<'a>>,
+}
+
+
+impl<'a> TopSortedCollector<'a> {
+    pub fn new(sort: &'a Sort, index_sort: &'a IndexSort<'a>, index_reader: &'a RocksDBIndexReader<'a>, max_docs: usize) -> TopSortedCollector<'a> {
+        TopSortedCollector {
+            sort: sort,
+            index_sort: index_sort,
+            index_reader: index_reader,
+            search_after: None,
+            max_docs: max_docs,
+            heap: BinaryHeap::new(),
+        }
+    }
+
+    /// Only collects hits that come strictly after the given sort key
+    pub fn search_after(mut self, search_after: Option<&'a SortKey>) -> TopSortedCollector<'a> {
+        self.search_after = search_after;
+        self
+    }
+
+    /// The collected hits, in the order they should be returned
+    pub fn into_sorted_vec(self) -> Vec<(u64, SortKey)> {
+        self.heap.into_sorted_vec().into_iter().map(|hit| (hit.doc_id, hit.key)).collect()
+    }
+}
+
+
+impl<'a> Collector for TopSortedCollector<'a> {
+    fn needs_score(&self) -> bool {
+        true
+    }
+
+    fn collect(&mut self, doc: DocumentMatch) {
+        if self.max_docs == 0 {
+            return;
+        }
+
+        let key = self.index_sort.read_key(self.index_reader, doc.doc_id(), doc.score().unwrap_or(0.0));
+
+        if let Some(search_after) = self.search_after {
+            if !self.sort.is_after(&key, search_after) {
+                return;
+            }
+        }
+
+        if self.heap.len() >= self.max_docs {
+            match self.heap.peek() {
+                Some(last) if self.sort.compare(&key, &last.key) == Ordering::Less => {}
+                _ => return,
+            }
+
+            self.heap.pop();
+        }
+
+        self.heap.push(SortedHit {
+            sort: self.sort,
+            doc_id: doc.doc_id(),
+            key: key,
+        });
+    }
+}
+
+
+/// Runs aggregations over the documents passed to another collector
+pub struct AggregationsCollector<'a, C: Collector + 'a> {
+    inner: &'a mut C,
+    index_reader: &'a RocksDBIndexReader<'a>,
+    aggregations: &'a mut Vec<Box<Aggregation>>,
+
+    /// How long each aggregation has spent collecting, if they're being timed
+    times: Option<Vec<Duration>>,
+}
+
+
+impl<'a, C: Collector + 'a> AggregationsCollector<'a, C> {
+    pub fn new(inner: &'a mut C, index_reader: &'a RocksDBIndexReader<'a>, aggregations: &'a mut Vec<Box<Aggregation>>) -> AggregationsCollector<'a, C> {
+        AggregationsCollector {
+            inner: inner,
+            index_reader: index_reader,
+            aggregations: aggregations,
+            times: None,
+        }
+    }
+
+    /// Measures how long each aggregation takes, for profiling
+    pub fn timed(mut self) -> AggregationsCollector<'a, C> {
+        self.times = Some(self.aggregations.iter().map(|_| Duration::new(0, 0)).collect());
+        self
+    }
+
+    /// The time each aggregation has taken, or nothing if they aren't being timed
+    pub fn times_in_nanos(&self) -> Vec<u64> {
+        self.times.as_ref().map(|times| times.iter().map(|time| duration_to_nanos(*time)).collect()).unwrap_or(Vec::new())
+    }
+}
+
+
+impl<'a, C: Collector + 'a> Collector for AggregationsCollector<'a, C> {
+    fn needs_score(&self) -> bool {
+        self.inner.needs_score()
+    }
+
+    fn collect(&mut self, doc: DocumentMatch) {
+        match self.times {
+            Some(ref mut times) => {
+                for (aggregation, time) in self.aggregations.iter_mut().zip(times.iter_mut()) {
+                    let start = Instant::now();
+                    aggregation.collect(self.index_reader, doc.doc_id());
+                    *time += start.elapsed();
+                }
+            }
+            None => {
+                for aggregation in self.aggregations.iter_mut() {
+                    aggregation.collect(self.index_reader, doc.doc_id());
+                }
+            }
+        }
+
+        self.inner.collect(doc);
+    }
+}
+
+
+/// Counts the documents passed to another collector
+///
+/// Used to find the total number of hits when the inner collector only keeps the top ones.
+pub struct CountingCollector<'a, C: Collector + 'a> {
+    inner: &'a mut C,
+    count: u64,
+}
+
+
+impl<'a, C: Collector + 'a> CountingCollector<'a, C> {
+    pub fn new(inner: &'a mut C) -> CountingCollector<'a, C> {
+        CountingCollector {
+            inner: inner,
+            count: 0,
+        }
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+}
+
+
+impl<'a, C: Collector + 'a> Collector for CountingCollector<'a, C> {
+    fn needs_score(&self) -> bool {
+        self.inner.needs_score()
+    }
+
+    fn collect(&mut self, doc: DocumentMatch) {
+        self.count += 1;
+        self.inner.collect(doc);
+    }
+}
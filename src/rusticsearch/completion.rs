@@ -0,0 +1,576 @@
+//! In-memory indices for "completion" fields
+//!
+//! Completion inputs are kept in a prefix tree, separately from the index store. Each node
+//! of the tree knows the highest weight of the inputs below it, so the top completions of
+//! a prefix are found by always following the node that could hold the heaviest input,
+//! without looking at every input that starts with the prefix.
+//!
+//! The tree isn't persisted. It's rebuilt from the documents' source when an index is
+//! opened.
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet};
+
+use serde_json::{self, Value as Json};
+
+use analysis::AnalyzerSpec;
+use mapping::{Mapping, MappingProperty, FieldMapping, FieldType};
+
+
+/// The default for the "max_input_length" setting of completion fields
+pub const DEFAULT_MAX_INPUT_LENGTH: usize = 50;
+
+
+#[derive(Debug, PartialEq)]
+pub struct CompletionValueError;
+
+
+/// An input of a completion field
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompletionInput {
+    pub input: String,
+    pub weight: u64,
+
+    /// The values of each of the field's contexts
+    pub contexts: HashMap<String, Vec<String>>,
+}
+
+
+fn parse_inputs(json: &Json) -> Result<Vec<String>, CompletionValueError> {
+    match *json {
+        Json::String(ref input) => Ok(vec![input.clone()]),
+        Json::Array(ref array) => {
+            let mut inputs = Vec::with_capacity(array.len());
+            for input in array.iter() {
+                inputs.push(try!(input.as_str().ok_or(CompletionValueError)).to_string());
+            }
+            Ok(inputs)
+        }
+        _ => Err(CompletionValueError),
+    }
+}
+
+
+fn parse_value_object(json: &Json) -> Result<Vec<CompletionInput>, CompletionValueError> {
+    let object = try!(json.as_object().ok_or(CompletionValueError));
+    let mut inputs = Vec::new();
+    let mut weight = 1;
+    let mut contexts = HashMap::new();
+
+    for (key, value) in object.iter() {
+        match key.as_ref() {
+            "input" => inputs = try!(parse_inputs(value)),
+            "weight" => {
+                // Weights may be given as strings
+                weight = match *value {
+                    Json::String(ref weight) => try!(weight.parse().map_err(|_| CompletionValueError)),
+                    ref value => try!(value.as_u64().ok_or(CompletionValueError)),
+                };
+            }
+            "contexts" => {
+                let contexts_object = try!(value.as_object().ok_or(CompletionValueError));
+                for (name, values) in contexts_object.iter() {
+                    contexts.insert(name.clone(), try!(parse_inputs(values)));
+                }
+            }
+            _ => return Err(CompletionValueError),
+        }
+    }
+
+    Ok(inputs.into_iter().map(|input| {
+        CompletionInput {
+            input: input,
+            weight: weight,
+            contexts: contexts.clone(),
+        }
+    }).collect())
+}
+
+
+/// Parses the value of a completion field in a document
+///
+/// This may be an input, an array of inputs or an object with "input", "weight" and
+/// "contexts". Arrays of these objects are allowed too.
+pub fn parse_value(json: &Json) -> Result<Vec<CompletionInput>, CompletionValueError> {
+    match *json {
+        Json::Object(_) => parse_value_object(json),
+        Json::Array(ref array) if array.iter().any(|item| item.is_object()) => {
+            let mut inputs = Vec::new();
+            for item in array.iter() {
+                inputs.extend(try!(parse_value(item)));
+            }
+            Ok(inputs)
+        }
+        _ => {
+            Ok(try!(parse_inputs(json)).into_iter().map(|input| {
+                CompletionInput {
+                    input: input,
+                    weight: 1,
+                    contexts: HashMap::new(),
+                }
+            }).collect())
+        }
+    }
+}
+
+
+/// Converts an input or a prefix into the form that's kept in the tree
+///
+/// The terms the analyzer produces are joined with spaces, so "The Beatles" and
+/// "the beatles" are the same with the standard analyzer.
+pub fn analyse_key(input: &str, analyzer: Option<&AnalyzerSpec>, max_length: usize) -> String {
+    let key = match analyzer {
+        Some(analyzer) => {
+            analyzer.initialise(input)
+                .filter_map(|token| String::from_utf8(token.term.as_bytes().to_vec()).ok())
+                .collect::<Vec<_>>()
+                .join(" ")
+        }
+        None => input.to_string(),
+    };
+
+    key.chars().take(max_length).collect()
+}
+
+
+/// The inputs of each completion field in a document, with the keys they're kept under
+pub type DocumentInputs = HashMap<String, Vec<(String, CompletionInput)>>;
+
+
+fn collect_field_inputs(field_name: &str, field_mapping: &FieldMapping, value: &Json, inputs: &mut DocumentInputs) {
+    if field_mapping.data_type == FieldType::Completion {
+        // Malformed values are rejected when the document is indexed
+        if let Ok(field_inputs) = parse_value(value) {
+            let field_inputs_with_keys = inputs.entry(field_name.to_string()).or_insert_with(Vec::new);
+            for input in field_inputs {
+                let key = analyse_key(&input.input, field_mapping.index_analyzer(), field_mapping.max_input_length);
+                field_inputs_with_keys.push((key, input));
+            }
+        }
+    }
+
+    // Completion fields may be multi-fields of other fields
+    for (name, multi_field) in field_mapping.multi_fields.iter() {
+        collect_field_inputs(&format!("{}.{}", field_name, name), multi_field, value, inputs);
+    }
+}
+
+
+fn collect_inputs(properties: &HashMap<String, MappingProperty>, prefix: &str, data: &serde_json::Map<String, Json>, inputs: &mut DocumentInputs) {
+    for (name, value) in data.iter() {
+        let field_name = if prefix.is_empty() {
+            name.clone()
+        } else {
+            format!("{}.{}", prefix, name)
+        };
+
+        match properties.get(name) {
+            Some(&MappingProperty::Field(ref field_mapping)) => collect_field_inputs(&field_name, field_mapping, value, inputs),
+            Some(&MappingProperty::NestedMapping(ref object_mapping)) => {
+                match *value {
+                    Json::Object(ref object) => collect_inputs(&object_mapping.properties, &field_name, object, inputs),
+                    Json::Array(ref array) => {
+                        for object in array.iter().filter_map(|item| item.as_object()) {
+                            collect_inputs(&object_mapping.properties, &field_name, object, inputs);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            None => {}
+        }
+    }
+}
+
+
+/// Finds the inputs of the completion fields in a document
+pub fn document_inputs(mapping: &Mapping, data: &serde_json::Map<String, Json>) -> DocumentInputs {
+    let mut inputs = HashMap::new();
+    collect_inputs(&mapping.properties, "", data, &mut inputs);
+    inputs
+}
+
+
+/// Restricts completions to inputs that have one of the given values for a context
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContextQuery {
+    pub name: String,
+
+    /// The values to match, with how much each one boosts the completion's weight
+    pub values: Vec<(String, f64)>,
+}
+
+
+#[derive(Debug, Clone)]
+struct Entry {
+    doc_key: String,
+    input: CompletionInput,
+}
+
+
+impl Entry {
+    /// Returns the entry's score for the context queries, or None if it doesn't match them
+    fn score(&self, contexts: &[ContextQuery]) -> Option<f64> {
+        let mut score = self.input.weight as f64;
+
+        for context in contexts.iter() {
+            let values = match self.input.contexts.get(&context.name) {
+                Some(values) => values,
+                None => return None,
+            };
+
+            let boost = context.values.iter()
+                .filter(|&&(ref value, _)| values.contains(value))
+                .map(|&(_, boost)| boost)
+                .fold(None, |max: Option<f64>, boost| Some(max.map_or(boost, |max| max.max(boost))));
+
+            match boost {
+                Some(boost) => score *= boost,
+                None => return None,
+            }
+        }
+
+        Some(score)
+    }
+}
+
+
+#[derive(Debug, Default)]
+struct Node {
+    children: BTreeMap<char, usize>,
+
+    /// The entries whose key ends at this node
+    entries: Vec<Entry>,
+
+    /// The highest weight of the entries at or below this node
+    max_weight: u64,
+    parent: Option<usize>,
+}
+
+
+/// An item on the queue of the top completions search
+#[derive(Debug)]
+enum SearchItem<'a> {
+    /// A node, scored with the highest score any of its entries could have
+    Node(usize, f64),
+    Entry(&'a Entry, f64),
+}
+
+
+impl<'a> SearchItem<'a> {
+    fn score(&self) -> f64 {
+        match *self {
+            SearchItem::Node(_, score) | SearchItem::Entry(_, score) => score,
+        }
+    }
+}
+
+
+impl<'a> PartialEq for SearchItem<'a> {
+    fn eq(&self, other: &SearchItem<'a>) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+
+impl<'a> Eq for SearchItem<'a> {}
+
+
+impl<'a> PartialOrd for SearchItem<'a> {
+    fn partial_cmp(&self, other: &SearchItem<'a>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+
+impl<'a> Ord for SearchItem<'a> {
+    fn cmp(&self, other: &SearchItem<'a>) -> Ordering {
+        // Nodes come before entries with the same score so every entry with that score is
+        // found before any are returned. Entries with the same score are ordered by input
+        let tie_break = match (self, other) {
+            (&SearchItem::Node(..), &SearchItem::Entry(..)) => Ordering::Greater,
+            (&SearchItem::Entry(..), &SearchItem::Node(..)) => Ordering::Less,
+            (&SearchItem::Entry(a, _), &SearchItem::Entry(b, _)) => b.input.input.cmp(&a.input.input),
+            (&SearchItem::Node(a, _), &SearchItem::Node(b, _)) => b.cmp(&a),
+        };
+
+        match self.score().partial_cmp(&other.score()).unwrap_or(Ordering::Equal) {
+            Ordering::Equal => tie_break,
+            ordering => ordering,
+        }
+    }
+}
+
+
+/// A completion found for a prefix
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompletionMatch {
+    pub doc_key: String,
+    pub text: String,
+    pub score: f64,
+}
+
+
+/// The inputs of one completion field
+#[derive(Debug)]
+pub struct CompletionIndex {
+    /// The nodes of the tree, the first one is the root
+    nodes: Vec<Node>,
+
+    /// The nodes that hold the entries of each document
+    documents: HashMap<String, Vec<usize>>,
+}
+
+
+impl Default for CompletionIndex {
+    fn default() -> CompletionIndex {
+        CompletionIndex {
+            nodes: vec![Node::default()],
+            documents: HashMap::new(),
+        }
+    }
+}
+
+
+impl CompletionIndex {
+    pub fn new() -> CompletionIndex {
+        CompletionIndex::default()
+    }
+
+    /// The number of documents that have inputs in the index
+    pub fn num_documents(&self) -> usize {
+        self.documents.len()
+    }
+
+    /// Finds the node for a key, returning None if there isn't one
+    fn find_node(&self, key: &str) -> Option<usize> {
+        let mut node = 0;
+        for c in key.chars() {
+            node = match self.nodes[node].children.get(&c) {
+                Some(&child) => child,
+                None => return None,
+            };
+        }
+
+        Some(node)
+    }
+
+    /// Recalculates the highest weight of a node and each of its parents
+    fn update_max_weights(&mut self, node: usize) {
+        let mut node = Some(node);
+        while let Some(current) = node {
+            let max_weight = {
+                let current_node = &self.nodes[current];
+                let entries_max = current_node.entries.iter().map(|entry| entry.input.weight).max().unwrap_or(0);
+                let children_max = current_node.children.values().map(|&child| self.nodes[child].max_weight).max().unwrap_or(0);
+                if entries_max > children_max { entries_max } else { children_max }
+            };
+
+            self.nodes[current].max_weight = max_weight;
+            node = self.nodes[current].parent;
+        }
+    }
+
+    /// Adds an input of a document under the given key
+    pub fn insert(&mut self, doc_key: &str, key: &str, input: CompletionInput) {
+        let mut node = 0;
+        for c in key.chars() {
+            let child = self.nodes[node].children.get(&c).cloned();
+            node = match child {
+                Some(child) => child,
+                None => {
+                    let child = self.nodes.len();
+                    self.nodes.push(Node {
+                        parent: Some(node),
+                        ..Node::default()
+                    });
+                    self.nodes[node].children.insert(c, child);
+                    child
+                }
+            };
+        }
+
+        self.nodes[node].entries.push(Entry {
+            doc_key: doc_key.to_string(),
+            input: input,
+        });
+        self.documents.entry(doc_key.to_string()).or_insert_with(Vec::new).push(node);
+        self.update_max_weights(node);
+    }
+
+    /// Removes all the inputs of a document
+    ///
+    /// Returns false if the document didn't have any.
+    pub fn remove_document(&mut self, doc_key: &str) -> bool {
+        let nodes = match self.documents.remove(doc_key) {
+            Some(nodes) => nodes,
+            None => return false,
+        };
+
+        for node in nodes {
+            self.nodes[node].entries.retain(|entry| entry.doc_key != doc_key);
+            self.update_max_weights(node);
+        }
+
+        true
+    }
+
+    /// Finds the top completions of a prefix
+    ///
+    /// Each document is only returned once, with its best input.
+    pub fn suggest(&self, prefix: &str, size: usize, contexts: &[ContextQuery]) -> Vec<CompletionMatch> {
+        let start = match self.find_node(prefix) {
+            Some(start) => start,
+            None => return Vec::new(),
+        };
+
+        // Boosts can raise a weight, so nodes are scored with the largest boost
+        let mut max_boost = 1.0;
+        for context in contexts.iter() {
+            max_boost *= context.values.iter().map(|&(_, boost)| boost).fold(1.0, f64::max);
+        }
+
+        let mut queue = BinaryHeap::new();
+        queue.push(SearchItem::Node(start, self.nodes[start].max_weight as f64 * max_boost));
+
+        let mut matches = Vec::new();
+        let mut seen_documents = HashSet::new();
+        while let Some(item) = queue.pop() {
+            match item {
+                SearchItem::Node(node, _) => {
+                    for entry in self.nodes[node].entries.iter() {
+                        if let Some(score) = entry.score(contexts) {
+                            queue.push(SearchItem::Entry(entry, score));
+                        }
+                    }
+
+                    for &child in self.nodes[node].children.values() {
+                        queue.push(SearchItem::Node(child, self.nodes[child].max_weight as f64 * max_boost));
+                    }
+                }
+                SearchItem::Entry(entry, score) => {
+                    if !seen_documents.insert(&entry.doc_key) {
+                        continue;
+                    }
+
+                    matches.push(CompletionMatch {
+                        doc_key: entry.doc_key.clone(),
+                        text: entry.input.input.clone(),
+                        score: score,
+                    });
+
+                    if matches.len() >= size {
+                        break;
+                    }
+                }
+            }
+        }
+
+        matches
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use analysis::AnalyzerSpec;
+    use analysis::tokenizers::TokenizerSpec;
+    use analysis::filters::FilterSpec;
+
+    use super::{parse_value, analyse_key, CompletionIndex, CompletionInput, ContextQuery, CompletionValueError};
+
+    fn input(input: &str, weight: u64) -> CompletionInput {
+        CompletionInput {
+            input: input.to_string(),
+            weight: weight,
+            contexts: HashMap::new(),
+        }
+    }
+
+    fn make_index() -> CompletionIndex {
+        let mut index = CompletionIndex::new();
+        index.insert("1", "nirvana", input("Nirvana", 34));
+        index.insert("1", "nevermind", input("Nevermind", 10));
+        index.insert("2", "nine inch nails", input("Nine Inch Nails", 20));
+        index.insert("3", "nickelback", input("Nickelback", 1));
+        index.insert("4", "metallica", input("Metallica", 50));
+        index
+    }
+
+    fn texts(matches: Vec<super::CompletionMatch>) -> Vec<String> {
+        matches.into_iter().map(|completion| completion.text).collect()
+    }
+
+    #[test]
+    fn test_parse_value() {
+        assert_eq!(parse_value(&json!("Nirvana")), Ok(vec![input("Nirvana", 1)]));
+        assert_eq!(parse_value(&json!({"input": ["Nevermind", "Nirvana"], "weight": 34})), Ok(vec![
+            input("Nevermind", 34),
+            input("Nirvana", 34),
+        ]));
+        assert_eq!(parse_value(&json!([{"input": "Nevermind", "weight": "10"}, {"input": "Nirvana"}])), Ok(vec![
+            input("Nevermind", 10),
+            input("Nirvana", 1),
+        ]));
+        assert_eq!(parse_value(&json!({"input": "Nirvana", "weight": -1})), Err(CompletionValueError));
+    }
+
+    #[test]
+    fn test_analyse_key() {
+        let analyzer = AnalyzerSpec {
+            char_filters: vec![],
+            tokenizer: TokenizerSpec::Standard,
+            filters: vec![
+                FilterSpec::Lowercase,
+            ],
+        };
+
+        assert_eq!(analyse_key("Nine Inch  Nails!", Some(&analyzer), 50), "nine inch nails");
+        assert_eq!(analyse_key("Nine Inch Nails", None, 4), "Nine");
+    }
+
+    #[test]
+    fn test_suggest() {
+        let index = make_index();
+
+        assert_eq!(texts(index.suggest("n", 10, &[])), vec!["Nirvana", "Nine Inch Nails", "Nickelback"]);
+        assert_eq!(texts(index.suggest("n", 2, &[])), vec!["Nirvana", "Nine Inch Nails"]);
+        assert_eq!(texts(index.suggest("ne", 10, &[])), vec!["Nevermind"]);
+        assert_eq!(texts(index.suggest("x", 10, &[])), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_remove_document() {
+        let mut index = make_index();
+
+        assert!(index.remove_document("1"));
+        assert!(!index.remove_document("1"));
+        assert_eq!(texts(index.suggest("n", 10, &[])), vec!["Nine Inch Nails", "Nickelback"]);
+        assert_eq!(index.num_documents(), 3);
+    }
+
+    #[test]
+    fn test_suggest_with_contexts() {
+        let mut index = CompletionIndex::new();
+        let mut rock = input("Nirvana", 10);
+        rock.contexts.insert("genre".to_string(), vec!["rock".to_string()]);
+        let mut pop = input("Nina Simone", 5);
+        pop.contexts.insert("genre".to_string(), vec!["jazz".to_string()]);
+        index.insert("1", "nirvana", rock);
+        index.insert("2", "nina simone", pop);
+        index.insert("3", "nickelback", input("Nickelback", 100));
+
+        let contexts = vec![ContextQuery {
+            name: "genre".to_string(),
+            values: vec![("rock".to_string(), 1.0), ("jazz".to_string(), 3.0)],
+        }];
+
+        let matches = index.suggest("n", 10, &contexts);
+        assert_eq!(matches.iter().map(|completion| (completion.text.as_ref(), completion.score)).collect::<Vec<_>>(), vec![
+            ("Nina Simone", 15.0),
+            ("Nirvana", 10.0),
+        ]);
+    }
+}
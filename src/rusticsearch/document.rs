@@ -1,9 +1,12 @@
+use std::fmt;
+use std::mem;
 use std::collections::HashMap;
 
 use serde_json;
 use kite::Document;
+use kite::document::FieldValue;
 
-use mapping::{Mapping, MappingProperty, FieldValueError};
+use mapping::{Mapping, MappingProperty, FieldMapping, FieldType, DynamicMapping, FieldValueError, join_parent_field_name};
 
 
 #[derive(Debug)]
@@ -26,77 +29,284 @@ pub enum PrepareDocumentError {
 }
 
 
+impl fmt::Display for PrepareDocumentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PrepareDocumentError::FieldDoesntExist{ref field_name} => {
+                write!(f, "mapping set to strict, dynamic introduction of [{}] is not allowed", field_name)
+            }
+            PrepareDocumentError::FieldValueError{ref field_name, ref value, ..} => {
+                write!(f, "failed to parse [{}] with value {}", field_name, value)
+            }
+        }
+    }
+}
+
+
+fn merge_values(existing: &mut serde_json::Value, value: serde_json::Value) {
+    let mut items = match mem::replace(existing, serde_json::Value::Null) {
+        serde_json::Value::Array(items) => items,
+        existing => vec![existing],
+    };
+
+    match value {
+        serde_json::Value::Array(more_items) => items.extend(more_items),
+        value => items.push(value),
+    }
+
+    *existing = serde_json::Value::Array(items);
+}
+
+
+/// Adds a value to be indexed into a field and each of the field's multi-fields
+fn add_field_value<'a>(field_name: String, field_mapping: &'a FieldMapping, value: &serde_json::Value, fields: &mut Vec<(String, &'a FieldMapping, serde_json::Value)>) {
+    for (multi_field_name, multi_field_mapping) in field_mapping.multi_fields.iter() {
+        add_field_value(format!("{}.{}", field_name, multi_field_name), multi_field_mapping, value, fields);
+    }
+
+    match fields.iter().position(|&(ref existing_name, _, _)| *existing_name == field_name) {
+        Some(position) => merge_values(&mut fields[position].2, value.clone()),
+        None => fields.push((field_name, field_mapping, value.clone())),
+    }
+}
+
+
+/// Records that some of a field's values weren't indexed
+fn add_ignored_field(field_name: &str, ignored_fields: &mut Vec<String>) {
+    if !ignored_fields.iter().any(|ignored_field| ignored_field == field_name) {
+        ignored_fields.push(field_name.to_string());
+    }
+}
+
+
+/// Pairs each value in a document with the mapping of its field
+///
+/// Fields inside objects are given "parent.child" names. The values of fields in an array
+/// of objects are merged into an array so they're all indexed into the same field.
+fn flatten_fields<'a>(properties: &'a HashMap<String, MappingProperty>, dynamic: DynamicMapping, prefix: &str, data: &serde_json::Map<String, serde_json::Value>, fields: &mut Vec<(String, &'a FieldMapping, serde_json::Value)>) -> Result<(), PrepareDocumentError> {
+    for (name, value) in data {
+        let field_name = if prefix.is_empty() {
+            name.clone()
+        } else {
+            format!("{}.{}", prefix, name)
+        };
+
+        match properties.get(name) {
+            Some(&MappingProperty::Field(ref field_mapping)) => {
+                // Nulls are replaced with the field's "null_value". If it doesn't have one, they're
+                // treated like a missing field
+                if let Some(value) = field_mapping.replace_nulls(value) {
+                    add_field_value(field_name, field_mapping, &value, fields);
+                }
+            }
+            Some(&MappingProperty::NestedMapping(ref object_mapping)) => {
+                match *value {
+                    serde_json::Value::Object(ref object) => {
+                        try!(flatten_fields(&object_mapping.properties, dynamic, &field_name, object, fields));
+                    }
+                    serde_json::Value::Null => {}
+                    serde_json::Value::Array(ref array) => {
+                        for item in array {
+                            match *item {
+                                serde_json::Value::Object(ref object) => {
+                                    try!(flatten_fields(&object_mapping.properties, dynamic, &field_name, object, fields));
+                                }
+                                serde_json::Value::Null => {}
+                                _ => {
+                                    return Err(PrepareDocumentError::FieldValueError {
+                                        field_name: field_name,
+                                        value: item.clone(),
+                                        error: FieldValueError,
+                                    });
+                                }
+                            }
+                        }
+                    }
+                    _ => {
+                        return Err(PrepareDocumentError::FieldValueError {
+                            field_name: field_name,
+                            value: value.clone(),
+                            error: FieldValueError,
+                        });
+                    }
+                }
+            }
+            None => {
+                // No mapping found. Dynamic mappings should have had the field added
+                // already, so this can only be a value that doesn't need indexing
+                if dynamic == DynamicMapping::Strict && *value != serde_json::Value::Null {
+                    return Err(PrepareDocumentError::FieldDoesntExist {
+                        field_name: field_name,
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+
 impl<'a> DocumentSource<'a> {
     pub fn prepare(&self, mapping: &Mapping) -> Result<Document, PrepareDocumentError> {
         let mut indexed_fields = HashMap::new();
         let mut stored_fields = HashMap::new();
         let mut all_field_strings: Vec<String> = Vec::new();
+        let mut ignored_fields: Vec<String> = Vec::new();
 
-        for (field_name, field_value) in self.data {
-            if *field_value == serde_json::Value::Null {
-                // Treat null like a missing field
-                continue;
+        // Find the mapping of each field. Fields inside objects are flattened into "parent.child" fields
+        let mut fields = Vec::new();
+        try!(flatten_fields(&mapping.properties, mapping.dynamic, "", self.data, &mut fields));
+
+        // Copy values into the fields named in each field's "copy_to" setting
+        let mut copied_values = Vec::new();
+        for &(_, field_mapping, ref field_value) in fields.iter() {
+            for target_field_name in field_mapping.copy_to.iter() {
+                if let Some(target_field_mapping) = mapping.get_field(target_field_name) {
+                    copied_values.push((target_field_name.clone(), target_field_mapping, field_value.clone()));
+                }
             }
+        }
 
-            match mapping.properties.get(field_name) {
-                Some(&MappingProperty::Field(ref field_mapping)) => {
-                    if field_mapping.is_indexed {
-                        let value = field_mapping.process_value_for_index(field_value);
-
-                        match value {
-                            Ok(Some(value)) => {
-                                // Copy the field's value into the _all field
-                                if field_mapping.is_in_all {
-                                    if let serde_json::Value::String(ref string) = *field_value {
-                                        all_field_strings.push(string.clone());
-                                    }
-                                }
+        for (target_field_name, target_field_mapping, field_value) in copied_values {
+            add_field_value(target_field_name, target_field_mapping, &field_value, &mut fields);
+        }
 
-                                // Insert the field
-                                indexed_fields.insert(field_mapping.index_ref.unwrap(), value);
-                            }
-                            Ok(None) => {}
-                            Err(error) => {
-                                return Err(PrepareDocumentError::FieldValueError {
-                                    field_name: field_name.clone(),
-                                    value: field_value.clone(),
-                                    error: error,
-                                });
-                            }
+        for (field_name, field_mapping, field_value) in fields {
+            let field_name = &field_name;
+            let field_value = &field_value;
+
+            if field_mapping.data_type == FieldType::Join {
+                let (relation, parent_keys) = match field_mapping.process_join_value(field_value, self.key) {
+                    Ok(result) => result,
+                    Err(error) => {
+                        return Err(PrepareDocumentError::FieldValueError {
+                            field_name: field_name.clone(),
+                            value: field_value.clone(),
+                            error: error,
+                        });
+                    }
+                };
+
+                // Index the relation name
+                let relation_json = serde_json::Value::String(relation);
+                if let Ok(Some(value)) = field_mapping.process_value_for_index(&relation_json) {
+                    indexed_fields.insert(field_mapping.index_ref.unwrap(), value);
+                }
+
+                // Index and store the parent keys into the hidden parent key fields
+                for (parent_relation, parent_key) in parent_keys {
+                    let parent_field_name = join_parent_field_name(field_name, &parent_relation);
+                    if let Some(&MappingProperty::Field(ref parent_field_mapping)) = mapping.properties.get(&parent_field_name) {
+                        let parent_key_json = serde_json::Value::String(parent_key);
+
+                        if let Ok(Some(value)) = parent_field_mapping.process_value_for_index(&parent_key_json) {
+                            indexed_fields.insert(parent_field_mapping.index_ref.unwrap(), value);
+                        }
+
+                        if let Ok(Some(value)) = parent_field_mapping.process_value_for_store(&parent_key_json) {
+                            stored_fields.insert(parent_field_mapping.index_ref.unwrap(), value);
+                        }
+                    }
+                }
+            } else {
+                // Strings longer than "ignore_above" are left out
+                let field_value = match field_mapping.remove_long_strings(field_value) {
+                    Some(value) => {
+                        if value != *field_value {
+                            add_ignored_field(field_name, &mut ignored_fields);
                         }
+
+                        value
+                    }
+                    None => {
+                        add_ignored_field(field_name, &mut ignored_fields);
+                        continue;
                     }
+                };
 
-                    if field_mapping.is_stored {
-                        let value = field_mapping.process_value_for_store(field_value);
+                let index_value = if field_mapping.is_indexed {
+                    field_mapping.process_value_for_index(&field_value)
+                } else {
+                    Ok(None)
+                };
 
-                        match value {
-                            Ok(Some(value)) => {
-                                // Insert the field
-                                stored_fields.insert(field_mapping.index_ref.unwrap(), value);
-                            }
-                            Ok(None) => {}
-                            Err(error) => {
-                                return Err(PrepareDocumentError::FieldValueError {
-                                    field_name: field_name.clone(),
-                                    value: field_value.clone(),
-                                    error: error,
-                                });
-                            }
+                let store_value = if field_mapping.is_stored || field_mapping.has_doc_values {
+                    field_mapping.process_value_for_store(&field_value)
+                } else {
+                    Ok(None)
+                };
+
+                let (index_value, store_value) = match (index_value, store_value) {
+                    (Ok(index_value), Ok(store_value)) => (index_value, store_value),
+                    (Err(error), _) | (_, Err(error)) => {
+                        // Fields with "ignore_malformed" set are skipped so the rest of the document can still be indexed
+                        if field_mapping.ignore_malformed {
+                            add_ignored_field(field_name, &mut ignored_fields);
+                            continue;
+                        }
+
+                        return Err(PrepareDocumentError::FieldValueError {
+                            field_name: field_name.clone(),
+                            value: field_value.clone(),
+                            error: error,
+                        });
+                    }
+                };
+
+                if let Some(value) = index_value {
+                    // Copy the field's value into the _all field
+                    if field_mapping.is_in_all {
+                        if let serde_json::Value::String(ref string) = field_value {
+                            all_field_strings.push(string.clone());
                         }
                     }
+
+                    // Insert the field
+                    indexed_fields.insert(field_mapping.index_ref.unwrap(), value);
                 }
-                Some(&MappingProperty::NestedMapping(ref _nested_mapping)) => {
-                    // TODO
+
+                if let Some(value) = store_value {
+                    stored_fields.insert(field_mapping.index_ref.unwrap(), value);
                 }
-                None => {
-                    // No mapping found
-                    return Err(PrepareDocumentError::FieldDoesntExist {
-                        field_name: field_name.clone(),
-                    });
+            }
+        }
+
+        // Insert _id field
+        if let Some(&MappingProperty::Field(ref field_mapping)) = mapping.properties.get("_id") {
+            let key_json = serde_json::Value::String(self.key.to_string());
+
+            if let Ok(Some(value)) = field_mapping.process_value_for_index(&key_json) {
+                indexed_fields.insert(field_mapping.index_ref.unwrap(), value);
+            }
+
+            if let Ok(Some(value)) = field_mapping.process_value_for_store(&key_json) {
+                stored_fields.insert(field_mapping.index_ref.unwrap(), value);
+            }
+        }
+
+        // Insert _ignored field
+        if !ignored_fields.is_empty() {
+            if let Some(&MappingProperty::Field(ref field_mapping)) = mapping.properties.get("_ignored") {
+                let ignored_json = json!(ignored_fields);
+
+                if let Ok(Some(value)) = field_mapping.process_value_for_index(&ignored_json) {
+                    indexed_fields.insert(field_mapping.index_ref.unwrap(), value);
+                }
+
+                if let Ok(Some(value)) = field_mapping.process_value_for_store(&ignored_json) {
+                    stored_fields.insert(field_mapping.index_ref.unwrap(), value);
                 }
             }
         }
 
+        // Insert _source field
+        if let Some(&MappingProperty::Field(ref field_mapping)) = mapping.properties.get("_source") {
+            if let Ok(source) = serde_json::to_string(self.data) {
+                stored_fields.insert(field_mapping.index_ref.unwrap(), FieldValue::String(source));
+            }
+        }
+
         // Insert _all field
         if let Some(property) = mapping.properties.get("_all") {
             if let MappingProperty::Field(ref field_mapping) = *property {
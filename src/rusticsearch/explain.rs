@@ -0,0 +1,582 @@
+//! Explains how the score of a hit was worked out, for searches with "explain" set
+//!
+//! The store only gives back the final score of each hit, so the explanation is worked
+//! out again by walking the query and scoring each term with kite's similarity model.
+//! Statistics for the whole index (which documents each term matches and the total length
+//! of each field) are gathered once for each search. Term frequencies and field lengths
+//! come from analysing the hit's source with the field's index analyzer, the same way the
+//! highlighter finds matches.
+
+use std::collections::{HashMap, HashSet};
+
+use serde_json::{self, Value as Json};
+use kite::{Term, Query, TermScorer, TermSelector};
+use kite::document::{DocRef, FieldValue};
+use kite::schema::FieldRef;
+use kite::similarity::SimilarityModel;
+use kite::collectors::total_count::TotalCountCollector;
+use kite_rocksdb::RocksDBIndexReader;
+
+use analysis::AnalyzerSpec;
+use collectors::AllDocumentsCollector;
+use highlight::get_source_values;
+use index::metadata::IndexMetadata;
+use mapping::FieldType;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Explanation {
+    pub value: f64,
+    pub description: String,
+    pub details: Vec<Explanation>,
+}
+
+
+impl Explanation {
+    pub fn new(value: f64, description: String, details: Vec<Explanation>) -> Explanation {
+        Explanation {
+            value: value,
+            description: description,
+            details: details,
+        }
+    }
+
+    fn leaf(value: f64, description: &str) -> Explanation {
+        Explanation::new(value, description.to_string(), Vec::new())
+    }
+
+    /// The explanation of a document that doesn't match the query
+    pub fn no_match() -> Explanation {
+        Explanation::leaf(0.0, "no matching term")
+    }
+
+    pub fn to_json(&self) -> Json {
+        json!({
+            "value": self.value,
+            "description": self.description,
+            "details": self.details.iter().map(|detail| detail.to_json()).collect::<Vec<_>>(),
+        })
+    }
+}
+
+
+/// Explains the inverse document frequency of a term, the same way kite works it out
+pub fn explain_idf(doc_freq: u64, doc_count: u64) -> Explanation {
+    let idf = ((doc_count as f64 + 1.0) / (doc_freq as f64 + 1.0)).ln() + 1.0;
+
+    Explanation::new(idf, "idf, computed as ln((docCount + 1) / (docFreq + 1)) + 1 from:".to_string(), vec![
+        Explanation::leaf(doc_freq as f64, "docFreq"),
+        Explanation::leaf(doc_count as f64, "docCount"),
+    ])
+}
+
+
+/// Explains the frequency of a term in a document, the same way kite works it out
+pub fn explain_tf(freq: u64) -> Explanation {
+    Explanation::new((freq as f64 + 1.0).ln() + 1.0, "tf, computed as ln(freq + 1) + 1 from:".to_string(), vec![
+        Explanation::leaf(freq as f64, "termFreq"),
+    ])
+}
+
+
+/// Explains the score of a term in one document
+///
+/// The score is worked out by kite's similarity model so it's the same as the score the
+/// store gave the hit. The details break it down the same way. Field lengths are counted
+/// in tokens.
+pub fn explain_term_score(similarity_model: &SimilarityModel, boost: f64, freq: u64, field_length: u64, total_tokens: u64, doc_freq: u64, doc_count: u64) -> Explanation {
+    let score = boost * similarity_model.score(freq as u32, field_length as f64, total_tokens, doc_count, doc_freq);
+    let idf = explain_idf(doc_freq, doc_count);
+    let tf = explain_tf(freq);
+
+    match *similarity_model {
+        SimilarityModel::TfIdf => {
+            Explanation::new(score, format!("score(freq={}) [TFIDF], product of:", freq), vec![
+                Explanation::leaf(boost, "boost"),
+                idf,
+                tf,
+            ])
+        }
+        SimilarityModel::Bm25{k1, b} => {
+            let avg_field_length = (total_tokens as f64 + 1.0) / (doc_count as f64 + 1.0);
+            let norm = k1 * ((1.0 - b) + b * (field_length as f64).sqrt() / avg_field_length.sqrt());
+            let tf_norm = (k1 + 1.0) * (tf.value / (tf.value + norm + 1.0));
+
+            Explanation::new(score, format!("score(freq={}) [BM25], product of:", freq), vec![
+                Explanation::leaf(boost, "boost"),
+                idf,
+                Explanation::new(tf_norm, "tfNorm, computed as (k1 + 1) * tf / (tf + k1 * (1 - b + b * sqrt(fieldLength) / sqrt(avgFieldLength)) + 1) from:".to_string(), vec![
+                    tf,
+                    Explanation::leaf(k1, "parameter k1"),
+                    Explanation::leaf(b, "parameter b"),
+                    Explanation::new(avg_field_length, "avgFieldLength, computed as (totalTokens + 1) / (docCount + 1) from:".to_string(), vec![
+                        Explanation::leaf(total_tokens as f64, "totalTokens"),
+                        Explanation::leaf(doc_count as f64, "docCount"),
+                    ]),
+                    Explanation::leaf(field_length as f64, "fieldLength"),
+                ]),
+            ])
+        }
+    }
+}
+
+
+/// Explains the score of a query that adds up the scores of its matching sub queries
+pub fn explain_sum(description: &str, details: Vec<Explanation>) -> Explanation {
+    let value: f64 = details.iter().map(|detail| detail.value).sum();
+    Explanation::new(value, description.to_string(), details)
+}
+
+
+/// Explains the score of a query that takes the best score of its matching sub queries
+pub fn explain_max(details: Vec<Explanation>) -> Explanation {
+    let value = details.iter().map(|detail| detail.value).fold(0.0, f64::max);
+    Explanation::new(value, "max of:".to_string(), details)
+}
+
+
+/// A term or prefix query in a search's query
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum LeafQuery {
+    Term(FieldRef, Vec<u8>),
+    Prefix(FieldRef, String),
+}
+
+
+impl LeafQuery {
+    fn field(&self) -> FieldRef {
+        match *self {
+            LeafQuery::Term(field, _) | LeafQuery::Prefix(field, _) => field,
+        }
+    }
+
+    fn to_query(&self) -> Query {
+        match *self {
+            LeafQuery::Term(field, ref term) => {
+                Query::Term {
+                    field: field,
+                    term: Term::from_bytes(term),
+                    scorer: TermScorer::default(),
+                }
+            }
+            LeafQuery::Prefix(field, ref prefix) => {
+                Query::MultiTerm {
+                    field: field,
+                    term_selector: TermSelector::Prefix(prefix.clone()),
+                    scorer: TermScorer::default(),
+                }
+            }
+        }
+    }
+}
+
+
+/// Finds the term and prefix queries in a query, with whether each one is scored with BM25
+fn find_leaf_queries(query: &Query, leaves: &mut Vec<(LeafQuery, bool)>) {
+    let leaf = match *query {
+        Query::Term{field, ref term, ref scorer} => (LeafQuery::Term(field, term.as_bytes().to_vec()), scorer),
+        Query::MultiTerm{field, term_selector: TermSelector::Prefix(ref prefix), ref scorer} => (LeafQuery::Prefix(field, prefix.clone()), scorer),
+        Query::Conjunction{ref queries} | Query::Disjunction{ref queries} | Query::DisjunctionMax{ref queries} => {
+            for query in queries.iter() {
+                find_leaf_queries(query, leaves);
+            }
+            return;
+        }
+        Query::Filter{ref query, ..} | Query::Exclude{ref query, ..} => {
+            find_leaf_queries(query, leaves);
+            return;
+        }
+        Query::All{..} | Query::None => return,
+    };
+
+    let is_bm25 = match leaf.1.similarity_model {
+        SimilarityModel::Bm25{..} => true,
+        SimilarityModel::TfIdf => false,
+    };
+
+    if !leaves.iter().any(|&(ref existing, _)| *existing == leaf.0) {
+        leaves.push((leaf.0, is_bm25));
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExplainError {
+    /// The store couldn't run one of the searches used to gather statistics
+    SearchFailed,
+}
+
+
+/// Explains the hits of a search on one index
+///
+/// The statistics for the search's query are gathered once, when this is created, so
+/// one of these should be used for all the hits on an index.
+pub struct Explainer<'a> {
+    index_reader: &'a RocksDBIndexReader<'a>,
+    index_metadata: &'a IndexMetadata,
+
+    /// The names of the fields in the store
+    field_names: Vec<(FieldRef, String)>,
+    doc_count: u64,
+
+    /// The documents that each term and prefix query in the search's query matches
+    leaf_matches: HashMap<LeafQuery, HashSet<u64>>,
+    doc_freqs: HashMap<(FieldRef, Vec<u8>), u64>,
+
+    /// The number of tokens in each field over the whole index, for fields scored with BM25
+    total_tokens: HashMap<String, u64>,
+}
+
+
+impl<'a> Explainer<'a> {
+    pub fn new(index_reader: &'a RocksDBIndexReader<'a>, index_metadata: &'a IndexMetadata, query: &Query) -> Result<Explainer<'a>, ExplainError> {
+        let mut explainer = Explainer {
+            index_reader: index_reader,
+            index_metadata: index_metadata,
+            field_names: index_metadata.get_field_names(&index_reader.schema()),
+            doc_count: 0,
+            leaf_matches: HashMap::new(),
+            doc_freqs: HashMap::new(),
+            total_tokens: HashMap::new(),
+        };
+        explainer.doc_count = try!(explainer.count(&Query::new_all()));
+
+        let mut leaves = Vec::new();
+        find_leaf_queries(query, &mut leaves);
+
+        let mut bm25_field_names = Vec::new();
+        for (leaf, is_bm25) in leaves {
+            let mut collector = AllDocumentsCollector::new().no_score();
+            try!(index_reader.search(&mut collector, &leaf.to_query()).map_err(|_| ExplainError::SearchFailed));
+            let matches = collector.into_vec().into_iter().map(|(doc_id, _)| doc_id).collect::<HashSet<_>>();
+
+            if let LeafQuery::Term(field, ref term) = leaf {
+                explainer.doc_freqs.insert((field, term.clone()), matches.len() as u64);
+            }
+
+            let field_name = explainer.field_name(leaf.field());
+            if is_bm25 && !bm25_field_names.contains(&field_name) {
+                bm25_field_names.push(field_name);
+            }
+
+            explainer.leaf_matches.insert(leaf, matches);
+        }
+
+        if !bm25_field_names.is_empty() {
+            explainer.total_tokens = try!(explainer.count_total_tokens(&bm25_field_names));
+        }
+
+        Ok(explainer)
+    }
+
+    fn field_name(&self, field: FieldRef) -> String {
+        self.field_names.iter()
+            .find(|&&(field_ref, _)| field_ref == field)
+            .map(|&(_, ref field_name)| field_name.clone())
+            .unwrap_or_else(|| "_unknown".to_string())
+    }
+
+    fn count(&self, query: &Query) -> Result<u64, ExplainError> {
+        let mut collector = TotalCountCollector::new();
+        try!(self.index_reader.search(&mut collector, query).map_err(|_| ExplainError::SearchFailed));
+        Ok(collector.get_total_count())
+    }
+
+    /// Counts the documents containing a term that isn't in the search's query, such as
+    /// one that a prefix query expanded to
+    fn doc_freq(&mut self, field: FieldRef, term: &Term) -> Result<u64, ExplainError> {
+        let key = (field, term.as_bytes().to_vec());
+        if let Some(&doc_freq) = self.doc_freqs.get(&key) {
+            return Ok(doc_freq);
+        }
+
+        let doc_freq = try!(self.count(&Query::Term {
+            field: field,
+            term: term.clone(),
+            scorer: TermScorer::default(),
+        }));
+        self.doc_freqs.insert(key, doc_freq);
+        Ok(doc_freq)
+    }
+
+    /// Analyses the values of a field in a document's source
+    ///
+    /// Values of the "_all" field are collected from the fields that are copied into it.
+    fn field_tokens(&self, field_name: &str, source: &Json) -> Vec<Vec<u8>> {
+        let analyzer = self.index_metadata.get_field_mapping(field_name).and_then(|field_mapping| field_mapping.index_analyzer());
+
+        let source_field_names = if field_name == "_all" {
+            self.index_metadata.mappings.values()
+                .flat_map(|mapping| mapping.fields().into_iter())
+                .filter(|&(_, field_mapping)| field_mapping.is_in_all && field_mapping.data_type == FieldType::String)
+                .map(|(name, _)| name)
+                .collect::<Vec<_>>()
+        } else {
+            vec![field_name.to_string()]
+        };
+
+        let mut tokens = Vec::new();
+        for source_field_name in source_field_names.iter() {
+            for value in get_source_values(source, source_field_name) {
+                tokens.extend(analyse(value, analyzer));
+            }
+        }
+
+        tokens
+    }
+
+    /// Counts the tokens in each of the given fields over every document on the index
+    ///
+    /// This reads the source of every document, so all the fields are counted in one pass.
+    fn count_total_tokens(&self, field_names: &[String]) -> Result<HashMap<String, u64>, ExplainError> {
+        let mut total_tokens = field_names.iter().map(|field_name| (field_name.clone(), 0)).collect::<HashMap<_, _>>();
+
+        if let Some(source_field) = self.index_reader.schema().get_field_by_name("_source") {
+            let mut collector = AllDocumentsCollector::new().no_score();
+            try!(self.index_reader.search(&mut collector, &Query::new_all()).map_err(|_| ExplainError::SearchFailed));
+
+            for (doc_id, _) in collector.into_vec() {
+                let source = match self.index_reader.read_stored_field(source_field, DocRef::from_u64(doc_id)) {
+                    Ok(Some(FieldValue::String(source))) => source,
+                    _ => continue,
+                };
+
+                if let Ok(source) = serde_json::from_str::<Json>(&source) {
+                    for field_name in field_names.iter() {
+                        let length = self.field_tokens(field_name, &source).len() as u64;
+                        if let Some(total) = total_tokens.get_mut(field_name) {
+                            *total += length;
+                        }
+                    }
+                }
+            }
+        }
+
+        // Fields that can't be read from the source (such as numbers) have one token per value
+        for total in total_tokens.values_mut() {
+            if *total == 0 {
+                *total = self.doc_count;
+            }
+        }
+
+        Ok(total_tokens)
+    }
+
+    fn matches(&self, leaf: &LeafQuery, doc_id: u64) -> bool {
+        self.leaf_matches.get(leaf).map(|matches| matches.contains(&doc_id)).unwrap_or(false)
+    }
+
+    fn explain_term(&mut self, field: FieldRef, term: &Term, scorer: &TermScorer, doc_id: u64, doc_key: &str, tokens: &[Vec<u8>]) -> Explanation {
+        let field_name = self.field_name(field);
+        let term_bytes = term.as_bytes();
+        let term_text = String::from_utf8_lossy(term_bytes).into_owned();
+
+        // Terms of fields that aren't in the source (such as numbers) are counted once
+        let freq = match tokens.iter().filter(|token| token.as_slice() == term_bytes).count() as u64 {
+            0 => 1,
+            freq => freq,
+        };
+        let field_length = if tokens.is_empty() { 1 } else { tokens.len() as u64 };
+
+        let doc_freq = match self.doc_freq(field, term) {
+            Ok(doc_freq) => doc_freq,
+            Err(_) => return Explanation::leaf(0.0, &format!("the documents containing {}:{} couldn't be counted", field_name, term_text)),
+        };
+        let total_tokens = self.total_tokens.get(&field_name).cloned().unwrap_or(self.doc_count);
+
+        let score = explain_term_score(&scorer.similarity_model, scorer.boost, freq, field_length, total_tokens, doc_freq, self.doc_count);
+        Explanation::new(score.value, format!("weight({}:{} in {}) [{}], result of:", field_name, term_text, doc_id, doc_key), vec![score])
+    }
+
+    fn explain_query(&mut self, query: &Query, doc_id: u64, doc_key: &str, source: &Json) -> Option<Explanation> {
+        match *query {
+            Query::All{score} => Some(Explanation::leaf(score, "*:*")),
+            Query::None => None,
+            Query::Term{field, ref term, ref scorer} => {
+                if !self.matches(&LeafQuery::Term(field, term.as_bytes().to_vec()), doc_id) {
+                    return None;
+                }
+
+                let field_name = self.field_name(field);
+                let tokens = self.field_tokens(&field_name, source);
+                Some(self.explain_term(field, term, scorer, doc_id, doc_key, &tokens))
+            }
+            Query::MultiTerm{field, term_selector: TermSelector::Prefix(ref prefix), ref scorer} => {
+                if !self.matches(&LeafQuery::Prefix(field, prefix.clone()), doc_id) {
+                    return None;
+                }
+
+                // Each term in the document that starts with the prefix is scored separately
+                let field_name = self.field_name(field);
+                let tokens = self.field_tokens(&field_name, source);
+                let mut terms = tokens.iter().filter(|token| token.starts_with(prefix.as_bytes())).cloned().collect::<Vec<_>>();
+                terms.sort();
+                terms.dedup();
+
+                if terms.is_empty() {
+                    return Some(Explanation::leaf(scorer.boost, &format!("{}:{}* matched a term that isn't in the source, scored as the boost", field_name, prefix)));
+                }
+
+                let details = terms.into_iter().map(|term| {
+                    self.explain_term(field, &Term::from_bytes(&term), scorer, doc_id, doc_key, &tokens)
+                }).collect();
+                Some(explain_sum("sum of:", details))
+            }
+            Query::Conjunction{ref queries} => {
+                let mut details = Vec::with_capacity(queries.len());
+                for query in queries.iter() {
+                    match self.explain_query(query, doc_id, doc_key, source) {
+                        Some(detail) => details.push(detail),
+                        None => return None,
+                    }
+                }
+
+                Some(explain_sum("sum of:", details))
+            }
+            Query::Disjunction{ref queries} => {
+                let details = queries.iter().filter_map(|query| self.explain_query(query, doc_id, doc_key, source)).collect::<Vec<_>>();
+                if details.is_empty() {
+                    return None;
+                }
+
+                Some(explain_sum("sum of:", details))
+            }
+            Query::DisjunctionMax{ref queries} => {
+                let details = queries.iter().filter_map(|query| self.explain_query(query, doc_id, doc_key, source)).collect::<Vec<_>>();
+                if details.is_empty() {
+                    return None;
+                }
+
+                Some(explain_max(details))
+            }
+            Query::Filter{ref query, ..} => {
+                // Filters don't change the score, the hit wouldn't be here if it didn't match
+                self.explain_query(query, doc_id, doc_key, source).map(|explanation| {
+                    explain_sum("sum of:", vec![
+                        explanation,
+                        Explanation::leaf(0.0, "match on required filter clause"),
+                    ])
+                })
+            }
+            Query::Exclude{ref query, ..} => self.explain_query(query, doc_id, doc_key, source),
+        }
+    }
+
+    /// Explains the score of a hit
+    ///
+    /// The query must be the one the explainer was created with.
+    pub fn explain(&mut self, query: &Query, doc_id: u64, source: &Json) -> Explanation {
+        let doc_key = match self.index_reader.schema().get_field_by_name("_id").map(|key_field| self.index_reader.read_stored_field(key_field, DocRef::from_u64(doc_id))) {
+            Some(Ok(Some(FieldValue::String(doc_key)))) => doc_key,
+            _ => return Explanation::no_match(),
+        };
+
+        self.explain_query(query, doc_id, &doc_key, source).unwrap_or_else(Explanation::no_match)
+    }
+}
+
+
+fn analyse(value: &str, analyzer: Option<&AnalyzerSpec>) -> Vec<Vec<u8>> {
+    match analyzer {
+        Some(analyzer) => analyzer.initialise(value).map(|token| token.term.as_bytes().to_vec()).collect(),
+        None => vec![value.as_bytes().to_vec()],
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use kite::similarity::SimilarityModel;
+
+    use super::{Explanation, explain_idf, explain_tf, explain_term_score, explain_sum, explain_max};
+
+    fn round(value: f64) -> f64 {
+        (value * 10000.0).round() / 10000.0
+    }
+
+    /// Multiplies the values of an explanation's details, which should give its value
+    fn product(explanation: &Explanation) -> f64 {
+        explanation.details.iter().fold(1.0, |product, detail| product * detail.value)
+    }
+
+    #[test]
+    fn test_explain_idf() {
+        let idf = explain_idf(1, 10);
+
+        assert_eq!(round(idf.value), round(5.5f64.ln() + 1.0));
+        assert_eq!(idf.details.iter().map(|detail| detail.value).collect::<Vec<_>>(), vec![1.0, 10.0]);
+    }
+
+    #[test]
+    fn test_explain_tf() {
+        assert_eq!(explain_tf(0).value, 1.0);
+        assert_eq!(round(explain_tf(3).value), round(4.0f64.ln() + 1.0));
+    }
+
+    #[test]
+    fn test_explain_term_score_bm25() {
+        let model = SimilarityModel::Bm25 {
+            k1: 1.2,
+            b: 0.75,
+        };
+
+        // The score must be the same as the one the store gives the hit
+        let score = explain_term_score(&model, 2.0, 2, 8, 40, 3, 10);
+        assert_eq!(score.value, 2.0 * model.score(2, 8.0, 40, 10, 3));
+        assert_eq!(round(product(&score)), round(score.value));
+        assert_eq!(score.description, "score(freq=2) [BM25], product of:");
+
+        // Longer fields score lower
+        assert!(explain_term_score(&model, 1.0, 1, 16, 40, 3, 10).value < explain_term_score(&model, 1.0, 1, 4, 40, 3, 10).value);
+    }
+
+    #[test]
+    fn test_explain_term_score_tf_idf() {
+        let model = SimilarityModel::TfIdf;
+        let score = explain_term_score(&model, 1.0, 4, 10, 50, 1, 10);
+
+        assert_eq!(score.value, model.score(4, 10.0, 50, 10, 1));
+        assert_eq!(round(product(&score)), round(score.value));
+        assert_eq!(score.details[2].description, "tf, computed as ln(freq + 1) + 1 from:");
+    }
+
+    #[test]
+    fn test_explain_term_score_handles_empty_index() {
+        let model = SimilarityModel::Bm25 {
+            k1: 1.2,
+            b: 0.75,
+        };
+
+        assert!(explain_term_score(&model, 1.0, 1, 1, 0, 0, 0).value.is_finite());
+    }
+
+    #[test]
+    fn test_combine() {
+        let details = vec![
+            Explanation::new(1.5, "a".to_string(), vec![]),
+            Explanation::new(2.0, "b".to_string(), vec![]),
+        ];
+
+        assert_eq!(explain_sum("sum of:", details.clone()).value, 3.5);
+        assert_eq!(explain_max(details).value, 2.0);
+    }
+
+    #[test]
+    fn test_to_json() {
+        let explanation = Explanation::new(1.0, "sum of:".to_string(), vec![
+            Explanation::new(1.0, "*:*".to_string(), vec![]),
+        ]);
+
+        assert_eq!(explanation.to_json(), json!({
+            "value": 1.0,
+            "description": "sum of:",
+            "details": [
+                {
+                    "value": 1.0,
+                    "description": "*:*",
+                    "details": [],
+                },
+            ],
+        }));
+    }
+}
@@ -0,0 +1,567 @@
+//! Geographic points, distances and geohashes
+//!
+//! "geo_point" fields are indexed as a single full precision geohash. The geohashes of
+//! points inside the same cell share a prefix, so geo queries are run as a set of prefix
+//! queries over the cells that cover the area being searched.
+
+use std::f64::consts::PI;
+
+use serde_json;
+
+
+const BASE32: &'static [u8] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+/// The length of the geohashes that points are indexed with (cells are a few centimetres wide)
+pub const MAX_PRECISION: usize = 12;
+
+/// The most prefix queries that a geo query can be split into
+///
+/// The most precise level of cells that stays under this limit is used, so larger
+/// areas are covered with larger cells.
+const MAX_COVERING_CELLS: usize = 64;
+
+/// The furthest latitude from the equator that's shown on a Web Mercator map
+const MAX_MERCATOR_LAT: f64 = 85.05112878;
+
+/// Mean radius of the earth in metres
+const EARTH_RADIUS: f64 = 6371008.8;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GeoPoint {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+
+impl GeoPoint {
+    pub fn new(lat: f64, lon: f64) -> Option<GeoPoint> {
+        if lat < -90.0 || lat > 90.0 || lon < -180.0 || lon > 180.0 {
+            return None;
+        }
+
+        Some(GeoPoint {
+            lat: lat,
+            lon: lon,
+        })
+    }
+
+    /// Reads a point from any of the formats that Elasticsearch accepts:
+    ///
+    ///  - An object: `{"lat": 41.12, "lon": -71.34}`
+    ///  - An array in GeoJSON order: `[-71.34, 41.12]`
+    ///  - A string: `"41.12,-71.34"`
+    ///  - A geohash: `"drm3btev3e86"` (the centre of the cell is used)
+    pub fn from_json(json: &serde_json::Value) -> Option<GeoPoint> {
+        match *json {
+            serde_json::Value::Object(ref object) => {
+                match (object.get("lat").and_then(|lat| lat.as_f64()), object.get("lon").and_then(|lon| lon.as_f64())) {
+                    (Some(lat), Some(lon)) => GeoPoint::new(lat, lon),
+                    _ => None,
+                }
+            }
+            serde_json::Value::Array(ref array) if array.len() == 2 => {
+                match (array[1].as_f64(), array[0].as_f64()) {
+                    (Some(lat), Some(lon)) => GeoPoint::new(lat, lon),
+                    _ => None,
+                }
+            }
+            serde_json::Value::String(ref string) => GeoPoint::parse(string),
+            _ => None,
+        }
+    }
+
+    /// Reads a point from either a `"lat,lon"` string or a geohash
+    pub fn parse(string: &str) -> Option<GeoPoint> {
+        let mut parts = string.splitn(2, ',');
+
+        match (parts.next(), parts.next()) {
+            (Some(lat), Some(lon)) => {
+                match (lat.trim().parse::<f64>(), lon.trim().parse::<f64>()) {
+                    (Ok(lat), Ok(lon)) => GeoPoint::new(lat, lon),
+                    _ => None,
+                }
+            }
+            _ => BoundingBox::from_geohash(string).map(|cell| cell.centre()),
+        }
+    }
+
+    pub fn to_geohash(&self, precision: usize) -> String {
+        let mut lat_range = (-90.0f64, 90.0f64);
+        let mut lon_range = (-180.0f64, 180.0f64);
+        let mut geohash = String::with_capacity(precision);
+        let mut is_lon_bit = true;
+
+        while geohash.len() < precision {
+            let mut value = 0;
+
+            for _ in 0..5 {
+                value <<= 1;
+
+                // Bits alternate between halving the longitude and latitude ranges
+                let (range, coordinate) = if is_lon_bit {
+                    (&mut lon_range, self.lon)
+                } else {
+                    (&mut lat_range, self.lat)
+                };
+
+                let mid = (range.0 + range.1) / 2.0;
+                if coordinate >= mid {
+                    value |= 1;
+                    range.0 = mid;
+                } else {
+                    range.1 = mid;
+                }
+
+                is_lon_bit = !is_lon_bit;
+            }
+
+            geohash.push(BASE32[value] as char);
+        }
+
+        geohash
+    }
+
+    /// Returns the x and y of the Web Mercator map tile that the point is on
+    ///
+    /// There are 2^zoom tiles along each side of the map.
+    pub fn to_geotile(&self, zoom: u32) -> (u32, u32) {
+        let tiles = (1u64 << zoom) as f64;
+
+        // The projection stretches to infinity at the poles so it's cut off just before them
+        let lat = self.lat.max(-MAX_MERCATOR_LAT).min(MAX_MERCATOR_LAT).to_radians();
+
+        let x = ((self.lon + 180.0) / 360.0 * tiles).floor();
+        let y = ((1.0 - (lat.tan() + 1.0 / lat.cos()).ln() / PI) / 2.0 * tiles).floor();
+
+        // Points on the right and bottom edges are put in the last tile
+        let clamp = |value: f64| if value < 0.0 { 0 } else if value >= tiles { tiles as u32 - 1 } else { value as u32 };
+        (clamp(x), clamp(y))
+    }
+
+    /// Returns the distance in metres to another point (using the haversine formula)
+    pub fn distance(&self, other: &GeoPoint) -> f64 {
+        let lat1 = self.lat.to_radians();
+        let lat2 = other.lat.to_radians();
+        let dlat = (other.lat - self.lat).to_radians();
+        let dlon = (other.lon - self.lon).to_radians();
+
+        let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS * a.sqrt().min(1.0).asin()
+    }
+}
+
+
+/// Reads the points back out of a stored "geo_point" field
+///
+/// Points are stored as `"lat,lon"` pairs separated by spaces.
+pub fn parse_stored_points(string: &str) -> Vec<GeoPoint> {
+    string.split_whitespace().filter_map(GeoPoint::parse).collect()
+}
+
+
+/// The difference in degrees between two longitudes, going the shortest way around the earth
+fn lon_difference(a: f64, b: f64) -> f64 {
+    ((a - b + 540.0) % 360.0 - 180.0).abs()
+}
+
+
+/// An area between two latitudes and two longitudes
+///
+/// If `left` is greater than `right`, the box crosses the 180th meridian.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub top: f64,
+    pub left: f64,
+    pub bottom: f64,
+    pub right: f64,
+}
+
+
+impl BoundingBox {
+    /// Returns the area of the cell that a geohash represents
+    pub fn from_geohash(geohash: &str) -> Option<BoundingBox> {
+        if geohash.is_empty() {
+            return None;
+        }
+
+        let mut cell = BoundingBox {
+            top: 90.0,
+            left: -180.0,
+            bottom: -90.0,
+            right: 180.0,
+        };
+        let mut is_lon_bit = true;
+
+        for c in geohash.bytes() {
+            let value = match BASE32.iter().position(|b| *b == c) {
+                Some(value) => value,
+                None => return None,
+            };
+
+            for bit in (0..5).rev() {
+                let is_set = (value >> bit) & 1 == 1;
+
+                if is_lon_bit {
+                    let mid = (cell.left + cell.right) / 2.0;
+                    if is_set { cell.left = mid } else { cell.right = mid }
+                } else {
+                    let mid = (cell.bottom + cell.top) / 2.0;
+                    if is_set { cell.bottom = mid } else { cell.top = mid }
+                }
+
+                is_lon_bit = !is_lon_bit;
+            }
+        }
+
+        Some(cell)
+    }
+
+    /// Returns the smallest box that contains every point within `distance` metres of `centre`
+    pub fn around(centre: &GeoPoint, distance: f64) -> BoundingBox {
+        let angle = (distance / EARTH_RADIUS) * 180.0 / PI;
+        let top = centre.lat + angle;
+        let bottom = centre.lat - angle;
+
+        // Circles around the poles include every longitude
+        if top >= 90.0 || bottom <= -90.0 {
+            return BoundingBox {
+                top: top.min(90.0),
+                left: -180.0,
+                bottom: bottom.max(-90.0),
+                right: 180.0,
+            };
+        }
+
+        let lon_angle = angle / centre.lat.to_radians().cos();
+        if lon_angle >= 180.0 {
+            return BoundingBox {
+                top: top,
+                left: -180.0,
+                bottom: bottom,
+                right: 180.0,
+            };
+        }
+
+        let mut left = centre.lon - lon_angle;
+        let mut right = centre.lon + lon_angle;
+
+        if left < -180.0 {
+            left += 360.0;
+        }
+
+        if right > 180.0 {
+            right -= 360.0;
+        }
+
+        BoundingBox {
+            top: top,
+            left: left,
+            bottom: bottom,
+            right: right,
+        }
+    }
+
+    pub fn centre(&self) -> GeoPoint {
+        GeoPoint {
+            lat: (self.top + self.bottom) / 2.0,
+            lon: (self.left + self.right) / 2.0,
+        }
+    }
+
+    /// Returns the point in the box that is closest to the given point
+    fn closest_point(&self, point: &GeoPoint) -> GeoPoint {
+        let lat = point.lat.max(self.bottom).min(self.top);
+
+        let lon = if point.lon >= self.left && point.lon <= self.right {
+            point.lon
+        } else if lon_difference(point.lon, self.left) < lon_difference(point.lon, self.right) {
+            self.left
+        } else {
+            self.right
+        };
+
+        GeoPoint {
+            lat: lat,
+            lon: lon,
+        }
+    }
+
+    pub fn contains(&self, point: &GeoPoint) -> bool {
+        if point.lat < self.bottom || point.lat > self.top {
+            return false;
+        }
+
+        if self.left <= self.right {
+            point.lon >= self.left && point.lon <= self.right
+        } else {
+            point.lon >= self.left || point.lon <= self.right
+        }
+    }
+
+    /// Returns true if another box (that doesn't cross the 180th meridian) is completely inside this one
+    pub fn contains_box(&self, other: &BoundingBox) -> bool {
+        if other.bottom < self.bottom || other.top > self.top {
+            return false;
+        }
+
+        if self.left <= self.right {
+            other.left >= self.left && other.right <= self.right
+        } else {
+            other.left >= self.left || other.right <= self.right
+        }
+    }
+
+    pub fn corners(&self) -> [GeoPoint; 4] {
+        [
+            GeoPoint { lat: self.top, lon: self.left },
+            GeoPoint { lat: self.top, lon: self.right },
+            GeoPoint { lat: self.bottom, lon: self.left },
+            GeoPoint { lat: self.bottom, lon: self.right },
+        ]
+    }
+
+    /// Returns the index ranges of the cells at a geohash precision that overlap the box
+    ///
+    /// Cells are numbered from the south west. There are two longitude ranges when the
+    /// box crosses the 180th meridian.
+    fn cell_ranges(&self, precision: usize) -> ((i64, i64), Vec<(i64, i64)>) {
+        let bits = 5 * precision as i32;
+        let lat_cells = 2i64.pow((bits / 2) as u32);
+        let lon_cells = 2i64.pow(((bits + 1) / 2) as u32);
+        let cell_height = 180.0 / lat_cells as f64;
+        let cell_width = 360.0 / lon_cells as f64;
+
+        let lat_index = |lat: f64| (((lat + 90.0) / cell_height).floor() as i64).max(0).min(lat_cells - 1);
+        let lon_index = |lon: f64| (((lon + 180.0) / cell_width).floor() as i64).max(0).min(lon_cells - 1);
+
+        let lat_range = (lat_index(self.bottom), lat_index(self.top));
+        let lon_ranges = if self.left <= self.right {
+            vec![(lon_index(self.left), lon_index(self.right))]
+        } else {
+            vec![(lon_index(self.left), lon_cells - 1), (0, lon_index(self.right))]
+        };
+
+        (lat_range, lon_ranges)
+    }
+
+    fn count_cells(&self, precision: usize) -> usize {
+        let ((bottom, top), lon_ranges) = self.cell_ranges(precision);
+        let lon_count: i64 = lon_ranges.iter().map(|&(left, right)| right - left + 1).sum();
+
+        ((top - bottom + 1) * lon_count) as usize
+    }
+
+    fn cells(&self, precision: usize) -> Vec<String> {
+        let bits = 5 * precision as i32;
+        let cell_height = 180.0 / 2i64.pow((bits / 2) as u32) as f64;
+        let cell_width = 360.0 / 2i64.pow(((bits + 1) / 2) as u32) as f64;
+        let ((bottom, top), lon_ranges) = self.cell_ranges(precision);
+
+        let mut cells = Vec::new();
+        for lat_index in bottom..(top + 1) {
+            for &(left, right) in lon_ranges.iter() {
+                for lon_index in left..(right + 1) {
+                    let cell_centre = GeoPoint {
+                        lat: -90.0 + (lat_index as f64 + 0.5) * cell_height,
+                        lon: -180.0 + (lon_index as f64 + 0.5) * cell_width,
+                    };
+
+                    cells.push(cell_centre.to_geohash(precision));
+                }
+            }
+        }
+
+        cells
+    }
+
+    /// Finds the geohashes of a set of cells that covers the box
+    ///
+    /// Cells on the edges of the box usually extend past it, so points that are slightly
+    /// outside the box can be matched as well. The amount depends on the size of the box.
+    pub fn covering_geohashes(&self) -> Vec<String> {
+        let mut precision = 1;
+        while precision < MAX_PRECISION && self.count_cells(precision + 1) <= MAX_COVERING_CELLS {
+            precision += 1;
+        }
+
+        self.cells(precision)
+    }
+}
+
+
+/// Finds the geohashes of a set of cells that covers a circle
+///
+/// This covers the bounding box of the circle and removes any cells that are completely
+/// outside the circle.
+pub fn covering_geohashes_within(centre: &GeoPoint, distance: f64) -> Vec<String> {
+    BoundingBox::around(centre, distance).covering_geohashes().into_iter().filter(|geohash| {
+        match BoundingBox::from_geohash(geohash) {
+            Some(cell) => cell.closest_point(centre).distance(centre) <= distance,
+            None => false,
+        }
+    }).collect()
+}
+
+
+/// Converts a distance such as "10km" or "500m" into metres
+///
+/// Numbers without a unit are in metres.
+pub fn parse_distance(json: &serde_json::Value) -> Option<f64> {
+    let string = match *json {
+        serde_json::Value::Number(ref num) => return num.as_f64(),
+        serde_json::Value::String(ref string) => string.trim(),
+        _ => return None,
+    };
+
+    let units: &[(&str, f64)] = &[
+        ("nmi", 1852.0),
+        ("km", 1000.0),
+        ("cm", 0.01),
+        ("mm", 0.001),
+        ("mi", 1609.344),
+        ("yd", 0.9144),
+        ("ft", 0.3048),
+        ("in", 0.0254),
+        ("m", 1.0),
+    ];
+
+    for &(unit, metres) in units.iter() {
+        if string.ends_with(unit) {
+            return string[..string.len() - unit.len()].trim().parse::<f64>().ok().map(|value| value * metres);
+        }
+    }
+
+    string.parse::<f64>().ok()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{GeoPoint, BoundingBox, covering_geohashes_within, parse_distance, parse_stored_points};
+
+    #[test]
+    fn test_to_geohash() {
+        assert_eq!(GeoPoint::new(57.64911, 10.40744).unwrap().to_geohash(11), "u4pruydqqvj");
+        assert_eq!(GeoPoint::new(42.6, -5.6).unwrap().to_geohash(5), "ezs42");
+    }
+
+    #[test]
+    fn test_to_geotile() {
+        assert_eq!(GeoPoint::new(0.0, 0.0).unwrap().to_geotile(0), (0, 0));
+        assert_eq!(GeoPoint::new(51.5, -0.12).unwrap().to_geotile(8), (127, 85));
+        assert_eq!(GeoPoint::new(-90.0, 180.0).unwrap().to_geotile(2), (3, 3));
+    }
+
+    #[test]
+    fn test_parse_stored_points() {
+        assert_eq!(parse_stored_points("41.12,-71.34 -5.5,10"), vec![
+            GeoPoint { lat: 41.12, lon: -71.34 },
+            GeoPoint { lat: -5.5, lon: 10.0 },
+        ]);
+    }
+
+    #[test]
+    fn test_from_geohash() {
+        let cell = BoundingBox::from_geohash("ezs42").unwrap();
+
+        assert!(cell.bottom <= 42.6 && cell.top >= 42.6);
+        assert!(cell.left <= -5.6 && cell.right >= -5.6);
+        assert_eq!(BoundingBox::from_geohash("ezs4a"), None);
+    }
+
+    #[test]
+    fn test_from_json() {
+        let point = Some(GeoPoint { lat: 41.12, lon: -71.34 });
+
+        assert_eq!(GeoPoint::from_json(&json!({"lat": 41.12, "lon": -71.34})), point);
+        assert_eq!(GeoPoint::from_json(&json!([-71.34, 41.12])), point);
+        assert_eq!(GeoPoint::from_json(&json!("41.12,-71.34")), point);
+        assert_eq!(GeoPoint::from_json(&json!("drm3btev3e86")).map(|point| point.to_geohash(12)), Some("drm3btev3e86".to_string()));
+    }
+
+    #[test]
+    fn test_from_json_invalid() {
+        assert_eq!(GeoPoint::from_json(&json!({"lat": 100.0, "lon": 0.0})), None);
+        assert_eq!(GeoPoint::from_json(&json!("foo,bar")), None);
+        assert_eq!(GeoPoint::from_json(&json!(123)), None);
+    }
+
+    #[test]
+    fn test_distance() {
+        let london = GeoPoint::new(51.5074, -0.1278).unwrap();
+        let paris = GeoPoint::new(48.8566, 2.3522).unwrap();
+
+        let distance = london.distance(&paris);
+        assert!(distance > 343000.0 && distance < 344000.0);
+    }
+
+    #[test]
+    fn test_covering_geohashes() {
+        let bbox = BoundingBox {
+            top: 40.73,
+            left: -74.1,
+            bottom: 40.01,
+            right: -71.12,
+        };
+        let cells = bbox.covering_geohashes();
+
+        assert!(cells.len() <= 64);
+        assert!(cells.iter().any(|cell| GeoPoint::new(40.5, -73.0).unwrap().to_geohash(12).starts_with(cell)));
+        assert!(!cells.iter().any(|cell| GeoPoint::new(45.0, -73.0).unwrap().to_geohash(12).starts_with(cell)));
+    }
+
+    #[test]
+    fn test_covering_geohashes_across_180th_meridian() {
+        let bbox = BoundingBox {
+            top: 10.0,
+            left: 170.0,
+            bottom: -10.0,
+            right: -170.0,
+        };
+        let cells = bbox.covering_geohashes();
+
+        assert!(cells.iter().any(|cell| GeoPoint::new(0.0, 175.0).unwrap().to_geohash(12).starts_with(cell)));
+        assert!(cells.iter().any(|cell| GeoPoint::new(0.0, -175.0).unwrap().to_geohash(12).starts_with(cell)));
+        assert!(!cells.iter().any(|cell| GeoPoint::new(0.0, 0.0).unwrap().to_geohash(12).starts_with(cell)));
+    }
+
+    #[test]
+    fn test_covering_geohashes_within() {
+        let centre = GeoPoint::new(51.5074, -0.1278).unwrap();
+        let cells = covering_geohashes_within(&centre, 10000.0);
+
+        assert!(cells.iter().any(|cell| centre.to_geohash(12).starts_with(cell)));
+        assert!(!cells.iter().any(|cell| GeoPoint::new(48.8566, 2.3522).unwrap().to_geohash(12).starts_with(cell)));
+    }
+
+    #[test]
+    fn test_contains() {
+        let bounding_box = BoundingBox { top: 40.73, left: -74.1, bottom: 40.01, right: -71.12 };
+
+        assert!(bounding_box.contains(&GeoPoint::new(40.5, -73.0).unwrap()));
+        assert!(!bounding_box.contains(&GeoPoint::new(40.5, -71.0).unwrap()));
+        assert!(bounding_box.contains_box(&BoundingBox { top: 40.6, left: -74.0, bottom: 40.5, right: -73.0 }));
+        assert!(!bounding_box.contains_box(&BoundingBox { top: 40.6, left: -74.0, bottom: 40.5, right: -71.0 }));
+    }
+
+    #[test]
+    fn test_contains_across_180th_meridian() {
+        let bounding_box = BoundingBox { top: 10.0, left: 170.0, bottom: -10.0, right: -170.0 };
+
+        assert!(bounding_box.contains(&GeoPoint::new(0.0, 175.0).unwrap()));
+        assert!(bounding_box.contains(&GeoPoint::new(0.0, -175.0).unwrap()));
+        assert!(!bounding_box.contains(&GeoPoint::new(0.0, 0.0).unwrap()));
+        assert!(bounding_box.contains_box(&BoundingBox { top: 5.0, left: -179.0, bottom: -5.0, right: -175.0 }));
+        assert!(!bounding_box.contains_box(&BoundingBox { top: 5.0, left: 160.0, bottom: -5.0, right: 175.0 }));
+    }
+
+    #[test]
+    fn test_parse_distance() {
+        assert_eq!(parse_distance(&json!("10km")), Some(10000.0));
+        assert_eq!(parse_distance(&json!("500 m")), Some(500.0));
+        assert_eq!(parse_distance(&json!("2mi")), Some(3218.688));
+        assert_eq!(parse_distance(&json!(100)), Some(100.0));
+        assert_eq!(parse_distance(&json!("far")), None);
+    }
+}
@@ -0,0 +1,786 @@
+//! Highlights the terms that a search matched in the text of each hit
+//!
+//! Token offsets aren't kept in the index so the text of each field is read from the
+//! document's source and re-analyzed. The text is split into words and each word is run
+//! through the field's analyzer on its own. Words that produce any of the terms that the
+//! query searched for are wrapped in tags. The text is then split into fragments and the
+//! ones with the most matches are returned.
+
+use unicode_segmentation::UnicodeSegmentation;
+use serde_json;
+use serde_json::Value as Json;
+use kite::{Query, TermSelector};
+use kite::schema::{Schema, FieldRef};
+
+use analysis::AnalyzerSpec;
+use index::metadata::IndexMetadata;
+
+
+#[derive(Debug, PartialEq)]
+pub enum HighlightParseError {
+    ExpectedObject,
+    ExpectedObjectOrArray,
+    ExpectedString,
+    ExpectedStringOrArray,
+    ExpectedBoolean,
+    ExpectedPositiveInteger,
+    UnrecognisedKey(String),
+    InvalidEncoder(String),
+    InvalidFragmenter(String),
+    InvalidOrder(String),
+    InvalidField(String, Box<HighlightParseError>),
+}
+
+
+/// How the text around the highlighted terms is escaped
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoder {
+    /// Text is returned as it is
+    Default,
+
+    /// Text is escaped so it can be put in an HTML document
+    Html,
+}
+
+
+/// How the text is split into fragments
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fragmenter {
+    /// Splits the text into fragments of the same size
+    Simple,
+
+    /// Puts each highlighted term in the middle of a fragment
+    Span,
+}
+
+
+/// How the fragments are ordered in the response
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FragmentOrder {
+    /// In the order that they appear in the text
+    None,
+
+    /// Fragments with the most highlighted terms first
+    Score,
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HighlightOptions {
+    /// Inserted before each highlighted term
+    ///
+    /// If more than one tag is given, each term that the query searched for gets the next one.
+    pub pre_tags: Vec<String>,
+
+    /// Inserted after each highlighted term, in the same order as `pre_tags`
+    pub post_tags: Vec<String>,
+
+    pub encoder: Encoder,
+    pub fragmenter: Fragmenter,
+
+    /// The length of each fragment in bytes
+    pub fragment_size: usize,
+
+    /// The most fragments to return for each field
+    ///
+    /// If this is zero, the whole field is returned as one fragment.
+    pub number_of_fragments: usize,
+
+    pub order: FragmentOrder,
+
+    /// The length of the text to return from the start of the field if nothing in it
+    /// matched (nothing is returned if this is zero)
+    pub no_match_size: usize,
+
+    /// If false, terms that the query searched for in other fields are highlighted too
+    pub require_field_match: bool,
+}
+
+
+impl Default for HighlightOptions {
+    fn default() -> HighlightOptions {
+        HighlightOptions {
+            pre_tags: vec!["<em>".to_string()],
+            post_tags: vec!["</em>".to_string()],
+            encoder: Encoder::Default,
+            fragmenter: Fragmenter::Span,
+            fragment_size: 100,
+            number_of_fragments: 5,
+            order: FragmentOrder::None,
+            no_match_size: 0,
+            require_field_match: true,
+        }
+    }
+}
+
+
+/// The "highlight" section of a search request
+#[derive(Debug, Clone, PartialEq)]
+pub struct HighlightRequest {
+    /// The fields to highlight, with their options
+    pub fields: Vec<(String, HighlightOptions)>,
+}
+
+
+/// The terms that a query searches for
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryTerms {
+    terms: Vec<(FieldRef, Vec<u8>)>,
+    prefixes: Vec<(FieldRef, Vec<u8>)>,
+}
+
+
+impl QueryTerms {
+    /// Finds all the terms that a query could match documents with
+    ///
+    /// Terms in "must_not" clauses are ignored as they can't be in any of the hits.
+    pub fn from_query(query: &Query) -> QueryTerms {
+        let mut query_terms = QueryTerms {
+            terms: Vec::new(),
+            prefixes: Vec::new(),
+        };
+
+        query_terms.add_query(query);
+        query_terms
+    }
+
+    fn add_query(&mut self, query: &Query) {
+        match *query {
+            Query::Term{field, ref term, ..} => {
+                self.terms.push((field, term.as_bytes().to_vec()));
+            }
+            Query::MultiTerm{field, term_selector: TermSelector::Prefix(ref prefix), ..} => {
+                self.prefixes.push((field, prefix.as_bytes().to_vec()));
+            }
+            Query::Conjunction{ref queries, ..} |
+            Query::Disjunction{ref queries, ..} |
+            Query::DisjunctionMax{ref queries, ..} => {
+                for query in queries.iter() {
+                    self.add_query(query);
+                }
+            }
+            Query::Filter{ref query, ref filter, ..} => {
+                self.add_query(query);
+                self.add_query(filter);
+            }
+            Query::Exclude{ref query, ..} => {
+                self.add_query(query);
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns the index of the first term or prefix that matches the given term
+    ///
+    /// If `field` is None, terms searched for in any field are used.
+    fn find_match(&self, field: Option<FieldRef>, term: &[u8]) -> Option<usize> {
+        let field_matches = |term_field: &FieldRef| field.map_or(true, |field| field == *term_field);
+
+        for (i, &(ref term_field, ref query_term)) in self.terms.iter().enumerate() {
+            if field_matches(term_field) && &query_term[..] == term {
+                return Some(i);
+            }
+        }
+
+        for (i, &(ref term_field, ref prefix)) in self.prefixes.iter().enumerate() {
+            if field_matches(term_field) && term.starts_with(prefix) {
+                return Some(self.terms.len() + i);
+            }
+        }
+
+        None
+    }
+}
+
+
+/// A highlighted term in the text
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Span {
+    start: usize,
+    end: usize,
+
+    /// Which of the query's terms matched, used to pick the tags
+    term: usize,
+}
+
+
+/// Splits text into the words (and the gaps between them) that it's made of
+///
+/// Returns the start and end of each piece in bytes.
+pub fn split_words(text: &str) -> Vec<(usize, usize)> {
+    let mut words = Vec::new();
+    let mut start = 0;
+    for word in text.split_word_bounds() {
+        words.push((start, start + word.len()));
+        start += word.len();
+    }
+
+    words
+}
+
+
+/// Finds the words in the text that match any of the query's terms
+fn find_spans(text: &str, analyzer: Option<&AnalyzerSpec>, field: Option<FieldRef>, query_terms: &QueryTerms) -> Vec<Span> {
+    let analyzer = match analyzer {
+        Some(analyzer) => analyzer,
+        None => {
+            // Fields that aren't analyzed are indexed as a single term
+            return match query_terms.find_match(field, text.as_bytes()) {
+                Some(term) if !text.is_empty() => vec![Span {start: 0, end: text.len(), term: term}],
+                _ => Vec::new(),
+            };
+        }
+    };
+
+    let mut spans = Vec::new();
+    for (start, end) in split_words(text) {
+        let word = &text[start..end];
+        if !word.chars().any(|c| c.is_alphanumeric()) {
+            continue;
+        }
+
+        for token in analyzer.initialise(word) {
+            if let Some(term) = query_terms.find_match(field, token.term.as_bytes()) {
+                spans.push(Span {start: start, end: end, term: term});
+                break;
+            }
+        }
+    }
+
+    spans
+}
+
+
+fn encode(text: &str, encoder: Encoder) -> String {
+    match encoder {
+        Encoder::Default => text.to_string(),
+        Encoder::Html => {
+            let mut encoded = String::with_capacity(text.len());
+            for c in text.chars() {
+                match c {
+                    '&' => encoded.push_str("&amp;"),
+                    '<' => encoded.push_str("&lt;"),
+                    '>' => encoded.push_str("&gt;"),
+                    '"' => encoded.push_str("&quot;"),
+                    '\'' => encoded.push_str("&#x27;"),
+                    '/' => encoded.push_str("&#x2F;"),
+                    c => encoded.push(c),
+                }
+            }
+            encoded
+        }
+    }
+}
+
+
+/// Narrows a fragment so it doesn't start or end with whitespace
+fn trim_fragment(text: &str, start: usize, end: usize) -> (usize, usize) {
+    let fragment = &text[start..end];
+    let trimmed_start = start + (fragment.len() - fragment.trim_left().len());
+    let trimmed_end = end - (fragment.len() - fragment.trim_right().len());
+
+    if trimmed_start >= trimmed_end {
+        (start, start)
+    } else {
+        (trimmed_start, trimmed_end)
+    }
+}
+
+
+/// Splits the text into fragments, returning the start and end of each one in bytes
+fn find_fragments(text: &str, spans: &[Span], options: &HighlightOptions) -> Vec<(usize, usize)> {
+    let words = split_words(text);
+    let fragment_size = if options.fragment_size > 0 { options.fragment_size } else { 1 };
+    let mut fragments = Vec::new();
+
+    match options.fragmenter {
+        Fragmenter::Simple => {
+            let mut start = 0;
+            for &(word_start, word_end) in words.iter() {
+                if word_end - start > fragment_size && word_start > start {
+                    fragments.push((start, word_start));
+                    start = word_start;
+                }
+            }
+
+            if start < text.len() {
+                fragments.push((start, text.len()));
+            }
+        }
+        Fragmenter::Span => {
+            let mut previous_end = 0;
+            for span in spans.iter() {
+                if span.start < previous_end {
+                    continue;
+                }
+
+                // Take the words that fit either side of the span
+                let margin = fragment_size.saturating_sub(span.end - span.start) / 2;
+                let start = words.iter()
+                    .map(|&(word_start, _)| word_start)
+                    .find(|&word_start| word_start + margin >= span.start && word_start >= previous_end)
+                    .unwrap_or(span.start);
+                let end = words.iter()
+                    .map(|&(_, word_end)| word_end)
+                    .filter(|&word_end| word_end >= span.end && word_end <= start + fragment_size)
+                    .last()
+                    .unwrap_or(span.end);
+
+                fragments.push((start, end));
+                previous_end = end;
+            }
+        }
+    }
+
+    fragments.into_iter().map(|(start, end)| trim_fragment(text, start, end)).filter(|&(start, end)| start < end).collect()
+}
+
+
+/// Wraps the spans in a fragment with tags
+fn render_fragment(text: &str, start: usize, end: usize, spans: &[Span], options: &HighlightOptions) -> String {
+    let mut rendered = String::new();
+    let mut position = start;
+
+    for span in spans.iter().filter(|span| span.start >= start && span.end <= end) {
+        let tag = span.term % options.pre_tags.len();
+
+        rendered.push_str(&encode(&text[position..span.start], options.encoder));
+        rendered.push_str(&options.pre_tags[tag]);
+        rendered.push_str(&encode(&text[span.start..span.end], options.encoder));
+        rendered.push_str(&options.post_tags[tag % options.post_tags.len()]);
+        position = span.end;
+    }
+
+    rendered.push_str(&encode(&text[position..end], options.encoder));
+    rendered
+}
+
+
+/// Highlights the query's terms in one value of a field
+///
+/// Returns the fragments with their scores (the number of highlighted terms in each one).
+fn highlight_text(text: &str, analyzer: Option<&AnalyzerSpec>, field: Option<FieldRef>, query_terms: &QueryTerms, options: &HighlightOptions) -> Vec<(usize, String)> {
+    let spans = find_spans(text, analyzer, field, query_terms);
+    if spans.is_empty() {
+        return Vec::new();
+    }
+
+    if options.number_of_fragments == 0 {
+        return vec![(spans.len(), render_fragment(text, 0, text.len(), &spans, options))];
+    }
+
+    find_fragments(text, &spans, options).into_iter().filter_map(|(start, end)| {
+        let score = spans.iter().filter(|span| span.start >= start && span.end <= end).count();
+        if score > 0 {
+            Some((score, render_fragment(text, start, end, &spans, options)))
+        } else {
+            None
+        }
+    }).collect()
+}
+
+
+/// Returns the start of the text to use when nothing in a field matched
+fn no_match_fragment(text: &str, options: &HighlightOptions) -> Option<String> {
+    if options.no_match_size == 0 {
+        return None;
+    }
+
+    let end = split_words(text).into_iter()
+        .map(|(_, word_end)| word_end)
+        .take_while(|&word_end| word_end <= options.no_match_size)
+        .last()
+        .unwrap_or(0);
+
+    let (start, end) = trim_fragment(text, 0, end);
+    if start < end {
+        Some(encode(&text[start..end], options.encoder))
+    } else {
+        None
+    }
+}
+
+
+/// Reads the text values of a field from a document's source
+///
+/// Dots in the field name are followed into inner objects.
+pub fn get_source_values<'a>(source: &'a Json, field_name: &str) -> Vec<&'a str> {
+    let mut value = source;
+    for part in field_name.split('.') {
+        value = match value.as_object().and_then(|object| object.get(part)) {
+            Some(value) => value,
+            None => return Vec::new(),
+        };
+    }
+
+    match *value {
+        Json::String(ref string) => vec![string],
+        Json::Array(ref array) => array.iter().filter_map(|item| item.as_str()).collect(),
+        _ => Vec::new(),
+    }
+}
+
+
+impl HighlightRequest {
+    /// Highlights a hit, returning the "highlight" section for it
+    ///
+    /// Returns None if none of the fields had anything to highlight.
+    pub fn highlight(&self, source: &Json, index_metadata: &IndexMetadata, schema: &Schema, query_terms: &QueryTerms) -> Option<Json> {
+        let mut highlight = serde_json::Map::new();
+
+        for &(ref field_name, ref options) in self.fields.iter() {
+            let analyzer = match index_metadata.get_field_mapping(field_name) {
+                Some(field_mapping) => field_mapping.index_analyzer(),
+                None => continue,
+            };
+
+            let field = if options.require_field_match {
+                match schema.get_field_by_name(field_name) {
+                    Some(field) => Some(field),
+                    None => continue,
+                }
+            } else {
+                None
+            };
+
+            let values = get_source_values(source, field_name);
+
+            let mut fragments = Vec::new();
+            for value in values.iter() {
+                fragments.extend(highlight_text(value, analyzer, field, query_terms, options));
+            }
+
+            if options.order == FragmentOrder::Score {
+                // Sorting is stable so fragments with the same score stay in order
+                fragments.sort_by(|a, b| b.0.cmp(&a.0));
+            }
+
+            let mut fragments = fragments.into_iter().map(|(_, fragment)| fragment).collect::<Vec<_>>();
+            if options.number_of_fragments > 0 {
+                fragments.truncate(options.number_of_fragments);
+            }
+
+            if fragments.is_empty() {
+                if let Some(fragment) = values.first().and_then(|value| no_match_fragment(value, options)) {
+                    fragments.push(fragment);
+                }
+            }
+
+            if !fragments.is_empty() {
+                highlight.insert(field_name.clone(), json!(fragments));
+            }
+        }
+
+        if highlight.is_empty() {
+            None
+        } else {
+            Some(Json::Object(highlight))
+        }
+    }
+}
+
+
+fn parse_tags(json: &Json) -> Result<Vec<String>, HighlightParseError> {
+    match *json {
+        Json::String(ref tag) => Ok(vec![tag.clone()]),
+        Json::Array(ref array) => {
+            let mut tags = Vec::new();
+            for tag in array.iter() {
+                tags.push(try!(tag.as_str().ok_or(HighlightParseError::ExpectedString)).to_string());
+            }
+            Ok(tags)
+        }
+        _ => Err(HighlightParseError::ExpectedStringOrArray),
+    }
+}
+
+
+fn parse_positive_integer(json: &Json) -> Result<usize, HighlightParseError> {
+    json.as_u64().map(|value| value as usize).ok_or(HighlightParseError::ExpectedPositiveInteger)
+}
+
+
+/// Applies an option to a set of highlight options
+///
+/// Returns false if the key isn't an option.
+fn parse_option(options: &mut HighlightOptions, key: &str, value: &Json) -> Result<bool, HighlightParseError> {
+    match key {
+        "pre_tags" => options.pre_tags = try!(parse_tags(value)),
+        "post_tags" => options.post_tags = try!(parse_tags(value)),
+        "encoder" => {
+            options.encoder = match try!(value.as_str().ok_or(HighlightParseError::ExpectedString)) {
+                "default" => Encoder::Default,
+                "html" => Encoder::Html,
+                encoder => return Err(HighlightParseError::InvalidEncoder(encoder.to_string())),
+            };
+        }
+        "fragmenter" => {
+            options.fragmenter = match try!(value.as_str().ok_or(HighlightParseError::ExpectedString)) {
+                "simple" => Fragmenter::Simple,
+                "span" => Fragmenter::Span,
+                fragmenter => return Err(HighlightParseError::InvalidFragmenter(fragmenter.to_string())),
+            };
+        }
+        "order" => {
+            options.order = match try!(value.as_str().ok_or(HighlightParseError::ExpectedString)) {
+                "none" => FragmentOrder::None,
+                "score" => FragmentOrder::Score,
+                order => return Err(HighlightParseError::InvalidOrder(order.to_string())),
+            };
+        }
+        "fragment_size" => options.fragment_size = try!(parse_positive_integer(value)),
+        "number_of_fragments" => options.number_of_fragments = try!(parse_positive_integer(value)),
+        "no_match_size" => options.no_match_size = try!(parse_positive_integer(value)),
+        "require_field_match" => options.require_field_match = try!(value.as_bool().ok_or(HighlightParseError::ExpectedBoolean)),
+        _ => return Ok(false),
+    }
+
+    // Tags can't be empty as every highlighted term needs one
+    if options.pre_tags.is_empty() {
+        options.pre_tags = HighlightOptions::default().pre_tags;
+    }
+
+    if options.post_tags.is_empty() {
+        options.post_tags = HighlightOptions::default().post_tags;
+    }
+
+    Ok(true)
+}
+
+
+fn parse_field_options(json: &Json, defaults: &HighlightOptions) -> Result<HighlightOptions, HighlightParseError> {
+    let object = try!(json.as_object().ok_or(HighlightParseError::ExpectedObject));
+
+    let mut options = defaults.clone();
+    for (key, value) in object.iter() {
+        if !try!(parse_option(&mut options, key, value)) {
+            return Err(HighlightParseError::UnrecognisedKey(key.clone()));
+        }
+    }
+
+    Ok(options)
+}
+
+
+/// Parses the "highlight" section of a search request
+pub fn parse(json: &Json) -> Result<HighlightRequest, HighlightParseError> {
+    let object = try!(json.as_object().ok_or(HighlightParseError::ExpectedObject));
+
+    // Options given at the top level are the defaults for every field
+    let mut defaults = HighlightOptions::default();
+    for (key, value) in object.iter() {
+        if key != "fields" && !try!(parse_option(&mut defaults, key, value)) {
+            return Err(HighlightParseError::UnrecognisedKey(key.clone()));
+        }
+    }
+
+    let mut field_options = Vec::new();
+    match object.get("fields") {
+        Some(&Json::Object(ref fields)) => {
+            for (field_name, options) in fields.iter() {
+                field_options.push((field_name.clone(), options));
+            }
+        }
+        Some(&Json::Array(ref fields)) => {
+            // An array of single key objects keeps the fields in order
+            for field in fields.iter() {
+                let field = try!(field.as_object().ok_or(HighlightParseError::ExpectedObject));
+                for (field_name, options) in field.iter() {
+                    field_options.push((field_name.clone(), options));
+                }
+            }
+        }
+        Some(_) => return Err(HighlightParseError::ExpectedObjectOrArray),
+        None => {}
+    }
+
+    let mut fields = Vec::new();
+    for (field_name, options) in field_options {
+        match parse_field_options(options, &defaults) {
+            Ok(options) => fields.push((field_name, options)),
+            Err(e) => return Err(HighlightParseError::InvalidField(field_name, Box::new(e))),
+        }
+    }
+
+    Ok(HighlightRequest {
+        fields: fields,
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+    use kite::{Term, Query, TermScorer};
+    use kite::schema::{Schema, FieldRef, FieldType, FIELD_INDEXED};
+
+    use analysis::AnalyzerSpec;
+    use analysis::tokenizers::TokenizerSpec;
+    use analysis::filters::FilterSpec;
+
+    use super::{parse, HighlightOptions, HighlightParseError, Encoder, Fragmenter, FragmentOrder, QueryTerms, highlight_text, no_match_fragment};
+
+    fn analyzer() -> AnalyzerSpec {
+        AnalyzerSpec {
+            char_filters: vec![],
+            tokenizer: TokenizerSpec::Standard,
+            filters: vec![
+                FilterSpec::Lowercase,
+            ],
+        }
+    }
+
+    fn make_fields() -> (FieldRef, FieldRef) {
+        let mut schema = Schema::new();
+        let title_field = schema.add_field("title".to_string(), FieldType::Text, FIELD_INDEXED).unwrap();
+        let body_field = schema.add_field("body".to_string(), FieldType::Text, FIELD_INDEXED).unwrap();
+        (title_field, body_field)
+    }
+
+    fn query_terms(field: FieldRef, terms: &[&str]) -> QueryTerms {
+        QueryTerms::from_query(&Query::Disjunction {
+            queries: terms.iter().map(|term| {
+                Query::Term {
+                    field: field,
+                    term: Term::from_string(term),
+                    scorer: TermScorer::default(),
+                }
+            }).collect(),
+        })
+    }
+
+    fn fragments(highlighted: Vec<(usize, String)>) -> Vec<String> {
+        highlighted.into_iter().map(|(_, fragment)| fragment).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        let request = parse(&serde_json::from_str("
+        {
+            \"pre_tags\": [\"<b>\"],
+            \"post_tags\": [\"</b>\"],
+            \"fields\": {
+                \"title\": {
+                    \"number_of_fragments\": 0
+                },
+                \"body\": {
+                    \"fragment_size\": 50,
+                    \"fragmenter\": \"simple\",
+                    \"order\": \"score\",
+                    \"encoder\": \"html\"
+                }
+            }
+        }
+        ").unwrap()).unwrap();
+
+        let defaults = HighlightOptions {
+            pre_tags: vec!["<b>".to_string()],
+            post_tags: vec!["</b>".to_string()],
+            ..HighlightOptions::default()
+        };
+
+        assert_eq!(request.fields, vec![
+            ("body".to_string(), HighlightOptions {
+                fragment_size: 50,
+                fragmenter: Fragmenter::Simple,
+                order: FragmentOrder::Score,
+                encoder: Encoder::Html,
+                ..defaults.clone()
+            }),
+            ("title".to_string(), HighlightOptions {
+                number_of_fragments: 0,
+                ..defaults.clone()
+            }),
+        ]);
+    }
+
+    #[test]
+    fn test_parse_invalid_field_option() {
+        let request = parse(&serde_json::from_str("
+        {
+            \"fields\": {
+                \"title\": {
+                    \"encoder\": \"xml\"
+                }
+            }
+        }
+        ").unwrap());
+
+        assert_eq!(request, Err(HighlightParseError::InvalidField("title".to_string(), Box::new(HighlightParseError::InvalidEncoder("xml".to_string())))));
+    }
+
+    #[test]
+    fn test_highlight_whole_field() {
+        let (field, _) = make_fields();
+        let options = HighlightOptions {
+            number_of_fragments: 0,
+            ..HighlightOptions::default()
+        };
+
+        let highlighted = highlight_text("The Quick brown fox, quickly", Some(&analyzer()), Some(field), &query_terms(field, &["quick", "fox"]), &options);
+
+        assert_eq!(fragments(highlighted), vec!["The <em>Quick</em> brown <em>fox</em>, quickly".to_string()]);
+    }
+
+    #[test]
+    fn test_highlight_other_field() {
+        let (field, other_field) = make_fields();
+        let options = HighlightOptions::default();
+
+        assert_eq!(highlight_text("The quick brown fox", Some(&analyzer()), Some(field), &query_terms(other_field, &["fox"]), &options), vec![]);
+        assert_eq!(fragments(highlight_text("The quick brown fox", Some(&analyzer()), None, &query_terms(other_field, &["fox"]), &options)), vec!["The quick brown <em>fox</em>".to_string()]);
+    }
+
+    #[test]
+    fn test_highlight_fragments() {
+        let (field, _) = make_fields();
+        let options = HighlightOptions {
+            fragment_size: 20,
+            ..HighlightOptions::default()
+        };
+        let text = "One two three four five six seven eight nine ten eleven twelve thirteen fourteen";
+
+        let highlighted = highlight_text(text, Some(&analyzer()), Some(field), &query_terms(field, &["two", "twelve"]), &options);
+
+        assert_eq!(fragments(highlighted), vec![
+            "One <em>two</em> three four".to_string(),
+            "eleven <em>twelve</em>".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn test_highlight_html_encoder() {
+        let (field, _) = make_fields();
+        let options = HighlightOptions {
+            encoder: Encoder::Html,
+            pre_tags: vec!["<b>".to_string(), "<i>".to_string()],
+            post_tags: vec!["</b>".to_string(), "</i>".to_string()],
+            ..HighlightOptions::default()
+        };
+
+        let highlighted = highlight_text("Tom & Jerry <3", Some(&analyzer()), Some(field), &query_terms(field, &["tom", "jerry"]), &options);
+
+        assert_eq!(fragments(highlighted), vec!["<b>Tom</b> &amp; <i>Jerry</i> &lt;3".to_string()]);
+    }
+
+    #[test]
+    fn test_highlight_not_analyzed() {
+        let (field, _) = make_fields();
+        let options = HighlightOptions::default();
+
+        let highlighted = highlight_text("New York", None, Some(field), &query_terms(field, &["New York"]), &options);
+
+        assert_eq!(fragments(highlighted), vec!["<em>New York</em>".to_string()]);
+    }
+
+    #[test]
+    fn test_no_match_fragment() {
+        let options = HighlightOptions {
+            no_match_size: 12,
+            ..HighlightOptions::default()
+        };
+
+        assert_eq!(no_match_fragment("The quick brown fox", &options), Some("The quick".to_string()));
+        assert_eq!(no_match_fragment("The quick brown fox", &HighlightOptions::default()), None);
+    }
+}
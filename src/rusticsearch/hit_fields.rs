@@ -0,0 +1,297 @@
+//! Parses the "stored_fields" and "docvalue_fields" sections of a search request
+//!
+//! Both of these add values to the "fields" section of each hit. Doc values are kept as
+//! stored fields, so the difference is that doc value fields may be formatted.
+
+use chrono::{DateTime, UTC, Datelike, Timelike};
+use serde_json::Value as Json;
+use kite::document::FieldValue;
+
+use aggregations::datetime_to_millis;
+
+
+#[derive(Debug, PartialEq)]
+pub enum HitFieldsParseError {
+    ExpectedString,
+    ExpectedStringOrArray,
+    ExpectedStringOrObject,
+    MissingField,
+    UnrecognisedKey(String),
+    InvalidFormat(String),
+}
+
+
+/// The stored fields to return with each hit
+#[derive(Debug, Clone, PartialEq)]
+pub enum StoredFields {
+    /// "_none_", don't return any stored fields or the source
+    None,
+    Fields(Vec<String>),
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DatePatternItem {
+    Year,
+    Month,
+    Day,
+    Hour,
+    Minute,
+    Second,
+    Millisecond,
+    Literal(String),
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DateFormat {
+    EpochMillis,
+    EpochSecond,
+    Pattern(Vec<DatePatternItem>),
+}
+
+
+impl DateFormat {
+    /// The format that dates are given in when no format is requested
+    pub fn date_time() -> DateFormat {
+        DateFormat::parse("yyyy-MM-dd'T'HH:mm:ss.SSS'Z'").unwrap()
+    }
+
+    /// Parses a named format or a pattern such as "yyyy-MM-dd"
+    pub fn parse(format: &str) -> Result<DateFormat, HitFieldsParseError> {
+        match format {
+            "epoch_millis" => return Ok(DateFormat::EpochMillis),
+            "epoch_second" => return Ok(DateFormat::EpochSecond),
+            "date_time" | "strict_date_time" | "date_optional_time" | "strict_date_optional_time" => return Ok(DateFormat::date_time()),
+            "date" | "strict_date" => return DateFormat::parse("yyyy-MM-dd"),
+            "basic_date" => return DateFormat::parse("yyyyMMdd"),
+            _ => {}
+        }
+
+        let mut items = Vec::new();
+        let mut chars = format.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            if c == '\'' {
+                // Quoted text is copied as it is
+                let mut literal = String::new();
+                while let Some(c) = chars.next() {
+                    if c == '\'' {
+                        break;
+                    }
+                    literal.push(c);
+                }
+                items.push(DatePatternItem::Literal(literal));
+            } else if c.is_alphabetic() {
+                let mut length = 1;
+                while chars.peek() == Some(&c) {
+                    chars.next();
+                    length += 1;
+                }
+
+                items.push(match (c, length) {
+                    ('y', 4) => DatePatternItem::Year,
+                    ('M', 2) => DatePatternItem::Month,
+                    ('d', 2) => DatePatternItem::Day,
+                    ('H', 2) => DatePatternItem::Hour,
+                    ('m', 2) => DatePatternItem::Minute,
+                    ('s', 2) => DatePatternItem::Second,
+                    ('S', 3) => DatePatternItem::Millisecond,
+                    _ => return Err(HitFieldsParseError::InvalidFormat(format.to_string())),
+                });
+            } else {
+                items.push(DatePatternItem::Literal(c.to_string()));
+            }
+        }
+
+        Ok(DateFormat::Pattern(items))
+    }
+
+    pub fn format(&self, date_time: &DateTime<UTC>) -> Json {
+        match *self {
+            DateFormat::EpochMillis => json!(datetime_to_millis(date_time)),
+            DateFormat::EpochSecond => json!(date_time.timestamp()),
+            DateFormat::Pattern(ref items) => {
+                let mut formatted = String::new();
+                for item in items.iter() {
+                    match *item {
+                        DatePatternItem::Year => formatted.push_str(&format!("{:04}", date_time.year())),
+                        DatePatternItem::Month => formatted.push_str(&format!("{:02}", date_time.month())),
+                        DatePatternItem::Day => formatted.push_str(&format!("{:02}", date_time.day())),
+                        DatePatternItem::Hour => formatted.push_str(&format!("{:02}", date_time.hour())),
+                        DatePatternItem::Minute => formatted.push_str(&format!("{:02}", date_time.minute())),
+                        DatePatternItem::Second => formatted.push_str(&format!("{:02}", date_time.second())),
+                        DatePatternItem::Millisecond => formatted.push_str(&format!("{:03}", date_time.nanosecond() / 1_000_000)),
+                        DatePatternItem::Literal(ref literal) => formatted.push_str(literal),
+                    }
+                }
+                Json::String(formatted)
+            }
+        }
+    }
+}
+
+
+/// A field to read from doc values
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocValueField {
+    pub field: String,
+
+    /// How dates are formatted, defaults to "date_time"
+    pub format: Option<DateFormat>,
+}
+
+
+impl DocValueField {
+    pub fn value_to_json(&self, value: FieldValue) -> Json {
+        match value {
+            FieldValue::String(string) => Json::String(string),
+            FieldValue::Integer(integer) => json!(integer),
+            FieldValue::Boolean(boolean) => Json::Bool(boolean),
+            FieldValue::DateTime(date_time) => {
+                match self.format {
+                    Some(ref format) => format.format(&date_time),
+                    None => DateFormat::date_time().format(&date_time),
+                }
+            }
+        }
+    }
+}
+
+
+fn parse_field_names(json: &Json) -> Result<Vec<String>, HitFieldsParseError> {
+    match *json {
+        Json::String(ref field_name) => Ok(vec![field_name.clone()]),
+        Json::Array(ref array) => {
+            let mut field_names = Vec::with_capacity(array.len());
+            for field_name in array.iter() {
+                field_names.push(try!(field_name.as_str().ok_or(HitFieldsParseError::ExpectedString)).to_string());
+            }
+            Ok(field_names)
+        }
+        _ => Err(HitFieldsParseError::ExpectedStringOrArray),
+    }
+}
+
+
+/// Parses the "stored_fields" section of a search request
+///
+/// This may be a field name or an array of them.
+pub fn parse_stored_fields(json: &Json) -> Result<StoredFields, HitFieldsParseError> {
+    let field_names = try!(parse_field_names(json));
+
+    if field_names.len() == 1 && field_names[0] == "_none_" {
+        Ok(StoredFields::None)
+    } else {
+        Ok(StoredFields::Fields(field_names))
+    }
+}
+
+
+fn parse_docvalue_field(json: &Json) -> Result<DocValueField, HitFieldsParseError> {
+    match *json {
+        Json::String(ref field) => {
+            Ok(DocValueField {
+                field: field.clone(),
+                format: None,
+            })
+        }
+        Json::Object(ref object) => {
+            let mut field = None;
+            let mut format = None;
+
+            for (key, value) in object.iter() {
+                match key.as_ref() {
+                    "field" => {
+                        field = Some(try!(value.as_str().ok_or(HitFieldsParseError::ExpectedString)).to_string());
+                    }
+                    "format" => {
+                        let value = try!(value.as_str().ok_or(HitFieldsParseError::ExpectedString));
+                        if value != "use_field_mapping" {
+                            format = Some(try!(DateFormat::parse(value)));
+                        }
+                    }
+                    _ => return Err(HitFieldsParseError::UnrecognisedKey(key.clone())),
+                }
+            }
+
+            Ok(DocValueField {
+                field: try!(field.ok_or(HitFieldsParseError::MissingField)),
+                format: format,
+            })
+        }
+        _ => Err(HitFieldsParseError::ExpectedStringOrObject),
+    }
+}
+
+
+/// Parses the "docvalue_fields" section of a search request
+///
+/// This is an array of field names or objects with "field" and "format" keys.
+pub fn parse_docvalue_fields(json: &Json) -> Result<Vec<DocValueField>, HitFieldsParseError> {
+    match *json {
+        Json::Array(ref array) => {
+            let mut fields = Vec::with_capacity(array.len());
+            for field in array.iter() {
+                fields.push(try!(parse_docvalue_field(field)));
+            }
+            Ok(fields)
+        }
+        _ => Ok(vec![try!(parse_docvalue_field(json))]),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use kite::document::FieldValue;
+
+    use super::{parse_stored_fields, parse_docvalue_fields, StoredFields, DocValueField, DateFormat, HitFieldsParseError};
+
+    #[test]
+    fn test_parse_stored_fields() {
+        assert_eq!(parse_stored_fields(&json!("title")), Ok(StoredFields::Fields(vec!["title".to_string()])));
+        assert_eq!(parse_stored_fields(&json!(["title", "pk"])), Ok(StoredFields::Fields(vec!["title".to_string(), "pk".to_string()])));
+        assert_eq!(parse_stored_fields(&json!("_none_")), Ok(StoredFields::None));
+        assert_eq!(parse_stored_fields(&json!(1)), Err(HitFieldsParseError::ExpectedStringOrArray));
+    }
+
+    #[test]
+    fn test_parse_docvalue_fields() {
+        assert_eq!(parse_docvalue_fields(&json!(["pk", {"field": "published", "format": "epoch_millis"}])), Ok(vec![
+            DocValueField {
+                field: "pk".to_string(),
+                format: None,
+            },
+            DocValueField {
+                field: "published".to_string(),
+                format: Some(DateFormat::EpochMillis),
+            },
+        ]));
+        assert_eq!(parse_docvalue_fields(&json!([{"format": "date"}])), Err(HitFieldsParseError::MissingField));
+        assert_eq!(parse_docvalue_fields(&json!([{"field": "published", "format": "yyyy-QQ"}])), Err(HitFieldsParseError::InvalidFormat("yyyy-QQ".to_string())));
+    }
+
+    #[test]
+    fn test_date_formats() {
+        let date = "2017-03-01T12:05:09.042Z".parse().unwrap();
+
+        assert_eq!(DateFormat::date_time().format(&date), json!("2017-03-01T12:05:09.042Z"));
+        assert_eq!(DateFormat::parse("date").unwrap().format(&date), json!("2017-03-01"));
+        assert_eq!(DateFormat::parse("dd/MM/yyyy 'at' HH:mm").unwrap().format(&date), json!("01/03/2017 at 12:05"));
+        assert_eq!(DateFormat::parse("epoch_millis").unwrap().format(&date), json!(1488369909042i64));
+        assert_eq!(DateFormat::parse("epoch_second").unwrap().format(&date), json!(1488369909));
+    }
+
+    #[test]
+    fn test_value_to_json() {
+        let field = DocValueField {
+            field: "published".to_string(),
+            format: Some(DateFormat::parse("yyyy").unwrap()),
+        };
+
+        assert_eq!(field.value_to_json(FieldValue::DateTime("2017-03-01T12:00:00Z".parse().unwrap())), json!("2017"));
+        assert_eq!(field.value_to_json(FieldValue::Integer(5)), json!(5));
+        assert_eq!(field.value_to_json(FieldValue::String("foo".to_string())), json!("foo"));
+    }
+}
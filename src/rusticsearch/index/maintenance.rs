@@ -1,56 +1,63 @@
 use index::Index;
 
 
-impl Index {
-    /// Run a maintenance task on the index
-    /// This must be run periodically by a background thread. It is not currently thread-safe
-    pub fn run_maintenance_task(&self) -> Result<(), String> {
-        let segment_stats = try!(self.store.get_segment_statistics());
+/// Decides which segments should be merged together by the maintenance task
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergePolicy {
+    /// The minimum number of similarly sized segments before a merge is started
+    pub min_segments_to_merge: usize,
+
+    /// The maximum number of documents that a merged segment can hold
+    pub max_segment_docs: u32,
+}
 
-        // TODO: Deactivate segments with 100% deletions
-        // TODO: Vacuum segments with many deletions
 
-        // Merge segments
+impl Default for MergePolicy {
+    fn default() -> MergePolicy {
+        MergePolicy {
+            min_segments_to_merge: 3,
+            max_segment_docs: 65536,
+        }
+    }
+}
+
 
-        // Firstly we classify each active segment into one of 5 groups (based on the number of
-        // total documents they have):
+impl MergePolicy {
+    /// Chooses a group of segments to merge given each segment's total number of documents
+    ///
+    /// Returns an empty Vec if there is nothing worth merging.
+    pub fn select_segments_to_merge(&self, segments: Vec<(u32, i64)>) -> Vec<u32> {
+        // Firstly we classify each active segment into a group based on the number of digits in
+        // its total number of documents:
         // Group 1: 1 - 9 docs
         // Group 2: 10 - 99 docs
         // Group 3: 100 - 999 docs
-        // Group 4: 1000 - 9999 docs
-        // Group 5: 10000 - 65536 docs
-
-        // The group with the most active segments can perform a merge. A merge can be done on
-        // between 5 - 1000 segments at a time. The smallest segments get merged first.
-
-        let mut segments_g1 = Vec::new();
-        let mut segments_g2 = Vec::new();
-        let mut segments_g3 = Vec::new();
-        let mut segments_g4 = Vec::new();
-        let mut segments_g5 = Vec::new();
-
-        for (segment, stats) in segment_stats {
-            match stats.total_docs() {
-                1 ... 9 => segments_g1.push((segment, stats)),
-                10 ... 99 => segments_g2.push((segment, stats)),
-                100 ... 999 => segments_g3.push((segment, stats)),
-                1000 ... 9999 => segments_g4.push((segment, stats)),
-                10000 ... 65536 => segments_g5.push((segment, stats)),
-                _ => {},
+        // ...and so on, up to the group that holds max_segment_docs. Segments with more
+        // documents than that are full so they're left alone.
+
+        // The group with the most active segments can perform a merge. The smallest segments get
+        // merged first.
+
+        let mut segments_grouped = vec![Vec::new(); number_of_digits(self.max_segment_docs as i64)];
+
+        for (segment, total_docs) in segments {
+            if total_docs < 1 || total_docs > self.max_segment_docs as i64 {
+                continue;
             }
+
+            segments_grouped[number_of_digits(total_docs) - 1].push((segment, total_docs));
         }
 
         // Now sort the groups by length in ascending order
-        let mut segments_grouped = vec![segments_g1, segments_g2, segments_g3, segments_g4, segments_g5];
         segments_grouped.sort_by_key(|group| group.len());
 
         // The group with the most segments is our merge candidate. Check that it has above the
-        // minimum number of documents to start a merge and truncate it to be less than the maximum.
+        // minimum number of segments to start a merge.
         let mut group_to_merge = segments_grouped.pop().unwrap();
 
-        if group_to_merge.len() < 3 {
+        if group_to_merge.len() < self.min_segments_to_merge {
             // No point in merging these
-            return Ok(());
+            return Vec::new();
         }
 
         // Now we've found a group of segments to merge, we must check that all the docs will fit in a
@@ -60,24 +67,127 @@ impl Index {
         let mut current_doc_count: u32 = 0;
         let mut segment_ids = Vec::new();
 
-        // Sort segments total_docs in descending order
-        // TODO: Check that this is descending order
-        group_to_merge.sort_by_key(|&(_, ref stats)| -stats.total_docs());
+        // Sort segments by total_docs in descending order
+        group_to_merge.sort_by_key(|&(_, total_docs)| -total_docs);
 
-        for (segment, stats) in group_to_merge {
-            if current_doc_count + stats.total_docs() as u32 > 65536 {
+        for (segment, total_docs) in group_to_merge {
+            if current_doc_count + total_docs as u32 > self.max_segment_docs {
                 // No space for this segment
                 continue;
             }
 
             segment_ids.push(segment);
-            current_doc_count += stats.total_docs() as u32;
+            current_doc_count += total_docs as u32;
+        }
+
+        if segment_ids.len() < 2 {
+            // Merging a single segment into itself wouldn't achieve anything
+            return Vec::new();
         }
 
+        segment_ids
+    }
+}
+
+
+/// Counts the digits in a positive number
+fn number_of_digits(mut number: i64) -> usize {
+    let mut digits = 1;
+
+    while number >= 10 {
+        number /= 10;
+        digits += 1;
+    }
+
+    digits
+}
+
+
+impl Index {
+    /// Run a maintenance task on the index
+    /// This must be run periodically by a background thread. It is not currently thread-safe
+    pub fn run_maintenance_task(&self) -> Result<(), String> {
+        let store = try!(self.store());
+        let segment_stats = try!(store.get_segment_statistics());
+
+        // TODO: Deactivate segments with 100% deletions
+        // TODO: Vacuum segments with many deletions
+
         // Merge segments
-        try!(self.store.merge_segments(&segment_ids));
-        try!(self.store.purge_segments(&segment_ids));
+        let segments = segment_stats.into_iter().map(|(segment, stats)| (segment, stats.total_docs())).collect();
+        let merge_policy = self.metadata.read().unwrap().settings.merge_policy.clone();
+        let segment_ids = merge_policy.select_segments_to_merge(segments);
+
+        if segment_ids.is_empty() {
+            return Ok(());
+        }
+
+        try!(store.merge_segments(&segment_ids));
+        try!(store.purge_segments(&segment_ids));
 
         Ok(())
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::MergePolicy;
+
+    #[test]
+    fn test_merges_largest_group() {
+        let policy = MergePolicy::default();
+        let segments = vec![(1, 5), (2, 50), (3, 3), (4, 8), (5, 500)];
+
+        assert_eq!(policy.select_segments_to_merge(segments), vec![4, 1, 3]);
+    }
+
+    #[test]
+    fn test_too_few_segments() {
+        let policy = MergePolicy::default();
+        let segments = vec![(1, 5), (2, 6), (3, 500)];
+
+        assert_eq!(policy.select_segments_to_merge(segments), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn test_min_segments_to_merge() {
+        let policy = MergePolicy {
+            min_segments_to_merge: 2,
+            .. MergePolicy::default()
+        };
+        let segments = vec![(1, 5), (2, 6), (3, 500)];
+
+        assert_eq!(policy.select_segments_to_merge(segments), vec![2, 1]);
+    }
+
+    #[test]
+    fn test_max_segment_docs() {
+        let policy = MergePolicy {
+            max_segment_docs: 10000,
+            .. MergePolicy::default()
+        };
+        let segments = vec![(1, 4000), (2, 3000), (3, 5000), (4, 2000)];
+
+        assert_eq!(policy.select_segments_to_merge(segments), vec![3, 1]);
+    }
+
+    #[test]
+    fn test_large_max_segment_docs() {
+        let policy = MergePolicy {
+            max_segment_docs: 1000000,
+            .. MergePolicy::default()
+        };
+        let segments = vec![(1, 200000), (2, 300000), (3, 100000), (4, 5)];
+
+        assert_eq!(policy.select_segments_to_merge(segments), vec![2, 1, 3]);
+    }
+
+    #[test]
+    fn test_full_segments_are_not_merged() {
+        let policy = MergePolicy::default();
+        let segments = vec![(1, 70000), (2, 80000), (3, 90000)];
+
+        assert_eq!(policy.select_segments_to_merge(segments), Vec::<u32>::new());
+    }
+}
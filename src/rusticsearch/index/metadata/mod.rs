@@ -1,15 +1,73 @@
 pub mod parse;
 pub mod file;
+pub mod settings;
 
 use std::collections::{HashMap, BTreeMap};
 
 use serde_json;
 use serde_json::value::ToJson;
+use kite::schema::{Schema, FieldRef};
 
 use analysis::AnalyzerSpec;
+use analysis::resources::ResourceError;
+use analysis::char_filters::CharFilterSpec;
 use analysis::tokenizers::TokenizerSpec;
 use analysis::filters::FilterSpec;
-use mapping::{Mapping, MappingProperty, FieldMapping};
+use analysis::filters::elision::FRENCH_ARTICLES;
+use analysis::stopwords;
+use mapping::{Mapping, MappingProperty, FieldMapping, FieldType};
+
+use self::settings::IndexSettings;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IndexState {
+    Open,
+
+    /// Closed indices can't be searched or have documents indexed into them but their
+    /// static settings can be changed
+    Closed,
+}
+
+
+impl ToString for IndexState {
+    fn to_string(&self) -> String {
+        match *self {
+            IndexState::Open => "open".to_string(),
+            IndexState::Closed => "close".to_string(),
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct AliasMetadata {
+    /// A query that documents must match to be seen through the alias
+    pub filter: Option<serde_json::Value>,
+
+    /// Whether documents sent to the alias are indexed into this index
+    ///
+    /// If this isn't set, documents can only be written through aliases that point to
+    /// one index.
+    pub is_write_index: Option<bool>,
+}
+
+
+impl ToJson for AliasMetadata {
+    fn to_json(&self) -> Result<serde_json::Value, serde_json::Error> {
+        let mut json = BTreeMap::new();
+
+        if let Some(ref filter) = self.filter {
+            json.insert("filter".to_string(), filter.clone());
+        }
+
+        if let Some(is_write_index) = self.is_write_index {
+            json.insert("is_write_index".to_string(), json!(is_write_index));
+        }
+
+        Ok(json!(json))
+    }
+}
 
 
 #[derive(Debug)]
@@ -17,7 +75,13 @@ pub struct IndexMetadata {
     analyzers: HashMap<String, AnalyzerSpec>,
     tokenizers: HashMap<String, TokenizerSpec>,
     filters: HashMap<String, FilterSpec>,
+    char_filters: HashMap<String, CharFilterSpec>,
     pub mappings: HashMap<String, Mapping>,
+    pub settings: IndexSettings,
+    pub state: IndexState,
+
+    /// The aliases that point to this index
+    pub aliases: BTreeMap<String, AliasMetadata>,
 }
 
 
@@ -27,19 +91,41 @@ impl Default for IndexMetadata {
             analyzers: HashMap::new(),
             tokenizers: HashMap::new(),
             filters: HashMap::new(),
+            char_filters: HashMap::new(),
             mappings: HashMap::new(),
+            settings: IndexSettings::default(),
+            state: IndexState::Open,
+            aliases: BTreeMap::new(),
         };
 
         // Builtin tokenizers
         metadata.insert_tokenizer("standard".to_string(), TokenizerSpec::Standard);
         metadata.insert_tokenizer("lowercase".to_string(), TokenizerSpec::Lowercase);
+        metadata.insert_tokenizer("keyword".to_string(), TokenizerSpec::Keyword);
+        metadata.insert_tokenizer("uax_url_email".to_string(), TokenizerSpec::UaxUrlEmail);
+        metadata.insert_tokenizer("cjk_bigram".to_string(), TokenizerSpec::CJKBigram);
 
         // Builtin filters
         metadata.insert_filter("asciifolding".to_string(), FilterSpec::ASCIIFolding);
         metadata.insert_filter("lowercase".to_string(), FilterSpec::Lowercase);
+        metadata.insert_filter("stop".to_string(), FilterSpec::new_stop(stopwords::ENGLISH.iter().map(|word| word.to_string()).collect(), None));
+        metadata.insert_filter("porter_stem".to_string(), FilterSpec::PorterStem);
+        metadata.insert_filter("trim".to_string(), FilterSpec::Trim);
+        metadata.insert_filter("unique".to_string(), FilterSpec::Unique {
+            only_on_same_position: false,
+        });
+        metadata.insert_filter("reverse".to_string(), FilterSpec::Reverse);
+        metadata.insert_filter("cjk_width".to_string(), FilterSpec::CJKWidth);
+        metadata.insert_filter("decimal_digit".to_string(), FilterSpec::DecimalDigit);
+
+        // Builtin character filters
+        metadata.insert_char_filter("html_strip".to_string(), CharFilterSpec::HTMLStrip {
+            escaped_tags: Vec::new(),
+        });
 
         // Builtin analyzers
         metadata.insert_analyzer("standard".to_string(), AnalyzerSpec {
+            char_filters: vec![],
             tokenizer: TokenizerSpec::Standard,
             filters: vec![
                 FilterSpec::Lowercase,
@@ -47,6 +133,37 @@ impl Default for IndexMetadata {
             ]
         });
 
+        // Builtin language analyzers
+        let languages: &[(&str, &[&str])] = &[
+            ("english", stopwords::ENGLISH),
+            ("french", stopwords::FRENCH),
+            ("german", stopwords::GERMAN),
+            ("spanish", stopwords::SPANISH),
+            ("russian", stopwords::RUSSIAN),
+        ];
+
+        for &(language, language_stopwords) in languages.iter() {
+            let mut filters = Vec::new();
+
+            if language == "french" {
+                filters.push(FilterSpec::Elision {
+                    articles: FRENCH_ARTICLES.iter().map(|article| article.to_string()).collect(),
+                });
+            }
+
+            filters.push(FilterSpec::Lowercase);
+            filters.push(FilterSpec::new_stop(language_stopwords.iter().map(|word| word.to_string()).collect(), None));
+            filters.push(FilterSpec::Stemmer {
+                language: language.to_string(),
+            });
+
+            metadata.insert_analyzer(language.to_string(), AnalyzerSpec {
+                char_filters: vec![],
+                tokenizer: TokenizerSpec::Standard,
+                filters: filters,
+            });
+        }
+
         metadata
     }
 }
@@ -73,6 +190,16 @@ impl IndexMetadata {
         &self.filters
     }
 
+    // Character filter helpers
+
+    pub fn insert_char_filter(&mut self, name: String, char_filter: CharFilterSpec) -> Option<CharFilterSpec> {
+        self.char_filters.insert(name, char_filter)
+    }
+
+    pub fn char_filters(&self) -> &HashMap<String, CharFilterSpec> {
+        &self.char_filters
+    }
+
     // Analyzer helpers
 
     pub fn insert_analyzer(&mut self, name: String, analyzer: AnalyzerSpec) -> Option<AnalyzerSpec> {
@@ -86,6 +213,7 @@ impl IndexMetadata {
     fn get_default_analyzer(&self) -> AnalyzerSpec {
         self.analyzers().get("default").cloned().unwrap_or_else(|| {
             AnalyzerSpec {
+                char_filters: vec![],
                 tokenizer: TokenizerSpec::Standard,
                 filters: vec![
                     FilterSpec::Lowercase,
@@ -107,13 +235,89 @@ impl IndexMetadata {
         })
     }
 
+    /// Re-reads the file-based resources (eg, synonym lists) used at search time
+    ///
+    /// Returns the names of the analyzers that were reloaded. Analyzers used at
+    /// index time keep their old resources until the index is rebuilt.
+    pub fn reload_search_analyzers(&mut self) -> Result<Vec<String>, ResourceError> {
+        // Reload the named filters and analyzers and the fields' search analyzers before
+        // swapping any of them in, so a bad file doesn't leave them half updated
+        let mut filters = HashMap::new();
+        for (name, filter) in self.filters.iter() {
+            if filter.uses_files() {
+                filters.insert(name.clone(), try!(filter.reload()));
+            }
+        }
+
+        let mut analyzers = HashMap::new();
+        for (name, analyzer) in self.analyzers.iter() {
+            if analyzer.uses_files() {
+                analyzers.insert(name.clone(), try!(analyzer.reload()));
+            }
+        }
+
+        // The search analyzers of fields, by mapping name and field path
+        let mut field_analyzers = HashMap::new();
+        for (mapping_name, mapping) in self.mappings.iter() {
+            for (field_path, field_mapping) in mapping.fields() {
+                if let Some(analyzer) = try!(field_mapping.reload_search_analyzer()) {
+                    field_analyzers.insert((mapping_name.clone(), field_path), analyzer);
+                }
+            }
+        }
+
+        let mut reloaded_analyzers = analyzers.keys().cloned().collect::<Vec<String>>();
+        reloaded_analyzers.sort();
+
+        self.filters.extend(filters);
+        self.analyzers.extend(analyzers);
+
+        for (mapping_name, mapping) in self.mappings.iter_mut() {
+            mapping.visit_fields_mut(|field_path, field_mapping| {
+                if let Some(analyzer) = field_analyzers.remove(&(mapping_name.clone(), field_path.to_string())) {
+                    field_mapping.set_search_analyzer(analyzer);
+                }
+            });
+        }
+
+        Ok(reloaded_analyzers)
+    }
+
     // Mapping helpers
 
     pub fn get_field_mapping(&self, name: &str) -> Option<&FieldMapping> {
         for mapping in self.mappings.values() {
-            if let Some(property) = mapping.properties.get(name) {
+            if let Some(field_mapping) = mapping.get_field(name) {
+                return Some(field_mapping);
+            }
+        }
+
+        None
+    }
+
+    /// Finds the name of each field in the schema that is in a mapping
+    pub fn get_field_names(&self, schema: &Schema) -> Vec<(FieldRef, String)> {
+        let mut field_names = Vec::new();
+        for mapping in self.mappings.values() {
+            for (field_name, _) in mapping.fields() {
+                if let Some(field_ref) = schema.get_field_by_name(&field_name) {
+                    if !field_names.iter().any(|&(existing_ref, _)| existing_ref == field_ref) {
+                        field_names.push((field_ref, field_name));
+                    }
+                }
+            }
+        }
+
+        field_names
+    }
+
+    pub fn get_join_field(&self) -> Option<(&str, &FieldMapping)> {
+        for mapping in self.mappings.values() {
+            for (name, property) in mapping.properties.iter() {
                 if let MappingProperty::Field(ref field_mapping) = *property {
-                    return Some(field_mapping);
+                    if field_mapping.data_type == FieldType::Join {
+                        return Some((name, field_mapping));
+                    }
                 }
             }
         }
@@ -137,21 +341,43 @@ impl ToJson for IndexMetadata {
             filters_json.insert(name.to_string(), try!(filter.to_json()));
         }
 
+        // Character filters
+        let mut char_filters_json = BTreeMap::new();
+        for (name, char_filter) in self.char_filters.iter() {
+            char_filters_json.insert(name.to_string(), try!(char_filter.to_json()));
+        }
+
+        // Analyzers
+        let mut analyzers_json = BTreeMap::new();
+        for (name, analyzer) in self.analyzers.iter() {
+            analyzers_json.insert(name.to_string(), try!(analyzer.to_json()));
+        }
+
         // Mappings
         let mut mappings_json = BTreeMap::new();
         for (name, mapping) in self.mappings.iter() {
             mappings_json.insert(name.to_string(), try!(mapping.to_json()));
         }
 
+        // Aliases
+        let mut aliases_json = BTreeMap::new();
+        for (name, alias) in self.aliases.iter() {
+            aliases_json.insert(name.to_string(), try!(alias.to_json()));
+        }
+
         Ok(json!({
             "settings": {
+                "index": try!(self.settings.to_json()),
                 "analysis": {
-                    "tokenizers": tokenizers_json,
-                    "filters": filters_json,
-                    "analyzers": {},  // TODO
+                    "tokenizer": tokenizers_json,
+                    "filter": filters_json,
+                    "char_filter": char_filters_json,
+                    "analyzer": analyzers_json,
                 },
             },
             "mappings": mappings_json,
+            "aliases": aliases_json,
+            "state": self.state.to_string(),
         }))
     }
 }
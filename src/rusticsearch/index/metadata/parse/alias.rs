@@ -0,0 +1,100 @@
+use serde_json;
+
+use index::metadata::AliasMetadata;
+use query_parser::{QueryParseError, parse as parse_query};
+
+
+#[derive(Debug, PartialEq)]
+pub enum AliasParseError {
+    ExpectedObject,
+    ExpectedBoolean(String),
+    UnrecognisedSetting(String),
+    InvalidFilter(QueryParseError),
+}
+
+
+pub fn parse(json: &serde_json::Value) -> Result<AliasMetadata, AliasParseError> {
+    let data = try!(json.as_object().ok_or(AliasParseError::ExpectedObject));
+
+    let mut alias = AliasMetadata::default();
+
+    for (key, value) in data.iter() {
+        match key.as_ref() {
+            "filter" => {
+                // Check the filter now so searches through the alias don't fail later
+                if let Err(e) = parse_query(value) {
+                    return Err(AliasParseError::InvalidFilter(e));
+                }
+
+                alias.filter = Some(value.clone());
+            }
+            "is_write_index" => {
+                alias.is_write_index = Some(try!(value.as_bool().ok_or_else(|| AliasParseError::ExpectedBoolean(key.clone()))));
+            }
+            _ => return Err(AliasParseError::UnrecognisedSetting(key.clone())),
+        }
+    }
+
+    Ok(alias)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+
+    use index::metadata::AliasMetadata;
+    use query_parser::QueryParseError;
+
+    use super::{parse, AliasParseError};
+
+    #[test]
+    fn test_parse() {
+        let alias = parse(&serde_json::from_str("
+        {
+            \"filter\": {
+                \"term\": {
+                    \"user\": \"kimchy\"
+                }
+            },
+            \"is_write_index\": true
+        }
+        ").unwrap());
+
+        assert_eq!(alias, Ok(AliasMetadata {
+            filter: Some(json!({"term": {"user": "kimchy"}})),
+            is_write_index: Some(true),
+        }));
+    }
+
+    #[test]
+    fn test_parse_empty() {
+        let alias = parse(&serde_json::from_str("{}").unwrap());
+
+        assert_eq!(alias, Ok(AliasMetadata::default()));
+    }
+
+    #[test]
+    fn test_parse_invalid_filter() {
+        let alias = parse(&serde_json::from_str("
+        {
+            \"filter\": {
+                \"foo\": {}
+            }
+        }
+        ").unwrap());
+
+        assert_eq!(alias, Err(AliasParseError::InvalidFilter(QueryParseError::UnrecognisedQueryType("foo".to_string()))));
+    }
+
+    #[test]
+    fn test_parse_unrecognised_setting() {
+        let alias = parse(&serde_json::from_str("
+        {
+            \"routing\": \"1\"
+        }
+        ").unwrap());
+
+        assert_eq!(alias, Err(AliasParseError::UnrecognisedSetting("routing".to_string())));
+    }
+}
@@ -1,7 +1,13 @@
 use serde_json;
 
 use analysis::AnalyzerSpec;
+use analysis::tokenizers::TokenizerSpec;
+use analysis::filters::FilterSpec;
+use analysis::char_filters::CharFilterSpec;
 use index::metadata::IndexMetadata;
+use index::metadata::parse::analysis_tokenizer::{TokenizerParseError, parse as parse_tokenizer};
+use index::metadata::parse::analysis_filter::{FilterParseError, parse as parse_filter};
+use index::metadata::parse::analysis_char_filter::{CharFilterParseError, parse as parse_char_filter};
 
 
 #[derive(Debug, PartialEq)]
@@ -13,6 +19,56 @@ pub enum AnalyzerParseError {
     UnrecognisedAnalyzerType(String),
     UnrecognisedTokenizer(String),
     UnrecognisedFilter(String),
+    UnrecognisedCharFilter(String),
+    ExpectedStringOrObject,
+    TokenizerParseError(TokenizerParseError),
+    FilterParseError(FilterParseError),
+    CharFilterParseError(CharFilterParseError),
+}
+
+
+// Tokenizers, filters and character filters can either be referenced by name or
+// defined inline. Index metadata is saved with inline definitions
+
+fn parse_tokenizer_reference(json: &serde_json::Value, index_metadata: &IndexMetadata) -> Result<TokenizerSpec, AnalyzerParseError> {
+    match *json {
+        serde_json::Value::String(ref name) => {
+            match index_metadata.tokenizers().get(name) {
+                Some(tokenizer_spec) => Ok(tokenizer_spec.clone()),
+                None => Err(AnalyzerParseError::UnrecognisedTokenizer(name.to_string())),
+            }
+        }
+        serde_json::Value::Object(_) => parse_tokenizer(json).map_err(AnalyzerParseError::TokenizerParseError),
+        _ => Err(AnalyzerParseError::ExpectedStringOrObject),
+    }
+}
+
+
+fn parse_filter_reference(json: &serde_json::Value, index_metadata: &IndexMetadata) -> Result<FilterSpec, AnalyzerParseError> {
+    match *json {
+        serde_json::Value::String(ref name) => {
+            match index_metadata.filters().get(name) {
+                Some(filter_spec) => Ok(filter_spec.clone()),
+                None => Err(AnalyzerParseError::UnrecognisedFilter(name.to_string())),
+            }
+        }
+        serde_json::Value::Object(_) => parse_filter(json, index_metadata).map_err(AnalyzerParseError::FilterParseError),
+        _ => Err(AnalyzerParseError::ExpectedStringOrObject),
+    }
+}
+
+
+fn parse_char_filter_reference(json: &serde_json::Value, index_metadata: &IndexMetadata) -> Result<CharFilterSpec, AnalyzerParseError> {
+    match *json {
+        serde_json::Value::String(ref name) => {
+            match index_metadata.char_filters().get(name) {
+                Some(char_filter_spec) => Ok(char_filter_spec.clone()),
+                None => Err(AnalyzerParseError::UnrecognisedCharFilter(name.to_string())),
+            }
+        }
+        serde_json::Value::Object(_) => parse_char_filter(json).map_err(AnalyzerParseError::CharFilterParseError),
+        _ => Err(AnalyzerParseError::ExpectedStringOrObject),
+    }
 }
 
 
@@ -26,47 +82,31 @@ pub fn parse(json: &serde_json::Value, index_metadata: &IndexMetadata) -> Result
     match analyzer_type {
         "custom" => {
             // Get tokenizer
-            let tokenizer_name = match data.get("tokenizer") {
-                Some(tokenizer_json) => {
-                    match tokenizer_json.as_str() {
-                        Some(tokenizer) => tokenizer,
-                        None => return Err(AnalyzerParseError::ExpectedString),
-                    }
-                }
-                None => return Err(AnalyzerParseError::ExpectedKey("tokenizer".to_string())),
-            };
-
-            let tokenizer_spec = match index_metadata.tokenizers().get(tokenizer_name) {
-                Some(tokenizer_spec) => tokenizer_spec,
-                None => return Err(AnalyzerParseError::UnrecognisedTokenizer(tokenizer_name.to_string())),
-            };
+            let tokenizer_json = try!(data.get("tokenizer").ok_or(AnalyzerParseError::ExpectedKey("tokenizer".to_string())));
+            let tokenizer_spec = try!(parse_tokenizer_reference(tokenizer_json, index_metadata));
 
             // Build analyzer
             let mut analyzer_spec = AnalyzerSpec {
-                tokenizer: tokenizer_spec.clone(),
+                char_filters: Vec::new(),
+                tokenizer: tokenizer_spec,
                 filters: Vec::new(),
             };
 
             // Add filters
             if let Some(filter_json) = data.get("filter") {
-                match filter_json.as_array() {
-                    Some(filter_names) => {
-                        for filter_name_json in filter_names.iter() {
-                            // Get filter
-                            match filter_name_json.as_str() {
-                                Some(filter_name) => {
-                                    let filter_spec = match index_metadata.filters().get(filter_name) {
-                                        Some(filter_spec) => filter_spec,
-                                        None => return Err(AnalyzerParseError::UnrecognisedFilter(filter_name.to_string())),
-                                    };
-
-                                    analyzer_spec.filters.push(filter_spec.clone());
-                                }
-                                None => return Err(AnalyzerParseError::ExpectedString),
-                            }
-                        }
-                    },
-                    None => return Err(AnalyzerParseError::ExpectedArray),
+                let filters = try!(filter_json.as_array().ok_or(AnalyzerParseError::ExpectedArray));
+
+                for filter_json in filters.iter() {
+                    analyzer_spec.filters.push(try!(parse_filter_reference(filter_json, index_metadata)));
+                }
+            }
+
+            // Add character filters
+            if let Some(char_filter_json) = data.get("char_filter") {
+                let char_filters = try!(char_filter_json.as_array().ok_or(AnalyzerParseError::ExpectedArray));
+
+                for char_filter_json in char_filters.iter() {
+                    analyzer_spec.char_filters.push(try!(parse_char_filter_reference(char_filter_json, index_metadata)));
                 }
             }
 
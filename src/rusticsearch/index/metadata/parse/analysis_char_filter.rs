@@ -0,0 +1,85 @@
+use serde_json;
+
+use analysis::char_filters::CharFilterSpec;
+use analysis::char_filters::pattern_replace::{self, PatternReplaceError};
+
+
+#[derive(Debug, PartialEq)]
+pub enum CharFilterParseError {
+    ExpectedObject,
+    ExpectedString,
+    ExpectedArray,
+    ExpectedKey(String),
+    UnrecognisedType(String),
+    UnrecognisedFlag(String),
+    InvalidPattern(String),
+}
+
+
+fn parse_string_option(data: &serde_json::Map<String, serde_json::Value>, key: &str, default: &str) -> Result<String, CharFilterParseError> {
+    match data.get(key) {
+        Some(value_json) => {
+            match value_json.as_str() {
+                Some(value) => Ok(value.to_string()),
+                None => Err(CharFilterParseError::ExpectedString),
+            }
+        }
+        None => Ok(default.to_string()),
+    }
+}
+
+
+fn parse_string_array_option(data: &serde_json::Map<String, serde_json::Value>, key: &str) -> Result<Vec<String>, CharFilterParseError> {
+    let array = match data.get(key) {
+        Some(array_json) => try!(array_json.as_array().ok_or(CharFilterParseError::ExpectedArray)),
+        None => return Ok(Vec::new()),
+    };
+
+    let mut strings = Vec::new();
+    for string_json in array.iter() {
+        strings.push(try!(string_json.as_str().ok_or(CharFilterParseError::ExpectedString)).to_string());
+    }
+
+    Ok(strings)
+}
+
+
+pub fn parse(json: &serde_json::Value) -> Result<CharFilterSpec, CharFilterParseError> {
+    let data = try!(json.as_object().ok_or(CharFilterParseError::ExpectedObject));
+
+    // Get type
+    let char_filter_type_json = try!(data.get("type").ok_or(CharFilterParseError::ExpectedKey("type".to_string())));
+    let char_filter_type = try!(char_filter_type_json.as_str().ok_or(CharFilterParseError::ExpectedString));
+
+    match char_filter_type {
+        "html_strip" => {
+            let escaped_tags = try!(parse_string_array_option(data, "escaped_tags"));
+
+            Ok(CharFilterSpec::HTMLStrip {
+                escaped_tags: escaped_tags.iter().map(|tag| tag.to_lowercase()).collect(),
+            })
+        }
+        "pattern_replace" => {
+            let pattern_json = try!(data.get("pattern").ok_or(CharFilterParseError::ExpectedKey("pattern".to_string())));
+            let pattern = try!(pattern_json.as_str().ok_or(CharFilterParseError::ExpectedString)).to_string();
+            let replacement = try!(parse_string_option(data, "replacement", ""));
+            let flags = try!(parse_string_option(data, "flags", ""));
+
+            let regex = match pattern_replace::compile(&pattern, &flags) {
+                Ok(regex) => regex,
+                Err(PatternReplaceError::UnrecognisedFlag(flag)) => return Err(CharFilterParseError::UnrecognisedFlag(flag)),
+                Err(PatternReplaceError::InvalidPattern(error)) => return Err(CharFilterParseError::InvalidPattern(error)),
+            };
+
+            Ok(CharFilterSpec::PatternReplace {
+                pattern: pattern,
+                replacement: replacement,
+                flags: flags,
+                regex: regex,
+            })
+        }
+        // TODO
+        // mapping
+        _ => Err(CharFilterParseError::UnrecognisedType(char_filter_type.to_string())),
+    }
+}
@@ -1,7 +1,18 @@
+use std::usize;
+
 use serde_json;
 
 use analysis::ngram_generator::Edge;
 use analysis::filters::FilterSpec;
+use analysis::phonetic::PhoneticEncoder;
+use analysis::stopwords::get_stopwords;
+use analysis::filters::stemmer::get_algorithm;
+use analysis::filters::word_delimiter::WordDelimiterOptions;
+use analysis::filters::elision::FRENCH_ARTICLES;
+use analysis::filters::stemmer_override;
+use analysis::filters::condition::TokenCondition;
+use analysis::resources::{self, ResourceError};
+use index::metadata::IndexMetadata;
 
 
 #[derive(Debug, PartialEq)]
@@ -12,10 +23,159 @@ pub enum FilterParseError {
     ExpectedKey(String),
     UnrecognisedType(String),
     InvalidSideValue,
+    ExpectedStringOrArray,
+    UnrecognisedStopwords(String),
+    UnrecognisedLanguage(String),
+    ExpectedArray,
+    InvalidSynonymRule(String),
+    ExpectedBoolean,
+    InvalidModeValue,
+    UnrecognisedEncoder(String),
+    InvalidStemmerOverrideRule(String),
+    UnrecognisedFilter(String),
+    InvalidCondition(String),
+    UnreadableFile(String),
+
+    /// Resource paths must be relative to the config directory
+    InvalidPath(String),
+}
+
+
+fn parse_string_array(data: &serde_json::Map<String, serde_json::Value>, key: &str) -> Result<Vec<String>, FilterParseError> {
+    let array_json = try!(data.get(key).ok_or(FilterParseError::ExpectedKey(key.to_string())));
+    let array = try!(array_json.as_array().ok_or(FilterParseError::ExpectedArray));
+
+    let mut strings = Vec::new();
+    for string_json in array.iter() {
+        strings.push(try!(string_json.as_str().ok_or(FilterParseError::ExpectedString)).to_string());
+    }
+
+    Ok(strings)
 }
 
 
-pub fn parse(json: &serde_json::Value) -> Result<FilterSpec, FilterParseError> {
+fn parse_positive_integer_option(data: &serde_json::Map<String, serde_json::Value>, key: &str, default: usize) -> Result<usize, FilterParseError> {
+    match data.get(key) {
+        Some(value_json) => {
+            match value_json.as_u64() {
+                Some(value) => Ok(value as usize),
+                None => Err(FilterParseError::ExpectedPositiveInteger),
+            }
+        }
+        None => Ok(default),
+    }
+}
+
+
+/// Reads the lines of the file given by a path option
+///
+/// Filters are saved in the index metadata with the lines that were read from the file
+/// under the inline key. If the file can't be read any more, those lines are used so the
+/// index can still be loaded.
+fn parse_path_option(data: &serde_json::Map<String, serde_json::Value>, key: &str, inline_key: &str) -> Result<Option<(String, Vec<String>)>, FilterParseError> {
+    match data.get(key) {
+        Some(path_json) => {
+            let path = try!(path_json.as_str().ok_or(FilterParseError::ExpectedString));
+
+            match resources::read_lines(path) {
+                Ok(lines) => Ok(Some((path.to_string(), lines))),
+                Err(ResourceError::InvalidPath(_)) => Err(FilterParseError::InvalidPath(path.to_string())),
+                Err(e) => {
+                    if let Some(&serde_json::Value::Array(_)) = data.get(inline_key) {
+                        warn!("{}, using the saved [{}] instead", e, inline_key);
+                        return Ok(Some((path.to_string(), try!(parse_string_array(data, inline_key)))));
+                    }
+
+                    Err(FilterParseError::UnreadableFile(path.to_string()))
+                }
+            }
+        }
+        None => Ok(None),
+    }
+}
+
+
+fn parse_boolean_option(data: &serde_json::Map<String, serde_json::Value>, key: &str, default: bool) -> Result<bool, FilterParseError> {
+    match data.get(key) {
+        Some(value_json) => value_json.as_bool().ok_or(FilterParseError::ExpectedBoolean),
+        None => Ok(default),
+    }
+}
+
+
+/// Parses a chain of filters for filters that wrap other filters (eg, multiplexer)
+///
+/// The chain can either be a string of comma-separated filter names or an array
+/// of filter names and inline filter definitions.
+fn parse_filter_chain(json: &serde_json::Value, index_metadata: &IndexMetadata) -> Result<Vec<FilterSpec>, FilterParseError> {
+    let find_filter = |name: &str| -> Result<FilterSpec, FilterParseError> {
+        match index_metadata.filters().get(name) {
+            Some(filter_spec) => Ok(filter_spec.clone()),
+            None => Err(FilterParseError::UnrecognisedFilter(name.to_string())),
+        }
+    };
+
+    let mut chain = Vec::new();
+
+    match *json {
+        serde_json::Value::String(ref names) => {
+            for name in names.split(',').map(|name| name.trim()).filter(|name| !name.is_empty()) {
+                chain.push(try!(find_filter(name)));
+            }
+        }
+        serde_json::Value::Array(ref filters) => {
+            for filter_json in filters.iter() {
+                match *filter_json {
+                    serde_json::Value::String(ref name) => chain.push(try!(find_filter(name))),
+                    _ => chain.push(try!(parse(filter_json, index_metadata))),
+                }
+            }
+        }
+        _ => return Err(FilterParseError::ExpectedStringOrArray),
+    }
+
+    Ok(chain)
+}
+
+
+fn parse_condition(json: &serde_json::Value) -> Result<TokenCondition, FilterParseError> {
+    let data = try!(json.as_object().ok_or(FilterParseError::ExpectedObject));
+
+    let condition = if let Some(length_json) = data.get("length") {
+        let length_data = try!(length_json.as_object().ok_or(FilterParseError::ExpectedObject));
+
+        TokenCondition::Length {
+            min: try!(parse_positive_integer_option(length_data, "min", 0)),
+            max: try!(parse_positive_integer_option(length_data, "max", usize::MAX)),
+        }
+    } else if let Some(script_json) = data.get("script") {
+        TokenCondition::Script(try!(script_json.as_str().ok_or(FilterParseError::ExpectedString)).to_string())
+    } else if let Some(pattern_json) = data.get("pattern") {
+        TokenCondition::Pattern(try!(pattern_json.as_str().ok_or(FilterParseError::ExpectedString)).to_string())
+    } else {
+        return Err(FilterParseError::ExpectedKey("length".to_string()));
+    };
+
+    if let Err(error) = condition.regex() {
+        return Err(FilterParseError::InvalidCondition(error.to_string()));
+    }
+
+    Ok(condition)
+}
+
+
+/// Returns true if the filter definition refers to other filters by name
+///
+/// These must be parsed after the filters they refer to have been defined.
+pub fn refers_to_other_filters(json: &serde_json::Value) -> bool {
+    match json.as_object().and_then(|data| data.get("type")).and_then(|filter_type| filter_type.as_str()) {
+        Some("multiplexer") | Some("condition") => true,
+        _ => false,
+    }
+}
+
+
+pub fn parse(json: &serde_json::Value, index_metadata: &IndexMetadata) -> Result<FilterSpec, FilterParseError> {
     let data = try!(json.as_object().ok_or(FilterParseError::ExpectedObject));
 
     // Get type
@@ -54,6 +214,7 @@ pub fn parse(json: &serde_json::Value) -> Result<FilterSpec, FilterParseError> {
                 min_size: min_gram,
                 max_size: max_gram,
                 edge: Edge::Neither,
+                preserve_original: try!(parse_boolean_option(data, "preserve_original", false)),
             })
         }
         "edgeNGram" | "edge_ngram" => {
@@ -102,29 +263,207 @@ pub fn parse(json: &serde_json::Value) -> Result<FilterSpec, FilterParseError> {
                 min_size: min_gram,
                 max_size: max_gram,
                 edge: edge,
+                preserve_original: try!(parse_boolean_option(data, "preserve_original", false)),
+            })
+        }
+        "stop" => {
+            // Stopwords can be read from a file, which takes precedence
+            if let Some((path, words)) = try!(parse_path_option(data, "stopwords_path", "stopwords")) {
+                return Ok(FilterSpec::new_stop(words, Some(path)));
+            }
+
+            let words = match data.get("stopwords") {
+                Some(&serde_json::Value::String(ref name)) => {
+                    match get_stopwords(name) {
+                        Some(words) => words.iter().map(|word| word.to_string()).collect(),
+                        None => return Err(FilterParseError::UnrecognisedStopwords(name.clone())),
+                    }
+                }
+                Some(&serde_json::Value::Array(ref words_json)) => {
+                    let mut words = Vec::new();
+
+                    for word_json in words_json.iter() {
+                        words.push(try!(word_json.as_str().ok_or(FilterParseError::ExpectedString)).to_string());
+                    }
+
+                    words
+                }
+                Some(_) => return Err(FilterParseError::ExpectedStringOrArray),
+                None => get_stopwords("_english_").unwrap().iter().map(|word| word.to_string()).collect(),
+            };
+
+            Ok(FilterSpec::new_stop(words, None))
+        }
+        "porter_stem" => {
+            Ok(FilterSpec::PorterStem)
+        }
+        "stemmer" | "snowball" => {
+            // The stemmer filter also accepts the language through the "name" key
+            let language_json = try!(data.get("language").or_else(|| data.get("name")).ok_or(FilterParseError::ExpectedKey("language".to_string())));
+            let language = try!(language_json.as_str().ok_or(FilterParseError::ExpectedString)).to_lowercase();
+
+            if get_algorithm(&language).is_none() {
+                return Err(FilterParseError::UnrecognisedLanguage(language));
+            }
+
+            Ok(FilterSpec::Stemmer {
+                language: language,
+            })
+        }
+        // There's no "synonym_graph" filter (see the synonym filter)
+        "synonym" => {
+            // Synonyms can be read from a file, which takes precedence
+            let (synonyms, synonyms_path) = match try!(parse_path_option(data, "synonyms_path", "synonyms")) {
+                Some((path, synonyms)) => (synonyms, Some(path)),
+                None => (try!(parse_string_array(data, "synonyms")), None),
+            };
+
+            FilterSpec::new_synonym(synonyms, synonyms_path).map_err(FilterParseError::InvalidSynonymRule)
+        }
+        "word_delimiter" | "word_delimiter_graph" => {
+            let defaults = WordDelimiterOptions::default();
+
+            Ok(FilterSpec::WordDelimiter(WordDelimiterOptions {
+                generate_word_parts: try!(parse_boolean_option(data, "generate_word_parts", defaults.generate_word_parts)),
+                generate_number_parts: try!(parse_boolean_option(data, "generate_number_parts", defaults.generate_number_parts)),
+                catenate_words: try!(parse_boolean_option(data, "catenate_words", defaults.catenate_words)),
+                catenate_numbers: try!(parse_boolean_option(data, "catenate_numbers", defaults.catenate_numbers)),
+                catenate_all: try!(parse_boolean_option(data, "catenate_all", defaults.catenate_all)),
+                split_on_case_change: try!(parse_boolean_option(data, "split_on_case_change", defaults.split_on_case_change)),
+                split_on_numerics: try!(parse_boolean_option(data, "split_on_numerics", defaults.split_on_numerics)),
+                preserve_original: try!(parse_boolean_option(data, "preserve_original", defaults.preserve_original)),
+                stem_english_possessive: try!(parse_boolean_option(data, "stem_english_possessive", defaults.stem_english_possessive)),
+            }))
+        }
+        "length" => {
+            Ok(FilterSpec::Length {
+                min: try!(parse_positive_integer_option(data, "min", 0)),
+                max: try!(parse_positive_integer_option(data, "max", usize::MAX)),
+            })
+        }
+        "trim" => {
+            Ok(FilterSpec::Trim)
+        }
+        "truncate" => {
+            Ok(FilterSpec::Truncate {
+                length: try!(parse_positive_integer_option(data, "length", 10)),
+            })
+        }
+        "unique" => {
+            Ok(FilterSpec::Unique {
+                only_on_same_position: try!(parse_boolean_option(data, "only_on_same_position", false)),
+            })
+        }
+        "reverse" => {
+            Ok(FilterSpec::Reverse)
+        }
+        "cjk_width" => {
+            Ok(FilterSpec::CJKWidth)
+        }
+        "decimal_digit" => {
+            Ok(FilterSpec::DecimalDigit)
+        }
+        "keep" => {
+            Ok(FilterSpec::KeepWords {
+                words: try!(parse_string_array(data, "keep_words")),
+                ignore_case: try!(parse_boolean_option(data, "keep_words_case", false)),
+            })
+        }
+        "keep_types" => {
+            let exclude = match data.get("mode") {
+                Some(mode_json) => {
+                    match mode_json.as_str() {
+                        Some("include") => false,
+                        Some("exclude") => true,
+                        Some(_) => return Err(FilterParseError::InvalidModeValue),
+                        None => return Err(FilterParseError::ExpectedString),
+                    }
+                }
+                None => false,
+            };
+
+            Ok(FilterSpec::KeepTypes {
+                types: try!(parse_string_array(data, "types")),
+                exclude: exclude,
+            })
+        }
+        "elision" => {
+            let articles = if data.contains_key("articles") {
+                try!(parse_string_array(data, "articles"))
+            } else {
+                FRENCH_ARTICLES.iter().map(|article| article.to_string()).collect()
+            };
+
+            Ok(FilterSpec::Elision {
+                articles: articles,
+            })
+        }
+        "phonetic" => {
+            let encoder = match data.get("encoder") {
+                Some(encoder_json) => {
+                    let encoder_name = try!(encoder_json.as_str().ok_or(FilterParseError::ExpectedString));
+
+                    match PhoneticEncoder::from_name(encoder_name) {
+                        Some(encoder) => encoder,
+                        None => return Err(FilterParseError::UnrecognisedEncoder(encoder_name.to_string())),
+                    }
+                }
+                None => PhoneticEncoder::Metaphone,
+            };
+
+            Ok(FilterSpec::Phonetic {
+                encoder: encoder,
+                replace: try!(parse_boolean_option(data, "replace", true)),
+            })
+        }
+        "keyword_marker" => {
+            Ok(FilterSpec::KeywordMarker {
+                keywords: try!(parse_string_array(data, "keywords")),
+            })
+        }
+        "stemmer_override" => {
+            let rules = try!(parse_string_array(data, "rules"));
+
+            match stemmer_override::parse_rules(&rules) {
+                Ok(overrides) => {
+                    Ok(FilterSpec::StemmerOverride {
+                        overrides: overrides,
+                    })
+                }
+                Err(rule) => Err(FilterParseError::InvalidStemmerOverrideRule(rule)),
+            }
+        }
+        "multiplexer" => {
+            let chains_json = try!(data.get("filters").ok_or(FilterParseError::ExpectedKey("filters".to_string())));
+            let chains_json = try!(chains_json.as_array().ok_or(FilterParseError::ExpectedArray));
+
+            let mut chains = Vec::new();
+            for chain_json in chains_json.iter() {
+                chains.push(try!(parse_filter_chain(chain_json, index_metadata)));
+            }
+
+            Ok(FilterSpec::Multiplexer {
+                chains: chains,
+                preserve_original: try!(parse_boolean_option(data, "preserve_original", true)),
+            })
+        }
+        "condition" => {
+            let filters_json = try!(data.get("filter").ok_or(FilterParseError::ExpectedKey("filter".to_string())));
+            let condition_json = try!(data.get("condition").ok_or(FilterParseError::ExpectedKey("condition".to_string())));
+
+            Ok(FilterSpec::Condition {
+                condition: try!(parse_condition(condition_json)),
+                filters: try!(parse_filter_chain(filters_json, index_metadata)),
             })
         }
         // TODO
-        // stop
-        // reverse
-        // length
         // uppercase
-        // porter_stem
         // kstem
         // standard
         // shingle
-        // unique
-        // truncate
-        // trim
         // limit
         // common_grams
-        // snowball
-        // stemmer
-        // word_delimiter
         // delimited_payload_filter
-        // elision
-        // keep
-        // keep_types
         // pattern_capture
         // pattern_replace
         // dictionary_decompounder
@@ -136,8 +475,6 @@ pub fn parse(json: &serde_json::Value) -> Result<FilterSpec, FilterParseError> {
         // french_stem
         // german_stem
         // russian_stem
-        // keyword_marker
-        // stemmer_override
         // arabic_normalization
         // german_normalization
         // hindi_normalization
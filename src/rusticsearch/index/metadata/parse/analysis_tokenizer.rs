@@ -1,6 +1,8 @@
 use serde_json;
 
 use analysis::ngram_generator::Edge;
+use analysis::char_class::CharClass;
+use analysis::compiled_regex::CompiledRegex;
 use analysis::tokenizers::TokenizerSpec;
 
 
@@ -12,6 +14,30 @@ pub enum TokenizerParseError {
     ExpectedKey(String),
     UnrecognisedType(String),
     InvalidSideValue,
+    ExpectedArray,
+    UnrecognisedTokenChars(String),
+    ExpectedInteger,
+    InvalidPattern(String),
+}
+
+
+fn parse_token_chars(data: &serde_json::Map<String, serde_json::Value>) -> Result<Vec<CharClass>, TokenizerParseError> {
+    let mut token_chars = Vec::new();
+
+    if let Some(token_chars_json) = data.get("token_chars") {
+        let token_chars_array = try!(token_chars_json.as_array().ok_or(TokenizerParseError::ExpectedArray));
+
+        for class_json in token_chars_array.iter() {
+            let class_name = try!(class_json.as_str().ok_or(TokenizerParseError::ExpectedString));
+
+            match CharClass::from_name(class_name) {
+                Some(class) => token_chars.push(class),
+                None => return Err(TokenizerParseError::UnrecognisedTokenChars(class_name.to_string())),
+            }
+        }
+    }
+
+    Ok(token_chars)
 }
 
 
@@ -29,6 +55,9 @@ pub fn parse(json: &serde_json::Value) -> Result<TokenizerSpec, TokenizerParseEr
         "lowercase" => {
             Ok(TokenizerSpec::Lowercase)
         }
+        "keyword" => {
+            Ok(TokenizerSpec::Keyword)
+        }
         "nGram" | "ngram" => {
             let min_gram = match data.get("min_gram") {
                 Some(min_gram_json) => {
@@ -54,6 +83,7 @@ pub fn parse(json: &serde_json::Value) -> Result<TokenizerSpec, TokenizerParseEr
                 min_size: min_gram,
                 max_size: max_gram,
                 edge: Edge::Neither,
+                token_chars: try!(parse_token_chars(data)),
             })
         }
         "edgeNGram" | "edge_ngram" => {
@@ -102,15 +132,76 @@ pub fn parse(json: &serde_json::Value) -> Result<TokenizerSpec, TokenizerParseEr
                 min_size: min_gram,
                 max_size: max_gram,
                 edge: edge,
+                token_chars: try!(parse_token_chars(data)),
             })
         }
+        "pattern" => {
+            let pattern = match data.get("pattern") {
+                Some(pattern_json) => {
+                    match pattern_json.as_str() {
+                        Some(pattern) => pattern,
+                        None => return Err(TokenizerParseError::ExpectedString),
+                    }
+                }
+                None => "\\W+",
+            };
+
+            let pattern = try!(CompiledRegex::new(pattern).map_err(|error| TokenizerParseError::InvalidPattern(error.to_string())));
+
+            let group = match data.get("group") {
+                Some(group_json) => {
+                    match group_json.as_i64() {
+                        Some(-1) => None,
+                        Some(group) if group >= 0 => Some(group as usize),
+                        _ => return Err(TokenizerParseError::ExpectedInteger),
+                    }
+                }
+                None => None,
+            };
+
+            Ok(TokenizerSpec::Pattern {
+                pattern: pattern,
+                group: group,
+            })
+        }
+        "uax_url_email" => {
+            Ok(TokenizerSpec::UaxUrlEmail)
+        }
+        "char_group" => {
+            let mut split_on_classes = Vec::new();
+            let mut split_on_chars = Vec::new();
+
+            let tokenize_on_chars_json = try!(data.get("tokenize_on_chars").ok_or(TokenizerParseError::ExpectedKey("tokenize_on_chars".to_string())));
+            let tokenize_on_chars = try!(tokenize_on_chars_json.as_array().ok_or(TokenizerParseError::ExpectedArray));
+
+            for item_json in tokenize_on_chars.iter() {
+                let item = try!(item_json.as_str().ok_or(TokenizerParseError::ExpectedString));
+
+                // Each item is either the name of a character class or a single character
+                if let Some(class) = CharClass::from_name(item) {
+                    split_on_classes.push(class);
+                    continue;
+                }
+
+                let mut chars = item.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => split_on_chars.push(c),
+                    _ => return Err(TokenizerParseError::UnrecognisedTokenChars(item.to_string())),
+                }
+            }
+
+            Ok(TokenizerSpec::CharGroup {
+                split_on_classes: split_on_classes,
+                split_on_chars: split_on_chars,
+            })
+        }
+        "cjk_bigram" => {
+            Ok(TokenizerSpec::CJKBigram)
+        }
         // TODO
-        // uax_url_email
         // path_hierarchy/PathHierarchy
-        // keyword
         // letter
         // whitespace
-        // pattern
         // classic
         // thai
         _ => Err(TokenizerParseError::UnrecognisedType(tokenizer_type.to_owned())),
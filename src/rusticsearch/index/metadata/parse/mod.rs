@@ -1,15 +1,24 @@
 pub mod analysis_tokenizer;
 pub mod analysis_filter;
+pub mod analysis_char_filter;
 pub mod analysis_analyzer;
+pub mod settings;
+pub mod alias;
+
+use std::fmt;
 
 use serde_json;
 
-use index::metadata::IndexMetadata;
+use index::metadata::{IndexMetadata, IndexState};
 use mapping::parse::{MappingParseError, parse as parse_mapping};
+use mapping::build::{MappingBuildError, FieldMappingBuildError};
 
 use self::analysis_tokenizer::{TokenizerParseError, parse as parse_tokenizer};
-use self::analysis_filter::{FilterParseError, parse as parse_filter};
+use self::analysis_filter::{FilterParseError, parse as parse_filter, refers_to_other_filters};
+use self::analysis_char_filter::{CharFilterParseError, parse as parse_char_filter};
 use self::analysis_analyzer::{AnalyzerParseError, parse as parse_analyzer};
+use self::settings::{IndexSettingsParseError, flatten_settings, parse as parse_settings};
+use self::alias::{AliasParseError, parse as parse_alias};
 
 
 #[derive(Debug, PartialEq)]
@@ -17,8 +26,45 @@ pub enum IndexMetadataParseError {
     ExpectedObject,
     TokenizerParseError(String, TokenizerParseError),
     FilterParseError(String, FilterParseError),
+    CharFilterParseError(String, CharFilterParseError),
     AnalyzerParseError(String, AnalyzerParseError),
     MappingParseError(String, MappingParseError),
+    MappingBuildError(String, MappingBuildError),
+    SettingsParseError(IndexSettingsParseError),
+    UnrecognisedState(String),
+    AliasParseError(String, AliasParseError),
+}
+
+
+impl fmt::Display for IndexMetadataParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            IndexMetadataParseError::ExpectedObject => write!(f, "expected an object"),
+            IndexMetadataParseError::TokenizerParseError(ref name, ref e) => write!(f, "invalid tokenizer [{}]: {:?}", name, e),
+            IndexMetadataParseError::FilterParseError(ref name, ref e) => write!(f, "invalid filter [{}]: {:?}", name, e),
+            IndexMetadataParseError::CharFilterParseError(ref name, ref e) => write!(f, "invalid char_filter [{}]: {:?}", name, e),
+            IndexMetadataParseError::AnalyzerParseError(ref name, ref e) => {
+                match *e {
+                    AnalyzerParseError::UnrecognisedTokenizer(ref tokenizer) => write!(f, "unknown tokenizer [{}] in analyzer [{}]", tokenizer, name),
+                    AnalyzerParseError::UnrecognisedFilter(ref filter) => write!(f, "unknown filter [{}] in analyzer [{}]", filter, name),
+                    AnalyzerParseError::UnrecognisedCharFilter(ref char_filter) => write!(f, "unknown char_filter [{}] in analyzer [{}]", char_filter, name),
+                    ref e => write!(f, "invalid analyzer [{}]: {:?}", name, e),
+                }
+            }
+            IndexMetadataParseError::MappingParseError(ref name, ref e) => write!(f, "invalid mapping [{}]: {:?}", name, e),
+            IndexMetadataParseError::SettingsParseError(ref e) => write!(f, "{}", e),
+            IndexMetadataParseError::UnrecognisedState(ref state) => write!(f, "unknown index state [{}]", state),
+            IndexMetadataParseError::AliasParseError(ref name, ref e) => write!(f, "invalid alias [{}]: {:?}", name, e),
+            IndexMetadataParseError::MappingBuildError(ref name, MappingBuildError::FieldMappingBuildError(ref field_name, ref e)) => {
+                match *e {
+                    FieldMappingBuildError::UnrecognisedAnalyzer(ref analyzer) => write!(f, "unknown analyzer [{}] for field [{}] in mapping [{}]", analyzer, field_name, name),
+                    FieldMappingBuildError::UnrecognisedSimilarity(ref similarity) => write!(f, "unknown similarity [{}] for field [{}] in mapping [{}]", similarity, field_name, name),
+                    FieldMappingBuildError::UnrecognisedCopyToField(ref target_field_name) => write!(f, "unknown copy_to field [{}] for field [{}] in mapping [{}]", target_field_name, field_name, name),
+                    FieldMappingBuildError::InvalidNullValue(ref null_value) => write!(f, "failed to parse null_value {} for field [{}] in mapping [{}]", null_value, field_name, name),
+                }
+            }
+        }
+    }
 }
 
 
@@ -31,10 +77,11 @@ pub fn parse(metadata: &mut IndexMetadata, data: serde_json::Value) -> Result<()
     };
 
     if let Some(settings) = data.get("settings") {
-        let settings = match settings.as_object() {
-            Some(object) => object,
-            None => return Err(IndexMetadataParseError::ExpectedObject),
-        };
+        if !settings.is_object() {
+            return Err(IndexMetadataParseError::ExpectedObject);
+        }
+
+        let settings = flatten_settings(settings);
 
         if let Some(analysis) = settings.get("analysis") {
             let analysis = match analysis.as_object() {
@@ -66,8 +113,11 @@ pub fn parse(metadata: &mut IndexMetadata, data: serde_json::Value) -> Result<()
                     None => return Err(IndexMetadataParseError::ExpectedObject),
                 };
 
-                for (name, data) in filter_data {
-                    let filter = match parse_filter(data) {
+                // Filters that refer to other filters are parsed last
+                let (dependent_filters, independent_filters): (Vec<_>, Vec<_>) = filter_data.iter().partition(|&(_, data)| refers_to_other_filters(data));
+
+                for (name, data) in independent_filters.into_iter().chain(dependent_filters.into_iter()) {
+                    let filter = match parse_filter(data, &metadata) {
                         Ok(filter) => filter,
                         Err(e) => return Err(IndexMetadataParseError::FilterParseError(name.to_string(), e)),
                     };
@@ -76,6 +126,23 @@ pub fn parse(metadata: &mut IndexMetadata, data: serde_json::Value) -> Result<()
                 }
             }
 
+            // Character filters
+            if let Some(char_filter_data) = analysis.get("char_filter") {
+                let char_filter_data = match char_filter_data.as_object() {
+                    Some(object) => object,
+                    None => return Err(IndexMetadataParseError::ExpectedObject),
+                };
+
+                for (name, data) in char_filter_data {
+                    let char_filter = match parse_char_filter(data) {
+                        Ok(char_filter) => char_filter,
+                        Err(e) => return Err(IndexMetadataParseError::CharFilterParseError(name.to_string(), e)),
+                    };
+
+                    metadata.insert_char_filter(name.clone(), char_filter);
+                }
+            }
+
             // Analyzers
             if let Some(analyzer_data) = analysis.get("analyzer") {
                 let analyzer_data = match analyzer_data.as_object() {
@@ -93,6 +160,11 @@ pub fn parse(metadata: &mut IndexMetadata, data: serde_json::Value) -> Result<()
                 }
             }
         }
+
+        metadata.settings = match parse_settings(&metadata.settings, &settings) {
+            Ok(settings) => settings,
+            Err(e) => return Err(IndexMetadataParseError::SettingsParseError(e)),
+        };
     }
 
     if let Some(mappings) = data.get("mappings") {
@@ -106,29 +178,104 @@ pub fn parse(metadata: &mut IndexMetadata, data: serde_json::Value) -> Result<()
                 Ok(mapping) => mapping,
                 Err(e) => return Err(IndexMetadataParseError::MappingParseError(name.to_string(), e)),
             };
-            let mapping = mapping_builder.build(&metadata);
+            let mapping = match mapping_builder.build(&metadata) {
+                Ok(mapping) => mapping,
+                Err(e) => return Err(IndexMetadataParseError::MappingBuildError(name.to_string(), e)),
+            };
+
             metadata.mappings.insert(name.clone(), mapping);
         }
     }
 
+    if let Some(aliases) = data.get("aliases") {
+        let aliases = match aliases.as_object() {
+            Some(object) => object,
+            None => return Err(IndexMetadataParseError::ExpectedObject),
+        };
+
+        for (name, data) in aliases {
+            let alias = match parse_alias(data) {
+                Ok(alias) => alias,
+                Err(e) => return Err(IndexMetadataParseError::AliasParseError(name.to_string(), e)),
+            };
+
+            metadata.aliases.insert(name.clone(), alias);
+        }
+    }
+
+    if let Some(state) = data.get("state") {
+        metadata.state = match state.as_str() {
+            Some("open") => IndexState::Open,
+            Some("close") => IndexState::Closed,
+            _ => return Err(IndexMetadataParseError::UnrecognisedState(state.to_string())),
+        };
+    }
+
     Ok(())
 }
 
 
+/// Parses updated settings into a copy of an index's metadata
+///
+/// New analysis components are added alongside the existing ones and the mappings are
+/// rebuilt afterwards so they pick up any analyzers that were redefined. The fields in
+/// the new mappings aren't linked to the store.
+pub fn parse_settings_update(metadata_json: serde_json::Value, settings: serde_json::Value) -> Result<IndexMetadata, IndexMetadataParseError> {
+    let mut metadata_json = match metadata_json {
+        serde_json::Value::Object(object) => object,
+        _ => return Err(IndexMetadataParseError::ExpectedObject),
+    };
+
+    let mut metadata = IndexMetadata::default();
+
+    if let Some(existing_settings) = metadata_json.remove("settings") {
+        try!(parse(&mut metadata, json!({"settings": existing_settings})));
+    }
+
+    try!(parse(&mut metadata, json!({"settings": settings})));
+
+    // Mappings and state
+    try!(parse(&mut metadata, serde_json::Value::Object(metadata_json)));
+
+    Ok(metadata)
+}
+
+
 #[cfg(test)]
 mod tests {
+    use std::usize;
+    use std::fs::{self, File};
+    use std::io::Write;
+    use std::path::Path;
+
     use serde_json;
+    use serde_json::value::ToJson;
+    use kite::similarity::SimilarityModel;
 
     use analysis::ngram_generator::Edge;
+    use analysis::char_class::CharClass;
+    use analysis::compiled_regex::CompiledRegex;
+    use analysis::char_filters::CharFilterSpec;
+    use analysis::char_filters::pattern_replace;
     use analysis::tokenizers::TokenizerSpec;
     use analysis::filters::FilterSpec;
+    use analysis::filters::word_delimiter::WordDelimiterOptions;
+    use analysis::filters::elision::FRENCH_ARTICLES;
+    use analysis::filters::condition::TokenCondition;
     use analysis::AnalyzerSpec;
+    use analysis::stopwords;
+    use analysis::resources::CONFIG_DIR;
+    use analysis::phonetic::PhoneticEncoder;
     use mapping::parse::MappingParseError;
-    use index::metadata::IndexMetadata;
+    use mapping::build::{MappingBuildError, FieldMappingBuildError};
+    use index::metadata::{IndexMetadata, IndexState, AliasMetadata};
+    use index::metadata::settings::IndexSettings;
 
-    use super::{parse, IndexMetadataParseError};
+    use super::{parse, parse_settings_update, IndexMetadataParseError};
     use super::analysis_tokenizer::TokenizerParseError;
     use super::analysis_filter::FilterParseError;
+    use super::analysis_analyzer::AnalyzerParseError;
+    use super::analysis_char_filter::CharFilterParseError;
 
     #[test]
     fn test_default() {
@@ -137,9 +284,9 @@ mod tests {
         {}
         ").unwrap()).expect("parse() returned an error");
 
-        assert_eq!(metadata.tokenizers().len(), 2);
-        assert_eq!(metadata.filters().len(), 2);
-        assert_eq!(metadata.analyzers().len(), 1);
+        assert_eq!(metadata.tokenizers().len(), 5);
+        assert_eq!(metadata.filters().len(), 9);
+        assert_eq!(metadata.analyzers().len(), 6);
 
         // Check builtin tokenizers
         let standard_tokenizer = metadata.tokenizers().get("standard").expect("'standard' tokenizer wasn't created");
@@ -148,6 +295,15 @@ mod tests {
         let lowercase_tokenizer = metadata.tokenizers().get("lowercase").expect("'lowercase' tokenizer wasn't created");
         assert_eq!(*lowercase_tokenizer, TokenizerSpec::Lowercase);
 
+        let keyword_tokenizer = metadata.tokenizers().get("keyword").expect("'keyword' tokenizer wasn't created");
+        assert_eq!(*keyword_tokenizer, TokenizerSpec::Keyword);
+
+        let uax_url_email_tokenizer = metadata.tokenizers().get("uax_url_email").expect("'uax_url_email' tokenizer wasn't created");
+        assert_eq!(*uax_url_email_tokenizer, TokenizerSpec::UaxUrlEmail);
+
+        let cjk_bigram_tokenizer = metadata.tokenizers().get("cjk_bigram").expect("'cjk_bigram' tokenizer wasn't created");
+        assert_eq!(*cjk_bigram_tokenizer, TokenizerSpec::CJKBigram);
+
         // Check builtin filters
         let lowercase_filter = metadata.filters().get("lowercase").expect("'lowercase' filter wasn't created");
         assert_eq!(*lowercase_filter, FilterSpec::Lowercase);
@@ -155,15 +311,65 @@ mod tests {
         let asciifolding_filter = metadata.filters().get("asciifolding").expect("'asciifolding' filter wasn't created");
         assert_eq!(*asciifolding_filter, FilterSpec::ASCIIFolding);
 
+        let stop_filter = metadata.filters().get("stop").expect("'stop' filter wasn't created");
+        assert_eq!(*stop_filter, FilterSpec::new_stop(stopwords::ENGLISH.iter().map(|word| word.to_string()).collect(), None));
+
+        let porter_stem_filter = metadata.filters().get("porter_stem").expect("'porter_stem' filter wasn't created");
+        assert_eq!(*porter_stem_filter, FilterSpec::PorterStem);
+
+        let trim_filter = metadata.filters().get("trim").expect("'trim' filter wasn't created");
+        assert_eq!(*trim_filter, FilterSpec::Trim);
+
+        let unique_filter = metadata.filters().get("unique").expect("'unique' filter wasn't created");
+        assert_eq!(*unique_filter, FilterSpec::Unique {
+            only_on_same_position: false,
+        });
+
+        let reverse_filter = metadata.filters().get("reverse").expect("'reverse' filter wasn't created");
+        assert_eq!(*reverse_filter, FilterSpec::Reverse);
+
+        let cjk_width_filter = metadata.filters().get("cjk_width").expect("'cjk_width' filter wasn't created");
+        assert_eq!(*cjk_width_filter, FilterSpec::CJKWidth);
+
+        let decimal_digit_filter = metadata.filters().get("decimal_digit").expect("'decimal_digit' filter wasn't created");
+        assert_eq!(*decimal_digit_filter, FilterSpec::DecimalDigit);
+
+        // Check builtin character filters
+        let html_strip_char_filter = metadata.char_filters().get("html_strip").expect("'html_strip' character filter wasn't created");
+        assert_eq!(*html_strip_char_filter, CharFilterSpec::HTMLStrip {
+            escaped_tags: vec![],
+        });
+
         // Check builtin analyzers
         let standard_analyzer = metadata.analyzers().get("standard").expect("'standard' analyzer wasn't created");
         assert_eq!(*standard_analyzer, AnalyzerSpec {
+            char_filters: vec![],
             tokenizer: TokenizerSpec::Standard,
             filters: vec![
                 FilterSpec::Lowercase,
                 FilterSpec::ASCIIFolding,
             ]
         });
+
+        let french_analyzer = metadata.analyzers().get("french").expect("'french' analyzer wasn't created");
+        assert_eq!(*french_analyzer, AnalyzerSpec {
+            char_filters: vec![],
+            tokenizer: TokenizerSpec::Standard,
+            filters: vec![
+                FilterSpec::Elision {
+                    articles: FRENCH_ARTICLES.iter().map(|article| article.to_string()).collect(),
+                },
+                FilterSpec::Lowercase,
+                FilterSpec::new_stop(stopwords::FRENCH.iter().map(|word| word.to_string()).collect(), None),
+                FilterSpec::Stemmer {
+                    language: "french".to_string(),
+                },
+            ]
+        });
+
+        for language in &["english", "german", "spanish", "russian"] {
+            assert!(metadata.analyzers().contains_key(*language), "'{}' analyzer wasn't created", language);
+        }
     }
 
     #[test]
@@ -226,9 +432,9 @@ mod tests {
         }
         ").unwrap()).expect("parse() returned an error");
 
-        assert_eq!(metadata.tokenizers().len(), 6);
-        assert_eq!(metadata.filters().len(), 6);
-        assert_eq!(metadata.analyzers().len(), 1);
+        assert_eq!(metadata.tokenizers().len(), 9);
+        assert_eq!(metadata.filters().len(), 13);
+        assert_eq!(metadata.analyzers().len(), 6);
 
         // Check tokenizers
         let ngram_tokenizer = metadata.tokenizers().get("ngram_tokenizer").expect("'ngram_tokenizer' wasn't created");
@@ -236,6 +442,7 @@ mod tests {
             min_size: 3,
             max_size: 15,
             edge: Edge::Neither,
+            token_chars: vec![],
         });
 
         let edgengram_tokenizer = metadata.tokenizers().get("edgengram_tokenizer").expect("'edgengram_tokenizer' wasn't created");
@@ -243,6 +450,7 @@ mod tests {
             min_size: 2,
             max_size: 15,
             edge: Edge::Left,
+            token_chars: vec![],
         });
 
         let edgengram_tokenizer_side_front = metadata.tokenizers().get("edgengram_tokenizer_side_front").expect("'edgengram_tokenizer_side_front' wasn't created");
@@ -250,6 +458,7 @@ mod tests {
             min_size: 2,
             max_size: 15,
             edge: Edge::Left,
+            token_chars: vec![],
         });
 
         let edgengram_tokenizer_side_back = metadata.tokenizers().get("edgengram_tokenizer_side_back").expect("'edgengram_tokenizer_side_back' wasn't created");
@@ -257,6 +466,7 @@ mod tests {
             min_size: 2,
             max_size: 15,
             edge: Edge::Right,
+            token_chars: vec![],
         });
 
         // Check filters
@@ -265,6 +475,7 @@ mod tests {
             min_size: 3,
             max_size: 15,
             edge: Edge::Neither,
+            preserve_original: false,
         });
 
         let edgengram_filter = metadata.filters().get("edgengram_filter").expect("'edgengram_filter' wasn't created");
@@ -272,6 +483,7 @@ mod tests {
             min_size: 2,
             max_size: 15,
             edge: Edge::Left,
+            preserve_original: false,
         });
 
         let edgengram_filter_side_front = metadata.filters().get("edgengram_filter_side_front").expect("'edgengram_filter_side_front' wasn't created");
@@ -279,6 +491,7 @@ mod tests {
             min_size: 2,
             max_size: 15,
             edge: Edge::Left,
+            preserve_original: false,
         });
 
         let edgengram_filter_side_back = metadata.filters().get("edgengram_filter_side_back").expect("'edgengram_filter_side_back' wasn't created");
@@ -286,6 +499,7 @@ mod tests {
             min_size: 2,
             max_size: 15,
             edge: Edge::Right,
+            preserve_original: false,
         });
     }
 
@@ -310,7 +524,203 @@ mod tests {
     }
 
     #[test]
-    fn test_custom_analyser_bad_filter_type() {
+    fn test_custom_analyser_builtins() {
+        let mut metadata = IndexMetadata::default();
+        parse(&mut metadata, serde_json::from_str("
+        {
+            \"settings\": {
+                \"analysis\": {
+                    \"analyzer\": {
+                        \"builtins\": {
+                            \"type\": \"custom\",
+                            \"tokenizer\": \"cjk_bigram\",
+                            \"filter\": [\"cjk_width\", \"decimal_digit\", \"lowercase\", \"trim\", \"unique\", \"reverse\"]
+                        }
+                    }
+                }
+            }
+        }
+        ").unwrap()).expect("parse() returned an error");
+
+        let analyzer = metadata.analyzers().get("builtins").expect("'builtins' analyzer wasn't created");
+        assert_eq!(*analyzer, AnalyzerSpec {
+            char_filters: vec![],
+            tokenizer: TokenizerSpec::CJKBigram,
+            filters: vec![
+                FilterSpec::CJKWidth,
+                FilterSpec::DecimalDigit,
+                FilterSpec::Lowercase,
+                FilterSpec::Trim,
+                FilterSpec::Unique {
+                    only_on_same_position: false,
+                },
+                FilterSpec::Reverse,
+            ]
+        });
+    }
+
+    #[test]
+    fn test_custom_analyser_pattern_tokenizer() {
+        let mut metadata = IndexMetadata::default();
+        parse(&mut metadata, serde_json::from_str("
+        {
+            \"settings\": {
+                \"analysis\": {
+                    \"tokenizer\": {
+                        \"sku_tokenizer\": {
+                            \"type\": \"pattern\",
+                            \"pattern\": \"([A-Z]+)-(\\\\d+)\",
+                            \"group\": 2
+                        },
+                        \"split_tokenizer\": {
+                            \"type\": \"pattern\"
+                        }
+                    }
+                }
+            }
+        }
+        ").unwrap()).expect("parse() returned an error");
+
+        let sku_tokenizer = metadata.tokenizers().get("sku_tokenizer").expect("'sku_tokenizer' wasn't created");
+        assert_eq!(*sku_tokenizer, TokenizerSpec::Pattern {
+            pattern: CompiledRegex::new("([A-Z]+)-(\\d+)").unwrap(),
+            group: Some(2),
+        });
+
+        let split_tokenizer = metadata.tokenizers().get("split_tokenizer").expect("'split_tokenizer' wasn't created");
+        assert_eq!(*split_tokenizer, TokenizerSpec::Pattern {
+            pattern: CompiledRegex::new("\\W+").unwrap(),
+            group: None,
+        });
+    }
+
+    #[test]
+    fn test_custom_analyser_char_group_tokenizer() {
+        let mut metadata = IndexMetadata::default();
+        parse(&mut metadata, serde_json::from_str("
+        {
+            \"settings\": {
+                \"analysis\": {
+                    \"tokenizer\": {
+                        \"char_group_tokenizer\": {
+                            \"type\": \"char_group\",
+                            \"tokenize_on_chars\": [\"whitespace\", \"-\", \"\\n\"]
+                        }
+                    }
+                }
+            }
+        }
+        ").unwrap()).expect("parse() returned an error");
+
+        let char_group_tokenizer = metadata.tokenizers().get("char_group_tokenizer").expect("'char_group_tokenizer' wasn't created");
+        assert_eq!(*char_group_tokenizer, TokenizerSpec::CharGroup {
+            split_on_classes: vec![CharClass::Whitespace],
+            split_on_chars: vec!['-', '\n'],
+        });
+    }
+
+    #[test]
+    fn test_custom_analyser_bad_char_group() {
+        let mut metadata = IndexMetadata::default();
+        let error = parse(&mut metadata, serde_json::from_str("
+        {
+            \"settings\": {
+                \"analysis\": {
+                    \"tokenizer\": {
+                        \"bad_tokenizer\": {
+                            \"type\": \"char_group\",
+                            \"tokenize_on_chars\": [\"foo\"]
+                        }
+                    }
+                }
+            }
+        }
+        ").unwrap()).err().expect("parse() was supposed to return an error, but didn't");
+
+        assert_eq!(error, IndexMetadataParseError::TokenizerParseError("bad_tokenizer".to_string(), TokenizerParseError::UnrecognisedTokenChars("foo".to_string())));
+    }
+
+    #[test]
+    fn test_custom_analyser_bad_tokenizer_pattern() {
+        let mut metadata = IndexMetadata::default();
+        let error = parse(&mut metadata, serde_json::from_str("
+        {
+            \"settings\": {
+                \"analysis\": {
+                    \"tokenizer\": {
+                        \"bad_tokenizer\": {
+                            \"type\": \"pattern\",
+                            \"pattern\": \"(foo\"
+                        }
+                    }
+                }
+            }
+        }
+        ").unwrap()).err().expect("parse() was supposed to return an error, but didn't");
+
+        match error {
+            IndexMetadataParseError::TokenizerParseError(ref name, TokenizerParseError::InvalidPattern(_)) => {
+                assert_eq!(name, "bad_tokenizer");
+            }
+            error => panic!("unexpected error: {:?}", error),
+        }
+    }
+
+    #[test]
+    fn test_custom_analyser_stop_filter() {
+        let mut metadata = IndexMetadata::default();
+        parse(&mut metadata, serde_json::from_str("
+        {
+            \"settings\": {
+                \"analysis\": {
+                    \"filter\": {
+                        \"french_stop\": {
+                            \"type\": \"stop\",
+                            \"stopwords\": \"_french_\"
+                        },
+                        \"custom_stop\": {
+                            \"type\": \"stop\",
+                            \"stopwords\": [\"foo\", \"bar\"]
+                        }
+                    }
+                }
+            }
+        }
+        ").unwrap()).expect("parse() returned an error");
+
+        let french_stop_filter = metadata.filters().get("french_stop").expect("'french_stop' wasn't created");
+        assert_eq!(*french_stop_filter, FilterSpec::new_stop(stopwords::FRENCH.iter().map(|word| word.to_string()).collect(), None));
+
+        let custom_stop_filter = metadata.filters().get("custom_stop").expect("'custom_stop' wasn't created");
+        assert_eq!(*custom_stop_filter, FilterSpec::new_stop(vec!["foo".to_string(), "bar".to_string()], None));
+    }
+
+    #[test]
+    fn test_custom_analyser_stemmer_filter() {
+        let mut metadata = IndexMetadata::default();
+        parse(&mut metadata, serde_json::from_str("
+        {
+            \"settings\": {
+                \"analysis\": {
+                    \"filter\": {
+                        \"german_stemmer\": {
+                            \"type\": \"stemmer\",
+                            \"language\": \"German\"
+                        }
+                    }
+                }
+            }
+        }
+        ").unwrap()).expect("parse() returned an error");
+
+        let german_stemmer_filter = metadata.filters().get("german_stemmer").expect("'german_stemmer' wasn't created");
+        assert_eq!(*german_stemmer_filter, FilterSpec::Stemmer {
+            language: "german".to_string(),
+        });
+    }
+
+    #[test]
+    fn test_custom_analyser_bad_stemmer_language() {
         let mut metadata = IndexMetadata::default();
         let error = parse(&mut metadata, serde_json::from_str("
         {
@@ -318,7 +728,8 @@ mod tests {
                 \"analysis\": {
                     \"filter\": {
                         \"bad_filter\": {
-                            \"type\": \"foo\"
+                            \"type\": \"stemmer\",
+                            \"language\": \"klingon\"
                         }
                     }
                 }
@@ -326,38 +737,1081 @@ mod tests {
         }
         ").unwrap()).err().expect("parse() was supposed to return an error, but didn't");
 
-        assert_eq!(error, IndexMetadataParseError::FilterParseError("bad_filter".to_string(), FilterParseError::UnrecognisedType("foo".to_string())));
+        assert_eq!(error, IndexMetadataParseError::FilterParseError("bad_filter".to_string(), FilterParseError::UnrecognisedLanguage("klingon".to_string())));
     }
 
     #[test]
-    fn test_mapping() {
+    fn test_custom_analyser_bad_synonym_rule() {
+        let mut metadata = IndexMetadata::default();
+        let error = parse(&mut metadata, serde_json::from_str("
+        {
+            \"settings\": {
+                \"analysis\": {
+                    \"filter\": {
+                        \"bad_filter\": {
+                            \"type\": \"synonym\",
+                            \"synonyms\": [\"nyc, new york\", \"foo => \"]
+                        }
+                    }
+                }
+            }
+        }
+        ").unwrap()).err().expect("parse() was supposed to return an error, but didn't");
+
+        assert_eq!(error, IndexMetadataParseError::FilterParseError("bad_filter".to_string(), FilterParseError::InvalidSynonymRule("foo =>".to_string())));
+    }
+
+    /// Writes a file into the config directory, returning its path relative to it
+    fn write_resource_file(name: &str, contents: &[u8]) -> String {
+        let path = Path::new(CONFIG_DIR).join("rusticsearch_test").join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        File::create(&path).unwrap().write_all(contents).unwrap();
+
+        format!("rusticsearch_test/{}", name)
+    }
+
+    #[test]
+    fn test_custom_analyser_stop_filter_path() {
+        let path = write_resource_file("stopwords.txt", b"# Stopwords\nfoo\n\nbar\n");
+
         let mut metadata = IndexMetadata::default();
         parse(&mut metadata, json!({
-            "mappings": {
-                "test_mapping": {
-                    "properties": {
-                        "test_field": {
-                            "type": "string",
+            "settings": {
+                "analysis": {
+                    "filter": {
+                        "file_stop": {
+                            "type": "stop",
+                            "stopwords_path": path
                         }
                     }
                 }
             }
         })).expect("parse() returned an error");
 
-        assert_eq!(metadata.mappings.len(), 1);
+        let file_stop_filter = metadata.filters().get("file_stop").expect("'file_stop' wasn't created");
+        assert_eq!(*file_stop_filter, FilterSpec::new_stop(vec!["foo".to_string(), "bar".to_string()], Some(path)));
     }
 
     #[test]
-    fn test_mapping_error() {
+    fn test_custom_analyser_unreadable_synonyms_path() {
         let mut metadata = IndexMetadata::default();
-        let error = parse(&mut metadata, json!({
-            "mappings": {
-                "test_mapping": {
-                    "foo": []
+        let error = parse(&mut metadata, serde_json::from_str("
+        {
+            \"settings\": {
+                \"analysis\": {
+                    \"filter\": {
+                        \"bad_filter\": {
+                            \"type\": \"synonym\",
+                            \"synonyms_path\": \"does/not/exist.txt\"
+                        }
+                    }
                 }
             }
-        })).err().expect("parse() was supposed to return an error, but didn't");
+        }
+        ").unwrap()).err().expect("parse() was supposed to return an error, but didn't");
 
-        assert_eq!(error, IndexMetadataParseError::MappingParseError("test_mapping".to_string(), MappingParseError::UnrecognisedKeys(vec!["foo".to_string()])));
+        assert_eq!(error, IndexMetadataParseError::FilterParseError("bad_filter".to_string(), FilterParseError::UnreadableFile("does/not/exist.txt".to_string())));
+    }
+
+    #[test]
+    fn test_custom_analyser_synonyms_path_outside_config_dir() {
+        for path in vec!["/etc/passwd", "../secret.txt"] {
+            let mut metadata = IndexMetadata::default();
+            let error = parse(&mut metadata, json!({
+                "settings": {
+                    "analysis": {
+                        "filter": {
+                            "bad_filter": {
+                                "type": "synonym",
+                                "synonyms_path": path,
+                                "synonyms": ["nyc, new york"]
+                            }
+                        }
+                    }
+                }
+            })).err().expect("parse() was supposed to return an error, but didn't");
+
+            assert_eq!(error, IndexMetadataParseError::FilterParseError("bad_filter".to_string(), FilterParseError::InvalidPath(path.to_string())));
+        }
+    }
+
+    #[test]
+    fn test_custom_analyser_missing_synonyms_file_uses_saved_synonyms() {
+        let mut metadata = IndexMetadata::default();
+        parse(&mut metadata, json!({
+            "settings": {
+                "analysis": {
+                    "filter": {
+                        "my_synonyms": {
+                            "type": "synonym",
+                            "synonyms_path": "does/not/exist.txt",
+                            "synonyms": ["nyc, new york"]
+                        }
+                    }
+                }
+            }
+        })).expect("parse() returned an error");
+
+        // The path is kept so the file is read again when the analyzers are reloaded
+        let filter = metadata.filters().get("my_synonyms").expect("'my_synonyms' wasn't created");
+        assert_eq!(*filter, FilterSpec::new_synonym(vec!["nyc, new york".to_string()], Some("does/not/exist.txt".to_string())).unwrap());
+    }
+
+    #[test]
+    fn test_reload_search_analyzers() {
+        let path_str = write_resource_file("synonyms.txt", b"nyc, new york\n");
+
+        let mut metadata = IndexMetadata::default();
+        parse(&mut metadata, json!({
+            "settings": {
+                "analysis": {
+                    "filter": {
+                        "my_synonyms": {
+                            "type": "synonym",
+                            "synonyms_path": path_str
+                        }
+                    },
+                    "analyzer": {
+                        "my_search_analyzer": {
+                            "tokenizer": "standard",
+                            "filter": ["lowercase", "my_synonyms"]
+                        }
+                    }
+                }
+            }
+        })).expect("parse() returned an error");
+
+        write_resource_file("synonyms.txt", b"nyc, new york\nuk, united kingdom\n");
+
+        let reloaded_analyzers = metadata.reload_search_analyzers().expect("reload_search_analyzers() returned an error");
+        assert_eq!(reloaded_analyzers, vec!["my_search_analyzer".to_string()]);
+
+        let synonyms = vec!["nyc, new york".to_string(), "uk, united kingdom".to_string()];
+
+        let filter = metadata.filters().get("my_synonyms").expect("'my_synonyms' wasn't created");
+        assert_eq!(*filter, FilterSpec::new_synonym(synonyms.clone(), Some(path_str.clone())).unwrap());
+
+        let analyzer = metadata.analyzers().get("my_search_analyzer").expect("'my_search_analyzer' wasn't created");
+        assert_eq!(analyzer.filters[1], FilterSpec::new_synonym(synonyms, Some(path_str)).unwrap());
+    }
+
+    #[test]
+    fn test_custom_analyser_word_delimiter_filter() {
+        let mut metadata = IndexMetadata::default();
+        parse(&mut metadata, serde_json::from_str("
+        {
+            \"settings\": {
+                \"analysis\": {
+                    \"filter\": {
+                        \"product_delimiter\": {
+                            \"type\": \"word_delimiter_graph\",
+                            \"catenate_words\": true,
+                            \"preserve_original\": true
+                        }
+                    }
+                }
+            }
+        }
+        ").unwrap()).expect("parse() returned an error");
+
+        let product_delimiter_filter = metadata.filters().get("product_delimiter").expect("'product_delimiter' wasn't created");
+        assert_eq!(*product_delimiter_filter, FilterSpec::WordDelimiter(WordDelimiterOptions {
+            catenate_words: true,
+            preserve_original: true,
+            ..WordDelimiterOptions::default()
+        }));
+    }
+
+    #[test]
+    fn test_custom_analyser_normalization_filters() {
+        let mut metadata = IndexMetadata::default();
+        parse(&mut metadata, serde_json::from_str("
+        {
+            \"settings\": {
+                \"analysis\": {
+                    \"filter\": {
+                        \"length_filter\": {
+                            \"type\": \"length\",
+                            \"min\": 2
+                        },
+                        \"trim_filter\": {
+                            \"type\": \"trim\"
+                        },
+                        \"truncate_filter\": {
+                            \"type\": \"truncate\",
+                            \"length\": 5
+                        },
+                        \"unique_filter\": {
+                            \"type\": \"unique\",
+                            \"only_on_same_position\": true
+                        },
+                        \"cjk_width_filter\": {
+                            \"type\": \"cjk_width\"
+                        },
+                        \"decimal_digit_filter\": {
+                            \"type\": \"decimal_digit\"
+                        }
+                    }
+                }
+            }
+        }
+        ").unwrap()).expect("parse() returned an error");
+
+        let length_filter = metadata.filters().get("length_filter").expect("'length_filter' wasn't created");
+        assert_eq!(*length_filter, FilterSpec::Length {
+            min: 2,
+            max: usize::MAX,
+        });
+
+        let trim_filter = metadata.filters().get("trim_filter").expect("'trim_filter' wasn't created");
+        assert_eq!(*trim_filter, FilterSpec::Trim);
+
+        let truncate_filter = metadata.filters().get("truncate_filter").expect("'truncate_filter' wasn't created");
+        assert_eq!(*truncate_filter, FilterSpec::Truncate {
+            length: 5,
+        });
+
+        let unique_filter = metadata.filters().get("unique_filter").expect("'unique_filter' wasn't created");
+        assert_eq!(*unique_filter, FilterSpec::Unique {
+            only_on_same_position: true,
+        });
+
+        let cjk_width_filter = metadata.filters().get("cjk_width_filter").expect("'cjk_width_filter' wasn't created");
+        assert_eq!(*cjk_width_filter, FilterSpec::CJKWidth);
+
+        let decimal_digit_filter = metadata.filters().get("decimal_digit_filter").expect("'decimal_digit_filter' wasn't created");
+        assert_eq!(*decimal_digit_filter, FilterSpec::DecimalDigit);
+    }
+
+    #[test]
+    fn test_custom_analyser_keep_filters() {
+        let mut metadata = IndexMetadata::default();
+        parse(&mut metadata, serde_json::from_str("
+        {
+            \"settings\": {
+                \"analysis\": {
+                    \"filter\": {
+                        \"colours\": {
+                            \"type\": \"keep\",
+                            \"keep_words\": [\"red\", \"blue\"],
+                            \"keep_words_case\": true
+                        },
+                        \"numbers\": {
+                            \"type\": \"keep_types\",
+                            \"types\": [\"<NUM>\"]
+                        },
+                        \"no_numbers\": {
+                            \"type\": \"keep_types\",
+                            \"types\": [\"<NUM>\"],
+                            \"mode\": \"exclude\"
+                        }
+                    }
+                }
+            }
+        }
+        ").unwrap()).expect("parse() returned an error");
+
+        let colours_filter = metadata.filters().get("colours").expect("'colours' wasn't created");
+        assert_eq!(*colours_filter, FilterSpec::KeepWords {
+            words: vec!["red".to_string(), "blue".to_string()],
+            ignore_case: true,
+        });
+
+        let numbers_filter = metadata.filters().get("numbers").expect("'numbers' wasn't created");
+        assert_eq!(*numbers_filter, FilterSpec::KeepTypes {
+            types: vec!["<NUM>".to_string()],
+            exclude: false,
+        });
+
+        let no_numbers_filter = metadata.filters().get("no_numbers").expect("'no_numbers' wasn't created");
+        assert_eq!(*no_numbers_filter, FilterSpec::KeepTypes {
+            types: vec!["<NUM>".to_string()],
+            exclude: true,
+        });
+    }
+
+    #[test]
+    fn test_custom_analyser_phonetic_filter() {
+        let mut metadata = IndexMetadata::default();
+        parse(&mut metadata, serde_json::from_str("
+        {
+            \"settings\": {
+                \"analysis\": {
+                    \"filter\": {
+                        \"name_sounds\": {
+                            \"type\": \"phonetic\",
+                            \"encoder\": \"soundex\",
+                            \"replace\": false
+                        }
+                    }
+                }
+            }
+        }
+        ").unwrap()).expect("parse() returned an error");
+
+        let name_sounds_filter = metadata.filters().get("name_sounds").expect("'name_sounds' wasn't created");
+        assert_eq!(*name_sounds_filter, FilterSpec::Phonetic {
+            encoder: PhoneticEncoder::Soundex,
+            replace: false,
+        });
+    }
+
+    #[test]
+    fn test_custom_analyser_bad_phonetic_encoder() {
+        let mut metadata = IndexMetadata::default();
+        let error = parse(&mut metadata, serde_json::from_str("
+        {
+            \"settings\": {
+                \"analysis\": {
+                    \"filter\": {
+                        \"bad_filter\": {
+                            \"type\": \"phonetic\",
+                            \"encoder\": \"foo\"
+                        }
+                    }
+                }
+            }
+        }
+        ").unwrap()).err().expect("parse() was supposed to return an error, but didn't");
+
+        assert_eq!(error, IndexMetadataParseError::FilterParseError("bad_filter".to_string(), FilterParseError::UnrecognisedEncoder("foo".to_string())));
+    }
+
+    #[test]
+    fn test_custom_analyser_html_strip_char_filter() {
+        let mut metadata = IndexMetadata::default();
+        parse(&mut metadata, serde_json::from_str("
+        {
+            \"settings\": {
+                \"analysis\": {
+                    \"char_filter\": {
+                        \"my_html_strip\": {
+                            \"type\": \"html_strip\",
+                            \"escaped_tags\": [\"B\"]
+                        }
+                    },
+                    \"analyzer\": {
+                        \"my_analyzer\": {
+                            \"type\": \"custom\",
+                            \"char_filter\": [\"my_html_strip\"],
+                            \"tokenizer\": \"standard\",
+                            \"filter\": [\"lowercase\"]
+                        }
+                    }
+                }
+            }
+        }
+        ").unwrap()).expect("parse() returned an error");
+
+        let html_strip_char_filter = metadata.char_filters().get("my_html_strip").expect("'my_html_strip' wasn't created");
+        assert_eq!(*html_strip_char_filter, CharFilterSpec::HTMLStrip {
+            escaped_tags: vec!["b".to_string()],
+        });
+
+        let analyzer = metadata.analyzers().get("my_analyzer").expect("'my_analyzer' wasn't created");
+        assert_eq!(*analyzer, AnalyzerSpec {
+            char_filters: vec![
+                CharFilterSpec::HTMLStrip {
+                    escaped_tags: vec!["b".to_string()],
+                },
+            ],
+            tokenizer: TokenizerSpec::Standard,
+            filters: vec![
+                FilterSpec::Lowercase,
+            ]
+        });
+    }
+
+    #[test]
+    fn test_custom_analyser_pattern_replace_char_filter() {
+        let mut metadata = IndexMetadata::default();
+        parse(&mut metadata, serde_json::from_str("
+        {
+            \"settings\": {
+                \"analysis\": {
+                    \"char_filter\": {
+                        \"phone_numbers\": {
+                            \"type\": \"pattern_replace\",
+                            \"pattern\": \"(\\\\d{3})-(\\\\d{4})\",
+                            \"replacement\": \"$1$2\",
+                            \"flags\": \"CASE_INSENSITIVE\"
+                        }
+                    }
+                }
+            }
+        }
+        ").unwrap()).expect("parse() returned an error");
+
+        let phone_numbers_char_filter = metadata.char_filters().get("phone_numbers").expect("'phone_numbers' wasn't created");
+        assert_eq!(*phone_numbers_char_filter, CharFilterSpec::PatternReplace {
+            pattern: "(\\d{3})-(\\d{4})".to_string(),
+            replacement: "$1$2".to_string(),
+            flags: "CASE_INSENSITIVE".to_string(),
+            regex: pattern_replace::compile("(\\d{3})-(\\d{4})", "CASE_INSENSITIVE").unwrap(),
+        });
+        assert_eq!(phone_numbers_char_filter.apply("555-1234"), "5551234");
+    }
+
+    #[test]
+    fn test_custom_analyser_bad_char_filter_flag() {
+        let mut metadata = IndexMetadata::default();
+        let error = parse(&mut metadata, serde_json::from_str("
+        {
+            \"settings\": {
+                \"analysis\": {
+                    \"char_filter\": {
+                        \"bad_char_filter\": {
+                            \"type\": \"pattern_replace\",
+                            \"pattern\": \"foo\",
+                            \"flags\": \"LITERAL\"
+                        }
+                    }
+                }
+            }
+        }
+        ").unwrap()).err().expect("parse() was supposed to return an error, but didn't");
+
+        assert_eq!(error, IndexMetadataParseError::CharFilterParseError("bad_char_filter".to_string(), CharFilterParseError::UnrecognisedFlag("LITERAL".to_string())));
+    }
+
+    #[test]
+    fn test_custom_analyser_bad_char_filter() {
+        let mut metadata = IndexMetadata::default();
+        let error = parse(&mut metadata, serde_json::from_str("
+        {
+            \"settings\": {
+                \"analysis\": {
+                    \"analyzer\": {
+                        \"my_analyzer\": {
+                            \"type\": \"custom\",
+                            \"char_filter\": [\"foo\"],
+                            \"tokenizer\": \"standard\"
+                        }
+                    }
+                }
+            }
+        }
+        ").unwrap()).err().expect("parse() was supposed to return an error, but didn't");
+
+        assert_eq!(error, IndexMetadataParseError::AnalyzerParseError("my_analyzer".to_string(), AnalyzerParseError::UnrecognisedCharFilter("foo".to_string())));
+    }
+
+    #[test]
+    fn test_custom_analyser_inline_definitions() {
+        let mut metadata = IndexMetadata::default();
+        parse(&mut metadata, serde_json::from_str("
+        {
+            \"settings\": {
+                \"analysis\": {
+                    \"analyzer\": {
+                        \"my_analyzer\": {
+                            \"type\": \"custom\",
+                            \"char_filter\": [
+                                {
+                                    \"type\": \"html_strip\"
+                                }
+                            ],
+                            \"tokenizer\": {
+                                \"type\": \"keyword\"
+                            },
+                            \"filter\": [
+                                \"lowercase\",
+                                {
+                                    \"type\": \"truncate\",
+                                    \"length\": 5
+                                }
+                            ]
+                        }
+                    }
+                }
+            }
+        }
+        ").unwrap()).expect("parse() returned an error");
+
+        let analyzer = metadata.analyzers().get("my_analyzer").expect("'my_analyzer' wasn't created");
+        assert_eq!(*analyzer, AnalyzerSpec {
+            char_filters: vec![
+                CharFilterSpec::HTMLStrip {
+                    escaped_tags: vec![],
+                },
+            ],
+            tokenizer: TokenizerSpec::Keyword,
+            filters: vec![
+                FilterSpec::Lowercase,
+                FilterSpec::Truncate {
+                    length: 5,
+                },
+            ]
+        });
+    }
+
+    #[test]
+    fn test_custom_analyser_bad_inline_filter() {
+        let mut metadata = IndexMetadata::default();
+        let error = parse(&mut metadata, serde_json::from_str("
+        {
+            \"settings\": {
+                \"analysis\": {
+                    \"analyzer\": {
+                        \"my_analyzer\": {
+                            \"type\": \"custom\",
+                            \"tokenizer\": \"standard\",
+                            \"filter\": [
+                                {
+                                    \"type\": \"foo\"
+                                }
+                            ]
+                        }
+                    }
+                }
+            }
+        }
+        ").unwrap()).err().expect("parse() was supposed to return an error, but didn't");
+
+        assert_eq!(error, IndexMetadataParseError::AnalyzerParseError("my_analyzer".to_string(), AnalyzerParseError::FilterParseError(FilterParseError::UnrecognisedType("foo".to_string()))));
+    }
+
+    #[test]
+    fn test_analysis_round_trip() {
+        let mut metadata = IndexMetadata::default();
+        parse(&mut metadata, serde_json::from_str("
+        {
+            \"settings\": {
+                \"analysis\": {
+                    \"char_filter\": {
+                        \"dashes\": {
+                            \"type\": \"pattern_replace\",
+                            \"pattern\": \"-\",
+                            \"replacement\": \" \"
+                        }
+                    },
+                    \"tokenizer\": {
+                        \"trigram\": {
+                            \"type\": \"ngram\",
+                            \"min_gram\": 3,
+                            \"max_gram\": 3
+                        }
+                    },
+                    \"filter\": {
+                        \"english_stemmer\": {
+                            \"type\": \"stemmer\",
+                            \"language\": \"english\"
+                        }
+                    },
+                    \"analyzer\": {
+                        \"my_analyzer\": {
+                            \"type\": \"custom\",
+                            \"char_filter\": [\"dashes\"],
+                            \"tokenizer\": \"trigram\",
+                            \"filter\": [\"lowercase\", \"english_stemmer\"]
+                        }
+                    }
+                }
+            }
+        }
+        ").unwrap()).expect("parse() returned an error");
+
+        // Reload the metadata from its JSON representation
+        let mut reloaded_metadata = IndexMetadata::default();
+        parse(&mut reloaded_metadata, metadata.to_json().unwrap()).expect("parse() returned an error");
+
+        assert_eq!(reloaded_metadata.char_filters(), metadata.char_filters());
+        assert_eq!(reloaded_metadata.tokenizers(), metadata.tokenizers());
+        assert_eq!(reloaded_metadata.filters(), metadata.filters());
+        assert_eq!(reloaded_metadata.analyzers(), metadata.analyzers());
+    }
+
+    #[test]
+    fn test_custom_analyser_multiplexer_filter() {
+        let mut metadata = IndexMetadata::default();
+        parse(&mut metadata, serde_json::from_str("
+        {
+            \"settings\": {
+                \"analysis\": {
+                    \"filter\": {
+                        \"forms\": {
+                            \"type\": \"multiplexer\",
+                            \"filters\": [
+                                \"lowercase\",
+                                \"lowercase, my_stemmer\",
+                                [{\"type\": \"phonetic\", \"encoder\": \"soundex\"}]
+                            ],
+                            \"preserve_original\": false
+                        },
+                        \"my_stemmer\": {
+                            \"type\": \"stemmer\",
+                            \"language\": \"english\"
+                        }
+                    }
+                }
+            }
+        }
+        ").unwrap()).expect("parse() returned an error");
+
+        let multiplexer_filter = metadata.filters().get("forms").expect("'forms' wasn't created");
+        assert_eq!(*multiplexer_filter, FilterSpec::Multiplexer {
+            chains: vec![
+                vec![
+                    FilterSpec::Lowercase,
+                ],
+                vec![
+                    FilterSpec::Lowercase,
+                    FilterSpec::Stemmer {
+                        language: "english".to_string(),
+                    },
+                ],
+                vec![
+                    FilterSpec::Phonetic {
+                        encoder: PhoneticEncoder::Soundex,
+                        replace: true,
+                    },
+                ],
+            ],
+            preserve_original: false,
+        });
+    }
+
+    #[test]
+    fn test_custom_analyser_bad_multiplexer_filter() {
+        let mut metadata = IndexMetadata::default();
+        let error = parse(&mut metadata, serde_json::from_str("
+        {
+            \"settings\": {
+                \"analysis\": {
+                    \"filter\": {
+                        \"bad_filter\": {
+                            \"type\": \"multiplexer\",
+                            \"filters\": [\"lowercase, foo\"]
+                        }
+                    }
+                }
+            }
+        }
+        ").unwrap()).err().expect("parse() was supposed to return an error, but didn't");
+
+        assert_eq!(error, IndexMetadataParseError::FilterParseError("bad_filter".to_string(), FilterParseError::UnrecognisedFilter("foo".to_string())));
+    }
+
+    #[test]
+    fn test_custom_analyser_condition_filter() {
+        let mut metadata = IndexMetadata::default();
+        parse(&mut metadata, serde_json::from_str("
+        {
+            \"settings\": {
+                \"analysis\": {
+                    \"filter\": {
+                        \"long_words_stemmer\": {
+                            \"type\": \"condition\",
+                            \"filter\": [\"porter_stem\"],
+                            \"condition\": {
+                                \"length\": {
+                                    \"min\": 5
+                                }
+                            }
+                        },
+                        \"cyrillic_lowercase\": {
+                            \"type\": \"condition\",
+                            \"filter\": \"lowercase\",
+                            \"condition\": {
+                                \"script\": \"cyrillic\"
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        ").unwrap()).expect("parse() returned an error");
+
+        let long_words_stemmer_filter = metadata.filters().get("long_words_stemmer").expect("'long_words_stemmer' wasn't created");
+        assert_eq!(*long_words_stemmer_filter, FilterSpec::Condition {
+            condition: TokenCondition::Length {
+                min: 5,
+                max: usize::MAX,
+            },
+            filters: vec![
+                FilterSpec::PorterStem,
+            ],
+        });
+
+        let cyrillic_lowercase_filter = metadata.filters().get("cyrillic_lowercase").expect("'cyrillic_lowercase' wasn't created");
+        assert_eq!(*cyrillic_lowercase_filter, FilterSpec::Condition {
+            condition: TokenCondition::Script("cyrillic".to_string()),
+            filters: vec![
+                FilterSpec::Lowercase,
+            ],
+        });
+    }
+
+    #[test]
+    fn test_custom_analyser_bad_condition() {
+        let mut metadata = IndexMetadata::default();
+        let error = parse(&mut metadata, serde_json::from_str("
+        {
+            \"settings\": {
+                \"analysis\": {
+                    \"filter\": {
+                        \"bad_filter\": {
+                            \"type\": \"condition\",
+                            \"filter\": [\"lowercase\"],
+                            \"condition\": {
+                                \"script\": \"klingon\"
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        ").unwrap()).err().expect("parse() was supposed to return an error, but didn't");
+
+        match error {
+            IndexMetadataParseError::FilterParseError(ref name, FilterParseError::InvalidCondition(_)) => {
+                assert_eq!(name, "bad_filter");
+            }
+            error => panic!("unexpected error: {:?}", error),
+        }
+    }
+
+    #[test]
+    fn test_custom_analyser_bad_filter_type() {
+        let mut metadata = IndexMetadata::default();
+        let error = parse(&mut metadata, serde_json::from_str("
+        {
+            \"settings\": {
+                \"analysis\": {
+                    \"filter\": {
+                        \"bad_filter\": {
+                            \"type\": \"foo\"
+                        }
+                    }
+                }
+            }
+        }
+        ").unwrap()).err().expect("parse() was supposed to return an error, but didn't");
+
+        assert_eq!(error, IndexMetadataParseError::FilterParseError("bad_filter".to_string(), FilterParseError::UnrecognisedType("foo".to_string())));
+    }
+
+    #[test]
+    fn test_custom_analyser_synonym_graph_filter() {
+        let mut metadata = IndexMetadata::default();
+        let error = parse(&mut metadata, serde_json::from_str("
+        {
+            \"settings\": {
+                \"analysis\": {
+                    \"filter\": {
+                        \"my_synonyms\": {
+                            \"type\": \"synonym_graph\",
+                            \"synonyms\": [\"nyc, new york\"]
+                        }
+                    }
+                }
+            }
+        }
+        ").unwrap()).err().expect("parse() was supposed to return an error, but didn't");
+
+        assert_eq!(error, IndexMetadataParseError::FilterParseError("my_synonyms".to_string(), FilterParseError::UnrecognisedType("synonym_graph".to_string())));
+    }
+
+    #[test]
+    fn test_mapping() {
+        let mut metadata = IndexMetadata::default();
+        parse(&mut metadata, json!({
+            "mappings": {
+                "test_mapping": {
+                    "properties": {
+                        "test_field": {
+                            "type": "string",
+                        }
+                    }
+                }
+            }
+        })).expect("parse() returned an error");
+
+        assert_eq!(metadata.mappings.len(), 1);
+    }
+
+    #[test]
+    fn test_mapping_field_analyzers_round_trip() {
+        let mut metadata = IndexMetadata::default();
+        parse(&mut metadata, json!({
+            "mappings": {
+                "test_mapping": {
+                    "properties": {
+                        "test_field": {
+                            "type": "string",
+                            "analyzer": "english",
+                            "search_analyzer": "standard",
+                        }
+                    }
+                }
+            }
+        })).expect("parse() returned an error");
+
+        // Reload the metadata from its JSON representation
+        let mut reloaded_metadata = IndexMetadata::default();
+        parse(&mut reloaded_metadata, metadata.to_json().unwrap()).expect("parse() returned an error");
+
+        let field_mapping = reloaded_metadata.get_field_mapping("test_field").expect("'test_field' wasn't created");
+        assert_eq!(field_mapping.index_analyzer(), metadata.analyzers().get("english"));
+        assert_eq!(field_mapping.search_analyzer(), metadata.analyzers().get("standard"));
+    }
+
+    #[test]
+    fn test_mapping_unrecognised_analyzer() {
+        let mut metadata = IndexMetadata::default();
+        let error = parse(&mut metadata, json!({
+            "mappings": {
+                "test_mapping": {
+                    "properties": {
+                        "test_field": {
+                            "type": "string",
+                            "analyzer": "foo",
+                        }
+                    }
+                }
+            }
+        })).err().expect("parse() was supposed to return an error, but didn't");
+
+        assert_eq!(error, IndexMetadataParseError::MappingBuildError("test_mapping".to_string(), MappingBuildError::FieldMappingBuildError("test_field".to_string(), FieldMappingBuildError::UnrecognisedAnalyzer("foo".to_string()))));
+        assert_eq!(error.to_string(), "unknown analyzer [foo] for field [test_field] in mapping [test_mapping]");
+    }
+
+    #[test]
+    fn test_mapping_similarity() {
+        let mut metadata = IndexMetadata::default();
+        parse(&mut metadata, json!({
+            "settings": {
+                "similarity": {
+                    "short_text": {
+                        "type": "BM25",
+                        "b": 0.3,
+                    }
+                }
+            },
+            "mappings": {
+                "test_mapping": {
+                    "properties": {
+                        "title": {
+                            "type": "string",
+                            "similarity": "short_text",
+                        },
+                        "body": {
+                            "type": "string",
+                            "similarity": "classic",
+                        },
+                        "summary": {
+                            "type": "string",
+                        }
+                    }
+                }
+            }
+        })).expect("parse() returned an error");
+
+        let similarity_model = |field_name: &str| metadata.get_field_mapping(field_name).unwrap().get_search_options().similarity_model;
+        assert_eq!(similarity_model("title"), SimilarityModel::Bm25 {
+            k1: 1.2,
+            b: 0.3,
+        });
+        assert_eq!(similarity_model("body"), SimilarityModel::TfIdf);
+        assert_eq!(similarity_model("summary"), SimilarityModel::Bm25 {
+            k1: 1.2,
+            b: 0.75,
+        });
+
+        // Reload the metadata from its JSON representation
+        let mut reloaded_metadata = IndexMetadata::default();
+        parse(&mut reloaded_metadata, metadata.to_json().unwrap()).expect("parse() returned an error");
+
+        assert_eq!(reloaded_metadata.get_field_mapping("title").unwrap().get_search_options().similarity_model, similarity_model("title"));
+    }
+
+    #[test]
+    fn test_mapping_unrecognised_similarity() {
+        let mut metadata = IndexMetadata::default();
+        let error = parse(&mut metadata, json!({
+            "mappings": {
+                "test_mapping": {
+                    "properties": {
+                        "test_field": {
+                            "type": "string",
+                            "similarity": "foo",
+                        }
+                    }
+                }
+            }
+        })).err().expect("parse() was supposed to return an error, but didn't");
+
+        assert_eq!(error.to_string(), "unknown similarity [foo] for field [test_field] in mapping [test_mapping]");
+    }
+
+    #[test]
+    fn test_error_message() {
+        let mut metadata = IndexMetadata::default();
+        let error = parse(&mut metadata, json!({
+            "settings": {
+                "analysis": {
+                    "analyzer": {
+                        "bar": {
+                            "type": "custom",
+                            "tokenizer": "standard",
+                            "filter": ["foo"],
+                        }
+                    }
+                }
+            }
+        })).err().expect("parse() was supposed to return an error, but didn't");
+
+        assert_eq!(error.to_string(), "unknown filter [foo] in analyzer [bar]");
+    }
+
+    #[test]
+    fn test_mapping_error() {
+        let mut metadata = IndexMetadata::default();
+        let error = parse(&mut metadata, json!({
+            "mappings": {
+                "test_mapping": {
+                    "foo": []
+                }
+            }
+        })).err().expect("parse() was supposed to return an error, but didn't");
+
+        assert_eq!(error, IndexMetadataParseError::MappingParseError("test_mapping".to_string(), MappingParseError::UnrecognisedKeys(vec!["foo".to_string()])));
+    }
+
+    #[test]
+    fn test_settings() {
+        let mut metadata = IndexMetadata::default();
+        parse(&mut metadata, json!({
+            "settings": {
+                "index": {
+                    "refresh_interval": "10s",
+                    "max_result_window": 500,
+                },
+                "analysis": {
+                    "analyzer": {
+                        "my_analyzer": {
+                            "type": "custom",
+                            "tokenizer": "standard",
+                        }
+                    }
+                }
+            },
+            "state": "close",
+        })).expect("parse() returned an error");
+
+        assert_eq!(metadata.settings, IndexSettings {
+            refresh_interval: Some(10000),
+            max_result_window: 500,
+            ..IndexSettings::default()
+        });
+        assert_eq!(metadata.state, IndexState::Closed);
+        assert!(metadata.analyzers().contains_key("my_analyzer"));
+
+        // Reload the metadata from its JSON representation
+        let mut reloaded_metadata = IndexMetadata::default();
+        parse(&mut reloaded_metadata, metadata.to_json().unwrap()).expect("parse() returned an error");
+
+        assert_eq!(reloaded_metadata.settings, metadata.settings);
+        assert_eq!(reloaded_metadata.state, metadata.state);
+    }
+
+    #[test]
+    fn test_settings_error() {
+        let mut metadata = IndexMetadata::default();
+        let error = parse(&mut metadata, json!({
+            "settings": {
+                "index.foo": "bar",
+            }
+        })).err().expect("parse() was supposed to return an error, but didn't");
+
+        assert_eq!(error.to_string(), "unknown setting [index.foo]");
+    }
+
+    #[test]
+    fn test_aliases() {
+        let mut metadata = IndexMetadata::default();
+        parse(&mut metadata, json!({
+            "aliases": {
+                "foo": {},
+                "bar": {
+                    "filter": {
+                        "term": {
+                            "user": "kimchy",
+                        }
+                    },
+                    "is_write_index": true,
+                },
+            }
+        })).expect("parse() returned an error");
+
+        assert_eq!(metadata.aliases, btreemap! {
+            "foo".to_string() => AliasMetadata::default(),
+            "bar".to_string() => AliasMetadata {
+                filter: Some(json!({"term": {"user": "kimchy"}})),
+                is_write_index: Some(true),
+            }
+        });
+        assert_eq!(metadata.to_json().unwrap().as_object().unwrap().get("aliases"), Some(&json!({
+            "foo": {},
+            "bar": {
+                "filter": {
+                    "term": {
+                        "user": "kimchy",
+                    }
+                },
+                "is_write_index": true,
+            },
+        })));
+    }
+
+    #[test]
+    fn test_settings_update() {
+        let mut metadata = IndexMetadata::default();
+        parse(&mut metadata, json!({
+            "settings": {
+                "analysis": {
+                    "analyzer": {
+                        "my_analyzer": {
+                            "type": "custom",
+                            "tokenizer": "standard",
+                        }
+                    }
+                }
+            },
+            "mappings": {
+                "doc": {
+                    "properties": {
+                        "title": {
+                            "type": "string",
+                            "analyzer": "my_analyzer",
+                        }
+                    }
+                }
+            },
+            "state": "close",
+        })).expect("parse() returned an error");
+
+        let metadata = parse_settings_update(metadata.to_json().unwrap(), json!({
+            "index.max_result_window": 100,
+            "analysis": {
+                "analyzer": {
+                    "my_analyzer": {
+                        "type": "custom",
+                        "tokenizer": "keyword",
+                    }
+                }
+            }
+        })).expect("parse_settings_update() returned an error");
+
+        assert_eq!(metadata.settings.max_result_window, 100);
+        assert_eq!(metadata.state, IndexState::Closed);
+
+        // The mapping must pick up the redefined analyzer
+        let title_mapping = metadata.mappings.get("doc").and_then(|mapping| mapping.get_field("title")).unwrap();
+        assert_eq!(title_mapping.index_analyzer(), Some(&AnalyzerSpec {
+            char_filters: vec![],
+            tokenizer: TokenizerSpec::Keyword,
+            filters: vec![],
+        }));
     }
 }
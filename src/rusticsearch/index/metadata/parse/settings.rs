@@ -0,0 +1,430 @@
+use std::fmt;
+use std::collections::BTreeMap;
+
+use serde_json;
+
+use index::metadata::settings::{IndexSettings, Similarity};
+
+
+#[derive(Debug, PartialEq)]
+pub enum IndexSettingsParseError {
+    UnrecognisedSetting(String),
+    InvalidValue(String),
+}
+
+
+impl fmt::Display for IndexSettingsParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            IndexSettingsParseError::UnrecognisedSetting(ref name) => write!(f, "unknown setting [index.{}]", name),
+            IndexSettingsParseError::InvalidValue(ref name) => write!(f, "invalid value for setting [index.{}]", name),
+        }
+    }
+}
+
+
+fn join_setting_name(prefix: &str, key: &str) -> String {
+    let name = if prefix.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", prefix, key)
+    };
+
+    // The "index." prefix is optional
+    if name == "index" {
+        String::new()
+    } else if name.starts_with("index.") {
+        name["index.".len()..].to_string()
+    } else {
+        name
+    }
+}
+
+
+fn flatten_into(prefix: &str, json: &serde_json::Value, settings: &mut BTreeMap<String, serde_json::Value>) {
+    if let serde_json::Value::Object(ref object) = *json {
+        // The analysis block is parsed separately so it's kept as an object
+        if prefix != "analysis" {
+            for (key, value) in object {
+                flatten_into(&join_setting_name(prefix, key), value, settings);
+            }
+
+            return;
+        }
+    }
+
+    settings.insert(prefix.to_string(), json.clone());
+}
+
+
+/// Flattens settings into a map of dotted setting names to values
+///
+/// Settings can be nested objects ({"index": {"refresh_interval": "1s"}}), have dotted
+/// names ({"index.refresh_interval": "1s"}) or a mix of both. The "index." prefix is
+/// removed from the names.
+pub fn flatten_settings(json: &serde_json::Value) -> BTreeMap<String, serde_json::Value> {
+    let mut settings = BTreeMap::new();
+    flatten_into("", json, &mut settings);
+    settings
+}
+
+
+/// Reads a number that may have been given as a string
+fn parse_number(json: &serde_json::Value) -> Option<f64> {
+    match *json {
+        serde_json::Value::Number(ref num) => num.as_f64(),
+        serde_json::Value::String(ref string) => string.parse().ok(),
+        _ => None,
+    }
+}
+
+
+/// Reads a time value (eg, "30s") as milliseconds
+///
+/// "-1" disables whatever the setting is for, this returns Some(None) in that case.
+pub fn parse_time_value(json: &serde_json::Value) -> Option<Option<u64>> {
+    let string = match *json {
+        serde_json::Value::Number(ref num) => num.to_string(),
+        serde_json::Value::String(ref string) => string.trim().to_string(),
+        _ => return None,
+    };
+
+    if string == "-1" {
+        return Some(None);
+    }
+
+    let unit_start = string.find(|c: char| !c.is_digit(10)).unwrap_or(string.len());
+    let number = match string[..unit_start].parse::<u64>() {
+        Ok(number) => number,
+        Err(_) => return None,
+    };
+
+    let multiplier = match &string[unit_start..] {
+        "" | "ms" => 1,
+        "s" => 1000,
+        "m" => 60 * 1000,
+        "h" => 60 * 60 * 1000,
+        "d" => 24 * 60 * 60 * 1000,
+        _ => return None,
+    };
+
+    Some(Some(number * multiplier))
+}
+
+
+/// Applies flattened settings on top of an index's current settings
+pub fn parse(current_settings: &IndexSettings, settings: &BTreeMap<String, serde_json::Value>) -> Result<IndexSettings, IndexSettingsParseError> {
+    let mut new_settings = current_settings.clone();
+
+    for (name, value) in settings.iter() {
+        let invalid_value = || IndexSettingsParseError::InvalidValue(name.clone());
+
+        match name.as_ref() {
+            // Parsed along with the rest of the index metadata
+            "analysis" => {}
+
+            // Indices only ever have one shard so these are ignored
+            "number_of_shards" | "number_of_replicas" => {}
+
+            "refresh_interval" => {
+                new_settings.refresh_interval = try!(parse_time_value(value).ok_or_else(&invalid_value));
+            }
+            "max_result_window" => {
+                let max_result_window = try!(parse_number(value).ok_or_else(&invalid_value));
+
+                if max_result_window < 1.0 || max_result_window.fract() != 0.0 {
+                    return Err(invalid_value());
+                }
+
+                new_settings.max_result_window = max_result_window as usize;
+            }
+            "merge.policy.min_segments_to_merge" => {
+                let min_segments_to_merge = try!(parse_number(value).ok_or_else(&invalid_value));
+
+                // Merging a single segment into itself wouldn't achieve anything
+                if min_segments_to_merge < 2.0 || min_segments_to_merge.fract() != 0.0 {
+                    return Err(invalid_value());
+                }
+
+                new_settings.merge_policy.min_segments_to_merge = min_segments_to_merge as usize;
+            }
+            "merge.policy.max_segment_docs" => {
+                let max_segment_docs = try!(parse_number(value).ok_or_else(&invalid_value));
+
+                if max_segment_docs < 1.0 || max_segment_docs > u32::max_value() as f64 || max_segment_docs.fract() != 0.0 {
+                    return Err(invalid_value());
+                }
+
+                new_settings.merge_policy.max_segment_docs = max_segment_docs as u32;
+            }
+            // Parsed below, once all of each similarity's settings have been found
+            _ if name.starts_with("similarity.") => {}
+            "creation_date" => {
+                let creation_date = try!(parse_number(value).ok_or_else(&invalid_value));
+
+                if creation_date < 0.0 || creation_date.fract() != 0.0 {
+                    return Err(invalid_value());
+                }
+
+                new_settings.creation_date = Some(creation_date as u64);
+            }
+            _ => return Err(IndexSettingsParseError::UnrecognisedSetting(name.clone())),
+        }
+    }
+
+    // Group the similarity settings by the name of the similarity
+    let mut similarities: BTreeMap<&str, BTreeMap<&str, &serde_json::Value>> = BTreeMap::new();
+    for (name, value) in settings.iter() {
+        if !name.starts_with("similarity.") {
+            continue;
+        }
+
+        let mut parts = name["similarity.".len()..].splitn(2, '.');
+        match (parts.next(), parts.next()) {
+            (Some(similarity_name), Some(key)) if !similarity_name.is_empty() => {
+                similarities.entry(similarity_name).or_insert_with(BTreeMap::new).insert(key, value);
+            }
+            _ => return Err(IndexSettingsParseError::UnrecognisedSetting(name.clone())),
+        }
+    }
+
+    for (similarity_name, similarity_settings) in similarities {
+        let current_similarity = if similarity_name == "default" {
+            Some(new_settings.default_similarity.clone())
+        } else {
+            new_settings.similarities.get(similarity_name).cloned()
+        };
+
+        let similarity = try!(parse_similarity(similarity_name, current_similarity, &similarity_settings));
+
+        if similarity_name == "default" {
+            new_settings.default_similarity = similarity;
+        } else {
+            new_settings.similarities.insert(similarity_name.to_string(), similarity);
+        }
+    }
+
+    Ok(new_settings)
+}
+
+
+/// Builds a similarity from its settings, keeping any parameters that aren't given from
+/// the current similarity with that name
+fn parse_similarity(name: &str, current_similarity: Option<Similarity>, settings: &BTreeMap<&str, &serde_json::Value>) -> Result<Similarity, IndexSettingsParseError> {
+    let setting_name = |key: &str| format!("similarity.{}.{}", name, key);
+    let invalid_value = |key: &str| IndexSettingsParseError::InvalidValue(setting_name(key));
+
+    let mut similarity = match settings.get("type") {
+        Some(similarity_type) => {
+            let new_similarity = match similarity_type.as_str() {
+                Some("BM25") => Similarity::default(),
+                Some("classic") => Similarity::Classic,
+                _ => return Err(invalid_value("type")),
+            };
+
+            // Keep the parameters of the current similarity if the type hasn't changed
+            match (new_similarity, current_similarity) {
+                (Similarity::Bm25{..}, Some(Similarity::Bm25{k1, b})) => Similarity::Bm25{k1: k1, b: b},
+                (new_similarity, _) => new_similarity,
+            }
+        }
+        None => {
+            match current_similarity {
+                Some(current_similarity) => current_similarity,
+                None => return Err(invalid_value("type")),
+            }
+        }
+    };
+
+    for (key, value) in settings.iter() {
+        match (*key, &mut similarity) {
+            ("type", _) => {}
+            ("k1", &mut Similarity::Bm25{ref mut k1, ..}) => {
+                *k1 = try!(parse_number(value).ok_or_else(|| invalid_value(*key)));
+            }
+            ("b", &mut Similarity::Bm25{ref mut b, ..}) => {
+                *b = try!(parse_number(value).ok_or_else(|| invalid_value(*key)));
+            }
+            _ => return Err(IndexSettingsParseError::UnrecognisedSetting(setting_name(*key))),
+        }
+    }
+
+    Ok(similarity)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+
+    use std::collections::BTreeMap;
+
+    use index::maintenance::MergePolicy;
+    use index::metadata::settings::{IndexSettings, Similarity};
+
+    use super::{flatten_settings, parse, IndexSettingsParseError};
+
+    #[test]
+    fn test_flatten_settings() {
+        let settings = flatten_settings(&serde_json::from_str("
+        {
+            \"index\": {
+                \"refresh_interval\": \"5s\",
+                \"similarity\": {
+                    \"default\": {
+                        \"type\": \"BM25\"
+                    }
+                }
+            },
+            \"index.max_result_window\": 500,
+            \"analysis\": {
+                \"analyzer\": {}
+            }
+        }
+        ").unwrap());
+
+        assert_eq!(settings, btreemap! {
+            "refresh_interval".to_string() => json!("5s"),
+            "similarity.default.type".to_string() => json!("BM25"),
+            "max_result_window".to_string() => json!(500),
+            "analysis".to_string() => json!({"analyzer": {}})
+        });
+    }
+
+    #[test]
+    fn test_parse() {
+        let settings = parse(&IndexSettings::default(), &flatten_settings(&serde_json::from_str("
+        {
+            \"index\": {
+                \"refresh_interval\": \"30s\",
+                \"max_result_window\": \"50000\",
+                \"similarity.default.k1\": 1.5
+            }
+        }
+        ").unwrap()));
+
+        assert_eq!(settings, Ok(IndexSettings {
+            refresh_interval: Some(30000),
+            max_result_window: 50000,
+            default_similarity: Similarity::Bm25 {
+                k1: 1.5,
+                b: 0.75,
+            },
+            similarities: BTreeMap::new(),
+            merge_policy: MergePolicy::default(),
+            creation_date: None,
+        }));
+    }
+
+    #[test]
+    fn test_parse_merge_policy() {
+        let settings = parse(&IndexSettings::default(), &flatten_settings(&serde_json::from_str("
+        {
+            \"index.merge.policy\": {
+                \"min_segments_to_merge\": 5,
+                \"max_segment_docs\": \"1000000\"
+            }
+        }
+        ").unwrap()));
+
+        assert_eq!(settings, Ok(IndexSettings {
+            merge_policy: MergePolicy {
+                min_segments_to_merge: 5,
+                max_segment_docs: 1000000,
+            },
+            ..IndexSettings::default()
+        }));
+
+        let settings = parse(&IndexSettings::default(), &flatten_settings(&serde_json::from_str("
+        {
+            \"merge.policy.min_segments_to_merge\": 1
+        }
+        ").unwrap()));
+
+        assert_eq!(settings, Err(IndexSettingsParseError::InvalidValue("merge.policy.min_segments_to_merge".to_string())));
+    }
+
+    #[test]
+    fn test_parse_disable_refresh() {
+        let settings = parse(&IndexSettings::default(), &flatten_settings(&serde_json::from_str("
+        {
+            \"refresh_interval\": -1
+        }
+        ").unwrap()));
+
+        assert_eq!(settings, Ok(IndexSettings {
+            refresh_interval: None,
+            ..IndexSettings::default()
+        }));
+    }
+
+    #[test]
+    fn test_parse_invalid_value() {
+        let settings = parse(&IndexSettings::default(), &flatten_settings(&serde_json::from_str("
+        {
+            \"refresh_interval\": \"soon\"
+        }
+        ").unwrap()));
+
+        assert_eq!(settings, Err(IndexSettingsParseError::InvalidValue("refresh_interval".to_string())));
+    }
+
+    #[test]
+    fn test_parse_unrecognised_setting() {
+        let settings = parse(&IndexSettings::default(), &flatten_settings(&serde_json::from_str("
+        {
+            \"index.foo\": \"bar\"
+        }
+        ").unwrap()));
+
+        assert_eq!(settings, Err(IndexSettingsParseError::UnrecognisedSetting("foo".to_string())));
+    }
+
+    #[test]
+    fn test_parse_similarities() {
+        let settings = parse(&IndexSettings::default(), &flatten_settings(&serde_json::from_str("
+        {
+            \"similarity\": {
+                \"default\": {
+                    \"type\": \"classic\"
+                },
+                \"short_text\": {
+                    \"type\": \"BM25\",
+                    \"b\": 0.3
+                }
+            }
+        }
+        ").unwrap()));
+
+        assert_eq!(settings, Ok(IndexSettings {
+            default_similarity: Similarity::Classic,
+            similarities: btreemap! {
+                "short_text".to_string() => Similarity::Bm25 {
+                    k1: 1.2,
+                    b: 0.3,
+                }
+            },
+            ..IndexSettings::default()
+        }));
+    }
+
+    #[test]
+    fn test_parse_similarity_errors() {
+        let settings = parse(&IndexSettings::default(), &flatten_settings(&serde_json::from_str("
+        {
+            \"similarity.tfidf.type\": \"classic\",
+            \"similarity.tfidf.k1\": 1.5
+        }
+        ").unwrap()));
+
+        assert_eq!(settings, Err(IndexSettingsParseError::UnrecognisedSetting("similarity.tfidf.k1".to_string())));
+
+        let settings = parse(&IndexSettings::default(), &flatten_settings(&serde_json::from_str("
+        {
+            \"similarity.custom.b\": 0.5
+        }
+        ").unwrap()));
+
+        assert_eq!(settings, Err(IndexSettingsParseError::InvalidValue("similarity.custom.type".to_string())));
+    }
+}
@@ -0,0 +1,183 @@
+//! Index settings
+//!
+//! Dynamic settings can be changed at any time. All other settings affect how documents
+//! are indexed so they can only be changed while the index is closed.
+
+use std::collections::BTreeMap;
+
+use serde_json;
+use serde_json::value::ToJson;
+use kite::similarity::SimilarityModel;
+
+use index::maintenance::MergePolicy;
+
+
+/// Settings that can be updated while the index is open
+pub const DYNAMIC_SETTINGS: &'static [&'static str] = &[
+    "refresh_interval",
+    "max_result_window",
+    "merge.policy.min_segments_to_merge",
+    "merge.policy.max_segment_docs",
+];
+
+
+pub fn is_dynamic_setting(name: &str) -> bool {
+    DYNAMIC_SETTINGS.contains(&name)
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Similarity {
+    Bm25 {
+        k1: f64,
+        b: f64,
+    },
+
+    /// TF/IDF
+    Classic,
+}
+
+
+impl Default for Similarity {
+    fn default() -> Similarity {
+        Similarity::Bm25 {
+            k1: 1.2,
+            b: 0.75,
+        }
+    }
+}
+
+
+impl Similarity {
+    /// Finds one of the similarities that are available in every index
+    pub fn builtin(name: &str) -> Option<Similarity> {
+        match name {
+            "BM25" => Some(Similarity::default()),
+            "classic" => Some(Similarity::Classic),
+            _ => None,
+        }
+    }
+
+    pub fn to_similarity_model(&self) -> SimilarityModel {
+        match *self {
+            Similarity::Bm25{k1, b} => {
+                SimilarityModel::Bm25 {
+                    k1: k1,
+                    b: b,
+                }
+            }
+            Similarity::Classic => SimilarityModel::TfIdf,
+        }
+    }
+}
+
+
+impl ToJson for Similarity {
+    fn to_json(&self) -> Result<serde_json::Value, serde_json::Error> {
+        match *self {
+            Similarity::Bm25{k1, b} => {
+                Ok(json!({
+                    "type": "BM25",
+                    "k1": k1,
+                    "b": b,
+                }))
+            }
+            Similarity::Classic => {
+                Ok(json!({
+                    "type": "classic",
+                }))
+            }
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexSettings {
+    /// How often (in milliseconds) new documents should be made visible to searches
+    ///
+    /// None if automatic refreshing is disabled.
+    pub refresh_interval: Option<u64>,
+
+    /// The highest value of "from + size" allowed in a search
+    pub max_result_window: usize,
+
+    /// The similarity used by fields that don't set their own
+    pub default_similarity: Similarity,
+
+    /// Similarities that fields can refer to by name
+    pub similarities: BTreeMap<String, Similarity>,
+
+    /// Decides which segments the maintenance task merges together
+    pub merge_policy: MergePolicy,
+
+    /// When the index was created (in milliseconds since the epoch)
+    ///
+    /// None for indices that were created before this was recorded.
+    pub creation_date: Option<u64>,
+}
+
+
+impl Default for IndexSettings {
+    fn default() -> IndexSettings {
+        IndexSettings {
+            refresh_interval: Some(1000),
+            max_result_window: 10000,
+            default_similarity: Similarity::default(),
+            similarities: BTreeMap::new(),
+            merge_policy: MergePolicy::default(),
+            creation_date: None,
+        }
+    }
+}
+
+
+impl IndexSettings {
+    /// Finds a similarity by the name it's referred to in a field mapping
+    pub fn get_similarity(&self, name: &str) -> Option<Similarity> {
+        if name == "default" {
+            return Some(self.default_similarity.clone());
+        }
+
+        match self.similarities.get(name) {
+            Some(similarity) => Some(similarity.clone()),
+            None => Similarity::builtin(name),
+        }
+    }
+}
+
+
+impl ToJson for IndexSettings {
+    fn to_json(&self) -> Result<serde_json::Value, serde_json::Error> {
+        let refresh_interval = match self.refresh_interval {
+            Some(refresh_interval) => format!("{}ms", refresh_interval),
+            None => "-1".to_string(),
+        };
+
+        let mut similarities_json = BTreeMap::new();
+        similarities_json.insert("default".to_string(), try!(self.default_similarity.to_json()));
+        for (name, similarity) in self.similarities.iter() {
+            similarities_json.insert(name.clone(), try!(similarity.to_json()));
+        }
+
+        let mut json = json!({
+            "refresh_interval": refresh_interval,
+            "max_result_window": self.max_result_window,
+            "similarity": similarities_json,
+            "merge": {
+                "policy": {
+                    "min_segments_to_merge": self.merge_policy.min_segments_to_merge,
+                    "max_segment_docs": self.merge_policy.max_segment_docs,
+                }
+            },
+        });
+
+        if let Some(creation_date) = self.creation_date {
+            if let Some(object) = json.as_object_mut() {
+                object.insert("creation_date".to_string(), json!(creation_date.to_string()));
+            }
+        }
+
+        Ok(json)
+    }
+}
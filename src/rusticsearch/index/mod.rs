@@ -1,13 +1,23 @@
 pub mod maintenance;
 pub mod metadata;
+pub mod stats;
 
-use std::sync::RwLock;
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::mem;
+use std::sync::{Arc, Weak, RwLock, Mutex};
+use std::path::{Path, PathBuf};
 
+use serde_json;
+use kite::Query;
+use kite::document::{DocRef, FieldValue};
 use kite_rocksdb::RocksDBIndexStore;
 use uuid::Uuid;
 
-use index::metadata::IndexMetadata;
+use index::metadata::{IndexMetadata, IndexState};
+use mapping::{Mapping, FieldType};
+use collectors::AllDocumentsCollector;
+use completion::{CompletionIndex, DocumentInputs, document_inputs};
+use suggest::TermDictionaryCache;
 
 
 #[derive(Debug)]
@@ -15,17 +25,43 @@ pub struct Index {
     id: Uuid,
     canonical_name: String,
     pub metadata: RwLock<IndexMetadata>,
-    pub store: RocksDBIndexStore,
+    path: PathBuf,
+
+    /// The store is only open while the index is open
+    store: RwLock<Option<Arc<RocksDBIndexStore>>>,
+
+    /// The store that was released when the index was closed, this can be upgraded until
+    /// every handle to it has been dropped
+    released_store: Mutex<Weak<RocksDBIndexStore>>,
+
+    /// The completion index of each "completion" field, by field name
+    pub completions: RwLock<HashMap<String, CompletionIndex>>,
+
+    /// The term dictionaries used by the term and phrase suggesters
+    pub term_dictionaries: RwLock<TermDictionaryCache>,
 }
 
 
 impl Index {
     pub fn new(id: Uuid, canonical_name: String, metadata: IndexMetadata, store: RocksDBIndexStore) -> Index {
+        let path = store.path().to_path_buf();
+
+        // Indices that were closed when they were saved don't keep their store open
+        let store = if metadata.state == IndexState::Closed {
+            None
+        } else {
+            Some(Arc::new(store))
+        };
+
         Index {
             id: id,
             canonical_name: canonical_name,
             metadata: RwLock::new(metadata),
-            store: store,
+            path: path,
+            store: RwLock::new(store),
+            released_store: Mutex::new(Weak::new()),
+            completions: RwLock::new(HashMap::new()),
+            term_dictionaries: RwLock::new(TermDictionaryCache::default()),
         }
     }
 
@@ -37,9 +73,165 @@ impl Index {
         &self.canonical_name
     }
 
+    /// The directory that the store and metadata are saved in
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
     pub fn metadata_path(&self) -> PathBuf {
-        let mut path = self.store.path().to_path_buf();
+        let mut path = self.path.clone();
         path.push("metadata.json");
         path
     }
+
+    /// Returns the store, or an error if the index is closed
+    ///
+    /// The store is closed once every handle returned by this has been dropped.
+    pub fn store(&self) -> Result<Arc<RocksDBIndexStore>, String> {
+        match *self.store.read().unwrap() {
+            Some(ref store) => Ok(store.clone()),
+            None => Err(format!("index [{}] is closed", self.canonical_name)),
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.metadata.read().unwrap().state == IndexState::Closed
+    }
+
+    /// Checks that the index is closed and nothing is still using its store
+    ///
+    /// Tasks like reindexing keep a handle to the store, so it can stay open for a while
+    /// after the index is closed.
+    pub fn is_store_released(&self) -> bool {
+        self.store.read().unwrap().is_none() && self.released_store.lock().unwrap().upgrade().is_none()
+    }
+
+    /// Opens or closes the index
+    ///
+    /// Closed indices reject reads and writes and are skipped by the maintenance task.
+    /// Closing releases the store, and opening loads it again from disk. The state is
+    /// left unchanged if either of these or saving the metadata fails.
+    /// Returns false if the index was already in the given state.
+    pub fn set_state(&self, state: IndexState) -> Result<bool, String> {
+        let mut metadata = self.metadata.write().unwrap();
+        if metadata.state == state {
+            return Ok(false);
+        }
+
+        let store = match state {
+            IndexState::Open => Some(Arc::new(try!(RocksDBIndexStore::open(&self.path)))),
+            IndexState::Closed => None,
+        };
+
+        // Roll back if the new state can't be saved, so the index isn't left in a
+        // different state to the one it would be loaded in on restart
+        let old_state = metadata.state;
+        metadata.state = state;
+        if let Err(e) = metadata.save(self.metadata_path()) {
+            metadata.state = old_state;
+            return Err(e.into());
+        }
+
+        let old_store = mem::replace(&mut *self.store.write().unwrap(), store);
+        if let Some(old_store) = old_store {
+            *self.released_store.lock().unwrap() = Arc::downgrade(&old_store);
+        }
+        drop(metadata);
+
+        self.clear_term_dictionaries();
+
+        match state {
+            IndexState::Open => self.rebuild_completions(),
+            IndexState::Closed => self.completions.write().unwrap().clear(),
+        }
+
+        Ok(true)
+    }
+
+    fn insert_completions(completions: &mut HashMap<String, CompletionIndex>, doc_key: &str, inputs: DocumentInputs) {
+        for (field_name, field_inputs) in inputs {
+            let completion_index = completions.entry(field_name).or_insert_with(CompletionIndex::new);
+            for (key, input) in field_inputs {
+                completion_index.insert(doc_key, &key, input);
+            }
+        }
+    }
+
+    /// Replaces a document's inputs in the completion indices
+    ///
+    /// This must be called whenever a document is indexed, after it's been inserted into the store.
+    pub fn update_completions(&self, mapping: &Mapping, doc_key: &str, data: &serde_json::Map<String, serde_json::Value>) {
+        let mut completions = self.completions.write().unwrap();
+        for completion_index in completions.values_mut() {
+            completion_index.remove_document(doc_key);
+        }
+
+        Index::insert_completions(&mut completions, doc_key, document_inputs(mapping, data));
+    }
+
+    /// Removes a deleted document from the completion indices
+    pub fn remove_completions(&self, doc_key: &str) {
+        let mut completions = self.completions.write().unwrap();
+        for completion_index in completions.values_mut() {
+            completion_index.remove_document(doc_key);
+        }
+    }
+
+    /// Rebuilds the completion indices from the source of each document in the store
+    ///
+    /// Completion indices are only kept in memory so this is done when the index is loaded.
+    pub fn rebuild_completions(&self) {
+        let metadata = self.metadata.read().unwrap();
+
+        // Most indices don't have any completion fields, so don't read every document for nothing
+        let has_completion_fields = metadata.mappings.values().any(|mapping| {
+            mapping.fields().iter().any(|&(_, field_mapping)| field_mapping.data_type == FieldType::Completion)
+        });
+        if !has_completion_fields {
+            return;
+        }
+
+        let store = match self.store() {
+            Ok(store) => store,
+            Err(_) => return,
+        };
+        let index_reader = store.reader();
+        let (key_field, source_field) = match (index_reader.schema().get_field_by_name("_id"), index_reader.schema().get_field_by_name("_source")) {
+            (Some(key_field), Some(source_field)) => (key_field, source_field),
+            _ => return,
+        };
+
+        let mut collector = AllDocumentsCollector::new().no_score();
+        index_reader.search(&mut collector, &Query::new_all()).unwrap();
+
+        let mut completions = HashMap::new();
+        for (doc_id, _) in collector.into_vec() {
+            let doc_key = match index_reader.read_stored_field(key_field, DocRef::from_u64(doc_id)) {
+                Ok(Some(FieldValue::String(doc_key))) => doc_key,
+                _ => continue,
+            };
+
+            let source = match index_reader.read_stored_field(source_field, DocRef::from_u64(doc_id)) {
+                Ok(Some(FieldValue::String(source))) => source,
+                _ => continue,
+            };
+
+            let data = match serde_json::from_str::<serde_json::Value>(&source) {
+                Ok(serde_json::Value::Object(data)) => data,
+                _ => continue,
+            };
+
+            // The mapping that each document was indexed with isn't recorded, so this uses
+            // the first one that gives it any inputs
+            let inputs = metadata.mappings.values()
+                .map(|mapping| document_inputs(mapping, &data))
+                .find(|inputs| !inputs.is_empty());
+
+            if let Some(inputs) = inputs {
+                Index::insert_completions(&mut completions, &doc_key, inputs);
+            }
+        }
+
+        *self.completions.write().unwrap() = completions;
+    }
 }
@@ -0,0 +1,96 @@
+use std::io;
+use std::fs;
+use std::path::Path;
+
+use index::Index;
+
+
+/// Statistics about the documents and files held by an index's store
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct StoreStats {
+    pub num_docs: u64,
+    pub deleted_docs: u64,
+    pub segment_count: u64,
+    pub size_in_bytes: u64,
+}
+
+
+impl StoreStats {
+    /// Adds another store's statistics to this one (used for totals across indices)
+    pub fn add(&mut self, other: &StoreStats) {
+        self.num_docs += other.num_docs;
+        self.deleted_docs += other.deleted_docs;
+        self.segment_count += other.segment_count;
+        self.size_in_bytes += other.size_in_bytes;
+    }
+}
+
+
+fn dir_size(path: &Path) -> io::Result<u64> {
+    let mut size = 0;
+
+    for entry in try!(fs::read_dir(path)) {
+        let entry = try!(entry);
+        let metadata = try!(entry.metadata());
+
+        if metadata.is_dir() {
+            size += try!(dir_size(&entry.path()));
+        } else {
+            size += metadata.len();
+        }
+    }
+
+    Ok(size)
+}
+
+
+impl Index {
+    pub fn store_stats(&self) -> Result<StoreStats, String> {
+        let store = try!(self.store());
+        let segment_stats = try!(store.get_segment_statistics());
+        let mut stats = StoreStats::default();
+
+        for (_, segment) in segment_stats {
+            let total_docs = segment.total_docs() as u64;
+            let deleted_docs = segment.deleted_docs() as u64;
+
+            stats.num_docs += total_docs.saturating_sub(deleted_docs);
+            stats.deleted_docs += deleted_docs;
+            stats.segment_count += 1;
+        }
+
+        stats.size_in_bytes = try!(dir_size(store.path()).map_err(|e| format!("failed to read store size: {}", e)));
+
+        Ok(stats)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::StoreStats;
+
+    #[test]
+    fn test_add() {
+        let mut stats = StoreStats {
+            num_docs: 10,
+            deleted_docs: 2,
+            segment_count: 3,
+            size_in_bytes: 1000,
+        };
+
+        stats.add(&StoreStats {
+            num_docs: 5,
+            deleted_docs: 0,
+            segment_count: 1,
+            size_in_bytes: 500,
+        });
+
+        assert_eq!(stats, StoreStats {
+            num_docs: 15,
+            deleted_docs: 2,
+            segment_count: 4,
+            size_in_bytes: 1500,
+        });
+    }
+}
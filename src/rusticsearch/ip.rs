@@ -0,0 +1,133 @@
+//! Encodes IP addresses for "ip" fields
+//!
+//! Addresses are indexed as the hex encoding of their 16 byte IPv6 form (IPv4 addresses are
+//! mapped into IPv6 first). This sorts in the same order as the addresses, and every address
+//! in a CIDR block shares a prefix, so a CIDR block can be searched with prefix queries.
+
+use std::net::IpAddr;
+
+
+/// Converts an address into its 16 byte IPv6 form
+fn to_octets(address: &IpAddr) -> [u8; 16] {
+    match *address {
+        IpAddr::V4(ref address) => address.to_ipv6_mapped().octets(),
+        IpAddr::V6(ref address) => address.octets(),
+    }
+}
+
+
+fn to_hex(octets: &[u8]) -> String {
+    octets.iter().map(|octet| format!("{:02x}", octet)).collect()
+}
+
+
+pub fn parse_ip(string: &str) -> Option<IpAddr> {
+    string.trim().parse::<IpAddr>().ok()
+}
+
+
+/// Returns the term that an address is indexed as
+pub fn encode_ip(address: &IpAddr) -> String {
+    to_hex(&to_octets(address))
+}
+
+
+/// Finds the term prefixes that match every address in a CIDR block (eg, "10.0.0.0/8")
+///
+/// Each hex digit holds 4 bits of the address, so blocks with a prefix length that isn't
+/// a multiple of 4 need a prefix for each value of the partially fixed digit.
+pub fn cidr_prefixes(cidr: &str) -> Option<Vec<String>> {
+    let mut parts = cidr.splitn(2, '/');
+    let (address, prefix_length) = match (parts.next().and_then(parse_ip), parts.next()) {
+        (Some(address), Some(prefix_length)) => (address, prefix_length),
+        (Some(address), None) => return Some(vec![encode_ip(&address)]),
+        _ => return None,
+    };
+
+    let prefix_length = match (address, prefix_length.trim().parse::<usize>()) {
+        (IpAddr::V4(_), Ok(prefix_length)) if prefix_length <= 32 => prefix_length + 96,
+        (IpAddr::V6(_), Ok(prefix_length)) if prefix_length <= 128 => prefix_length,
+        _ => return None,
+    };
+
+    let encoded = encode_ip(&address);
+    let full_digits = prefix_length / 4;
+    let extra_bits = prefix_length % 4;
+    let fixed = &encoded[..full_digits];
+
+    if extra_bits == 0 {
+        return Some(vec![fixed.to_string()]);
+    }
+
+    // Enumerate the values of the next digit that keep its first `extra_bits` bits
+    let digit = u8::from_str_radix(&encoded[full_digits..full_digits + 1], 16).unwrap();
+    let free_bits = 0x0fu8 >> extra_bits;
+    let base = digit & !free_bits & 0x0f;
+
+    Some((0..free_bits + 1).map(|value| format!("{}{:x}", fixed, base | value)).collect())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_ip, encode_ip, cidr_prefixes};
+
+    #[test]
+    fn test_encode_ipv4() {
+        assert_eq!(encode_ip(&parse_ip("192.168.1.1").unwrap()), "00000000000000000000ffffc0a80101");
+    }
+
+    #[test]
+    fn test_encode_ipv6() {
+        assert_eq!(encode_ip(&parse_ip("2001:db8::1").unwrap()), "20010db8000000000000000000000001");
+    }
+
+    #[test]
+    fn test_encoding_is_ordered() {
+        let low = encode_ip(&parse_ip("10.0.0.9").unwrap());
+        let high = encode_ip(&parse_ip("10.0.0.10").unwrap());
+
+        assert!(low < high);
+    }
+
+    #[test]
+    fn test_parse_ip_invalid() {
+        assert_eq!(parse_ip("foo"), None);
+        assert_eq!(parse_ip("256.0.0.1"), None);
+    }
+
+    #[test]
+    fn test_cidr_prefixes() {
+        assert_eq!(cidr_prefixes("10.0.0.0/8"), Some(vec!["00000000000000000000ffff0a".to_string()]));
+        assert_eq!(cidr_prefixes("172.16.0.0/12"), Some(vec!["00000000000000000000ffffac1".to_string()]));
+        assert_eq!(cidr_prefixes("2001:db8::/32"), Some(vec!["20010db8".to_string()]));
+    }
+
+    #[test]
+    fn test_cidr_prefixes_partial_digit() {
+        // 172.16.0.0/14 covers 172.16.0.0 to 172.19.255.255
+        assert_eq!(cidr_prefixes("172.16.0.0/14"), Some(vec![
+            "00000000000000000000ffffac10".to_string(),
+            "00000000000000000000ffffac11".to_string(),
+            "00000000000000000000ffffac12".to_string(),
+            "00000000000000000000ffffac13".to_string(),
+        ]));
+
+        // 192.168.0.0/23 covers 192.168.0.0 to 192.168.1.255
+        assert_eq!(cidr_prefixes("192.168.0.0/23"), Some(vec![
+            "00000000000000000000ffffc0a800".to_string(),
+            "00000000000000000000ffffc0a801".to_string(),
+        ]));
+    }
+
+    #[test]
+    fn test_cidr_prefixes_single_address() {
+        assert_eq!(cidr_prefixes("10.1.2.3"), Some(vec!["00000000000000000000ffff0a010203".to_string()]));
+    }
+
+    #[test]
+    fn test_cidr_prefixes_invalid() {
+        assert_eq!(cidr_prefixes("10.0.0.0/33"), None);
+        assert_eq!(cidr_prefixes("foo/8"), None);
+    }
+}
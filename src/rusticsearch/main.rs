@@ -16,14 +16,35 @@ extern crate uuid;
 #[macro_use]
 extern crate serde_json;
 extern crate atomicwrites;
+extern crate regex;
+extern crate rust_stemmers;
+#[macro_use]
+extern crate lazy_static;
 
 pub mod analysis;
 pub mod query_parser;
 pub mod mapping;
 pub mod document;
+pub mod collectors;
+pub mod aggregations;
+pub mod highlight;
+pub mod sort;
+pub mod scroll;
+pub mod source_filter;
+pub mod hit_fields;
+pub mod explain;
+pub mod profile;
+pub mod completion;
+pub mod suggest;
 pub mod index;
 pub mod cluster;
 pub mod system;
+pub mod snapshot;
+pub mod template;
+pub mod reindex;
+pub mod rollover;
+pub mod geo;
+pub mod ip;
 mod api;
 mod logger;
 
@@ -54,13 +75,16 @@ fn main() {
     system.log.info("[sys] loading indices", b!());
     system.load_indices();
 
+    system.log.info("[sys] loading index templates", b!());
+    system.load_templates();
+
     {
         let system = system.clone();
         thread::spawn(move || {
             loop {
                 {
                     let cluster_metadata = system.metadata.read().unwrap();
-                    for index in cluster_metadata.indices.values() {
+                    for index in cluster_metadata.indices.values().filter(|index| !index.is_closed()) {
                         let result = panic::catch_unwind(|| {
                             index.run_maintenance_task().unwrap();
                         });
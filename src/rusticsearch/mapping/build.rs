@@ -1,7 +1,26 @@
 use std::collections::HashMap;
 
-use mapping::{Mapping, MappingProperty, FieldMapping, NestedMapping, FieldType, get_standard_analyzer};
+use serde_json;
+
+use mapping::{Mapping, MappingProperty, FieldMapping, NestedMapping, FieldType, DynamicMapping, get_standard_analyzer, join_parent_field_name};
+use analysis::AnalyzerSpec;
 use index::metadata::IndexMetadata;
+use completion::DEFAULT_MAX_INPUT_LENGTH;
+
+
+#[derive(Debug, PartialEq)]
+pub enum FieldMappingBuildError {
+    UnrecognisedAnalyzer(String),
+    UnrecognisedSimilarity(String),
+    UnrecognisedCopyToField(String),
+    InvalidNullValue(serde_json::Value),
+}
+
+
+#[derive(Debug, PartialEq)]
+pub enum MappingBuildError {
+    FieldMappingBuildError(String, FieldMappingBuildError),
+}
 
 
 #[derive(Debug, PartialEq)]
@@ -10,11 +29,21 @@ pub struct FieldMappingBuilder {
     pub is_indexed: bool,
     pub is_analyzed: bool,
     pub is_stored: bool,
+    pub has_doc_values: bool,
     pub is_in_all: bool,
     pub boost: f64,
     pub base_analyzer: Option<String>,
     pub index_analyzer: Option<String>,
-    pub search_analyzer: Option<String>
+    pub search_analyzer: Option<String>,
+    pub similarity: Option<String>,
+    pub join_relations: HashMap<String, Vec<String>>,
+    pub multi_fields: HashMap<String, FieldMappingBuilder>,
+    pub copy_to: Vec<String>,
+    pub null_value: Option<serde_json::Value>,
+    pub ignore_above: Option<usize>,
+    pub ignore_malformed: bool,
+    pub completion_contexts: Vec<String>,
+    pub max_input_length: usize,
 }
 
 
@@ -25,72 +54,111 @@ impl Default for FieldMappingBuilder {
             is_indexed: true,
             is_analyzed: true,
             is_stored: false,
+            has_doc_values: false,
             is_in_all: true,
             boost: 1.0f64,
             base_analyzer: None,
             index_analyzer: None,
             search_analyzer: None,
+            similarity: None,
+            join_relations: HashMap::new(),
+            multi_fields: HashMap::new(),
+            copy_to: Vec::new(),
+            null_value: None,
+            ignore_above: None,
+            ignore_malformed: false,
+            completion_contexts: Vec::new(),
+            max_input_length: DEFAULT_MAX_INPUT_LENGTH,
         }
     }
 }
 
 
 impl FieldMappingBuilder {
-    pub fn build(&self, index_metadata: &IndexMetadata) -> FieldMapping {
+    pub fn build(&self, index_metadata: &IndexMetadata) -> Result<FieldMapping, FieldMappingBuildError> {
         let base_analyzer = match self.base_analyzer {
             Some(ref base_analyzer) => {
                 match index_metadata.analyzers().get(base_analyzer) {
-                    Some(analyzer) => Some(analyzer),
-                    None => None,
+                    Some(analyzer) => Some((base_analyzer.clone(), analyzer.clone())),
+                    None => return Err(FieldMappingBuildError::UnrecognisedAnalyzer(base_analyzer.clone())),
                 }
             }
             None => None,
         };
 
-        let index_analyzer = if self.is_analyzed {
-            match self.index_analyzer {
-                Some(ref index_analyzer) => {
-                    match index_metadata.analyzers().get(index_analyzer) {
-                        Some(analyzer) => Some(analyzer.clone()),
-                        None => {
-                            // TODO: error
-                            Some(base_analyzer.cloned().unwrap_or_else(|| index_metadata.get_default_index_analyzer()))
-                        },
-                    }
-                }
-                None => Some(base_analyzer.cloned().unwrap_or_else(|| index_metadata.get_default_index_analyzer())),
+        // Finds the named analyzer, falling back to the base analyzer and then the default
+        // The name of the analyzer is kept so the mapping can be serialised
+        let resolve_analyzer = |name: &Option<String>, default: AnalyzerSpec| -> Result<(Option<String>, AnalyzerSpec), FieldMappingBuildError> {
+            if let Some(ref name) = *name {
+                return match index_metadata.analyzers().get(name) {
+                    Some(analyzer) => Ok((Some(name.clone()), analyzer.clone())),
+                    None => Err(FieldMappingBuildError::UnrecognisedAnalyzer(name.clone())),
+                };
+            }
+
+            match base_analyzer {
+                Some((ref name, ref analyzer)) => Ok((Some(name.clone()), analyzer.clone())),
+                None => Ok((None, default)),
             }
+        };
+
+        let (index_analyzer_name, index_analyzer) = if self.is_analyzed {
+            let (name, analyzer) = try!(resolve_analyzer(&self.index_analyzer, index_metadata.get_default_index_analyzer()));
+            (name, Some(analyzer))
         } else {
-            None
+            (None, None)
         };
 
-        let search_analyzer = if self.is_analyzed {
-            match self.search_analyzer {
-                Some(ref search_analyzer) => {
-                    match index_metadata.analyzers().get(search_analyzer) {
-                        Some(analyzer) => Some(analyzer.clone()),
-                        None => {
-                            // TODO: error
-                            Some(base_analyzer.cloned().unwrap_or_else(|| index_metadata.get_default_search_analyzer()))
-                        },
-                    }
-                }
-                None => Some(base_analyzer.cloned().unwrap_or_else(|| index_metadata.get_default_search_analyzer())),
-            }
+        let (search_analyzer_name, search_analyzer) = if self.is_analyzed {
+            let (name, analyzer) = try!(resolve_analyzer(&self.search_analyzer, index_metadata.get_default_search_analyzer()));
+            (name, Some(analyzer))
         } else {
-            None
+            (None, None)
+        };
+
+        // Fields that don't name a similarity use the index's default similarity
+        let similarity = match self.similarity {
+            Some(ref name) => try!(index_metadata.settings.get_similarity(name).ok_or_else(|| FieldMappingBuildError::UnrecognisedSimilarity(name.clone()))),
+            None => index_metadata.settings.default_similarity.clone(),
         };
 
-        FieldMapping {
+        let mut multi_fields = HashMap::new();
+        for (name, multi_field_builder) in self.multi_fields.iter() {
+            multi_fields.insert(name.clone(), try!(multi_field_builder.build(index_metadata)));
+        }
+
+        let field_mapping = FieldMapping {
             data_type: self.field_type,
             index_ref: None,
             is_indexed: self.is_indexed,
             is_stored: self.is_stored,
+            has_doc_values: self.has_doc_values,
             is_in_all: self.is_in_all,
             boost: self.boost,
             index_analyzer: index_analyzer,
             search_analyzer: search_analyzer,
+            index_analyzer_name: index_analyzer_name,
+            search_analyzer_name: search_analyzer_name,
+            similarity: similarity,
+            similarity_name: self.similarity.clone(),
+            join_relations: self.join_relations.clone(),
+            multi_fields: multi_fields,
+            copy_to: self.copy_to.clone(),
+            null_value: self.null_value.clone(),
+            ignore_above: self.ignore_above,
+            ignore_malformed: self.ignore_malformed,
+            completion_contexts: self.completion_contexts.clone(),
+            max_input_length: self.max_input_length,
+        };
+
+        // Nulls are indexed as the null value so it must be a valid value for the field
+        if let Some(ref null_value) = field_mapping.null_value {
+            if field_mapping.process_value_for_index(null_value).is_err() {
+                return Err(FieldMappingBuildError::InvalidNullValue(null_value.clone()));
+            }
         }
+
+        Ok(field_mapping)
     }
 }
 
@@ -98,6 +166,7 @@ impl FieldMappingBuilder {
 #[derive(Debug, PartialEq)]
 pub struct NestedMappingBuilder {
     pub properties: HashMap<String, MappingPropertyBuilder>,
+    pub is_nested: bool,
 }
 
 
@@ -105,29 +174,43 @@ impl Default for NestedMappingBuilder {
     fn default() -> NestedMappingBuilder {
         NestedMappingBuilder {
             properties: HashMap::new(),
+            is_nested: false,
         }
     }
 }
 
 
 impl NestedMappingBuilder {
-    pub fn build(&self, index_metadata: &IndexMetadata) -> NestedMapping {
+    pub fn build(&self, index_metadata: &IndexMetadata) -> Result<NestedMapping, MappingBuildError> {
         // Insert fields
         let mut properties = HashMap::new();
         for (field_name, builder) in self.properties.iter() {
             match *builder {
                 MappingPropertyBuilder::Field(ref field_builder) => {
-                     properties.insert(field_name.to_string(), MappingProperty::Field(field_builder.build(index_metadata)));
+                    let field_mapping = match field_builder.build(index_metadata) {
+                        Ok(field_mapping) => field_mapping,
+                        Err(e) => return Err(MappingBuildError::FieldMappingBuildError(field_name.to_string(), e)),
+                    };
+
+                    properties.insert(field_name.to_string(), MappingProperty::Field(field_mapping));
                 }
                 MappingPropertyBuilder::NestedMapping(ref nested_mapping_builder) => {
-                    properties.insert(field_name.to_string(), MappingProperty::NestedMapping(Box::new(nested_mapping_builder.build(index_metadata))));
+                    let nested_mapping = match nested_mapping_builder.build(index_metadata) {
+                        Ok(nested_mapping) => nested_mapping,
+                        Err(MappingBuildError::FieldMappingBuildError(nested_field_name, e)) => {
+                            return Err(MappingBuildError::FieldMappingBuildError(format!("{}.{}", field_name, nested_field_name), e));
+                        }
+                    };
+
+                    properties.insert(field_name.to_string(), MappingProperty::NestedMapping(Box::new(nested_mapping)));
                 }
             }
         }
 
-        NestedMapping {
+        Ok(NestedMapping {
             properties: properties,
-        }
+            is_nested: self.is_nested,
+        })
     }
 }
 
@@ -142,24 +225,112 @@ pub enum MappingPropertyBuilder {
 #[derive(Debug, PartialEq)]
 pub struct MappingBuilder {
     pub properties: HashMap<String, MappingPropertyBuilder>,
+    pub source_enabled: bool,
+    pub dynamic: DynamicMapping,
+}
+
+
+impl Default for MappingBuilder {
+    fn default() -> MappingBuilder {
+        MappingBuilder {
+            properties: HashMap::new(),
+            source_enabled: true,
+            dynamic: DynamicMapping::default(),
+        }
+    }
 }
 
 
 impl MappingBuilder {
-    pub fn build(&self, index_metadata: &IndexMetadata) -> Mapping {
+    pub fn build(&self, index_metadata: &IndexMetadata) -> Result<Mapping, MappingBuildError> {
         // Insert fields
         let mut properties = HashMap::new();
         for (field_name, builder) in self.properties.iter() {
             match *builder {
                 MappingPropertyBuilder::Field(ref field_builder) => {
-                     properties.insert(field_name.to_string(), MappingProperty::Field(field_builder.build(index_metadata)));
+                    let field_mapping = match field_builder.build(index_metadata) {
+                        Ok(field_mapping) => field_mapping,
+                        Err(e) => return Err(MappingBuildError::FieldMappingBuildError(field_name.to_string(), e)),
+                    };
+
+                    properties.insert(field_name.to_string(), MappingProperty::Field(field_mapping));
                 }
                 MappingPropertyBuilder::NestedMapping(ref nested_mapping_builder) => {
-                    properties.insert(field_name.to_string(), MappingProperty::NestedMapping(Box::new(nested_mapping_builder.build(index_metadata))));
+                    let nested_mapping = match nested_mapping_builder.build(index_metadata) {
+                        Ok(nested_mapping) => nested_mapping,
+                        Err(MappingBuildError::FieldMappingBuildError(nested_field_name, e)) => {
+                            return Err(MappingBuildError::FieldMappingBuildError(format!("{}.{}", field_name, nested_field_name), e));
+                        }
+                    };
+
+                    properties.insert(field_name.to_string(), MappingProperty::NestedMapping(Box::new(nested_mapping)));
+                }
+            }
+        }
+
+        // Insert parent key fields for join fields
+        let mut join_parent_fields = Vec::new();
+        for (field_name, property) in properties.iter() {
+            if let MappingProperty::Field(ref field_mapping) = *property {
+                if field_mapping.data_type == FieldType::Join {
+                    for parent_relation in field_mapping.join_relations.keys() {
+                        join_parent_fields.push(join_parent_field_name(field_name, parent_relation));
+                    }
                 }
             }
         }
 
+        for field_name in join_parent_fields {
+            properties.insert(field_name, MappingProperty::Field(
+                FieldMapping {
+                    data_type: FieldType::String,
+                    is_stored: true,
+                    is_in_all: false,
+                    is_join_parent_key: true,
+                    .. FieldMapping::default()
+                }
+            ));
+        }
+
+        // Insert _id field
+        if !properties.contains_key("_id") {
+            properties.insert("_id".to_string(), MappingProperty::Field(
+                FieldMapping {
+                    data_type: FieldType::String,
+                    is_stored: true,
+                    is_in_all: false,
+                    .. FieldMapping::default()
+                }
+            ));
+        }
+
+        // Insert _ignored field
+        // This holds the names of the fields that had values left out by "ignore_above" or "ignore_malformed"
+        if !properties.contains_key("_ignored") {
+            properties.insert("_ignored".to_string(), MappingProperty::Field(
+                FieldMapping {
+                    data_type: FieldType::String,
+                    is_stored: true,
+                    is_in_all: false,
+                    .. FieldMapping::default()
+                }
+            ));
+        }
+
+        // Insert _source field
+        // This holds the original JSON of each document so it can be returned in search results
+        if self.source_enabled {
+            properties.insert("_source".to_string(), MappingProperty::Field(
+                FieldMapping {
+                    data_type: FieldType::String,
+                    is_indexed: false,
+                    is_stored: true,
+                    is_in_all: false,
+                    .. FieldMapping::default()
+                }
+            ));
+        }
+
         // Insert _all field
         if !properties.contains_key("_all") {
             // TODO: Support disabling the _all field
@@ -170,14 +341,27 @@ impl MappingBuilder {
                     is_in_all: false,
                     index_analyzer: Some(get_standard_analyzer()),
                     search_analyzer: Some(get_standard_analyzer()),
+                    similarity: index_metadata.settings.default_similarity.clone(),
                     .. FieldMapping::default()
                 }
             ));
         }
 
-        Mapping {
+        let mapping = Mapping {
             properties: properties,
+            dynamic: self.dynamic,
+        };
+
+        // Check that copy_to fields exist
+        for (field_name, field_mapping) in mapping.fields() {
+            for target_field_name in field_mapping.copy_to.iter() {
+                if mapping.get_field(target_field_name).is_none() {
+                    return Err(MappingBuildError::FieldMappingBuildError(field_name, FieldMappingBuildError::UnrecognisedCopyToField(target_field_name.clone())));
+                }
+            }
         }
+
+        Ok(mapping)
     }
 }
 
@@ -187,10 +371,13 @@ mod tests {
     use analysis::AnalyzerSpec;
     use analysis::tokenizers::TokenizerSpec;
     use analysis::filters::FilterSpec;
-    use mapping::{Mapping, MappingProperty, FieldMapping, FieldType, get_standard_analyzer};
+    use serde_json::value::ToJson;
+
+    use mapping::{Mapping, MappingProperty, FieldMapping, FieldType, DynamicMapping, get_standard_analyzer};
     use index::metadata::IndexMetadata;
+    use completion::DEFAULT_MAX_INPUT_LENGTH;
 
-    use super::{MappingBuilder, MappingPropertyBuilder, FieldMappingBuilder};
+    use super::{MappingBuilder, MappingPropertyBuilder, FieldMappingBuilder, MappingBuildError, FieldMappingBuildError};
 
     #[test]
     fn test_build() {
@@ -206,9 +393,10 @@ mod tests {
                     }
                 )
             },
+            ..MappingBuilder::default()
         };
 
-        let mapping = builder.build(&index_metadata);
+        let mapping = builder.build(&index_metadata).unwrap();
 
         assert_eq!(mapping, Mapping {
             properties: hashmap! {
@@ -220,6 +408,25 @@ mod tests {
                     search_analyzer: Some(get_standard_analyzer()),
                     ..FieldMapping::default()
                 }),
+                "_id".to_string() => MappingProperty::Field(FieldMapping {
+                    data_type: FieldType::String,
+                    is_stored: true,
+                    is_in_all: false,
+                    ..FieldMapping::default()
+                }),
+                "_ignored".to_string() => MappingProperty::Field(FieldMapping {
+                    data_type: FieldType::String,
+                    is_stored: true,
+                    is_in_all: false,
+                    ..FieldMapping::default()
+                }),
+                "_source".to_string() => MappingProperty::Field(FieldMapping {
+                    data_type: FieldType::String,
+                    is_indexed: false,
+                    is_stored: true,
+                    is_in_all: false,
+                    ..FieldMapping::default()
+                }),
                 "_all".to_string() => MappingProperty::Field(FieldMapping {
                     data_type: FieldType::String,
                     is_in_all: false,
@@ -227,7 +434,8 @@ mod tests {
                     search_analyzer: Some(get_standard_analyzer()),
                     ..FieldMapping::default()
                 })
-            }
+            },
+            dynamic: DynamicMapping::True,
         });
     }
 
@@ -236,12 +444,32 @@ mod tests {
         let index_metadata = IndexMetadata::default();
         let builder = MappingBuilder {
             properties: hashmap! {},
+            ..MappingBuilder::default()
         };
 
-        let mapping = builder.build(&index_metadata);
+        let mapping = builder.build(&index_metadata).unwrap();
 
         assert_eq!(mapping, Mapping {
             properties: hashmap! {
+                "_id".to_string() => MappingProperty::Field(FieldMapping {
+                    data_type: FieldType::String,
+                    is_stored: true,
+                    is_in_all: false,
+                    ..FieldMapping::default()
+                }),
+                "_ignored".to_string() => MappingProperty::Field(FieldMapping {
+                    data_type: FieldType::String,
+                    is_stored: true,
+                    is_in_all: false,
+                    ..FieldMapping::default()
+                }),
+                "_source".to_string() => MappingProperty::Field(FieldMapping {
+                    data_type: FieldType::String,
+                    is_indexed: false,
+                    is_stored: true,
+                    is_in_all: false,
+                    ..FieldMapping::default()
+                }),
                 "_all".to_string() => MappingProperty::Field(FieldMapping {
                     data_type: FieldType::String,
                     is_in_all: false,
@@ -249,7 +477,8 @@ mod tests {
                     search_analyzer: Some(get_standard_analyzer()),
                     ..FieldMapping::default()
                 })
-            }
+            },
+            dynamic: DynamicMapping::True,
         });
     }
 
@@ -266,12 +495,32 @@ mod tests {
                     }
                 )
             },
+            ..MappingBuilder::default()
         };
 
-        let mapping = builder.build(&index_metadata);
+        let mapping = builder.build(&index_metadata).unwrap();
 
         assert_eq!(mapping, Mapping {
             properties: hashmap! {
+                "_id".to_string() => MappingProperty::Field(FieldMapping {
+                    data_type: FieldType::String,
+                    is_stored: true,
+                    is_in_all: false,
+                    ..FieldMapping::default()
+                }),
+                "_ignored".to_string() => MappingProperty::Field(FieldMapping {
+                    data_type: FieldType::String,
+                    is_stored: true,
+                    is_in_all: false,
+                    ..FieldMapping::default()
+                }),
+                "_source".to_string() => MappingProperty::Field(FieldMapping {
+                    data_type: FieldType::String,
+                    is_indexed: false,
+                    is_stored: true,
+                    is_in_all: false,
+                    ..FieldMapping::default()
+                }),
                 "_all".to_string() => MappingProperty::Field(FieldMapping {
                     data_type: FieldType::String,
                     boost: 2.0f64,
@@ -279,10 +528,25 @@ mod tests {
                     search_analyzer: Some(get_standard_analyzer()),
                     ..FieldMapping::default()
                 })
-            }
+            },
+            dynamic: DynamicMapping::True,
         });
     }
 
+    #[test]
+    fn test_build_source_disabled() {
+        let index_metadata = IndexMetadata::default();
+        let builder = MappingBuilder {
+            properties: hashmap! {},
+            source_enabled: false,
+            ..MappingBuilder::default()
+        };
+
+        let mapping = builder.build(&index_metadata).unwrap();
+
+        assert!(!mapping.properties.contains_key("_source"));
+    }
+
     #[test]
     fn test_build_field() {
         let index_metadata = IndexMetadata::default();
@@ -291,7 +555,7 @@ mod tests {
             ..FieldMappingBuilder::default()
         };
 
-        let mapping = builder.build(&index_metadata);
+        let mapping = builder.build(&index_metadata).unwrap();
 
         assert_eq!(mapping, FieldMapping {
             data_type: FieldType::String,
@@ -310,7 +574,7 @@ mod tests {
             ..FieldMappingBuilder::default()
         };
 
-        let mapping = builder.build(&index_metadata);
+        let mapping = builder.build(&index_metadata).unwrap();
 
         assert_eq!(mapping, FieldMapping {
             data_type: FieldType::Integer,
@@ -329,7 +593,7 @@ mod tests {
             ..FieldMappingBuilder::default()
         };
 
-        let mapping = builder.build(&index_metadata);
+        let mapping = builder.build(&index_metadata).unwrap();
 
         assert_eq!(mapping, FieldMapping {
             data_type: FieldType::String,
@@ -349,7 +613,7 @@ mod tests {
             ..FieldMappingBuilder::default()
         };
 
-        let mapping = builder.build(&index_metadata);
+        let mapping = builder.build(&index_metadata).unwrap();
 
         assert_eq!(mapping, FieldMapping {
             data_type: FieldType::String,
@@ -369,7 +633,7 @@ mod tests {
             ..FieldMappingBuilder::default()
         };
 
-        let mapping = builder.build(&index_metadata);
+        let mapping = builder.build(&index_metadata).unwrap();
 
         assert_eq!(mapping, FieldMapping {
             data_type: FieldType::String,
@@ -388,7 +652,7 @@ mod tests {
             ..FieldMappingBuilder::default()
         };
 
-        let mapping = builder.build(&index_metadata);
+        let mapping = builder.build(&index_metadata).unwrap();
 
         assert_eq!(mapping, FieldMapping {
             data_type: FieldType::String,
@@ -400,6 +664,7 @@ mod tests {
 
     fn build_test_analyzer() -> AnalyzerSpec {
         AnalyzerSpec {
+            char_filters: vec![],
             tokenizer: TokenizerSpec::Standard,
             filters: vec![
                 FilterSpec::Lowercase,
@@ -418,12 +683,14 @@ mod tests {
             ..FieldMappingBuilder::default()
         };
 
-        let mapping = builder.build(&index_metadata);
+        let mapping = builder.build(&index_metadata).unwrap();
 
         assert_eq!(mapping, FieldMapping {
             data_type: FieldType::String,
             index_analyzer: Some(build_test_analyzer()),
             search_analyzer: Some(build_test_analyzer()),
+            index_analyzer_name: Some("my-analyzer".to_string()),
+            search_analyzer_name: Some("my-analyzer".to_string()),
             ..FieldMapping::default()
         });
     }
@@ -439,12 +706,13 @@ mod tests {
             ..FieldMappingBuilder::default()
         };
 
-        let mapping = builder.build(&index_metadata);
+        let mapping = builder.build(&index_metadata).unwrap();
 
         assert_eq!(mapping, FieldMapping {
             data_type: FieldType::String,
             index_analyzer: Some(build_test_analyzer()),
             search_analyzer: Some(get_standard_analyzer()),
+            index_analyzer_name: Some("my-analyzer".to_string()),
             ..FieldMapping::default()
         });
     }
@@ -460,13 +728,153 @@ mod tests {
             ..FieldMappingBuilder::default()
         };
 
-        let mapping = builder.build(&index_metadata);
+        let mapping = builder.build(&index_metadata).unwrap();
 
         assert_eq!(mapping, FieldMapping {
             data_type: FieldType::String,
             index_analyzer: Some(get_standard_analyzer()),
             search_analyzer: Some(build_test_analyzer()),
+            search_analyzer_name: Some("my-analyzer".to_string()),
             ..FieldMapping::default()
         });
     }
+
+    #[test]
+    fn test_build_field_unrecognised_analyzer() {
+        let index_metadata = IndexMetadata::default();
+        let builder = MappingBuilder {
+            properties: hashmap! {
+                "title".to_string() => MappingPropertyBuilder::Field(
+                    FieldMappingBuilder {
+                        field_type: FieldType::String,
+                        search_analyzer: Some("foo".to_string()),
+                        ..FieldMappingBuilder::default()
+                    }
+                )
+            },
+            ..MappingBuilder::default()
+        };
+
+        let error = builder.build(&index_metadata).err().expect("build() was supposed to return an error, but didn't");
+
+        assert_eq!(error, MappingBuildError::FieldMappingBuildError("title".to_string(), FieldMappingBuildError::UnrecognisedAnalyzer("foo".to_string())));
+    }
+
+    #[test]
+    fn test_build_unrecognised_copy_to_field() {
+        let index_metadata = IndexMetadata::default();
+        let builder = MappingBuilder {
+            properties: hashmap! {
+                "title".to_string() => MappingPropertyBuilder::Field(
+                    FieldMappingBuilder {
+                        copy_to: vec!["all_text".to_string()],
+                        ..FieldMappingBuilder::default()
+                    }
+                )
+            },
+            ..MappingBuilder::default()
+        };
+
+        let error = builder.build(&index_metadata).err().expect("build() was supposed to return an error, but didn't");
+
+        assert_eq!(error, MappingBuildError::FieldMappingBuildError("title".to_string(), FieldMappingBuildError::UnrecognisedCopyToField("all_text".to_string())));
+    }
+
+    #[test]
+    fn test_build_invalid_null_value() {
+        let index_metadata = IndexMetadata::default();
+        let builder = MappingBuilder {
+            properties: hashmap! {
+                "views".to_string() => MappingPropertyBuilder::Field(
+                    FieldMappingBuilder {
+                        field_type: FieldType::Integer,
+                        is_analyzed: false,
+                        null_value: Some(json!("none")),
+                        ..FieldMappingBuilder::default()
+                    }
+                )
+            },
+            ..MappingBuilder::default()
+        };
+
+        let error = builder.build(&index_metadata).err().expect("build() was supposed to return an error, but didn't");
+
+        assert_eq!(error, MappingBuildError::FieldMappingBuildError("views".to_string(), FieldMappingBuildError::InvalidNullValue(json!("none"))));
+    }
+
+    #[test]
+    fn test_build_join_field() {
+        let index_metadata = IndexMetadata::default();
+        let builder = MappingBuilder {
+            properties: hashmap! {
+                "my_join".to_string() => MappingPropertyBuilder::Field(
+                    FieldMappingBuilder {
+                        field_type: FieldType::Join,
+                        is_analyzed: false,
+                        is_in_all: false,
+                        join_relations: hashmap! {
+                            "question".to_string() => vec!["answer".to_string()]
+                        },
+                        ..FieldMappingBuilder::default()
+                    }
+                )
+            },
+            ..MappingBuilder::default()
+        };
+
+        let mapping = builder.build(&index_metadata).unwrap();
+
+        assert_eq!(mapping, Mapping {
+            properties: hashmap! {
+                "my_join".to_string() => MappingProperty::Field(FieldMapping {
+                    data_type: FieldType::Join,
+                    is_in_all: false,
+                    join_relations: hashmap! {
+                        "question".to_string() => vec!["answer".to_string()]
+                    },
+                    ..FieldMapping::default()
+                }),
+                "my_join#question".to_string() => MappingProperty::Field(FieldMapping {
+                    data_type: FieldType::String,
+                    is_stored: true,
+                    is_in_all: false,
+                    is_join_parent_key: true,
+                    ..FieldMapping::default()
+                }),
+                "_id".to_string() => MappingProperty::Field(FieldMapping {
+                    data_type: FieldType::String,
+                    is_stored: true,
+                    is_in_all: false,
+                    ..FieldMapping::default()
+                }),
+                "_ignored".to_string() => MappingProperty::Field(FieldMapping {
+                    data_type: FieldType::String,
+                    is_stored: true,
+                    is_in_all: false,
+                    ..FieldMapping::default()
+                }),
+                "_source".to_string() => MappingProperty::Field(FieldMapping {
+                    data_type: FieldType::String,
+                    is_indexed: false,
+                    is_stored: true,
+                    is_in_all: false,
+                    ..FieldMapping::default()
+                }),
+                "_all".to_string() => MappingProperty::Field(FieldMapping {
+                    data_type: FieldType::String,
+                    is_in_all: false,
+                    index_analyzer: Some(get_standard_analyzer()),
+                    search_analyzer: Some(get_standard_analyzer()),
+                    ..FieldMapping::default()
+                })
+            },
+            dynamic: DynamicMapping::True,
+        });
+
+        // The parent key field is left out of the mapping's JSON
+        let mapping_json = mapping.to_json().unwrap();
+        let properties_json = mapping_json.as_object().unwrap().get("properties").unwrap().as_object().unwrap();
+        assert!(properties_json.contains_key("my_join"));
+        assert!(!properties_json.contains_key("my_join#question"));
+    }
 }
@@ -0,0 +1,274 @@
+//! Adds fields that aren't in a mapping when a document containing them is indexed
+//!
+//! The type of each new field is guessed from its first value:
+//!
+//!  - Strings that contain an RFC 3339 date and time (eg, "2017-05-01T12:00:00Z") become
+//!    "date" fields, other strings are analyzed with a "keyword" multi-field that holds the
+//!    whole value (if it's 256 characters or less)
+//!  - Whole numbers become "long" fields
+//!  - Booleans become "boolean" fields
+//!
+//! Date fields can only index full date-times, so dates without a time (eg, "2017-05-01")
+//! are mapped as strings. Arrays take the type of their first non-null item. Nulls, empty
+//! arrays, objects and numbers with a fractional part don't add anything to the mapping
+//! (there's no floating point field type yet), but they're still kept in the source.
+
+use std::collections::HashMap;
+
+use serde_json;
+use chrono::{DateTime, UTC};
+
+use mapping::{Mapping, MappingProperty, FieldType, DynamicMapping};
+use mapping::build::FieldMappingBuilder;
+use index::Index;
+
+
+/// Guesses the mapping of a field from a value
+pub fn infer_field_mapping(value: &serde_json::Value) -> Option<FieldMappingBuilder> {
+    match *value {
+        serde_json::Value::String(ref string) => {
+            if string.parse::<DateTime<UTC>>().is_ok() {
+                Some(FieldMappingBuilder {
+                    field_type: FieldType::Date,
+                    is_analyzed: false,
+                    ..FieldMappingBuilder::default()
+                })
+            } else {
+                Some(FieldMappingBuilder {
+                    multi_fields: hashmap! {
+                        "keyword".to_string() => FieldMappingBuilder {
+                            is_analyzed: false,
+                            is_in_all: false,
+                            ignore_above: Some(256),
+                            ..FieldMappingBuilder::default()
+                        }
+                    },
+                    ..FieldMappingBuilder::default()
+                })
+            }
+        }
+        serde_json::Value::Number(ref num) => {
+            if num.is_i64() || num.is_u64() {
+                Some(FieldMappingBuilder {
+                    field_type: FieldType::Integer,
+                    is_analyzed: false,
+                    ..FieldMappingBuilder::default()
+                })
+            } else {
+                None
+            }
+        }
+        serde_json::Value::Bool(_) => {
+            Some(FieldMappingBuilder {
+                field_type: FieldType::Boolean,
+                is_analyzed: false,
+                ..FieldMappingBuilder::default()
+            })
+        }
+        serde_json::Value::Array(ref array) => {
+            array.iter().filter(|item| **item != serde_json::Value::Null).nth(0).and_then(infer_field_mapping)
+        }
+        serde_json::Value::Null | serde_json::Value::Object(_) => None,
+    }
+}
+
+
+impl Mapping {
+    /// Guesses mappings for the fields in a document that aren't in this mapping
+    ///
+    /// Returns nothing unless the mapping is dynamic.
+    pub fn find_unmapped_fields(&self, data: &serde_json::Map<String, serde_json::Value>) -> Vec<(String, FieldMappingBuilder)> {
+        if self.dynamic != DynamicMapping::True {
+            return Vec::new();
+        }
+
+        let mut fields = Vec::new();
+
+        for (field_name, field_value) in data {
+            if self.properties.contains_key(field_name) {
+                continue;
+            }
+
+            if let Some(field_builder) = infer_field_mapping(field_value) {
+                fields.push((field_name.clone(), field_builder));
+            }
+        }
+
+        fields
+    }
+}
+
+
+impl Index {
+    /// Adds any fields in a document that aren't in the mapping to the mapping and the store
+    ///
+    /// Returns the names of the fields that were added.
+    pub fn update_dynamic_mapping(&self, mapping_name: &str, data: &serde_json::Map<String, serde_json::Value>) -> Result<Vec<String>, String> {
+        // Most documents don't add any fields so check before taking the write lock
+        {
+            let index_metadata = self.metadata.read().unwrap();
+            match index_metadata.mappings.get(mapping_name) {
+                Some(mapping) => {
+                    if mapping.find_unmapped_fields(data).is_empty() {
+                        return Ok(Vec::new());
+                    }
+                }
+                None => return Ok(Vec::new()),
+            }
+        }
+
+        let mut index_metadata = self.metadata.write().unwrap();
+
+        // Look again as another thread may have added the fields while the lock was released
+        let new_fields = match index_metadata.mappings.get(mapping_name) {
+            Some(mapping) => mapping.find_unmapped_fields(data),
+            None => return Ok(Vec::new()),
+        };
+
+        // Build the new fields into a separate mapping so they can be linked to the store
+        // before being added to the index's mapping
+        let mut new_mapping = Mapping {
+            properties: HashMap::new(),
+            dynamic: DynamicMapping::True,
+        };
+
+        for (field_name, field_builder) in new_fields {
+            let field_mapping = try!(field_builder.build(&index_metadata).map_err(|e| format!("failed to build mapping for field [{}]: {:?}", field_name, e)));
+            new_mapping.properties.insert(field_name, MappingProperty::Field(field_mapping));
+        }
+
+        // Add the fields into the store
+        let store = try!(self.store());
+        for (field_name, field_mapping) in new_mapping.fields() {
+            if store.reader().schema().get_field_by_name(&field_name).is_none() {
+                let (field_type, field_flags) = field_mapping.store_field_info();
+                try!(store.add_field(field_name, field_type, field_flags));
+            }
+        }
+
+        // Link them to the mapping
+        {
+            let index_reader = store.reader();
+            let schema = index_reader.schema();
+
+            new_mapping.visit_fields_mut(|field_name, field_mapping| {
+                field_mapping.index_ref = schema.get_field_by_name(field_name);
+            });
+        }
+
+        let mut field_names = new_mapping.properties.keys().cloned().collect::<Vec<String>>();
+        field_names.sort();
+
+        index_metadata.mappings.get_mut(mapping_name).unwrap().properties.extend(new_mapping.properties);
+
+        try!(index_metadata.save(self.metadata_path()));
+
+        Ok(field_names)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use mapping::{Mapping, FieldType, DynamicMapping};
+    use mapping::build::FieldMappingBuilder;
+
+    use super::infer_field_mapping;
+
+    fn keyword_string_builder() -> FieldMappingBuilder {
+        FieldMappingBuilder {
+            multi_fields: hashmap! {
+                "keyword".to_string() => FieldMappingBuilder {
+                    is_analyzed: false,
+                    is_in_all: false,
+                    ignore_above: Some(256),
+                    ..FieldMappingBuilder::default()
+                }
+            },
+            ..FieldMappingBuilder::default()
+        }
+    }
+
+    #[test]
+    fn test_infer_string() {
+        assert_eq!(infer_field_mapping(&json!("hello world")), Some(keyword_string_builder()));
+    }
+
+    #[test]
+    fn test_infer_date() {
+        assert_eq!(infer_field_mapping(&json!("2017-05-01T12:00:00Z")), Some(FieldMappingBuilder {
+            field_type: FieldType::Date,
+            is_analyzed: false,
+            ..FieldMappingBuilder::default()
+        }));
+
+        // Dates without a time aren't detected
+        assert_eq!(infer_field_mapping(&json!("2017-05-01")), Some(keyword_string_builder()));
+    }
+
+    #[test]
+    fn test_infer_numbers() {
+        assert_eq!(infer_field_mapping(&json!(123)), Some(FieldMappingBuilder {
+            field_type: FieldType::Integer,
+            is_analyzed: false,
+            ..FieldMappingBuilder::default()
+        }));
+
+        assert_eq!(infer_field_mapping(&json!(1.5)), None);
+    }
+
+    #[test]
+    fn test_infer_boolean() {
+        assert_eq!(infer_field_mapping(&json!(true)), Some(FieldMappingBuilder {
+            field_type: FieldType::Boolean,
+            is_analyzed: false,
+            ..FieldMappingBuilder::default()
+        }));
+    }
+
+    #[test]
+    fn test_infer_array() {
+        assert_eq!(infer_field_mapping(&json!([null, 1, 2])), Some(FieldMappingBuilder {
+            field_type: FieldType::Integer,
+            is_analyzed: false,
+            ..FieldMappingBuilder::default()
+        }));
+
+        assert_eq!(infer_field_mapping(&json!([])), None);
+    }
+
+    #[test]
+    fn test_infer_nothing() {
+        assert_eq!(infer_field_mapping(&json!(null)), None);
+        assert_eq!(infer_field_mapping(&json!({"foo": "bar"})), None);
+    }
+
+    #[test]
+    fn test_find_unmapped_fields() {
+        let mapping = Mapping {
+            properties: HashMap::new(),
+            dynamic: DynamicMapping::True,
+        };
+        let data = json!({"title": "Hello"});
+
+        assert_eq!(mapping.find_unmapped_fields(data.as_object().unwrap()), vec![
+            ("title".to_string(), keyword_string_builder()),
+        ]);
+    }
+
+    #[test]
+    fn test_find_unmapped_fields_not_dynamic() {
+        let data = json!({"title": "Hello"});
+
+        for dynamic in vec![DynamicMapping::False, DynamicMapping::Strict] {
+            let mapping = Mapping {
+                properties: HashMap::new(),
+                dynamic: dynamic,
+            };
+
+            assert_eq!(mapping.find_unmapped_fields(data.as_object().unwrap()), vec![]);
+        }
+    }
+}
@@ -0,0 +1,390 @@
+//! Merges new fields and settings into an existing mapping
+//!
+//! New fields can be added at any time. Fields that already exist may have documents indexed
+//! into them, so only the settings that don't change how values are indexed can be updated.
+
+use std::fmt;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+
+use mapping::{Mapping, MappingProperty, FieldMapping, FieldType, join_path};
+
+
+#[derive(Debug, PartialEq)]
+pub enum MappingMergeError {
+    FieldTypeChanged {
+        field_name: String,
+        existing_type: FieldType,
+        new_type: FieldType,
+    },
+    FieldSettingChanged {
+        field_name: String,
+        setting: &'static str,
+    },
+    FieldChangedToObject(String),
+    ObjectChangedToField(String),
+    NestedSettingChanged(String),
+    SourceSettingChanged,
+}
+
+
+impl fmt::Display for MappingMergeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MappingMergeError::FieldTypeChanged{ref field_name, ref existing_type, ref new_type} => {
+                write!(f, "mapper [{}] cannot be changed from type [{}] to [{}]", field_name, existing_type.to_string(), new_type.to_string())
+            }
+            MappingMergeError::FieldSettingChanged{ref field_name, setting} => {
+                write!(f, "mapper [{}] has different [{}] values", field_name, setting)
+            }
+            MappingMergeError::FieldChangedToObject(ref field_name) => {
+                write!(f, "can't merge an object mapping [{}] with a non object mapping", field_name)
+            }
+            MappingMergeError::ObjectChangedToField(ref field_name) => {
+                write!(f, "can't merge a non object mapping [{}] with an object mapping", field_name)
+            }
+            MappingMergeError::NestedSettingChanged(ref field_name) => {
+                write!(f, "object mapping [{}] can't be changed between nested and non-nested", field_name)
+            }
+            MappingMergeError::SourceSettingChanged => {
+                write!(f, "the [_source] enabled setting can't be changed")
+            }
+        }
+    }
+}
+
+
+/// Checks that a field's new mapping indexes values the same way as its existing mapping
+fn check_field(field_name: &str, existing: &FieldMapping, new: &FieldMapping) -> Result<(), MappingMergeError> {
+    if existing.data_type != new.data_type {
+        return Err(MappingMergeError::FieldTypeChanged {
+            field_name: field_name.to_string(),
+            existing_type: existing.data_type,
+            new_type: new.data_type,
+        });
+    }
+
+    let changed_setting = if existing.is_indexed != new.is_indexed || existing.index_analyzer.is_some() != new.index_analyzer.is_some() {
+        Some("index")
+    } else if existing.index_analyzer_name != new.index_analyzer_name {
+        Some("analyzer")
+    } else if existing.search_analyzer_name != new.search_analyzer_name {
+        Some("search_analyzer")
+    } else if existing.similarity_name != new.similarity_name {
+        Some("similarity")
+    } else if existing.is_stored != new.is_stored {
+        Some("store")
+    } else if existing.has_doc_values != new.has_doc_values {
+        Some("doc_values")
+    } else if existing.null_value != new.null_value {
+        Some("null_value")
+    } else if existing.join_relations != new.join_relations {
+        Some("relations")
+    } else if existing.completion_contexts != new.completion_contexts {
+        Some("contexts")
+    } else if existing.max_input_length != new.max_input_length {
+        Some("max_input_length")
+    } else {
+        None
+    };
+
+    if let Some(setting) = changed_setting {
+        return Err(MappingMergeError::FieldSettingChanged {
+            field_name: field_name.to_string(),
+            setting: setting,
+        });
+    }
+
+    for (name, new_multi_field) in new.multi_fields.iter() {
+        if let Some(existing_multi_field) = existing.multi_fields.get(name) {
+            try!(check_field(&join_path(field_name, name), existing_multi_field, new_multi_field));
+        }
+    }
+
+    Ok(())
+}
+
+
+fn check_properties(prefix: &str, existing: &HashMap<String, MappingProperty>, new: &HashMap<String, MappingProperty>) -> Result<(), MappingMergeError> {
+    for (name, new_property) in new.iter() {
+        let path = join_path(prefix, name);
+
+        match (existing.get(name), new_property) {
+            (Some(&MappingProperty::Field(ref existing_field)), &MappingProperty::Field(ref new_field)) => {
+                try!(check_field(&path, existing_field, new_field));
+            }
+            (Some(&MappingProperty::Field(_)), &MappingProperty::NestedMapping(_)) => {
+                return Err(MappingMergeError::FieldChangedToObject(path));
+            }
+            (Some(&MappingProperty::NestedMapping(_)), &MappingProperty::Field(_)) => {
+                return Err(MappingMergeError::ObjectChangedToField(path));
+            }
+            (Some(&MappingProperty::NestedMapping(ref existing_object)), &MappingProperty::NestedMapping(ref new_object)) => {
+                if existing_object.is_nested != new_object.is_nested {
+                    return Err(MappingMergeError::NestedSettingChanged(path));
+                }
+
+                try!(check_properties(&path, &existing_object.properties, &new_object.properties));
+            }
+            (None, _) => {}
+        }
+    }
+
+    Ok(())
+}
+
+
+fn merge_field(existing: &mut FieldMapping, new: FieldMapping) {
+    // These settings only affect searching and documents indexed from now on
+    existing.boost = new.boost;
+    existing.is_in_all = new.is_in_all;
+    existing.copy_to = new.copy_to;
+    existing.ignore_above = new.ignore_above;
+    existing.ignore_malformed = new.ignore_malformed;
+
+    for (name, new_multi_field) in new.multi_fields {
+        match existing.multi_fields.entry(name) {
+            Entry::Occupied(mut entry) => merge_field(entry.get_mut(), new_multi_field),
+            Entry::Vacant(entry) => {
+                entry.insert(new_multi_field);
+            }
+        }
+    }
+}
+
+
+fn merge_properties(existing: &mut HashMap<String, MappingProperty>, new: HashMap<String, MappingProperty>) {
+    for (name, new_property) in new {
+        match existing.entry(name) {
+            Entry::Occupied(mut entry) => {
+                match (entry.get_mut(), new_property) {
+                    (&mut MappingProperty::Field(ref mut existing_field), MappingProperty::Field(new_field)) => {
+                        merge_field(existing_field, new_field);
+                    }
+                    (&mut MappingProperty::NestedMapping(ref mut existing_object), MappingProperty::NestedMapping(new_object)) => {
+                        let new_object = *new_object;
+                        merge_properties(&mut existing_object.properties, new_object.properties);
+                    }
+                    _ => {
+                        // Changing between fields and objects is rejected by check_properties
+                    }
+                }
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(new_property);
+            }
+        }
+    }
+}
+
+
+impl Mapping {
+    /// Checks that another mapping can be merged into this one
+    pub fn check_merge(&self, other: &Mapping) -> Result<(), MappingMergeError> {
+        if self.properties.contains_key("_source") != other.properties.contains_key("_source") {
+            return Err(MappingMergeError::SourceSettingChanged);
+        }
+
+        check_properties("", &self.properties, &other.properties)
+    }
+
+    /// Merges another mapping into this one
+    ///
+    /// Nothing is changed if the mappings aren't compatible.
+    pub fn merge(&mut self, other: Mapping) -> Result<(), MappingMergeError> {
+        try!(self.check_merge(&other));
+
+        self.dynamic = other.dynamic;
+        merge_properties(&mut self.properties, other.properties);
+
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use mapping::{Mapping, MappingProperty, FieldMapping, NestedMapping, FieldType, DynamicMapping};
+
+    use super::MappingMergeError;
+
+    fn string_field() -> MappingProperty {
+        MappingProperty::Field(FieldMapping {
+            data_type: FieldType::String,
+            ..FieldMapping::default()
+        })
+    }
+
+    #[test]
+    fn test_merge_new_field() {
+        let mut mapping = Mapping {
+            properties: hashmap! {
+                "title".to_string() => string_field()
+            },
+            dynamic: DynamicMapping::True,
+        };
+
+        let result = mapping.merge(Mapping {
+            properties: hashmap! {
+                "body".to_string() => string_field()
+            },
+            dynamic: DynamicMapping::Strict,
+        });
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(mapping, Mapping {
+            properties: hashmap! {
+                "title".to_string() => string_field(),
+                "body".to_string() => string_field()
+            },
+            dynamic: DynamicMapping::Strict,
+        });
+    }
+
+    #[test]
+    fn test_merge_new_multi_field() {
+        let mut mapping = Mapping {
+            properties: hashmap! {
+                "title".to_string() => string_field()
+            },
+            dynamic: DynamicMapping::True,
+        };
+
+        let title_with_keyword = || {
+            MappingProperty::Field(FieldMapping {
+                data_type: FieldType::String,
+                multi_fields: hashmap! {
+                    "keyword".to_string() => FieldMapping {
+                        data_type: FieldType::String,
+                        is_in_all: false,
+                        ..FieldMapping::default()
+                    }
+                },
+                ..FieldMapping::default()
+            })
+        };
+
+        let result = mapping.merge(Mapping {
+            properties: hashmap! {
+                "title".to_string() => title_with_keyword()
+            },
+            dynamic: DynamicMapping::True,
+        });
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(mapping.properties.get("title"), Some(&title_with_keyword()));
+    }
+
+    #[test]
+    fn test_merge_field_type_changed() {
+        let mut mapping = Mapping {
+            properties: hashmap! {
+                "title".to_string() => string_field()
+            },
+            dynamic: DynamicMapping::True,
+        };
+
+        let result = mapping.merge(Mapping {
+            properties: hashmap! {
+                "title".to_string() => MappingProperty::Field(FieldMapping {
+                    data_type: FieldType::Integer,
+                    ..FieldMapping::default()
+                })
+            },
+            dynamic: DynamicMapping::True,
+        });
+
+        assert_eq!(result, Err(MappingMergeError::FieldTypeChanged {
+            field_name: "title".to_string(),
+            existing_type: FieldType::String,
+            new_type: FieldType::Integer,
+        }));
+
+        // The existing mapping must be left alone
+        assert_eq!(mapping.properties.get("title"), Some(&string_field()));
+    }
+
+    #[test]
+    fn test_merge_analyzer_changed() {
+        let mut mapping = Mapping {
+            properties: hashmap! {
+                "title".to_string() => string_field()
+            },
+            dynamic: DynamicMapping::True,
+        };
+
+        let result = mapping.merge(Mapping {
+            properties: hashmap! {
+                "title".to_string() => MappingProperty::Field(FieldMapping {
+                    data_type: FieldType::String,
+                    index_analyzer_name: Some("english".to_string()),
+                    ..FieldMapping::default()
+                })
+            },
+            dynamic: DynamicMapping::True,
+        });
+
+        assert_eq!(result, Err(MappingMergeError::FieldSettingChanged {
+            field_name: "title".to_string(),
+            setting: "analyzer",
+        }));
+    }
+
+    #[test]
+    fn test_merge_object_field_changed() {
+        let mut mapping = Mapping {
+            properties: hashmap! {
+                "user".to_string() => MappingProperty::NestedMapping(Box::new(NestedMapping {
+                    properties: hashmap! {
+                        "name".to_string() => string_field()
+                    },
+                    is_nested: false,
+                }))
+            },
+            dynamic: DynamicMapping::True,
+        };
+
+        let result = mapping.merge(Mapping {
+            properties: hashmap! {
+                "user".to_string() => MappingProperty::NestedMapping(Box::new(NestedMapping {
+                    properties: hashmap! {
+                        "name".to_string() => MappingProperty::Field(FieldMapping {
+                            data_type: FieldType::Boolean,
+                            ..FieldMapping::default()
+                        })
+                    },
+                    is_nested: false,
+                }))
+            },
+            dynamic: DynamicMapping::True,
+        });
+
+        assert_eq!(result, Err(MappingMergeError::FieldTypeChanged {
+            field_name: "user.name".to_string(),
+            existing_type: FieldType::String,
+            new_type: FieldType::Boolean,
+        }));
+    }
+
+    #[test]
+    fn test_merge_field_changed_to_object() {
+        let mut mapping = Mapping {
+            properties: hashmap! {
+                "user".to_string() => string_field()
+            },
+            dynamic: DynamicMapping::True,
+        };
+
+        let result = mapping.merge(Mapping {
+            properties: hashmap! {
+                "user".to_string() => MappingProperty::NestedMapping(Box::new(NestedMapping {
+                    properties: hashmap! {},
+                    is_nested: false,
+                }))
+            },
+            dynamic: DynamicMapping::True,
+        });
+
+        assert_eq!(result, Err(MappingMergeError::FieldChangedToObject("user".to_string())));
+    }
+}
@@ -1,24 +1,33 @@
 pub mod build;
 pub mod parse;
+pub mod dynamic;
+pub mod merge;
 
 use std::collections::{HashMap, BTreeMap};
+use std::net::IpAddr;
 
 use serde_json;
 use serde_json::value::ToJson;
 use chrono::{DateTime, UTC};
-use kite::{Term, Token};
+use kite::{Term, Token, TermScorer};
 use kite::document::FieldValue;
 use kite::similarity::SimilarityModel;
-use kite::schema::FieldRef;
+use kite::schema::{self, FieldRef, FieldFlags, FIELD_INDEXED, FIELD_STORED};
 
 use analysis::AnalyzerSpec;
+use analysis::resources::ResourceError;
 use analysis::tokenizers::TokenizerSpec;
 use analysis::filters::FilterSpec;
+use index::metadata::settings::Similarity;
+use geo::{self, GeoPoint};
+use ip;
+use completion::{self, DEFAULT_MAX_INPUT_LENGTH};
 
 
 // TEMPORARY
 fn get_standard_analyzer() -> AnalyzerSpec {
     AnalyzerSpec {
+        char_filters: vec![],
         tokenizer: TokenizerSpec::Standard,
         filters: vec![
             FilterSpec::Lowercase,
@@ -34,6 +43,10 @@ pub enum FieldType {
     Integer,
     Boolean,
     Date,
+    Join,
+    GeoPoint,
+    Ip,
+    Completion,
 }
 
 
@@ -51,6 +64,10 @@ impl ToString for FieldType {
             FieldType::Integer => "integer".to_string(),
             FieldType::Boolean => "boolean".to_string(),
             FieldType::Date => "date".to_string(),
+            FieldType::Join => "join".to_string(),
+            FieldType::GeoPoint => "geo_point".to_string(),
+            FieldType::Ip => "ip".to_string(),
+            FieldType::Completion => "completion".to_string(),
         }
     }
 }
@@ -76,20 +93,75 @@ impl Default for FieldSearchOptions {
 }
 
 
+impl FieldSearchOptions {
+    /// Creates a scorer for term queries on the field
+    pub fn term_scorer(&self) -> TermScorer {
+        TermScorer {
+            similarity_model: self.similarity_model.clone(),
+            boost: 1.0f64,
+        }
+    }
+}
+
+
 #[derive(Debug)]
 pub struct FieldValueError;
 
 
+/// Returns the name of the hidden field that holds parent keys for a relation of a "join" field
+///
+/// Parent documents index their own key into this field and child documents index the
+/// key of their parent, so both sides of the relation can be matched with a term query.
+pub fn join_parent_field_name(join_field_name: &str, parent_relation: &str) -> String {
+    format!("{}#{}", join_field_name, parent_relation)
+}
+
+
 #[derive(Debug, PartialEq)]
 pub struct FieldMapping {
     pub data_type: FieldType,
     pub index_ref: Option<FieldRef>,
     pub is_indexed: bool,
     pub is_stored: bool,
+
+    /// Keeps the value of each document so it can be read back when sorting and aggregating
+    ///
+    /// The store doesn't have column storage so these are kept as stored fields.
+    pub has_doc_values: bool,
     pub is_in_all: bool,
     boost: f64,
     index_analyzer: Option<AnalyzerSpec>,
     search_analyzer: Option<AnalyzerSpec>,
+    index_analyzer_name: Option<String>,
+    search_analyzer_name: Option<String>,
+
+    /// How matches on this field are scored
+    similarity: Similarity,
+    similarity_name: Option<String>,
+    pub join_relations: HashMap<String, Vec<String>>,
+
+    /// Holds the parent keys of a relation of a "join" field
+    ///
+    /// These fields are added by the mapping builder so they're left out of the mapping's JSON.
+    pub is_join_parent_key: bool,
+    pub multi_fields: HashMap<String, FieldMapping>,
+    pub copy_to: Vec<String>,
+
+    /// Explicit nulls in documents are indexed as this value. Without it, nulls are
+    /// treated the same as a missing field
+    pub null_value: Option<serde_json::Value>,
+
+    /// Strings longer than this many characters aren't indexed or stored
+    pub ignore_above: Option<usize>,
+
+    /// Skip values that can't be parsed instead of rejecting the document
+    pub ignore_malformed: bool,
+
+    /// The names of the category contexts of a "completion" field
+    pub completion_contexts: Vec<String>,
+
+    /// Completion inputs are cut down to this many characters after analysis
+    pub max_input_length: usize,
 }
 
 
@@ -100,10 +172,24 @@ impl Default for FieldMapping {
             index_ref: None,
             is_indexed: true,
             is_stored: false,
+            has_doc_values: false,
             is_in_all: true,
             boost: 1.0f64,
             index_analyzer: None,
             search_analyzer: None,
+            index_analyzer_name: None,
+            search_analyzer_name: None,
+            similarity: Similarity::default(),
+            similarity_name: None,
+            join_relations: HashMap::new(),
+            is_join_parent_key: false,
+            multi_fields: HashMap::new(),
+            copy_to: Vec::new(),
+            null_value: None,
+            ignore_above: None,
+            ignore_malformed: false,
+            completion_contexts: Vec::new(),
+            max_input_length: DEFAULT_MAX_INPUT_LENGTH,
         }
     }
 }
@@ -111,6 +197,40 @@ impl Default for FieldMapping {
 
 impl ToJson for FieldMapping {
     fn to_json(&self) -> Result<serde_json::Value, serde_json::Error> {
+        if self.data_type == FieldType::Join {
+            return Ok(json!({
+                "type": self.data_type.to_string(),
+                "relations": self.join_relations,
+            }));
+        }
+
+        if self.data_type == FieldType::Completion {
+            let contexts = self.completion_contexts.iter().map(|name| {
+                json!({
+                    "name": name,
+                    "type": "category",
+                })
+            }).collect::<Vec<_>>();
+
+            let mut json = json!({
+                "type": self.data_type.to_string(),
+                "max_input_length": self.max_input_length,
+                "contexts": contexts,
+            });
+
+            if let Some(object) = json.as_object_mut() {
+                if let Some(ref index_analyzer_name) = self.index_analyzer_name {
+                    object.insert("analyzer".to_string(), json!(index_analyzer_name));
+                }
+
+                if let Some(ref search_analyzer_name) = self.search_analyzer_name {
+                    object.insert("search_analyzer".to_string(), json!(search_analyzer_name));
+                }
+            }
+
+            return Ok(json);
+        }
+
         let index = match (self.is_indexed, &self.index_analyzer) {
             (false, &None) => "no",
             (true, &None) => "not_analyzed",
@@ -123,16 +243,56 @@ impl ToJson for FieldMapping {
             }
         };
 
-        Ok(json!({
+        let mut json = json!({
             "type": self.data_type.to_string(),
             "index": index,
             "store": self.is_stored,
-            // TODO
-            // "index_analyzer"
-            // "search_analyzer"
+            "doc_values": self.has_doc_values,
             "boost": self.boost,
             "include_in_all": self.is_in_all
-        }))
+        });
+
+        // Analyzers are referenced by the name they were given in the mapping
+        if let Some(object) = json.as_object_mut() {
+            if let Some(ref index_analyzer_name) = self.index_analyzer_name {
+                object.insert("analyzer".to_string(), json!(index_analyzer_name));
+            }
+
+            if let Some(ref search_analyzer_name) = self.search_analyzer_name {
+                object.insert("search_analyzer".to_string(), json!(search_analyzer_name));
+            }
+
+            if let Some(ref similarity_name) = self.similarity_name {
+                object.insert("similarity".to_string(), json!(similarity_name));
+            }
+
+            if !self.copy_to.is_empty() {
+                object.insert("copy_to".to_string(), json!(self.copy_to));
+            }
+
+            if let Some(ref null_value) = self.null_value {
+                object.insert("null_value".to_string(), null_value.clone());
+            }
+
+            if let Some(ignore_above) = self.ignore_above {
+                object.insert("ignore_above".to_string(), json!(ignore_above));
+            }
+
+            if self.ignore_malformed {
+                object.insert("ignore_malformed".to_string(), json!(true));
+            }
+
+            if !self.multi_fields.is_empty() {
+                let mut multi_fields_json = BTreeMap::new();
+                for (name, multi_field) in self.multi_fields.iter() {
+                    multi_fields_json.insert(name.to_string(), try!(multi_field.to_json()));
+                }
+
+                object.insert("fields".to_string(), json!(multi_fields_json));
+            }
+        }
+
+        Ok(json)
     }
 }
 
@@ -154,10 +314,180 @@ impl FieldMapping {
         }
     }
 
+    /// Re-reads the file-based resources used by the search analyzer, returning the reloaded analyzer
+    ///
+    /// Returns None if the search analyzer doesn't use any files. The index analyzer is
+    /// left alone as changing it would require reindexing.
+    pub fn reload_search_analyzer(&self) -> Result<Option<AnalyzerSpec>, ResourceError> {
+        match self.search_analyzer {
+            Some(ref search_analyzer) if search_analyzer.uses_files() => Ok(Some(try!(search_analyzer.reload()))),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn set_search_analyzer(&mut self, search_analyzer: AnalyzerSpec) {
+        self.search_analyzer = Some(search_analyzer);
+    }
+
+    /// Finds the parent relation of a child relation in a "join" field
+    pub fn get_join_parent(&self, child_relation: &str) -> Option<&str> {
+        for (parent_relation, child_relations) in self.join_relations.iter() {
+            if child_relations.iter().any(|relation| relation == child_relation) {
+                return Some(parent_relation);
+            }
+        }
+
+        None
+    }
+
+    /// Finds the child relations of a parent relation in a "join" field
+    pub fn get_join_children(&self, parent_relation: &str) -> Option<&Vec<String>> {
+        self.join_relations.get(parent_relation)
+    }
+
+    /// Interprets the value of a "join" field
+    ///
+    /// The value can either be the name of the relation (for documents at the top of the
+    /// hierarchy) or an object containing the relation "name" and the key of the "parent".
+    ///
+    /// Returns the relation name of the document along with a list of (parent relation, key)
+    /// pairs that need to be indexed into the hidden parent key fields.
+    pub fn process_join_value(&self, value: &serde_json::Value, key: &str) -> Result<(String, Vec<(String, String)>), FieldValueError> {
+        let (relation, parent_key) = match *value {
+            serde_json::Value::String(ref relation) => (relation.clone(), None),
+            serde_json::Value::Object(ref object) => {
+                let relation = match object.get("name") {
+                    Some(&serde_json::Value::String(ref relation)) => relation.clone(),
+                    _ => return Err(FieldValueError),
+                };
+
+                let parent_key = match object.get("parent") {
+                    Some(&serde_json::Value::String(ref parent_key)) => Some(parent_key.clone()),
+                    Some(&serde_json::Value::Number(ref parent_key)) => Some(parent_key.to_string()),
+                    Some(_) => return Err(FieldValueError),
+                    None => None,
+                };
+
+                (relation, parent_key)
+            }
+            _ => return Err(FieldValueError),
+        };
+
+        let mut parent_keys = Vec::new();
+
+        // Child documents must point at their parent
+        match (self.get_join_parent(&relation), parent_key) {
+            (Some(parent_relation), Some(parent_key)) => {
+                parent_keys.push((parent_relation.to_string(), parent_key));
+            }
+            (Some(_), None) | (None, Some(_)) => return Err(FieldValueError),
+            (None, None) => {
+                // Relation must be a parent
+                if !self.join_relations.contains_key(&relation) {
+                    return Err(FieldValueError);
+                }
+            }
+        }
+
+        // Parent documents are found by their own key
+        if self.join_relations.contains_key(&relation) {
+            parent_keys.push((relation.clone(), key.to_string()));
+        }
+
+        Ok((relation, parent_keys))
+    }
+
+    /// Replaces the nulls in a value with the field's "null_value"
+    ///
+    /// Returns None when there is nothing left to index. This happens for nulls, empty arrays
+    /// and arrays of nulls when the field doesn't have a "null_value", so all of these are
+    /// indexed the same way as a missing field.
+    pub fn replace_nulls(&self, value: &serde_json::Value) -> Option<serde_json::Value> {
+        match *value {
+            serde_json::Value::Null => self.null_value.clone(),
+            serde_json::Value::Array(ref array) => {
+                let items = array.iter().filter_map(|item| {
+                    match *item {
+                        serde_json::Value::Null => self.null_value.clone(),
+                        _ => Some(item.clone()),
+                    }
+                }).collect::<Vec<serde_json::Value>>();
+
+                if items.is_empty() {
+                    None
+                } else {
+                    Some(serde_json::Value::Array(items))
+                }
+            }
+            _ => Some(value.clone()),
+        }
+    }
+
+    /// Removes strings that are longer than the field's "ignore_above" setting from a value
+    ///
+    /// Returns None if there is nothing left to index.
+    pub fn remove_long_strings(&self, value: &serde_json::Value) -> Option<serde_json::Value> {
+        let ignore_above = match self.ignore_above {
+            Some(ignore_above) => ignore_above,
+            None => return Some(value.clone()),
+        };
+
+        match *value {
+            serde_json::Value::String(ref string) => {
+                if string.chars().count() > ignore_above {
+                    None
+                } else {
+                    Some(value.clone())
+                }
+            }
+            serde_json::Value::Array(ref array) => {
+                let items = array.iter().filter(|item| {
+                    match **item {
+                        serde_json::Value::String(ref string) => string.chars().count() <= ignore_above,
+                        _ => true,
+                    }
+                }).cloned().collect::<Vec<serde_json::Value>>();
+
+                if items.is_empty() {
+                    None
+                } else {
+                    Some(serde_json::Value::Array(items))
+                }
+            }
+            _ => Some(value.clone()),
+        }
+    }
+
+    /// Returns the type and flags of the field that holds this mapping's data in the store
+    pub fn store_field_info(&self) -> (schema::FieldType, FieldFlags) {
+        let field_type = match self.data_type {
+            FieldType::String => schema::FieldType::Text,
+            FieldType::Integer => schema::FieldType::I64,
+            FieldType::Boolean => schema::FieldType::Boolean,
+            FieldType::Date => schema::FieldType::DateTime,
+            FieldType::Join => schema::FieldType::Text,
+            FieldType::GeoPoint => schema::FieldType::Text,
+            FieldType::Ip => schema::FieldType::Text,
+            FieldType::Completion => schema::FieldType::Text,
+        };
+
+        let mut field_flags = FieldFlags::empty();
+
+        if self.is_indexed {
+            field_flags |= FIELD_INDEXED;
+        }
+
+        if self.is_stored || self.has_doc_values {
+            field_flags |= FIELD_STORED;
+        }
+
+        (field_type, field_flags)
+    }
+
     pub fn get_search_options(&self) -> FieldSearchOptions {
         FieldSearchOptions {
             analyzer: self.search_analyzer().cloned(),
-            .. FieldSearchOptions::default()
+            similarity_model: self.similarity.to_similarity_model(),
         }
     }
 
@@ -256,6 +586,48 @@ impl FieldMapping {
                     _ => Err(FieldValueError),
                 }
             }
+            FieldType::Join => {
+                match *value {
+                    serde_json::Value::String(ref relation) => {
+                        Ok(Some(vec![Token{term: Term::from_string(relation), position: 1}]))
+                    }
+                    serde_json::Value::Object(ref object) => {
+                        match object.get("name") {
+                            Some(&serde_json::Value::String(ref relation)) => {
+                                Ok(Some(vec![Token{term: Term::from_string(relation), position: 1}]))
+                            }
+                            _ => Err(FieldValueError),
+                        }
+                    }
+                    _ => Err(FieldValueError),
+                }
+            }
+            FieldType::GeoPoint => {
+                // Points are indexed as a geohash so they can be found with prefix queries
+                let tokens = try!(parse_geo_points(value)).iter().enumerate().map(|(i, point)| {
+                    Token{term: Term::from_string(&point.to_geohash(geo::MAX_PRECISION)), position: i as u32 + 1}
+                }).collect();
+
+                Ok(Some(tokens))
+            }
+            FieldType::Ip => {
+                // Addresses are indexed in a form that shares a prefix with every other address in the same CIDR block
+                let tokens = try!(parse_ips(value)).iter().enumerate().map(|(i, address)| {
+                    Token{term: Term::from_string(&ip::encode_ip(address)), position: i as u32 + 1}
+                }).collect();
+
+                Ok(Some(tokens))
+            }
+            FieldType::Completion => {
+                // Each input is indexed as a single term. Completions themselves are found with the completion index
+                let inputs = try!(completion::parse_value(value).map_err(|_| FieldValueError));
+                let tokens = inputs.iter().enumerate().map(|(i, input)| {
+                    let key = completion::analyse_key(&input.input, self.index_analyzer(), self.max_input_length);
+                    Token{term: Term::from_string(&key), position: i as u32 + 1}
+                }).collect();
+
+                Ok(Some(tokens))
+            }
         }
     }
 
@@ -323,7 +695,78 @@ impl FieldMapping {
                     _ => Err(FieldValueError)
                 }
             }
+            FieldType::Join | FieldType::Completion => Ok(None),
+            FieldType::GeoPoint => {
+                let points = try!(parse_geo_points(value)).iter().map(|point| format!("{},{}", point.lat, point.lon)).collect::<Vec<String>>();
+
+                Ok(Some(FieldValue::String(points.join(" "))))
+            }
+            FieldType::Ip => {
+                let addresses = try!(parse_ips(value)).iter().map(|address| address.to_string()).collect::<Vec<String>>();
+
+                Ok(Some(FieldValue::String(addresses.join(" "))))
+            }
+        }
+    }
+}
+
+
+/// Reads the value of a "geo_point" field, which may be a single point or an array of them
+fn parse_geo_points(value: &serde_json::Value) -> Result<Vec<GeoPoint>, FieldValueError> {
+    if let Some(point) = GeoPoint::from_json(value) {
+        return Ok(vec![point]);
+    }
+
+    match *value {
+        serde_json::Value::Array(ref array) => {
+            let mut points = Vec::new();
+
+            for item in array {
+                if *item == serde_json::Value::Null {
+                    continue;
+                }
+
+                match GeoPoint::from_json(item) {
+                    Some(point) => points.push(point),
+                    None => return Err(FieldValueError),
+                }
+            }
+
+            Ok(points)
         }
+        _ => Err(FieldValueError),
+    }
+}
+
+
+/// Reads the value of an "ip" field, which may be a single address or an array of them
+fn parse_ips(value: &serde_json::Value) -> Result<Vec<IpAddr>, FieldValueError> {
+    match *value {
+        serde_json::Value::String(ref string) => {
+            match ip::parse_ip(string) {
+                Some(address) => Ok(vec![address]),
+                None => Err(FieldValueError),
+            }
+        }
+        serde_json::Value::Array(ref array) => {
+            let mut addresses = Vec::new();
+
+            for item in array {
+                match *item {
+                    serde_json::Value::String(ref string) => {
+                        match ip::parse_ip(string) {
+                            Some(address) => addresses.push(address),
+                            None => return Err(FieldValueError),
+                        }
+                    }
+                    serde_json::Value::Null => {}
+                    _ => return Err(FieldValueError),
+                }
+            }
+
+            Ok(addresses)
+        }
+        _ => Err(FieldValueError),
     }
 }
 
@@ -331,6 +774,12 @@ impl FieldMapping {
 #[derive(Debug, PartialEq)]
 pub struct NestedMapping {
     pub properties: HashMap<String, MappingProperty>,
+
+    /// Set for "nested" fields, otherwise this is an "object" field
+    ///
+    /// The store has no way to index nested objects as separate documents yet so both
+    /// kinds are flattened into "parent.child" fields when indexing.
+    pub is_nested: bool,
 }
 
 
@@ -344,7 +793,7 @@ impl ToJson for NestedMapping {
         }
 
         Ok(json!({
-            "type": "nested",
+            "type": if self.is_nested { "nested" } else { "object" },
             "properties": properties_json,
         }))
     }
@@ -368,9 +817,134 @@ impl ToJson for MappingProperty {
 }
 
 
+/// Finds a field by its path, where the names of objects and their fields are separated by "."
+fn get_field_by_path<'a>(properties: &'a HashMap<String, MappingProperty>, path: &str) -> Option<&'a FieldMapping> {
+    if let Some(&MappingProperty::Field(ref field_mapping)) = properties.get(path) {
+        return Some(field_mapping);
+    }
+
+    let mut parts = path.splitn(2, '.');
+    match (parts.next(), parts.next()) {
+        (Some(name), Some(rest)) => {
+            match properties.get(name) {
+                Some(&MappingProperty::NestedMapping(ref mapping)) => get_field_by_path(&mapping.properties, rest),
+                Some(&MappingProperty::Field(ref field_mapping)) => field_mapping.multi_fields.get(rest),
+                None => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+
+fn join_path(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", prefix, name)
+    }
+}
+
+
+fn collect_field<'a>(path: String, field_mapping: &'a FieldMapping, fields: &mut Vec<(String, &'a FieldMapping)>) {
+    for (name, multi_field) in field_mapping.multi_fields.iter() {
+        collect_field(join_path(&path, name), multi_field, fields);
+    }
+
+    fields.push((path, field_mapping));
+}
+
+
+fn collect_fields<'a>(prefix: &str, properties: &'a HashMap<String, MappingProperty>, fields: &mut Vec<(String, &'a FieldMapping)>) {
+    for (name, property) in properties.iter() {
+        let path = join_path(prefix, name);
+
+        match *property {
+            MappingProperty::Field(ref field_mapping) => collect_field(path, field_mapping, fields),
+            MappingProperty::NestedMapping(ref mapping) => collect_fields(&path, &mapping.properties, fields),
+        }
+    }
+}
+
+
+fn visit_field_mut<F: FnMut(&str, &mut FieldMapping)>(path: &str, field_mapping: &mut FieldMapping, visitor: &mut F) {
+    visitor(path, field_mapping);
+
+    for (name, multi_field) in field_mapping.multi_fields.iter_mut() {
+        visit_field_mut(&join_path(path, name), multi_field, visitor);
+    }
+}
+
+
+fn visit_fields_mut<F: FnMut(&str, &mut FieldMapping)>(prefix: &str, properties: &mut HashMap<String, MappingProperty>, visitor: &mut F) {
+    for (name, property) in properties.iter_mut() {
+        let path = join_path(prefix, name);
+
+        match *property {
+            MappingProperty::Field(ref mut field_mapping) => visit_field_mut(&path, field_mapping, visitor),
+            MappingProperty::NestedMapping(ref mut mapping) => visit_fields_mut(&path, &mut mapping.properties, visitor),
+        }
+    }
+}
+
+
+/// What to do with fields in a document that aren't in the mapping
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DynamicMapping {
+    /// Add the field to the mapping, guessing its type from its value
+    True,
+
+    /// Ignore the field
+    False,
+
+    /// Reject the document
+    Strict,
+}
+
+
+impl Default for DynamicMapping {
+    fn default() -> DynamicMapping {
+        DynamicMapping::True
+    }
+}
+
+
+impl ToJson for DynamicMapping {
+    fn to_json(&self) -> Result<serde_json::Value, serde_json::Error> {
+        Ok(match *self {
+            DynamicMapping::True => json!(true),
+            DynamicMapping::False => json!(false),
+            DynamicMapping::Strict => json!("strict"),
+        })
+    }
+}
+
+
 #[derive(Debug, PartialEq)]
 pub struct Mapping {
     pub properties: HashMap<String, MappingProperty>,
+    pub dynamic: DynamicMapping,
+}
+
+
+impl Mapping {
+    /// Finds a field by its path (eg, "user.name" for the "name" field of the "user" object)
+    pub fn get_field(&self, path: &str) -> Option<&FieldMapping> {
+        get_field_by_path(&self.properties, path)
+    }
+
+    /// Returns every field in the mapping, including those inside objects and multi-fields,
+    /// along with their paths
+    pub fn fields(&self) -> Vec<(String, &FieldMapping)> {
+        let mut fields = Vec::new();
+        collect_fields("", &self.properties, &mut fields);
+        fields
+    }
+
+    /// Calls the visitor with every field in the mapping and its path
+    pub fn visit_fields_mut<F: FnMut(&str, &mut FieldMapping)>(&mut self, mut visitor: F) {
+        visit_fields_mut("", &mut self.properties, &mut visitor);
+    }
 }
 
 
@@ -380,10 +954,25 @@ impl ToJson for Mapping {
 
         // TODO: Exclude "_all" field
         for (name, prop) in self.properties.iter() {
+            // The _source field and the _ignored field are generated by the mapping builder
+            if name == "_source" || name == "_ignored" {
+                continue;
+            }
+
+            if let MappingProperty::Field(ref field_mapping) = *prop {
+                if field_mapping.is_join_parent_key {
+                    continue;
+                }
+            }
+
             properties_json.insert(name.to_string(), try!(prop.to_json()));
         }
 
         Ok(json!({
+            "_source": {
+                "enabled": self.properties.contains_key("_source"),
+            },
+            "dynamic": try!(self.dynamic.to_json()),
             "properties": properties_json,
         }))
     }
@@ -2,7 +2,7 @@ use std::collections::{HashMap, BTreeSet};
 
 use serde_json;
 
-use mapping::FieldType;
+use mapping::{FieldType, DynamicMapping};
 use mapping::build::{MappingBuilder, MappingPropertyBuilder, FieldMappingBuilder, NestedMappingBuilder};
 
 
@@ -29,6 +29,35 @@ pub enum FieldMappingParseError {
     // "boost" setting
     BoostOnlyAllowedOnIndexedFields,
     BoostMustBePositive,
+
+    // "similarity" setting
+    SimilarityOnlyAllowedOnIndexedFields,
+
+    // "relations" setting
+    RelationsOnlyAllowedOnJoinType,
+    ExpectedArrayOrString,
+
+    // "doc_values" setting
+    DocValuesNotAllowedOnAnalyzedFields,
+
+    // "fields" setting
+    MultiFieldsNotAllowedOnJoinType,
+    MultiFieldParseError(String, Box<FieldMappingParseError>),
+
+    // "null_value" setting
+    NullValueNotAllowedOnJoinType,
+    ExpectedScalar,
+
+    // "ignore_above" setting
+    IgnoreAboveOnlyAllowedOnNotAnalyzedStringFields,
+    ExpectedPositiveInteger,
+
+    // "ignore_malformed" setting
+    IgnoreMalformedNotAllowedOnFieldType,
+
+    // "contexts" and "max_input_length" settings
+    CompletionSettingsOnlyAllowedOnCompletionType,
+    UnrecognisedContextType(String),
 }
 
 
@@ -42,6 +71,7 @@ pub enum MappingParseError {
     UnrecognisedKeys(Vec<String>),
     FieldMappingParseError(String, FieldMappingParseError),
     NestedMappingParseError(String, Box<MappingParseError>),
+    UnrecognisedDynamicSetting(String),
 }
 
 
@@ -75,10 +105,14 @@ fn parse_float(json: &serde_json::Value) -> Result<f64, FieldMappingParseError>
 
 fn parse_field_type(field_type_str: &str) -> Result<FieldType, FieldMappingParseError> {
     match field_type_str {
-        "string" => Ok(FieldType::String),
-        "integer" => Ok(FieldType::Integer),
+        "string" | "text" | "keyword" => Ok(FieldType::String),
+        "integer" | "long" | "short" | "byte" => Ok(FieldType::Integer),
         "boolean" => Ok(FieldType::Boolean),
         "date" => Ok(FieldType::Date),
+        "join" => Ok(FieldType::Join),
+        "geo_point" => Ok(FieldType::GeoPoint),
+        "ip" => Ok(FieldType::Ip),
+        "completion" => Ok(FieldType::Completion),
         _ => Err(FieldMappingParseError::UnrecognisedFieldType(field_type_str.to_string())),
     }
 }
@@ -94,11 +128,21 @@ fn parse_field(json: &serde_json::Value) -> Result<FieldMappingBuilder, FieldMap
         "type".to_string(),
         "index".to_string(),
         "store".to_string(),
+        "doc_values".to_string(),
         "analyzer".to_string(),
         "index_analyzer".to_string(),
         "search_analyzer".to_string(),
         "boost".to_string(),
         "include_in_all".to_string(),
+        "relations".to_string(),
+        "fields".to_string(),
+        "copy_to".to_string(),
+        "null_value".to_string(),
+        "ignore_above".to_string(),
+        "ignore_malformed".to_string(),
+        "similarity".to_string(),
+        "contexts".to_string(),
+        "max_input_length".to_string(),
     ];
     let unrecognised_keys = provided_keys.difference(&allowed_keys).cloned().collect::<Vec<String>>();
 
@@ -111,13 +155,21 @@ fn parse_field(json: &serde_json::Value) -> Result<FieldMappingBuilder, FieldMap
     let field_type_str = try!(field_type_json.as_str().ok_or(FieldMappingParseError::ExpectedString));
     mapping_builder.field_type = try!(parse_field_type(field_type_str));
 
-    // Non-string fields cannot be analyzed
-    if mapping_builder.field_type != FieldType::String {
+    // Only string and completion fields can be analyzed. Keyword fields are strings that are indexed as a single term
+    let allows_analyzers = mapping_builder.field_type == FieldType::String || mapping_builder.field_type == FieldType::Completion;
+    if !allows_analyzers || field_type_str == "keyword" {
         mapping_builder.is_analyzed = false;
     }
 
     // "index" setting
-    if let Some(index_json) = field_object.get("index") {
+    // This can either be a boolean or one of the older "no", "not_analyzed" and "analyzed" values
+    if let Some(&serde_json::Value::Bool(is_indexed)) = field_object.get("index") {
+        mapping_builder.is_indexed = is_indexed;
+
+        if !is_indexed {
+            mapping_builder.is_analyzed = false;
+        }
+    } else if let Some(index_json) = field_object.get("index") {
         let index_str = try!(index_json.as_str().ok_or(FieldMappingParseError::ExpectedString));
 
         match index_str {
@@ -149,12 +201,22 @@ fn parse_field(json: &serde_json::Value) -> Result<FieldMappingBuilder, FieldMap
         mapping_builder.is_stored = try!(parse_boolean(store_json));
     }
 
+    // "doc_values" setting
+    if let Some(doc_values_json) = field_object.get("doc_values") {
+        mapping_builder.has_doc_values = try!(parse_boolean(doc_values_json));
+
+        // Analyzed fields have many terms per value so there isn't a single value to keep
+        if mapping_builder.has_doc_values && mapping_builder.is_analyzed {
+            return Err(FieldMappingParseError::DocValuesNotAllowedOnAnalyzedFields);
+        }
+    }
+
     // Analyzers
     if let Some(analyzer_json) = field_object.get("analyzer") {
         let analyzer_str = try!(analyzer_json.as_str().ok_or(FieldMappingParseError::ExpectedString));
         mapping_builder.base_analyzer = Some(analyzer_str.to_string());
 
-        if mapping_builder.field_type != FieldType::String {
+        if !allows_analyzers {
             return Err(FieldMappingParseError::AnalyzersOnlyAllowedOnStringType);
         }
 
@@ -167,7 +229,7 @@ fn parse_field(json: &serde_json::Value) -> Result<FieldMappingBuilder, FieldMap
         let index_analyzer_str = try!(index_analyzer_json.as_str().ok_or(FieldMappingParseError::ExpectedString));
         mapping_builder.index_analyzer = Some(index_analyzer_str.to_string());
 
-        if mapping_builder.field_type != FieldType::String {
+        if !allows_analyzers {
             return Err(FieldMappingParseError::AnalyzersOnlyAllowedOnStringType);
         }
 
@@ -180,7 +242,7 @@ fn parse_field(json: &serde_json::Value) -> Result<FieldMappingBuilder, FieldMap
         let search_analyzer_str = try!(search_analyzer_json.as_str().ok_or(FieldMappingParseError::ExpectedString));
         mapping_builder.search_analyzer = Some(search_analyzer_str.to_string());
 
-        if mapping_builder.field_type != FieldType::String {
+        if !allows_analyzers {
             return Err(FieldMappingParseError::AnalyzersOnlyAllowedOnStringType);
         }
 
@@ -203,16 +265,179 @@ fn parse_field(json: &serde_json::Value) -> Result<FieldMappingBuilder, FieldMap
         }
     }
 
+    // "similarity" setting
+    // The name is checked when the mapping is built, as it may refer to a similarity in the index settings
+    if let Some(similarity_json) = field_object.get("similarity") {
+        let similarity_str = try!(similarity_json.as_str().ok_or(FieldMappingParseError::ExpectedString));
+        mapping_builder.similarity = Some(similarity_str.to_string());
+
+        if !mapping_builder.is_indexed {
+            return Err(FieldMappingParseError::SimilarityOnlyAllowedOnIndexedFields);
+        }
+    }
+
     // "include_in_all" setting
     if let Some(include_in_all_json) = field_object.get("include_in_all") {
         let include_in_all = try!(parse_boolean(include_in_all_json));
         mapping_builder.is_in_all = include_in_all;
     }
 
+    // "relations" setting
+    if let Some(relations_json) = field_object.get("relations") {
+        if mapping_builder.field_type != FieldType::Join {
+            return Err(FieldMappingParseError::RelationsOnlyAllowedOnJoinType);
+        }
+
+        let relations_object = try!(relations_json.as_object().ok_or(FieldMappingParseError::ExpectedObject));
+
+        for (parent_relation, child_relations_json) in relations_object {
+            let child_relations = match *child_relations_json {
+                serde_json::Value::String(ref child_relation) => vec![child_relation.clone()],
+                serde_json::Value::Array(ref array) => {
+                    let mut child_relations = Vec::new();
+
+                    for child_relation_json in array {
+                        let child_relation = try!(child_relation_json.as_str().ok_or(FieldMappingParseError::ExpectedString));
+                        child_relations.push(child_relation.to_string());
+                    }
+
+                    child_relations
+                }
+                _ => return Err(FieldMappingParseError::ExpectedArrayOrString),
+            };
+
+            mapping_builder.join_relations.insert(parent_relation.clone(), child_relations);
+        }
+    } else if mapping_builder.field_type == FieldType::Join {
+        return Err(FieldMappingParseError::ExpectedKey("relations".to_string()));
+    }
+
+    // Join fields are only used for matching up relations, geo points and ips aren't text and
+    // completion inputs are only used for completions
+    match mapping_builder.field_type {
+        FieldType::Join | FieldType::GeoPoint | FieldType::Ip | FieldType::Completion => mapping_builder.is_in_all = false,
+        _ => {}
+    }
+
+    // "contexts" setting
+    // Only category contexts are supported
+    if let Some(contexts_json) = field_object.get("contexts") {
+        if mapping_builder.field_type != FieldType::Completion {
+            return Err(FieldMappingParseError::CompletionSettingsOnlyAllowedOnCompletionType);
+        }
+
+        let contexts_array = try!(contexts_json.as_array().ok_or(FieldMappingParseError::ExpectedArrayOrString));
+        for context_json in contexts_array {
+            let context_object = try!(context_json.as_object().ok_or(FieldMappingParseError::ExpectedObject));
+
+            let name = try!(try!(context_object.get("name").ok_or(FieldMappingParseError::ExpectedKey("name".to_string()))).as_str().ok_or(FieldMappingParseError::ExpectedString));
+            let context_type = try!(try!(context_object.get("type").ok_or(FieldMappingParseError::ExpectedKey("type".to_string()))).as_str().ok_or(FieldMappingParseError::ExpectedString));
+            if context_type != "category" {
+                return Err(FieldMappingParseError::UnrecognisedContextType(context_type.to_string()));
+            }
+
+            mapping_builder.completion_contexts.push(name.to_string());
+        }
+    }
+
+    // "max_input_length" setting
+    if let Some(max_input_length_json) = field_object.get("max_input_length") {
+        if mapping_builder.field_type != FieldType::Completion {
+            return Err(FieldMappingParseError::CompletionSettingsOnlyAllowedOnCompletionType);
+        }
+
+        let max_input_length = try!(max_input_length_json.as_u64().ok_or(FieldMappingParseError::ExpectedPositiveInteger));
+        mapping_builder.max_input_length = max_input_length as usize;
+    }
+
+    // "copy_to" setting
+    if let Some(copy_to_json) = field_object.get("copy_to") {
+        match *copy_to_json {
+            serde_json::Value::String(ref field_name) => mapping_builder.copy_to.push(field_name.clone()),
+            serde_json::Value::Array(ref array) => {
+                for field_name_json in array {
+                    let field_name = try!(field_name_json.as_str().ok_or(FieldMappingParseError::ExpectedString));
+                    mapping_builder.copy_to.push(field_name.to_string());
+                }
+            }
+            _ => return Err(FieldMappingParseError::ExpectedArrayOrString),
+        }
+    }
+
+    // "null_value" setting
+    if let Some(null_value_json) = field_object.get("null_value") {
+        if mapping_builder.field_type == FieldType::Join {
+            return Err(FieldMappingParseError::NullValueNotAllowedOnJoinType);
+        }
+
+        match *null_value_json {
+            // A null "null_value" is the same as not setting it
+            serde_json::Value::Null => {}
+            serde_json::Value::String(_) | serde_json::Value::Number(_) | serde_json::Value::Bool(_) => {
+                mapping_builder.null_value = Some(null_value_json.clone());
+            }
+            _ => return Err(FieldMappingParseError::ExpectedScalar),
+        }
+    }
+
+    // "ignore_above" setting
+    if let Some(ignore_above_json) = field_object.get("ignore_above") {
+        if mapping_builder.field_type != FieldType::String || mapping_builder.is_analyzed {
+            return Err(FieldMappingParseError::IgnoreAboveOnlyAllowedOnNotAnalyzedStringFields);
+        }
+
+        let ignore_above = try!(ignore_above_json.as_u64().ok_or(FieldMappingParseError::ExpectedPositiveInteger));
+        mapping_builder.ignore_above = Some(ignore_above as usize);
+    }
+
+    // "ignore_malformed" setting
+    if let Some(ignore_malformed_json) = field_object.get("ignore_malformed") {
+        // Only values that need parsing can be malformed
+        match mapping_builder.field_type {
+            FieldType::Integer | FieldType::Date | FieldType::GeoPoint | FieldType::Ip => {}
+            _ => return Err(FieldMappingParseError::IgnoreMalformedNotAllowedOnFieldType),
+        }
+
+        mapping_builder.ignore_malformed = try!(parse_boolean(ignore_malformed_json));
+    }
+
+    // "fields" setting
+    if let Some(multi_fields_json) = field_object.get("fields") {
+        if mapping_builder.field_type == FieldType::Join {
+            return Err(FieldMappingParseError::MultiFieldsNotAllowedOnJoinType);
+        }
+
+        let multi_fields_object = try!(multi_fields_json.as_object().ok_or(FieldMappingParseError::ExpectedObject));
+
+        for (name, multi_field_json) in multi_fields_object {
+            let mut multi_field = match parse_field(multi_field_json) {
+                Ok(multi_field) => multi_field,
+                Err(e) => return Err(FieldMappingParseError::MultiFieldParseError(name.clone(), Box::new(e))),
+            };
+
+            // Values are only copied into the _all field once
+            multi_field.is_in_all = false;
+
+            mapping_builder.multi_fields.insert(name.clone(), multi_field);
+        }
+    }
+
     Ok(mapping_builder)
 }
 
 
+/// Checks if a property is an "object" or "nested" mapping rather than a field
+///
+/// Objects don't need a type, they can be recognised by their "properties".
+fn is_object_mapping(prop_object: &serde_json::Map<String, serde_json::Value>) -> bool {
+    match prop_object.get("type") {
+        Some(&serde_json::Value::String(ref prop_type)) => prop_type == "object" || prop_type == "nested",
+        Some(_) => false,
+        None => prop_object.contains_key("properties"),
+    }
+}
+
+
 fn parse_nested_mapping(json: &serde_json::Value) -> Result<NestedMappingBuilder, MappingParseError> {
     let mapping_object = try!(json.as_object().ok_or(MappingParseError::ExpectedObject));
 
@@ -228,6 +453,8 @@ fn parse_nested_mapping(json: &serde_json::Value) -> Result<NestedMappingBuilder
         return Err(MappingParseError::UnrecognisedKeys(unrecognised_keys));
     }
 
+    let is_nested = mapping_object.get("type") == Some(&serde_json::Value::String("nested".to_string()));
+
     // Parse properties
     let properties_json = try!(mapping_object.get("properties").ok_or(MappingParseError::ExpectedKey("properties".to_string())));
     let properties_object = try!(properties_json.as_object().ok_or(MappingParseError::ExpectedObject));
@@ -236,8 +463,8 @@ fn parse_nested_mapping(json: &serde_json::Value) -> Result<NestedMappingBuilder
     for (prop_name, prop_json) in properties_object {
         let prop_object = try!(prop_json.as_object().ok_or(MappingParseError::FieldMappingParseError(prop_name.to_string(), FieldMappingParseError::ExpectedObject)));
 
-        if prop_object.get("type") == Some(&serde_json::Value::String("nested".to_string())) {
-            // Property is a nested mapping
+        if is_object_mapping(prop_object) {
+            // Property is an object or nested mapping
             match parse_nested_mapping(prop_json) {
                 Ok(mapping) => {
                     properties.insert(prop_name.to_string(), MappingPropertyBuilder::NestedMapping(Box::new(mapping)));
@@ -261,6 +488,7 @@ fn parse_nested_mapping(json: &serde_json::Value) -> Result<NestedMappingBuilder
 
     Ok(NestedMappingBuilder {
         properties: properties,
+        is_nested: is_nested,
     })
 }
 
@@ -272,6 +500,8 @@ pub fn parse(json: &serde_json::Value) -> Result<MappingBuilder, MappingParseErr
     let provided_keys = mapping_object.keys().cloned().collect::<BTreeSet<String>>();
     let allowed_keys = btreeset![
         "properties".to_string(),
+        "_source".to_string(),
+        "dynamic".to_string(),
     ];
     let unrecognised_keys = provided_keys.difference(&allowed_keys).cloned().collect::<Vec<String>>();
 
@@ -279,6 +509,32 @@ pub fn parse(json: &serde_json::Value) -> Result<MappingBuilder, MappingParseErr
         return Err(MappingParseError::UnrecognisedKeys(unrecognised_keys));
     }
 
+    // Parse _source settings
+    let mut source_enabled = true;
+    if let Some(source_json) = mapping_object.get("_source") {
+        let source_object = try!(source_json.as_object().ok_or(MappingParseError::ExpectedObject));
+
+        if let Some(enabled_json) = source_object.get("enabled") {
+            source_enabled = try!(enabled_json.as_bool().ok_or(MappingParseError::ExpectedBoolean));
+        }
+    }
+
+    // Parse dynamic setting
+    let dynamic = match mapping_object.get("dynamic") {
+        Some(&serde_json::Value::Bool(true)) => DynamicMapping::True,
+        Some(&serde_json::Value::Bool(false)) => DynamicMapping::False,
+        Some(&serde_json::Value::String(ref dynamic)) => {
+            match dynamic.as_ref() {
+                "true" => DynamicMapping::True,
+                "false" => DynamicMapping::False,
+                "strict" => DynamicMapping::Strict,
+                _ => return Err(MappingParseError::UnrecognisedDynamicSetting(dynamic.clone())),
+            }
+        }
+        Some(_) => return Err(MappingParseError::ExpectedString),
+        None => DynamicMapping::default(),
+    };
+
     // Parse properties
     let properties_json = try!(mapping_object.get("properties").ok_or(MappingParseError::ExpectedKey("properties".to_string())));
     let properties_object = try!(properties_json.as_object().ok_or(MappingParseError::ExpectedObject));
@@ -287,8 +543,8 @@ pub fn parse(json: &serde_json::Value) -> Result<MappingBuilder, MappingParseErr
     for (prop_name, prop_json) in properties_object {
         let prop_object = try!(prop_json.as_object().ok_or(MappingParseError::FieldMappingParseError(prop_name.to_string(), FieldMappingParseError::ExpectedObject)));
 
-        if prop_object.get("type") == Some(&serde_json::Value::String("nested".to_string())) {
-            // Property is a nested mapping
+        if is_object_mapping(prop_object) {
+            // Property is an object or nested mapping
             match parse_nested_mapping(prop_json) {
                 Ok(mapping) => {
                     properties.insert(prop_name.to_string(), MappingPropertyBuilder::NestedMapping(Box::new(mapping)));
@@ -312,6 +568,8 @@ pub fn parse(json: &serde_json::Value) -> Result<MappingBuilder, MappingParseErr
 
     Ok(MappingBuilder {
         properties: properties,
+        source_enabled: source_enabled,
+        dynamic: dynamic,
     })
 }
 
@@ -320,7 +578,7 @@ pub fn parse(json: &serde_json::Value) -> Result<MappingBuilder, MappingParseErr
 mod tests {
     use serde_json;
 
-    use mapping::FieldType;
+    use mapping::{FieldType, DynamicMapping};
     use mapping::build::{FieldMappingBuilder, NestedMappingBuilder, MappingPropertyBuilder, MappingBuilder};
 
     use super::{MappingParseError, FieldMappingParseError, parse, parse_field};
@@ -345,7 +603,8 @@ mod tests {
                         ..FieldMappingBuilder::default()
                     }
                 )
-            }
+            },
+            ..MappingBuilder::default()
         }));
     }
 
@@ -377,10 +636,12 @@ mod tests {
                                     ..FieldMappingBuilder::default()
                                 }
                             )
-                        }
+                        },
+                        is_nested: true,
                     }
                 ))
-            }
+            },
+            ..MappingBuilder::default()
         }));
     }
 
@@ -420,16 +681,336 @@ mod tests {
                                                 ..FieldMappingBuilder::default()
                                             }
                                         )
-                                    }
+                                    },
+                                    is_nested: true,
                                 }
                             ))
-                        }
+                        },
+                        is_nested: true,
                     }
                 ))
+            },
+            ..MappingBuilder::default()
+        }));
+    }
+
+    #[test]
+    fn test_parse_multi_fields() {
+        let mapping = parse(&serde_json::from_str("
+        {
+            \"properties\": {
+                \"title\": {
+                    \"type\": \"text\",
+                    \"fields\": {
+                        \"keyword\": {
+                            \"type\": \"keyword\"
+                        },
+                        \"autocomplete\": {
+                            \"type\": \"text\",
+                            \"analyzer\": \"autocomplete\"
+                        }
+                    }
+                }
+            }
+        }
+        ").unwrap());
+
+        assert_eq!(mapping, Ok(MappingBuilder {
+            properties: hashmap! {
+                "title".to_string() => MappingPropertyBuilder::Field(
+                    FieldMappingBuilder {
+                        multi_fields: hashmap! {
+                            "keyword".to_string() => FieldMappingBuilder {
+                                is_analyzed: false,
+                                is_in_all: false,
+                                ..FieldMappingBuilder::default()
+                            },
+                            "autocomplete".to_string() => FieldMappingBuilder {
+                                base_analyzer: Some("autocomplete".to_string()),
+                                is_in_all: false,
+                                ..FieldMappingBuilder::default()
+                            }
+                        },
+                        ..FieldMappingBuilder::default()
+                    }
+                )
+            },
+            ..MappingBuilder::default()
+        }));
+    }
+
+    #[test]
+    fn test_parse_multi_field_error() {
+        let mapping = parse(&serde_json::from_str("
+        {
+            \"properties\": {
+                \"title\": {
+                    \"type\": \"string\",
+                    \"fields\": {
+                        \"raw\": {
+                            \"type\": \"foo\"
+                        }
+                    }
+                }
+            }
+        }
+        ").unwrap());
+
+        assert_eq!(mapping, Err(MappingParseError::FieldMappingParseError(
+            "title".to_string(),
+            FieldMappingParseError::MultiFieldParseError(
+                "raw".to_string(),
+                Box::new(FieldMappingParseError::UnrecognisedFieldType("foo".to_string()))
+            )
+        )));
+    }
+
+    #[test]
+    fn test_parse_copy_to() {
+        let mapping = parse_field(&serde_json::from_str("
+        {
+            \"type\": \"string\",
+            \"copy_to\": \"all_text\"
+        }
+        ").unwrap());
+
+        assert_eq!(mapping, Ok(FieldMappingBuilder {
+            copy_to: vec!["all_text".to_string()],
+            ..FieldMappingBuilder::default()
+        }));
+
+        let mapping = parse_field(&serde_json::from_str("
+        {
+            \"type\": \"string\",
+            \"copy_to\": [\"all_text\", \"title_and_body\"]
+        }
+        ").unwrap());
+
+        assert_eq!(mapping, Ok(FieldMappingBuilder {
+            copy_to: vec!["all_text".to_string(), "title_and_body".to_string()],
+            ..FieldMappingBuilder::default()
+        }));
+    }
+
+    #[test]
+    fn test_parse_doc_values() {
+        let mapping = parse_field(&serde_json::from_str("
+        {
+            \"type\": \"keyword\",
+            \"doc_values\": true
+        }
+        ").unwrap());
+
+        assert_eq!(mapping, Ok(FieldMappingBuilder {
+            is_analyzed: false,
+            has_doc_values: true,
+            ..FieldMappingBuilder::default()
+        }));
+    }
+
+    #[test]
+    fn test_parse_doc_values_on_analyzed_field() {
+        let mapping = parse_field(&serde_json::from_str("
+        {
+            \"type\": \"string\",
+            \"doc_values\": true
+        }
+        ").unwrap());
+
+        assert_eq!(mapping, Err(FieldMappingParseError::DocValuesNotAllowedOnAnalyzedFields));
+    }
+
+    #[test]
+    fn test_parse_ignore_above() {
+        let mapping = parse_field(&serde_json::from_str("
+        {
+            \"type\": \"keyword\",
+            \"ignore_above\": 256
+        }
+        ").unwrap());
+
+        assert_eq!(mapping, Ok(FieldMappingBuilder {
+            is_analyzed: false,
+            ignore_above: Some(256),
+            ..FieldMappingBuilder::default()
+        }));
+    }
+
+    #[test]
+    fn test_parse_ignore_above_on_analyzed_field() {
+        let mapping = parse_field(&serde_json::from_str("
+        {
+            \"type\": \"string\",
+            \"ignore_above\": 256
+        }
+        ").unwrap());
+
+        assert_eq!(mapping, Err(FieldMappingParseError::IgnoreAboveOnlyAllowedOnNotAnalyzedStringFields));
+    }
+
+    #[test]
+    fn test_parse_ignore_malformed() {
+        let mapping = parse_field(&serde_json::from_str("
+        {
+            \"type\": \"date\",
+            \"ignore_malformed\": true
+        }
+        ").unwrap());
+
+        assert_eq!(mapping, Ok(FieldMappingBuilder {
+            field_type: FieldType::Date,
+            is_analyzed: false,
+            ignore_malformed: true,
+            ..FieldMappingBuilder::default()
+        }));
+    }
+
+    #[test]
+    fn test_parse_ignore_malformed_on_string_field() {
+        let mapping = parse_field(&serde_json::from_str("
+        {
+            \"type\": \"keyword\",
+            \"ignore_malformed\": true
+        }
+        ").unwrap());
+
+        assert_eq!(mapping, Err(FieldMappingParseError::IgnoreMalformedNotAllowedOnFieldType));
+    }
+
+    #[test]
+    fn test_parse_null_value() {
+        let mapping = parse_field(&serde_json::from_str("
+        {
+            \"type\": \"keyword\",
+            \"null_value\": \"NULL\"
+        }
+        ").unwrap());
+
+        assert_eq!(mapping, Ok(FieldMappingBuilder {
+            is_analyzed: false,
+            null_value: Some(json!("NULL")),
+            ..FieldMappingBuilder::default()
+        }));
+    }
+
+    #[test]
+    fn test_parse_null_value_object() {
+        let mapping = parse_field(&serde_json::from_str("
+        {
+            \"type\": \"keyword\",
+            \"null_value\": {\"foo\": \"bar\"}
+        }
+        ").unwrap());
+
+        assert_eq!(mapping, Err(FieldMappingParseError::ExpectedScalar));
+    }
+
+    #[test]
+    fn test_parse_object() {
+        let mapping = parse(&serde_json::from_str("
+        {
+            \"properties\": {
+                \"user\": {
+                    \"type\": \"object\",
+                    \"properties\": {
+                        \"name\": {
+                            \"type\": \"string\"
+                        }
+                    }
+                },
+                \"address\": {
+                    \"properties\": {
+                        \"city\": {
+                            \"type\": \"string\"
+                        }
+                    }
+                }
             }
+        }
+        ").unwrap());
+
+        assert_eq!(mapping, Ok(MappingBuilder {
+            properties: hashmap! {
+                "user".to_string() => MappingPropertyBuilder::NestedMapping(Box::new(
+                    NestedMappingBuilder {
+                        properties: hashmap! {
+                            "name".to_string() => MappingPropertyBuilder::Field(FieldMappingBuilder::default())
+                        },
+                        is_nested: false,
+                    }
+                )),
+                "address".to_string() => MappingPropertyBuilder::NestedMapping(Box::new(
+                    NestedMappingBuilder {
+                        properties: hashmap! {
+                            "city".to_string() => MappingPropertyBuilder::Field(FieldMappingBuilder::default())
+                        },
+                        is_nested: false,
+                    }
+                ))
+            },
+            ..MappingBuilder::default()
+        }));
+    }
+
+    #[test]
+    fn test_parse_source_disabled() {
+        let mapping = parse(&serde_json::from_str("
+        {
+            \"_source\": {
+                \"enabled\": false
+            },
+            \"properties\": {}
+        }
+        ").unwrap());
+
+        assert_eq!(mapping, Ok(MappingBuilder {
+            properties: hashmap! {},
+            source_enabled: false,
+            ..MappingBuilder::default()
+        }));
+    }
+
+    #[test]
+    fn test_parse_dynamic() {
+        let mapping = parse(&serde_json::from_str("
+        {
+            \"dynamic\": \"strict\",
+            \"properties\": {}
+        }
+        ").unwrap());
+
+        assert_eq!(mapping, Ok(MappingBuilder {
+            properties: hashmap! {},
+            dynamic: DynamicMapping::Strict,
+            ..MappingBuilder::default()
+        }));
+
+        let mapping = parse(&serde_json::from_str("
+        {
+            \"dynamic\": false,
+            \"properties\": {}
+        }
+        ").unwrap());
+
+        assert_eq!(mapping, Ok(MappingBuilder {
+            properties: hashmap! {},
+            dynamic: DynamicMapping::False,
+            ..MappingBuilder::default()
         }));
     }
 
+    #[test]
+    fn test_parse_dynamic_unrecognised() {
+        let mapping = parse(&serde_json::from_str("
+        {
+            \"dynamic\": \"foo\",
+            \"properties\": {}
+        }
+        ").unwrap());
+
+        assert_eq!(mapping, Err(MappingParseError::UnrecognisedDynamicSetting("foo".to_string())));
+    }
+
     #[test]
     fn test_parse_field_error() {
         let mapping = parse(&serde_json::from_str("
@@ -487,6 +1068,7 @@ mod tests {
 
         assert_eq!(mapping, Ok(MappingBuilder {
             properties: hashmap! {},
+            ..MappingBuilder::default()
         }));
     }
 
@@ -586,6 +1168,85 @@ mod tests {
             is_analyzed: false,
             ..FieldMappingBuilder::default()
         }));
+
+        // Text
+        let mapping = parse_field(&serde_json::from_str("
+        {
+            \"type\": \"text\"
+        }
+        ").unwrap());
+
+        assert_eq!(mapping, Ok(FieldMappingBuilder {
+            field_type: FieldType::String,
+            is_analyzed: true,
+            ..FieldMappingBuilder::default()
+        }));
+
+        // Keyword
+        let mapping = parse_field(&serde_json::from_str("
+        {
+            \"type\": \"keyword\"
+        }
+        ").unwrap());
+
+        assert_eq!(mapping, Ok(FieldMappingBuilder {
+            field_type: FieldType::String,
+            is_analyzed: false,
+            ..FieldMappingBuilder::default()
+        }));
+
+        // Long
+        let mapping = parse_field(&serde_json::from_str("
+        {
+            \"type\": \"long\"
+        }
+        ").unwrap());
+
+        assert_eq!(mapping, Ok(FieldMappingBuilder {
+            field_type: FieldType::Integer,
+            is_analyzed: false,
+            ..FieldMappingBuilder::default()
+        }));
+
+        // Geo point
+        let mapping = parse_field(&serde_json::from_str("
+        {
+            \"type\": \"geo_point\"
+        }
+        ").unwrap());
+
+        assert_eq!(mapping, Ok(FieldMappingBuilder {
+            field_type: FieldType::GeoPoint,
+            is_analyzed: false,
+            is_in_all: false,
+            ..FieldMappingBuilder::default()
+        }));
+
+        // IP
+        let mapping = parse_field(&serde_json::from_str("
+        {
+            \"type\": \"ip\"
+        }
+        ").unwrap());
+
+        assert_eq!(mapping, Ok(FieldMappingBuilder {
+            field_type: FieldType::Ip,
+            is_analyzed: false,
+            is_in_all: false,
+            ..FieldMappingBuilder::default()
+        }));
+    }
+
+    #[test]
+    fn test_parse_keyword_analyzer() {
+        let mapping = parse_field(&serde_json::from_str("
+        {
+            \"type\": \"keyword\",
+            \"analyzer\": \"standard\"
+        }
+        ").unwrap());
+
+        assert_eq!(mapping, Err(FieldMappingParseError::AnalyzersOnlyAllowedOnAnalyzedFields));
     }
 
     #[test]
@@ -724,6 +1385,40 @@ mod tests {
         assert_eq!(mapping, Err(FieldMappingParseError::UnrecognisedIndexSetting("foo".to_string())));
     }
 
+    #[test]
+    fn test_parse_index_false() {
+        let mapping = parse_field(&serde_json::from_str("
+        {
+            \"type\": \"string\",
+            \"index\": false
+        }
+        ").unwrap());
+
+        assert_eq!(mapping, Ok(FieldMappingBuilder {
+            field_type: FieldType::String,
+            is_indexed: false,
+            is_analyzed: false,
+            ..FieldMappingBuilder::default()
+        }));
+    }
+
+    #[test]
+    fn test_parse_index_true() {
+        let mapping = parse_field(&serde_json::from_str("
+        {
+            \"type\": \"keyword\",
+            \"index\": true
+        }
+        ").unwrap());
+
+        assert_eq!(mapping, Ok(FieldMappingBuilder {
+            field_type: FieldType::String,
+            is_indexed: true,
+            is_analyzed: false,
+            ..FieldMappingBuilder::default()
+        }));
+    }
+
     #[test]
     fn test_parse_store_default() {
         let mapping = parse_field(&serde_json::from_str("
@@ -1014,4 +1709,108 @@ mod tests {
             ..FieldMappingBuilder::default()
         }));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_parse_join() {
+        let mapping = parse_field(&serde_json::from_str("
+        {
+            \"type\": \"join\",
+            \"relations\": {
+                \"question\": [\"answer\", \"comment\"],
+                \"answer\": \"vote\"
+            }
+        }
+        ").unwrap());
+
+        assert_eq!(mapping, Ok(FieldMappingBuilder {
+            field_type: FieldType::Join,
+            is_analyzed: false,
+            is_in_all: false,
+            join_relations: hashmap! {
+                "question".to_string() => vec!["answer".to_string(), "comment".to_string()],
+                "answer".to_string() => vec!["vote".to_string()]
+            },
+            ..FieldMappingBuilder::default()
+        }));
+    }
+
+    #[test]
+    fn test_parse_join_without_relations() {
+        let mapping = parse_field(&serde_json::from_str("
+        {
+            \"type\": \"join\"
+        }
+        ").unwrap());
+
+        assert_eq!(mapping, Err(FieldMappingParseError::ExpectedKey("relations".to_string())));
+    }
+
+    #[test]
+    fn test_parse_relations_on_non_join_field() {
+        let mapping = parse_field(&serde_json::from_str("
+        {
+            \"type\": \"string\",
+            \"relations\": {
+                \"question\": \"answer\"
+            }
+        }
+        ").unwrap());
+
+        assert_eq!(mapping, Err(FieldMappingParseError::RelationsOnlyAllowedOnJoinType));
+    }
+
+    #[test]
+    fn test_parse_completion() {
+        let mapping = parse_field(&serde_json::from_str("
+        {
+            \"type\": \"completion\",
+            \"analyzer\": \"simple\",
+            \"max_input_length\": 20,
+            \"contexts\": [
+                {
+                    \"name\": \"genre\",
+                    \"type\": \"category\"
+                }
+            ]
+        }
+        ").unwrap());
+
+        assert_eq!(mapping, Ok(FieldMappingBuilder {
+            field_type: FieldType::Completion,
+            is_in_all: false,
+            base_analyzer: Some("simple".to_string()),
+            completion_contexts: vec!["genre".to_string()],
+            max_input_length: 20,
+            ..FieldMappingBuilder::default()
+        }));
+    }
+
+    #[test]
+    fn test_parse_completion_geo_context() {
+        let mapping = parse_field(&serde_json::from_str("
+        {
+            \"type\": \"completion\",
+            \"contexts\": [
+                {
+                    \"name\": \"location\",
+                    \"type\": \"geo\"
+                }
+            ]
+        }
+        ").unwrap());
+
+        assert_eq!(mapping, Err(FieldMappingParseError::UnrecognisedContextType("geo".to_string())));
+    }
+
+    #[test]
+    fn test_parse_contexts_on_non_completion_field() {
+        let mapping = parse_field(&serde_json::from_str("
+        {
+            \"type\": \"string\",
+            \"max_input_length\": 20
+        }
+        ").unwrap());
+
+        assert_eq!(mapping, Err(FieldMappingParseError::CompletionSettingsOnlyAllowedOnCompletionType));
+    }
+}
@@ -0,0 +1,419 @@
+//! Measures where the time of a search goes, for searches with "profile" set
+//!
+//! The store runs a whole query at once, so only the top query's time comes from the
+//! search itself. The time of each query below it is measured by running that query again
+//! on its own, so these include finding and scoring matches but not collecting them. These
+//! profiles have "timed_separately" set in the response.
+
+use std::time::{Duration, Instant};
+
+use serde_json::Value as Json;
+use kite::{Query, TermSelector};
+use kite::schema::FieldRef;
+use kite::collectors::{Collector, DocumentMatch};
+use kite_rocksdb::RocksDBIndexReader;
+
+use collectors::AllDocumentsCollector;
+use index::metadata::IndexMetadata;
+
+
+pub fn duration_to_nanos(duration: Duration) -> u64 {
+    duration.as_secs() * 1_000_000_000 + duration.subsec_nanos() as u64
+}
+
+
+/// Times how long another collector spends collecting matches
+pub struct TimingCollector<'a, C: Collector + 'a> {
+    inner: &'a mut C,
+    time: Duration,
+}
+
+
+impl<'a, C: Collector + 'a> TimingCollector<'a, C> {
+    pub fn new(inner: &'a mut C) -> TimingCollector<'a, C> {
+        TimingCollector {
+            inner: inner,
+            time: Duration::new(0, 0),
+        }
+    }
+
+    pub fn time_in_nanos(&self) -> u64 {
+        duration_to_nanos(self.time)
+    }
+}
+
+
+impl<'a, C: Collector + 'a> Collector for TimingCollector<'a, C> {
+    fn needs_score(&self) -> bool {
+        self.inner.needs_score()
+    }
+
+    fn collect(&mut self, doc: DocumentMatch) {
+        let start = Instant::now();
+        self.inner.collect(doc);
+        self.time += start.elapsed();
+    }
+}
+
+
+/// The times measured while searching one index
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SearchTimings {
+    /// The whole search, including collecting matches and running aggregations
+    pub search_time_in_nanos: u64,
+    pub collector_time_in_nanos: u64,
+    pub aggregation_times_in_nanos: Vec<u64>,
+}
+
+
+impl SearchTimings {
+    /// The time spent finding and scoring matches
+    pub fn query_time_in_nanos(&self) -> u64 {
+        let collect_time = self.aggregation_times_in_nanos.iter().fold(self.collector_time_in_nanos, |total, time| total + time);
+        self.search_time_in_nanos.saturating_sub(collect_time)
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryProfile {
+    pub query_type: String,
+    pub description: String,
+    pub time_in_nanos: u64,
+
+    /// Whether the time was measured by running the query again on its own, rather than
+    /// in the search
+    pub timed_separately: bool,
+    pub children: Vec<QueryProfile>,
+}
+
+
+impl QueryProfile {
+    pub fn to_json(&self) -> Json {
+        let mut json = json!({
+            "type": self.query_type,
+            "description": self.description,
+            "time_in_nanos": self.time_in_nanos,
+        });
+
+        if self.timed_separately {
+            if let Some(object) = json.as_object_mut() {
+                object.insert("timed_separately".to_string(), Json::Bool(true));
+            }
+        }
+
+        if !self.children.is_empty() {
+            if let Some(object) = json.as_object_mut() {
+                object.insert("children".to_string(), Json::Array(self.children.iter().map(|child| child.to_json()).collect()));
+            }
+        }
+
+        json
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CollectorProfile {
+    pub name: String,
+
+    /// Why the collector was used, eg "search_top_hits"
+    pub reason: String,
+    pub time_in_nanos: u64,
+}
+
+
+impl CollectorProfile {
+    pub fn to_json(&self) -> Json {
+        json!({
+            "name": self.name,
+            "reason": self.reason,
+            "time_in_nanos": self.time_in_nanos,
+        })
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AggregationProfile {
+    pub aggregation_type: String,
+
+    /// The name that the aggregation was given in the request
+    pub description: String,
+    pub time_in_nanos: u64,
+}
+
+
+impl AggregationProfile {
+    pub fn to_json(&self) -> Json {
+        json!({
+            "type": self.aggregation_type,
+            "description": self.description,
+            "time_in_nanos": self.time_in_nanos,
+        })
+    }
+}
+
+
+/// The profile of a search on one index
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexProfile {
+    pub index_name: String,
+    pub query: QueryProfile,
+
+    /// The time taken to build the query for the index
+    pub rewrite_time_in_nanos: u64,
+    pub collector: CollectorProfile,
+    pub aggregations: Vec<AggregationProfile>,
+}
+
+
+impl IndexProfile {
+    pub fn to_json(&self) -> Json {
+        json!({
+            "id": format!("[{}]", self.index_name),
+            "searches": [
+                {
+                    "query": [self.query.to_json()],
+                    "rewrite_time": self.rewrite_time_in_nanos,
+                    "collector": [self.collector.to_json()],
+                }
+            ],
+            "aggregations": self.aggregations.iter().map(|aggregation| aggregation.to_json()).collect::<Vec<_>>(),
+        })
+    }
+}
+
+
+pub fn query_type(query: &Query) -> &'static str {
+    match *query {
+        Query::All{..} => "AllQuery",
+        Query::None => "NoneQuery",
+        Query::Term{..} => "TermQuery",
+        Query::MultiTerm{..} => "MultiTermQuery",
+        Query::Conjunction{..} => "ConjunctionQuery",
+        Query::Disjunction{..} => "DisjunctionQuery",
+        Query::DisjunctionMax{..} => "DisjunctionMaxQuery",
+        Query::Filter{..} => "FilterQuery",
+        Query::Exclude{..} => "ExcludeQuery",
+    }
+}
+
+
+pub fn sub_queries(query: &Query) -> Vec<&Query> {
+    match *query {
+        Query::Conjunction{ref queries} |
+        Query::Disjunction{ref queries} |
+        Query::DisjunctionMax{ref queries} => queries.iter().collect(),
+        Query::Filter{ref query, ref filter} => vec![&**query, &**filter],
+        Query::Exclude{ref query, ref exclude} => vec![&**query, &**exclude],
+        _ => Vec::new(),
+    }
+}
+
+
+/// Describes a query in a similar syntax to Lucene's
+///
+/// Required clauses are prefixed with "+", filters with "#" and exclusions with "-".
+pub fn describe_query(query: &Query, field_names: &[(FieldRef, String)]) -> String {
+    let field_name = |field: FieldRef| {
+        field_names.iter()
+            .find(|&&(field_ref, _)| field_ref == field)
+            .map(|&(_, ref field_name)| field_name.clone())
+            .unwrap_or_else(|| "_unknown".to_string())
+    };
+
+    let describe_all = |queries: &[Query], prefix: &str, separator: &str| {
+        queries.iter().map(|query| format!("{}{}", prefix, describe_query(query, field_names))).collect::<Vec<_>>().join(separator)
+    };
+
+    match *query {
+        Query::All{..} => "*:*".to_string(),
+        Query::None => "MatchNoDocs".to_string(),
+        Query::Term{field, ref term, ..} => {
+            match String::from_utf8(term.as_bytes().to_vec()) {
+                Ok(term) => format!("{}:{}", field_name(field), term),
+                Err(_) => format!("{}:{:?}", field_name(field), term.as_bytes()),
+            }
+        }
+        Query::MultiTerm{field, term_selector: TermSelector::Prefix(ref prefix), ..} => format!("{}:{}*", field_name(field), prefix),
+        Query::Conjunction{ref queries} => format!("({})", describe_all(queries, "+", " ")),
+        Query::Disjunction{ref queries} => format!("({})", describe_all(queries, "", " ")),
+        Query::DisjunctionMax{ref queries} => format!("({})", describe_all(queries, "", " | ")),
+        Query::Filter{ref query, ref filter} => format!("(+{} #{})", describe_query(query, field_names), describe_query(filter, field_names)),
+        Query::Exclude{ref query, ref exclude} => format!("(+{} -{})", describe_query(query, field_names), describe_query(exclude, field_names)),
+    }
+}
+
+
+pub struct QueryProfiler<'a> {
+    index_reader: &'a RocksDBIndexReader<'a>,
+    field_names: Vec<(FieldRef, String)>,
+}
+
+
+impl<'a> QueryProfiler<'a> {
+    pub fn new(index_reader: &'a RocksDBIndexReader<'a>, index_metadata: &IndexMetadata) -> QueryProfiler<'a> {
+        QueryProfiler {
+            index_reader: index_reader,
+            field_names: index_metadata.get_field_names(&index_reader.schema()),
+        }
+    }
+
+    fn time_query(&self, query: &Query) -> u64 {
+        let start = Instant::now();
+        let mut collector = AllDocumentsCollector::new();
+        self.index_reader.search(&mut collector, query).unwrap();
+        duration_to_nanos(start.elapsed())
+    }
+
+    /// Profiles a query that took the given time to run in the search
+    ///
+    /// Each query below it is run again to time it.
+    pub fn profile(&self, query: &Query, time_in_nanos: u64) -> QueryProfile {
+        self.profile_query(query, time_in_nanos, false)
+    }
+
+    fn profile_query(&self, query: &Query, time_in_nanos: u64, timed_separately: bool) -> QueryProfile {
+        QueryProfile {
+            query_type: query_type(query).to_string(),
+            description: describe_query(query, &self.field_names),
+            time_in_nanos: time_in_nanos,
+            timed_separately: timed_separately,
+            children: sub_queries(query).into_iter().map(|sub_query| self.profile_query(sub_query, self.time_query(sub_query), true)).collect(),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use kite::{Term, Query, TermScorer, TermSelector};
+    use kite::schema::FieldRef;
+    use kite::collectors::{Collector, DocumentMatch};
+
+    use collectors::AllDocumentsCollector;
+
+    use super::{duration_to_nanos, describe_query, query_type, sub_queries, TimingCollector, SearchTimings, QueryProfile};
+
+    fn term_query(field: FieldRef, term: &str) -> Query {
+        Query::Term {
+            field: field,
+            term: Term::from_string(term),
+            scorer: TermScorer::default(),
+        }
+    }
+
+    #[test]
+    fn test_duration_to_nanos() {
+        assert_eq!(duration_to_nanos(Duration::new(2, 500)), 2_000_000_500);
+    }
+
+    #[test]
+    fn test_describe_query() {
+        let title = FieldRef::new(1);
+        let body = FieldRef::new(2);
+        let field_names = vec![(title, "title".to_string()), (body, "body".to_string())];
+
+        let query = Query::Filter {
+            query: Box::new(Query::Conjunction {
+                queries: vec![
+                    term_query(title, "hello"),
+                    Query::DisjunctionMax {
+                        queries: vec![
+                            term_query(title, "world"),
+                            term_query(body, "world"),
+                        ],
+                    },
+                ],
+            }),
+            filter: Box::new(Query::MultiTerm {
+                field: body,
+                term_selector: TermSelector::Prefix("wor".to_string()),
+                scorer: TermScorer::default(),
+            }),
+        };
+
+        assert_eq!(describe_query(&query, &field_names), "(+(+title:hello +(title:world | body:world)) #body:wor*)");
+        assert_eq!(describe_query(&term_query(FieldRef::new(3), "foo"), &field_names), "_unknown:foo");
+    }
+
+    #[test]
+    fn test_sub_queries() {
+        let query = Query::Exclude {
+            query: Box::new(Query::new_all()),
+            exclude: Box::new(Query::None),
+        };
+
+        assert_eq!(query_type(&query), "ExcludeQuery");
+        assert_eq!(sub_queries(&query).into_iter().map(query_type).collect::<Vec<_>>(), vec!["AllQuery", "NoneQuery"]);
+        assert!(sub_queries(&Query::new_all()).is_empty());
+    }
+
+    #[test]
+    fn test_timing_collector() {
+        let mut inner = AllDocumentsCollector::new().no_score();
+
+        {
+            let mut collector = TimingCollector::new(&mut inner);
+            assert!(!collector.needs_score());
+            collector.collect(DocumentMatch::new_unscored(1));
+            collector.collect(DocumentMatch::new_unscored(2));
+        }
+
+        assert_eq!(inner.into_vec(), vec![(1, None), (2, None)]);
+    }
+
+    #[test]
+    fn test_query_time() {
+        let timings = SearchTimings {
+            search_time_in_nanos: 1000,
+            collector_time_in_nanos: 300,
+            aggregation_times_in_nanos: vec![100, 200],
+        };
+        assert_eq!(timings.query_time_in_nanos(), 400);
+
+        // Timer resolution can make the parts add up to more than the whole
+        let timings = SearchTimings {
+            search_time_in_nanos: 100,
+            collector_time_in_nanos: 300,
+            aggregation_times_in_nanos: Vec::new(),
+        };
+        assert_eq!(timings.query_time_in_nanos(), 0);
+    }
+
+    #[test]
+    fn test_query_profile_to_json() {
+        let profile = QueryProfile {
+            query_type: "DisjunctionQuery".to_string(),
+            description: "(title:hello title:world)".to_string(),
+            time_in_nanos: 500,
+            timed_separately: false,
+            children: vec![
+                QueryProfile {
+                    query_type: "TermQuery".to_string(),
+                    description: "title:hello".to_string(),
+                    time_in_nanos: 200,
+                    timed_separately: true,
+                    children: Vec::new(),
+                },
+            ],
+        };
+
+        assert_eq!(profile.to_json(), json!({
+            "type": "DisjunctionQuery",
+            "description": "(title:hello title:world)",
+            "time_in_nanos": 500,
+            "children": [
+                {
+                    "type": "TermQuery",
+                    "description": "title:hello",
+                    "time_in_nanos": 200,
+                    "timed_separately": true,
+                }
+            ],
+        }));
+    }
+}
@@ -1,6 +1,8 @@
 //! Parses "and" queries
 
+use serde_json;
 use serde_json::Value as Json;
+use serde_json::value::ToJson;
 use kite::Query;
 use kite::schema::Schema;
 
@@ -8,11 +10,20 @@ use query_parser::{QueryBuildContext, QueryParseError, QueryBuilder, parse as pa
 
 
 #[derive(Debug)]
-struct AndQueryBuilder {
+pub struct AndQueryBuilder {
     queries: Vec<Box<QueryBuilder>>,
 }
 
 
+impl AndQueryBuilder {
+    pub fn new(queries: Vec<Box<QueryBuilder>>) -> AndQueryBuilder {
+        AndQueryBuilder {
+            queries: queries,
+        }
+    }
+}
+
+
 impl QueryBuilder for AndQueryBuilder {
     fn build(&self, context: &QueryBuildContext, schema: &Schema) -> Query {
         let mut queries = Vec::new();
@@ -26,6 +37,20 @@ impl QueryBuilder for AndQueryBuilder {
 }
 
 
+impl ToJson for AndQueryBuilder {
+    fn to_json(&self) -> Result<Json, serde_json::Error> {
+        let mut queries = Vec::new();
+        for query in self.queries.iter() {
+            queries.push(try!(query.to_json()));
+        }
+
+        Ok(json!({
+            "and": queries
+        }))
+    }
+}
+
+
 pub fn parse(json: &Json) -> Result<Box<QueryBuilder>, QueryParseError> {
     let filters = try!(json.as_array().ok_or(QueryParseError::ExpectedArray));
 
@@ -0,0 +1,380 @@
+//! Parses "bool" queries
+
+use serde_json;
+use serde_json::Value as Json;
+use serde_json::value::ToJson;
+use kite::Query;
+use kite::schema::Schema;
+
+use query_parser::{QueryBuildContext, QueryParseError, QueryBuilder, parse as parse_query};
+use query_parser::utils::parse_float;
+
+
+#[derive(Debug)]
+pub struct BoolQueryBuilder {
+    must: Vec<Box<QueryBuilder>>,
+    must_not: Vec<Box<QueryBuilder>>,
+    should: Vec<Box<QueryBuilder>>,
+    filter: Vec<Box<QueryBuilder>>,
+    boost: f64,
+}
+
+
+impl BoolQueryBuilder {
+    pub fn new() -> BoolQueryBuilder {
+        BoolQueryBuilder {
+            must: Vec::new(),
+            must_not: Vec::new(),
+            should: Vec::new(),
+            filter: Vec::new(),
+            boost: 1.0f64,
+        }
+    }
+
+    pub fn must(mut self, query: Box<QueryBuilder>) -> BoolQueryBuilder {
+        self.must.push(query);
+        self
+    }
+
+    pub fn must_not(mut self, query: Box<QueryBuilder>) -> BoolQueryBuilder {
+        self.must_not.push(query);
+        self
+    }
+
+    pub fn should(mut self, query: Box<QueryBuilder>) -> BoolQueryBuilder {
+        self.should.push(query);
+        self
+    }
+
+    pub fn filter(mut self, query: Box<QueryBuilder>) -> BoolQueryBuilder {
+        self.filter.push(query);
+        self
+    }
+
+    pub fn boost(mut self, boost: f64) -> BoolQueryBuilder {
+        self.boost = boost;
+        self
+    }
+}
+
+
+fn build_queries(queries: &Vec<Box<QueryBuilder>>, context: &QueryBuildContext, schema: &Schema) -> Vec<Query> {
+    let mut built_queries = Vec::new();
+
+    for query in queries.iter() {
+        built_queries.push(query.build(context, schema));
+    }
+
+    built_queries
+}
+
+
+impl QueryBuilder for BoolQueryBuilder {
+    fn build(&self, context: &QueryBuildContext, schema: &Schema) -> Query {
+        let mut query = match (self.must.is_empty(), self.should.is_empty()) {
+            (true, true) => Query::new_all(),
+            (true, false) => Query::new_disjunction(build_queries(&self.should, context, schema)),
+            (false, true) => Query::new_conjunction(build_queries(&self.must, context, schema)),
+            (false, false) => {
+                // The "should" clauses only contribute to the score when there are "must" clauses
+                let must = Query::new_conjunction(build_queries(&self.must, context, schema));
+
+                let mut scoring_queries = vec![must];
+                scoring_queries.extend(build_queries(&self.should, context, schema));
+
+                Query::Filter {
+                    query: Box::new(Query::new_disjunction(scoring_queries)),
+                    filter: Box::new(Query::new_conjunction(build_queries(&self.must, &context.clone().no_score(), schema))),
+                }
+            }
+        };
+
+        // Filters and exclusions don't affect the score
+        if !self.filter.is_empty() {
+            query = Query::Filter {
+                query: Box::new(query),
+                filter: Box::new(Query::new_conjunction(build_queries(&self.filter, &context.clone().no_score(), schema))),
+            };
+        }
+
+        if !self.must_not.is_empty() {
+            query = Query::Exclude {
+                query: Box::new(query),
+                exclude: Box::new(Query::new_disjunction(build_queries(&self.must_not, &context.clone().no_score(), schema))),
+            };
+        }
+
+        // Add boost (scores are thrown away in filter context)
+        if context.score_required() {
+            query.boost(self.boost);
+        }
+
+        query
+    }
+}
+
+
+fn queries_to_json(queries: &Vec<Box<QueryBuilder>>) -> Result<Json, serde_json::Error> {
+    let mut queries_json = Vec::new();
+
+    for query in queries.iter() {
+        queries_json.push(try!(query.to_json()));
+    }
+
+    Ok(Json::Array(queries_json))
+}
+
+
+impl ToJson for BoolQueryBuilder {
+    fn to_json(&self) -> Result<Json, serde_json::Error> {
+        Ok(json!({
+            "bool": {
+                "must": try!(queries_to_json(&self.must)),
+                "must_not": try!(queries_to_json(&self.must_not)),
+                "should": try!(queries_to_json(&self.should)),
+                "filter": try!(queries_to_json(&self.filter)),
+                "boost": self.boost,
+            }
+        }))
+    }
+}
+
+
+/// Parses a bool clause, which can either be a single query or an array of queries
+fn parse_clause(json: &Json) -> Result<Vec<Box<QueryBuilder>>, QueryParseError> {
+    match *json {
+        Json::Object(_) => Ok(vec![try!(parse_query(json))]),
+        Json::Array(ref array) => {
+            let mut queries = Vec::new();
+
+            for query in array.iter() {
+                queries.push(try!(parse_query(query)));
+            }
+
+            Ok(queries)
+        }
+        _ => Err(QueryParseError::ExpectedObjectOrArray),
+    }
+}
+
+
+pub fn parse(json: &Json) -> Result<Box<QueryBuilder>, QueryParseError> {
+    let object = try!(json.as_object().ok_or(QueryParseError::ExpectedObject));
+
+    let mut builder = BoolQueryBuilder::new();
+
+    for (key, value) in object.iter() {
+        match key.as_ref() {
+            "must" => {
+                builder.must = try!(parse_clause(value));
+            }
+            "must_not" => {
+                builder.must_not = try!(parse_clause(value));
+            }
+            "should" => {
+                builder.should = try!(parse_clause(value));
+            }
+            "filter" => {
+                builder.filter = try!(parse_clause(value));
+            }
+            "boost" => {
+                builder.boost = try!(parse_float(value));
+            }
+            _ => return Err(QueryParseError::UnrecognisedKey(key.clone()))
+        }
+    }
+
+    Ok(Box::new(builder))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+
+    use kite::{Term, Query, TermScorer};
+    use kite::schema::{Schema, FieldType, FIELD_INDEXED};
+
+    use query_parser::{QueryBuildContext, QueryParseError};
+
+    use super::parse;
+
+    #[test]
+    fn test_bool_query_must() {
+        let mut schema = Schema::new();
+        let test_field = schema.add_field("test".to_string(), FieldType::Text, FIELD_INDEXED).unwrap();
+
+        let query = parse(&serde_json::from_str("
+        {
+            \"must\": [
+                {
+                    \"term\": {
+                        \"test\": \"foo\"
+                    }
+                },
+                {
+                    \"term\": {
+                        \"test\": \"bar\"
+                    }
+                }
+            ]
+        }
+        ").unwrap()).and_then(|builder| Ok(builder.build(&QueryBuildContext::new(), &schema)));
+
+        assert_eq!(query, Ok(Query::Conjunction {
+            queries: vec![
+                Query::Term {
+                    field: test_field,
+                    term: Term::from_string("foo"),
+                    scorer: TermScorer::default(),
+                },
+                Query::Term {
+                    field: test_field,
+                    term: Term::from_string("bar"),
+                    scorer: TermScorer::default(),
+                },
+            ],
+        }))
+    }
+
+    #[test]
+    fn test_bool_query_filter_and_must_not() {
+        let mut schema = Schema::new();
+        let test_field = schema.add_field("test".to_string(), FieldType::Text, FIELD_INDEXED).unwrap();
+
+        let query = parse(&serde_json::from_str("
+        {
+            \"filter\": [
+                {
+                    \"term\": {
+                        \"test\": \"foo\"
+                    }
+                },
+                {
+                    \"term\": {
+                        \"test\": \"bar\"
+                    }
+                }
+            ],
+            \"must_not\": [
+                {
+                    \"term\": {
+                        \"test\": \"baz\"
+                    }
+                },
+                {
+                    \"term\": {
+                        \"test\": \"quux\"
+                    }
+                }
+            ]
+        }
+        ").unwrap()).and_then(|builder| Ok(builder.build(&QueryBuildContext::new(), &schema)));
+
+        assert_eq!(query, Ok(Query::Exclude {
+            query: Box::new(Query::Filter {
+                query: Box::new(Query::new_all()),
+                filter: Box::new(Query::Conjunction {
+                    queries: vec![
+                        Query::Term {
+                            field: test_field,
+                            term: Term::from_string("foo"),
+                            scorer: TermScorer::default(),
+                        },
+                        Query::Term {
+                            field: test_field,
+                            term: Term::from_string("bar"),
+                            scorer: TermScorer::default(),
+                        },
+                    ],
+                }),
+            }),
+            exclude: Box::new(Query::Disjunction {
+                queries: vec![
+                    Query::Term {
+                        field: test_field,
+                        term: Term::from_string("baz"),
+                        scorer: TermScorer::default(),
+                    },
+                    Query::Term {
+                        field: test_field,
+                        term: Term::from_string("quux"),
+                        scorer: TermScorer::default(),
+                    },
+                ],
+            }),
+        }))
+    }
+
+    #[test]
+    fn test_bool_query_filter_ignores_boost() {
+        let mut schema = Schema::new();
+        let test_field = schema.add_field("test".to_string(), FieldType::Text, FIELD_INDEXED).unwrap();
+
+        let query = parse(&serde_json::from_str("
+        {
+            \"filter\": {
+                \"term\": {
+                    \"test\": {
+                        \"value\": \"foo\",
+                        \"boost\": 2.0
+                    }
+                }
+            }
+        }
+        ").unwrap()).and_then(|builder| Ok(builder.build(&QueryBuildContext::new(), &schema)));
+
+        assert_eq!(query, Ok(Query::Filter {
+            query: Box::new(Query::new_all()),
+            filter: Box::new(Query::new_conjunction(vec![
+                Query::Term {
+                    field: test_field,
+                    term: Term::from_string("foo"),
+                    scorer: TermScorer::default(),
+                },
+            ])),
+        }))
+    }
+
+    #[test]
+    fn test_empty_bool_query() {
+        let schema = Schema::new();
+
+        let query = parse(&serde_json::from_str("
+        {}
+        ").unwrap()).and_then(|builder| Ok(builder.build(&QueryBuildContext::new(), &schema)));
+
+        assert_eq!(query, Ok(Query::new_all()))
+    }
+
+    #[test]
+    fn test_gives_error_for_incorrect_type() {
+        let query = parse(&serde_json::from_str("
+        \"hello\"
+        ").unwrap());
+
+        assert_eq!(query.err(), Some(QueryParseError::ExpectedObject));
+    }
+
+    #[test]
+    fn test_gives_error_for_incorrect_clause_type() {
+        let query = parse(&serde_json::from_str("
+        {
+            \"must\": \"foo\"
+        }
+        ").unwrap());
+
+        assert_eq!(query.err(), Some(QueryParseError::ExpectedObjectOrArray));
+    }
+
+    #[test]
+    fn test_gives_error_for_unexpected_key() {
+        let query = parse(&serde_json::from_str("
+        {
+            \"foo\": \"bar\"
+        }
+        ").unwrap());
+
+        assert_eq!(query.err(), Some(QueryParseError::UnrecognisedKey("foo".to_string())));
+    }
+}
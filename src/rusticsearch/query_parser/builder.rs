@@ -0,0 +1,189 @@
+//! Typed API for building queries from Rust code
+//!
+//! These functions construct the same builders that the JSON parsers produce, so
+//! applications embedding the crate don't need to go through the Query DSL:
+//!
+//! ```ignore
+//! let query = builder::bool()
+//!     .must(Box::new(builder::match_query("title", "hello world")))
+//!     .filter(Box::new(builder::term("published", true).boost(2.0)));
+//! ```
+
+use serde_json::Value as Json;
+use serde_json::value::ToJson;
+
+use query_parser::QueryBuilder;
+
+pub use query_parser::utils::Operator;
+pub use query_parser::match_query::MatchQueryBuilder;
+pub use query_parser::multi_match_query::MultiMatchQueryBuilder;
+pub use query_parser::match_all_query::MatchAllQueryBuilder;
+pub use query_parser::match_none_query::MatchNoneQueryBuilder;
+pub use query_parser::filtered_query::FilteredQueryBuilder;
+pub use query_parser::terms_query::TermsQueryBuilder;
+pub use query_parser::term_query::TermQueryBuilder;
+pub use query_parser::prefix_query::PrefixQueryBuilder;
+pub use query_parser::and_query::AndQueryBuilder;
+pub use query_parser::or_query::OrQueryBuilder;
+pub use query_parser::not_query::NotQueryBuilder;
+pub use query_parser::has_child_query::{HasChildQueryBuilder, ScoreMode};
+pub use query_parser::has_parent_query::HasParentQueryBuilder;
+pub use query_parser::bool_query::BoolQueryBuilder;
+pub use query_parser::dis_max_query::DisMaxQueryBuilder;
+
+
+fn value_to_json<T: ToJson>(value: T) -> Json {
+    value.to_json().unwrap_or(Json::Null)
+}
+
+
+pub fn match_all() -> MatchAllQueryBuilder {
+    MatchAllQueryBuilder::new()
+}
+
+
+pub fn match_none() -> MatchNoneQueryBuilder {
+    MatchNoneQueryBuilder
+}
+
+
+pub fn match_query(field: &str, query: &str) -> MatchQueryBuilder {
+    MatchQueryBuilder::new(field, query)
+}
+
+
+pub fn multi_match(fields: &[&str], query: &str) -> MultiMatchQueryBuilder {
+    MultiMatchQueryBuilder::new(fields.iter().map(|field| (field.to_string(), 1.0f64)).collect(), query)
+}
+
+
+pub fn term<T: ToJson>(field: &str, value: T) -> TermQueryBuilder {
+    TermQueryBuilder::new(field, value_to_json(value))
+}
+
+
+pub fn terms<T: ToJson>(field: &str, values: Vec<T>) -> TermsQueryBuilder {
+    TermsQueryBuilder::new(field, values.into_iter().map(value_to_json).collect())
+}
+
+
+pub fn prefix(field: &str, prefix: &str) -> PrefixQueryBuilder {
+    PrefixQueryBuilder::new(field, prefix)
+}
+
+
+pub fn filtered(query: Option<Box<QueryBuilder>>, filter: Box<QueryBuilder>) -> FilteredQueryBuilder {
+    FilteredQueryBuilder::new(query, filter)
+}
+
+
+pub fn and(queries: Vec<Box<QueryBuilder>>) -> AndQueryBuilder {
+    AndQueryBuilder::new(queries)
+}
+
+
+pub fn or(queries: Vec<Box<QueryBuilder>>) -> OrQueryBuilder {
+    OrQueryBuilder::new(queries)
+}
+
+
+pub fn not(query: Box<QueryBuilder>) -> NotQueryBuilder {
+    NotQueryBuilder::new(query)
+}
+
+
+pub fn bool() -> BoolQueryBuilder {
+    BoolQueryBuilder::new()
+}
+
+
+pub fn dis_max(queries: Vec<Box<QueryBuilder>>) -> DisMaxQueryBuilder {
+    DisMaxQueryBuilder::new(queries)
+}
+
+
+pub fn has_child(child_type: &str, query: Box<QueryBuilder>) -> HasChildQueryBuilder {
+    HasChildQueryBuilder::new(child_type, query)
+}
+
+
+pub fn has_parent(parent_type: &str, query: Box<QueryBuilder>) -> HasParentQueryBuilder {
+    HasParentQueryBuilder::new(parent_type, query)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+
+    use kite::{Term, Query, TermScorer};
+    use kite::schema::{Schema, FieldType, FIELD_INDEXED};
+
+    use query_parser::{QueryBuildContext, QueryBuilder, parse};
+
+    use super::{term, bool, dis_max, match_all};
+
+    #[test]
+    fn test_term() {
+        let mut schema = Schema::new();
+        let test_field = schema.add_field("test".to_string(), FieldType::Text, FIELD_INDEXED).unwrap();
+
+        let query = term("test", "foo").build(&QueryBuildContext::new(), &schema);
+
+        assert_eq!(query, Query::Term {
+            field: test_field,
+            term: Term::from_string("foo"),
+            scorer: TermScorer::default(),
+        });
+    }
+
+    #[test]
+    fn test_term_with_boost() {
+        let mut schema = Schema::new();
+        let test_field = schema.add_field("test".to_string(), FieldType::Text, FIELD_INDEXED).unwrap();
+
+        let query = term("test", 123).boost(2.0f64).build(&QueryBuildContext::new(), &schema);
+
+        assert_eq!(query, Query::Term {
+            field: test_field,
+            term: Term::from_integer(123),
+            scorer: TermScorer::default_with_boost(2.0f64),
+        });
+    }
+
+    #[test]
+    fn test_matches_parsed_query() {
+        let mut schema = Schema::new();
+        schema.add_field("test".to_string(), FieldType::Text, FIELD_INDEXED).unwrap();
+
+        let queries: Vec<Box<QueryBuilder>> = vec![
+            Box::new(term("test", "foo")),
+            Box::new(term("test", "bar")),
+        ];
+
+        let built = bool()
+            .must(Box::new(dis_max(queries)))
+            .filter(Box::new(match_all()))
+            .build(&QueryBuildContext::new(), &schema);
+
+        let parsed = parse(&serde_json::from_str("
+        {
+            \"bool\": {
+                \"must\": {
+                    \"dis_max\": {
+                        \"queries\": [
+                            {\"term\": {\"test\": \"foo\"}},
+                            {\"term\": {\"test\": \"bar\"}}
+                        ]
+                    }
+                },
+                \"filter\": {
+                    \"match_all\": {}
+                }
+            }
+        }
+        ").unwrap()).unwrap().build(&QueryBuildContext::new(), &schema);
+
+        assert_eq!(built, parsed);
+    }
+}
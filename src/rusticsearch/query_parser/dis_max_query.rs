@@ -0,0 +1,200 @@
+//! Parses "dis_max" queries
+
+use serde_json;
+use serde_json::Value as Json;
+use serde_json::value::ToJson;
+use kite::Query;
+use kite::schema::Schema;
+
+use query_parser::{QueryBuildContext, QueryParseError, QueryBuilder, parse as parse_query};
+use query_parser::utils::parse_float;
+
+
+#[derive(Debug)]
+pub struct DisMaxQueryBuilder {
+    queries: Vec<Box<QueryBuilder>>,
+    boost: f64,
+}
+
+
+impl DisMaxQueryBuilder {
+    pub fn new(queries: Vec<Box<QueryBuilder>>) -> DisMaxQueryBuilder {
+        DisMaxQueryBuilder {
+            queries: queries,
+            boost: 1.0f64,
+        }
+    }
+
+    pub fn boost(mut self, boost: f64) -> DisMaxQueryBuilder {
+        self.boost = boost;
+        self
+    }
+}
+
+
+impl QueryBuilder for DisMaxQueryBuilder {
+    fn build(&self, context: &QueryBuildContext, schema: &Schema) -> Query {
+        let mut queries = Vec::new();
+
+        for query in self.queries.iter() {
+            queries.push(query.build(context, schema));
+        }
+
+        let mut query = Query::new_disjunction_max(queries);
+
+        // Add boost (scores are thrown away in filter context)
+        if context.score_required() {
+            query.boost(self.boost);
+        }
+
+        query
+    }
+}
+
+
+impl ToJson for DisMaxQueryBuilder {
+    fn to_json(&self) -> Result<Json, serde_json::Error> {
+        let mut queries = Vec::new();
+        for query in self.queries.iter() {
+            queries.push(try!(query.to_json()));
+        }
+
+        Ok(json!({
+            "dis_max": {
+                "queries": queries,
+                "boost": self.boost,
+            }
+        }))
+    }
+}
+
+
+pub fn parse(json: &Json) -> Result<Box<QueryBuilder>, QueryParseError> {
+    let object = try!(json.as_object().ok_or(QueryParseError::ExpectedObject));
+
+    let mut queries = Vec::new();
+    let mut has_queries_key = false;
+    let mut boost = 1.0f64;
+
+    for (key, value) in object.iter() {
+        match key.as_ref() {
+            "queries" => {
+                has_queries_key = true;
+
+                let array = try!(value.as_array().ok_or(QueryParseError::ExpectedArray));
+                for query in array.iter() {
+                    queries.push(try!(parse_query(query)));
+                }
+            }
+            "boost" => {
+                boost = try!(parse_float(value));
+            }
+            _ => return Err(QueryParseError::UnrecognisedKey(key.clone()))
+        }
+    }
+
+    if !has_queries_key {
+        return Err(QueryParseError::ExpectedKey("queries"))
+    }
+
+    Ok(Box::new(DisMaxQueryBuilder {
+        queries: queries,
+        boost: boost,
+    }))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+
+    use kite::{Term, Query, TermScorer};
+    use kite::schema::{Schema, FieldType, FIELD_INDEXED};
+
+    use query_parser::{QueryBuildContext, QueryParseError};
+
+    use super::parse;
+
+    #[test]
+    fn test_dis_max_query() {
+        let mut schema = Schema::new();
+        let test_field = schema.add_field("test".to_string(), FieldType::Text, FIELD_INDEXED).unwrap();
+
+        let query = parse(&serde_json::from_str("
+        {
+            \"queries\": [
+                {
+                    \"term\": {
+                        \"test\": \"foo\"
+                    }
+                },
+                {
+                    \"term\": {
+                        \"test\": \"bar\"
+                    }
+                }
+            ]
+        }
+        ").unwrap()).and_then(|builder| Ok(builder.build(&QueryBuildContext::new(), &schema)));
+
+        assert_eq!(query, Ok(Query::DisjunctionMax {
+            queries: vec![
+                Query::Term {
+                    field: test_field,
+                    term: Term::from_string("foo"),
+                    scorer: TermScorer::default(),
+                },
+                Query::Term {
+                    field: test_field,
+                    term: Term::from_string("bar"),
+                    scorer: TermScorer::default(),
+                },
+            ],
+        }))
+    }
+
+    #[test]
+    fn test_gives_error_for_incorrect_type() {
+        let query = parse(&serde_json::from_str("
+        \"hello\"
+        ").unwrap());
+
+        assert_eq!(query.err(), Some(QueryParseError::ExpectedObject));
+    }
+
+    #[test]
+    fn test_gives_error_for_missing_queries() {
+        let query = parse(&serde_json::from_str("
+        {
+            \"boost\": 2.0
+        }
+        ").unwrap());
+
+        assert_eq!(query.err(), Some(QueryParseError::ExpectedKey("queries")));
+    }
+
+    #[test]
+    fn test_gives_error_for_incorrect_queries_type() {
+        let query = parse(&serde_json::from_str("
+        {
+            \"queries\": {
+                \"match_all\": {}
+            }
+        }
+        ").unwrap());
+
+        assert_eq!(query.err(), Some(QueryParseError::ExpectedArray));
+    }
+
+    #[test]
+    fn test_gives_error_for_unexpected_key() {
+        let query = parse(&serde_json::from_str("
+        {
+            \"queries\": [],
+            \"foo\": \"bar\"
+        }
+        ").unwrap());
+
+        assert_eq!(query.err(), Some(QueryParseError::UnrecognisedKey("foo".to_string())));
+    }
+}
@@ -1,6 +1,10 @@
 //! Parses "filtered" queries
 
+use std::collections::BTreeMap;
+
+use serde_json;
 use serde_json::Value as Json;
+use serde_json::value::ToJson;
 use kite::Query;
 use kite::schema::Schema;
 
@@ -8,12 +12,22 @@ use query_parser::{QueryBuildContext, QueryParseError, QueryBuilder, parse as pa
 
 
 #[derive(Debug)]
-struct FilteredQueryBuilder {
+pub struct FilteredQueryBuilder {
     query: Option<Box<QueryBuilder>>,
     filter: Box<QueryBuilder>,
 }
 
 
+impl FilteredQueryBuilder {
+    pub fn new(query: Option<Box<QueryBuilder>>, filter: Box<QueryBuilder>) -> FilteredQueryBuilder {
+        FilteredQueryBuilder {
+            query: query,
+            filter: filter,
+        }
+    }
+}
+
+
 impl QueryBuilder for FilteredQueryBuilder {
     fn build(&self, context: &QueryBuildContext, schema: &Schema) -> Query {
         let query = match self.query {
@@ -29,6 +43,23 @@ impl QueryBuilder for FilteredQueryBuilder {
 }
 
 
+impl ToJson for FilteredQueryBuilder {
+    fn to_json(&self) -> Result<Json, serde_json::Error> {
+        let mut inner = BTreeMap::new();
+
+        if let Some(ref query) = self.query {
+            inner.insert("query".to_string(), try!(query.to_json()));
+        }
+
+        inner.insert("filter".to_string(), try!(self.filter.to_json()));
+
+        Ok(json!({
+            "filtered": inner
+        }))
+    }
+}
+
+
 pub fn parse(json: &Json) -> Result<Box<QueryBuilder>, QueryParseError> {
     let object = try!(json.as_object().ok_or(QueryParseError::ExpectedObject));
 
@@ -64,6 +95,7 @@ pub fn parse(json: &Json) -> Result<Box<QueryBuilder>, QueryParseError> {
 #[cfg(test)]
 mod tests {
     use serde_json;
+    use serde_json::value::ToJson;
 
     use kite::{Term, Query, TermScorer};
     use kite::schema::{Schema, FieldType, FIELD_INDEXED};
@@ -231,4 +263,31 @@ mod tests {
 
         assert_eq!(query.err(), Some(QueryParseError::UnrecognisedKey("foo".to_string())));
     }
+
+    #[test]
+    fn test_to_json() {
+        let query = parse(&serde_json::from_str("
+        {
+            \"filter\": {
+                \"term\": {
+                    \"the\": \"filter\"
+                }
+            }
+        }
+        ").unwrap()).and_then(|builder| Ok(builder.to_json().unwrap()));
+
+        assert_eq!(query, Ok(json!({
+            "filtered": {
+                "filter": {
+                    "term": {
+                        "the": {
+                            "value": "filter",
+                            "case_insensitive": false,
+                            "boost": 1.0
+                        }
+                    }
+                }
+            }
+        })));
+    }
 }
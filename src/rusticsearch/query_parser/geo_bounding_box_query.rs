@@ -0,0 +1,315 @@
+//! Parses "geo_bounding_box" queries
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde_json;
+use serde_json::Value as Json;
+use serde_json::value::ToJson;
+use kite::{Term, Query, TermSelector, TermScorer};
+use kite::schema::{Schema, FieldRef};
+use kite::document::{DocRef, FieldValue};
+
+use collectors::AllDocumentsCollector;
+use geo::{self, GeoPoint, BoundingBox};
+use query_parser::{QueryBuildContext, QueryParseError, QueryBuilder};
+use query_parser::utils::parse_float;
+
+
+/// Builds a query that matches the points in an area from a set of cells that cover it
+///
+/// Cells that are completely inside the area (`is_inside`) are searched by their prefix.
+/// The points in the cells on the edges are read from the index and checked (`contains`),
+/// then the geohashes of the ones inside the area are searched for exactly so points just
+/// outside it aren't matched. This needs an index reader, without one the edge cells are
+/// searched by their prefix as well.
+pub fn build_covering_query<I, C>(context: &QueryBuildContext, field: FieldRef, cells: Vec<String>, is_inside: I, contains: C) -> Query
+    where I: Fn(&BoundingBox) -> bool,
+          C: Fn(&GeoPoint) -> bool
+{
+    let prefix_query = |geohash: String| {
+        Query::MultiTerm {
+            field: field,
+            term_selector: TermSelector::Prefix(geohash),
+            scorer: TermScorer::default(),
+        }
+    };
+
+    let index_reader = match context.index_reader {
+        Some(index_reader) => index_reader,
+        None => return Query::new_disjunction(cells.into_iter().map(&prefix_query).collect()),
+    };
+
+    let (inner_cells, edge_cells): (Vec<String>, Vec<String>) = cells.into_iter().partition(|geohash| {
+        BoundingBox::from_geohash(geohash).map(|cell| is_inside(&cell)).unwrap_or(false)
+    });
+
+    let mut queries = inner_cells.into_iter().map(&prefix_query).collect::<Vec<_>>();
+    if edge_cells.is_empty() {
+        return Query::new_disjunction(queries);
+    }
+
+    let edge_query = Query::new_disjunction(edge_cells.into_iter().map(&prefix_query).collect());
+    let mut collector = AllDocumentsCollector::new().no_score();
+    if index_reader.search(&mut collector, &edge_query).is_err() {
+        queries.push(edge_query);
+        return Query::new_disjunction(queries);
+    }
+
+    let mut geohashes = BTreeSet::new();
+    for (doc_id, _) in collector.into_vec() {
+        if let Ok(Some(FieldValue::String(points))) = index_reader.read_stored_field(field, DocRef::from_u64(doc_id)) {
+            for point in geo::parse_stored_points(&points) {
+                if contains(&point) {
+                    geohashes.insert(point.to_geohash(geo::MAX_PRECISION));
+                }
+            }
+        }
+    }
+
+    queries.extend(geohashes.into_iter().map(|geohash| {
+        Query::Term {
+            field: field,
+            term: Term::from_string(&geohash),
+            scorer: TermScorer::default(),
+        }
+    }));
+
+    Query::new_disjunction(queries)
+}
+
+
+#[derive(Debug)]
+pub struct GeoBoundingBoxQueryBuilder {
+    field: String,
+    bounding_box: BoundingBox,
+}
+
+
+impl GeoBoundingBoxQueryBuilder {
+    pub fn new(field: &str, bounding_box: BoundingBox) -> GeoBoundingBoxQueryBuilder {
+        GeoBoundingBoxQueryBuilder {
+            field: field.to_string(),
+            bounding_box: bounding_box,
+        }
+    }
+}
+
+
+impl QueryBuilder for GeoBoundingBoxQueryBuilder {
+    fn build(&self, context: &QueryBuildContext, schema: &Schema) -> Query {
+        let field = schema.get_field_by_name(&self.field).unwrap();
+
+        // Points are indexed as geohashes, so search for the prefixes of the cells that cover the box
+        build_covering_query(context, field, self.bounding_box.covering_geohashes(), |cell| {
+            self.bounding_box.contains_box(cell)
+        }, |point| {
+            self.bounding_box.contains(point)
+        })
+    }
+}
+
+
+impl ToJson for GeoBoundingBoxQueryBuilder {
+    fn to_json(&self) -> Result<Json, serde_json::Error> {
+        let mut field_json = BTreeMap::new();
+        field_json.insert(self.field.clone(), json!({
+            "top_left": {
+                "lat": self.bounding_box.top,
+                "lon": self.bounding_box.left,
+            },
+            "bottom_right": {
+                "lat": self.bounding_box.bottom,
+                "lon": self.bounding_box.right,
+            },
+        }));
+
+        Ok(json!({
+            "geo_bounding_box": field_json
+        }))
+    }
+}
+
+
+fn parse_point(json: &Json) -> Result<GeoPoint, QueryParseError> {
+    GeoPoint::from_json(json).ok_or(QueryParseError::InvalidValue)
+}
+
+
+pub fn parse(json: &Json) -> Result<Box<QueryBuilder>, QueryParseError> {
+    let object = try!(json.as_object().ok_or(QueryParseError::ExpectedObject));
+
+    let field_name = if object.len() == 1 {
+        object.keys().collect::<Vec<_>>()[0]
+    } else {
+        return Err(QueryParseError::ExpectedSingleKey)
+    };
+
+    let inner_object = try!(object.get(field_name).unwrap().as_object().ok_or(QueryParseError::ExpectedObject));
+
+    // The corners can either be given as points or as separate coordinates
+    let mut top = None;
+    let mut left = None;
+    let mut bottom = None;
+    let mut right = None;
+
+    for (key, val) in inner_object.iter() {
+        match key.as_ref() {
+            "top_left" => {
+                let point = try!(parse_point(val));
+                top = Some(point.lat);
+                left = Some(point.lon);
+            }
+            "bottom_right" => {
+                let point = try!(parse_point(val));
+                bottom = Some(point.lat);
+                right = Some(point.lon);
+            }
+            "top_right" => {
+                let point = try!(parse_point(val));
+                top = Some(point.lat);
+                right = Some(point.lon);
+            }
+            "bottom_left" => {
+                let point = try!(parse_point(val));
+                bottom = Some(point.lat);
+                left = Some(point.lon);
+            }
+            "top" => top = Some(try!(parse_float(val))),
+            "left" => left = Some(try!(parse_float(val))),
+            "bottom" => bottom = Some(try!(parse_float(val))),
+            "right" => right = Some(try!(parse_float(val))),
+            _ => return Err(QueryParseError::UnrecognisedKey(key.clone()))
+        }
+    }
+
+    let bounding_box = BoundingBox {
+        top: try!(top.ok_or(QueryParseError::ExpectedKey("top_left"))),
+        left: try!(left.ok_or(QueryParseError::ExpectedKey("top_left"))),
+        bottom: try!(bottom.ok_or(QueryParseError::ExpectedKey("bottom_right"))),
+        right: try!(right.ok_or(QueryParseError::ExpectedKey("bottom_right"))),
+    };
+
+    if bounding_box.top < bounding_box.bottom {
+        return Err(QueryParseError::InvalidValue);
+    }
+
+    Ok(Box::new(GeoBoundingBoxQueryBuilder::new(field_name, bounding_box)))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+    use serde_json::value::ToJson;
+
+    use kite::{Query, TermSelector, TermScorer};
+    use kite::schema::{Schema, FieldType, FIELD_INDEXED};
+
+    use geo::BoundingBox;
+    use query_parser::{QueryBuildContext, QueryParseError};
+
+    use super::parse;
+
+    #[test]
+    fn test_geo_bounding_box_query() {
+        let mut schema = Schema::new();
+        let location_field = schema.add_field("location".to_string(), FieldType::Text, FIELD_INDEXED).unwrap();
+
+        let query = parse(&serde_json::from_str("
+        {
+            \"location\": {
+                \"top_left\": {
+                    \"lat\": 40.73,
+                    \"lon\": -74.1
+                },
+                \"bottom_right\": \"40.01,-71.12\"
+            }
+        }
+        ").unwrap()).and_then(|builder| Ok(builder.build(&QueryBuildContext::new(), &schema)));
+
+        let bounding_box = BoundingBox {
+            top: 40.73,
+            left: -74.1,
+            bottom: 40.01,
+            right: -71.12,
+        };
+
+        assert_eq!(query, Ok(Query::new_disjunction(bounding_box.covering_geohashes().into_iter().map(|geohash| {
+            Query::MultiTerm {
+                field: location_field,
+                term_selector: TermSelector::Prefix(geohash),
+                scorer: TermScorer::default(),
+            }
+        }).collect())));
+    }
+
+    #[test]
+    fn test_with_coordinates() {
+        let query = parse(&serde_json::from_str("
+        {
+            \"location\": {
+                \"top\": 40.73,
+                \"left\": -74.1,
+                \"bottom\": 40.01,
+                \"right\": -71.12
+            }
+        }
+        ").unwrap()).and_then(|builder| Ok(builder.to_json().unwrap()));
+
+        assert_eq!(query, Ok(json!({
+            "geo_bounding_box": {
+                "location": {
+                    "top_left": {
+                        "lat": 40.73,
+                        "lon": -74.1
+                    },
+                    "bottom_right": {
+                        "lat": 40.01,
+                        "lon": -71.12
+                    }
+                }
+            }
+        })));
+    }
+
+    #[test]
+    fn test_gives_error_for_missing_corner() {
+        let query = parse(&serde_json::from_str("
+        {
+            \"location\": {
+                \"top_left\": [-74.1, 40.73]
+            }
+        }
+        ").unwrap());
+
+        assert_eq!(query.err(), Some(QueryParseError::ExpectedKey("bottom_right")));
+    }
+
+    #[test]
+    fn test_gives_error_for_invalid_point() {
+        let query = parse(&serde_json::from_str("
+        {
+            \"location\": {
+                \"top_left\": \"foo\",
+                \"bottom_right\": [-71.12, 40.01]
+            }
+        }
+        ").unwrap());
+
+        assert_eq!(query.err(), Some(QueryParseError::InvalidValue));
+    }
+
+    #[test]
+    fn test_gives_error_for_upside_down_box() {
+        let query = parse(&serde_json::from_str("
+        {
+            \"location\": {
+                \"top_left\": [-74.1, 40.01],
+                \"bottom_right\": [-71.12, 40.73]
+            }
+        }
+        ").unwrap());
+
+        assert_eq!(query.err(), Some(QueryParseError::InvalidValue));
+    }
+}
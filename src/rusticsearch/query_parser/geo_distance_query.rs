@@ -0,0 +1,195 @@
+//! Parses "geo_distance" queries
+
+use std::collections::BTreeMap;
+
+use serde_json;
+use serde_json::Value as Json;
+use serde_json::value::ToJson;
+use kite::Query;
+use kite::schema::Schema;
+
+use geo::{GeoPoint, covering_geohashes_within, parse_distance};
+use query_parser::{QueryBuildContext, QueryParseError, QueryBuilder};
+use query_parser::geo_bounding_box_query::build_covering_query;
+
+
+#[derive(Debug)]
+pub struct GeoDistanceQueryBuilder {
+    field: String,
+    centre: GeoPoint,
+    distance: f64,
+}
+
+
+impl GeoDistanceQueryBuilder {
+    pub fn new(field: &str, centre: GeoPoint, distance: f64) -> GeoDistanceQueryBuilder {
+        GeoDistanceQueryBuilder {
+            field: field.to_string(),
+            centre: centre,
+            distance: distance,
+        }
+    }
+}
+
+
+impl QueryBuilder for GeoDistanceQueryBuilder {
+    fn build(&self, context: &QueryBuildContext, schema: &Schema) -> Query {
+        let field = schema.get_field_by_name(&self.field).unwrap();
+
+        // Points are indexed as geohashes, so search for the prefixes of the cells that cover the circle.
+        // Cells are treated as inside the circle if all of their corners are
+        build_covering_query(context, field, covering_geohashes_within(&self.centre, self.distance), |cell| {
+            cell.corners().iter().all(|corner| corner.distance(&self.centre) <= self.distance)
+        }, |point| {
+            point.distance(&self.centre) <= self.distance
+        })
+    }
+}
+
+
+impl ToJson for GeoDistanceQueryBuilder {
+    fn to_json(&self) -> Result<Json, serde_json::Error> {
+        let mut json = BTreeMap::new();
+        json.insert("distance".to_string(), json!(self.distance));
+        json.insert(self.field.clone(), json!({
+            "lat": self.centre.lat,
+            "lon": self.centre.lon,
+        }));
+
+        Ok(json!({
+            "geo_distance": json
+        }))
+    }
+}
+
+
+pub fn parse(json: &Json) -> Result<Box<QueryBuilder>, QueryParseError> {
+    let object = try!(json.as_object().ok_or(QueryParseError::ExpectedObject));
+
+    let mut field = None;
+    let mut distance = None;
+
+    for (key, val) in object.iter() {
+        match key.as_ref() {
+            "distance" => {
+                distance = Some(try!(parse_distance(val).ok_or(QueryParseError::InvalidValue)));
+            }
+            _ => {
+                // Any other key is the name of the field with the point in its value
+                if field.is_some() {
+                    return Err(QueryParseError::UnrecognisedKey(key.clone()));
+                }
+
+                let centre = try!(GeoPoint::from_json(val).ok_or(QueryParseError::InvalidValue));
+                field = Some((key, centre));
+            }
+        }
+    }
+
+    let distance = try!(distance.ok_or(QueryParseError::ExpectedKey("distance")));
+    if distance < 0.0 {
+        return Err(QueryParseError::InvalidValue);
+    }
+
+    match field {
+        Some((field_name, centre)) => Ok(Box::new(GeoDistanceQueryBuilder::new(field_name, centre, distance))),
+        None => Err(QueryParseError::ExpectedSingleKey),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+    use serde_json::value::ToJson;
+
+    use kite::{Query, TermSelector, TermScorer};
+    use kite::schema::{Schema, FieldType, FIELD_INDEXED};
+
+    use geo::{GeoPoint, covering_geohashes_within};
+    use query_parser::{QueryBuildContext, QueryParseError};
+
+    use super::parse;
+
+    #[test]
+    fn test_geo_distance_query() {
+        let mut schema = Schema::new();
+        let location_field = schema.add_field("location".to_string(), FieldType::Text, FIELD_INDEXED).unwrap();
+
+        let query = parse(&serde_json::from_str("
+        {
+            \"distance\": \"10km\",
+            \"location\": {
+                \"lat\": 51.5074,
+                \"lon\": -0.1278
+            }
+        }
+        ").unwrap()).and_then(|builder| Ok(builder.build(&QueryBuildContext::new(), &schema)));
+
+        let centre = GeoPoint::new(51.5074, -0.1278).unwrap();
+
+        assert_eq!(query, Ok(Query::new_disjunction(covering_geohashes_within(&centre, 10000.0).into_iter().map(|geohash| {
+            Query::MultiTerm {
+                field: location_field,
+                term_selector: TermSelector::Prefix(geohash),
+                scorer: TermScorer::default(),
+            }
+        }).collect())));
+    }
+
+    #[test]
+    fn test_to_json() {
+        let query = parse(&serde_json::from_str("
+        {
+            \"distance\": \"2km\",
+            \"location\": [-0.1278, 51.5074]
+        }
+        ").unwrap()).and_then(|builder| Ok(builder.to_json().unwrap()));
+
+        assert_eq!(query, Ok(json!({
+            "geo_distance": {
+                "distance": 2000.0,
+                "location": {
+                    "lat": 51.5074,
+                    "lon": -0.1278
+                }
+            }
+        })));
+    }
+
+    #[test]
+    fn test_gives_error_for_missing_distance() {
+        let query = parse(&serde_json::from_str("
+        {
+            \"location\": \"51.5074,-0.1278\"
+        }
+        ").unwrap());
+
+        assert_eq!(query.err(), Some(QueryParseError::ExpectedKey("distance")));
+    }
+
+    #[test]
+    fn test_gives_error_for_invalid_distance() {
+        let query = parse(&serde_json::from_str("
+        {
+            \"distance\": \"far\",
+            \"location\": \"51.5074,-0.1278\"
+        }
+        ").unwrap());
+
+        assert_eq!(query.err(), Some(QueryParseError::InvalidValue));
+    }
+
+    #[test]
+    fn test_gives_error_for_extra_key() {
+        let query = parse(&serde_json::from_str("
+        {
+            \"distance\": \"10km\",
+            \"location\": \"51.5074,-0.1278\",
+            \"other_location\": \"48.8566,2.3522\"
+        }
+        ").unwrap());
+
+        assert!(query.is_err());
+    }
+}
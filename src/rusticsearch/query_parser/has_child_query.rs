@@ -0,0 +1,449 @@
+//! Parses "has_child" queries
+
+use std::collections::{HashMap, BTreeMap};
+
+use serde_json;
+use serde_json::Value as Json;
+use serde_json::value::ToJson;
+use kite::{Term, Query, TermScorer};
+use kite::schema::{Schema, FieldRef};
+use kite::document::{DocRef, FieldValue};
+
+use collectors::AllDocumentsCollector;
+use mapping::join_parent_field_name;
+use query_parser::{QueryBuildContext, QueryParseError, QueryBuilder, parse as parse_query};
+use query_parser::utils::{parse_string, parse_float, parse_positive_integer};
+
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ScoreMode {
+    None,
+    Avg,
+    Sum,
+    Max,
+    Min,
+}
+
+
+impl ScoreMode {
+    fn to_string(&self) -> String {
+        match *self {
+            ScoreMode::None => "none".to_string(),
+            ScoreMode::Avg => "avg".to_string(),
+            ScoreMode::Sum => "sum".to_string(),
+            ScoreMode::Max => "max".to_string(),
+            ScoreMode::Min => "min".to_string(),
+        }
+    }
+
+    fn combine(&self, scores: &[f64]) -> f64 {
+        match *self {
+            ScoreMode::None => 1.0f64,
+            ScoreMode::Avg => scores.iter().fold(0.0f64, |acc, score| acc + score) / scores.len() as f64,
+            ScoreMode::Sum => scores.iter().fold(0.0f64, |acc, score| acc + score),
+            ScoreMode::Max => scores.iter().cloned().fold(::std::f64::MIN, f64::max),
+            ScoreMode::Min => scores.iter().cloned().fold(::std::f64::MAX, f64::min),
+        }
+    }
+}
+
+
+fn parse_score_mode(json: &Json) -> Result<ScoreMode, QueryParseError> {
+    match try!(parse_string(json)).as_ref() {
+        "none" => Ok(ScoreMode::None),
+        "avg" => Ok(ScoreMode::Avg),
+        "sum" => Ok(ScoreMode::Sum),
+        "max" => Ok(ScoreMode::Max),
+        "min" => Ok(ScoreMode::Min),
+        _ => Err(QueryParseError::InvalidScoreMode),
+    }
+}
+
+
+/// Runs a query against the index and reads the key stored in the given parent key field
+/// of each matching document
+///
+/// Returns a map of keys to the scores of all documents that referenced them.
+pub fn collect_join_keys(context: &QueryBuildContext, query: &Query, key_field: FieldRef) -> HashMap<String, Vec<f64>> {
+    let mut keys: HashMap<String, Vec<f64>> = HashMap::new();
+
+    let index_reader = match context.index_reader {
+        Some(index_reader) => index_reader,
+        None => return keys,
+    };
+
+    let mut collector = AllDocumentsCollector::new();
+    if index_reader.search(&mut collector, query).is_err() {
+        return keys;
+    }
+
+    for (doc_id, score) in collector.into_vec() {
+        if let Ok(Some(FieldValue::String(key))) = index_reader.read_stored_field(key_field, DocRef::from_u64(doc_id)) {
+            keys.entry(key).or_insert_with(Vec::new).push(score.unwrap_or(1.0f64));
+        }
+    }
+
+    keys
+}
+
+
+/// Builds a query that matches the documents with the given keys in their parent key
+/// field, giving each one the specified score
+pub fn build_join_keys_query(key_field: FieldRef, keys: Vec<(String, f64)>) -> Query {
+    if keys.is_empty() {
+        return Query::None;
+    }
+
+    Query::new_disjunction_max(keys.into_iter().map(|(key, score)| {
+        Query::Filter {
+            query: Box::new(Query::All {
+                score: score,
+            }),
+            filter: Box::new(Query::Term {
+                field: key_field,
+                term: Term::from_string(&key),
+                scorer: TermScorer::default(),
+            }),
+        }
+    }).collect())
+}
+
+
+#[derive(Debug)]
+pub struct HasChildQueryBuilder {
+    child_type: String,
+    query: Box<QueryBuilder>,
+    score_mode: ScoreMode,
+    min_children: Option<u64>,
+    max_children: Option<u64>,
+    boost: f64,
+}
+
+
+impl HasChildQueryBuilder {
+    pub fn new(child_type: &str, query: Box<QueryBuilder>) -> HasChildQueryBuilder {
+        HasChildQueryBuilder {
+            child_type: child_type.to_string(),
+            query: query,
+            score_mode: ScoreMode::None,
+            min_children: None,
+            max_children: None,
+            boost: 1.0f64,
+        }
+    }
+
+    pub fn score_mode(mut self, score_mode: ScoreMode) -> HasChildQueryBuilder {
+        self.score_mode = score_mode;
+        self
+    }
+
+    pub fn min_children(mut self, min_children: u64) -> HasChildQueryBuilder {
+        self.min_children = Some(min_children);
+        self
+    }
+
+    pub fn max_children(mut self, max_children: u64) -> HasChildQueryBuilder {
+        self.max_children = Some(max_children);
+        self
+    }
+
+    pub fn boost(mut self, boost: f64) -> HasChildQueryBuilder {
+        self.boost = boost;
+        self
+    }
+}
+
+
+impl QueryBuilder for HasChildQueryBuilder {
+    fn build(&self, context: &QueryBuildContext, schema: &Schema) -> Query {
+        let (join_field_name, join_field_mapping) = match context.index_metadata.and_then(|index_metadata| index_metadata.get_join_field()) {
+            Some(join_field) => join_field,
+            None => return Query::None,
+        };
+
+        let parent_type = match join_field_mapping.get_join_parent(&self.child_type) {
+            Some(parent_type) => parent_type,
+            None => return Query::None,
+        };
+
+        let join_field = match schema.get_field_by_name(join_field_name) {
+            Some(join_field) => join_field,
+            None => return Query::None,
+        };
+
+        let parent_key_field = match schema.get_field_by_name(&join_parent_field_name(join_field_name, parent_type)) {
+            Some(parent_key_field) => parent_key_field,
+            None => return Query::None,
+        };
+
+        // Find children
+        let child_query = Query::Filter {
+            query: Box::new(self.query.build(context, schema)),
+            filter: Box::new(Query::Term {
+                field: join_field,
+                term: Term::from_string(&self.child_type),
+                scorer: TermScorer::default(),
+            }),
+        };
+
+        let child_scores = collect_join_keys(context, &child_query, parent_key_field);
+
+        // Work out the score of each parent
+        let mut parents = Vec::new();
+        for (parent_key, scores) in child_scores {
+            let num_children = scores.len() as u64;
+
+            if let Some(min_children) = self.min_children {
+                if num_children < min_children {
+                    continue;
+                }
+            }
+
+            if let Some(max_children) = self.max_children {
+                if num_children > max_children {
+                    continue;
+                }
+            }
+
+            let score = if context.score_required() {
+                self.score_mode.combine(&scores)
+            } else {
+                1.0f64
+            };
+
+            parents.push((parent_key, score));
+        }
+
+        if parents.is_empty() {
+            return Query::None;
+        }
+
+        // Parent documents share the parent key field with their children so they
+        // must be filtered by relation as well
+        let mut query = Query::Filter {
+            query: Box::new(build_join_keys_query(parent_key_field, parents)),
+            filter: Box::new(Query::Term {
+                field: join_field,
+                term: Term::from_string(parent_type),
+                scorer: TermScorer::default(),
+            }),
+        };
+
+        // Add boost (scores are thrown away in filter context)
+        if context.score_required() {
+            query.boost(self.boost);
+        }
+
+        query
+    }
+}
+
+
+impl ToJson for HasChildQueryBuilder {
+    fn to_json(&self) -> Result<Json, serde_json::Error> {
+        let mut inner = BTreeMap::new();
+        inner.insert("type".to_string(), json!(self.child_type));
+        inner.insert("query".to_string(), try!(self.query.to_json()));
+        inner.insert("score_mode".to_string(), json!(self.score_mode.to_string()));
+
+        if let Some(min_children) = self.min_children {
+            inner.insert("min_children".to_string(), json!(min_children));
+        }
+
+        if let Some(max_children) = self.max_children {
+            inner.insert("max_children".to_string(), json!(max_children));
+        }
+
+        inner.insert("boost".to_string(), json!(self.boost));
+
+        Ok(json!({
+            "has_child": inner
+        }))
+    }
+}
+
+
+pub fn parse(json: &Json) -> Result<Box<QueryBuilder>, QueryParseError> {
+    let object = try!(json.as_object().ok_or(QueryParseError::ExpectedObject));
+
+    let mut child_type = None;
+    let mut query = None;
+    let mut score_mode = ScoreMode::None;
+    let mut min_children = None;
+    let mut max_children = None;
+    let mut boost = 1.0f64;
+
+    for (key, value) in object.iter() {
+        match key.as_ref() {
+            "type" => {
+                child_type = Some(try!(parse_string(value)));
+            }
+            "query" => {
+                query = Some(try!(parse_query(value)));
+            }
+            "score_mode" => {
+                score_mode = try!(parse_score_mode(value));
+            }
+            "min_children" => {
+                min_children = Some(try!(parse_positive_integer(value)));
+            }
+            "max_children" => {
+                max_children = Some(try!(parse_positive_integer(value)));
+            }
+            "boost" => {
+                boost = try!(parse_float(value));
+            }
+            _ => return Err(QueryParseError::UnrecognisedKey(key.clone()))
+        }
+    }
+
+    let child_type = match child_type {
+        Some(child_type) => child_type,
+        None => return Err(QueryParseError::ExpectedKey("type")),
+    };
+
+    let query = match query {
+        Some(query) => query,
+        None => return Err(QueryParseError::ExpectedKey("query")),
+    };
+
+    Ok(Box::new(HasChildQueryBuilder {
+        child_type: child_type,
+        query: query,
+        score_mode: score_mode,
+        min_children: min_children,
+        max_children: max_children,
+        boost: boost,
+    }))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+
+    use kite::Query;
+    use kite::schema::{Schema, FieldType, FIELD_INDEXED};
+
+    use query_parser::{QueryBuildContext, QueryParseError};
+
+    use super::{parse, ScoreMode};
+
+    #[test]
+    fn test_has_child_query_without_index() {
+        let mut schema = Schema::new();
+        schema.add_field("test".to_string(), FieldType::Text, FIELD_INDEXED).unwrap();
+
+        let query = parse(&serde_json::from_str("
+        {
+            \"type\": \"answer\",
+            \"query\": {
+                \"term\": {
+                    \"test\": \"foo\"
+                }
+            }
+        }
+        ").unwrap()).and_then(|builder| Ok(builder.build(&QueryBuildContext::new(), &schema)));
+
+        assert_eq!(query, Ok(Query::None));
+    }
+
+    #[test]
+    fn test_score_modes() {
+        let scores = vec![1.0f64, 2.0f64, 3.0f64];
+
+        assert_eq!(ScoreMode::None.combine(&scores), 1.0f64);
+        assert_eq!(ScoreMode::Avg.combine(&scores), 2.0f64);
+        assert_eq!(ScoreMode::Sum.combine(&scores), 6.0f64);
+        assert_eq!(ScoreMode::Max.combine(&scores), 3.0f64);
+        assert_eq!(ScoreMode::Min.combine(&scores), 1.0f64);
+    }
+
+    #[test]
+    fn test_gives_error_for_incorrect_type() {
+        // String
+        let query = parse(&serde_json::from_str("
+        \"hello\"
+        ").unwrap());
+
+        assert_eq!(query.err(), Some(QueryParseError::ExpectedObject));
+
+        // Array
+        let query = parse(&serde_json::from_str("
+        [
+            \"foo\"
+        ]
+        ").unwrap());
+
+        assert_eq!(query.err(), Some(QueryParseError::ExpectedObject));
+    }
+
+    #[test]
+    fn test_gives_error_for_missing_type() {
+        let query = parse(&serde_json::from_str("
+        {
+            \"query\": {
+                \"match_all\": {}
+            }
+        }
+        ").unwrap());
+
+        assert_eq!(query.err(), Some(QueryParseError::ExpectedKey("type")));
+    }
+
+    #[test]
+    fn test_gives_error_for_missing_query() {
+        let query = parse(&serde_json::from_str("
+        {
+            \"type\": \"answer\"
+        }
+        ").unwrap());
+
+        assert_eq!(query.err(), Some(QueryParseError::ExpectedKey("query")));
+    }
+
+    #[test]
+    fn test_gives_error_for_invalid_score_mode() {
+        let query = parse(&serde_json::from_str("
+        {
+            \"type\": \"answer\",
+            \"query\": {
+                \"match_all\": {}
+            },
+            \"score_mode\": \"foo\"
+        }
+        ").unwrap());
+
+        assert_eq!(query.err(), Some(QueryParseError::InvalidScoreMode));
+    }
+
+    #[test]
+    fn test_gives_error_for_invalid_min_children() {
+        let query = parse(&serde_json::from_str("
+        {
+            \"type\": \"answer\",
+            \"query\": {
+                \"match_all\": {}
+            },
+            \"min_children\": -1
+        }
+        ").unwrap());
+
+        assert_eq!(query.err(), Some(QueryParseError::ExpectedPositiveInteger));
+    }
+
+    #[test]
+    fn test_gives_error_for_unexpected_key() {
+        let query = parse(&serde_json::from_str("
+        {
+            \"type\": \"answer\",
+            \"query\": {
+                \"match_all\": {}
+            },
+            \"foo\": \"bar\"
+        }
+        ").unwrap());
+
+        assert_eq!(query.err(), Some(QueryParseError::UnrecognisedKey("foo".to_string())));
+    }
+}
@@ -0,0 +1,260 @@
+//! Parses "has_parent" queries
+
+use serde_json;
+use serde_json::Value as Json;
+use serde_json::value::ToJson;
+use kite::{Term, Query, TermScorer};
+use kite::schema::Schema;
+
+use mapping::join_parent_field_name;
+use query_parser::{QueryBuildContext, QueryParseError, QueryBuilder, parse as parse_query};
+use query_parser::utils::{parse_string, parse_float, parse_boolean};
+use query_parser::has_child_query::{collect_join_keys, build_join_keys_query};
+
+
+#[derive(Debug)]
+pub struct HasParentQueryBuilder {
+    parent_type: String,
+    query: Box<QueryBuilder>,
+    score: bool,
+    boost: f64,
+}
+
+
+impl HasParentQueryBuilder {
+    pub fn new(parent_type: &str, query: Box<QueryBuilder>) -> HasParentQueryBuilder {
+        HasParentQueryBuilder {
+            parent_type: parent_type.to_string(),
+            query: query,
+            score: false,
+            boost: 1.0f64,
+        }
+    }
+
+    pub fn score(mut self, score: bool) -> HasParentQueryBuilder {
+        self.score = score;
+        self
+    }
+
+    pub fn boost(mut self, boost: f64) -> HasParentQueryBuilder {
+        self.boost = boost;
+        self
+    }
+}
+
+
+impl QueryBuilder for HasParentQueryBuilder {
+    fn build(&self, context: &QueryBuildContext, schema: &Schema) -> Query {
+        let (join_field_name, join_field_mapping) = match context.index_metadata.and_then(|index_metadata| index_metadata.get_join_field()) {
+            Some(join_field) => join_field,
+            None => return Query::None,
+        };
+
+        let child_types = match join_field_mapping.get_join_children(&self.parent_type) {
+            Some(child_types) => child_types,
+            None => return Query::None,
+        };
+
+        let join_field = match schema.get_field_by_name(join_field_name) {
+            Some(join_field) => join_field,
+            None => return Query::None,
+        };
+
+        let parent_key_field = match schema.get_field_by_name(&join_parent_field_name(join_field_name, &self.parent_type)) {
+            Some(parent_key_field) => parent_key_field,
+            None => return Query::None,
+        };
+
+        // Find parents
+        let parent_query = Query::Filter {
+            query: Box::new(self.query.build(context, schema)),
+            filter: Box::new(Query::Term {
+                field: join_field,
+                term: Term::from_string(&self.parent_type),
+                scorer: TermScorer::default(),
+            }),
+        };
+
+        let parent_scores = collect_join_keys(context, &parent_query, parent_key_field);
+
+        let parents = parent_scores.into_iter().map(|(parent_key, scores)| {
+            let score = if self.score && context.score_required() {
+                scores.iter().cloned().fold(0.0f64, f64::max)
+            } else {
+                1.0f64
+            };
+
+            (parent_key, score)
+        }).collect::<Vec<_>>();
+
+        if parents.is_empty() {
+            return Query::None;
+        }
+
+        // Parent documents share the parent key field with their children so they
+        // must be filtered out by relation
+        let child_filter = Query::new_disjunction(child_types.iter().map(|child_type| {
+            Query::Term {
+                field: join_field,
+                term: Term::from_string(child_type),
+                scorer: TermScorer::default(),
+            }
+        }).collect());
+
+        let mut query = Query::Filter {
+            query: Box::new(build_join_keys_query(parent_key_field, parents)),
+            filter: Box::new(child_filter),
+        };
+
+        // Add boost (scores are thrown away in filter context)
+        if context.score_required() {
+            query.boost(self.boost);
+        }
+
+        query
+    }
+}
+
+
+impl ToJson for HasParentQueryBuilder {
+    fn to_json(&self) -> Result<Json, serde_json::Error> {
+        Ok(json!({
+            "has_parent": {
+                "parent_type": self.parent_type,
+                "query": try!(self.query.to_json()),
+                "score": self.score,
+                "boost": self.boost,
+            }
+        }))
+    }
+}
+
+
+pub fn parse(json: &Json) -> Result<Box<QueryBuilder>, QueryParseError> {
+    let object = try!(json.as_object().ok_or(QueryParseError::ExpectedObject));
+
+    let mut parent_type = None;
+    let mut query = None;
+    let mut score = false;
+    let mut boost = 1.0f64;
+
+    for (key, value) in object.iter() {
+        match key.as_ref() {
+            "parent_type" => {
+                parent_type = Some(try!(parse_string(value)));
+            }
+            "query" => {
+                query = Some(try!(parse_query(value)));
+            }
+            "score" => {
+                score = try!(parse_boolean(value));
+            }
+            "boost" => {
+                boost = try!(parse_float(value));
+            }
+            _ => return Err(QueryParseError::UnrecognisedKey(key.clone()))
+        }
+    }
+
+    let parent_type = match parent_type {
+        Some(parent_type) => parent_type,
+        None => return Err(QueryParseError::ExpectedKey("parent_type")),
+    };
+
+    let query = match query {
+        Some(query) => query,
+        None => return Err(QueryParseError::ExpectedKey("query")),
+    };
+
+    Ok(Box::new(HasParentQueryBuilder {
+        parent_type: parent_type,
+        query: query,
+        score: score,
+        boost: boost,
+    }))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+
+    use kite::Query;
+    use kite::schema::{Schema, FieldType, FIELD_INDEXED};
+
+    use query_parser::{QueryBuildContext, QueryParseError};
+
+    use super::parse;
+
+    #[test]
+    fn test_has_parent_query_without_index() {
+        let mut schema = Schema::new();
+        schema.add_field("test".to_string(), FieldType::Text, FIELD_INDEXED).unwrap();
+
+        let query = parse(&serde_json::from_str("
+        {
+            \"parent_type\": \"question\",
+            \"query\": {
+                \"term\": {
+                    \"test\": \"foo\"
+                }
+            },
+            \"score\": true
+        }
+        ").unwrap()).and_then(|builder| Ok(builder.build(&QueryBuildContext::new(), &schema)));
+
+        assert_eq!(query, Ok(Query::None));
+    }
+
+    #[test]
+    fn test_gives_error_for_incorrect_type() {
+        let query = parse(&serde_json::from_str("
+        \"hello\"
+        ").unwrap());
+
+        assert_eq!(query.err(), Some(QueryParseError::ExpectedObject));
+    }
+
+    #[test]
+    fn test_gives_error_for_missing_parent_type() {
+        let query = parse(&serde_json::from_str("
+        {
+            \"query\": {
+                \"match_all\": {}
+            }
+        }
+        ").unwrap());
+
+        assert_eq!(query.err(), Some(QueryParseError::ExpectedKey("parent_type")));
+    }
+
+    #[test]
+    fn test_gives_error_for_invalid_score() {
+        let query = parse(&serde_json::from_str("
+        {
+            \"parent_type\": \"question\",
+            \"query\": {
+                \"match_all\": {}
+            },
+            \"score\": \"yes\"
+        }
+        ").unwrap());
+
+        assert_eq!(query.err(), Some(QueryParseError::ExpectedBoolean));
+    }
+
+    #[test]
+    fn test_gives_error_for_unexpected_key() {
+        let query = parse(&serde_json::from_str("
+        {
+            \"parent_type\": \"question\",
+            \"query\": {
+                \"match_all\": {}
+            },
+            \"foo\": \"bar\"
+        }
+        ").unwrap());
+
+        assert_eq!(query.err(), Some(QueryParseError::UnrecognisedKey("foo".to_string())));
+    }
+}
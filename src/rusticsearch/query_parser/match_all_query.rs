@@ -1,6 +1,8 @@
 //! Parses "match_all" queries
 
+use serde_json;
 use serde_json::Value as Json;
+use serde_json::value::ToJson;
 use kite::Query;
 use kite::schema::Schema;
 
@@ -9,23 +11,50 @@ use query_parser::utils::parse_float;
 
 
 #[derive(Debug)]
-struct MatchAllQueryBuilder {
+pub struct MatchAllQueryBuilder {
     boost: f64,
 }
 
 
+impl MatchAllQueryBuilder {
+    pub fn new() -> MatchAllQueryBuilder {
+        MatchAllQueryBuilder {
+            boost: 1.0f64,
+        }
+    }
+
+    pub fn boost(mut self, boost: f64) -> MatchAllQueryBuilder {
+        self.boost = boost;
+        self
+    }
+}
+
+
 impl QueryBuilder for MatchAllQueryBuilder {
-    fn build(&self, _context: &QueryBuildContext, _schema: &Schema) -> Query {
+    fn build(&self, context: &QueryBuildContext, _schema: &Schema) -> Query {
         let mut query = Query::new_all();
 
-        // Add boost
-        query.boost(self.boost);
+        // Add boost (scores are thrown away in filter context)
+        if context.score_required() {
+            query.boost(self.boost);
+        }
 
         query
     }
 }
 
 
+impl ToJson for MatchAllQueryBuilder {
+    fn to_json(&self) -> Result<Json, serde_json::Error> {
+        Ok(json!({
+            "match_all": {
+                "boost": self.boost,
+            }
+        }))
+    }
+}
+
+
 pub fn parse(json: &Json) -> Result<Box<QueryBuilder>, QueryParseError> {
     let object = try!(json.as_object().ok_or(QueryParseError::ExpectedObject));
 
@@ -1,6 +1,8 @@
 //! Parses "match_none" queries
 
+use serde_json;
 use serde_json::Value as Json;
+use serde_json::value::ToJson;
 use kite::Query;
 use kite::schema::Schema;
 
@@ -8,7 +10,7 @@ use query_parser::{QueryBuildContext, QueryParseError, QueryBuilder};
 
 
 #[derive(Debug)]
-struct MatchNoneQueryBuilder;
+pub struct MatchNoneQueryBuilder;
 
 
 impl QueryBuilder for MatchNoneQueryBuilder {
@@ -18,6 +20,15 @@ impl QueryBuilder for MatchNoneQueryBuilder {
 }
 
 
+impl ToJson for MatchNoneQueryBuilder {
+    fn to_json(&self) -> Result<Json, serde_json::Error> {
+        Ok(json!({
+            "match_none": {}
+        }))
+    }
+}
+
+
 pub fn parse(json: &Json) -> Result<Box<QueryBuilder>, QueryParseError> {
     let object = try!(json.as_object().ok_or(QueryParseError::ExpectedObject));
 
@@ -1,7 +1,11 @@
 //! Parses "match" queries
 
+use std::collections::BTreeMap;
+
+use serde_json;
 use serde_json::Value as Json;
-use kite::{Term, Token, Query, TermScorer};
+use serde_json::value::ToJson;
+use kite::{Term, Token, Query};
 use kite::schema::Schema;
 
 use mapping::FieldSearchOptions;
@@ -11,7 +15,7 @@ use query_parser::utils::{parse_string, parse_float, Operator, parse_operator};
 
 
 #[derive(Debug)]
-struct MatchQueryBuilder {
+pub struct MatchQueryBuilder {
     field: String,
     query: String,
     operator: Operator,
@@ -19,6 +23,28 @@ struct MatchQueryBuilder {
 }
 
 
+impl MatchQueryBuilder {
+    pub fn new(field: &str, query: &str) -> MatchQueryBuilder {
+        MatchQueryBuilder {
+            field: field.to_string(),
+            query: query.to_string(),
+            operator: Operator::Or,
+            boost: 1.0f64,
+        }
+    }
+
+    pub fn operator(mut self, operator: Operator) -> MatchQueryBuilder {
+        self.operator = operator;
+        self
+    }
+
+    pub fn boost(mut self, boost: f64) -> MatchQueryBuilder {
+        self.boost = boost;
+        self
+    }
+}
+
+
 impl QueryBuilder for MatchQueryBuilder {
     fn build(&self, context: &QueryBuildContext, schema: &Schema) -> Query {
         // Get search options for field
@@ -49,7 +75,7 @@ impl QueryBuilder for MatchQueryBuilder {
             sub_queries.push(Query::Term {
                 field: schema.get_field_by_name(&self.field).unwrap(),
                 term: token.term,
-                scorer: TermScorer::default(),
+                scorer: field_search_options.term_scorer(),
             });
         }
 
@@ -63,14 +89,32 @@ impl QueryBuilder for MatchQueryBuilder {
             }
         };
 
-        // Add boost
-        query.boost(self.boost);
+        // Add boost (scores are thrown away in filter context)
+        if context.score_required() {
+            query.boost(self.boost);
+        }
 
         query
     }
 }
 
 
+impl ToJson for MatchQueryBuilder {
+    fn to_json(&self) -> Result<Json, serde_json::Error> {
+        let mut field_json = BTreeMap::new();
+        field_json.insert(self.field.clone(), json!({
+            "query": self.query,
+            "operator": try!(self.operator.to_json()),
+            "boost": self.boost,
+        }));
+
+        Ok(json!({
+            "match": field_json
+        }))
+    }
+}
+
+
 pub fn parse(json: &Json) -> Result<Box<QueryBuilder>, QueryParseError> {
     let object = try!(json.as_object().ok_or(QueryParseError::ExpectedObject));
 
@@ -125,6 +169,7 @@ pub fn parse(json: &Json) -> Result<Box<QueryBuilder>, QueryParseError> {
 #[cfg(test)]
 mod tests {
     use serde_json;
+    use serde_json::value::ToJson;
 
     use kite::{Term, Query, TermScorer};
     use kite::schema::{Schema, FieldType, FIELD_INDEXED};
@@ -387,4 +432,23 @@ mod tests {
 
         assert_eq!(query.err(), Some(QueryParseError::UnrecognisedKey("hello".to_string())));
     }
+
+    #[test]
+    fn test_to_json() {
+        let query = parse(&serde_json::from_str("
+        {
+            \"foo\": \"bar\"
+        }
+        ").unwrap()).and_then(|builder| Ok(builder.to_json().unwrap()));
+
+        assert_eq!(query, Ok(json!({
+            "match": {
+                "foo": {
+                    "query": "bar",
+                    "operator": "or",
+                    "boost": 1.0
+                }
+            }
+        })));
+    }
 }
@@ -12,27 +12,57 @@ pub mod prefix_query;
 pub mod and_query;
 pub mod or_query;
 pub mod not_query;
-
+pub mod has_child_query;
+pub mod has_parent_query;
+pub mod bool_query;
+pub mod dis_max_query;
+pub mod geo_bounding_box_query;
+pub mod geo_distance_query;
+pub mod builder;
+
+use std::fmt;
 use std::fmt::Debug;
 
 use serde_json::Value as Json;
+use serde_json::value::ToJson;
 use kite::Query;
 use kite::schema::Schema;
+use kite_rocksdb::RocksDBIndexReader;
 
 use index::metadata::IndexMetadata;
+use cluster::metadata::ClusterMetadata;
 
 
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct QueryBuildContext<'a> {
     pub index_metadata: Option<&'a IndexMetadata>,
+    pub index_reader: Option<&'a RocksDBIndexReader<'a>>,
+    pub cluster_metadata: Option<&'a ClusterMetadata>,
+    pub search_query: Option<&'a QueryBuilder>,
     score_required: bool,
 }
 
 
+impl<'a> fmt::Debug for QueryBuildContext<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("QueryBuildContext")
+            .field("index_metadata", &self.index_metadata)
+            .field("has_index_reader", &self.index_reader.is_some())
+            .field("has_cluster_metadata", &self.cluster_metadata.is_some())
+            .field("search_query", &self.search_query)
+            .field("score_required", &self.score_required)
+            .finish()
+    }
+}
+
+
 impl<'a> QueryBuildContext<'a> {
     pub fn new() -> QueryBuildContext<'a> {
         QueryBuildContext {
             index_metadata: None,
+            index_reader: None,
+            cluster_metadata: None,
+            search_query: None,
             score_required: true
         }
     }
@@ -43,11 +73,43 @@ impl<'a> QueryBuildContext<'a> {
         self
     }
 
+    /// Gives query builders access to the index so they can run sub queries
+    /// (for example, "has_child" needs to find the parents of matching children)
+    #[inline]
+    pub fn set_index_reader(mut self, index_reader: &'a RocksDBIndexReader<'a>) -> QueryBuildContext<'a> {
+        self.index_reader = Some(index_reader);
+        self
+    }
+
+    /// Gives query builders access to other indices (for example, "terms" lookups)
+    #[inline]
+    pub fn set_cluster_metadata(mut self, cluster_metadata: &'a ClusterMetadata) -> QueryBuildContext<'a> {
+        self.cluster_metadata = Some(cluster_metadata);
+        self
+    }
+
+    /// Gives aggregations the query of the search they're running in, so they can
+    /// limit any sub queries to the documents that the search matches
+    #[inline]
+    pub fn set_search_query(mut self, search_query: &'a QueryBuilder) -> QueryBuildContext<'a> {
+        self.search_query = Some(search_query);
+        self
+    }
+
+    /// Switches to filter context, where the score of the resulting query isn't used
     #[inline]
     pub fn no_score(mut self) -> QueryBuildContext<'a> {
         self.score_required = false;
         self
     }
+
+    /// Returns false when building in filter context (eg, inside "bool.filter")
+    ///
+    /// Builders can use this to skip any work that only affects the score.
+    #[inline]
+    pub fn score_required(&self) -> bool {
+        self.score_required
+    }
 }
 
 
@@ -62,13 +124,21 @@ pub enum QueryParseError {
     ExpectedString,
     ExpectedFloat,
     ExpectedObjectOrString,
+    ExpectedObjectOrArray,
     InvalidValue,
     ExpectedSingleKey,
     InvalidOperator,
+    InvalidScoreMode,
+    ExpectedBoolean,
+    ExpectedPositiveInteger,
 }
 
 
-pub trait QueryBuilder: Debug {
+/// Builds a kite `Query` from a parsed query
+///
+/// `to_json` gives back the query in a canonical form that can be fed straight back
+/// into `parse`.
+pub trait QueryBuilder: Debug + ToJson {
     fn build(&self, context: &QueryBuildContext, schema: &Schema) -> Query;
 }
 
@@ -87,6 +157,12 @@ fn get_query_parser(query_name: &str) -> Option<fn(&Json) -> Result<Box<QueryBui
         "and" => Some(and_query::parse),
         "or" => Some(or_query::parse),
         "not" => Some(not_query::parse),
+        "has_child" => Some(has_child_query::parse),
+        "has_parent" => Some(has_parent_query::parse),
+        "bool" => Some(bool_query::parse),
+        "dis_max" => Some(dis_max_query::parse),
+        "geo_bounding_box" => Some(geo_bounding_box_query::parse),
+        "geo_distance" => Some(geo_distance_query::parse),
         _ => None
     }
 }
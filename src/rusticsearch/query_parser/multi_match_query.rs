@@ -1,7 +1,9 @@
 //! Parses "multi_match" queries
 
+use serde_json;
 use serde_json::Value as Json;
-use kite::{Term, Token, Query, TermScorer};
+use serde_json::value::ToJson;
+use kite::{Term, Token, Query};
 use kite::schema::Schema;
 
 use mapping::FieldSearchOptions;
@@ -11,7 +13,7 @@ use query_parser::utils::{parse_string, parse_float, Operator, parse_operator, p
 
 
 #[derive(Debug)]
-struct MultiMatchQueryBuilder {
+pub struct MultiMatchQueryBuilder {
     fields: Vec<(String, f64)>,
     query: String,
     operator: Operator,
@@ -19,6 +21,28 @@ struct MultiMatchQueryBuilder {
 }
 
 
+impl MultiMatchQueryBuilder {
+    pub fn new(fields: Vec<(String, f64)>, query: &str) -> MultiMatchQueryBuilder {
+        MultiMatchQueryBuilder {
+            fields: fields,
+            query: query.to_string(),
+            operator: Operator::Or,
+            boost: 1.0f64,
+        }
+    }
+
+    pub fn operator(mut self, operator: Operator) -> MultiMatchQueryBuilder {
+        self.operator = operator;
+        self
+    }
+
+    pub fn boost(mut self, boost: f64) -> MultiMatchQueryBuilder {
+        self.boost = boost;
+        self
+    }
+}
+
+
 impl QueryBuilder for MultiMatchQueryBuilder {
     fn build(&self, context: &QueryBuildContext, schema: &Schema) -> Query {
         // Convert query string into term query objects
@@ -51,7 +75,7 @@ impl QueryBuilder for MultiMatchQueryBuilder {
                 term_queries.push(Query::Term {
                     field: schema.get_field_by_name(field_name).unwrap(),
                     term: token.term,
-                    scorer: TermScorer::default(),
+                    scorer: field_search_options.term_scorer(),
                 });
             }
 
@@ -65,21 +89,47 @@ impl QueryBuilder for MultiMatchQueryBuilder {
             };
 
             // Add boost
-            field_query.boost(field_boost);
+            if context.score_required() {
+                field_query.boost(field_boost);
+            }
 
             field_queries.push(field_query);
         }
 
         let mut query = Query::new_disjunction_max(field_queries);
 
-        // Add boost
-        query.boost(self.boost);
+        // Add boost (scores are thrown away in filter context)
+        if context.score_required() {
+            query.boost(self.boost);
+        }
 
         query
     }
 }
 
 
+impl ToJson for MultiMatchQueryBuilder {
+    fn to_json(&self) -> Result<Json, serde_json::Error> {
+        let fields = self.fields.iter().map(|&(ref field_name, field_boost)| {
+            if field_boost == 1.0f64 {
+                field_name.clone()
+            } else {
+                format!("{}^{}", field_name, field_boost)
+            }
+        }).collect::<Vec<String>>();
+
+        Ok(json!({
+            "multi_match": {
+                "fields": fields,
+                "query": self.query,
+                "operator": try!(self.operator.to_json()),
+                "boost": self.boost,
+            }
+        }))
+    }
+}
+
+
 pub fn parse(json: &Json) -> Result<Box<QueryBuilder>, QueryParseError> {
     let object = try!(json.as_object().ok_or(QueryParseError::ExpectedObject));
 
@@ -1,6 +1,8 @@
 //! Parses "not" queries
 
+use serde_json;
 use serde_json::Value as Json;
+use serde_json::value::ToJson;
 use kite::Query;
 use kite::schema::Schema;
 
@@ -8,11 +10,20 @@ use query_parser::{QueryBuildContext, QueryParseError, QueryBuilder, parse as pa
 
 
 #[derive(Debug)]
-struct NotQueryBuilder {
+pub struct NotQueryBuilder {
     query: Box<QueryBuilder>,
 }
 
 
+impl NotQueryBuilder {
+    pub fn new(query: Box<QueryBuilder>) -> NotQueryBuilder {
+        NotQueryBuilder {
+            query: query,
+        }
+    }
+}
+
+
 impl QueryBuilder for NotQueryBuilder {
     fn build(&self, context: &QueryBuildContext, schema: &Schema) -> Query {
         Query::Exclude {
@@ -23,6 +34,15 @@ impl QueryBuilder for NotQueryBuilder {
 }
 
 
+impl ToJson for NotQueryBuilder {
+    fn to_json(&self) -> Result<Json, serde_json::Error> {
+        Ok(json!({
+            "not": try!(self.query.to_json())
+        }))
+    }
+}
+
+
 pub fn parse(json: &Json) -> Result<Box<QueryBuilder>, QueryParseError> {
     Ok(Box::new(NotQueryBuilder {
         query: try!(parse_query(json)),
@@ -1,6 +1,8 @@
 //! Parses "or" queries
 
+use serde_json;
 use serde_json::Value as Json;
+use serde_json::value::ToJson;
 use kite::Query;
 use kite::schema::Schema;
 
@@ -8,11 +10,20 @@ use query_parser::{QueryBuildContext, QueryParseError, QueryBuilder, parse as pa
 
 
 #[derive(Debug)]
-struct OrQueryBuilder {
+pub struct OrQueryBuilder {
     queries: Vec<Box<QueryBuilder>>,
 }
 
 
+impl OrQueryBuilder {
+    pub fn new(queries: Vec<Box<QueryBuilder>>) -> OrQueryBuilder {
+        OrQueryBuilder {
+            queries: queries,
+        }
+    }
+}
+
+
 impl QueryBuilder for OrQueryBuilder {
     fn build(&self, context: &QueryBuildContext, schema: &Schema) -> Query {
         let mut queries = Vec::new();
@@ -26,6 +37,19 @@ impl QueryBuilder for OrQueryBuilder {
 }
 
 
+impl ToJson for OrQueryBuilder {
+    fn to_json(&self) -> Result<Json, serde_json::Error> {
+        let mut queries = Vec::new();
+        for query in self.queries.iter() {
+            queries.push(try!(query.to_json()));
+        }
+
+        Ok(json!({
+            "or": queries
+        }))
+    }
+}
+
 
 pub fn parse(json: &Json) -> Result<Box<QueryBuilder>, QueryParseError> {
     let filters = try!(json.as_array().ok_or(QueryParseError::ExpectedArray));
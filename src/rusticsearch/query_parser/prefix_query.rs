@@ -1,37 +1,103 @@
 //! Parses "prefix" queries
 
+use std::collections::BTreeMap;
+
+use serde_json;
 use serde_json::Value as Json;
+use serde_json::value::ToJson;
 use kite::{Query, TermSelector, TermScorer};
 use kite::schema::Schema;
 
 use query_parser::{QueryBuildContext, QueryParseError, QueryBuilder};
-use query_parser::utils::parse_float;
+use query_parser::utils::{parse_float, parse_boolean, case_variants};
 
 
 #[derive(Debug)]
-struct PrefixQueryBuilder {
+pub struct PrefixQueryBuilder {
     field: String,
     prefix: String,
+    case_insensitive: bool,
     boost: f64,
 }
 
 
+impl PrefixQueryBuilder {
+    pub fn new(field: &str, prefix: &str) -> PrefixQueryBuilder {
+        PrefixQueryBuilder {
+            field: field.to_string(),
+            prefix: prefix.to_string(),
+            case_insensitive: false,
+            boost: 1.0f64,
+        }
+    }
+
+    pub fn case_insensitive(mut self, case_insensitive: bool) -> PrefixQueryBuilder {
+        self.case_insensitive = case_insensitive;
+        self
+    }
+
+    pub fn boost(mut self, boost: f64) -> PrefixQueryBuilder {
+        self.boost = boost;
+        self
+    }
+}
+
+
 impl QueryBuilder for PrefixQueryBuilder {
-    fn build(&self, _context: &QueryBuildContext, schema: &Schema) -> Query {
-        let mut query = Query::MultiTerm {
-            field: schema.get_field_by_name(&self.field).unwrap(),
-            term_selector: TermSelector::Prefix(self.prefix.clone()),
-            scorer: TermScorer::default(),
+    fn build(&self, context: &QueryBuildContext, schema: &Schema) -> Query {
+        let field = schema.get_field_by_name(&self.field).unwrap();
+
+        // Matches are scored with the field's similarity
+        let scorer = || {
+            match context.index_metadata.and_then(|index_metadata| index_metadata.get_field_mapping(&self.field)) {
+                Some(field_mapping) => field_mapping.get_search_options().term_scorer(),
+                None => TermScorer::default(),
+            }
         };
 
-        // Add boost
-        query.boost(self.boost);
+        let mut query = if self.case_insensitive {
+            // Search for every casing of the prefix
+            Query::new_disjunction(case_variants(&self.prefix).into_iter().map(|variant| {
+                Query::MultiTerm {
+                    field: field,
+                    term_selector: TermSelector::Prefix(variant),
+                    scorer: scorer(),
+                }
+            }).collect())
+        } else {
+            Query::MultiTerm {
+                field: field,
+                term_selector: TermSelector::Prefix(self.prefix.clone()),
+                scorer: scorer(),
+            }
+        };
+
+        // Add boost (scores are thrown away in filter context)
+        if context.score_required() {
+            query.boost(self.boost);
+        }
 
         query
     }
 }
 
 
+impl ToJson for PrefixQueryBuilder {
+    fn to_json(&self) -> Result<Json, serde_json::Error> {
+        let mut field_json = BTreeMap::new();
+        field_json.insert(self.field.clone(), json!({
+            "value": self.prefix,
+            "case_insensitive": self.case_insensitive,
+            "boost": self.boost,
+        }));
+
+        Ok(json!({
+            "prefix": field_json
+        }))
+    }
+}
+
+
 pub fn parse(json: &Json) -> Result<Box<QueryBuilder>, QueryParseError> {
     let object = try!(json.as_object().ok_or(QueryParseError::ExpectedObject));
 
@@ -45,6 +111,7 @@ pub fn parse(json: &Json) -> Result<Box<QueryBuilder>, QueryParseError> {
 
     // Get configuration
     let mut value: Option<&Json> = None;
+    let mut case_insensitive = false;
     let mut boost = 1.0f64;
 
     match *object {
@@ -58,6 +125,9 @@ pub fn parse(json: &Json) -> Result<Box<QueryBuilder>, QueryParseError> {
                     "prefix" => {
                         value = Some(val);
                     }
+                    "case_insensitive" => {
+                        case_insensitive = try!(parse_boolean(val));
+                    }
                     "boost" => {
                         boost = try!(parse_float(val));
                     }
@@ -74,6 +144,7 @@ pub fn parse(json: &Json) -> Result<Box<QueryBuilder>, QueryParseError> {
                 Ok(Box::new(PrefixQueryBuilder {
                     field: field_name.clone(),
                     prefix: string.clone(),
+                    case_insensitive: case_insensitive,
                     boost: boost,
                 }))
             } else {
@@ -116,6 +187,50 @@ mod tests {
         }));
     }
 
+    #[test]
+    fn test_case_insensitive() {
+        let mut schema = Schema::new();
+        let foo_field = schema.add_field("foo".to_string(), FieldType::Text, FIELD_INDEXED).unwrap();
+
+        let query = parse(&serde_json::from_str("
+        {
+            \"foo\": {
+                \"value\": \"B\",
+                \"case_insensitive\": true
+            }
+        }
+        ").unwrap()).and_then(|builder| Ok(builder.build(&QueryBuildContext::new(), &schema)));
+
+        assert_eq!(query, Ok(Query::Disjunction {
+            queries: vec![
+                Query::MultiTerm {
+                    field: foo_field,
+                    term_selector: TermSelector::Prefix("b".to_string()),
+                    scorer: TermScorer::default(),
+                },
+                Query::MultiTerm {
+                    field: foo_field,
+                    term_selector: TermSelector::Prefix("B".to_string()),
+                    scorer: TermScorer::default(),
+                },
+            ],
+        }));
+    }
+
+    #[test]
+    fn test_gives_error_for_invalid_case_insensitive() {
+        let query = parse(&serde_json::from_str("
+        {
+            \"foo\": {
+                \"value\": \"bar\",
+                \"case_insensitive\": \"yes\"
+            }
+        }
+        ").unwrap());
+
+        assert_eq!(query.err(), Some(QueryParseError::ExpectedBoolean));
+    }
+
     #[test]
     fn test_simple_prefix_query() {
         let mut schema = Schema::new();
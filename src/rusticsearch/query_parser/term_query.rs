@@ -1,37 +1,136 @@
 //! Parses "term" queries
 
+use std::collections::BTreeMap;
+
+use serde_json;
 use serde_json::Value as Json;
-use kite::{Term, Query, TermScorer};
+use serde_json::value::ToJson;
+use kite::{Term, Query, TermSelector, TermScorer};
 use kite::schema::Schema;
 
+use mapping::FieldType;
+use ip::cidr_prefixes;
 use query_parser::{QueryBuildContext, QueryParseError, QueryBuilder};
-use query_parser::utils::{parse_float, json_value_to_term};
+use query_parser::utils::{parse_float, parse_boolean, json_value_to_term, case_variants};
 
 
 #[derive(Debug)]
-struct TermQueryBuilder {
+pub struct TermQueryBuilder {
     field: String,
-    term: Term,
+    value: Json,
+    case_insensitive: bool,
     boost: f64,
 }
 
 
+impl TermQueryBuilder {
+    pub fn new(field: &str, value: Json) -> TermQueryBuilder {
+        TermQueryBuilder {
+            field: field.to_string(),
+            value: value,
+            case_insensitive: false,
+            boost: 1.0f64,
+        }
+    }
+
+    pub fn case_insensitive(mut self, case_insensitive: bool) -> TermQueryBuilder {
+        self.case_insensitive = case_insensitive;
+        self
+    }
+
+    pub fn boost(mut self, boost: f64) -> TermQueryBuilder {
+        self.boost = boost;
+        self
+    }
+}
+
+
 impl QueryBuilder for TermQueryBuilder {
-    fn build(&self, _context: &QueryBuildContext, schema: &Schema) -> Query {
-        let mut query = Query::Term {
-            field: schema.get_field_by_name(&self.field).unwrap(),
-            term: self.term.clone(),
-            scorer: TermScorer::default(),
+    fn build(&self, context: &QueryBuildContext, schema: &Schema) -> Query {
+        let field = schema.get_field_by_name(&self.field).unwrap();
+
+        let field_mapping = context.index_metadata.and_then(|index_metadata| index_metadata.get_field_mapping(&self.field));
+
+        let is_ip_field = match field_mapping {
+            Some(field_mapping) => field_mapping.data_type == FieldType::Ip,
+            None => false,
+        };
+
+        // Matches are scored with the field's similarity
+        let scorer = || {
+            match field_mapping {
+                Some(field_mapping) => field_mapping.get_search_options().term_scorer(),
+                None => TermScorer::default(),
+            }
         };
 
-        // Add boost
-        query.boost(self.boost);
+        let mut query = match (self.case_insensitive, &self.value) {
+            (_, &Json::String(ref string)) if is_ip_field => {
+                // Values of IP fields may be a CIDR block (eg, "10.0.0.0/8"). Every address in
+                // the block is indexed with the same prefix so search for the block's prefixes
+                let prefixes = match cidr_prefixes(string) {
+                    Some(prefixes) => prefixes,
+                    None => return Query::None,
+                };
+
+                Query::new_disjunction(prefixes.into_iter().map(|prefix| {
+                    Query::MultiTerm {
+                        field: field,
+                        term_selector: TermSelector::Prefix(prefix),
+                        scorer: scorer(),
+                    }
+                }).collect())
+            }
+            (true, &Json::String(ref string)) => {
+                // Search for every casing of the term
+                Query::new_disjunction(case_variants(string).iter().map(|variant| {
+                    Query::Term {
+                        field: field,
+                        term: Term::from_string(variant),
+                        scorer: scorer(),
+                    }
+                }).collect())
+            }
+            _ => {
+                let term = match json_value_to_term(&self.value) {
+                    Some(term) => term,
+                    None => return Query::None,
+                };
+
+                Query::Term {
+                    field: field,
+                    term: term,
+                    scorer: scorer(),
+                }
+            }
+        };
+
+        // Add boost (scores are thrown away in filter context)
+        if context.score_required() {
+            query.boost(self.boost);
+        }
 
         query
     }
 }
 
 
+impl ToJson for TermQueryBuilder {
+    fn to_json(&self) -> Result<Json, serde_json::Error> {
+        let mut field_json = BTreeMap::new();
+        field_json.insert(self.field.clone(), json!({
+            "value": self.value,
+            "case_insensitive": self.case_insensitive,
+            "boost": self.boost,
+        }));
+
+        Ok(json!({
+            "term": field_json
+        }))
+    }
+}
+
+
 pub fn parse(json: &Json) -> Result<Box<QueryBuilder>, QueryParseError> {
     let object = try!(json.as_object().ok_or(QueryParseError::ExpectedObject));
 
@@ -45,6 +144,8 @@ pub fn parse(json: &Json) -> Result<Box<QueryBuilder>, QueryParseError> {
 
     // Get configuration
     let mut term: Option<Term> = None;
+    let mut value = Json::Null;
+    let mut case_insensitive = false;
     let mut boost = 1.0f64;
 
     match *object {
@@ -53,11 +154,15 @@ pub fn parse(json: &Json) -> Result<Box<QueryBuilder>, QueryParseError> {
                 match key.as_ref() {
                     "value" => {
                         term = json_value_to_term(val);
+                        value = val.clone();
 
                         if term == None {
                             return Err(QueryParseError::InvalidValue);
                         }
                     }
+                    "case_insensitive" => {
+                        case_insensitive = try!(parse_boolean(val));
+                    }
                     "boost" => {
                         boost = try!(parse_float(val));
                     }
@@ -65,14 +170,18 @@ pub fn parse(json: &Json) -> Result<Box<QueryBuilder>, QueryParseError> {
                 }
             }
         }
-        _ => term = json_value_to_term(object),
+        _ => {
+            term = json_value_to_term(object);
+            value = object.clone();
+        }
     }
 
     match term {
-        Some(term) => {
+        Some(_) => {
             Ok(Box::new(TermQueryBuilder {
                 field: field_name.clone(),
-                term: term,
+                value: value,
+                case_insensitive: case_insensitive,
                 boost: boost,
             }))
         }
@@ -84,10 +193,13 @@ pub fn parse(json: &Json) -> Result<Box<QueryBuilder>, QueryParseError> {
 #[cfg(test)]
 mod tests {
     use serde_json;
+    use serde_json::value::ToJson;
 
-    use kite::{Term, Query, TermScorer};
+    use kite::{Term, Query, TermSelector, TermScorer};
     use kite::schema::{Schema, FieldType, FIELD_INDEXED};
 
+    use index::metadata::IndexMetadata;
+    use mapping::{self, Mapping, MappingProperty, FieldMapping, DynamicMapping};
     use query_parser::{QueryBuildContext, QueryParseError};
 
     use super::parse;
@@ -132,6 +244,42 @@ mod tests {
         }));
     }
 
+    #[test]
+    fn test_with_cidr_block() {
+        let mut schema = Schema::new();
+        let address_field = schema.add_field("address".to_string(), FieldType::Text, FIELD_INDEXED).unwrap();
+
+        let mut index_metadata = IndexMetadata::default();
+        index_metadata.mappings.insert("test".to_string(), Mapping {
+            properties: hashmap! {
+                "address".to_string() => MappingProperty::Field(FieldMapping {
+                    data_type: mapping::FieldType::Ip,
+                    ..FieldMapping::default()
+                })
+            },
+            dynamic: DynamicMapping::True,
+        });
+
+        let query = parse(&serde_json::from_str("
+        {
+            \"address\": \"192.168.0.0/23\"
+        }
+        ").unwrap()).and_then(|builder| Ok(builder.build(&QueryBuildContext::new().set_index_metadata(&index_metadata), &schema)));
+
+        assert_eq!(query, Ok(Query::new_disjunction(vec![
+            Query::MultiTerm {
+                field: address_field,
+                term_selector: TermSelector::Prefix("00000000000000000000ffffc0a800".to_string()),
+                scorer: TermScorer::default(),
+            },
+            Query::MultiTerm {
+                field: address_field,
+                term_selector: TermSelector::Prefix("00000000000000000000ffffc0a801".to_string()),
+                scorer: TermScorer::default(),
+            },
+        ])));
+    }
+
     #[test]
     fn test_simple_term_query() {
         let mut schema = Schema::new();
@@ -171,6 +319,67 @@ mod tests {
         }));
     }
 
+    #[test]
+    fn test_with_boost_in_filter_context() {
+        let mut schema = Schema::new();
+        let foo_field = schema.add_field("foo".to_string(), FieldType::Text, FIELD_INDEXED).unwrap();
+
+        let query = parse(&serde_json::from_str("
+        {
+            \"foo\": {
+                \"value\": \"bar\",
+                \"boost\": 2.0
+            }
+        }
+        ").unwrap()).and_then(|builder| Ok(builder.build(&QueryBuildContext::new().no_score(), &schema)));
+
+        assert_eq!(query, Ok(Query::Term {
+            field: foo_field,
+            term: Term::from_string("bar"),
+            scorer: TermScorer::default(),
+        }));
+    }
+
+    #[test]
+    fn test_case_insensitive() {
+        let mut schema = Schema::new();
+        let foo_field = schema.add_field("foo".to_string(), FieldType::Text, FIELD_INDEXED).unwrap();
+
+        let query = parse(&serde_json::from_str("
+        {
+            \"foo\": {
+                \"value\": \"Ab1\",
+                \"case_insensitive\": true
+            }
+        }
+        ").unwrap()).and_then(|builder| Ok(builder.build(&QueryBuildContext::new(), &schema)));
+
+        assert_eq!(query, Ok(Query::Disjunction {
+            queries: vec![
+                Query::Term {
+                    field: foo_field,
+                    term: Term::from_string("ab1"),
+                    scorer: TermScorer::default(),
+                },
+                Query::Term {
+                    field: foo_field,
+                    term: Term::from_string("aB1"),
+                    scorer: TermScorer::default(),
+                },
+                Query::Term {
+                    field: foo_field,
+                    term: Term::from_string("Ab1"),
+                    scorer: TermScorer::default(),
+                },
+                Query::Term {
+                    field: foo_field,
+                    term: Term::from_string("AB1"),
+                    scorer: TermScorer::default(),
+                },
+            ],
+        }));
+    }
+
     #[test]
     fn test_with_boost_integer() {
         let mut schema = Schema::new();
@@ -298,4 +507,26 @@ mod tests {
 
         assert_eq!(query.err(), Some(QueryParseError::UnrecognisedKey("hello".to_string())));
     }
+
+    #[test]
+    fn test_to_json() {
+        let query = parse(&serde_json::from_str("
+        {
+            \"foo\": {
+                \"value\": 123,
+                \"boost\": 2.0
+            }
+        }
+        ").unwrap()).and_then(|builder| Ok(builder.to_json().unwrap()));
+
+        assert_eq!(query, Ok(json!({
+            "term": {
+                "foo": {
+                    "value": 123,
+                    "case_insensitive": false,
+                    "boost": 2.0
+                }
+            }
+        })));
+    }
 }
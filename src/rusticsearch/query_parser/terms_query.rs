@@ -1,28 +1,132 @@
 //! Parses "match" queries
 
+use std::collections::BTreeMap;
+
+use serde_json;
 use serde_json::Value as Json;
+use serde_json::value::ToJson;
 use kite::{Term, Query, TermScorer};
 use kite::schema::Schema;
+use kite::document::{DocRef, FieldValue};
+use kite::collectors::top_score::TopScoreCollector;
 
 use query_parser::{QueryBuildContext, QueryParseError, QueryBuilder};
-use query_parser::utils::json_value_to_term;
+use query_parser::utils::{parse_string, json_value_to_term};
+
+
+/// Location of a stored field in another document to read the terms from
+#[derive(Debug, Clone, PartialEq)]
+pub struct TermsLookup {
+    pub index: String,
+    pub id: String,
+    pub path: String,
+}
+
+
+impl TermsLookup {
+    /// Reads the terms out of the document
+    ///
+    /// Stored string fields hold arrays as a space-separated string, so they are split
+    /// back up on whitespace.
+    fn get_terms(&self, context: &QueryBuildContext) -> Vec<Term> {
+        let cluster_metadata = match context.cluster_metadata {
+            Some(cluster_metadata) => cluster_metadata,
+            None => return Vec::new(),
+        };
+
+        let index = match cluster_metadata.names.find_canonical(&self.index).and_then(|index_ref| cluster_metadata.indices.get(&index_ref)) {
+            Some(index) => index,
+            None => return Vec::new(),
+        };
+
+        // Lookups on closed indices don't match anything
+        let store = match index.store() {
+            Ok(store) => store,
+            Err(_) => return Vec::new(),
+        };
+        let index_reader = store.reader();
+        let schema = index_reader.schema();
+
+        let (id_field, path_field) = match (schema.get_field_by_name("_id"), schema.get_field_by_name(&self.path)) {
+            (Some(id_field), Some(path_field)) => (id_field, path_field),
+            _ => return Vec::new(),
+        };
+
+        // Find the document
+        let mut collector = TopScoreCollector::new(1);
+        let id_query = Query::Term {
+            field: id_field,
+            term: Term::from_string(&self.id),
+            scorer: TermScorer::default(),
+        };
+
+        if index_reader.search(&mut collector, &id_query).is_err() {
+            return Vec::new();
+        }
+
+        let doc_id = match collector.into_sorted_vec().first() {
+            Some(doc_match) => doc_match.doc_id(),
+            None => return Vec::new(),
+        };
+
+        // Read the terms
+        match index_reader.read_stored_field(path_field, DocRef::from_u64(doc_id)) {
+            Ok(Some(FieldValue::String(string))) => string.split_whitespace().map(Term::from_string).collect(),
+            Ok(Some(FieldValue::Integer(value))) => vec![Term::from_integer(value)],
+            Ok(Some(FieldValue::Boolean(value))) => vec![Term::from_boolean(value)],
+            _ => Vec::new(),
+        }
+    }
+}
+
 
 #[derive(Debug)]
-struct TermsQueryBuilder {
+pub struct TermsQueryBuilder {
     field: String,
-    terms: Vec<Term>,
+    values: Vec<Json>,
+    lookup: Option<TermsLookup>,
+}
+
+
+impl TermsQueryBuilder {
+    pub fn new(field: &str, values: Vec<Json>) -> TermsQueryBuilder {
+        TermsQueryBuilder {
+            field: field.to_string(),
+            values: values,
+            lookup: None,
+        }
+    }
+
+    pub fn new_lookup(field: &str, lookup: TermsLookup) -> TermsQueryBuilder {
+        TermsQueryBuilder {
+            field: field.to_string(),
+            values: Vec::new(),
+            lookup: Some(lookup),
+        }
+    }
 }
 
 
 impl QueryBuilder for TermsQueryBuilder {
-    fn build(&self, _context: &QueryBuildContext, schema: &Schema) -> Query {
+    fn build(&self, context: &QueryBuildContext, schema: &Schema) -> Query {
+        let terms = match self.lookup {
+            Some(ref lookup) => lookup.get_terms(context),
+            None => self.values.iter().filter_map(json_value_to_term).collect(),
+        };
+
+        // Matches are scored with the field's similarity
+        let scorer = match context.index_metadata.and_then(|index_metadata| index_metadata.get_field_mapping(&self.field)) {
+            Some(field_mapping) => field_mapping.get_search_options().term_scorer(),
+            None => TermScorer::default(),
+        };
+
         // Create a term query for each token
         let mut queries = Vec::new();
-        for term in self.terms.iter() {
+        for term in terms {
             queries.push(Query::Term {
                 field: schema.get_field_by_name(&self.field).unwrap(),
-                term: term.clone(),
-                scorer: TermScorer::default(),
+                term: term,
+                scorer: scorer.clone(),
             });
         }
 
@@ -31,6 +135,30 @@ impl QueryBuilder for TermsQueryBuilder {
 }
 
 
+impl ToJson for TermsQueryBuilder {
+    fn to_json(&self) -> Result<Json, serde_json::Error> {
+        let mut field_json = BTreeMap::new();
+
+        match self.lookup {
+            Some(ref lookup) => {
+                field_json.insert(self.field.clone(), json!({
+                    "index": lookup.index,
+                    "id": lookup.id,
+                    "path": lookup.path,
+                }));
+            }
+            None => {
+                field_json.insert(self.field.clone(), Json::Array(self.values.clone()));
+            }
+        }
+
+        Ok(json!({
+            "terms": field_json
+        }))
+    }
+}
+
+
 pub fn parse(json: &Json) -> Result<Box<QueryBuilder>, QueryParseError> {
     let object = try!(json.as_object().ok_or(QueryParseError::ExpectedObject));
 
@@ -41,22 +169,56 @@ pub fn parse(json: &Json) -> Result<Box<QueryBuilder>, QueryParseError> {
     };
 
     // Get configuration
-    let terms: Vec<Term> = if let &Json::Array(ref arr) = object.get(field_name).unwrap() {
-        arr.iter().filter_map(|term| json_value_to_term(&term)).collect()
-    } else {
-        return Err(QueryParseError::ExpectedArray);
-    };
+    match *object.get(field_name).unwrap() {
+        Json::Array(ref arr) => {
+            let values = arr.iter().filter(|value| json_value_to_term(value).is_some()).cloned().collect();
 
-    Ok(Box::new(TermsQueryBuilder {
-        field: field_name.clone(),
-        terms: terms,
-    }))
+            Ok(Box::new(TermsQueryBuilder::new(field_name, values)))
+        }
+        Json::Object(ref inner_object) => {
+            Ok(Box::new(TermsQueryBuilder::new_lookup(field_name, try!(parse_lookup(inner_object)))))
+        }
+        _ => Err(QueryParseError::ExpectedArray),
+    }
+}
+
+
+fn parse_lookup(object: &serde_json::Map<String, Json>) -> Result<TermsLookup, QueryParseError> {
+    let mut index = None;
+    let mut id = None;
+    let mut path = None;
+
+    for (key, value) in object.iter() {
+        match key.as_ref() {
+            "index" => {
+                index = Some(try!(parse_string(value)));
+            }
+            "id" => {
+                id = Some(try!(parse_string(value)));
+            }
+            "path" => {
+                path = Some(try!(parse_string(value)));
+            }
+            "type" => {
+                // Document keys are unique across mappings so the type isn't needed
+                try!(parse_string(value));
+            }
+            _ => return Err(QueryParseError::UnrecognisedKey(key.clone()))
+        }
+    }
+
+    Ok(TermsLookup {
+        index: try!(index.ok_or(QueryParseError::ExpectedKey("index"))),
+        id: try!(id.ok_or(QueryParseError::ExpectedKey("id"))),
+        path: try!(path.ok_or(QueryParseError::ExpectedKey("path"))),
+    })
 }
 
 
 #[cfg(test)]
 mod tests {
     use serde_json;
+    use serde_json::value::ToJson;
 
     use kite::{Term, Query, TermScorer};
 
@@ -129,7 +291,7 @@ mod tests {
         }
         ").unwrap());
 
-        assert_eq!(query.err(), Some(QueryParseError::ExpectedArray));
+        assert_eq!(query.err(), Some(QueryParseError::UnrecognisedKey("query".to_string())));
 
         // String
         let query = parse(&serde_json::from_str("
@@ -162,4 +324,56 @@ mod tests {
 
         assert_eq!(query.err(), Some(QueryParseError::ExpectedSingleKey));
     }
+
+    #[test]
+    fn test_to_json() {
+        let query = parse(&serde_json::from_str("
+        {
+            \"foo\": [\"bar\", \"baz\"]
+        }
+        ").unwrap()).and_then(|builder| Ok(builder.to_json().unwrap()));
+
+        assert_eq!(query, Ok(json!({
+            "terms": {
+                "foo": ["bar", "baz"]
+            }
+        })));
+    }
+
+    #[test]
+    fn test_lookup_to_json() {
+        let query = parse(&serde_json::from_str("
+        {
+            \"foo\": {
+                \"index\": \"users\",
+                \"id\": \"2\",
+                \"path\": \"groups\"
+            }
+        }
+        ").unwrap()).and_then(|builder| Ok(builder.to_json().unwrap()));
+
+        assert_eq!(query, Ok(json!({
+            "terms": {
+                "foo": {
+                    "index": "users",
+                    "id": "2",
+                    "path": "groups"
+                }
+            }
+        })));
+    }
+
+    #[test]
+    fn test_gives_error_for_lookup_without_path() {
+        let query = parse(&serde_json::from_str("
+        {
+            \"foo\": {
+                \"index\": \"users\",
+                \"id\": \"2\"
+            }
+        }
+        ").unwrap());
+
+        assert_eq!(query.err(), Some(QueryParseError::ExpectedKey("path")));
+    }
 }
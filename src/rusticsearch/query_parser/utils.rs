@@ -1,4 +1,6 @@
+use serde_json;
 use serde_json::Value as Json;
+use serde_json::value::ToJson;
 use kite::term::Term;
 
 use query_parser::QueryParseError;
@@ -25,6 +27,27 @@ pub fn parse_float(json: &Json) -> Result<f64, QueryParseError> {
 }
 
 
+pub fn parse_boolean(json: &Json) -> Result<bool, QueryParseError> {
+    match *json {
+        Json::Bool(value) => Ok(value),
+        _ => Err(QueryParseError::ExpectedBoolean),
+    }
+}
+
+
+pub fn parse_positive_integer(json: &Json) -> Result<u64, QueryParseError> {
+    match *json {
+        Json::Number(ref number) => {
+            match number.as_u64() {
+                Some(val) => Ok(val),
+                None => Err(QueryParseError::ExpectedPositiveInteger),
+            }
+        }
+        _ => Err(QueryParseError::ExpectedPositiveInteger),
+    }
+}
+
+
 #[derive(Debug)]
 pub enum Operator {
     Or,
@@ -32,6 +55,16 @@ pub enum Operator {
 }
 
 
+impl ToJson for Operator {
+    fn to_json(&self) -> Result<Json, serde_json::Error> {
+        Ok(match *self {
+            Operator::Or => Json::String("or".to_string()),
+            Operator::And => Json::String("and".to_string()),
+        })
+    }
+}
+
+
 pub fn parse_operator(json: &Json) -> Result<Operator, QueryParseError> {
     match *json {
         Json::String(ref value) => {
@@ -75,3 +108,52 @@ pub fn json_value_to_term(json: &Json) -> Option<Term> {
         &Json::Object(_) => None,
     }
 }
+
+
+/// Maximum number of cased characters to generate all case variants for
+const MAX_CASE_VARIANT_CHARS: usize = 10;
+
+
+/// Generates the different ways a string could be cased in the index
+///
+/// Terms are indexed verbatim, so case-insensitive lookups need to search for every
+/// casing of the term. For long strings, this falls back to the common casings.
+pub fn case_variants(string: &str) -> Vec<String> {
+    let num_cased_chars = string.chars().filter(|c| c.to_lowercase().collect::<String>() != c.to_uppercase().collect::<String>()).count();
+
+    if num_cased_chars > MAX_CASE_VARIANT_CHARS {
+        let mut variants = vec![string.to_string()];
+
+        for variant in vec![string.to_lowercase(), string.to_uppercase()] {
+            if !variants.contains(&variant) {
+                variants.push(variant);
+            }
+        }
+
+        return variants;
+    }
+
+    let mut variants = vec![String::new()];
+
+    for c in string.chars() {
+        let lower = c.to_lowercase().collect::<String>();
+        let upper = c.to_uppercase().collect::<String>();
+
+        if lower == upper {
+            for variant in variants.iter_mut() {
+                variant.push(c);
+            }
+        } else {
+            let mut next_variants = Vec::with_capacity(variants.len() * 2);
+
+            for variant in variants {
+                next_variants.push(variant.clone() + &lower);
+                next_variants.push(variant + &upper);
+            }
+
+            variants = next_variants;
+        }
+    }
+
+    variants
+}
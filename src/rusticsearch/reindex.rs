@@ -0,0 +1,526 @@
+//! Copies documents from one index into another
+//!
+//! Documents are read back from their `_source` field and indexed into the destination
+//! in the same way as documents sent to the document API, so they pick up any changes
+//! to the destination's mappings and analyzers.
+
+use std::fmt;
+use std::collections::{BTreeSet, HashSet};
+use std::sync::Arc;
+
+use serde_json;
+use serde_json::value::ToJson;
+use kite::{Term, Query, TermScorer};
+use kite::document::{DocRef, FieldValue};
+use kite::collectors::total_count::TotalCountCollector;
+use kite_rocksdb::RocksDBIndexReader;
+
+use system::System;
+use index::Index;
+use document::DocumentSource;
+use collectors::AllDocumentsCollector;
+use query_parser::{QueryBuildContext, QueryParseError, parse as parse_query};
+
+
+/// How often (in documents) progress is logged
+const PROGRESS_LOG_INTERVAL: u64 = 1000;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OpType {
+    /// Replace documents that already exist in the destination
+    Index,
+
+    /// Only copy documents that don't exist in the destination
+    Create,
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReindexRequest {
+    pub source_index: String,
+
+    /// Only documents matching this query are copied
+    pub query: Option<serde_json::Value>,
+
+    pub dest_index: String,
+
+    /// The mapping to index the documents with
+    ///
+    /// If this isn't set, the name of the source index's mapping is used.
+    pub dest_type: Option<String>,
+
+    pub op_type: OpType,
+
+    /// Carry on when a document already exists in the destination (with the "create" op type)
+    pub proceed_on_conflicts: bool,
+}
+
+
+#[derive(Debug, PartialEq)]
+pub enum ReindexParseError {
+    ExpectedObject,
+    ExpectedString,
+    ExpectedKey(String),
+    UnrecognisedKey(String),
+    UnrecognisedOpType(String),
+    UnrecognisedConflicts(String),
+    InvalidQuery(QueryParseError),
+}
+
+
+impl fmt::Display for ReindexParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ReindexParseError::ExpectedObject => write!(f, "expected an object"),
+            ReindexParseError::ExpectedString => write!(f, "expected a string"),
+            ReindexParseError::ExpectedKey(ref key) => write!(f, "[{}] is required", key),
+            ReindexParseError::UnrecognisedKey(ref key) => write!(f, "unknown key [{}]", key),
+            ReindexParseError::UnrecognisedOpType(ref op_type) => write!(f, "unknown op_type [{}]", op_type),
+            ReindexParseError::UnrecognisedConflicts(ref conflicts) => write!(f, "conflicts may only be \"proceed\" or \"abort\" but was [{}]", conflicts),
+            ReindexParseError::InvalidQuery(ref e) => write!(f, "invalid query: {:?}", e),
+        }
+    }
+}
+
+
+fn parse_string(json: &serde_json::Value) -> Result<String, ReindexParseError> {
+    json.as_str().map(|string| string.to_string()).ok_or(ReindexParseError::ExpectedString)
+}
+
+
+pub fn parse(json: &serde_json::Value) -> Result<ReindexRequest, ReindexParseError> {
+    let data = try!(json.as_object().ok_or(ReindexParseError::ExpectedObject));
+
+    let mut request = ReindexRequest {
+        source_index: String::new(),
+        query: None,
+        dest_index: String::new(),
+        dest_type: None,
+        op_type: OpType::Index,
+        proceed_on_conflicts: false,
+    };
+
+    for (key, value) in data.iter() {
+        match key.as_ref() {
+            "source" => {
+                let source = try!(value.as_object().ok_or(ReindexParseError::ExpectedObject));
+
+                for (key, value) in source.iter() {
+                    match key.as_ref() {
+                        "index" => request.source_index = try!(parse_string(value)),
+                        "query" => {
+                            if let Err(e) = parse_query(value) {
+                                return Err(ReindexParseError::InvalidQuery(e));
+                            }
+
+                            request.query = Some(value.clone());
+                        }
+                        _ => return Err(ReindexParseError::UnrecognisedKey(format!("source.{}", key))),
+                    }
+                }
+            }
+            "dest" => {
+                let dest = try!(value.as_object().ok_or(ReindexParseError::ExpectedObject));
+
+                for (key, value) in dest.iter() {
+                    match key.as_ref() {
+                        "index" => request.dest_index = try!(parse_string(value)),
+                        "type" => request.dest_type = Some(try!(parse_string(value))),
+                        "op_type" => {
+                            request.op_type = match try!(parse_string(value)).as_ref() {
+                                "index" => OpType::Index,
+                                "create" => OpType::Create,
+                                op_type => return Err(ReindexParseError::UnrecognisedOpType(op_type.to_string())),
+                            };
+                        }
+                        _ => return Err(ReindexParseError::UnrecognisedKey(format!("dest.{}", key))),
+                    }
+                }
+            }
+            "conflicts" => {
+                request.proceed_on_conflicts = match try!(parse_string(value)).as_ref() {
+                    "proceed" => true,
+                    "abort" => false,
+                    conflicts => return Err(ReindexParseError::UnrecognisedConflicts(conflicts.to_string())),
+                };
+            }
+            _ => return Err(ReindexParseError::UnrecognisedKey(key.clone())),
+        }
+    }
+
+    if request.source_index.is_empty() {
+        return Err(ReindexParseError::ExpectedKey("source.index".to_string()));
+    }
+
+    if request.dest_index.is_empty() {
+        return Err(ReindexParseError::ExpectedKey("dest.index".to_string()));
+    }
+
+    Ok(request)
+}
+
+
+#[derive(Debug)]
+pub enum ReindexError {
+    IndexNotFound(String),
+    IndexClosed(String),
+    NoWriteIndex(String),
+    SameIndex(String),
+    AmbiguousType(String),
+    MappingNotFound(String),
+    InvalidQuery(QueryParseError),
+    CreateIndexError(String),
+}
+
+
+impl fmt::Display for ReindexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ReindexError::IndexNotFound(ref name) => write!(f, "no such index [{}]", name),
+            ReindexError::IndexClosed(ref name) => write!(f, "index [{}] is closed", name),
+            ReindexError::NoWriteIndex(ref name) => write!(f, "no write index is defined for alias [{}]", name),
+            ReindexError::SameIndex(ref name) => write!(f, "reindex cannot write into an index its reading from [{}]", name),
+            ReindexError::AmbiguousType(ref name) => write!(f, "index [{}] has more than one mapping so [dest.type] is required", name),
+            ReindexError::MappingNotFound(ref name) => write!(f, "the destination index has no mapping [{}]", name),
+            ReindexError::InvalidQuery(ref e) => write!(f, "invalid query: {:?}", e),
+            ReindexError::CreateIndexError(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+
+#[derive(Debug, Default)]
+pub struct ReindexStats {
+    pub total: u64,
+    pub created: u64,
+    pub updated: u64,
+    pub version_conflicts: u64,
+
+    /// The keys of the documents that couldn't be copied, with the reason
+    pub failures: Vec<(String, String)>,
+}
+
+
+impl ToJson for ReindexStats {
+    fn to_json(&self) -> Result<serde_json::Value, serde_json::Error> {
+        let failures_json = self.failures.iter().map(|&(ref key, ref reason)| {
+            json!({
+                "id": key,
+                "cause": reason,
+            })
+        }).collect::<Vec<_>>();
+
+        Ok(json!({
+            "total": self.total,
+            "created": self.created,
+            "updated": self.updated,
+            "version_conflicts": self.version_conflicts,
+            "failures": failures_json,
+        }))
+    }
+}
+
+
+enum CopyDocumentError {
+    AlreadyExists,
+    Failed(String),
+}
+
+
+fn document_exists(index_reader: &RocksDBIndexReader, key: &str) -> Result<bool, String> {
+    let key_field = match index_reader.schema().get_field_by_name("_id") {
+        Some(key_field) => key_field,
+        None => return Ok(false),
+    };
+
+    let query = Query::Term {
+        field: key_field,
+        term: Term::from_string(key),
+        scorer: TermScorer::default(),
+    };
+
+    let mut collector = TotalCountCollector::new();
+    try!(index_reader.search(&mut collector, &query).map_err(|_| "failed to check if the document exists".to_string()));
+    Ok(collector.get_total_count() > 0)
+}
+
+
+/// Indexes a document into the destination index
+///
+/// The reader is opened on the destination before the copy starts, so it doesn't see the
+/// documents that have been copied since. Their keys are kept in `copied_keys` instead.
+/// Returns true if the document is new to the destination.
+fn copy_document(index: &Index, index_reader: &RocksDBIndexReader, copied_keys: &mut HashSet<String>, mapping_name: &str, key: &str, data: &serde_json::Map<String, serde_json::Value>, op_type: OpType) -> Result<bool, CopyDocumentError> {
+    let exists = copied_keys.contains(key) || try!(document_exists(index_reader, key).map_err(CopyDocumentError::Failed));
+    if exists && op_type == OpType::Create {
+        return Err(CopyDocumentError::AlreadyExists);
+    }
+
+    // Add any new fields to the mapping
+    try!(index.update_dynamic_mapping(mapping_name, data).map_err(CopyDocumentError::Failed));
+
+    let index_metadata = index.metadata.read().unwrap();
+    let mapping = match index_metadata.mappings.get(mapping_name) {
+        Some(mapping) => mapping,
+        None => return Err(CopyDocumentError::Failed("Mapping not found".to_string())),
+    };
+
+    let document_source = DocumentSource {
+        key: key,
+        data: data,
+    };
+
+    let doc = try!(document_source.prepare(mapping).map_err(|e| CopyDocumentError::Failed(e.to_string())));
+    let store = try!(index.store().map_err(CopyDocumentError::Failed));
+    try!(store.insert_or_update_document(&doc).map_err(|e| CopyDocumentError::Failed(format!("{:?}", e))));
+    index.update_completions(mapping, key, data);
+    index.clear_term_dictionaries();
+    copied_keys.insert(key.to_string());
+
+    Ok(!exists)
+}
+
+
+impl System {
+    /// Copies the documents from one index (or alias) into another
+    ///
+    /// The destination index is created if it doesn't exist yet, so any matching index
+    /// template is applied to it. The cluster metadata is only locked while the indices are
+    /// found and the queries are built, not while the documents are copied.
+    pub fn reindex(&self, request: &ReindexRequest) -> Result<ReindexStats, ReindexError> {
+        if try!(self.auto_create_index(&request.dest_index).map_err(ReindexError::CreateIndexError)) {
+            self.log.info("[sys] created index", b!("index" => request.dest_index.clone()));
+        }
+
+        let (sources, dest, dest_type) = {
+            let cluster_metadata = self.metadata.read().unwrap();
+
+            let source_indices = cluster_metadata.names.find(&request.source_index).iter().filter_map(|index_ref| cluster_metadata.indices.get(index_ref)).cloned().collect::<Vec<Arc<Index>>>();
+            if source_indices.is_empty() {
+                return Err(ReindexError::IndexNotFound(request.source_index.clone()));
+            }
+
+            let dest = match cluster_metadata.find_write_index(&request.dest_index) {
+                Some(dest) => dest.clone(),
+                None => return Err(ReindexError::NoWriteIndex(request.dest_index.clone())),
+            };
+
+            if source_indices.iter().any(|source| source.id() == dest.id()) {
+                return Err(ReindexError::SameIndex(dest.canonical_name().to_string()));
+            }
+
+            for index in source_indices.iter().chain(Some(&dest)) {
+                if index.is_closed() {
+                    return Err(ReindexError::IndexClosed(index.canonical_name().to_string()));
+                }
+            }
+
+            // Work out which mapping to index the documents with
+            let dest_type = match request.dest_type {
+                Some(ref dest_type) => dest_type.clone(),
+                None => {
+                    let mut mapping_names = BTreeSet::new();
+                    for index in source_indices.iter() {
+                        mapping_names.extend(index.metadata.read().unwrap().mappings.keys().cloned());
+                    }
+
+                    if mapping_names.len() != 1 {
+                        return Err(ReindexError::AmbiguousType(request.source_index.clone()));
+                    }
+
+                    mapping_names.into_iter().next().unwrap()
+                }
+            };
+
+            if !dest.metadata.read().unwrap().mappings.contains_key(&dest_type) {
+                return Err(ReindexError::MappingNotFound(dest_type));
+            }
+
+            // Find the documents to copy. Queries can look up terms in other indices so
+            // they're built before the lock is released
+            let mut sources = Vec::with_capacity(source_indices.len());
+            for source in source_indices {
+                let query = match request.query {
+                    Some(ref query_json) => {
+                        let source_store = try!(source.store().map_err(|_| ReindexError::IndexClosed(source.canonical_name().to_string())));
+                        let source_reader = source_store.reader();
+                        let source_metadata = source.metadata.read().unwrap();
+                        let context = QueryBuildContext::new().set_index_metadata(&source_metadata).set_index_reader(&source_reader).set_cluster_metadata(&cluster_metadata).no_score();
+
+                        match parse_query(query_json) {
+                            Ok(query) => query.build(&context, &source_reader.schema()),
+                            Err(e) => return Err(ReindexError::InvalidQuery(e)),
+                        }
+                    }
+                    None => Query::new_all(),
+                };
+
+                sources.push((source, query));
+            }
+
+            (sources, dest, dest_type)
+        };
+
+        let dest_store = try!(dest.store().map_err(|_| ReindexError::IndexClosed(dest.canonical_name().to_string())));
+        let dest_reader = dest_store.reader();
+        let mut copied_keys = HashSet::new();
+        let mut stats = ReindexStats::default();
+
+        for (source, query) in sources {
+            let source_store = try!(source.store().map_err(|_| ReindexError::IndexClosed(source.canonical_name().to_string())));
+            let source_reader = source_store.reader();
+
+            let (key_field, source_field) = match (source_reader.schema().get_field_by_name("_id"), source_reader.schema().get_field_by_name("_source")) {
+                (Some(key_field), Some(source_field)) => (key_field, source_field),
+                _ => continue,
+            };
+
+            let mut collector = AllDocumentsCollector::new().no_score();
+            source_reader.search(&mut collector, &query).unwrap();
+
+            for (doc_id, _) in collector.into_vec() {
+                stats.total += 1;
+
+                let key = match source_reader.read_stored_field(key_field, DocRef::from_u64(doc_id)) {
+                    Ok(Some(FieldValue::String(key))) => key,
+                    _ => {
+                        stats.failures.push((String::new(), "document has no _id".to_string()));
+                        continue;
+                    }
+                };
+
+                let data = match source_reader.read_stored_field(source_field, DocRef::from_u64(doc_id)) {
+                    Ok(Some(FieldValue::String(source))) => serde_json::from_str::<serde_json::Value>(&source).ok(),
+                    _ => None,
+                };
+
+                let data = match data {
+                    Some(serde_json::Value::Object(data)) => data,
+                    _ => {
+                        stats.failures.push((key, "document has no _source".to_string()));
+                        continue;
+                    }
+                };
+
+                match copy_document(&dest, &dest_reader, &mut copied_keys, &dest_type, &key, &data, request.op_type) {
+                    Ok(true) => stats.created += 1,
+                    Ok(false) => stats.updated += 1,
+                    Err(CopyDocumentError::AlreadyExists) => {
+                        stats.version_conflicts += 1;
+
+                        if !request.proceed_on_conflicts {
+                            stats.failures.push((key, "document already exists".to_string()));
+                            return Ok(stats);
+                        }
+                    }
+                    Err(CopyDocumentError::Failed(reason)) => {
+                        stats.failures.push((key, reason));
+                    }
+                }
+
+                if stats.total % PROGRESS_LOG_INTERVAL == 0 {
+                    self.log.info("[sys] reindex progress", b!("source" => request.source_index.clone(), "dest" => request.dest_index.clone(), "documents" => stats.total));
+                }
+            }
+        }
+
+        Ok(stats)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+
+    use query_parser::QueryParseError;
+
+    use super::{parse, ReindexRequest, ReindexParseError, OpType};
+
+    #[test]
+    fn test_parse() {
+        let request = parse(&serde_json::from_str("
+        {
+            \"source\": {
+                \"index\": \"twitter\",
+                \"query\": {
+                    \"term\": {
+                        \"user\": \"kimchy\"
+                    }
+                }
+            },
+            \"dest\": {
+                \"index\": \"new_twitter\",
+                \"type\": \"tweet\",
+                \"op_type\": \"create\"
+            },
+            \"conflicts\": \"proceed\"
+        }
+        ").unwrap());
+
+        assert_eq!(request, Ok(ReindexRequest {
+            source_index: "twitter".to_string(),
+            query: Some(json!({"term": {"user": "kimchy"}})),
+            dest_index: "new_twitter".to_string(),
+            dest_type: Some("tweet".to_string()),
+            op_type: OpType::Create,
+            proceed_on_conflicts: true,
+        }));
+    }
+
+    #[test]
+    fn test_parse_defaults() {
+        let request = parse(&serde_json::from_str("
+        {
+            \"source\": {
+                \"index\": \"twitter\"
+            },
+            \"dest\": {
+                \"index\": \"new_twitter\"
+            }
+        }
+        ").unwrap());
+
+        assert_eq!(request, Ok(ReindexRequest {
+            source_index: "twitter".to_string(),
+            query: None,
+            dest_index: "new_twitter".to_string(),
+            dest_type: None,
+            op_type: OpType::Index,
+            proceed_on_conflicts: false,
+        }));
+    }
+
+    #[test]
+    fn test_parse_missing_dest() {
+        let request = parse(&serde_json::from_str("
+        {
+            \"source\": {
+                \"index\": \"twitter\"
+            }
+        }
+        ").unwrap());
+
+        assert_eq!(request, Err(ReindexParseError::ExpectedKey("dest.index".to_string())));
+    }
+
+    #[test]
+    fn test_parse_invalid_query() {
+        let request = parse(&serde_json::from_str("
+        {
+            \"source\": {
+                \"index\": \"twitter\",
+                \"query\": {
+                    \"foo\": {}
+                }
+            },
+            \"dest\": {
+                \"index\": \"new_twitter\"
+            }
+        }
+        ").unwrap());
+
+        assert_eq!(request, Err(ReindexParseError::InvalidQuery(QueryParseError::UnrecognisedQueryType("foo".to_string()))));
+    }
+}
@@ -0,0 +1,455 @@
+//! Index rollover
+//!
+//! An alias that documents are written through can be rolled over to a new index once its
+//! current write index gets too big or too old. The new index is named after the old one
+//! with its numeric suffix incremented ("logs-000001" is followed by "logs-000002").
+
+use std::fmt;
+
+use serde_json;
+use serde_json::value::ToJson;
+
+use system::{System, current_timestamp};
+use index::metadata::AliasMetadata;
+use index::metadata::parse::settings::parse_time_value;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RolloverCondition {
+    /// The index has at least this many documents
+    MaxDocs(u64),
+
+    /// The index was created at least this many milliseconds ago
+    MaxAge(u64),
+
+    /// The index's store takes up at least this many bytes
+    MaxSize(u64),
+}
+
+
+impl fmt::Display for RolloverCondition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RolloverCondition::MaxDocs(max_docs) => write!(f, "[max_docs: {}]", max_docs),
+            RolloverCondition::MaxAge(max_age) => write!(f, "[max_age: {}ms]", max_age),
+            RolloverCondition::MaxSize(max_size) => write!(f, "[max_size: {}b]", max_size),
+        }
+    }
+}
+
+
+impl RolloverCondition {
+    /// Checks the condition against an index's document count, age and size
+    ///
+    /// Indices without a creation date never meet the "max_age" condition.
+    pub fn is_met(&self, num_docs: u64, age: Option<u64>, size_in_bytes: u64) -> bool {
+        match *self {
+            RolloverCondition::MaxDocs(max_docs) => num_docs >= max_docs,
+            RolloverCondition::MaxAge(max_age) => age.map(|age| age >= max_age).unwrap_or(false),
+            RolloverCondition::MaxSize(max_size) => size_in_bytes >= max_size,
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RolloverRequest {
+    /// The index is rolled over if any of these are met (or if there aren't any)
+    pub conditions: Vec<RolloverCondition>,
+
+    /// The settings, mappings and aliases for the new index
+    pub new_index_data: serde_json::Value,
+}
+
+
+#[derive(Debug, PartialEq)]
+pub enum RolloverParseError {
+    ExpectedObject,
+    ExpectedPositiveInteger(String),
+    InvalidTimeValue(String),
+    InvalidByteSize(String),
+    UnrecognisedCondition(String),
+    UnrecognisedKey(String),
+}
+
+
+impl fmt::Display for RolloverParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RolloverParseError::ExpectedObject => write!(f, "expected an object"),
+            RolloverParseError::ExpectedPositiveInteger(ref key) => write!(f, "[{}] must be a positive integer", key),
+            RolloverParseError::InvalidTimeValue(ref key) => write!(f, "[{}] must be a time value (eg, \"7d\")", key),
+            RolloverParseError::InvalidByteSize(ref key) => write!(f, "[{}] must be a byte size (eg, \"5gb\")", key),
+            RolloverParseError::UnrecognisedCondition(ref key) => write!(f, "unknown rollover condition [{}]", key),
+            RolloverParseError::UnrecognisedKey(ref key) => write!(f, "unknown key [{}] in the rollover request", key),
+        }
+    }
+}
+
+
+/// Reads a byte size (eg, "5gb") as a number of bytes
+fn parse_byte_size(json: &serde_json::Value) -> Option<u64> {
+    let string = match *json {
+        serde_json::Value::Number(ref num) => return num.as_u64(),
+        serde_json::Value::String(ref string) => string.trim().to_lowercase(),
+        _ => return None,
+    };
+
+    let unit_start = string.find(|c: char| !c.is_digit(10)).unwrap_or(string.len());
+    let number = match string[..unit_start].parse::<u64>() {
+        Ok(number) => number,
+        Err(_) => return None,
+    };
+
+    let multiplier: u64 = match &string[unit_start..] {
+        "" | "b" => 1,
+        "kb" => 1 << 10,
+        "mb" => 1 << 20,
+        "gb" => 1 << 30,
+        "tb" => 1 << 40,
+        "pb" => 1 << 50,
+        _ => return None,
+    };
+
+    number.checked_mul(multiplier)
+}
+
+
+pub fn parse(json: &serde_json::Value) -> Result<RolloverRequest, RolloverParseError> {
+    let data = try!(json.as_object().ok_or(RolloverParseError::ExpectedObject));
+
+    let mut conditions = Vec::new();
+    let mut new_index_data = serde_json::Map::new();
+
+    for (key, value) in data.iter() {
+        match key.as_ref() {
+            "conditions" => {
+                let conditions_data = try!(value.as_object().ok_or(RolloverParseError::ExpectedObject));
+
+                for (key, value) in conditions_data.iter() {
+                    let condition = match key.as_ref() {
+                        "max_docs" => {
+                            RolloverCondition::MaxDocs(try!(value.as_u64().ok_or_else(|| RolloverParseError::ExpectedPositiveInteger(key.clone()))))
+                        }
+                        "max_age" => {
+                            match parse_time_value(value) {
+                                Some(Some(max_age)) => RolloverCondition::MaxAge(max_age),
+                                _ => return Err(RolloverParseError::InvalidTimeValue(key.clone())),
+                            }
+                        }
+                        "max_size" => {
+                            RolloverCondition::MaxSize(try!(parse_byte_size(value).ok_or_else(|| RolloverParseError::InvalidByteSize(key.clone()))))
+                        }
+                        _ => return Err(RolloverParseError::UnrecognisedCondition(key.clone())),
+                    };
+
+                    conditions.push(condition);
+                }
+            }
+            "settings" | "mappings" | "aliases" => {
+                // These are checked when the new index is created
+                new_index_data.insert(key.clone(), value.clone());
+            }
+            _ => return Err(RolloverParseError::UnrecognisedKey(key.clone())),
+        }
+    }
+
+    Ok(RolloverRequest {
+        conditions: conditions,
+        new_index_data: serde_json::Value::Object(new_index_data),
+    })
+}
+
+
+/// Works out the name of the index that follows the given one
+///
+/// The name must end with a "-" followed by a number, which is incremented and padded to
+/// six digits.
+pub fn next_index_name(index_name: &str) -> Option<String> {
+    let separator = match index_name.rfind('-') {
+        Some(separator) => separator,
+        None => return None,
+    };
+
+    let (prefix, suffix) = (&index_name[..separator], &index_name[separator + 1..]);
+    if suffix.is_empty() || !suffix.chars().all(|c| c.is_digit(10)) {
+        return None;
+    }
+
+    let number = match suffix.parse::<u64>() {
+        Ok(number) => number,
+        Err(_) => return None,
+    };
+
+    Some(format!("{}-{:06}", prefix, number + 1))
+}
+
+
+#[derive(Debug)]
+pub enum RolloverError {
+    AliasNotFound(String),
+    NotAnAlias(String),
+    NoWriteIndex(String),
+    InvalidIndexName(String),
+    IndexAlreadyExists(String),
+    IndexClosed(String),
+    StatsError(String),
+    CreateIndexError(String),
+    SaveMetadataError(String),
+}
+
+
+impl fmt::Display for RolloverError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RolloverError::AliasNotFound(ref name) => write!(f, "no such alias [{}]", name),
+            RolloverError::NotAnAlias(ref name) => write!(f, "rollover target [{}] is not an alias", name),
+            RolloverError::NoWriteIndex(ref name) => write!(f, "no write index is defined for alias [{}]", name),
+            RolloverError::InvalidIndexName(ref name) => write!(f, "index name [{}] does not match pattern '^.*-\\d+$'", name),
+            RolloverError::IndexAlreadyExists(ref name) => write!(f, "index [{}] already exists", name),
+            RolloverError::IndexClosed(ref name) => write!(f, "index [{}] is closed", name),
+            RolloverError::StatsError(ref e) => write!(f, "{}", e),
+            RolloverError::CreateIndexError(ref e) => write!(f, "{}", e),
+            RolloverError::SaveMetadataError(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+
+#[derive(Debug)]
+pub struct RolloverResult {
+    pub old_index: String,
+    pub new_index: String,
+    pub rolled_over: bool,
+    pub dry_run: bool,
+
+    /// Each of the request's conditions and whether it was met
+    pub conditions: Vec<(RolloverCondition, bool)>,
+}
+
+
+impl ToJson for RolloverResult {
+    fn to_json(&self) -> Result<serde_json::Value, serde_json::Error> {
+        let mut conditions_json = serde_json::Map::new();
+        for &(ref condition, is_met) in self.conditions.iter() {
+            conditions_json.insert(condition.to_string(), json!(is_met));
+        }
+
+        Ok(json!({
+            "acknowledged": self.rolled_over,
+            "old_index": self.old_index,
+            "new_index": self.new_index,
+            "rolled_over": self.rolled_over,
+            "dry_run": self.dry_run,
+            "conditions": conditions_json,
+        }))
+    }
+}
+
+
+impl System {
+    /// Points an alias's writes at a new index if any of the request's conditions are met
+    ///
+    /// If the alias marks its current index as the write index, the alias is kept on the
+    /// old index so it can still be searched. Otherwise, the alias is moved to the new index.
+    pub fn rollover(&self, alias_name: &str, new_index_name: Option<&str>, request: &RolloverRequest, dry_run: bool) -> Result<RolloverResult, RolloverError> {
+        let mut cluster_metadata_guard = self.metadata.write().unwrap();
+        let cluster_metadata = &mut *cluster_metadata_guard;
+
+        if !cluster_metadata.names.is_alias(alias_name) {
+            if cluster_metadata.names.find(alias_name).is_empty() {
+                return Err(RolloverError::AliasNotFound(alias_name.to_string()));
+            } else {
+                return Err(RolloverError::NotAnAlias(alias_name.to_string()));
+            }
+        }
+
+        // Find the index that's currently being written to
+        let (old_index_name, alias, stats, creation_date) = {
+            let old_index = match cluster_metadata.find_write_index(alias_name) {
+                Some(old_index) => old_index,
+                None => return Err(RolloverError::NoWriteIndex(alias_name.to_string())),
+            };
+
+            if old_index.is_closed() {
+                return Err(RolloverError::IndexClosed(old_index.canonical_name().to_string()));
+            }
+
+            let stats = try!(old_index.store_stats().map_err(RolloverError::StatsError));
+            let old_index_metadata = old_index.metadata.read().unwrap();
+            let alias = old_index_metadata.aliases.get(alias_name).cloned().unwrap_or_default();
+
+            (old_index.canonical_name().to_string(), alias, stats, old_index_metadata.settings.creation_date)
+        };
+        let old_index_ref = cluster_metadata.names.find_canonical(&old_index_name).unwrap();
+
+        let new_index_name = match new_index_name {
+            Some(new_index_name) => new_index_name.to_string(),
+            None => try!(next_index_name(&old_index_name).ok_or_else(|| RolloverError::InvalidIndexName(old_index_name.clone()))),
+        };
+
+        if !cluster_metadata.names.find(&new_index_name).is_empty() {
+            return Err(RolloverError::IndexAlreadyExists(new_index_name));
+        }
+
+        // Check the conditions
+        let age = creation_date.map(|creation_date| current_timestamp().saturating_sub(creation_date));
+        let conditions = request.conditions.iter().map(|condition| {
+            (*condition, condition.is_met(stats.num_docs, age, stats.size_in_bytes))
+        }).collect::<Vec<_>>();
+        let conditions_met = conditions.is_empty() || conditions.iter().any(|&(_, is_met)| is_met);
+
+        let mut result = RolloverResult {
+            old_index: old_index_name.clone(),
+            new_index: new_index_name.clone(),
+            rolled_over: false,
+            dry_run: dry_run,
+            conditions: conditions,
+        };
+
+        if dry_run || !conditions_met {
+            return Ok(result);
+        }
+
+        let new_index_ref = try!(self.create_index(cluster_metadata, &new_index_name, Some(request.new_index_data.clone())).map_err(RolloverError::CreateIndexError));
+
+        // Switch the alias over to the new index
+        //
+        // If either index's metadata can't be saved, the alias is put back on the old
+        // index so it isn't left pointing at a different index after a restart. The new
+        // index is kept.
+        let old_index = cluster_metadata.indices.get(&old_index_ref).cloned();
+        let new_index = cluster_metadata.indices.get(&new_index_ref).cloned();
+        let keep_on_old_index = alias.is_write_index == Some(true);
+
+        if let Some(ref old_index) = old_index {
+            let mut old_index_metadata = old_index.metadata.write().unwrap();
+
+            if keep_on_old_index {
+                old_index_metadata.aliases.insert(alias_name.to_string(), AliasMetadata {
+                    is_write_index: Some(false),
+                    ..alias.clone()
+                });
+            } else {
+                old_index_metadata.aliases.remove(alias_name);
+            }
+
+            if let Err(e) = old_index_metadata.save(old_index.metadata_path()) {
+                old_index_metadata.aliases.insert(alias_name.to_string(), alias.clone());
+                return Err(RolloverError::SaveMetadataError(e.into()));
+            }
+        }
+
+        if let Some(ref new_index) = new_index {
+            let mut new_index_metadata = new_index.metadata.write().unwrap();
+            new_index_metadata.aliases.insert(alias_name.to_string(), alias.clone());
+
+            if let Err(e) = new_index_metadata.save(new_index.metadata_path()) {
+                new_index_metadata.aliases.remove(alias_name);
+
+                if let Some(ref old_index) = old_index {
+                    let mut old_index_metadata = old_index.metadata.write().unwrap();
+                    old_index_metadata.aliases.insert(alias_name.to_string(), alias.clone());
+
+                    if let Err(e) = old_index_metadata.save(old_index.metadata_path()) {
+                        let e: String = e.into();
+                        self.log.warn("[sys] unable to restore alias after failed rollover", b!("alias" => alias_name, "index" => old_index_name.clone(), "error" => e));
+                    }
+                }
+
+                return Err(RolloverError::SaveMetadataError(e.into()));
+            }
+        }
+
+        // Both indices have been saved so the names can be updated. The alias was checked
+        // above and the metadata lock is held so these can't fail.
+        if !keep_on_old_index {
+            try!(cluster_metadata.names.delete_alias(alias_name, old_index_ref).map_err(|_| RolloverError::NotAnAlias(alias_name.to_string())));
+        }
+        try!(cluster_metadata.names.add_alias_index(alias_name.to_string(), new_index_ref).map_err(|_| RolloverError::NotAnAlias(alias_name.to_string())));
+
+        self.log.info("[sys] rolled over alias", b!("alias" => alias_name, "old_index" => old_index_name, "new_index" => new_index_name));
+
+        result.rolled_over = true;
+        Ok(result)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+
+    use super::{RolloverRequest, RolloverCondition, RolloverParseError, parse, next_index_name};
+
+    #[test]
+    fn test_parse() {
+        let request = parse(&serde_json::from_str("
+        {
+            \"conditions\": {
+                \"max_docs\": 1000,
+                \"max_age\": \"7d\",
+                \"max_size\": \"5gb\"
+            },
+            \"settings\": {
+                \"refresh_interval\": \"5s\"
+            }
+        }
+        ").unwrap());
+
+        assert_eq!(request, Ok(RolloverRequest {
+            conditions: vec![
+                RolloverCondition::MaxAge(7 * 24 * 60 * 60 * 1000),
+                RolloverCondition::MaxDocs(1000),
+                RolloverCondition::MaxSize(5 * 1024 * 1024 * 1024),
+            ],
+            new_index_data: json!({
+                "settings": {
+                    "refresh_interval": "5s"
+                }
+            }),
+        }));
+    }
+
+    #[test]
+    fn test_parse_invalid_condition() {
+        let request = parse(&serde_json::from_str("
+        {
+            \"conditions\": {
+                \"max_size\": \"lots\"
+            }
+        }
+        ").unwrap());
+
+        assert_eq!(request, Err(RolloverParseError::InvalidByteSize("max_size".to_string())));
+
+        let request = parse(&serde_json::from_str("
+        {
+            \"conditions\": {
+                \"max_foo\": 1
+            }
+        }
+        ").unwrap());
+
+        assert_eq!(request, Err(RolloverParseError::UnrecognisedCondition("max_foo".to_string())));
+    }
+
+    #[test]
+    fn test_condition_is_met() {
+        assert!(RolloverCondition::MaxDocs(10).is_met(10, None, 0));
+        assert!(!RolloverCondition::MaxDocs(10).is_met(9, None, 0));
+        assert!(RolloverCondition::MaxAge(1000).is_met(0, Some(2000), 0));
+        assert!(!RolloverCondition::MaxAge(1000).is_met(0, None, 0));
+        assert!(RolloverCondition::MaxSize(1024).is_met(0, None, 2048));
+    }
+
+    #[test]
+    fn test_next_index_name() {
+        assert_eq!(next_index_name("logs-000001"), Some("logs-000002".to_string()));
+        assert_eq!(next_index_name("logs-2017-000009"), Some("logs-2017-000010".to_string()));
+        assert_eq!(next_index_name("logs-1"), Some("logs-000002".to_string()));
+        assert_eq!(next_index_name("logs"), None);
+        assert_eq!(next_index_name("logs-"), None);
+        assert_eq!(next_index_name("logs-a1"), None);
+    }
+}
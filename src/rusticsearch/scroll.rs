@@ -0,0 +1,342 @@
+//! Keeps the state of scrolling searches between requests
+//!
+//! A scroll keeps the query it ran on each index and the sort key of the last hit it
+//! returned. Each request to "/_search/scroll" runs the query again and returns the next
+//! page of hits after that key, so only one page is held in memory at a time. Every hit
+//! has a unique tiebreaker in its sort key, so none are skipped or returned twice.
+//!
+//! The store of each index is kept open for as long as the scroll, but kite's readers
+//! borrow their store so a reader (and the point in time view it has) can't be kept in
+//! the context. Pages are read from the store as it is when they're requested, so
+//! documents that were indexed or updated after the scroll started can be returned.
+//!
+//! Contexts that aren't used within their keep alive time are removed, and there's a
+//! limit on how many can be open at once.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use uuid::Uuid;
+use kite::Query;
+use kite_rocksdb::RocksDBIndexStore;
+
+use index::Index;
+use collectors::TopSortedCollector;
+use highlight::{HighlightRequest, QueryTerms};
+use sort::{Sort, SortKey};
+use source_filter::SourceFilter;
+use hit_fields::DocValueField;
+
+
+/// The most scroll contexts that can be open at once, like Elasticsearch's "search.max_open_scroll_context"
+pub const MAX_OPEN_SCROLL_CONTEXTS: usize = 500;
+
+
+#[derive(Debug, PartialEq)]
+pub enum ScrollError {
+    TooManyContexts,
+}
+
+
+impl fmt::Display for ScrollError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ScrollError::TooManyContexts => write!(f, "trying to create too many scroll contexts, must be less than or equal to: [{}]", MAX_OPEN_SCROLL_CONTEXTS),
+        }
+    }
+}
+
+
+/// An index that a scroll searches, with the query that was built for it
+#[derive(Debug)]
+pub struct ScrollIndex {
+    pub index_id: Uuid,
+
+    /// The store the scroll was started on, kept open until the scroll is removed
+    pub store: Arc<RocksDBIndexStore>,
+    pub query: Arc<Query>,
+}
+
+
+/// A hit that can be read back from its index later
+#[derive(Debug, Clone, PartialEq)]
+pub struct HitRef {
+    pub index_id: Uuid,
+    pub doc_id: u64,
+    pub sort_key: SortKey,
+}
+
+
+/// How the hits of a search are shown in the response
+#[derive(Debug, Clone)]
+pub struct HitOptions {
+    /// Stored fields to put in the "fields" section of each hit
+    pub field_names: Vec<String>,
+
+    /// Fields to read from doc values into the "fields" section of each hit
+    pub docvalue_fields: Vec<DocValueField>,
+    pub source_filter: SourceFilter,
+    pub highlight: Option<HighlightRequest>,
+
+    /// The terms that the search's query was built into on each index, used for highlighting
+    pub query_terms: HashMap<Uuid, QueryTerms>,
+
+    /// If set, each hit gets a "sort" section with its values for this sort
+    pub sort: Option<Sort>,
+
+    /// Add an "_explanation" of its score to each hit
+    pub explain: bool,
+
+    /// The query that was run on each index, only kept if the hits are being explained
+    pub explain_queries: HashMap<Uuid, Arc<Query>>,
+}
+
+
+#[derive(Debug)]
+pub struct ScrollContext {
+    /// The indices in the order they were searched, which is part of the hits' tiebreakers
+    indices: Vec<ScrollIndex>,
+    sort: Sort,
+
+    /// The sort key of the last hit that was returned, the next page starts after it
+    last_key: Option<SortKey>,
+    pub total_hits: u64,
+
+    /// The number of hits to return with each page
+    size: usize,
+    pub hit_options: HitOptions,
+
+    /// How long the context is kept after it was last used, in milliseconds
+    keep_alive: u64,
+    expires_at: u64,
+}
+
+
+impl ScrollContext {
+    pub fn new(indices: Vec<ScrollIndex>, sort: Sort, last_key: Option<SortKey>, total_hits: u64, size: usize, hit_options: HitOptions, keep_alive: u64, now: u64) -> ScrollContext {
+        ScrollContext {
+            indices: indices,
+            sort: sort,
+            last_key: last_key,
+            total_hits: total_hits,
+            size: size,
+            hit_options: hit_options,
+            keep_alive: keep_alive,
+            expires_at: now + keep_alive,
+        }
+    }
+
+    /// Keeps the context alive for another keep alive period from now
+    ///
+    /// If a new keep alive is given, it's used for this and all later requests.
+    pub fn renew(&mut self, keep_alive: Option<u64>, now: u64) {
+        if let Some(keep_alive) = keep_alive {
+            self.keep_alive = keep_alive;
+        }
+
+        self.expires_at = now + self.keep_alive;
+    }
+
+    /// Searches for the next page of hits
+    ///
+    /// Indices that aren't in the list (because they've been deleted since the scroll
+    /// started) are skipped. Returns an empty page once all the hits have been returned.
+    pub fn next_page(&mut self, indices: &[&Index]) -> Vec<HitRef> {
+        let mut hits = Vec::new();
+
+        for (index_ordinal, scroll_index) in self.indices.iter().enumerate() {
+            let index = match indices.iter().find(|index| *index.id() == scroll_index.index_id) {
+                Some(index) => index,
+                None => continue,
+            };
+            let index_reader = scroll_index.store.reader();
+            let index_metadata = index.metadata.read().unwrap();
+
+            // The mapping may have changed so the sort fields can't be read any more
+            let index_sort = match self.sort.build(&index_metadata, &index_reader.schema(), index_ordinal) {
+                Ok(index_sort) => index_sort,
+                Err(e) => {
+                    warn!("couldn't sort scroll hits on index {:?}: {:?}", index.canonical_name(), e);
+                    continue;
+                }
+            };
+
+            let mut collector = TopSortedCollector::new(&self.sort, &index_sort, &index_reader, self.size).search_after(self.last_key.as_ref());
+            if index_reader.search(&mut collector, &scroll_index.query).is_err() {
+                warn!("couldn't search index {:?} for scroll hits", index.canonical_name());
+                continue;
+            }
+
+            hits.extend(collector.into_sorted_vec().into_iter().map(|(doc_id, sort_key)| {
+                HitRef {
+                    index_id: scroll_index.index_id,
+                    doc_id: doc_id,
+                    sort_key: sort_key,
+                }
+            }));
+        }
+
+        // Merge the hits from each index
+        hits.sort_by(|a, b| self.sort.compare(&a.sort_key, &b.sort_key));
+        hits.truncate(self.size);
+
+        if let Some(hit) = hits.last() {
+            self.last_key = Some(hit.sort_key.clone());
+        }
+
+        hits
+    }
+}
+
+
+/// The scroll contexts that are currently open, by scroll id
+#[derive(Debug)]
+pub struct ScrollContexts {
+    contexts: HashMap<String, ScrollContext>,
+}
+
+
+impl ScrollContexts {
+    pub fn new() -> ScrollContexts {
+        ScrollContexts {
+            contexts: HashMap::new(),
+        }
+    }
+
+    /// Adds a context, returning its scroll id
+    ///
+    /// Fails if the maximum number of contexts are already open.
+    pub fn insert(&mut self, context: ScrollContext, now: u64) -> Result<String, ScrollError> {
+        self.remove_expired(now);
+
+        if self.contexts.len() >= MAX_OPEN_SCROLL_CONTEXTS {
+            return Err(ScrollError::TooManyContexts);
+        }
+
+        let scroll_id = Uuid::new_v4().simple().to_string();
+        self.contexts.insert(scroll_id.clone(), context);
+        Ok(scroll_id)
+    }
+
+    /// Finds a context that hasn't expired
+    pub fn get_mut(&mut self, scroll_id: &str, now: u64) -> Option<&mut ScrollContext> {
+        self.remove_expired(now);
+        self.contexts.get_mut(scroll_id)
+    }
+
+    /// Removes a context, returning false if there wasn't one with the given id
+    pub fn remove(&mut self, scroll_id: &str) -> bool {
+        self.contexts.remove(scroll_id).is_some()
+    }
+
+    /// Removes the contexts that search an index
+    ///
+    /// This is done when the index is closed or deleted so its store is released.
+    pub fn remove_index(&mut self, index_id: &Uuid) {
+        let scroll_ids = self.contexts.iter()
+            .filter(|&(_, context)| context.indices.iter().any(|scroll_index| scroll_index.index_id == *index_id))
+            .map(|(scroll_id, _)| scroll_id.clone())
+            .collect::<Vec<_>>();
+
+        for scroll_id in scroll_ids {
+            self.contexts.remove(&scroll_id);
+        }
+    }
+
+    /// Removes every context, returning how many there were
+    pub fn clear(&mut self) -> usize {
+        let num_contexts = self.contexts.len();
+        self.contexts.clear();
+        num_contexts
+    }
+
+    pub fn remove_expired(&mut self, now: u64) {
+        let expired = self.contexts.iter()
+            .filter(|&(_, context)| context.expires_at <= now)
+            .map(|(scroll_id, _)| scroll_id.clone())
+            .collect::<Vec<_>>();
+
+        for scroll_id in expired {
+            self.contexts.remove(&scroll_id);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use sort::Sort;
+    use source_filter::SourceFilter;
+
+    use super::{HitOptions, ScrollContext, ScrollContexts, ScrollError, MAX_OPEN_SCROLL_CONTEXTS};
+
+    fn make_context(keep_alive: u64, now: u64) -> ScrollContext {
+        let hit_options = HitOptions {
+            field_names: Vec::new(),
+            docvalue_fields: Vec::new(),
+            source_filter: SourceFilter::default(),
+            highlight: None,
+            query_terms: HashMap::new(),
+            sort: None,
+            explain: false,
+            explain_queries: HashMap::new(),
+        };
+
+        ScrollContext::new(Vec::new(), Sort::default(), None, 0, 10, hit_options, keep_alive, now)
+    }
+
+    #[test]
+    fn test_next_page_without_indices() {
+        let mut context = make_context(1000, 0);
+
+        assert_eq!(context.next_page(&[]), vec![]);
+    }
+
+    #[test]
+    fn test_expiry() {
+        let mut contexts = ScrollContexts::new();
+        let scroll_id = contexts.insert(make_context(1000, 0), 0).unwrap();
+
+        // Using the context keeps it alive for longer
+        contexts.get_mut(&scroll_id, 900).unwrap().renew(None, 900);
+        assert!(contexts.get_mut(&scroll_id, 1500).is_some());
+
+        assert!(contexts.get_mut(&scroll_id, 1900).is_none());
+    }
+
+    #[test]
+    fn test_renew_with_new_keep_alive() {
+        let mut contexts = ScrollContexts::new();
+        let scroll_id = contexts.insert(make_context(1000, 0), 0).unwrap();
+
+        contexts.get_mut(&scroll_id, 500).unwrap().renew(Some(5000), 500);
+        assert!(contexts.get_mut(&scroll_id, 5000).is_some());
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut contexts = ScrollContexts::new();
+        let scroll_id = contexts.insert(make_context(1000, 0), 0).unwrap();
+        contexts.insert(make_context(1000, 0), 0).unwrap();
+
+        assert!(contexts.remove(&scroll_id));
+        assert!(!contexts.remove(&scroll_id));
+        assert_eq!(contexts.clear(), 1);
+    }
+
+    #[test]
+    fn test_too_many_contexts() {
+        let mut contexts = ScrollContexts::new();
+        for _ in 0..MAX_OPEN_SCROLL_CONTEXTS {
+            contexts.insert(make_context(1000, 0), 0).unwrap();
+        }
+
+        assert_eq!(contexts.insert(make_context(1000, 0), 0), Err(ScrollError::TooManyContexts));
+
+        // Expired contexts don't count towards the limit
+        assert!(contexts.insert(make_context(1000, 1000), 1000).is_ok());
+    }
+}
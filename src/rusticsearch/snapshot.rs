@@ -0,0 +1,281 @@
+//! Copies indices into snapshot directories and restores them
+//!
+//! Snapshots are stored in the data directory under
+//! `snapshots/{repository}/{snapshot}/{index}`. Each index is saved as a copy of its
+//! store directory (which includes the index metadata), so restoring a snapshot is
+//! the same as loading an index on startup.
+//!
+//! RocksDB keeps writing to the store directory while it's open (flushes and background
+//! compactions), so a copy of an open store may not be consistent. Only closed indices,
+//! whose stores have been released, can be snapshotted. They're restored closed too.
+
+use std::io;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use uuid::Uuid;
+
+use system::System;
+
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    InvalidName(String),
+    IndexNotFound(String),
+    IndexAlreadyExists(String),
+    IndexNotClosed(String),
+    SnapshotAlreadyExists(String),
+    SnapshotNotFound(String),
+    IoError(io::Error),
+    LoadError(String, String),
+}
+
+
+impl From<io::Error> for SnapshotError {
+    fn from(e: io::Error) -> SnapshotError {
+        SnapshotError::IoError(e)
+    }
+}
+
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SnapshotError::InvalidName(ref name) => write!(f, "invalid name [{}]", name),
+            SnapshotError::IndexNotFound(ref name) => write!(f, "no such index [{}]", name),
+            SnapshotError::IndexAlreadyExists(ref name) => write!(f, "index [{}] already exists", name),
+            SnapshotError::IndexNotClosed(ref name) => write!(f, "index [{}] must be closed before it can be snapshotted", name),
+            SnapshotError::SnapshotAlreadyExists(ref name) => write!(f, "snapshot [{}] already exists", name),
+            SnapshotError::SnapshotNotFound(ref name) => write!(f, "snapshot [{}] is missing", name),
+            SnapshotError::IoError(ref e) => write!(f, "{}", e),
+            SnapshotError::LoadError(ref name, ref e) => write!(f, "unable to load index [{}]: {}", name, e),
+        }
+    }
+}
+
+
+/// Repository and snapshot names are used as directory names so they can't be paths
+fn check_name(name: &str) -> Result<(), SnapshotError> {
+    if name.is_empty() || name.starts_with('.') || name.contains('/') || name.contains('\\') {
+        return Err(SnapshotError::InvalidName(name.to_string()));
+    }
+
+    Ok(())
+}
+
+
+fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    try!(fs::create_dir_all(to));
+
+    for entry in try!(fs::read_dir(from)) {
+        let entry = try!(entry);
+        let target = to.join(entry.file_name());
+
+        if try!(entry.file_type()).is_dir() {
+            try!(copy_dir(&entry.path(), &target));
+        } else {
+            try!(fs::copy(entry.path(), target));
+        }
+    }
+
+    Ok(())
+}
+
+
+impl System {
+    pub fn get_snapshots_dir(&self) -> PathBuf {
+        let mut dir = self.get_data_dir().to_path_buf();
+        dir.push("snapshots");
+        dir
+    }
+
+    fn get_snapshot_dir(&self, repository: &str, snapshot: &str) -> Result<PathBuf, SnapshotError> {
+        try!(check_name(repository));
+        try!(check_name(snapshot));
+
+        let mut dir = self.get_snapshots_dir();
+        dir.push(repository);
+        dir.push(snapshot);
+        Ok(dir)
+    }
+
+    /// Copies the indices matched by the selector into a new snapshot
+    ///
+    /// Every index must be closed. The cluster metadata is locked while the copy is made
+    /// so none of them can be opened again until it's finished.
+    /// Returns the names of the indices in the snapshot.
+    pub fn create_snapshot(&self, repository: &str, snapshot: &str, index_selector: &str) -> Result<Vec<String>, SnapshotError> {
+        let snapshot_dir = try!(self.get_snapshot_dir(repository, snapshot));
+        if snapshot_dir.exists() {
+            return Err(SnapshotError::SnapshotAlreadyExists(snapshot.to_string()));
+        }
+
+        let cluster_metadata = self.metadata.write().unwrap();
+
+        // Find the indices to copy. The selector is a comma separated list of index names
+        // and aliases, or "_all"/"*" to select every index
+        let mut index_refs = Vec::new();
+        for name in index_selector.split(',').map(|name| name.trim()) {
+            let found = if name == "_all" || name == "*" {
+                cluster_metadata.indices.keys().cloned().collect()
+            } else {
+                cluster_metadata.names.find(name)
+            };
+
+            if found.is_empty() && name != "_all" && name != "*" {
+                return Err(SnapshotError::IndexNotFound(name.to_string()));
+            }
+
+            for index_ref in found {
+                if !index_refs.contains(&index_ref) {
+                    index_refs.push(index_ref);
+                }
+            }
+        }
+
+        if index_refs.is_empty() {
+            return Err(SnapshotError::IndexNotFound(index_selector.to_string()));
+        }
+
+        // Requests hold the cluster metadata read lock while they use a store, but tasks like
+        // reindexing don't so the store of a closed index may still be open
+        for index in index_refs.iter().filter_map(|index_ref| cluster_metadata.indices.get(index_ref)) {
+            if !index.is_closed() || !index.is_store_released() {
+                return Err(SnapshotError::IndexNotClosed(index.canonical_name().to_string()));
+            }
+        }
+
+        // Copy them
+        let mut index_names = Vec::new();
+        for index in index_refs.iter().filter_map(|index_ref| cluster_metadata.indices.get(index_ref)) {
+            let index_name = index.canonical_name().to_string();
+
+            // The index metadata is saved whenever it changes, so it's already in the store directory
+            if let Err(e) = copy_dir(index.path(), &snapshot_dir.join(&index_name)) {
+                // Don't leave a partial snapshot behind
+                let _ = fs::remove_dir_all(&snapshot_dir);
+                return Err(SnapshotError::IoError(e));
+            }
+
+            index_names.push(index_name);
+        }
+
+        index_names.sort();
+        Ok(index_names)
+    }
+
+    /// Lists the names of the indices saved in a snapshot
+    pub fn get_snapshot_indices(&self, repository: &str, snapshot: &str) -> Result<Vec<String>, SnapshotError> {
+        let snapshot_dir = try!(self.get_snapshot_dir(repository, snapshot));
+        if !snapshot_dir.is_dir() {
+            return Err(SnapshotError::SnapshotNotFound(snapshot.to_string()));
+        }
+
+        let mut index_names = Vec::new();
+        for entry in try!(fs::read_dir(&snapshot_dir)) {
+            let entry = try!(entry);
+            if try!(entry.file_type()).is_dir() {
+                if let Some(index_name) = entry.file_name().to_str() {
+                    index_names.push(index_name.to_string());
+                }
+            }
+        }
+
+        index_names.sort();
+        Ok(index_names)
+    }
+
+    /// Restores every index in a snapshot as a new index
+    ///
+    /// Nothing is restored if any of the indices have the name of an existing index or
+    /// alias, or if any of them fail to load.
+    pub fn restore_snapshot(&self, repository: &str, snapshot: &str) -> Result<Vec<String>, SnapshotError> {
+        let snapshot_dir = try!(self.get_snapshot_dir(repository, snapshot));
+        let index_names = try!(self.get_snapshot_indices(repository, snapshot));
+
+        let mut cluster_metadata = self.metadata.write().unwrap();
+
+        for index_name in index_names.iter() {
+            if !cluster_metadata.names.find(index_name).is_empty() {
+                return Err(SnapshotError::IndexAlreadyExists(index_name.clone()));
+            }
+        }
+
+        // Copy and load every index before any of them are registered, so a failure
+        // only has to clean up the directories
+        let mut index_dirs = Vec::new();
+        let mut indices = Vec::new();
+        for index_name in index_names.iter() {
+            let mut index_dir = self.get_indices_dir();
+            index_dir.push(index_name);
+
+            let result = copy_dir(&snapshot_dir.join(index_name), &index_dir).map_err(SnapshotError::IoError)
+                .and_then(|_| self.load_index(Uuid::new_v4(), index_name.clone(), &index_dir).map_err(|e| SnapshotError::LoadError(index_name.clone(), e)));
+            index_dirs.push(index_dir);
+
+            match result {
+                Ok(index) => indices.push(index),
+                Err(e) => {
+                    // Close the stores of the indices that were loaded before removing them
+                    drop(indices);
+                    for index_dir in index_dirs.iter() {
+                        let _ = fs::remove_dir_all(index_dir);
+                    }
+
+                    return Err(e);
+                }
+            }
+        }
+
+        // The names were checked above while the cluster metadata was locked so they're
+        // still free. Aliases are registered once every index has its name, so an alias
+        // with the name of another restored index is skipped rather than taking it
+        let mut index_refs = Vec::new();
+        for (index_name, index) in index_names.iter().zip(indices) {
+            let index_ref = cluster_metadata.insert_index(index);
+            if cluster_metadata.names.insert_canonical(index_name.clone(), index_ref).is_err() {
+                cluster_metadata.indices.remove(&index_ref);
+                for (index_name, index_ref) in index_names.iter().zip(index_refs) {
+                    cluster_metadata.indices.remove(&index_ref);
+                    let _ = cluster_metadata.names.delete_canonical(index_name, index_ref);
+                }
+                for index_dir in index_dirs.iter() {
+                    let _ = fs::remove_dir_all(index_dir);
+                }
+
+                return Err(SnapshotError::IndexAlreadyExists(index_name.clone()));
+            }
+
+            index_refs.push(index_ref);
+        }
+
+        for (index_name, index_ref) in index_names.iter().zip(index_refs) {
+            if let Err(e) = cluster_metadata.insert_index_aliases(index_ref) {
+                self.log.warn("[sys] could not register index aliases", b!("index" => index_name.clone(), "error" => e));
+            }
+
+            self.log.info("[sys] restored index", b!("index" => index_name.clone(), "snapshot" => snapshot.to_string()));
+        }
+
+        Ok(index_names)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::check_name;
+
+    #[test]
+    fn test_check_name() {
+        assert!(check_name("my_backup").is_ok());
+        assert!(check_name("snapshot-2017.05.01").is_ok());
+
+        assert!(check_name("").is_err());
+        assert!(check_name("..").is_err());
+        assert!(check_name(".hidden").is_err());
+        assert!(check_name("foo/bar").is_err());
+        assert!(check_name("foo\\bar").is_err());
+    }
+}
@@ -0,0 +1,966 @@
+//! Parses the "sort" section of search requests and orders hits by it
+//!
+//! Hits are sorted by a list of clauses, each of which sorts by the score, the value of a
+//! field or the distance from a "geo_point" field to a point. Later clauses are only used
+//! to order hits that are equal on all of the earlier ones.
+
+use std::cmp::Ordering;
+
+use chrono::{DateTime, UTC};
+use serde_json;
+use serde_json::Value as Json;
+use kite::document::{DocRef, FieldValue};
+use kite::schema::{Schema, FieldRef};
+use kite_rocksdb::RocksDBIndexReader;
+
+use index::metadata::IndexMetadata;
+use mapping::{FieldMapping, FieldType};
+use aggregations::datetime_to_millis;
+use geo::{self, GeoPoint, parse_distance};
+
+
+#[derive(Debug, PartialEq)]
+pub enum SortParseError {
+    ExpectedObjectOrString,
+    ExpectedSingleKey,
+    ExpectedString,
+    UnrecognisedKey(String),
+    InvalidOrder(String),
+    InvalidMode(String),
+    ExpectedMissingValue,
+    ExpectedArray,
+    ExpectedObject,
+    InvalidGeoPoint,
+    InvalidUnit(String),
+    InvalidSearchAfterValue(Json),
+
+    /// There must be a "search_after" value for each sort clause, optionally followed by the tiebreaker
+    SearchAfterLengthMismatch {
+        expected: usize,
+        actual: usize,
+    },
+}
+
+
+#[derive(Debug, PartialEq)]
+pub enum SortBuildError {
+    FieldNotSortable(String),
+    FieldNotNumeric(String),
+    FieldNotGeoPoint(String),
+    InvalidMissingValue(String),
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SortField {
+    /// Sorts by relevance
+    Score,
+
+    /// Sorts by the value of a field
+    Field(String),
+
+    /// Sorts by the distance from the points in a "geo_point" field to the origin
+    ///
+    /// Distances are given in the unit, which is the number of metres in it.
+    GeoDistance {
+        field: String,
+        origin: GeoPoint,
+        unit: f64,
+    },
+}
+
+
+/// Where hits that don't have a value for the sort field go
+#[derive(Debug, Clone, PartialEq)]
+pub enum Missing {
+    First,
+    Last,
+
+    /// Hits without a value are sorted as if they had this one
+    Value(Json),
+}
+
+
+/// How one value is picked for sorting from a field with many values
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortMode {
+    Min,
+    Max,
+
+    /// The mean of the values, numeric fields only
+    Avg,
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SortClause {
+    pub field: SortField,
+    pub order: SortOrder,
+    pub missing: Missing,
+
+    /// If not set, the lowest value is used in ascending order and the highest in descending
+    pub mode: Option<SortMode>,
+}
+
+
+impl SortClause {
+    pub fn new(field: SortField, order: SortOrder) -> SortClause {
+        SortClause {
+            field: field,
+            order: order,
+            missing: Missing::Last,
+            mode: None,
+        }
+    }
+
+    fn mode(&self) -> SortMode {
+        match (self.mode, self.order) {
+            (Some(mode), _) => mode,
+            (None, SortOrder::Asc) => SortMode::Min,
+            (None, SortOrder::Desc) => SortMode::Max,
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sort {
+    pub clauses: Vec<SortClause>,
+}
+
+
+impl Default for Sort {
+    fn default() -> Sort {
+        Sort {
+            clauses: vec![
+                SortClause::new(SortField::Score, SortOrder::Desc),
+            ],
+        }
+    }
+}
+
+
+/// The value of a field that a hit is sorted by
+#[derive(Debug, Clone, PartialEq)]
+pub enum SortValue {
+    Integer(i64),
+
+    /// A number that isn't an integer, such as the average of a field's values
+    Float(f64),
+
+    Boolean(bool),
+    DateTime(DateTime<UTC>),
+    String(String),
+}
+
+
+impl SortValue {
+    pub fn from_field_value(value: FieldValue) -> SortValue {
+        match value {
+            FieldValue::String(string) => SortValue::String(string),
+            FieldValue::Integer(integer) => SortValue::Integer(integer),
+            FieldValue::Boolean(boolean) => SortValue::Boolean(boolean),
+            FieldValue::DateTime(date_time) => SortValue::DateTime(date_time),
+        }
+    }
+
+    /// Booleans and dates are given as numbers, the same as aggregation keys
+    pub fn to_json(&self) -> Json {
+        match *self {
+            SortValue::Integer(integer) => json!(integer),
+            SortValue::Float(float) => json!(float),
+            SortValue::Boolean(boolean) => json!(if boolean { 1 } else { 0 }),
+            SortValue::DateTime(ref date_time) => json!(datetime_to_millis(date_time)),
+            SortValue::String(ref string) => json!(string),
+        }
+    }
+
+    /// Reads a value given back by a client, such as in "search_after"
+    ///
+    /// Dates and booleans come back as the numbers that `to_json` gave them as.
+    pub fn from_json(json: &Json) -> Option<SortValue> {
+        match *json {
+            Json::Number(ref number) => number.as_i64().map(SortValue::Integer).or_else(|| number.as_f64().map(SortValue::Float)),
+            Json::Bool(boolean) => Some(SortValue::Boolean(boolean)),
+            Json::String(ref string) => Some(SortValue::String(string.clone())),
+            _ => None,
+        }
+    }
+
+    /// The value as a number, if it has a numeric representation
+    fn as_number(&self) -> Option<f64> {
+        match *self {
+            SortValue::Integer(integer) => Some(integer as f64),
+            SortValue::Float(float) => Some(float),
+            SortValue::Boolean(boolean) => Some(if boolean { 1.0 } else { 0.0 }),
+            SortValue::DateTime(ref date_time) => Some(datetime_to_millis(date_time) as f64),
+            SortValue::String(_) => None,
+        }
+    }
+
+    /// Values of the same type are compared directly, values of different types are compared
+    /// as numbers if they can be or otherwise by type
+    fn compare(&self, other: &SortValue) -> Ordering {
+        match (self, other) {
+            (&SortValue::Integer(a), &SortValue::Integer(b)) => a.cmp(&b),
+            (&SortValue::Boolean(a), &SortValue::Boolean(b)) => a.cmp(&b),
+            (&SortValue::DateTime(ref a), &SortValue::DateTime(ref b)) => a.cmp(b),
+            (&SortValue::String(ref a), &SortValue::String(ref b)) => a.cmp(b),
+            (a, b) => {
+                match (a.as_number(), b.as_number()) {
+                    (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+                    _ => a.type_rank().cmp(&b.type_rank()),
+                }
+            }
+        }
+    }
+
+    fn type_rank(&self) -> u8 {
+        match *self {
+            SortValue::Integer(_) | SortValue::Float(_) => 0,
+            SortValue::Boolean(_) => 1,
+            SortValue::DateTime(_) => 2,
+            SortValue::String(_) => 3,
+        }
+    }
+}
+
+
+/// Picks the value to sort by from all of a field's values
+fn select_value(values: Vec<SortValue>, mode: SortMode) -> Option<SortValue> {
+    match mode {
+        SortMode::Min => values.into_iter().min_by(|a, b| a.compare(b)),
+        SortMode::Max => values.into_iter().max_by(|a, b| a.compare(b)),
+        SortMode::Avg => {
+            let numbers = values.iter().filter_map(|value| {
+                match *value {
+                    SortValue::Integer(integer) => Some(integer as f64),
+                    SortValue::Float(float) => Some(float),
+                    _ => None,
+                }
+            }).collect::<Vec<_>>();
+
+            if numbers.is_empty() {
+                None
+            } else {
+                Some(SortValue::Float(numbers.iter().fold(0.0, |sum, number| sum + number) / numbers.len() as f64))
+            }
+        }
+    }
+}
+
+
+/// What a hit is sorted by
+#[derive(Debug, Clone, PartialEq)]
+pub struct SortKey {
+    /// The value of each clause's field, None for clauses that sort by score or if the hit doesn't have a value
+    pub values: Vec<Option<SortValue>>,
+    pub score: f64,
+
+    /// Orders hits that are equal on every clause, see `tiebreaker`
+    ///
+    /// This is None for "search_after" cursors that were given without one.
+    pub tiebreaker: Option<u64>,
+}
+
+
+/// Makes the tiebreaker of a hit from the position of its index in the search and its document id
+///
+/// Document ids only use the lower 48 bits so the index goes in the upper 16.
+pub fn tiebreaker(index_ordinal: usize, doc_id: u64) -> u64 {
+    (index_ordinal as u64) << 48 | doc_id
+}
+
+
+/// A sort clause with the field it reads on one index
+#[derive(Debug)]
+struct IndexSortClause<'a> {
+    clause: &'a SortClause,
+    field_mapping: Option<&'a FieldMapping>,
+    field_ref: Option<FieldRef>,
+
+    /// The "missing" value converted to the field's type
+    missing_value: Option<SortValue>,
+}
+
+
+/// Reads the sort values of hits on one index
+#[derive(Debug)]
+pub struct IndexSort<'a> {
+    clauses: Vec<IndexSortClause<'a>>,
+    source_field: Option<FieldRef>,
+    index_ordinal: usize,
+}
+
+
+impl Sort {
+    /// Finds the fields to read sort values from in an index
+    ///
+    /// The index ordinal is the index's position in the search, which must be the same
+    /// on every page for the tiebreakers to match.
+    pub fn build<'a>(&'a self, index_metadata: &'a IndexMetadata, schema: &Schema, index_ordinal: usize) -> Result<IndexSort<'a>, SortBuildError> {
+        let mut clauses = Vec::with_capacity(self.clauses.len());
+
+        for clause in self.clauses.iter() {
+            let field_name = match clause.field {
+                SortField::Score => {
+                    clauses.push(IndexSortClause {
+                        clause: clause,
+                        field_mapping: None,
+                        field_ref: None,
+                        missing_value: None,
+                    });
+                    continue;
+                }
+                SortField::Field(ref field_name) => field_name,
+                SortField::GeoDistance { ref field, .. } => {
+                    let field_mapping = index_metadata.get_field_mapping(field);
+                    if let Some(field_mapping) = field_mapping {
+                        if field_mapping.data_type != FieldType::GeoPoint {
+                            return Err(SortBuildError::FieldNotGeoPoint(field.clone()));
+                        }
+                    }
+
+                    clauses.push(IndexSortClause {
+                        clause: clause,
+                        field_mapping: field_mapping,
+                        field_ref: schema.get_field_by_name(field),
+                        missing_value: None,
+                    });
+                    continue;
+                }
+            };
+
+            let field_mapping = index_metadata.get_field_mapping(field_name);
+            if let Some(field_mapping) = field_mapping {
+                if !field_mapping.has_doc_values && !field_mapping.is_stored {
+                    return Err(SortBuildError::FieldNotSortable(field_name.clone()));
+                }
+
+                if clause.mode == Some(SortMode::Avg) && field_mapping.data_type != FieldType::Integer {
+                    return Err(SortBuildError::FieldNotNumeric(field_name.clone()));
+                }
+            }
+
+            let missing_value = match clause.missing {
+                Missing::Value(ref value) => {
+                    match field_mapping.map(|field_mapping| field_mapping.process_value_for_store(value)) {
+                        Some(Ok(Some(value))) => Some(SortValue::from_field_value(value)),
+                        Some(_) => return Err(SortBuildError::InvalidMissingValue(field_name.clone())),
+                        None => None,
+                    }
+                }
+                _ => None,
+            };
+
+            clauses.push(IndexSortClause {
+                clause: clause,
+                field_mapping: field_mapping,
+                field_ref: schema.get_field_by_name(field_name),
+                missing_value: missing_value,
+            });
+        }
+
+        Ok(IndexSort {
+            clauses: clauses,
+            source_field: schema.get_field_by_name("_source"),
+            index_ordinal: index_ordinal,
+        })
+    }
+
+    /// Compares two hits, the one that should be returned first is the lesser
+    ///
+    /// Hits that are equal on every clause are ordered by their tiebreaker, so every hit
+    /// has a fixed place and pages that end between equal hits don't skip any.
+    pub fn compare(&self, a: &SortKey, b: &SortKey) -> Ordering {
+        match self.compare_clauses(a, b) {
+            Ordering::Equal => a.tiebreaker.cmp(&b.tiebreaker),
+            ordering => ordering,
+        }
+    }
+
+    /// Returns true if a hit would be returned strictly after the given sort key
+    ///
+    /// If the key has no tiebreaker, hits that are equal to it on every clause aren't after it.
+    pub fn is_after(&self, key: &SortKey, after: &SortKey) -> bool {
+        match (self.compare_clauses(key, after), key.tiebreaker, after.tiebreaker) {
+            (Ordering::Equal, Some(tiebreaker), Some(after_tiebreaker)) => tiebreaker > after_tiebreaker,
+            (ordering, _, _) => ordering == Ordering::Greater,
+        }
+    }
+
+    fn compare_clauses(&self, a: &SortKey, b: &SortKey) -> Ordering {
+        for (i, clause) in self.clauses.iter().enumerate() {
+            let ordering = match clause.field {
+                SortField::Score => a.score.partial_cmp(&b.score).unwrap_or(Ordering::Equal),
+                SortField::Field(_) | SortField::GeoDistance { .. } => {
+                    match (&a.values[i], &b.values[i]) {
+                        (&Some(ref a_value), &Some(ref b_value)) => a_value.compare(b_value),
+                        (&None, &None) => Ordering::Equal,
+
+                        // Missing values go first or last whatever the order is
+                        (&Some(_), &None) => return if clause.missing == Missing::First { Ordering::Greater } else { Ordering::Less },
+                        (&None, &Some(_)) => return if clause.missing == Missing::First { Ordering::Less } else { Ordering::Greater },
+                    }
+                }
+            };
+
+            let ordering = match clause.order {
+                SortOrder::Asc => ordering,
+                SortOrder::Desc => ordering.reverse(),
+            };
+
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+
+        Ordering::Equal
+    }
+
+    /// Parses the "search_after" section of a search request
+    ///
+    /// This has the "sort" values of the last hit the client saw, one for each clause
+    /// followed by its tiebreaker. The tiebreaker can be left out, but then any hits that
+    /// are equal to the last one on every clause are skipped.
+    pub fn parse_search_after(&self, json: &Json) -> Result<SortKey, SortParseError> {
+        let array = try!(json.as_array().ok_or(SortParseError::ExpectedArray));
+        if array.len() != self.clauses.len() && array.len() != self.clauses.len() + 1 {
+            return Err(SortParseError::SearchAfterLengthMismatch {
+                expected: self.clauses.len() + 1,
+                actual: array.len(),
+            });
+        }
+
+        let mut key = SortKey {
+            values: Vec::with_capacity(self.clauses.len()),
+            score: 0.0,
+            tiebreaker: None,
+        };
+
+        if let Some(value) = array.get(self.clauses.len()) {
+            key.tiebreaker = Some(try!(value.as_u64().ok_or_else(|| SortParseError::InvalidSearchAfterValue(value.clone()))));
+        }
+
+        for (clause, value) in self.clauses.iter().zip(array.iter()) {
+            match clause.field {
+                SortField::Score => {
+                    key.score = try!(value.as_f64().ok_or_else(|| SortParseError::InvalidSearchAfterValue(value.clone())));
+                    key.values.push(None);
+                }
+                SortField::Field(_) | SortField::GeoDistance { .. } => {
+                    if value.is_null() {
+                        key.values.push(None);
+                    } else {
+                        key.values.push(Some(try!(SortValue::from_json(value).ok_or_else(|| SortParseError::InvalidSearchAfterValue(value.clone())))));
+                    }
+                }
+            }
+        }
+
+        Ok(key)
+    }
+
+    /// The values to show in the "sort" section of a hit, with the tiebreaker last
+    pub fn to_hit_json(&self, key: &SortKey) -> Json {
+        let mut values = self.clauses.iter().zip(key.values.iter()).map(|(clause, value)| {
+            match clause.field {
+                SortField::Score => json!(key.score),
+                SortField::Field(_) | SortField::GeoDistance { .. } => value.as_ref().map(|value| value.to_json()).unwrap_or(Json::Null),
+            }
+        }).collect::<Vec<_>>();
+
+        if let Some(tiebreaker) = key.tiebreaker {
+            values.push(json!(tiebreaker));
+        }
+
+        Json::Array(values)
+    }
+}
+
+
+/// Finds a field's value in a document's source, following dots into inner objects
+fn get_source_value<'a>(source: &'a Json, field_name: &str) -> Option<&'a Json> {
+    let mut value = source;
+    for part in field_name.split('.') {
+        value = match value.as_object().and_then(|object| object.get(part)) {
+            Some(value) => value,
+            None => return None,
+        };
+    }
+
+    Some(value)
+}
+
+
+impl<'a> IndexSort<'a> {
+    fn read_source(&self, index_reader: &RocksDBIndexReader, doc_id: u64) -> Option<Json> {
+        match self.source_field.map(|source_field| index_reader.read_stored_field(source_field, DocRef::from_u64(doc_id))) {
+            Some(Ok(Some(FieldValue::String(source)))) => serde_json::from_str::<Json>(&source).ok(),
+            _ => None,
+        }
+    }
+
+    /// Reads all the values of a field in a document
+    ///
+    /// Doc values only keep one value for each document (arrays of strings are joined
+    /// together and arrays of numbers aren't kept at all), so all of the values have to be
+    /// read from the document's source. Parsing it is slow so this is only done if the
+    /// clause has a "mode" or the document has no doc value for the field. Otherwise,
+    /// arrays of strings are sorted by their joined value.
+    ///
+    /// The source is parsed at most once for each document, it's kept in `source` for
+    /// the other clauses.
+    fn read_values(&self, clause: &IndexSortClause, source: &mut Option<Option<Json>>, index_reader: &RocksDBIndexReader, doc_id: u64) -> Vec<SortValue> {
+        let read_doc_value = || {
+            match clause.field_ref.map(|field_ref| index_reader.read_stored_field(field_ref, DocRef::from_u64(doc_id))) {
+                Some(Ok(Some(value))) => Some(SortValue::from_field_value(value)),
+                _ => None,
+            }
+        };
+
+        if clause.clause.mode.is_none() {
+            if let Some(value) = read_doc_value() {
+                return vec![value];
+            }
+        }
+
+        if let (Some(field_mapping), &SortField::Field(ref field_name)) = (clause.field_mapping, &clause.clause.field) {
+            if source.is_none() {
+                *source = Some(self.read_source(index_reader, doc_id));
+            }
+
+            if let Some(Some(&Json::Array(ref items))) = source.as_ref().and_then(|source| source.as_ref()).map(|source| get_source_value(source, field_name)) {
+                return items.iter().filter_map(|item| {
+                    match field_mapping.process_value_for_store(item) {
+                        Ok(Some(value)) => Some(SortValue::from_field_value(value)),
+                        _ => None,
+                    }
+                }).collect();
+            }
+        }
+
+        if clause.clause.mode.is_some() {
+            read_doc_value().into_iter().collect()
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Reads the distances from each of the points in a document to the origin
+    ///
+    /// Points are always read from the doc value, which keeps all of them.
+    fn read_distances(&self, clause: &IndexSortClause, origin: &GeoPoint, unit: f64, index_reader: &RocksDBIndexReader, doc_id: u64) -> Vec<SortValue> {
+        match clause.field_ref.map(|field_ref| index_reader.read_stored_field(field_ref, DocRef::from_u64(doc_id))) {
+            Some(Ok(Some(FieldValue::String(points)))) => {
+                geo::parse_stored_points(&points).iter().map(|point| SortValue::Float(point.distance(origin) / unit)).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn read_key(&self, index_reader: &RocksDBIndexReader, doc_id: u64, score: f64) -> SortKey {
+        let mut source = None;
+
+        let values = self.clauses.iter().map(|clause| {
+            match clause.clause.field {
+                SortField::Score => None,
+                SortField::Field(_) => {
+                    let values = self.read_values(clause, &mut source, index_reader, doc_id);
+                    select_value(values, clause.clause.mode()).or_else(|| clause.missing_value.clone())
+                }
+                SortField::GeoDistance { ref origin, unit, .. } => {
+                    let values = self.read_distances(clause, origin, unit, index_reader, doc_id);
+                    select_value(values, clause.clause.mode()).or_else(|| clause.missing_value.clone())
+                }
+            }
+        }).collect();
+
+        SortKey {
+            values: values,
+            score: score,
+            tiebreaker: Some(tiebreaker(self.index_ordinal, doc_id)),
+        }
+    }
+}
+
+
+fn parse_order(json: &Json) -> Result<SortOrder, SortParseError> {
+    match try!(json.as_str().ok_or(SortParseError::ExpectedString)) {
+        "asc" => Ok(SortOrder::Asc),
+        "desc" => Ok(SortOrder::Desc),
+        order => Err(SortParseError::InvalidOrder(order.to_string())),
+    }
+}
+
+
+fn parse_mode(json: &Json) -> Result<SortMode, SortParseError> {
+    match try!(json.as_str().ok_or(SortParseError::ExpectedString)) {
+        "min" => Ok(SortMode::Min),
+        "max" => Ok(SortMode::Max),
+        "avg" => Ok(SortMode::Avg),
+        mode => Err(SortParseError::InvalidMode(mode.to_string())),
+    }
+}
+
+
+fn parse_missing(json: &Json) -> Result<Missing, SortParseError> {
+    match *json {
+        Json::String(ref string) if string == "_first" => Ok(Missing::First),
+        Json::String(ref string) if string == "_last" => Ok(Missing::Last),
+        Json::Null | Json::Array(_) | Json::Object(_) => Err(SortParseError::ExpectedMissingValue),
+        ref value => Ok(Missing::Value(value.clone())),
+    }
+}
+
+
+/// Reads a distance unit such as "km" as the number of metres in it
+fn parse_unit(json: &Json) -> Result<f64, SortParseError> {
+    let unit = try!(json.as_str().ok_or(SortParseError::ExpectedString));
+    if unit.is_empty() {
+        return Err(SortParseError::InvalidUnit(unit.to_string()));
+    }
+
+    parse_distance(&Json::String(format!("1{}", unit))).ok_or_else(|| SortParseError::InvalidUnit(unit.to_string()))
+}
+
+
+/// Parses the options of a "_geo_distance" clause
+///
+/// The point to measure from is keyed by the name of the field, the other keys are options.
+fn parse_geo_distance_clause(json: &Json) -> Result<SortClause, SortParseError> {
+    let object = try!(json.as_object().ok_or(SortParseError::ExpectedObject));
+
+    let mut field = None;
+    let mut order = SortOrder::Asc;
+    let mut mode = None;
+    let mut unit = 1.0;
+
+    for (key, value) in object.iter() {
+        match key.as_ref() {
+            "order" => order = try!(parse_order(value)),
+            "mode" => mode = Some(try!(parse_mode(value))),
+            "unit" => unit = try!(parse_unit(value)),
+            _ => {
+                if field.is_some() {
+                    return Err(SortParseError::UnrecognisedKey(key.clone()));
+                }
+
+                let origin = try!(GeoPoint::from_json(value).ok_or(SortParseError::InvalidGeoPoint));
+                field = Some((key.clone(), origin));
+            }
+        }
+    }
+
+    let (field, origin) = try!(field.ok_or(SortParseError::ExpectedSingleKey));
+
+    Ok(SortClause {
+        mode: mode,
+        ..SortClause::new(SortField::GeoDistance {
+            field: field,
+            origin: origin,
+            unit: unit,
+        }, order)
+    })
+}
+
+
+fn parse_sort_field(field_name: &str) -> SortClause {
+    // Scores are sorted highest first by default, everything else lowest first
+    if field_name == "_score" {
+        SortClause::new(SortField::Score, SortOrder::Desc)
+    } else {
+        SortClause::new(SortField::Field(field_name.to_string()), SortOrder::Asc)
+    }
+}
+
+
+fn parse_clause(json: &Json) -> Result<SortClause, SortParseError> {
+    match *json {
+        Json::String(ref field_name) => Ok(parse_sort_field(field_name)),
+        Json::Object(ref object) => {
+            if object.len() != 1 {
+                return Err(SortParseError::ExpectedSingleKey);
+            }
+
+            let (field_name, options) = object.iter().next().unwrap();
+            if field_name == "_geo_distance" {
+                return parse_geo_distance_clause(options);
+            }
+
+            let mut clause = parse_sort_field(field_name);
+
+            match *options {
+                Json::String(_) => {
+                    clause.order = try!(parse_order(options));
+                }
+                Json::Object(ref options) => {
+                    for (key, value) in options.iter() {
+                        match key.as_ref() {
+                            "order" => clause.order = try!(parse_order(value)),
+                            "mode" => clause.mode = Some(try!(parse_mode(value))),
+                            "missing" => clause.missing = try!(parse_missing(value)),
+                            _ => return Err(SortParseError::UnrecognisedKey(key.clone())),
+                        }
+                    }
+                }
+                _ => return Err(SortParseError::ExpectedObjectOrString),
+            }
+
+            Ok(clause)
+        }
+        _ => Err(SortParseError::ExpectedObjectOrString),
+    }
+}
+
+
+/// Parses the "sort" section of a search request
+///
+/// Each clause is a field name, `{"field": "desc"}` or `{"field": {"order": "desc", ...}}`.
+/// Many clauses can be given in an array.
+pub fn parse(json: &Json) -> Result<Sort, SortParseError> {
+    let clauses = match *json {
+        Json::Array(ref array) => {
+            let mut clauses = Vec::with_capacity(array.len());
+            for clause in array.iter() {
+                clauses.push(try!(parse_clause(clause)));
+            }
+            clauses
+        }
+        _ => vec![try!(parse_clause(json))],
+    };
+
+    if clauses.is_empty() {
+        return Ok(Sort::default());
+    }
+
+    Ok(Sort {
+        clauses: clauses,
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use serde_json;
+
+    use geo::GeoPoint;
+
+    use super::{parse, select_value, Sort, SortClause, SortField, SortOrder, SortMode, SortValue, SortKey, SortParseError, Missing};
+
+    fn key(values: &[Option<i64>], score: f64) -> SortKey {
+        SortKey {
+            values: values.iter().map(|value| value.map(SortValue::Integer)).collect(),
+            score: score,
+            tiebreaker: None,
+        }
+    }
+
+    fn key_with_tiebreaker(values: &[Option<i64>], tiebreaker: u64) -> SortKey {
+        SortKey {
+            tiebreaker: Some(tiebreaker),
+            ..key(values, 1.0)
+        }
+    }
+
+    fn field_sort(field_name: &str, order: SortOrder) -> Sort {
+        Sort {
+            clauses: vec![
+                SortClause::new(SortField::Field(field_name.to_string()), order),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_parse_field_name() {
+        assert_eq!(parse(&serde_json::from_str("\"price\"").unwrap()), Ok(field_sort("price", SortOrder::Asc)));
+        assert_eq!(parse(&serde_json::from_str("\"_score\"").unwrap()), Ok(Sort::default()));
+    }
+
+    #[test]
+    fn test_parse_order() {
+        assert_eq!(parse(&serde_json::from_str("[{\"price\": \"desc\"}]").unwrap()), Ok(field_sort("price", SortOrder::Desc)));
+        assert_eq!(parse(&serde_json::from_str("{\"price\": {\"order\": \"desc\"}}").unwrap()), Ok(field_sort("price", SortOrder::Desc)));
+    }
+
+    #[test]
+    fn test_parse_invalid_order() {
+        assert_eq!(parse(&serde_json::from_str("{\"price\": \"up\"}").unwrap()), Err(SortParseError::InvalidOrder("up".to_string())));
+    }
+
+    #[test]
+    fn test_parse_multiple_clauses() {
+        let sort = parse(&serde_json::from_str("
+        [
+            {\"price\": {\"order\": \"desc\", \"mode\": \"avg\", \"missing\": \"_first\"}},
+            {\"rating\": {\"missing\": 0}},
+            \"_score\"
+        ]
+        ").unwrap());
+
+        assert_eq!(sort, Ok(Sort {
+            clauses: vec![
+                SortClause {
+                    mode: Some(SortMode::Avg),
+                    missing: Missing::First,
+                    ..SortClause::new(SortField::Field("price".to_string()), SortOrder::Desc)
+                },
+                SortClause {
+                    missing: Missing::Value(json!(0)),
+                    ..SortClause::new(SortField::Field("rating".to_string()), SortOrder::Asc)
+                },
+                SortClause::new(SortField::Score, SortOrder::Desc),
+            ],
+        }));
+    }
+
+    #[test]
+    fn test_parse_geo_distance() {
+        let sort = parse(&serde_json::from_str("
+        {
+            \"_geo_distance\": {
+                \"location\": [-0.1278, 51.5074],
+                \"order\": \"desc\",
+                \"mode\": \"max\",
+                \"unit\": \"km\"
+            }
+        }
+        ").unwrap());
+
+        assert_eq!(sort, Ok(Sort {
+            clauses: vec![
+                SortClause {
+                    mode: Some(SortMode::Max),
+                    ..SortClause::new(SortField::GeoDistance {
+                        field: "location".to_string(),
+                        origin: GeoPoint::new(51.5074, -0.1278).unwrap(),
+                        unit: 1000.0,
+                    }, SortOrder::Desc)
+                },
+            ],
+        }));
+    }
+
+    #[test]
+    fn test_parse_geo_distance_invalid() {
+        assert_eq!(parse(&serde_json::from_str("{\"_geo_distance\": {\"location\": \"foo\"}}").unwrap()), Err(SortParseError::InvalidGeoPoint));
+        assert_eq!(parse(&serde_json::from_str("{\"_geo_distance\": {\"location\": \"51.5,-0.12\", \"unit\": \"parsecs\"}}").unwrap()), Err(SortParseError::InvalidUnit("parsecs".to_string())));
+        assert_eq!(parse(&serde_json::from_str("{\"_geo_distance\": {\"order\": \"asc\"}}").unwrap()), Err(SortParseError::ExpectedSingleKey));
+    }
+
+    #[test]
+    fn test_compare_field() {
+        let asc = field_sort("price", SortOrder::Asc);
+        let desc = field_sort("price", SortOrder::Desc);
+
+        assert_eq!(asc.compare(&key(&[Some(1)], 1.0), &key(&[Some(2)], 1.0)), Ordering::Less);
+        assert_eq!(desc.compare(&key(&[Some(1)], 1.0), &key(&[Some(2)], 1.0)), Ordering::Greater);
+
+        // Ties are broken by the tiebreaker
+        assert_eq!(asc.compare(&key_with_tiebreaker(&[Some(1)], 2), &key_with_tiebreaker(&[Some(1)], 1)), Ordering::Greater);
+        assert_eq!(desc.compare(&key_with_tiebreaker(&[Some(1)], 1), &key_with_tiebreaker(&[Some(1)], 2)), Ordering::Less);
+
+        // Missing values go last in both orders
+        assert_eq!(asc.compare(&key(&[None], 1.0), &key(&[Some(2)], 1.0)), Ordering::Greater);
+        assert_eq!(desc.compare(&key(&[None], 1.0), &key(&[Some(2)], 1.0)), Ordering::Greater);
+    }
+
+    #[test]
+    fn test_compare_missing_first() {
+        let mut sort = field_sort("price", SortOrder::Desc);
+        sort.clauses[0].missing = Missing::First;
+
+        assert_eq!(sort.compare(&key(&[None], 1.0), &key(&[Some(2)], 1.0)), Ordering::Less);
+    }
+
+    #[test]
+    fn test_compare_multiple_clauses() {
+        let sort = Sort {
+            clauses: vec![
+                SortClause::new(SortField::Field("category".to_string()), SortOrder::Asc),
+                SortClause::new(SortField::Field("price".to_string()), SortOrder::Desc),
+            ],
+        };
+
+        assert_eq!(sort.compare(&key(&[Some(1), Some(5)], 1.0), &key(&[Some(2), Some(10)], 1.0)), Ordering::Less);
+        assert_eq!(sort.compare(&key(&[Some(1), Some(5)], 1.0), &key(&[Some(1), Some(10)], 1.0)), Ordering::Greater);
+    }
+
+    #[test]
+    fn test_compare_score() {
+        assert_eq!(Sort::default().compare(&key(&[None], 2.0), &key(&[None], 1.0)), Ordering::Less);
+
+        let asc = Sort {
+            clauses: vec![
+                SortClause::new(SortField::Score, SortOrder::Asc),
+            ],
+        };
+        assert_eq!(asc.compare(&key(&[None], 2.0), &key(&[None], 1.0)), Ordering::Greater);
+    }
+
+    #[test]
+    fn test_search_after() {
+        let sort = parse(&serde_json::from_str("[{\"price\": \"desc\"}, \"_score\"]").unwrap()).unwrap();
+        let after = sort.parse_search_after(&json!([5, 1.5])).unwrap();
+
+        assert!(sort.is_after(&key(&[Some(4), None], 3.0), &after));
+        assert!(sort.is_after(&key(&[Some(5), None], 1.0), &after));
+        assert!(!sort.is_after(&key(&[Some(5), None], 1.5), &after));
+        assert!(!sort.is_after(&key(&[Some(6), None], 1.0), &after));
+    }
+
+    #[test]
+    fn test_search_after_tiebreaker() {
+        let sort = field_sort("price", SortOrder::Asc);
+        let after = sort.parse_search_after(&json!([5, 12])).unwrap();
+        assert_eq!(after, key_with_tiebreaker(&[Some(5)], 12));
+
+        // Hits equal to the last one are only skipped if they came before it
+        assert!(sort.is_after(&key_with_tiebreaker(&[Some(5)], 13), &after));
+        assert!(!sort.is_after(&key_with_tiebreaker(&[Some(5)], 12), &after));
+        assert!(!sort.is_after(&key_with_tiebreaker(&[Some(5)], 11), &after));
+        assert!(sort.is_after(&key_with_tiebreaker(&[Some(6)], 1), &after));
+
+        assert_eq!(sort.to_hit_json(&after), json!([5, 12]));
+    }
+
+    #[test]
+    fn test_search_after_invalid_tiebreaker() {
+        assert_eq!(Sort::default().parse_search_after(&json!([1.0, "foo"])), Err(SortParseError::InvalidSearchAfterValue(json!("foo"))));
+    }
+
+    #[test]
+    fn test_search_after_length_mismatch() {
+        assert_eq!(Sort::default().parse_search_after(&json!([1.0, 5, "foo"])), Err(SortParseError::SearchAfterLengthMismatch {
+            expected: 2,
+            actual: 3,
+        }));
+    }
+
+    #[test]
+    fn test_compare_date_with_millis() {
+        let date = "2017-03-01T12:00:00Z".parse().unwrap();
+
+        assert_eq!(SortValue::DateTime(date).compare(&SortValue::Integer(1488369600000)), Ordering::Equal);
+        assert_eq!(SortValue::DateTime(date).compare(&SortValue::Integer(1488369600001)), Ordering::Less);
+    }
+
+    #[test]
+    fn test_select_value() {
+        let values = vec![SortValue::Integer(3), SortValue::Integer(1), SortValue::Integer(8)];
+
+        assert_eq!(select_value(values.clone(), SortMode::Min), Some(SortValue::Integer(1)));
+        assert_eq!(select_value(values.clone(), SortMode::Max), Some(SortValue::Integer(8)));
+        assert_eq!(select_value(values, SortMode::Avg), Some(SortValue::Float(4.0)));
+        assert_eq!(select_value(vec![], SortMode::Min), None);
+    }
+}
@@ -0,0 +1,278 @@
+//! Parses "_source" filters and prunes documents' source with them
+//!
+//! Filters select fields by their full dotted path and may use "*" wildcards. Selecting an
+//! object selects everything inside it. Excludes are applied after includes.
+
+use serde_json;
+use serde_json::Value as Json;
+
+use template::matches_pattern;
+
+
+#[derive(Debug, PartialEq)]
+pub enum SourceFilterParseError {
+    ExpectedString,
+    ExpectedStringOrArray,
+    ExpectedBooleanStringArrayOrObject,
+    UnrecognisedKey(String),
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceFilter {
+    /// False if the source shouldn't be returned at all
+    pub enabled: bool,
+
+    /// Patterns of the fields to return, all fields are returned if this is empty
+    pub includes: Vec<String>,
+
+    /// Patterns of the fields to leave out
+    pub excludes: Vec<String>,
+}
+
+
+impl Default for SourceFilter {
+    fn default() -> SourceFilter {
+        SourceFilter {
+            enabled: true,
+            includes: Vec::new(),
+            excludes: Vec::new(),
+        }
+    }
+}
+
+
+impl SourceFilter {
+    pub fn disabled() -> SourceFilter {
+        SourceFilter {
+            enabled: false,
+            ..SourceFilter::default()
+        }
+    }
+
+    /// Removes the fields from a document's source that the filter doesn't select
+    pub fn apply(&self, source: Json) -> Json {
+        if self.includes.is_empty() && self.excludes.is_empty() {
+            return source;
+        }
+
+        match self.filter_value(source, "", self.includes.is_empty()) {
+            Some(source) => source,
+            None => Json::Object(serde_json::Map::new()),
+        }
+    }
+
+    /// Filters a value found at the given path
+    ///
+    /// `included` is true if the path (or one of its parents) matched an include pattern.
+    /// Returns None if nothing in the value was selected.
+    fn filter_value(&self, value: Json, path: &str, included: bool) -> Option<Json> {
+        match value {
+            Json::Object(object) => {
+                let mut filtered = serde_json::Map::new();
+                for (key, child) in object {
+                    let child_path = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+
+                    if self.excludes.iter().any(|pattern| matches_pattern(pattern, &child_path)) {
+                        continue;
+                    }
+
+                    let child_included = included || self.includes.iter().any(|pattern| matches_pattern(pattern, &child_path));
+                    if let Some(child) = self.filter_value(child, &child_path, child_included) {
+                        filtered.insert(key, child);
+                    }
+                }
+
+                // Objects that were emptied by the filter are dropped, unless they were selected
+                if filtered.is_empty() && !included {
+                    None
+                } else {
+                    Some(Json::Object(filtered))
+                }
+            }
+            Json::Array(array) => {
+                // Items of arrays have the same path as the array itself
+                let filtered = array.into_iter()
+                    .filter_map(|item| self.filter_value(item, path, included))
+                    .collect::<Vec<_>>();
+
+                if filtered.is_empty() && !included {
+                    None
+                } else {
+                    Some(Json::Array(filtered))
+                }
+            }
+            value => if included { Some(value) } else { None },
+        }
+    }
+}
+
+
+fn parse_patterns(json: &Json) -> Result<Vec<String>, SourceFilterParseError> {
+    match *json {
+        Json::String(ref pattern) => Ok(vec![pattern.clone()]),
+        Json::Array(ref array) => {
+            let mut patterns = Vec::with_capacity(array.len());
+            for pattern in array.iter() {
+                patterns.push(try!(pattern.as_str().ok_or(SourceFilterParseError::ExpectedString)).to_string());
+            }
+            Ok(patterns)
+        }
+        _ => Err(SourceFilterParseError::ExpectedStringOrArray),
+    }
+}
+
+
+/// Parses the "_source" section of a request
+///
+/// This may be a boolean, a pattern, an array of patterns or an object with "includes"
+/// and "excludes".
+pub fn parse(json: &Json) -> Result<SourceFilter, SourceFilterParseError> {
+    match *json {
+        Json::Bool(true) => Ok(SourceFilter::default()),
+        Json::Bool(false) => Ok(SourceFilter::disabled()),
+        Json::String(_) | Json::Array(_) => {
+            Ok(SourceFilter {
+                includes: try!(parse_patterns(json)),
+                ..SourceFilter::default()
+            })
+        }
+        Json::Object(ref object) => {
+            let mut filter = SourceFilter::default();
+
+            for (key, value) in object.iter() {
+                match key.as_ref() {
+                    "includes" | "include" => filter.includes = try!(parse_patterns(value)),
+                    "excludes" | "exclude" => filter.excludes = try!(parse_patterns(value)),
+                    _ => return Err(SourceFilterParseError::UnrecognisedKey(key.clone())),
+                }
+            }
+
+            Ok(filter)
+        }
+        _ => Err(SourceFilterParseError::ExpectedBooleanStringArrayOrObject),
+    }
+}
+
+
+/// Applies one of the "_source", "_source_includes" or "_source_excludes" URL parameters to a filter
+///
+/// Returns false if the key isn't one of these.
+pub fn parse_url_parameter(filter: &mut SourceFilter, key: &str, value: &str) -> bool {
+    let patterns = || value.split(',').map(|pattern| pattern.trim().to_string()).filter(|pattern| !pattern.is_empty()).collect::<Vec<_>>();
+
+    match key {
+        "_source" => {
+            match value {
+                "true" => filter.enabled = true,
+                "false" => filter.enabled = false,
+                _ => {
+                    filter.enabled = true;
+                    filter.includes = patterns();
+                }
+            }
+        }
+        "_source_includes" | "_source_include" => filter.includes = patterns(),
+        "_source_excludes" | "_source_exclude" => filter.excludes = patterns(),
+        _ => return false,
+    }
+
+    true
+}
+
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+
+    use super::{parse, parse_url_parameter, SourceFilter, SourceFilterParseError};
+
+    fn source() -> serde_json::Value {
+        json!({
+            "title": "Hello",
+            "author": {
+                "name": "Karl",
+                "email": "karl@example.com",
+            },
+            "comments": [
+                {"user": "anna", "body": "Nice"},
+                {"user": "ben", "body": "Great"},
+            ],
+        })
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse(&json!(false)), Ok(SourceFilter::disabled()));
+        assert_eq!(parse(&json!("title")), Ok(SourceFilter {
+            includes: vec!["title".to_string()],
+            ..SourceFilter::default()
+        }));
+        assert_eq!(parse(&json!({"includes": ["author.*"], "excludes": "author.email"})), Ok(SourceFilter {
+            includes: vec!["author.*".to_string()],
+            excludes: vec!["author.email".to_string()],
+            ..SourceFilter::default()
+        }));
+        assert_eq!(parse(&json!({"fields": "title"})), Err(SourceFilterParseError::UnrecognisedKey("fields".to_string())));
+    }
+
+    #[test]
+    fn test_parse_url_parameter() {
+        let mut filter = SourceFilter::default();
+
+        assert!(parse_url_parameter(&mut filter, "_source", "title,author.*"));
+        assert!(parse_url_parameter(&mut filter, "_source_excludes", "author.email"));
+        assert!(!parse_url_parameter(&mut filter, "size", "10"));
+
+        assert_eq!(filter, SourceFilter {
+            includes: vec!["title".to_string(), "author.*".to_string()],
+            excludes: vec!["author.email".to_string()],
+            ..SourceFilter::default()
+        });
+    }
+
+    #[test]
+    fn test_apply_no_filter() {
+        assert_eq!(SourceFilter::default().apply(source()), source());
+    }
+
+    #[test]
+    fn test_apply_includes_object() {
+        let filter = parse(&json!(["title", "author"])).unwrap();
+
+        assert_eq!(filter.apply(source()), json!({
+            "title": "Hello",
+            "author": {
+                "name": "Karl",
+                "email": "karl@example.com",
+            },
+        }));
+    }
+
+    #[test]
+    fn test_apply_wildcards_and_excludes() {
+        let filter = parse(&json!({"includes": ["author.*", "comments.user"], "excludes": ["*.email"]})).unwrap();
+
+        assert_eq!(filter.apply(source()), json!({
+            "author": {
+                "name": "Karl",
+            },
+            "comments": [
+                {"user": "anna"},
+                {"user": "ben"},
+            ],
+        }));
+    }
+
+    #[test]
+    fn test_apply_excludes_only() {
+        let filter = parse(&json!({"excludes": ["comments", "author.name"]})).unwrap();
+
+        assert_eq!(filter.apply(source()), json!({
+            "title": "Hello",
+            "author": {
+                "email": "karl@example.com",
+            },
+        }));
+    }
+}
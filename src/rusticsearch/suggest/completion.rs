@@ -0,0 +1,256 @@
+//! The completion suggester, which finds the inputs of a "completion" field that start with
+//! the suggest text
+//!
+//! Inputs are looked up in the field's completion index rather than the index store, so
+//! this is fast enough to run on every key press of a search box.
+
+use std::collections::HashSet;
+
+use serde_json::Value as Json;
+
+use mapping::FieldMapping;
+use completion::{CompletionIndex, CompletionMatch, ContextQuery, analyse_key};
+
+use super::{SuggestParseError, parse_positive_integer};
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompletionSuggester {
+    pub field: String,
+    pub size: usize,
+
+    /// Only return one completion for each text
+    pub skip_duplicates: bool,
+    pub contexts: Vec<ContextQuery>,
+}
+
+
+impl CompletionSuggester {
+    pub fn new(field: String) -> CompletionSuggester {
+        CompletionSuggester {
+            field: field,
+            size: 5,
+            skip_duplicates: false,
+            contexts: Vec::new(),
+        }
+    }
+
+    fn remove_duplicates<T>(&self, completions: Vec<(T, CompletionMatch)>) -> Vec<(T, CompletionMatch)> {
+        if !self.skip_duplicates {
+            return completions;
+        }
+
+        let mut seen_texts = HashSet::new();
+        completions.into_iter().filter(|&(_, ref completion)| seen_texts.insert(completion.text.clone())).collect()
+    }
+
+    /// Finds the top completions of the text in a field's completion index
+    ///
+    /// The text is analysed with the field's search analyzer, so it must be a prefix of an
+    /// analysed input.
+    pub fn find_completions(&self, text: &str, field_mapping: &FieldMapping, completion_index: &CompletionIndex) -> Vec<CompletionMatch> {
+        let prefix = analyse_key(text, field_mapping.search_analyzer(), field_mapping.max_input_length);
+
+        // Duplicates are removed after the search, so keep asking for more until there are
+        // enough without them or the index runs out
+        let mut limit = self.size;
+        loop {
+            let completions = completion_index.suggest(&prefix, limit, &self.contexts);
+            let exhausted = completions.len() < limit;
+
+            let mut completions = self.remove_duplicates(completions.into_iter().map(|completion| ((), completion)).collect());
+            if exhausted || completions.len() >= self.size {
+                completions.truncate(self.size);
+                return completions.into_iter().map(|(_, completion)| completion).collect();
+            }
+
+            limit *= 2;
+        }
+    }
+
+    /// Merges the completions found on each index, keeping the top ones
+    pub fn merge<T>(&self, mut completions: Vec<(T, CompletionMatch)>) -> Vec<(T, CompletionMatch)> {
+        completions.sort_by(|&(_, ref a), &(_, ref b)| (b.score, &a.text).partial_cmp(&(a.score, &b.text)).unwrap());
+
+        let mut completions = self.remove_duplicates(completions);
+        completions.truncate(self.size);
+        completions
+    }
+
+    pub fn to_json(&self, text: &str, options: Vec<Json>) -> Json {
+        json!([
+            {
+                "text": text,
+                "offset": 0,
+                "length": text.chars().count(),
+                "options": options,
+            }
+        ])
+    }
+}
+
+
+/// Parses the contexts to filter and boost completions by
+///
+/// Each context can be given a value, an array of values or objects with a "context" and
+/// a "boost".
+fn parse_contexts(json: &Json) -> Result<Vec<ContextQuery>, SuggestParseError> {
+    let object = try!(json.as_object().ok_or(SuggestParseError::ExpectedObject));
+
+    let mut contexts = Vec::new();
+    for (name, values_json) in object.iter() {
+        let values_json = match *values_json {
+            Json::Array(ref array) => array.iter().collect::<Vec<_>>(),
+            ref value => vec![value],
+        };
+
+        let mut values = Vec::new();
+        for value_json in values_json {
+            match *value_json {
+                Json::String(ref value) => values.push((value.clone(), 1.0)),
+                Json::Object(ref value_object) => {
+                    let mut value = None;
+                    let mut boost = 1.0;
+                    for (key, setting) in value_object.iter() {
+                        match key.as_ref() {
+                            "context" => value = Some(try!(setting.as_str().ok_or(SuggestParseError::ExpectedString)).to_string()),
+                            "boost" => boost = try!(setting.as_f64().ok_or(SuggestParseError::ExpectedNumber)),
+                            _ => return Err(SuggestParseError::UnrecognisedKey(key.clone())),
+                        }
+                    }
+
+                    values.push((try!(value.ok_or(SuggestParseError::MissingContext)), boost));
+                }
+                _ => return Err(SuggestParseError::ExpectedString),
+            }
+        }
+
+        contexts.push(ContextQuery {
+            name: name.clone(),
+            values: values,
+        });
+    }
+
+    Ok(contexts)
+}
+
+
+pub fn parse(json: &Json) -> Result<CompletionSuggester, SuggestParseError> {
+    let object = try!(json.as_object().ok_or(SuggestParseError::ExpectedObject));
+    let field = try!(try!(object.get("field").ok_or(SuggestParseError::MissingField)).as_str().ok_or(SuggestParseError::ExpectedString));
+    let mut suggester = CompletionSuggester::new(field.to_string());
+
+    for (key, value) in object.iter() {
+        match key.as_ref() {
+            "field" => {}
+            "size" => suggester.size = try!(parse_positive_integer(value)) as usize,
+            "skip_duplicates" => suggester.skip_duplicates = try!(value.as_bool().ok_or(SuggestParseError::ExpectedBoolean)),
+            "contexts" => suggester.contexts = try!(parse_contexts(value)),
+            _ => return Err(SuggestParseError::UnrecognisedKey(key.clone())),
+        }
+    }
+
+    Ok(suggester)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use mapping::{FieldMapping, FieldType};
+    use completion::{CompletionIndex, CompletionInput, CompletionMatch, ContextQuery};
+    use suggest::SuggestParseError;
+
+    use super::{parse, CompletionSuggester};
+
+    fn insert(completion_index: &mut CompletionIndex, doc_key: &str, input: &str, weight: u64) {
+        completion_index.insert(doc_key, &input.to_lowercase(), CompletionInput {
+            input: input.to_string(),
+            weight: weight,
+            contexts: HashMap::new(),
+        });
+    }
+
+    fn completion(doc_key: &str, text: &str, score: f64) -> CompletionMatch {
+        CompletionMatch {
+            doc_key: doc_key.to_string(),
+            text: text.to_string(),
+            score: score,
+        }
+    }
+
+    #[test]
+    fn test_find_completions() {
+        let field_mapping = FieldMapping {
+            data_type: FieldType::Completion,
+            ..FieldMapping::default()
+        };
+
+        let mut completion_index = CompletionIndex::new();
+        insert(&mut completion_index, "1", "Nirvana", 10);
+        insert(&mut completion_index, "2", "Nirvana", 5);
+        insert(&mut completion_index, "3", "Nine Inch Nails", 2);
+
+        let mut suggester = CompletionSuggester::new("suggest".to_string());
+        suggester.size = 2;
+        assert_eq!(suggester.find_completions("ni", &field_mapping, &completion_index), vec![
+            completion("1", "Nirvana", 10.0),
+            completion("2", "Nirvana", 5.0),
+        ]);
+
+        suggester.skip_duplicates = true;
+        assert_eq!(suggester.find_completions("ni", &field_mapping, &completion_index), vec![
+            completion("1", "Nirvana", 10.0),
+            completion("3", "Nine Inch Nails", 2.0),
+        ]);
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut suggester = CompletionSuggester::new("suggest".to_string());
+        suggester.size = 2;
+        suggester.skip_duplicates = true;
+
+        let merged = suggester.merge(vec![
+            ("a", completion("1", "Nirvana", 3.0)),
+            ("b", completion("1", "Nirvana", 5.0)),
+            ("b", completion("2", "Nickelback", 1.0)),
+        ]);
+        assert_eq!(merged, vec![
+            ("b", completion("1", "Nirvana", 5.0)),
+            ("b", completion("2", "Nickelback", 1.0)),
+        ]);
+    }
+
+    #[test]
+    fn test_parse() {
+        let suggester = parse(&json!({
+            "field": "suggest",
+            "size": 10,
+            "skip_duplicates": true,
+            "contexts": {
+                "genre": ["rock", {"context": "pop", "boost": 2}],
+            },
+        }));
+
+        assert_eq!(suggester, Ok(CompletionSuggester {
+            size: 10,
+            skip_duplicates: true,
+            contexts: vec![
+                ContextQuery {
+                    name: "genre".to_string(),
+                    values: vec![("rock".to_string(), 1.0), ("pop".to_string(), 2.0)],
+                },
+            ],
+            ..CompletionSuggester::new("suggest".to_string())
+        }));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(parse(&json!({})), Err(SuggestParseError::MissingField));
+        assert_eq!(parse(&json!({"field": "suggest", "contexts": {"genre": [{"boost": 2}]}})), Err(SuggestParseError::MissingContext));
+        assert_eq!(parse(&json!({"field": "suggest", "fuzzy": true})), Err(SuggestParseError::UnrecognisedKey("fuzzy".to_string())));
+    }
+}
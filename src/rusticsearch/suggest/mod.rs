@@ -0,0 +1,476 @@
+//! Parses the "suggest" section of a search request and runs the suggesters in it
+//!
+//! The term suggester proposes corrections for each word of the suggest text and the
+//! phrase suggester corrects the text as a whole, using how often words appear next to
+//! each other to pick the most likely phrase. The completion suggester finds the inputs of
+//! a "completion" field that start with the text.
+//!
+//! The index store doesn't expose its term dictionary, so the terms of a field are
+//! collected by analysing the field's values in each document's source. Each index keeps
+//! the dictionaries it has built until one of its documents or mappings changes.
+
+pub mod term;
+pub mod phrase;
+pub mod completion;
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use serde_json;
+use serde_json::Value as Json;
+use kite::Query;
+use kite::document::{DocRef, FieldValue};
+
+use analysis::AnalyzerSpec;
+use collectors::AllDocumentsCollector;
+use highlight::{get_source_values, split_words};
+use index::Index;
+use index::metadata::IndexMetadata;
+
+use self::term::{TermSuggester, parse as parse_term_suggester};
+use self::phrase::{PhraseSuggester, parse as parse_phrase_suggester};
+use self::completion::{CompletionSuggester, parse as parse_completion_suggester};
+
+
+#[derive(Debug, PartialEq)]
+pub enum SuggestParseError {
+    ExpectedObject,
+    ExpectedString,
+    ExpectedBoolean,
+    ExpectedPositiveInteger,
+    ExpectedNumber,
+    ExpectedSingleSuggester,
+    ExpectedSingleGenerator,
+    UnrecognisedKey(String),
+    InvalidSuggestMode(String),
+    InvalidSort(String),
+    InvalidMaxEdits,
+    InvalidGramSize,
+    MissingField,
+    MissingText,
+    MissingContext,
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Suggester {
+    Term(TermSuggester),
+    Phrase(PhraseSuggester),
+    Completion(CompletionSuggester),
+}
+
+
+impl Suggester {
+    pub fn field(&self) -> &str {
+        match *self {
+            Suggester::Term(ref suggester) => &suggester.field,
+            Suggester::Phrase(ref suggester) => &suggester.field,
+            Suggester::Completion(ref suggester) => &suggester.field,
+        }
+    }
+}
+
+
+/// A named suggestion in the "suggest" section
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suggestion {
+    pub name: String,
+    pub text: String,
+    pub suggester: Suggester,
+}
+
+
+/// The terms of a field, with statistics about how often they're used
+#[derive(Debug, Default)]
+pub struct TermDictionary {
+    /// The number of documents that contain each term
+    terms: HashMap<String, u64>,
+
+    /// The number of times each term appears
+    term_counts: HashMap<String, u64>,
+
+    /// The number of times each pair of terms appears next to each other
+    bigram_counts: HashMap<(String, String), u64>,
+    total_terms: u64,
+}
+
+
+impl TermDictionary {
+    pub fn new() -> TermDictionary {
+        TermDictionary::default()
+    }
+
+    pub fn doc_freq(&self, term: &str) -> u64 {
+        self.terms.get(term).cloned().unwrap_or(0)
+    }
+
+    pub fn term_count(&self, term: &str) -> u64 {
+        self.term_counts.get(term).cloned().unwrap_or(0)
+    }
+
+    pub fn bigram_count(&self, first: &str, second: &str) -> u64 {
+        self.bigram_counts.get(&(first.to_string(), second.to_string())).cloned().unwrap_or(0)
+    }
+
+    pub fn total_terms(&self) -> u64 {
+        self.total_terms
+    }
+
+    pub fn num_unique_terms(&self) -> u64 {
+        self.terms.len() as u64
+    }
+
+    pub fn terms(&self) -> &HashMap<String, u64> {
+        &self.terms
+    }
+
+    /// Adds the terms of a field from a document's source
+    pub fn add_document(&mut self, source: &Json, field_name: &str, analyzer: Option<&AnalyzerSpec>) {
+        let mut document_terms = HashSet::new();
+
+        for value in get_source_values(source, field_name) {
+            let terms = match analyzer {
+                Some(analyzer) => {
+                    analyzer.initialise(value)
+                        .filter_map(|token| String::from_utf8(token.term.as_bytes().to_vec()).ok())
+                        .collect::<Vec<_>>()
+                }
+                None => vec![value.to_string()],
+            };
+
+            // Pairs don't cross from one value of the field to the next
+            for pair in terms.windows(2) {
+                *self.bigram_counts.entry((pair[0].clone(), pair[1].clone())).or_insert(0) += 1;
+            }
+
+            for term in terms {
+                *self.term_counts.entry(term.clone()).or_insert(0) += 1;
+                self.total_terms += 1;
+                document_terms.insert(term);
+            }
+        }
+
+        for term in document_terms {
+            *self.terms.entry(term).or_insert(0) += 1;
+        }
+    }
+
+    /// Adds the terms of another dictionary, for suggestions across multiple indices
+    pub fn merge(&mut self, other: &TermDictionary) {
+        for (term, doc_freq) in other.terms.iter() {
+            *self.terms.entry(term.clone()).or_insert(0) += *doc_freq;
+        }
+
+        for (term, count) in other.term_counts.iter() {
+            *self.term_counts.entry(term.clone()).or_insert(0) += *count;
+        }
+
+        for (bigram, count) in other.bigram_counts.iter() {
+            *self.bigram_counts.entry(bigram.clone()).or_insert(0) += *count;
+        }
+
+        self.total_terms += other.total_terms;
+    }
+}
+
+
+/// Combines the term dictionaries of a field on each index into one
+pub fn merge_term_dictionaries(mut term_dictionaries: Vec<Arc<TermDictionary>>) -> Arc<TermDictionary> {
+    // Most searches are on a single index so there's nothing to copy
+    if term_dictionaries.len() == 1 {
+        return term_dictionaries.remove(0);
+    }
+
+    let mut merged = TermDictionary::new();
+    for term_dictionary in term_dictionaries.iter() {
+        merged.merge(term_dictionary);
+    }
+
+    Arc::new(merged)
+}
+
+
+/// The term dictionaries that have been built for an index, by field name
+#[derive(Debug, Default)]
+pub struct TermDictionaryCache {
+    /// Incremented whenever the cache is cleared, so dictionaries that were being built
+    /// at the time aren't added afterwards
+    generation: u64,
+    term_dictionaries: HashMap<String, Arc<TermDictionary>>,
+}
+
+
+impl Index {
+    /// Returns the term dictionary of a field, building it from the documents' source
+    /// if it isn't cached
+    pub fn term_dictionary(&self, index_metadata: &IndexMetadata, field_name: &str) -> Result<Arc<TermDictionary>, String> {
+        let generation = {
+            let cache = self.term_dictionaries.read().unwrap();
+            if let Some(term_dictionary) = cache.term_dictionaries.get(field_name) {
+                return Ok(term_dictionary.clone());
+            }
+
+            cache.generation
+        };
+
+        // The reader must be opened after the generation is read, so any write that it
+        // doesn't see clears the cache afterwards
+        let store = try!(self.store());
+        let index_reader = store.reader();
+
+        let mut term_dictionary = TermDictionary::new();
+        let analyzer = index_metadata.get_field_mapping(field_name).and_then(|field_mapping| field_mapping.index_analyzer());
+
+        if let Some(source_field) = index_reader.schema().get_field_by_name("_source") {
+            let mut collector = AllDocumentsCollector::new().no_score();
+            try!(index_reader.search(&mut collector, &Query::new_all()).map_err(|_| "failed to read documents".to_string()));
+
+            for (doc_id, _) in collector.into_vec() {
+                let source = match index_reader.read_stored_field(source_field, DocRef::from_u64(doc_id)) {
+                    Ok(Some(FieldValue::String(source))) => source,
+                    _ => continue,
+                };
+
+                if let Ok(source) = serde_json::from_str::<Json>(&source) {
+                    term_dictionary.add_document(&source, field_name, analyzer);
+                }
+            }
+        }
+
+        let term_dictionary = Arc::new(term_dictionary);
+
+        let mut cache = self.term_dictionaries.write().unwrap();
+        if cache.generation == generation {
+            cache.term_dictionaries.insert(field_name.to_string(), term_dictionary.clone());
+        }
+
+        Ok(term_dictionary)
+    }
+
+    /// Discards the cached term dictionaries
+    ///
+    /// This must be called whenever a document is indexed or deleted, after the store has
+    /// been changed, and whenever the mappings change.
+    pub fn clear_term_dictionaries(&self) {
+        let mut cache = self.term_dictionaries.write().unwrap();
+        cache.generation += 1;
+        cache.term_dictionaries.clear();
+    }
+}
+
+
+/// Splits suggest text into words and analyses each of them
+///
+/// Returns the byte offset, original text and analysed term of each word. Words that are
+/// removed by the analyzer (such as stop words) are left out.
+pub fn analyse_words<'a>(text: &'a str, analyzer: Option<&AnalyzerSpec>) -> Vec<(usize, &'a str, String)> {
+    let analyzer = match analyzer {
+        Some(analyzer) => analyzer,
+        None => {
+            // Fields that aren't analyzed are looked up as a whole
+            if text.is_empty() {
+                return Vec::new();
+            }
+
+            return vec![(0, text, text.to_string())];
+        }
+    };
+
+    let mut words = Vec::new();
+    for (start, end) in split_words(text) {
+        let word = &text[start..end];
+        if !word.chars().any(|c| c.is_alphanumeric()) {
+            continue;
+        }
+
+        if let Some(token) = analyzer.initialise(word).next() {
+            if let Ok(term) = String::from_utf8(token.term.as_bytes().to_vec()) {
+                words.push((start, word, term));
+            }
+        }
+    }
+
+    words
+}
+
+
+fn parse_positive_integer(json: &Json) -> Result<u64, SuggestParseError> {
+    json.as_u64().ok_or(SuggestParseError::ExpectedPositiveInteger)
+}
+
+
+/// Parses the "suggest" section of a search request
+///
+/// A "text" key applies to every suggestion that doesn't have its own. Completion
+/// suggestions may give their text as a "prefix" instead.
+pub fn parse(json: &Json) -> Result<Vec<Suggestion>, SuggestParseError> {
+    let object = try!(json.as_object().ok_or(SuggestParseError::ExpectedObject));
+
+    let global_text = match object.get("text") {
+        Some(text) => Some(try!(text.as_str().ok_or(SuggestParseError::ExpectedString))),
+        None => None,
+    };
+
+    let mut suggestions = Vec::new();
+    for (name, suggestion_json) in object.iter() {
+        if name == "text" {
+            continue;
+        }
+
+        let suggestion_object = try!(suggestion_json.as_object().ok_or(SuggestParseError::ExpectedObject));
+
+        let mut text = global_text;
+        let mut suggester = None;
+        for (key, value) in suggestion_object.iter() {
+            match key.as_ref() {
+                "text" | "prefix" => text = Some(try!(value.as_str().ok_or(SuggestParseError::ExpectedString))),
+                "term" | "phrase" | "completion" => {
+                    if suggester.is_some() {
+                        return Err(SuggestParseError::ExpectedSingleSuggester);
+                    }
+
+                    suggester = Some(match key.as_ref() {
+                        "term" => Suggester::Term(try!(parse_term_suggester(value))),
+                        "phrase" => Suggester::Phrase(try!(parse_phrase_suggester(value))),
+                        _ => Suggester::Completion(try!(parse_completion_suggester(value))),
+                    });
+                }
+                _ => return Err(SuggestParseError::UnrecognisedKey(key.clone())),
+            }
+        }
+
+        suggestions.push(Suggestion {
+            name: name.clone(),
+            text: try!(text.ok_or(SuggestParseError::MissingText)).to_string(),
+            suggester: try!(suggester.ok_or(SuggestParseError::ExpectedSingleSuggester)),
+        });
+    }
+
+    Ok(suggestions)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use analysis::AnalyzerSpec;
+    use analysis::tokenizers::TokenizerSpec;
+    use analysis::filters::FilterSpec;
+
+    use super::{parse, analyse_words, merge_term_dictionaries, Suggestion, Suggester, TermDictionary, SuggestParseError};
+    use super::term::{TermSuggester, SuggestMode, SuggestSort};
+    use super::completion::CompletionSuggester;
+
+    pub fn analyzer() -> AnalyzerSpec {
+        AnalyzerSpec {
+            char_filters: vec![],
+            tokenizer: TokenizerSpec::Standard,
+            filters: vec![
+                FilterSpec::Lowercase,
+            ],
+        }
+    }
+
+    #[test]
+    fn test_add_document() {
+        let mut term_dictionary = TermDictionary::new();
+        term_dictionary.add_document(&json!({"title": "The quick brown fox"}), "title", Some(&analyzer()));
+        term_dictionary.add_document(&json!({"title": ["Quick quick", "fox"]}), "title", Some(&analyzer()));
+
+        assert_eq!(term_dictionary.doc_freq("quick"), 2);
+        assert_eq!(term_dictionary.doc_freq("slow"), 0);
+        assert_eq!(term_dictionary.term_count("quick"), 3);
+        assert_eq!(term_dictionary.bigram_count("quick", "brown"), 1);
+        assert_eq!(term_dictionary.bigram_count("quick", "quick"), 1);
+        assert_eq!(term_dictionary.bigram_count("quick", "fox"), 0);
+        assert_eq!(term_dictionary.total_terms(), 7);
+        assert_eq!(term_dictionary.num_unique_terms(), 4);
+    }
+
+    #[test]
+    fn test_merge_term_dictionaries() {
+        let mut first = TermDictionary::new();
+        first.add_document(&json!({"title": "quick brown fox"}), "title", Some(&analyzer()));
+
+        let mut second = TermDictionary::new();
+        second.add_document(&json!({"title": "quick brown dog"}), "title", Some(&analyzer()));
+
+        let term_dictionary = merge_term_dictionaries(vec![Arc::new(first), Arc::new(second)]);
+
+        assert_eq!(term_dictionary.doc_freq("quick"), 2);
+        assert_eq!(term_dictionary.doc_freq("fox"), 1);
+        assert_eq!(term_dictionary.term_count("brown"), 2);
+        assert_eq!(term_dictionary.bigram_count("quick", "brown"), 2);
+        assert_eq!(term_dictionary.bigram_count("brown", "dog"), 1);
+        assert_eq!(term_dictionary.total_terms(), 6);
+        assert_eq!(term_dictionary.num_unique_terms(), 4);
+    }
+
+    #[test]
+    fn test_analyse_words() {
+        assert_eq!(analyse_words("Noble, prize!", Some(&analyzer())), vec![
+            (0, "Noble", "noble".to_string()),
+            (7, "prize", "prize".to_string()),
+        ]);
+        assert_eq!(analyse_words("Noble prize", None), vec![
+            (0, "Noble prize", "Noble prize".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_parse() {
+        let suggestions = parse(&json!({
+            "text": "quikc",
+            "my-suggestion": {
+                "term": {
+                    "field": "title",
+                    "size": 3,
+                    "sort": "frequency",
+                    "suggest_mode": "always",
+                }
+            }
+        }));
+
+        assert_eq!(suggestions, Ok(vec![
+            Suggestion {
+                name: "my-suggestion".to_string(),
+                text: "quikc".to_string(),
+                suggester: Suggester::Term(TermSuggester {
+                    size: 3,
+                    sort: SuggestSort::Frequency,
+                    suggest_mode: SuggestMode::Always,
+                    ..TermSuggester::new("title".to_string())
+                }),
+            }
+        ]));
+    }
+
+    #[test]
+    fn test_parse_completion() {
+        let suggestions = parse(&json!({
+            "song-suggest": {
+                "prefix": "nir",
+                "completion": {
+                    "field": "suggest",
+                }
+            }
+        }));
+
+        assert_eq!(suggestions, Ok(vec![
+            Suggestion {
+                name: "song-suggest".to_string(),
+                text: "nir".to_string(),
+                suggester: Suggester::Completion(CompletionSuggester::new("suggest".to_string())),
+            }
+        ]));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(parse(&json!({"s": {"term": {"field": "title"}}})), Err(SuggestParseError::MissingText));
+        assert_eq!(parse(&json!({"s": {"text": "foo", "term": {}}})), Err(SuggestParseError::MissingField));
+        assert_eq!(parse(&json!({"s": {"text": "foo", "term": {"field": "title"}, "phrase": {"field": "title"}}})), Err(SuggestParseError::ExpectedSingleSuggester));
+        assert_eq!(parse(&json!({"s": {"text": "foo", "context": {"field": "title"}}})), Err(SuggestParseError::UnrecognisedKey("context".to_string())));
+    }
+}
@@ -0,0 +1,482 @@
+//! The phrase suggester, which corrects the text as a whole
+//!
+//! Each word is given candidates by the term suggester. Combinations of these are scored
+//! with a language model built from how often terms appear next to each other in the
+//! field, and the ones that are more likely than the text itself are returned.
+
+use std::cmp;
+
+use serde_json;
+use serde_json::Value as Json;
+
+use analysis::AnalyzerSpec;
+
+use super::{TermDictionary, SuggestParseError, analyse_words, parse_positive_integer};
+use super::term::{TermSuggester, SuggestMode, parse_setting as parse_term_setting};
+
+
+/// How much the probability of a term is reduced when it's never been seen after the term before it
+///
+/// This is the "stupid backoff" model.
+const BACKOFF_DISCOUNT: f64 = 0.4;
+
+
+/// A query that each suggestion is checked against
+#[derive(Debug, Clone, PartialEq)]
+pub struct CollateOptions {
+    pub query: Json,
+    pub params: serde_json::Map<String, Json>,
+
+    /// If true, suggestions that don't match are kept and marked with "collate_match"
+    pub prune: bool,
+}
+
+
+impl CollateOptions {
+    /// Fills in the "{{suggestion}}" and parameter placeholders of the query
+    pub fn render(&self, suggestion: &str) -> Json {
+        let mut replacements = vec![("{{suggestion}}".to_string(), suggestion.to_string())];
+        for (name, value) in self.params.iter() {
+            let value = match *value {
+                Json::String(ref string) => string.clone(),
+                ref value => value.to_string(),
+            };
+            replacements.push((format!("{{{{{}}}}}", name), value));
+        }
+
+        render_json(&self.query, &replacements)
+    }
+}
+
+
+fn render_string(string: &str, replacements: &[(String, String)]) -> String {
+    let mut string = string.to_string();
+    for &(ref placeholder, ref value) in replacements.iter() {
+        string = string.replace(placeholder, value);
+    }
+    string
+}
+
+
+fn render_json(json: &Json, replacements: &[(String, String)]) -> Json {
+    match *json {
+        Json::String(ref string) => Json::String(render_string(string, replacements)),
+        Json::Array(ref array) => Json::Array(array.iter().map(|item| render_json(item, replacements)).collect()),
+        Json::Object(ref object) => {
+            let mut rendered = serde_json::Map::new();
+            for (key, value) in object.iter() {
+                rendered.insert(render_string(key, replacements), render_json(value, replacements));
+            }
+            Json::Object(rendered)
+        }
+        ref json => json.clone(),
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PhraseSuggester {
+    pub field: String,
+    pub size: usize,
+
+    /// 1 to score terms on their own or 2 to take the term before each one into account
+    pub gram_size: usize,
+
+    /// Only suggestions that are at least this many times as likely as the text are returned
+    pub confidence: f64,
+
+    /// The most words that can be corrected, as a fraction of the words if less than 1
+    pub max_errors: f64,
+
+    /// How likely it is that a word is right even though it's in the field
+    pub real_word_error_likelihood: f64,
+
+    /// The tags to put around corrected words in a "highlighted" copy of each suggestion
+    pub highlight: Option<(String, String)>,
+
+    /// Finds the candidates for each word
+    pub generator: TermSuggester,
+    pub collate: Option<CollateOptions>,
+}
+
+
+/// A corrected phrase
+#[derive(Debug, Clone, PartialEq)]
+pub struct PhraseOption {
+    pub text: String,
+    pub highlighted: Option<String>,
+
+    /// The log10 of how likely the phrase is
+    pub score: f64,
+}
+
+
+/// Finds every way of picking a candidate for each word with at most `max_errors` changes
+///
+/// Choice 0 is the word itself.
+fn enumerate_choices(candidates: &[Vec<(String, f64)>], max_errors: usize, chosen: &mut Vec<usize>, choices: &mut Vec<Vec<usize>>) {
+    if chosen.len() == candidates.len() {
+        choices.push(chosen.clone());
+        return;
+    }
+
+    let errors = chosen.iter().filter(|&&choice| choice != 0).count();
+    let num_choices = if errors < max_errors { candidates[chosen.len()].len() } else { 1 };
+
+    for choice in 0..num_choices {
+        chosen.push(choice);
+        enumerate_choices(candidates, max_errors, chosen, choices);
+        chosen.pop();
+    }
+}
+
+
+impl PhraseSuggester {
+    pub fn new(field: String) -> PhraseSuggester {
+        let mut generator = TermSuggester::new(field.clone());
+
+        // Real words can be mistakes too
+        generator.suggest_mode = SuggestMode::Always;
+
+        PhraseSuggester {
+            field: field,
+            size: 5,
+            gram_size: 2,
+            confidence: 1.0,
+            max_errors: 1.0,
+            real_word_error_likelihood: 0.95,
+            highlight: None,
+            generator: generator,
+            collate: None,
+        }
+    }
+
+    fn unigram_probability(&self, term: &str, term_dictionary: &TermDictionary) -> f64 {
+        // Terms that aren't in the field are given a small probability
+        (term_dictionary.term_count(term) as f64 + 1.0) / (term_dictionary.total_terms() + term_dictionary.num_unique_terms()) as f64
+    }
+
+    /// Returns the log10 of how likely a sequence of terms is
+    fn language_model_score(&self, terms: &[&str], term_dictionary: &TermDictionary) -> f64 {
+        let mut score = 0.0;
+
+        for (i, term) in terms.iter().enumerate() {
+            let probability = if i == 0 || self.gram_size < 2 {
+                self.unigram_probability(term, term_dictionary)
+            } else {
+                match term_dictionary.bigram_count(terms[i - 1], term) {
+                    0 => BACKOFF_DISCOUNT * self.unigram_probability(term, term_dictionary),
+                    bigram_count => bigram_count as f64 / term_dictionary.term_count(terms[i - 1]) as f64,
+                }
+            };
+
+            score += probability.log10();
+        }
+
+        score
+    }
+
+    /// Finds the corrections of the text that are more likely than the text itself
+    ///
+    /// These are ordered from most to least likely.
+    pub fn find_options(&self, text: &str, analyzer: Option<&AnalyzerSpec>, term_dictionary: &TermDictionary) -> Vec<PhraseOption> {
+        let words = analyse_words(text, analyzer);
+        if words.is_empty() || term_dictionary.total_terms() == 0 {
+            return Vec::new();
+        }
+
+        // Each word can stay as it is or be replaced with one of its candidates
+        let candidates = words.iter().map(|&(_, _, ref term)| {
+            let mut candidates = vec![(term.clone(), self.real_word_error_likelihood.log10())];
+            for (candidate, score, _) in self.generator.find_candidates(term, term_dictionary) {
+                candidates.push((candidate.to_string(), ((1.0 - self.real_word_error_likelihood) * score).log10()));
+            }
+            candidates
+        }).collect::<Vec<_>>();
+
+        let max_errors = if self.max_errors < 1.0 {
+            cmp::max(1, (self.max_errors * words.len() as f64) as usize)
+        } else {
+            self.max_errors as usize
+        };
+
+        let mut choices = Vec::new();
+        enumerate_choices(&candidates, max_errors, &mut Vec::new(), &mut choices);
+
+        let score_choice = |choice: &Vec<usize>| {
+            let terms = choice.iter().enumerate().map(|(i, &choice)| candidates[i][choice].0.as_ref()).collect::<Vec<&str>>();
+            let channel_score = choice.iter().enumerate().map(|(i, &choice)| candidates[i][choice].1).sum::<f64>();
+            channel_score + self.language_model_score(&terms, term_dictionary)
+        };
+
+        // The first choice leaves every word as it is
+        let threshold = if self.confidence > 0.0 {
+            score_choice(&choices[0]) + self.confidence.log10()
+        } else {
+            ::std::f64::NEG_INFINITY
+        };
+
+        let mut options = choices.iter().skip(1).filter_map(|choice| {
+            let score = score_choice(choice);
+            if score < threshold {
+                return None;
+            }
+
+            let text = choice.iter().enumerate().map(|(i, &choice)| candidates[i][choice].0.clone()).collect::<Vec<_>>().join(" ");
+            let highlighted = self.highlight.as_ref().map(|&(ref pre_tag, ref post_tag)| {
+                choice.iter().enumerate().map(|(i, &choice)| {
+                    if choice == 0 {
+                        candidates[i][choice].0.clone()
+                    } else {
+                        format!("{}{}{}", pre_tag, candidates[i][choice].0, post_tag)
+                    }
+                }).collect::<Vec<_>>().join(" ")
+            });
+
+            Some(PhraseOption {
+                text: text,
+                highlighted: highlighted,
+                score: score,
+            })
+        }).collect::<Vec<_>>();
+
+        options.sort_by(|a, b| (b.score, &a.text).partial_cmp(&(a.score, &b.text)).unwrap_or(cmp::Ordering::Equal));
+        options
+    }
+
+    /// Converts the options for the text into the suggestion's entries
+    ///
+    /// Each option may be given whether it matched the collate query.
+    pub fn to_json(&self, text: &str, options: Vec<(PhraseOption, Option<bool>)>) -> Json {
+        let options_json = options.into_iter().take(self.size).map(|(option, collate_match)| {
+            let mut option_json = serde_json::Map::new();
+            option_json.insert("text".to_string(), Json::String(option.text));
+            if let Some(highlighted) = option.highlighted {
+                option_json.insert("highlighted".to_string(), Json::String(highlighted));
+            }
+            option_json.insert("score".to_string(), json!(10f64.powf(option.score)));
+            if let Some(collate_match) = collate_match {
+                option_json.insert("collate_match".to_string(), Json::Bool(collate_match));
+            }
+            Json::Object(option_json)
+        }).collect::<Vec<_>>();
+
+        json!([
+            {
+                "text": text,
+                "offset": 0,
+                "length": text.chars().count(),
+                "options": options_json,
+            }
+        ])
+    }
+}
+
+
+fn parse_number(json: &Json) -> Result<f64, SuggestParseError> {
+    json.as_f64().ok_or(SuggestParseError::ExpectedNumber)
+}
+
+
+fn parse_highlight(json: &Json) -> Result<(String, String), SuggestParseError> {
+    let object = try!(json.as_object().ok_or(SuggestParseError::ExpectedObject));
+    let mut pre_tag = "<em>".to_string();
+    let mut post_tag = "</em>".to_string();
+
+    for (key, value) in object.iter() {
+        match key.as_ref() {
+            "pre_tag" => pre_tag = try!(value.as_str().ok_or(SuggestParseError::ExpectedString)).to_string(),
+            "post_tag" => post_tag = try!(value.as_str().ok_or(SuggestParseError::ExpectedString)).to_string(),
+            _ => return Err(SuggestParseError::UnrecognisedKey(key.clone())),
+        }
+    }
+
+    Ok((pre_tag, post_tag))
+}
+
+
+/// Parses the "direct_generator" section into the suggester's generator
+///
+/// Only one generator is supported. Its candidates come from the suggester's own field.
+fn parse_generator(json: &Json, generator: &mut TermSuggester) -> Result<(), SuggestParseError> {
+    let generator_json = match *json {
+        Json::Array(ref array) if array.len() == 1 => &array[0],
+        Json::Array(_) => return Err(SuggestParseError::ExpectedSingleGenerator),
+        ref json => json,
+    };
+
+    let object = try!(generator_json.as_object().ok_or(SuggestParseError::ExpectedObject));
+    for (key, value) in object.iter() {
+        if key == "field" {
+            continue;
+        }
+
+        if !try!(parse_term_setting(generator, key, value)) {
+            return Err(SuggestParseError::UnrecognisedKey(key.clone()));
+        }
+    }
+
+    Ok(())
+}
+
+
+fn parse_collate(json: &Json) -> Result<CollateOptions, SuggestParseError> {
+    let object = try!(json.as_object().ok_or(SuggestParseError::ExpectedObject));
+    let mut query = None;
+    let mut params = serde_json::Map::new();
+    let mut prune = false;
+
+    for (key, value) in object.iter() {
+        match key.as_ref() {
+            "query" => {
+                // The query may be wrapped in a "source" (or older "inline") key
+                let query_json = match value.as_object() {
+                    Some(query_object) if query_object.len() == 1 && (query_object.contains_key("source") || query_object.contains_key("inline")) => {
+                        query_object.get("source").or(query_object.get("inline")).unwrap()
+                    }
+                    _ => value,
+                };
+
+                if !query_json.is_object() {
+                    return Err(SuggestParseError::ExpectedObject);
+                }
+
+                query = Some(query_json.clone());
+            }
+            "params" => params = try!(value.as_object().ok_or(SuggestParseError::ExpectedObject)).clone(),
+            "prune" => prune = try!(value.as_bool().ok_or(SuggestParseError::ExpectedBoolean)),
+            _ => return Err(SuggestParseError::UnrecognisedKey(key.clone())),
+        }
+    }
+
+    Ok(CollateOptions {
+        query: try!(query.ok_or(SuggestParseError::ExpectedObject)),
+        params: params,
+        prune: prune,
+    })
+}
+
+
+pub fn parse(json: &Json) -> Result<PhraseSuggester, SuggestParseError> {
+    let object = try!(json.as_object().ok_or(SuggestParseError::ExpectedObject));
+    let field = try!(try!(object.get("field").ok_or(SuggestParseError::MissingField)).as_str().ok_or(SuggestParseError::ExpectedString));
+    let mut suggester = PhraseSuggester::new(field.to_string());
+
+    for (key, value) in object.iter() {
+        match key.as_ref() {
+            "field" => {}
+            "size" => suggester.size = try!(parse_positive_integer(value)) as usize,
+            "gram_size" => {
+                suggester.gram_size = match try!(parse_positive_integer(value)) {
+                    gram_size @ 1...2 => gram_size as usize,
+                    _ => return Err(SuggestParseError::InvalidGramSize),
+                };
+            }
+            "confidence" => suggester.confidence = try!(parse_number(value)),
+            "max_errors" => suggester.max_errors = try!(parse_number(value)),
+            "real_word_error_likelihood" => suggester.real_word_error_likelihood = try!(parse_number(value)),
+            "highlight" => suggester.highlight = Some(try!(parse_highlight(value))),
+            "direct_generator" => try!(parse_generator(value, &mut suggester.generator)),
+            "collate" => suggester.collate = Some(try!(parse_collate(value))),
+            _ => return Err(SuggestParseError::UnrecognisedKey(key.clone())),
+        }
+    }
+
+    Ok(suggester)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use suggest::TermDictionary;
+    use suggest::tests::analyzer;
+    use suggest::SuggestParseError;
+
+    use super::{parse, PhraseSuggester, CollateOptions};
+
+    fn term_dictionary() -> TermDictionary {
+        let mut term_dictionary = TermDictionary::new();
+        term_dictionary.add_document(&json!({"title": "The nobel prize winners"}), "title", Some(&analyzer()));
+        term_dictionary.add_document(&json!({"title": "Nobel prize in physics"}), "title", Some(&analyzer()));
+        term_dictionary.add_document(&json!({"title": "A nobel prize for peace"}), "title", Some(&analyzer()));
+        term_dictionary.add_document(&json!({"title": "The noble gases"}), "title", Some(&analyzer()));
+        term_dictionary
+    }
+
+    #[test]
+    fn test_find_options() {
+        let suggester = PhraseSuggester::new("title".to_string());
+        let options = suggester.find_options("noble prize", Some(&analyzer()), &term_dictionary());
+
+        assert_eq!(options.first().map(|option| option.text.as_ref()), Some("nobel prize"));
+    }
+
+    #[test]
+    fn test_find_options_correct_phrase() {
+        let suggester = PhraseSuggester::new("title".to_string());
+        let options = suggester.find_options("nobel prize", Some(&analyzer()), &term_dictionary());
+
+        assert!(options.is_empty());
+    }
+
+    #[test]
+    fn test_find_options_highlight() {
+        let mut suggester = PhraseSuggester::new("title".to_string());
+        suggester.highlight = Some(("<b>".to_string(), "</b>".to_string()));
+        let options = suggester.find_options("noble prize", Some(&analyzer()), &term_dictionary());
+
+        assert_eq!(options.first().and_then(|option| option.highlighted.as_ref()).map(|highlighted| highlighted.as_ref()), Some("<b>nobel</b> prize"));
+    }
+
+    #[test]
+    fn test_max_errors() {
+        // Without a confidence every correction is returned, so only max_errors limits them
+        let mut suggester = PhraseSuggester::new("title".to_string());
+        suggester.confidence = 0.0;
+        let options = suggester.find_options("noble prise", Some(&analyzer()), &term_dictionary());
+        assert!(options.iter().all(|option| option.text != "nobel prize"));
+
+        suggester.max_errors = 2.0;
+        let options = suggester.find_options("noble prise", Some(&analyzer()), &term_dictionary());
+        assert_eq!(options.first().map(|option| option.text.as_ref()), Some("nobel prize"));
+    }
+
+    #[test]
+    fn test_collate_render() {
+        let collate = CollateOptions {
+            query: json!({"match": {"{{field_name}}": "{{suggestion}}"}}),
+            params: json!({"field_name": "title"}).as_object().unwrap().clone(),
+            prune: false,
+        };
+
+        assert_eq!(collate.render("nobel prize"), json!({"match": {"title": "nobel prize"}}));
+    }
+
+    #[test]
+    fn test_parse() {
+        let suggester = parse(&json!({
+            "field": "title",
+            "confidence": 2.0,
+            "max_errors": 0.5,
+            "highlight": {"pre_tag": "<b>", "post_tag": "</b>"},
+            "direct_generator": [{"field": "title", "min_word_length": 3}],
+            "collate": {
+                "query": {"source": {"match": {"title": "{{suggestion}}"}}},
+                "prune": true,
+            },
+        })).unwrap();
+
+        assert_eq!(suggester.confidence, 2.0);
+        assert_eq!(suggester.max_errors, 0.5);
+        assert_eq!(suggester.highlight, Some(("<b>".to_string(), "</b>".to_string())));
+        assert_eq!(suggester.generator.min_word_length, 3);
+        assert_eq!(suggester.collate.map(|collate| (collate.query, collate.prune)), Some((json!({"match": {"title": "{{suggestion}}"}}), true)));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(parse(&json!({"field": "title", "gram_size": 3})), Err(SuggestParseError::InvalidGramSize));
+        assert_eq!(parse(&json!({"field": "title", "direct_generator": [{}, {}]})), Err(SuggestParseError::ExpectedSingleGenerator));
+        assert_eq!(parse(&json!({"field": "title", "collate": {"prune": true}})), Err(SuggestParseError::ExpectedObject));
+    }
+}
@@ -0,0 +1,309 @@
+//! The term suggester, which proposes corrections for each word of the text
+//!
+//! Candidates are the terms of the field that are within a few edits of the word.
+
+use std::cmp;
+
+use serde_json::Value as Json;
+
+use analysis::AnalyzerSpec;
+
+use super::{TermDictionary, SuggestParseError, analyse_words, parse_positive_integer};
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SuggestMode {
+    /// Only suggest for words that aren't in the field
+    Missing,
+
+    /// Only suggest terms that are in more documents than the word
+    Popular,
+
+    Always,
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SuggestSort {
+    Score,
+    Frequency,
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TermSuggester {
+    pub field: String,
+    pub size: usize,
+    pub suggest_mode: SuggestMode,
+    pub sort: SuggestSort,
+
+    /// The most edits a candidate can be from the word, either 1 or 2
+    pub max_edits: usize,
+
+    /// The number of characters at the start of the word that candidates must share
+    pub prefix_length: usize,
+
+    /// Words shorter than this don't get any suggestions
+    pub min_word_length: usize,
+    pub min_doc_freq: u64,
+}
+
+
+impl TermSuggester {
+    pub fn new(field: String) -> TermSuggester {
+        TermSuggester {
+            field: field,
+            size: 5,
+            suggest_mode: SuggestMode::Missing,
+            sort: SuggestSort::Score,
+            max_edits: 2,
+            prefix_length: 1,
+            min_word_length: 4,
+            min_doc_freq: 0,
+        }
+    }
+
+    /// Finds the candidates for one analysed word, returning (term, score, frequency) tuples
+    pub fn find_candidates<'a>(&self, word: &str, term_dictionary: &'a TermDictionary) -> Vec<(&'a str, f64, u64)> {
+        let word_chars = word.chars().collect::<Vec<_>>();
+        if word_chars.len() < self.min_word_length {
+            return Vec::new();
+        }
+
+        let word_freq = term_dictionary.doc_freq(word);
+        if self.suggest_mode == SuggestMode::Missing && word_freq > 0 {
+            return Vec::new();
+        }
+
+        let prefix = word_chars.iter().take(self.prefix_length).cloned().collect::<Vec<_>>();
+
+        let mut candidates = Vec::new();
+        for (term, &freq) in term_dictionary.terms().iter() {
+            if term == word || freq < self.min_doc_freq {
+                continue;
+            }
+
+            if self.suggest_mode == SuggestMode::Popular && freq <= word_freq {
+                continue;
+            }
+
+            let term_chars = term.chars().collect::<Vec<_>>();
+            if !term_chars.starts_with(&prefix) {
+                continue;
+            }
+
+            // Terms with very different lengths can't be close enough
+            let length_difference = if term_chars.len() > word_chars.len() { term_chars.len() - word_chars.len() } else { word_chars.len() - term_chars.len() };
+            if length_difference > self.max_edits {
+                continue;
+            }
+
+            let distance = edit_distance(&word_chars, &term_chars);
+            if distance > self.max_edits {
+                continue;
+            }
+
+            let score = 1.0 - distance as f64 / cmp::min(word_chars.len(), term_chars.len()) as f64;
+            candidates.push((term.as_ref(), score, freq));
+        }
+
+        // Ties are broken by the term so the order is stable
+        match self.sort {
+            SuggestSort::Score => candidates.sort_by(|a, b| (b.1, b.2, a.0).partial_cmp(&(a.1, a.2, b.0)).unwrap()),
+            SuggestSort::Frequency => candidates.sort_by(|a, b| (b.2, b.1, a.0).partial_cmp(&(a.2, a.1, b.0)).unwrap()),
+        }
+
+        candidates.truncate(self.size);
+        candidates
+    }
+
+    /// Suggests corrections for each word of the text
+    ///
+    /// The words are analysed with the field's search analyzer before being looked up.
+    pub fn suggest(&self, text: &str, analyzer: Option<&AnalyzerSpec>, term_dictionary: &TermDictionary) -> Json {
+        let entries = analyse_words(text, analyzer).into_iter().map(|(start, word, term)| {
+            let options = self.find_candidates(&term, term_dictionary).into_iter().map(|(text, score, freq)| {
+                json!({
+                    "text": text,
+                    "score": score,
+                    "freq": freq,
+                })
+            }).collect::<Vec<_>>();
+
+            // Offsets are given in characters
+            json!({
+                "text": word,
+                "offset": text[..start].chars().count(),
+                "length": word.chars().count(),
+                "options": options,
+            })
+        }).collect::<Vec<_>>();
+
+        Json::Array(entries)
+    }
+}
+
+
+/// Counts the number of characters that must be inserted, deleted, substituted or
+/// swapped with their neighbour to turn one word into another
+pub fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+
+    for i in 0..a.len() + 1 {
+        distances[i][0] = i;
+    }
+
+    for j in 0..b.len() + 1 {
+        distances[0][j] = j;
+    }
+
+    for i in 1..a.len() + 1 {
+        for j in 1..b.len() + 1 {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+
+            let mut distance = cmp::min(cmp::min(distances[i - 1][j] + 1, distances[i][j - 1] + 1), distances[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = cmp::min(distance, distances[i - 2][j - 2] + 1);
+            }
+
+            distances[i][j] = distance;
+        }
+    }
+
+    distances[a.len()][b.len()]
+}
+
+
+/// Applies a setting of the term suggester, returning false if the key isn't one
+///
+/// These are shared with the phrase suggester's "direct_generator".
+pub fn parse_setting(suggester: &mut TermSuggester, key: &str, value: &Json) -> Result<bool, SuggestParseError> {
+    match key {
+        "size" => suggester.size = try!(parse_positive_integer(value)) as usize,
+        "suggest_mode" => {
+            let suggest_mode = try!(value.as_str().ok_or(SuggestParseError::ExpectedString));
+            suggester.suggest_mode = match suggest_mode {
+                "missing" => SuggestMode::Missing,
+                "popular" => SuggestMode::Popular,
+                "always" => SuggestMode::Always,
+                _ => return Err(SuggestParseError::InvalidSuggestMode(suggest_mode.to_string())),
+            };
+        }
+        "max_edits" => {
+            suggester.max_edits = match try!(parse_positive_integer(value)) {
+                max_edits @ 1...2 => max_edits as usize,
+                _ => return Err(SuggestParseError::InvalidMaxEdits),
+            };
+        }
+        "prefix_length" => suggester.prefix_length = try!(parse_positive_integer(value)) as usize,
+        "min_word_length" => suggester.min_word_length = try!(parse_positive_integer(value)) as usize,
+        "min_doc_freq" => suggester.min_doc_freq = try!(parse_positive_integer(value)),
+        _ => return Ok(false),
+    }
+
+    Ok(true)
+}
+
+
+pub fn parse(json: &Json) -> Result<TermSuggester, SuggestParseError> {
+    let object = try!(json.as_object().ok_or(SuggestParseError::ExpectedObject));
+    let field = try!(try!(object.get("field").ok_or(SuggestParseError::MissingField)).as_str().ok_or(SuggestParseError::ExpectedString));
+    let mut suggester = TermSuggester::new(field.to_string());
+
+    for (key, value) in object.iter() {
+        match key.as_ref() {
+            "field" => {}
+            "sort" => {
+                let sort = try!(value.as_str().ok_or(SuggestParseError::ExpectedString));
+                suggester.sort = match sort {
+                    "score" => SuggestSort::Score,
+                    "frequency" => SuggestSort::Frequency,
+                    _ => return Err(SuggestParseError::InvalidSort(sort.to_string())),
+                };
+            }
+            _ => {
+                if !try!(parse_setting(&mut suggester, key, value)) {
+                    return Err(SuggestParseError::UnrecognisedKey(key.clone()));
+                }
+            }
+        }
+    }
+
+    Ok(suggester)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use suggest::TermDictionary;
+    use suggest::tests::analyzer;
+    use suggest::SuggestParseError;
+
+    use super::{parse, edit_distance, TermSuggester, SuggestMode};
+
+    fn term_dictionary() -> TermDictionary {
+        let mut term_dictionary = TermDictionary::new();
+        term_dictionary.add_document(&json!({"title": "The quick brown fox"}), "title", Some(&analyzer()));
+        term_dictionary.add_document(&json!({"title": "Quick thinking"}), "title", Some(&analyzer()));
+        term_dictionary.add_document(&json!({"title": "Quack quack"}), "title", Some(&analyzer()));
+        term_dictionary
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        let distance = |a: &str, b: &str| edit_distance(&a.chars().collect::<Vec<_>>(), &b.chars().collect::<Vec<_>>());
+
+        assert_eq!(distance("quick", "quick"), 0);
+        assert_eq!(distance("quikc", "quick"), 1);
+        assert_eq!(distance("quck", "quick"), 1);
+        assert_eq!(distance("qiuck", "quack"), 2);
+        assert_eq!(distance("", "fox"), 3);
+    }
+
+    #[test]
+    fn test_suggest() {
+        let suggester = TermSuggester::new("title".to_string());
+
+        assert_eq!(suggester.suggest("Quikc brown", Some(&analyzer()), &term_dictionary()), json!([
+            {
+                "text": "Quikc",
+                "offset": 0,
+                "length": 5,
+                "options": [
+                    {"text": "quick", "score": 0.8, "freq": 2},
+                    {"text": "quack", "score": 0.6, "freq": 1},
+                ],
+            },
+            {
+                "text": "brown",
+                "offset": 6,
+                "length": 5,
+                "options": [],
+            },
+        ]));
+    }
+
+    #[test]
+    fn test_suggest_popular() {
+        let mut suggester = TermSuggester::new("title".to_string());
+        suggester.suggest_mode = SuggestMode::Popular;
+
+        assert_eq!(suggester.suggest("quack", Some(&analyzer()), &term_dictionary()), json!([
+            {
+                "text": "quack",
+                "offset": 0,
+                "length": 5,
+                "options": [
+                    {"text": "quick", "score": 0.8, "freq": 2},
+                ],
+            },
+        ]));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(parse(&json!({"field": "title", "max_edits": 3})), Err(SuggestParseError::InvalidMaxEdits));
+        assert_eq!(parse(&json!({"field": "title", "sort": "popularity"})), Err(SuggestParseError::InvalidSort("popularity".to_string())));
+        assert_eq!(parse(&json!({"field": "title", "foo": 1})), Err(SuggestParseError::UnrecognisedKey("foo".to_string())));
+    }
+}
@@ -1,20 +1,35 @@
-use std::sync::RwLock;
+use std::sync::{RwLock, Mutex};
 use std::path::{Path, PathBuf};
 use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use serde_json;
 use slog::Logger;
 use kite_rocksdb::RocksDBIndexStore;
 use uuid::Uuid;
 
 use index::Index;
 use index::metadata::IndexMetadata;
-use cluster::metadata::ClusterMetadata;
+use index::metadata::parse::parse as parse_index_metadata;
+use cluster::metadata::{ClusterMetadata, IndexRef};
+use template::merge_json;
+use scroll::ScrollContexts;
+
+
+/// The current time in milliseconds since the epoch
+pub fn current_timestamp() -> u64 {
+    let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    since_epoch.as_secs() * 1000 + (since_epoch.subsec_nanos() / 1_000_000) as u64
+}
 
 
 pub struct System {
     pub log: Logger,
     data_dir: PathBuf,
     pub metadata: RwLock<ClusterMetadata>,
+
+    /// Scrolling searches that are still open
+    pub scrolls: Mutex<ScrollContexts>,
 }
 
 
@@ -24,16 +39,21 @@ impl System {
             log: log,
             data_dir: data_dir,
             metadata: RwLock::new(ClusterMetadata::new()),
+            scrolls: Mutex::new(ScrollContexts::new()),
         }
     }
 
+    pub fn get_data_dir(&self) -> &Path {
+        &self.data_dir
+    }
+
     pub fn get_indices_dir(&self) -> PathBuf {
         let mut dir = self.data_dir.clone();
         dir.push("indices");
         dir
     }
 
-    fn load_index(&self, id: Uuid, name: String, path: &Path) -> Result<Index, String> {
+    pub fn load_index(&self, id: Uuid, name: String, path: &Path) -> Result<Index, String> {
         let store = try!(RocksDBIndexStore::open(path));
 
         // Load metadata
@@ -41,7 +61,89 @@ impl System {
         metadata_path.push("metadata.json");
         let metadata = try!(IndexMetadata::load(metadata_path));
 
-        Ok(Index::new(id, name, metadata, store))
+        let index = Index::new(id, name, metadata, store);
+        index.rebuild_completions();
+        Ok(index)
+    }
+
+    /// Creates a new index
+    ///
+    /// The matching index template with the highest priority is used as a base for the
+    /// settings, mappings and aliases given in "data".
+    pub fn create_index(&self, cluster_metadata: &mut ClusterMetadata, index_name: &str, data: Option<serde_json::Value>) -> Result<IndexRef, String> {
+        let mut metadata_json = match cluster_metadata.find_index_template(index_name) {
+            Some(template) => template.template.clone(),
+            None => json!({}),
+        };
+
+        if let Some(data) = data {
+            merge_json(&mut metadata_json, data);
+        }
+
+        // Load metadata
+        let mut metadata = IndexMetadata::default();
+        if let Err(e) = parse_index_metadata(&mut metadata, metadata_json) {
+            return Err(format!("Couldn't parse index settings: {}", e));
+        }
+
+        if metadata.settings.creation_date.is_none() {
+            metadata.settings.creation_date = Some(current_timestamp());
+        }
+
+        // Aliases can't have the name of an index
+        for alias_name in metadata.aliases.keys() {
+            if alias_name == index_name || cluster_metadata.names.find_canonical(alias_name).is_some() {
+                return Err(format!("Invalid alias name [{}], an index exists with the same name as the alias", alias_name));
+            }
+        }
+
+        // Create index
+        let mut indices_dir = self.get_indices_dir();
+        indices_dir.push(index_name);
+        let index = Index::new(Uuid::new_v4(), index_name.to_owned(), metadata, RocksDBIndexStore::create(indices_dir).unwrap());
+        index.metadata.read().unwrap().save(index.metadata_path()).unwrap();
+        let index_ref = cluster_metadata.insert_index(index);
+
+        // If there's an alias with the new indexes name, delete it.
+        if cluster_metadata.names.is_alias(index_name) {
+            for alias_index_ref in cluster_metadata.names.find(index_name) {
+                if let Some(alias_index) = cluster_metadata.indices.get(&alias_index_ref) {
+                    let mut alias_index_metadata = alias_index.metadata.write().unwrap();
+                    alias_index_metadata.aliases.remove(index_name);
+                    alias_index_metadata.save(alias_index.metadata_path()).unwrap();
+                }
+            }
+        }
+
+        let alias_deleted = cluster_metadata.names.delete_alias_whole(index_name).unwrap();
+        if alias_deleted {
+             self.log.info("[sys] deleted alias", b!("alias" => format!("{}", index_name), "reason" => "replaced by index"));
+        }
+
+        // Register canonical name
+        cluster_metadata.names.insert_canonical(index_name.to_owned(), index_ref).unwrap();
+        cluster_metadata.insert_index_aliases(index_ref).unwrap();
+
+        Ok(index_ref)
+    }
+
+    /// Creates an index for documents sent to a name that isn't used yet
+    ///
+    /// Returns true if an index was created.
+    pub fn auto_create_index(&self, index_name: &str) -> Result<bool, String> {
+        if !self.metadata.read().unwrap().names.find(index_name).is_empty() {
+            return Ok(false);
+        }
+
+        let mut cluster_metadata = self.metadata.write().unwrap();
+
+        // Another request may have created it while the lock was released
+        if !cluster_metadata.names.find(index_name).is_empty() {
+            return Ok(false);
+        }
+
+        try!(self.create_index(&mut cluster_metadata, index_name, None));
+        Ok(true)
     }
 
     pub fn load_indices(&self) {
@@ -59,6 +161,10 @@ impl System {
                                 let index_ref = cluster_metadata.insert_index(index);
                                 cluster_metadata.names.insert_canonical(index_name.clone(), index_ref).unwrap();
 
+                                if let Err(e) = cluster_metadata.insert_index_aliases(index_ref) {
+                                    self.log.warn("[sys] could not register index aliases", b!("index" => index_name.clone(), "error" => e));
+                                }
+
                                 self.log.info("[sys] loaded index", b!("index" => index_name));
                             }
                             Err(e) => {
@@ -0,0 +1,410 @@
+//! Index templates
+//!
+//! A template holds the settings, mappings and aliases for any new index with a name that
+//! matches one of its patterns. When an index is created, the matching template with the
+//! highest priority is used as a base for the settings given in the request.
+//!
+//! Templates are saved together in `templates.json` in the data directory.
+
+use std::io::{Read, Write};
+use std::fmt;
+use std::fs::File;
+use std::path::PathBuf;
+use std::collections::BTreeMap;
+
+use serde_json;
+use serde_json::value::ToJson;
+use atomicwrites::{AtomicFile, AllowOverwrite};
+
+use system::System;
+use cluster::metadata::ClusterMetadata;
+use index::metadata::IndexMetadata;
+use index::metadata::parse::{parse as parse_index_metadata, IndexMetadataParseError};
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexTemplate {
+    pub index_patterns: Vec<String>,
+
+    /// Decides which template is used when more than one matches an index name
+    pub priority: u64,
+
+    /// The settings, mappings and aliases for new indices, in the format used by the
+    /// create index API
+    pub template: serde_json::Value,
+}
+
+
+impl IndexTemplate {
+    pub fn matches(&self, index_name: &str) -> bool {
+        self.index_patterns.iter().any(|pattern| matches_pattern(pattern, index_name))
+    }
+}
+
+
+impl ToJson for IndexTemplate {
+    fn to_json(&self) -> Result<serde_json::Value, serde_json::Error> {
+        Ok(json!({
+            "index_patterns": self.index_patterns,
+            "priority": self.priority,
+            "template": self.template,
+        }))
+    }
+}
+
+
+#[derive(Debug, PartialEq)]
+pub enum IndexTemplateParseError {
+    ExpectedObject,
+    ExpectedString,
+    ExpectedPositiveInteger(String),
+    ExpectedKey(String),
+    UnrecognisedKey(String),
+    InvalidTemplate(IndexMetadataParseError),
+}
+
+
+impl fmt::Display for IndexTemplateParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            IndexTemplateParseError::ExpectedObject => write!(f, "expected an object"),
+            IndexTemplateParseError::ExpectedString => write!(f, "expected a string"),
+            IndexTemplateParseError::ExpectedPositiveInteger(ref key) => write!(f, "[{}] must be a positive integer", key),
+            IndexTemplateParseError::ExpectedKey(ref key) => write!(f, "[{}] is required", key),
+            IndexTemplateParseError::UnrecognisedKey(ref key) => write!(f, "unknown key [{}] in the index template", key),
+            IndexTemplateParseError::InvalidTemplate(ref e) => write!(f, "invalid template: {}", e),
+        }
+    }
+}
+
+
+/// Checks if a name matches a pattern, where "*" matches any number of characters
+pub fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+
+    // The name must start with the text before the first wildcard
+    let first_part = parts.next().unwrap_or("");
+    if !name.starts_with(first_part) {
+        return false;
+    }
+
+    let mut remaining = &name[first_part.len()..];
+    let mut parts = parts.collect::<Vec<&str>>();
+
+    // No wildcards, so the name must be exactly the same as the pattern
+    let last_part = match parts.pop() {
+        Some(last_part) => last_part,
+        None => return remaining.is_empty(),
+    };
+
+    // Match the text between wildcards as early as possible, leaving the most room for the rest
+    for part in parts {
+        match remaining.find(part) {
+            Some(position) => remaining = &remaining[position + part.len()..],
+            None => return false,
+        }
+    }
+
+    remaining.ends_with(last_part)
+}
+
+
+/// Merges two JSON values, with values from "overrides" replacing those in "base"
+///
+/// Objects are merged key by key, everything else is replaced.
+pub fn merge_json(base: &mut serde_json::Value, overrides: serde_json::Value) {
+    match overrides {
+        serde_json::Value::Object(overrides_object) => {
+            if let serde_json::Value::Object(ref mut base_object) = *base {
+                for (key, value) in overrides_object {
+                    if base_object.contains_key(&key) {
+                        merge_json(base_object.get_mut(&key).unwrap(), value);
+                    } else {
+                        base_object.insert(key, value);
+                    }
+                }
+
+                return;
+            }
+
+            *base = serde_json::Value::Object(overrides_object);
+        }
+        overrides => *base = overrides,
+    }
+}
+
+
+pub fn parse(json: &serde_json::Value) -> Result<IndexTemplate, IndexTemplateParseError> {
+    let data = try!(json.as_object().ok_or(IndexTemplateParseError::ExpectedObject));
+
+    let mut index_patterns = None;
+    let mut priority = 0;
+    let mut template = json!({});
+
+    for (key, value) in data.iter() {
+        match key.as_ref() {
+            "index_patterns" => {
+                let mut patterns = Vec::new();
+
+                match *value {
+                    serde_json::Value::String(ref pattern) => patterns.push(pattern.clone()),
+                    serde_json::Value::Array(ref array) => {
+                        for pattern in array.iter() {
+                            patterns.push(try!(pattern.as_str().ok_or(IndexTemplateParseError::ExpectedString)).to_string());
+                        }
+                    }
+                    _ => return Err(IndexTemplateParseError::ExpectedString),
+                }
+
+                index_patterns = Some(patterns);
+            }
+            "priority" => {
+                priority = try!(value.as_u64().ok_or_else(|| IndexTemplateParseError::ExpectedPositiveInteger(key.clone())));
+            }
+            "template" => {
+                if !value.is_object() {
+                    return Err(IndexTemplateParseError::ExpectedObject);
+                }
+
+                // Make sure indices can be created from the template
+                if let Err(e) = parse_index_metadata(&mut IndexMetadata::default(), value.clone()) {
+                    return Err(IndexTemplateParseError::InvalidTemplate(e));
+                }
+
+                template = value.clone();
+            }
+            _ => return Err(IndexTemplateParseError::UnrecognisedKey(key.clone())),
+        }
+    }
+
+    let index_patterns = match index_patterns {
+        Some(ref index_patterns) if !index_patterns.is_empty() => index_patterns.clone(),
+        _ => return Err(IndexTemplateParseError::ExpectedKey("index_patterns".to_string())),
+    };
+
+    Ok(IndexTemplate {
+        index_patterns: index_patterns,
+        priority: priority,
+        template: template,
+    })
+}
+
+
+impl ClusterMetadata {
+    /// Finds the template with the highest priority that matches an index name
+    pub fn find_index_template(&self, index_name: &str) -> Option<&IndexTemplate> {
+        let mut found: Option<&IndexTemplate> = None;
+
+        for template in self.templates.values() {
+            if !template.matches(index_name) {
+                continue;
+            }
+
+            found = match found {
+                Some(found) if found.priority >= template.priority => Some(found),
+                _ => Some(template),
+            };
+        }
+
+        found
+    }
+}
+
+
+impl System {
+    fn get_templates_path(&self) -> PathBuf {
+        let mut path = self.get_data_dir().to_path_buf();
+        path.push("templates.json");
+        path
+    }
+
+    pub fn save_templates(&self, cluster_metadata: &ClusterMetadata) -> Result<(), String> {
+        let mut templates_json = BTreeMap::new();
+        for (name, template) in cluster_metadata.templates.iter() {
+            templates_json.insert(name.clone(), try!(template.to_json().map_err(|e| format!("failed to save index templates: {}", e))));
+        }
+
+        let s = format!("{}", json!(templates_json));
+        let file = AtomicFile::new(self.get_templates_path(), AllowOverwrite);
+        try!(file.write(|f| f.write_all(s.as_bytes())).map_err(|e| format!("failed to save index templates: {}", e)));
+
+        Ok(())
+    }
+
+    pub fn load_templates(&self) {
+        let path = self.get_templates_path();
+        if !path.exists() {
+            return;
+        }
+
+        let mut s = String::new();
+        if let Err(error) = File::open(&path).and_then(|mut file| file.read_to_string(&mut s)) {
+            self.log.error("[sys] could not read index templates", b!("error" => format!("{}", error)));
+            return;
+        }
+
+        let templates_json = match serde_json::from_str::<serde_json::Value>(&s) {
+            Ok(serde_json::Value::Object(templates_json)) => templates_json,
+            _ => {
+                self.log.error("[sys] could not parse index templates", b!());
+                return;
+            }
+        };
+
+        let mut cluster_metadata = self.metadata.write().unwrap();
+        for (name, template_json) in templates_json {
+            match parse(&template_json) {
+                Ok(template) => {
+                    cluster_metadata.templates.insert(name.clone(), template);
+                    self.log.info("[sys] loaded index template", b!("template" => name));
+                }
+                Err(e) => {
+                    self.log.error("[sys] load index template failed", b!("template" => name, "error" => format!("{}", e)));
+                }
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+
+    use cluster::metadata::ClusterMetadata;
+
+    use super::{IndexTemplate, IndexTemplateParseError, matches_pattern, merge_json, parse};
+
+    #[test]
+    fn test_matches_pattern() {
+        assert!(matches_pattern("logs", "logs"));
+        assert!(!matches_pattern("logs", "logs-2017"));
+        assert!(matches_pattern("logs-*", "logs-2017-05-01"));
+        assert!(matches_pattern("logs-*", "logs-"));
+        assert!(!matches_pattern("logs-*", "metrics-2017"));
+        assert!(matches_pattern("*-2017", "logs-2017"));
+        assert!(matches_pattern("logs-*-01", "logs-2017-05-01"));
+        assert!(!matches_pattern("logs-*-01", "logs-2017-05-02"));
+        assert!(matches_pattern("*", "anything"));
+        assert!(!matches_pattern("a*a", "a"));
+    }
+
+    #[test]
+    fn test_merge_json() {
+        let mut base = json!({
+            "settings": {
+                "refresh_interval": "1s",
+                "max_result_window": 100,
+            },
+            "mappings": {
+                "doc": {
+                    "properties": {
+                        "title": {"type": "string"},
+                    }
+                }
+            }
+        });
+
+        merge_json(&mut base, json!({
+            "settings": {
+                "refresh_interval": "5s",
+            },
+            "mappings": {
+                "doc": {
+                    "properties": {
+                        "body": {"type": "string"},
+                    }
+                }
+            }
+        }));
+
+        assert_eq!(base, json!({
+            "settings": {
+                "refresh_interval": "5s",
+                "max_result_window": 100,
+            },
+            "mappings": {
+                "doc": {
+                    "properties": {
+                        "title": {"type": "string"},
+                        "body": {"type": "string"},
+                    }
+                }
+            }
+        }));
+    }
+
+    #[test]
+    fn test_parse() {
+        let template = parse(&serde_json::from_str("
+        {
+            \"index_patterns\": [\"logs-*\"],
+            \"priority\": 10,
+            \"template\": {
+                \"settings\": {
+                    \"refresh_interval\": \"5s\"
+                }
+            }
+        }
+        ").unwrap());
+
+        assert_eq!(template, Ok(IndexTemplate {
+            index_patterns: vec!["logs-*".to_string()],
+            priority: 10,
+            template: json!({
+                "settings": {
+                    "refresh_interval": "5s",
+                }
+            }),
+        }));
+    }
+
+    #[test]
+    fn test_parse_missing_patterns() {
+        let template = parse(&serde_json::from_str("
+        {
+            \"template\": {}
+        }
+        ").unwrap());
+
+        assert_eq!(template, Err(IndexTemplateParseError::ExpectedKey("index_patterns".to_string())));
+    }
+
+    #[test]
+    fn test_parse_invalid_template() {
+        let template = parse(&serde_json::from_str("
+        {
+            \"index_patterns\": \"logs-*\",
+            \"template\": {
+                \"settings\": {
+                    \"foo\": \"bar\"
+                }
+            }
+        }
+        ").unwrap());
+
+        assert!(match template {
+            Err(IndexTemplateParseError::InvalidTemplate(_)) => true,
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn test_find_index_template() {
+        let mut cluster_metadata = ClusterMetadata::new();
+        cluster_metadata.templates.insert("logs".to_string(), IndexTemplate {
+            index_patterns: vec!["logs-*".to_string()],
+            priority: 0,
+            template: json!({}),
+        });
+        cluster_metadata.templates.insert("important_logs".to_string(), IndexTemplate {
+            index_patterns: vec!["logs-important-*".to_string()],
+            priority: 1,
+            template: json!({}),
+        });
+
+        assert_eq!(cluster_metadata.find_index_template("logs-2017").map(|template| template.priority), Some(0));
+        assert_eq!(cluster_metadata.find_index_template("logs-important-2017").map(|template| template.priority), Some(1));
+        assert_eq!(cluster_metadata.find_index_template("metrics-2017"), None);
+    }
+}